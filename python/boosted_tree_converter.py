@@ -0,0 +1,196 @@
+import argparse
+import json
+import numpy as np
+import msgpack
+from tree_ensemble import tree_ensemble_layer, sequential_model_dict
+
+# Converts XGBoost JSON models (save_model('model.json')) and LightGBM text models
+# (save_model('model.txt')) to the TreeEnsemble layer
+# Thresholds and leaf values are quantized by the scale factor, see tree_ensemble.py
+
+def one_hot_value(value, out_idx, num_outputs):
+  outp = np.zeros(num_outputs)
+  outp[out_idx] = value
+  return outp.tolist()
+
+def parse_base_score(base_score):
+  # Newer versions of XGBoost store the base score as "[5E-1]"
+  if isinstance(base_score, str):
+    base_score = base_score.strip('[]')
+  return float(base_score)
+
+# Returns (trees, num_outputs, num_features, base_score, post transform, strict)
+def load_xgboost(path):
+  with open(path, 'r') as f:
+    model = json.load(f)
+  learner = model['learner']
+  booster = learner['gradient_booster']
+  if booster['name'] != 'gbtree':
+    raise NotImplementedError(f'Only gbtree boosters are supported: {booster["name"]}')
+
+  params = learner['learner_model_param']
+  num_features = int(params['num_feature'])
+  num_outputs = max(int(params.get('num_class', 0)), 1)
+  objective = learner['objective']['name']
+
+  base_score = parse_base_score(params['base_score'])
+  if objective in ['binary:logistic', 'reg:logistic']:
+    # The base score is a probability
+    base_score = float(np.log(base_score / (1. - base_score)))
+    post = 'Logistic'
+  elif objective in ['multi:softprob', 'multi:softmax']:
+    post = 'Softmax'
+  elif objective.startswith('reg:') or objective == 'binary:logitraw':
+    post = None
+  else:
+    raise NotImplementedError(f'Unsupported objective: {objective}')
+
+  gb_model = booster['model']
+  tree_info = gb_model['tree_info']
+  trees = []
+  for tree, out_idx in zip(gb_model['trees'], tree_info):
+    if tree.get('categories_nodes'):
+      raise NotImplementedError('Categorical splits are not supported')
+    nodes = []
+    for i in range(int(tree['tree_param']['num_nodes'])):
+      left = tree['left_children'][i]
+      # Leaves store the value in the split condition
+      if left == -1:
+        value = tree['split_conditions'][i]
+        nodes.append({'value': one_hot_value(value, out_idx, num_outputs)})
+      else:
+        nodes.append({
+          'left': left,
+          'right': tree['right_children'][i],
+          'feature': tree['split_indices'][i],
+          'threshold': tree['split_conditions'][i],
+        })
+    trees.append(nodes)
+
+  base_score = [base_score] * num_outputs
+  # XGBoost goes left if x < threshold
+  return trees, num_outputs, num_features, base_score, post, True
+
+def parse_lightgbm_sections(path):
+  header = {}
+  trees = []
+  cur = header
+  with open(path, 'r') as f:
+    for line in f:
+      line = line.strip()
+      if line.startswith('Tree='):
+        cur = {}
+        trees.append(cur)
+      elif line == 'end of trees':
+        break
+      elif '=' in line:
+        key, value = line.split('=', 1)
+        cur[key] = value
+  return header, trees
+
+def load_lightgbm(path):
+  header, sections = parse_lightgbm_sections(path)
+  num_features = int(header['max_feature_idx']) + 1
+  num_outputs = int(header.get('num_class', '1'))
+  num_per_iter = int(header.get('num_tree_per_iteration', str(num_outputs)))
+
+  objective = header['objective'].split(' ')
+  sigmoid = 1.
+  if objective[0] == 'binary':
+    post = 'Logistic'
+    for opt in objective[1:]:
+      if opt.startswith('sigmoid:'):
+        sigmoid = float(opt.split(':')[1])
+  elif objective[0] in ['multiclass', 'softmax']:
+    post = 'Softmax'
+  elif objective[0] in ['regression', 'regression_l1', 'huber', 'fair', 'quantile', 'mape']:
+    post = None
+  else:
+    raise NotImplementedError(f'Unsupported objective: {header["objective"]}')
+
+  trees = []
+  for tree_idx, section in enumerate(sections):
+    out_idx = tree_idx % num_per_iter
+    num_leaves = int(section['num_leaves'])
+    leaf_values = [float(x) * sigmoid for x in section['leaf_value'].split(' ')]
+    if num_leaves == 1:
+      trees.append([{'value': one_hot_value(leaf_values[0], out_idx, num_outputs)}])
+      continue
+
+    split_feature = [int(x) for x in section['split_feature'].split(' ')]
+    thresholds = [float(x) for x in section['threshold'].split(' ')]
+    decision_type = [int(x) for x in section['decision_type'].split(' ')]
+    left_child = [int(x) for x in section['left_child'].split(' ')]
+    right_child = [int(x) for x in section['right_child'].split(' ')]
+    num_internal = num_leaves - 1
+
+    # Internal nodes come first, then the leaves. Negative children are leaves: ~child
+    def child(c):
+      return c if c >= 0 else num_internal + ~c
+
+    nodes = []
+    for i in range(num_internal):
+      if decision_type[i] & 1:
+        raise NotImplementedError('Categorical splits are not supported')
+      nodes.append({
+        'left': child(left_child[i]),
+        'right': child(right_child[i]),
+        'feature': split_feature[i],
+        'threshold': thresholds[i],
+      })
+    for value in leaf_values:
+      nodes.append({'value': one_hot_value(value, out_idx, num_outputs)})
+    trees.append(nodes)
+
+  # LightGBM folds the initial score into the first trees and goes left if x <= threshold
+  return trees, num_outputs, num_features, None, post, False
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--model', type=str, required=True)
+  parser.add_argument('--format', type=str, required=False, default=None, choices=['xgboost', 'lightgbm'])
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--raw_scores', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--scale_factor', type=int, default=2**8)
+  parser.add_argument('--k', type=int, default=17)
+  parser.add_argument('--num_cols', type=int, default=10)
+  parser.add_argument('--use_selectors', action=argparse.BooleanOptionalAction, required=False, default=True)
+  parser.add_argument('--commit', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--num_randoms', type=int, default=1024)
+  args = parser.parse_args()
+
+  fmt = args.format
+  if fmt is None:
+    fmt = 'xgboost' if args.model.endswith('.json') else 'lightgbm'
+  if fmt == 'xgboost':
+    trees, num_outputs, num_features, base_score, post, strict = load_xgboost(args.model)
+  else:
+    trees, num_outputs, num_features, base_score, post, strict = load_lightgbm(args.model)
+  print(f'{len(trees)} trees, {num_features} features, {num_outputs} outputs')
+
+  params, thresholds, leaf_values = tree_ensemble_layer(
+    trees,
+    num_outputs,
+    args.scale_factor,
+    base_score=base_score,
+    strict=strict,
+  )
+  layers = [('TreeEnsemble', params, [thresholds, leaf_values], [1, num_outputs])]
+  if post is not None and not args.raw_scores:
+    layers.append((post, [], [], [1, num_outputs]))
+
+  d = sequential_model_dict(
+    layers,
+    [1, num_features],
+    args.scale_factor,
+    args.k,
+    args.num_cols,
+    args.num_randoms,
+    args.use_selectors,
+    args.commit,
+  )
+  with open(args.output, 'wb') as f:
+    f.write(msgpack.packb(d, use_bin_type=True))
+
+if __name__ == '__main__':
+  main()
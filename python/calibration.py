@@ -0,0 +1,66 @@
+# Calibration of the global scale factor from activation ranges
+# The ranges can come from an external JSON file, e.g., the calibration stats of a teacher model
+# when converting a distilled student. The file maps tensor names (or indices) to [min, max]:
+#
+#   {"model/dense/MatMul": [-3.2, 4.1], "12": [0.0, 6.0]}
+#
+# Tensors are matched to the model being converted by name first and by index second.
+
+import json
+import numpy as np
+import tflite
+
+def load_range_file(range_file):
+  with open(range_file, 'r') as f:
+    ranges = json.load(f)
+  parsed = {}
+  for key, val in ranges.items():
+    if len(val) != 2:
+      raise RuntimeError('Range for {} must be [min, max]'.format(key))
+    if val[0] > val[1]:
+      raise RuntimeError('Range for {} has min > max'.format(key))
+    parsed[key] = (float(val[0]), float(val[1]))
+  return parsed
+
+def get_tensor_names(model_path):
+  with open(model_path, 'rb') as f:
+    buf = f.read()
+    model = tflite.Model.GetRootAsModel(buf, 0)
+  graph = model.Subgraphs(0)
+  if graph is None:
+    raise RuntimeError('Graph is None')
+  names = {}
+  for tensor_idx in range(graph.TensorsLength()):
+    tensor = graph.Tensors(tensor_idx)
+    if tensor is None:
+      raise NotImplementedError('Tensor is None')
+    names[tensor_idx] = tensor.Name().decode('utf-8')
+  return names
+
+def match_ranges(model_path, ranges):
+  names = get_tensor_names(model_path)
+  matched = {}
+  for tensor_idx, name in names.items():
+    if name in ranges:
+      matched[tensor_idx] = ranges[name]
+    elif str(tensor_idx) in ranges:
+      matched[tensor_idx] = ranges[str(tensor_idx)]
+  return matched
+
+# The activations (after scaling) must fit in the lookup range [-2^(k-1), 2^(k-1)).
+# The margin leaves room for the intermediate values of the nonlinearities.
+def choose_scale_factor(ranges, k, margin=4., max_scale_factor=2**16):
+  if len(ranges) == 0:
+    raise RuntimeError('No ranges to calibrate from')
+  max_abs = max(max(abs(lo), abs(hi)) for lo, hi in ranges.values())
+  if max_abs == 0:
+    return max_scale_factor
+  limit = (1 << (k - 1)) / (max_abs * margin)
+  if limit < 1:
+    raise RuntimeError('Range {} does not fit in k = {}'.format(max_abs, k))
+  return int(min(2 ** int(np.floor(np.log2(limit))), max_scale_factor))
+
+def calibrate_from_file(model_path, range_file, k, margin=4., max_scale_factor=2**16):
+  ranges = match_ranges(model_path, load_range_file(range_file))
+  print('calibrating from {} matched tensors'.format(len(ranges)))
+  return choose_scale_factor(ranges, k, margin, max_scale_factor)
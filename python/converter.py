@@ -5,6 +5,7 @@ import tensorflow as tf
 import numpy as np
 import tflite
 import msgpack
+from calibration import calibrate_from_file
 
 def get_shape(interpreter: tf.lite.Interpreter, tensor_idx):
   if tensor_idx == -1:
@@ -23,8 +24,27 @@ def get_inputs(op: tflite.Operator):
   idxes = list(filter(lambda x: x != -1, idxes))
   return idxes
 
+# Pointwise ops without a dedicated layer, converted to an explicit lookup table
+TABULATED_OPS = {
+  tflite.BuiltinOperator.ABS: np.abs,
+  tflite.BuiltinOperator.COS: np.cos,
+  tflite.BuiltinOperator.ELU: lambda x: np.where(x > 0, x, np.expm1(x)),
+  tflite.BuiltinOperator.EXP: np.exp,
+  tflite.BuiltinOperator.HARD_SWISH: lambda x: x * np.clip(x + 3, 0, 6) / 6,
+  tflite.BuiltinOperator.LOG: lambda x: np.log(np.maximum(x, 1e-6)),
+  tflite.BuiltinOperator.NEG: np.negative,
+  tflite.BuiltinOperator.RELU: lambda x: np.maximum(x, 0),
+  tflite.BuiltinOperator.RELU6: lambda x: np.clip(x, 0, 6),
+  tflite.BuiltinOperator.RELU_N1_TO_1: lambda x: np.clip(x, -1, 1),
+  tflite.BuiltinOperator.SIN: np.sin,
+}
+
 class Converter:
-  def __init__(self, model_path, scale_factor, k, num_cols, num_randoms, use_selectors, commit):
+  def __init__(self, model_path, scale_factor, k, num_cols, num_randoms, use_selectors, commit,
+               tabulated_range=8., pwl_error=None, softmax_top_k=None, rlc_inputs=False,
+               drop_final_softmax=False, zero_knowledge=True, column_profile=None,
+               commit_weights=False, hash_inputs=False, private_argmax=None, private_threshold=None,
+               activation_table_bits=None, reveal_top_k=None):
     self.model_path = model_path
     self.scale_factor = scale_factor
     self.k = k
@@ -32,6 +52,19 @@ class Converter:
     self.num_randoms = num_randoms
     self.use_selectors = use_selectors
     self.commit = commit
+    self.tabulated_range = tabulated_range
+    self.pwl_error = pwl_error
+    self.softmax_top_k = softmax_top_k
+    self.rlc_inputs = rlc_inputs
+    self.drop_final_softmax = drop_final_softmax
+    self.zero_knowledge = zero_knowledge
+    self.column_profile = column_profile
+    self.commit_weights = commit_weights
+    self.hash_inputs = hash_inputs
+    self.private_argmax = private_argmax
+    self.private_threshold = private_threshold
+    self.activation_table_bits = activation_table_bits
+    self.reveal_top_k = reveal_top_k
 
     self.interpreter = tf.lite.Interpreter(
       model_path=self.model_path,
@@ -91,6 +124,50 @@ class Converter:
       return ('Add', params)
 
 
+  # Table is [x_start, f(x_start), f(x_start + 1), ...] in the scaled domain
+  # Inputs outside of the table are clamped to the end points in the circuit
+  def _tabulate(self, fn):
+    sf = self.scale_factor
+    x_max = int(self.tabulated_range * sf)
+    x_max = min(x_max, (1 << (self.k - 1)) - 1)
+    xs = np.arange(-x_max, x_max + 1, dtype=np.int64)
+    with np.errstate(all='ignore'):
+      ys = fn(xs.astype(np.float64) / sf)
+    ys = (np.nan_to_num(ys) * sf).round().astype(np.int64)
+    return [int(-x_max)] + ys.tolist()
+
+  # Greedy piecewise-linear fit: extend every segment while the chord stays within max_err
+  # Params are [num_knots, x_0, ..., x_{n-1}, y_0, ..., y_{n-1}] in the scaled domain
+  def _fit_pwl(self, fn, max_err):
+    table = self._tabulate(fn)
+    x_start = table[0]
+    ys = np.array(table[1:], dtype=np.float64)
+    xs = np.arange(x_start, x_start + len(ys), dtype=np.float64)
+    max_err = max_err * self.scale_factor
+
+    def fits(start, end):
+      slope = (ys[end] - ys[start]) / (xs[end] - xs[start])
+      approx = ys[start] + slope * (xs[start:end + 1] - xs[start])
+      return np.abs(approx - ys[start:end + 1]).max() <= max_err
+
+    knots = [0]
+    start = 0
+    while start < len(ys) - 1:
+      good, length = start + 1, 2
+      while start + length < len(ys) and fits(start, start + length):
+        good = start + length
+        length *= 2
+      bad = min(start + length, len(ys))
+      while bad - good > 1:
+        mid = (good + bad) // 2
+        if fits(start, mid):
+          good = mid
+        else:
+          bad = mid
+      knots.append(good)
+      start = good
+    return [len(knots)] + [int(xs[i]) for i in knots] + [int(ys[i]) for i in knots]
+
   def to_dict(self, start_layer, end_layer):
     interpreter = self.interpreter
     model = self.model
@@ -186,6 +263,10 @@ class Converter:
           [opt.Padding()] + \
           [opt.FusedActivationFunction()] + \
           [opt.StrideH(), opt.StrideW()]
+        # The filters of a grouped convolution have the input channels of a group
+        groups = get_shape(interpreter, op.Inputs(0))[-1] // get_shape(interpreter, op.Inputs(1))[-1]
+        if groups > 1:
+          params.append(groups)
       # DepthwiseConv2D
       elif op_code == tflite.BuiltinOperator.DEPTHWISE_CONV_2D:
         layer_type = 'Conv2D'
@@ -222,7 +303,6 @@ class Converter:
           raise RuntimeError('BatchMatMul options is None')
         opt = tflite.BatchMatMulOptions()
         opt.Init(op_opt.Bytes, op_opt.Pos)
-        if opt.AdjX() is True: raise NotImplementedError('AdjX is not supported')
         params = [int(opt.AdjX()), int(opt.AdjY())]
 
       ## Arithmetic
@@ -297,6 +377,14 @@ class Converter:
       elif op_code == tflite.BuiltinOperator.TANH:
         layer_type = 'Tanh'
         params = []
+      elif op_code == tflite.BuiltinOperator.GELU:
+        op_opt = op.BuiltinOptions()
+        if op_opt is not None:
+          opt = tflite.GeluOptions()
+          opt.Init(op_opt.Bytes, op_opt.Pos)
+          if opt.Approximate(): raise NotImplementedError('The tanh approximation of GELU is not supported')
+        layer_type = 'Gelu'
+        params = []
       elif op_code == tflite.BuiltinOperator.POW:
         layer_type = 'Pow'
         power = interpreter.get_tensor(op.Inputs(1)).flatten().astype(np.float32)
@@ -386,6 +474,15 @@ class Converter:
         # Can take the out shape directly from the tensor
         params = [int(opt.AlignCorners()), int(opt.HalfPixelCenters())]
 
+      # Fallback for pointwise ops: enforce an explicit table via lookup
+      elif op_code in TABULATED_OPS:
+        if self.pwl_error is not None:
+          layer_type = 'PiecewiseLinear'
+          params = self._fit_pwl(TABULATED_OPS[op_code], self.pwl_error)
+        else:
+          layer_type = 'Tabulated'
+          params = self._tabulate(TABULATED_OPS[op_code])
+
       # Not implemented
       else:
         op_name = None
@@ -485,6 +582,42 @@ class Converter:
       'commit_before': commit_before,
       'commit_after': commit_after,
     }
+    # Coarser GELU and SiLU tables over a wider input range
+    if self.activation_table_bits is not None:
+      d['activation_table_bits'] = self.activation_table_bits
+    # Opt-in approximation: every softmax only keeps its k largest inputs
+    if self.softmax_top_k is not None:
+      d['softmax_top_k'] = self.softmax_top_k
+    # Public inputs are bound by a random linear combination rather than the instance
+    if self.rlc_inputs:
+      d['rlc_inputs'] = [inp['index'] for inp in input_details]
+      d['commit_before'] = [group for group in commit_before if group != d['rlc_inputs']]
+    # The circuit checks that the softmax is final and outputs the logits
+    if self.drop_final_softmax:
+      d['drop_final_softmax'] = True
+    # Deterministic blinding, for public inputs and weights
+    if not self.zero_knowledge:
+      d['zero_knowledge'] = False
+    # Commits to all weights as the first public value (see src/commitments/model_commit.rs)
+    if self.commit_weights:
+      d['commit_weights'] = True
+    # Exposes the Poseidon hash of the private inputs (see src/commitments/input_hash.rs)
+    if self.hash_inputs:
+      d['hash_inputs'] = True
+    # Reveals a predicate of the output instead of the output (see src/utils/predicate.rs)
+    if self.private_argmax is not None:
+      d['output_predicate'] = [0, d['out_idxes'][0], self.private_argmax]
+    if self.private_threshold is not None:
+      element, threshold = self.private_threshold
+      threshold = int(np.round(threshold * self.scale_factor))
+      d['output_predicate'] = [1, d['out_idxes'][0], int(element), threshold]
+    # Reveals the indices of the top k elements of the output instead (see src/utils/predicate.rs)
+    if self.reveal_top_k is not None:
+      order, k = self.reveal_top_k
+      d['output_top_k'] = [order, d['out_idxes'][0], k]
+    # Named column budget, overrides num_cols in the circuit (see src/utils/profiles.rs)
+    if self.column_profile is not None:
+      d['column_profile'] = self.column_profile
     print()
     print(d['layers'][-1])
     # d['out_idxes'] = [14]
@@ -510,8 +643,47 @@ def main():
   parser.add_argument('--start_layer', type=int, default=0)
   parser.add_argument('--end_layer', type=int, default=10000)
   parser.add_argument('--num_randoms', type=int, default=20001)
+  parser.add_argument('--tabulated_range', type=float, default=8.)
+  parser.add_argument('--pwl_error', type=float, required=False, default=None)
+  parser.add_argument('--softmax_top_k', type=int, required=False, default=None)
+  parser.add_argument('--activation_table_bits', type=int, required=False, default=None)
+  parser.add_argument('--rlc_inputs', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--drop_final_softmax', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--zero_knowledge', action=argparse.BooleanOptionalAction, required=False, default=True)
+  parser.add_argument('--column_profile', type=str, required=False, default=None,
+                      choices=['low-memory', 'balanced', 'fast-prover'])
+  parser.add_argument('--commit_weights', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--hash_inputs', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--private_argmax', type=int, required=False, default=None)
+  parser.add_argument('--private_threshold', type=float, nargs=2, required=False, default=None,
+                      metavar=('ELEMENT', 'THRESHOLD'))
+  parser.add_argument('--reveal_argmax', action='store_true')
+  parser.add_argument('--reveal_argmin', action='store_true')
+  parser.add_argument('--reveal_top_k', type=int, required=False, default=None)
+  parser.add_argument('--range_file', type=str, required=False, default=None)
+  parser.add_argument('--range_margin', type=float, default=4.)
   args = parser.parse_args()
 
+  # Reuse external activation ranges (e.g., from a teacher model) to set the scale factor
+  if args.range_file is not None:
+    args.scale_factor = calibrate_from_file(
+      args.model,
+      args.range_file,
+      args.k,
+      args.range_margin,
+      args.scale_factor,
+    )
+    print('calibrated scale factor:', args.scale_factor)
+
+  # [order, k], the largest first is order 0
+  reveal_top_k = None
+  if args.reveal_argmax:
+    reveal_top_k = (0, 1)
+  if args.reveal_argmin:
+    reveal_top_k = (1, 1)
+  if args.reveal_top_k is not None:
+    reveal_top_k = (0, args.reveal_top_k)
+
   converter = Converter(
     args.model,
     args.scale_factor,
@@ -520,6 +692,19 @@ def main():
     args.num_randoms,
     args.use_selectors,
     args.commit,
+    args.tabulated_range,
+    args.pwl_error,
+    args.softmax_top_k,
+    args.rlc_inputs,
+    args.drop_final_softmax,
+    args.zero_knowledge,
+    args.column_profile,
+    args.commit_weights,
+    args.hash_inputs,
+    args.private_argmax,
+    args.private_threshold,
+    args.activation_table_bits,
+    reveal_top_k,
   )
 
   packed = converter.to_msgpack(
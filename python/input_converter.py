@@ -3,29 +3,62 @@ import ast
 import numpy as np
 import msgpack
 
+# Integer dtypes are already quantized and are passed through as is. Float inputs are converted to
+# fixed point with the scale factor.
+INT_DTYPES = ['int8', 'int32', 'int64']
+FLOAT_DTYPES = ['float32', 'float64']
+
+def load_input(fname, dtype, shape):
+  if fname.endswith('.npy'):
+    tensor = np.load(fname)
+    if dtype is None:
+      dtype = str(tensor.dtype)
+    elif str(tensor.dtype) != dtype:
+      raise ValueError('{} has dtype {}, not {}'.format(fname, tensor.dtype, dtype))
+  else:
+    if dtype is None:
+      dtype = 'int64'
+    tensor = np.fromfile(fname, dtype=dtype)
+  if dtype not in INT_DTYPES + FLOAT_DTYPES:
+    raise ValueError('unsupported dtype {}'.format(dtype))
+  return tensor.reshape(shape), dtype
+
 def main():
   parser = argparse.ArgumentParser()
   parser.add_argument('--inputs', type=str, required=True)
   parser.add_argument('--input_shapes', type=str, required=True)
   parser.add_argument('--input_idxes', type=str, required=True)
   parser.add_argument('--output', type=str, required=True)
+  # Comma separated, one per input. Defaults to the .npy dtype or int64 for raw files
+  parser.add_argument('--dtypes', type=str, default=None)
+  parser.add_argument('--scale_factor', type=int, default=None)
   args = parser.parse_args()
 
   inputs = args.inputs.split(',')
   input_shapes = ast.literal_eval(args.input_shapes)
   input_idxes = ast.literal_eval(args.input_idxes)
+  dtypes = args.dtypes.split(',') if args.dtypes else [None] * len(inputs)
 
   assert len(inputs) == len(input_shapes)
   assert len(inputs) == len(input_idxes)
+  assert len(inputs) == len(dtypes)
 
   tensors = []
-  for inp, shape, idx in zip(inputs, input_shapes, input_idxes):
-    tensor = np.fromfile(inp, dtype=np.int64).reshape(shape)
-    tensors.append({
+  for inp, shape, idx, dtype in zip(inputs, input_shapes, input_idxes, dtypes):
+    tensor, dtype = load_input(inp, dtype, shape)
+    if dtype in FLOAT_DTYPES:
+      if args.scale_factor is None:
+        raise ValueError('--scale_factor is required for float inputs')
+      tensor = np.round(tensor * args.scale_factor).astype(np.int64)
+      dtype = 'int64'
+    entry = {
       'idx': idx,
       'shape': shape,
-      'data': tensor.flatten().tolist(),
-    })
+      'data': tensor.astype(np.int64).flatten().tolist(),
+    }
+    if dtype != 'int64':
+      entry['dtype'] = dtype
+    tensors.append(entry)
 
   packed = msgpack.packb(tensors, use_bin_type=True)
 
@@ -34,4 +67,4 @@ def main():
 
 
 if __name__ == '__main__':
-  main()
\ No newline at end of file
+  main()
@@ -0,0 +1,162 @@
+# Prunes and clusters the weights of a TFLite model
+# The weights below the threshold are set to zero and the remaining weights are clustered to a
+# small codebook. The resulting model is written as a new TFLite file, which can then be passed
+# to converter.py. The accuracy impact is estimated by running both models in the interpreter.
+#
+# Shortcut:
+# `python3 python/prune.py --model model.tflite --output pruned.tflite --threshold 0.001 --num_clusters 16`
+#
+
+import argparse
+import numpy as np
+import tensorflow as tf
+import tflite
+
+def kmeans_1d(values: np.ndarray, num_clusters, num_iters):
+  # Initialize with the quantiles so that the codebook covers the distribution
+  quantiles = np.linspace(0, 1, num_clusters)
+  centroids = np.unique(np.quantile(values, quantiles))
+  for _ in range(num_iters):
+    assignment = np.abs(values[:, None] - centroids[None, :]).argmin(axis=1)
+    new_centroids = centroids.copy()
+    for i in range(len(centroids)):
+      members = values[assignment == i]
+      if len(members) > 0:
+        new_centroids[i] = members.mean()
+    if np.allclose(new_centroids, centroids):
+      break
+    centroids = new_centroids
+  assignment = np.abs(values[:, None] - centroids[None, :]).argmin(axis=1)
+  return centroids, assignment
+
+def prune_and_cluster(weights: np.ndarray, threshold, num_clusters, num_iters):
+  flat = weights.flatten().astype(np.float64)
+  mask = np.abs(flat) >= threshold
+  out = np.zeros_like(flat)
+  if num_clusters > 0 and mask.sum() > num_clusters:
+    centroids, assignment = kmeans_1d(flat[mask], num_clusters, num_iters)
+    out[mask] = centroids[assignment]
+  else:
+    out[mask] = flat[mask]
+  return out.reshape(weights.shape).astype(weights.dtype)
+
+class Pruner:
+  def __init__(self, model_path, threshold, num_clusters, num_iters, min_size):
+    self.model_path = model_path
+    self.threshold = threshold
+    self.num_clusters = num_clusters
+    self.num_iters = num_iters
+    self.min_size = min_size
+
+    # The buffer must be mutable so that the weights can be rewritten in place
+    with open(self.model_path, 'rb') as f:
+      self.buf = bytearray(f.read())
+    self.model = tflite.Model.GetRootAsModel(self.buf, 0)
+    self.graph = self.model.Subgraphs(0)
+    if self.graph is None:
+      raise RuntimeError('Graph is None')
+
+  def prune(self):
+    report = []
+    for tensor_idx in range(self.graph.TensorsLength()):
+      tensor = self.graph.Tensors(tensor_idx)
+      if tensor is None:
+        raise NotImplementedError('Tensor is None')
+      if tensor.Type() != tflite.TensorType.FLOAT32:
+        continue
+      buffer = self.model.Buffers(tensor.Buffer())
+      if buffer is None or buffer.DataLength() == 0:
+        continue
+
+      raw = buffer.DataAsNumpy()
+      weights = raw.view(np.float32)
+      if weights.size < self.min_size:
+        continue
+
+      pruned = prune_and_cluster(weights, self.threshold, self.num_clusters, self.num_iters)
+      raw[:] = pruned.view(np.uint8)
+
+      report.append({
+        'idx': tensor_idx,
+        'name': tensor.Name().decode('utf-8'),
+        'size': int(weights.size),
+        'sparsity': float((pruned == 0).mean()),
+        'num_unique': int(len(np.unique(pruned))),
+        'max_abs_err': float(np.abs(pruned - weights).max()),
+      })
+    return report
+
+  def write(self, output_path):
+    with open(output_path, 'wb') as f:
+      f.write(self.buf)
+
+def get_interpreter(model_path):
+  interpreter = tf.lite.Interpreter(model_path=model_path)
+  interpreter.allocate_tensors()
+  return interpreter
+
+def run_interpreter(interpreter: tf.lite.Interpreter, inps):
+  input_details = interpreter.get_input_details()
+  output_details = interpreter.get_output_details()
+  for inp_detail, inp in zip(input_details, inps):
+    interpreter.set_tensor(inp_detail['index'], inp)
+  interpreter.invoke()
+  return [interpreter.get_tensor(out['index']) for out in output_details]
+
+def estimate_accuracy_impact(model_path, pruned_path, num_samples, seed):
+  orig = get_interpreter(model_path)
+  pruned = get_interpreter(pruned_path)
+  rng = np.random.default_rng(seed)
+
+  max_abs_err = 0.
+  top1_agree = 0
+  for _ in range(num_samples):
+    inps = []
+    for inp_detail in orig.get_input_details():
+      inp = rng.standard_normal(inp_detail['shape']).astype(inp_detail['dtype'])
+      inps.append(inp)
+    orig_outs = run_interpreter(orig, inps)
+    pruned_outs = run_interpreter(pruned, inps)
+    for orig_out, pruned_out in zip(orig_outs, pruned_outs):
+      max_abs_err = max(max_abs_err, float(np.abs(orig_out - pruned_out).max()))
+    top1_agree += int(orig_outs[0].flatten().argmax() == pruned_outs[0].flatten().argmax())
+
+  return {
+    'num_samples': num_samples,
+    'max_abs_err': max_abs_err,
+    'top1_agreement': top1_agree / num_samples,
+  }
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--model', type=str, required=True)
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--threshold', type=float, default=0.)
+  parser.add_argument('--num_clusters', type=int, default=0)
+  parser.add_argument('--num_iters', type=int, default=20)
+  parser.add_argument('--min_size', type=int, default=16)
+  parser.add_argument('--num_samples', type=int, default=16)
+  parser.add_argument('--seed', type=int, default=0)
+  args = parser.parse_args()
+
+  pruner = Pruner(
+    args.model,
+    args.threshold,
+    args.num_clusters,
+    args.num_iters,
+    args.min_size,
+  )
+  report = pruner.prune()
+  pruner.write(args.output)
+
+  for entry in report:
+    print(entry)
+  total = sum(entry['size'] for entry in report)
+  zeros = sum(entry['size'] * entry['sparsity'] for entry in report)
+  print('pruned weights: {} / {} ({:.2%})'.format(int(zeros), total, zeros / max(total, 1)))
+
+  impact = estimate_accuracy_impact(args.model, args.output, args.num_samples, args.seed)
+  print('accuracy impact:', impact)
+
+if __name__ == '__main__':
+  main()
@@ -0,0 +1,112 @@
+import argparse
+import os
+import tempfile
+import tensorflow as tf
+from tensorflow.python.framework.convert_to_constants import convert_variables_to_constants_v2
+from converter import Converter
+
+# Imports a TensorFlow SavedModel or Keras model: freezes the graph, checks the op set, and
+# converts it to float TFLite, which is then converted to the msgpack config as usual
+
+# Frozen graph ops that the TFLite conversion maps to supported layers. Batch norms and bias
+# adds are folded into the preceding conv or matmul by the TFLite converter.
+SUPPORTED_TF_OPS = set([
+  'Add', 'AddV2', 'AvgPool', 'BiasAdd', 'ConcatV2', 'Const', 'Conv2D', 'DepthwiseConv2dNative',
+  'FusedBatchNorm', 'FusedBatchNormV3', 'Identity', 'MatMul', 'MaxPool', 'Mean', 'Mul', 'NoOp',
+  'Pack', 'Pad', 'Placeholder', 'RealDiv', 'Relu', 'Relu6', 'Reshape', 'Rsqrt', 'Shape',
+  'Sigmoid', 'Softmax', 'Square', 'SquaredDifference', 'Squeeze', 'StridedSlice', 'Sub', 'Tanh',
+  'Transpose',
+])
+
+def load_concrete_function(model_path, signature):
+  if model_path.endswith('.h5') or model_path.endswith('.keras'):
+    model = tf.keras.models.load_model(model_path, compile=False)
+    fn = tf.function(lambda x: model(x))
+    spec = tf.TensorSpec([1] + list(model.input_shape[1:]), model.inputs[0].dtype)
+    return fn.get_concrete_function(spec)
+
+  loaded = tf.saved_model.load(model_path)
+  if signature not in loaded.signatures:
+    raise RuntimeError(f'Signature {signature} not found: {list(loaded.signatures.keys())}')
+  return loaded.signatures[signature]
+
+def check_ops(frozen_fn):
+  op_types = set(op.type for op in frozen_fn.graph.get_operations())
+  return sorted(op_types - SUPPORTED_TF_OPS)
+
+# Batch dimensions must be fixed for the circuit
+def fix_batch_size(concrete_fn):
+  specs = []
+  for inp in concrete_fn.inputs:
+    if inp.dtype == tf.resource:
+      continue
+    shape = [1 if dim is None else dim for dim in inp.shape.as_list()]
+    specs.append(tf.TensorSpec(shape, inp.dtype))
+  return specs
+
+def to_tflite(concrete_fn):
+  frozen_fn = convert_variables_to_constants_v2(concrete_fn)
+  converter = tf.lite.TFLiteConverter.from_concrete_functions([frozen_fn])
+  # Only builtin float ops: the circuit does its own quantization
+  converter.target_spec.supported_ops = [tf.lite.OpsSet.TFLITE_BUILTINS]
+  converter.optimizations = []
+  return frozen_fn, converter.convert()
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--model', type=str, required=True, help='SavedModel directory or Keras file')
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--signature', type=str, default='serving_default')
+  parser.add_argument('--tflite_output', type=str, required=False, default=None)
+  parser.add_argument('--allow_unsupported', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--scale_factor', type=int, default=2**16)
+  parser.add_argument('--k', type=int, default=19)
+  parser.add_argument('--num_cols', type=int, default=6)
+  parser.add_argument('--use_selectors', action=argparse.BooleanOptionalAction, required=False, default=True)
+  parser.add_argument('--commit', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--num_randoms', type=int, default=20001)
+  parser.add_argument('--tabulated_range', type=float, default=8.)
+  parser.add_argument('--pwl_error', type=float, required=False, default=None)
+  args = parser.parse_args()
+
+  concrete_fn = load_concrete_function(args.model, args.signature)
+  if any(dim is None for inp in concrete_fn.inputs for dim in inp.shape.as_list()):
+    concrete_fn = tf.function(concrete_fn).get_concrete_function(*fix_batch_size(concrete_fn))
+
+  frozen_fn, tflite_model = to_tflite(concrete_fn)
+  unsupported = check_ops(frozen_fn)
+  if len(unsupported) > 0:
+    msg = f'Unsupported ops in the frozen graph: {unsupported}'
+    if not args.allow_unsupported:
+      raise NotImplementedError(msg)
+    print('WARNING:', msg)
+
+  if args.tflite_output is not None:
+    tflite_path = args.tflite_output
+  else:
+    tflite_path = os.path.join(tempfile.mkdtemp(), 'model.tflite')
+  with open(tflite_path, 'wb') as f:
+    f.write(tflite_model)
+  print('wrote tflite model to', tflite_path)
+
+  converter = Converter(
+    tflite_path,
+    args.scale_factor,
+    args.k,
+    args.num_cols,
+    args.num_randoms,
+    args.use_selectors,
+    args.commit,
+    args.tabulated_range,
+    args.pwl_error,
+  )
+
+  packed = converter.to_msgpack(start_layer=0, end_layer=10000)
+  if packed is None:
+    raise Exception('Failed to convert model')
+
+  with open(args.output, 'wb') as f:
+    f.write(packed)
+
+if __name__ == '__main__':
+  main()
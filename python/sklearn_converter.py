@@ -0,0 +1,271 @@
+import argparse
+import numpy as np
+import msgpack
+from tree_ensemble import tree_ensemble_layer, sequential_model_dict
+
+# Converts scikit-learn pipelines (saved with skops or joblib) to the msgpack config
+# Supported steps: StandardScaler and MinMaxScaler (folded into the next step), linear and
+# logistic regression, MLPs, and decision tree ensembles (decision trees, random forests, extra
+# trees, and gradient boosting)
+
+LINEAR_MODELS = ['LinearRegression', 'Ridge', 'Lasso', 'ElasticNet', 'LogisticRegression',
+                 'LinearSVC', 'SGDClassifier', 'SGDRegressor']
+FOREST_MODELS = ['DecisionTreeClassifier', 'DecisionTreeRegressor', 'RandomForestClassifier',
+                 'RandomForestRegressor', 'ExtraTreesClassifier', 'ExtraTreesRegressor']
+BOOSTED_MODELS = ['GradientBoostingClassifier', 'GradientBoostingRegressor']
+
+# skops only loads the types it trusts by default, and the types of the file outside of them only
+# if they are in the trusted list. joblib files are pickles, which run code when loaded, so they
+# are only loaded with allow_pickle.
+def load_pipeline(path, trusted=(), allow_pickle=False):
+  if path.endswith('.skops'):
+    import skops.io as sio
+    untrusted = [t for t in sio.get_untrusted_types(file=path) if t not in trusted]
+    if untrusted:
+      raise RuntimeError(f'{path} has untrusted types, pass them to --trusted if they are safe: '
+                         f'{", ".join(untrusted)}')
+    return sio.load(path, trusted=list(trusted))
+  if path.endswith('.onnx'):
+    raise NotImplementedError('Use the ONNX importer for ONNX exports')
+  if not allow_pickle:
+    raise RuntimeError(f'{path} is a pickle, which can run code when loaded; save it with skops, '
+                       'or pass --allow_pickle if it is from a trusted source')
+  import joblib
+  return joblib.load(path)
+
+def get_steps(model):
+  if type(model).__name__ == 'Pipeline':
+    return [step for _, step in model.steps if step != 'passthrough' and step is not None]
+  return [model]
+
+# Scalers are x' = scale * x + offset
+def scaler_affine(step, num_features):
+  name = type(step).__name__
+  if name == 'StandardScaler':
+    mean = step.mean_ if step.with_mean else np.zeros(num_features)
+    std = step.scale_ if step.with_std else np.ones(num_features)
+    return 1. / std, -mean / std
+  if name == 'MinMaxScaler':
+    return step.scale_, step.min_
+  raise NotImplementedError(f'Unsupported scaler: {name}')
+
+def compose_affine(first, second):
+  if first is None:
+    return second
+  (s1, o1), (s2, o2) = first, second
+  return s1 * s2, o1 * s2 + o2
+
+# W (scale * x + offset) + b = (W scale) x + (W offset + b), with W as [out, in]
+def fold_affine(weight, bias, affine):
+  if affine is None:
+    return weight, bias
+  scale, offset = affine
+  return weight * scale[None, :], bias + weight @ offset
+
+def sklearn_tree_nodes(tree, normalize):
+  tree = tree.tree_
+  nodes = []
+  for i in range(tree.node_count):
+    if tree.children_left[i] == -1:
+      value = tree.value[i][0].astype(np.float64)
+      if normalize:
+        value = value / value.sum()
+      nodes.append({'value': value.tolist()})
+    else:
+      nodes.append({
+        'left': int(tree.children_left[i]),
+        'right': int(tree.children_right[i]),
+        'feature': int(tree.feature[i]),
+        'threshold': float(tree.threshold[i]),
+      })
+  return nodes
+
+class SklearnConverter:
+  def __init__(self, pipeline, num_features, scale_factor, k, num_cols, num_randoms,
+               use_selectors, commit, raw_scores):
+    self.pipeline = pipeline
+    self.num_features = num_features
+    self.scale_factor = scale_factor
+    self.k = k
+    self.num_cols = num_cols
+    self.num_randoms = num_randoms
+    self.use_selectors = use_selectors
+    self.commit = commit
+    self.raw_scores = raw_scores
+
+  def _linear(self, step, affine):
+    weight = np.atleast_2d(np.asarray(step.coef_, dtype=np.float64))
+    bias = np.atleast_1d(np.asarray(step.intercept_, dtype=np.float64))
+    if bias.shape[0] != weight.shape[0]:
+      bias = np.broadcast_to(bias, (weight.shape[0],)).copy()
+    weight, bias = fold_affine(weight, bias, affine)
+    num_out = weight.shape[0]
+
+    layers = [('FullyConnected', [0], [weight, bias], [1, num_out])]
+    if type(step).__name__ == 'LogisticRegression' and not self.raw_scores:
+      layers.append(('Logistic' if num_out == 1 else 'Softmax', [], [], [1, num_out]))
+    return layers
+
+  def _mlp(self, step, affine):
+    layers = []
+    for i, (coef, intercept) in enumerate(zip(step.coefs_, step.intercepts_)):
+      # sklearn stores the weights as [in, out]
+      weight = np.asarray(coef, dtype=np.float64).T
+      bias = np.asarray(intercept, dtype=np.float64)
+      if i == 0:
+        weight, bias = fold_affine(weight, bias, affine)
+      num_out = weight.shape[0]
+
+      is_last = i == len(step.coefs_) - 1
+      activation = step.out_activation_ if is_last else step.activation
+      if activation == 'relu':
+        layers.append(('FullyConnected', [1], [weight, bias], [1, num_out]))
+        continue
+      layers.append(('FullyConnected', [0], [weight, bias], [1, num_out]))
+      if is_last and self.raw_scores:
+        continue
+      if activation == 'tanh':
+        layers.append(('Tanh', [], [], [1, num_out]))
+      elif activation == 'logistic':
+        layers.append(('Logistic', [], [], [1, num_out]))
+      elif activation == 'softmax':
+        layers.append(('Softmax', [], [], [1, num_out]))
+      elif activation != 'identity':
+        raise NotImplementedError(f'Unsupported MLP activation: {activation}')
+    return layers
+
+  def _forest(self, step, affine):
+    name = type(step).__name__
+    is_classifier = name.endswith('Classifier')
+    estimators = step.estimators_ if hasattr(step, 'estimators_') else [step]
+
+    trees = []
+    for est in estimators:
+      nodes = sklearn_tree_nodes(est, is_classifier)
+      # Forests average the trees
+      for node in nodes:
+        if 'value' in node:
+          node['value'] = (np.array(node['value']) / len(estimators)).tolist()
+      trees.append(nodes)
+    first_leaf = next(node for node in trees[0] if 'value' in node)
+    num_outputs = len(first_leaf['value'])
+
+    params, thresholds, leaf_values = tree_ensemble_layer(
+      trees, num_outputs, self.scale_factor, affine=affine)
+    return [('TreeEnsemble', params, [thresholds, leaf_values], [1, num_outputs])]
+
+  def _boosted(self, step, affine):
+    is_classifier = type(step).__name__.endswith('Classifier')
+    estimators = step.estimators_
+    num_outputs = estimators.shape[1]
+
+    # Tree k of every stage contributes to output k
+    trees = []
+    for stage in estimators:
+      for out_idx, est in enumerate(stage):
+        nodes = sklearn_tree_nodes(est, False)
+        for node in nodes:
+          if 'value' in node:
+            value = np.zeros(num_outputs)
+            value[out_idx] = node['value'][0] * step.learning_rate
+            node['value'] = value.tolist()
+        trees.append(nodes)
+
+    zero = np.zeros((1, self.num_features))
+    if affine is not None:
+      # The init estimator sees the scaled features
+      zero = zero * affine[0] + affine[1]
+    base_score = step._raw_predict_init(zero)[0]
+
+    params, thresholds, leaf_values = tree_ensemble_layer(
+      trees, num_outputs, self.scale_factor, base_score=base_score, affine=affine)
+    layers = [('TreeEnsemble', params, [thresholds, leaf_values], [1, num_outputs])]
+    if is_classifier and not self.raw_scores:
+      layers.append(('Logistic' if num_outputs == 1 else 'Softmax', [], [], [1, num_outputs]))
+    return layers
+
+  def to_dict(self):
+    steps = get_steps(self.pipeline)
+    affine = None
+    layers = []
+    for i, step in enumerate(steps):
+      name = type(step).__name__
+      if name in ['StandardScaler', 'MinMaxScaler']:
+        affine = compose_affine(affine, scaler_affine(step, self.num_features))
+        continue
+      if i != len(steps) - 1:
+        raise NotImplementedError(f'Only scalers may precede the final estimator: {name}')
+
+      if name in LINEAR_MODELS:
+        layers += self._linear(step, affine)
+      elif name in ['MLPClassifier', 'MLPRegressor']:
+        layers += self._mlp(step, affine)
+      elif name in FOREST_MODELS:
+        layers += self._forest(step, affine)
+      elif name in BOOSTED_MODELS:
+        layers += self._boosted(step, affine)
+      else:
+        raise NotImplementedError(f'Unsupported estimator: {name}')
+      affine = None
+
+    if affine is not None:
+      raise NotImplementedError('The pipeline must end with an estimator')
+
+    return sequential_model_dict(
+      layers,
+      [1, self.num_features],
+      self.scale_factor,
+      self.k,
+      self.num_cols,
+      self.num_randoms,
+      self.use_selectors,
+      self.commit,
+    )
+
+  def to_msgpack(self):
+    d = self.to_dict()
+    return msgpack.packb(d, use_bin_type=True)
+
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--model', type=str, required=True, help='.skops or joblib pipeline')
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--trusted', type=str, nargs='*', default=[],
+                      help='types of the .skops file to load besides the ones skops trusts')
+  parser.add_argument('--allow_pickle', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--num_features', type=int, required=False, default=None)
+  parser.add_argument('--raw_scores', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--scale_factor', type=int, default=2**8)
+  parser.add_argument('--k', type=int, default=17)
+  parser.add_argument('--num_cols', type=int, default=10)
+  parser.add_argument('--use_selectors', action=argparse.BooleanOptionalAction, required=False, default=True)
+  parser.add_argument('--commit', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--num_randoms', type=int, default=1024)
+  args = parser.parse_args()
+
+  pipeline = load_pipeline(args.model, args.trusted, args.allow_pickle)
+  num_features = args.num_features
+  if num_features is None:
+    num_features = getattr(pipeline, 'n_features_in_', None)
+    if num_features is None:
+      raise RuntimeError('Could not infer the number of features, pass --num_features')
+
+  converter = SklearnConverter(
+    pipeline,
+    num_features,
+    args.scale_factor,
+    args.k,
+    args.num_cols,
+    args.num_randoms,
+    args.use_selectors,
+    args.commit,
+    args.raw_scores,
+  )
+
+  packed = converter.to_msgpack()
+  with open(args.output, 'wb') as f:
+    f.write(packed)
+
+if __name__ == '__main__':
+  main()
@@ -0,0 +1,53 @@
+# Runs an int8 TFLite model in the TFLite interpreter and writes the reference for tflite_parity:
+#
+#   {"inputs": [[...]], "outputs": [[...]], "output_zero_points": [...]}
+#
+# with one flattened array of quantized values per subgraph input and output. The inputs are
+# random unless given as .npy files, one per subgraph input. The interpreter uses the reference
+# kernels, which the parity mode of the TFLite loader computes in-circuit.
+
+import argparse
+import json
+import numpy as np
+import tensorflow as tf
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--model', type=str, required=True)
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--inputs', type=str, nargs='*', default=[])
+  parser.add_argument('--seed', type=int, default=0)
+  args = parser.parse_args()
+
+  interpreter = tf.lite.Interpreter(
+    model_path=args.model,
+    experimental_op_resolver_type=tf.lite.experimental.OpResolverType.BUILTIN_REF,
+  )
+  interpreter.allocate_tensors()
+  rng = np.random.default_rng(args.seed)
+
+  inputs = []
+  for i, detail in enumerate(interpreter.get_input_details()):
+    if detail['dtype'] != np.int8:
+      raise RuntimeError('Input {} is not int8, convert with int8 inputs'.format(i))
+    if i < len(args.inputs):
+      inp = np.load(args.inputs[i]).astype(np.int8).reshape(detail['shape'])
+    else:
+      inp = rng.integers(-128, 128, size=detail['shape'], dtype=np.int8)
+    interpreter.set_tensor(detail['index'], inp)
+    inputs.append(inp.flatten().tolist())
+  interpreter.invoke()
+
+  outputs = []
+  zero_points = []
+  for i, detail in enumerate(interpreter.get_output_details()):
+    if detail['dtype'] != np.int8:
+      raise RuntimeError('Output {} is not int8, convert with int8 outputs'.format(i))
+    outputs.append(interpreter.get_tensor(detail['index']).flatten().tolist())
+    zero_points.append(int(detail['quantization'][1]))
+
+  with open(args.output, 'w') as f:
+    json.dump({'inputs': inputs, 'outputs': outputs, 'output_zero_points': zero_points}, f)
+
+if __name__ == '__main__':
+  main()
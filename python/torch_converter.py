@@ -0,0 +1,334 @@
+import argparse
+import numpy as np
+import msgpack
+import yaml
+
+# Converts a PyTorch state_dict plus a small YAML architecture description to the msgpack config
+# The architecture is a sequential list of layers, e.g.,
+#
+#   input_shape: [1, 1, 28, 28]  # NCHW (or NCDHW), as in PyTorch
+#   layers:
+#     - {type: conv2d, name: conv1, stride: 1, padding: valid}
+#     - {type: relu}
+#     - {type: maxpool2d, kernel_size: 2, stride: 2}
+#     - {type: flatten}
+#     - {type: linear, name: fc1}
+#     - {type: softmax}
+#
+# `name` is the state_dict prefix of the layer's weight and bias. Tensors are converted to the
+# NHWC layout used by the circuit, and ReLU/ReLU6 are fused into the preceding conv or linear.
+# Video and volumetric models use conv3d, maxpool3d, and avgpool3d over NDHWC instead.
+
+# TFLite activation function types, which the conv and fully connected layers use
+ACTIVATIONS = {
+  'relu': 1,
+  'relu6': 3,
+}
+
+def load_state_dict(path):
+  if path.endswith('.safetensors'):
+    from safetensors.numpy import load_file
+    return load_file(path)
+
+  import torch
+  state_dict = torch.load(path, map_location='cpu')
+  if 'state_dict' in state_dict:
+    state_dict = state_dict['state_dict']
+  return {k: v.detach().cpu().numpy() for k, v in state_dict.items()}
+
+def as_pair(x):
+  if isinstance(x, (list, tuple)):
+    return list(x)
+  return [x, x]
+
+def as_triple(x):
+  if isinstance(x, (list, tuple)):
+    return list(x)
+  return [x, x, x]
+
+def out_hw(h, w, kernel, stride, padding):
+  (kh, kw), (sh, sw) = kernel, stride
+  if padding == 'same':
+    return (h + sh - 1) // sh, (w + sw - 1) // sw
+  return (h - kh) // sh + 1, (w - kw) // sw + 1
+
+def out_len(x, kernel, stride, padding):
+  if padding == 'same':
+    return (x + stride - 1) // stride
+  return (x - kernel) // stride + 1
+
+class TorchConverter:
+  def __init__(self, state_dict, arch, scale_factor, k, num_cols, num_randoms, use_selectors,
+               commit):
+    self.state_dict = state_dict
+    self.arch = arch
+    self.scale_factor = scale_factor
+    self.k = k
+    self.num_cols = num_cols
+    self.num_randoms = num_randoms
+    self.use_selectors = use_selectors
+    self.commit = commit
+
+    self.tensors = []
+    self.next_idx = 1
+
+  def _get(self, name, suffix):
+    key = f'{name}.{suffix}'
+    if key not in self.state_dict:
+      if suffix == 'bias':
+        return None
+      raise KeyError(f'{key} is not in the state_dict')
+    return np.asarray(self.state_dict[key], dtype=np.float64)
+
+  def _add_tensor(self, data):
+    idx = self.next_idx
+    self.next_idx += 1
+    self.tensors.append({
+      'idx': idx,
+      'shape': list(data.shape),
+      'data': (data * self.scale_factor).round().astype(np.int64).flatten().tolist(),
+    })
+    return idx
+
+  def _new_idx(self):
+    idx = self.next_idx
+    self.next_idx += 1
+    return idx
+
+  def _conv2d(self, spec, shape, activation):
+    _, h, w, c = shape
+    weight = self._get(spec['name'], 'weight')
+    bias = self._get(spec['name'], 'bias')
+    cout, cin, kh, kw = weight.shape
+    groups = spec.get('groups', 1)
+
+    if groups == c and cin == 1 and cout % c == 0:
+      # Depthwise, with cout / c output channels per input channel: [cout, 1, kh, kw] ->
+      # [1, kh, kw, cout]
+      weight = np.transpose(weight, (1, 2, 3, 0))
+      conv_type = 1
+    elif c % groups == 0 and cout % groups == 0:
+      # OIHW -> OHWI, with the input channels of a group
+      weight = np.transpose(weight, (0, 2, 3, 1))
+      conv_type = 0
+    else:
+      raise NotImplementedError(f'{groups} groups do not split the channels: {spec}')
+    if as_pair(spec.get('dilation', 1)) != [1, 1]:
+      raise NotImplementedError('Dilation is not supported')
+
+    padding = spec.get('padding', 'valid')
+    if padding == 0:
+      padding = 'valid'
+    if padding not in ['valid', 'same']:
+      raise NotImplementedError(f'Only valid and same padding are supported: {padding}')
+    stride = as_pair(spec.get('stride', 1))
+    if bias is None:
+      bias = np.zeros(cout)
+
+    inp_idxes = [self._add_tensor(weight), self._add_tensor(bias)]
+    oh, ow = out_hw(h, w, (kh, kw), stride, padding)
+    # 0 is SAME, 1 is VALID
+    params = [conv_type, 0 if padding == 'same' else 1, activation] + stride
+    if conv_type == 0 and groups > 1:
+      params.append(groups)
+    return 'Conv2D', params, inp_idxes, [1, oh, ow, cout]
+
+  def _conv3d(self, spec, shape, activation):
+    _, d, h, w, c = shape
+    weight = self._get(spec['name'], 'weight')
+    bias = self._get(spec['name'], 'bias')
+    cout, cin, kd, kh, kw = weight.shape
+    if spec.get('groups', 1) != 1 or cin != c:
+      raise NotImplementedError(f'Grouped 3D convolutions are not supported: {spec}')
+    if as_triple(spec.get('dilation', 1)) != [1, 1, 1]:
+      raise NotImplementedError('Dilation is not supported')
+    padding = spec.get('padding', 'valid')
+    if padding == 0:
+      padding = 'valid'
+    if padding not in ['valid', 'same']:
+      raise NotImplementedError(f'Only valid and same padding are supported: {padding}')
+    stride = as_triple(spec.get('stride', 1))
+    if bias is None:
+      bias = np.zeros(cout)
+
+    # OIDHW -> ODHWI
+    weight = np.transpose(weight, (0, 2, 3, 4, 1))
+    inp_idxes = [self._add_tensor(weight), self._add_tensor(bias)]
+    od, oh, ow = [out_len(x, k, s, padding) for x, k, s in zip([d, h, w], [kd, kh, kw], stride)]
+    # 0 is SAME, 1 is VALID
+    params = [0 if padding == 'same' else 1, activation] + stride
+    return 'Conv3D', params, inp_idxes, [1, od, oh, ow, cout]
+
+  def _linear(self, spec, shape, activation, flattened_chw):
+    weight = self._get(spec['name'], 'weight')
+    bias = self._get(spec['name'], 'bias')
+    out_features, in_features = weight.shape
+    if activation not in [0, 1]:
+      raise NotImplementedError('Only ReLU can be fused into a linear layer')
+
+    # PyTorch flattens in CHW order, but the circuit flattens NHWC tensors
+    if flattened_chw is not None:
+      weight = weight.reshape(out_features, *flattened_chw)
+      perm = [0] + list(range(2, len(flattened_chw) + 1)) + [1]
+      weight = np.transpose(weight, perm).reshape(out_features, in_features)
+    if shape[-1] != in_features:
+      raise RuntimeError(f'Linear input size mismatch: {shape} vs {weight.shape}')
+    if bias is None:
+      bias = np.zeros(out_features)
+
+    inp_idxes = [self._add_tensor(weight), self._add_tensor(bias)]
+    return 'FullyConnected', [activation], inp_idxes, [1, out_features]
+
+  def _pool2d(self, layer_type, spec, shape):
+    _, h, w, c = shape
+    kernel = as_pair(spec['kernel_size'])
+    stride = as_pair(spec.get('stride', spec['kernel_size']))
+    if spec.get('padding', 0) != 0:
+      raise NotImplementedError('Padded pooling is not supported')
+    oh, ow = out_hw(h, w, kernel, stride, 'valid')
+    return layer_type, kernel + stride, [], [1, oh, ow, c]
+
+  def _pool3d(self, layer_type, spec, shape):
+    _, d, h, w, c = shape
+    kernel = as_triple(spec['kernel_size'])
+    stride = as_triple(spec.get('stride', spec['kernel_size']))
+    if spec.get('padding', 0) != 0:
+      raise NotImplementedError('Padded pooling is not supported')
+    out_dhw = [out_len(x, k, s, 'valid') for x, k, s in zip([d, h, w], kernel, stride)]
+    return layer_type, kernel + stride, [], [1] + out_dhw + [c]
+
+  def to_dict(self):
+    inp_shape = list(self.arch['input_shape'])
+    if len(inp_shape) in [4, 5]:
+      shape = [inp_shape[0]] + inp_shape[2:] + [inp_shape[1]]
+    else:
+      shape = inp_shape
+
+    layers = []
+    cur_idx = 0
+    flattened_chw = None
+    specs = self.arch['layers']
+    i = 0
+    while i < len(specs):
+      spec = specs[i]
+      layer_type = spec['type'].lower()
+
+      # Fuse the following activation if possible
+      activation = 0
+      if layer_type in ['conv2d', 'conv3d', 'linear'] and i + 1 < len(specs):
+        next_type = specs[i + 1]['type'].lower()
+        if next_type in ACTIVATIONS:
+          activation = ACTIVATIONS[next_type]
+          i += 1
+
+      if layer_type == 'conv2d':
+        out = self._conv2d(spec, shape, activation)
+      elif layer_type == 'conv3d':
+        out = self._conv3d(spec, shape, activation)
+      elif layer_type == 'linear':
+        out = self._linear(spec, shape, activation, flattened_chw)
+        flattened_chw = None
+      elif layer_type == 'maxpool2d':
+        out = self._pool2d('MaxPool2D', spec, shape)
+      elif layer_type == 'avgpool2d':
+        out = self._pool2d('AveragePool2D', spec, shape)
+      elif layer_type == 'maxpool3d':
+        out = self._pool3d('MaxPool3D', spec, shape)
+      elif layer_type == 'avgpool3d':
+        out = self._pool3d('AveragePool3D', spec, shape)
+      elif layer_type == 'flatten':
+        if len(shape) in [4, 5]:
+          flattened_chw = (shape[-1],) + tuple(shape[1:-1])
+        out = ('Reshape', [], [], [shape[0], int(np.prod(shape[1:]))])
+      elif layer_type == 'tanh':
+        out = ('Tanh', [], [], shape)
+      elif layer_type == 'sigmoid':
+        out = ('Logistic', [], [], shape)
+      elif layer_type == 'gelu':
+        out = ('Gelu', [], [], shape)
+      elif layer_type in ['silu', 'swish']:
+        out = ('Silu', [], [], shape)
+      elif layer_type == 'softmax':
+        out = ('Softmax', [], [], shape)
+      elif layer_type in ACTIVATIONS:
+        raise NotImplementedError(f'{layer_type} must follow a conv or linear layer')
+      else:
+        raise NotImplementedError(f'Unsupported layer at {i}: {spec}')
+
+      layer_type, params, weight_idxes, out_shape = out
+      out_idx = self._new_idx()
+      inp_idxes = [cur_idx] + weight_idxes
+      shapes = {t['idx']: t['shape'] for t in self.tensors}
+      layers.append({
+        'layer_type': layer_type,
+        'inp_idxes': inp_idxes,
+        'inp_shapes': [shape] + [shapes[idx] for idx in weight_idxes],
+        'out_idxes': [out_idx],
+        'out_shapes': [out_shape],
+        'params': params,
+        'mask': [],
+      })
+      cur_idx = out_idx
+      shape = out_shape
+      i += 1
+
+    commit_before = []
+    commit_after = []
+    if self.commit:
+      weight_tensors = [tensor['idx'] for tensor in self.tensors]
+      commit_before = [weight_tensors, [0]]
+      commit_after = [[cur_idx]]
+
+    return {
+      'global_sf': self.scale_factor,
+      'k': self.k,
+      'num_cols': self.num_cols,
+      'num_random': self.num_randoms,
+      'inp_idxes': [0],
+      'out_idxes': [cur_idx],
+      'layers': layers,
+      'tensors': self.tensors,
+      'use_selectors': self.use_selectors,
+      'commit_before': commit_before,
+      'commit_after': commit_after,
+    }
+
+  def to_msgpack(self):
+    d = self.to_dict()
+    return msgpack.packb(d, use_bin_type=True)
+
+
+def main():
+  parser = argparse.ArgumentParser()
+  parser.add_argument('--weights', type=str, required=True, help='.safetensors or .pt state_dict')
+  parser.add_argument('--arch', type=str, required=True, help='YAML architecture description')
+  parser.add_argument('--output', type=str, required=True)
+  parser.add_argument('--scale_factor', type=int, default=2**16)
+  parser.add_argument('--k', type=int, default=19)
+  parser.add_argument('--num_cols', type=int, default=6)
+  parser.add_argument('--use_selectors', action=argparse.BooleanOptionalAction, required=False, default=True)
+  parser.add_argument('--commit', action=argparse.BooleanOptionalAction, required=False, default=False)
+  parser.add_argument('--num_randoms', type=int, default=20001)
+  args = parser.parse_args()
+
+  with open(args.arch, 'r') as f:
+    arch = yaml.safe_load(f)
+  state_dict = load_state_dict(args.weights)
+
+  converter = TorchConverter(
+    state_dict,
+    arch,
+    args.scale_factor,
+    args.k,
+    args.num_cols,
+    args.num_randoms,
+    args.use_selectors,
+    args.commit,
+  )
+
+  packed = converter.to_msgpack()
+  with open(args.output, 'wb') as f:
+    f.write(packed)
+
+if __name__ == '__main__':
+  main()
@@ -0,0 +1,115 @@
+import numpy as np
+
+# Helpers for converting tree ensembles to the TreeEnsemble layer
+# A tree is a list of nodes and node 0 is the root. Internal nodes are
+#   {'left': i, 'right': j, 'feature': f, 'threshold': t}
+# and go left if x[f] <= t (or x[f] < t if strict). Leaves are {'value': [v_0, ..., v_{C-1}]}.
+# The circuit compares round(x * sf) against the quantized thresholds, so |x - t| * sf must be
+# less than the number of rows (roughly 2^k).
+
+# x <= t is round(x * sf) <= floor(t * sf) and x < t is round(x * sf) <= ceil(t * sf) - 1, up
+# to the rounding of x
+def quantize_threshold(threshold, scale_factor, strict):
+  if strict:
+    return int(np.ceil(threshold * scale_factor)) - 1
+  return int(np.floor(threshold * scale_factor))
+
+# Features seen by the trees may be an affine function (from a folded scaler) of the inputs:
+# x' = scale * x + offset
+def unscale_threshold(threshold, feature, affine):
+  if affine is None:
+    return threshold
+  scale, offset = affine
+  if scale[feature] <= 0:
+    raise NotImplementedError('Only positive feature scales can be folded into the thresholds')
+  return (threshold - offset[feature]) / scale[feature]
+
+def is_leaf(node):
+  return 'value' in node
+
+# Returns the layer params, the quantized thresholds and the quantized leaf values
+# base_score is added to the leaves of the first tree, since exactly one leaf per tree is used
+def tree_ensemble_layer(trees, num_outputs, scale_factor, base_score=None, strict=False,
+                        affine=None):
+  params = [num_outputs, len(trees)]
+  thresholds = []
+  leaf_values = []
+  for tree_idx, tree in enumerate(trees):
+    params.append(len(tree))
+    for node in tree:
+      if is_leaf(node):
+        value = np.array(node['value'], dtype=np.float64)
+        if len(value) != num_outputs:
+          raise RuntimeError(f'Leaf has {len(value)} values, expected {num_outputs}')
+        if tree_idx == 0 and base_score is not None:
+          value = value + np.array(base_score, dtype=np.float64)
+        leaf_values.append(value)
+        params += [0, 0, -1]
+      else:
+        feature = int(node['feature'])
+        threshold = unscale_threshold(node['threshold'], feature, affine)
+        thresholds.append(quantize_threshold(threshold, scale_factor, strict))
+        params += [int(node['left']), int(node['right']), feature]
+
+  thresholds = np.array(thresholds, dtype=np.int64)
+  leaf_values = (np.array(leaf_values) * scale_factor).round().astype(np.int64)
+  return params, thresholds, leaf_values
+
+# Builds a sequential model dict: layers are (layer_type, params, [weight tensors], out_shape)
+def sequential_model_dict(layers, inp_shape, scale_factor, k, num_cols, num_randoms,
+                          use_selectors, commit):
+  tensors = []
+  layer_dicts = []
+  next_idx = 1
+  cur_idx = 0
+  cur_shape = list(inp_shape)
+
+  for layer_type, params, weights, out_shape in layers:
+    weight_idxes = []
+    for weight in weights:
+      # Already quantized tensors are int64
+      if weight.dtype == np.int64:
+        data = weight
+      else:
+        data = (weight * scale_factor).round().astype(np.int64)
+      tensors.append({
+        'idx': next_idx,
+        'shape': list(weight.shape) if len(weight.shape) > 0 else [1],
+        'data': data.flatten().tolist(),
+      })
+      weight_idxes.append(next_idx)
+      next_idx += 1
+
+    out_idx = next_idx
+    next_idx += 1
+    layer_dicts.append({
+      'layer_type': layer_type,
+      'inp_idxes': [cur_idx] + weight_idxes,
+      'inp_shapes': [cur_shape] + [list(w.shape) if len(w.shape) > 0 else [1] for w in weights],
+      'out_idxes': [out_idx],
+      'out_shapes': [list(out_shape)],
+      'params': [int(p) for p in params],
+      'mask': [],
+    })
+    cur_idx = out_idx
+    cur_shape = list(out_shape)
+
+  commit_before = []
+  commit_after = []
+  if commit:
+    commit_before = [[tensor['idx'] for tensor in tensors], [0]]
+    commit_after = [[cur_idx]]
+
+  return {
+    'global_sf': scale_factor,
+    'k': k,
+    'num_cols': num_cols,
+    'num_random': num_randoms,
+    'inp_idxes': [0],
+    'out_idxes': [cur_idx],
+    'layers': layer_dicts,
+    'tensors': tensors,
+    'use_selectors': use_selectors,
+    'commit_before': commit_before,
+    'commit_after': commit_after,
+  }
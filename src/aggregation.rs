@@ -0,0 +1,479 @@
+use std::rc::Rc;
+
+use halo2_proofs::{
+  arithmetic::CurveAffine,
+  circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+  halo2curves::{
+    bn256::{Bn256, Fq, Fr, G1Affine},
+    pairing::Engine,
+  },
+  plonk::{
+    create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ConstraintSystem, Error, ProvingKey,
+    VerifyingKey,
+  },
+  poly::{
+    commitment::{Params, ParamsProver},
+    kzg::{
+      commitment::{KZGCommitmentScheme, ParamsKZG},
+      multiopen::{ProverSHPLONK, VerifierSHPLONK},
+      strategy::SingleStrategy,
+    },
+  },
+  transcript::{
+    Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+  },
+};
+use rand::{rngs::OsRng, RngCore};
+use snark_verifier::{
+  loader::{
+    self,
+    halo2::halo2_wrong_ecc::{
+      self,
+      integer::rns::Rns,
+      maingate::{
+        MainGate, MainGateConfig, MainGateInstructions, RangeChip, RangeConfig, RangeInstructions,
+        RegionCtx,
+      },
+      EccConfig,
+    },
+    native::NativeLoader,
+  },
+  pcs::{
+    kzg::{
+      Bdfg21, KzgAccumulator, KzgAs, KzgSuccinctVerifyingKey, LimbsEncoding,
+      LimbsEncodingInstructions,
+    },
+    AccumulationScheme, AccumulationSchemeProver,
+  },
+  system::halo2::{compile, transcript, Config},
+  util::arithmetic::{fe_from_limbs, fe_to_limbs},
+  verifier::{self, plonk::PlonkProtocol, SnarkVerifier},
+};
+
+use crate::{
+  commitments::poseidon_params::{
+    FULL_ROUNDS, PARTIAL_ROUNDS_T5, TRANSCRIPT_RATE, TRANSCRIPT_WIDTH,
+  },
+  model::ModelCircuit,
+  utils::{
+    errors::ZkmlError,
+    helpers::{instance_columns, instance_slices},
+  },
+};
+
+// Aggregation of inference proofs: the outer circuit runs the succinct verifier of every inner
+// proof and folds the pairing checks into a single KZG accumulator, which the outer proof exposes
+// as limbs. Verifying the aggregated proof is then one SHPLONK proof and one pairing check on the
+// accumulator, however many inner proofs there are.
+// The inner proofs must be made with prove_for_aggregation, since the outer circuit hashes their
+// transcript with Poseidon instead of Blake2b. They can be of different models, but must use the
+// same SRS as the outer circuit, which is downsized to the k of every inner circuit.
+// The public values of the outer proof are the accumulator limbs, then the instance columns of
+// every inner proof in order, so the outputs of every inference are bound.
+
+const LIMBS: usize = 4;
+const BITS: usize = 68;
+const NUM_ACC_LIMBS: usize = 4 * LIMBS;
+
+// Poseidon parameters of the inner transcripts, see poseidon_params.rs
+const T: usize = TRANSCRIPT_WIDTH;
+const RATE: usize = TRANSCRIPT_RATE;
+const R_F: usize = FULL_ROUNDS;
+const R_P: usize = PARTIAL_ROUNDS_T5;
+
+type As = KzgAs<Bn256, Bdfg21>;
+type Svk = KzgSuccinctVerifyingKey<G1Affine>;
+type PlonkSuccinctVerifier = verifier::plonk::PlonkSuccinctVerifier<As, LimbsEncoding<LIMBS, BITS>>;
+type BaseFieldEccChip = halo2_wrong_ecc::BaseFieldEccChip<G1Affine, LIMBS, BITS>;
+type Halo2Loader<'a> = loader::halo2::Halo2Loader<'a, G1Affine, BaseFieldEccChip>;
+type PoseidonTranscript<L, S> =
+  transcript::halo2::PoseidonTranscript<G1Affine, L, S, T, RATE, R_F, R_P>;
+
+// An inner proof, with the public values already split into the instance columns
+#[derive(Clone, Debug)]
+pub struct InferenceProof {
+  pub instances: Vec<Vec<Fr>>,
+  pub proof: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AggregatedProof {
+  // The number of values in every instance column of every inner proof
+  pub num_instances: Vec<Vec<usize>>,
+  pub instances: Vec<Fr>,
+  pub proof: Vec<u8>,
+}
+
+impl AggregatedProof {
+  // The instance columns of every inner proof, as the outer proof exposes them
+  pub fn inner_instances(&self) -> Vec<Vec<Vec<Fr>>> {
+    let mut vals = self.instances[NUM_ACC_LIMBS..].iter().cloned();
+    self
+      .num_instances
+      .iter()
+      .map(|cols| {
+        cols
+          .iter()
+          .map(|len| (&mut vals).take(*len).collect())
+          .collect()
+      })
+      .collect()
+  }
+}
+
+// Proves an inference with the Poseidon transcript, so that the proof can be aggregated
+pub fn prove_for_aggregation<R: RngCore>(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  circuit: ModelCircuit<Fr>,
+  rng: R,
+) -> Result<InferenceProof, ZkmlError> {
+  let public_vals = circuit
+    .compute_public_values()
+    .map_err(ZkmlError::public_values)?;
+  let instances = instance_columns(&public_vals, circuit.num_commitments());
+  let mut transcript = PoseidonTranscript::<NativeLoader, _>::init(vec![]);
+  create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+    params,
+    pk,
+    &[circuit],
+    &[&instance_slices(&instances)[..]],
+    rng,
+    &mut transcript,
+  )
+  .map_err(|e| ZkmlError::prover("proving", &e))?;
+  Ok(InferenceProof {
+    instances,
+    proof: transcript.finalize(),
+  })
+}
+
+#[derive(Clone)]
+struct Snark {
+  protocol: PlonkProtocol<G1Affine>,
+  instances: Vec<Vec<Value<Fr>>>,
+  proof: Value<Vec<u8>>,
+}
+
+impl Snark {
+  fn without_witnesses(&self) -> Self {
+    Snark {
+      protocol: self.protocol.clone(),
+      instances: self
+        .instances
+        .iter()
+        .map(|col| vec![Value::unknown(); col.len()])
+        .collect(),
+      proof: Value::unknown(),
+    }
+  }
+}
+
+// The protocol of an inner circuit, with the SRS downsized to its k
+fn inner_protocol(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  num_instance: Vec<usize>,
+) -> PlonkProtocol<G1Affine> {
+  let mut inner_params = params.clone();
+  inner_params.downsize(vk.get_domain().k());
+  compile(
+    &inner_params,
+    vk,
+    Config::kzg().with_num_instance(num_instance),
+  )
+}
+
+#[derive(Clone)]
+pub struct AggregationConfig {
+  main_gate_config: MainGateConfig,
+  range_config: RangeConfig,
+}
+
+#[derive(Clone)]
+pub struct AggregationCircuit {
+  svk: Svk,
+  snarks: Vec<Snark>,
+  instances: Vec<Fr>,
+  as_proof: Value<Vec<u8>>,
+}
+
+impl AggregationCircuit {
+  // Runs the succinct verifiers natively to get the accumulator and the accumulation proof
+  fn new(
+    params: &ParamsKZG<Bn256>,
+    proofs: &[InferenceProof],
+    vks: &[VerifyingKey<G1Affine>],
+  ) -> Result<Self, String> {
+    if proofs.len() != vks.len() {
+      return Err(format!("{} proofs but {} vks", proofs.len(), vks.len()));
+    }
+    let svk: Svk = params.get_g()[0].into();
+
+    let mut accumulators = vec![];
+    let mut snarks = vec![];
+    for (i, (proof, vk)) in proofs.iter().zip(vks.iter()).enumerate() {
+      let num_instance = proof.instances.iter().map(|col| col.len()).collect();
+      let protocol = inner_protocol(params, vk, num_instance);
+      let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(proof.proof.as_slice());
+      let succinct =
+        PlonkSuccinctVerifier::read_proof(&svk, &protocol, &proof.instances, &mut transcript)
+          .and_then(|x| PlonkSuccinctVerifier::verify(&svk, &protocol, &proof.instances, &x))
+          .map_err(|e| format!("malformed proof {}: {:?}", i, e))?;
+      accumulators.extend(succinct);
+      snarks.push(Snark {
+        protocol,
+        instances: proof
+          .instances
+          .iter()
+          .map(|col| col.iter().map(|x| Value::known(*x)).collect())
+          .collect(),
+        proof: Value::known(proof.proof.clone()),
+      });
+    }
+
+    let mut transcript = PoseidonTranscript::<NativeLoader, _>::new(vec![]);
+    let accumulator = As::create_proof(&Default::default(), &accumulators, &mut transcript, OsRng)
+      .map_err(|e| format!("accumulation failed: {:?}", e))?;
+    let as_proof = transcript.finalize();
+
+    let KzgAccumulator { lhs, rhs } = accumulator;
+    let mut instances = [lhs.x, lhs.y, rhs.x, rhs.y]
+      .map(fe_to_limbs::<_, _, LIMBS, BITS>)
+      .concat();
+    instances.extend(proofs.iter().flat_map(|p| p.instances.iter().flatten()));
+
+    Ok(Self {
+      svk,
+      snarks,
+      instances,
+      as_proof: Value::known(as_proof),
+    })
+  }
+
+  // The circuit the verifier rebuilds the vk from
+  fn empty(
+    params: &ParamsKZG<Bn256>,
+    vks: &[VerifyingKey<G1Affine>],
+    num_instances: &Vec<Vec<usize>>,
+  ) -> Self {
+    let snarks = vks
+      .iter()
+      .zip(num_instances.iter())
+      .map(|(vk, num_instance)| Snark {
+        protocol: inner_protocol(params, vk, num_instance.clone()),
+        instances: num_instance
+          .iter()
+          .map(|len| vec![Value::unknown(); *len])
+          .collect(),
+        proof: Value::unknown(),
+      })
+      .collect();
+    Self {
+      svk: params.get_g()[0].into(),
+      snarks,
+      instances: vec![],
+      as_proof: Value::unknown(),
+    }
+  }
+
+  // Verifies the inner proofs in the circuit, returns the accumulator limbs and the inner instances
+  fn verify_snarks(
+    &self,
+    loader: &Rc<Halo2Loader<'_>>,
+  ) -> Result<(Vec<AssignedCell<Fr, Fr>>, Vec<AssignedCell<Fr, Fr>>), Error> {
+    let mut accumulators = vec![];
+    let mut inner_instances = vec![];
+    for snark in self.snarks.iter() {
+      let protocol = snark.protocol.loaded(loader);
+      let instances = snark
+        .instances
+        .iter()
+        .map(|col| col.iter().map(|x| loader.assign_scalar(*x)).collect())
+        .collect::<Vec<Vec<_>>>();
+      let proof = snark.proof.as_ref().map(Vec::as_slice);
+      let mut transcript = PoseidonTranscript::<Rc<Halo2Loader>, _>::new(loader, proof);
+      let succinct =
+        PlonkSuccinctVerifier::read_proof(&self.svk, &protocol, &instances, &mut transcript)
+          .and_then(|x| PlonkSuccinctVerifier::verify(&self.svk, &protocol, &instances, &x))
+          .map_err(|_| Error::Synthesis)?;
+      accumulators.extend(succinct);
+      inner_instances.extend(instances.into_iter().flatten());
+    }
+
+    let as_proof = self.as_proof.as_ref().map(Vec::as_slice);
+    let mut transcript = PoseidonTranscript::<Rc<Halo2Loader>, _>::new(loader, as_proof);
+    let accumulator = As::read_proof(&Default::default(), &accumulators, &mut transcript)
+      .and_then(|x| As::verify(&Default::default(), &accumulators, &x))
+      .map_err(|_| Error::Synthesis)?;
+
+    let mut limbs = vec![];
+    for point in [accumulator.lhs, accumulator.rhs].iter() {
+      limbs.extend(
+        loader
+          .ecc_chip()
+          .assign_ec_point_to_limbs(&mut loader.ctx_mut(), point.assigned())?,
+      );
+    }
+    let inner_instances = inner_instances
+      .into_iter()
+      .map(|x| x.into_assigned())
+      .collect();
+    Ok((limbs, inner_instances))
+  }
+}
+
+impl Circuit<Fr> for AggregationCircuit {
+  type Config = AggregationConfig;
+  type FloorPlanner = SimpleFloorPlanner;
+  type Params = ();
+
+  fn without_witnesses(&self) -> Self {
+    Self {
+      svk: self.svk,
+      snarks: self.snarks.iter().map(Snark::without_witnesses).collect(),
+      instances: vec![],
+      as_proof: Value::unknown(),
+    }
+  }
+
+  fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+    let main_gate_config = MainGate::<Fr>::configure(meta);
+    let range_config = RangeChip::<Fr>::configure(
+      meta,
+      &main_gate_config,
+      vec![BITS / LIMBS],
+      Rns::<Fq, Fr, LIMBS, BITS>::construct().overflow_lengths(),
+    );
+    AggregationConfig {
+      main_gate_config,
+      range_config,
+    }
+  }
+
+  fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+    let main_gate = MainGate::<Fr>::new(config.main_gate_config.clone());
+    let range_chip = RangeChip::<Fr>::new(config.range_config.clone());
+    range_chip.load_table(&mut layouter)?;
+
+    let (limbs, inner_instances) = layouter.assign_region(
+      || "aggregation",
+      |region| {
+        let ctx = RegionCtx::new(region, 0);
+        let ecc_chip = BaseFieldEccChip::new(EccConfig::new(
+          config.range_config.clone(),
+          config.main_gate_config.clone(),
+        ));
+        let loader = Halo2Loader::new(ecc_chip, ctx);
+        self.verify_snarks(&loader)
+      },
+    )?;
+
+    for (row, cell) in limbs.into_iter().chain(inner_instances).enumerate() {
+      main_gate.expose_public(layouter.namespace(|| "aggregation public"), cell, row)?;
+    }
+    Ok(())
+  }
+}
+
+// Aggregates the inference proofs into a single proof. The params are the SRS of the outer
+// circuit, whose k must fit the verifiers of all of the inner proofs. The outer keys depend on the
+// inner vks and on the number of public values of every inner proof.
+pub fn aggregate(
+  params: &ParamsKZG<Bn256>,
+  proofs: &[InferenceProof],
+  vks: &[VerifyingKey<G1Affine>],
+) -> Result<AggregatedProof, String> {
+  let circuit = AggregationCircuit::new(params, proofs, vks)?;
+  if !decide(params, &circuit.instances) {
+    return Err("an inner proof does not verify".to_string());
+  }
+
+  let vk = keygen_vk(params, &circuit.without_witnesses())
+    .map_err(|e| format!("aggregation keygen failed: {}", e))?;
+  let pk = keygen_pk(params, vk, &circuit.without_witnesses())
+    .map_err(|e| format!("aggregation keygen failed: {}", e))?;
+
+  let instances = circuit.instances.clone();
+  let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+  create_proof::<
+    KZGCommitmentScheme<Bn256>,
+    ProverSHPLONK<'_, Bn256>,
+    Challenge255<G1Affine>,
+    _,
+    Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+    AggregationCircuit,
+  >(
+    params,
+    &pk,
+    &[circuit],
+    &[&[&instances[..]]],
+    OsRng,
+    &mut transcript,
+  )
+  .map_err(|e| format!("aggregation proof failed: {}", e))?;
+
+  Ok(AggregatedProof {
+    num_instances: proofs
+      .iter()
+      .map(|p| p.instances.iter().map(|col| col.len()).collect())
+      .collect(),
+    instances,
+    proof: transcript.finalize(),
+  })
+}
+
+// The vk of the outer circuit for these inner vks
+pub fn aggregation_vk(
+  params: &ParamsKZG<Bn256>,
+  vks: &[VerifyingKey<G1Affine>],
+  num_instances: &Vec<Vec<usize>>,
+) -> Result<VerifyingKey<G1Affine>, Error> {
+  keygen_vk(
+    params,
+    &AggregationCircuit::empty(params, vks, num_instances),
+  )
+}
+
+// The pairing check the inner proofs were folded into: e(lhs, g2) == e(rhs, s g2)
+fn decide(params: &ParamsKZG<Bn256>, instances: &[Fr]) -> bool {
+  if instances.len() < NUM_ACC_LIMBS {
+    return false;
+  }
+  let coords = instances[..NUM_ACC_LIMBS]
+    .chunks(LIMBS)
+    .map(|limbs| fe_from_limbs::<Fr, Fq, LIMBS, BITS>(limbs.try_into().unwrap()))
+    .collect::<Vec<_>>();
+  let lhs: Option<G1Affine> = G1Affine::from_xy(coords[0], coords[1]).into();
+  let rhs: Option<G1Affine> = G1Affine::from_xy(coords[2], coords[3]).into();
+  match (lhs, rhs) {
+    (Some(lhs), Some(rhs)) => {
+      Bn256::pairing(&lhs, &params.g2()) == Bn256::pairing(&rhs, &params.s_g2())
+    }
+    _ => false,
+  }
+}
+
+// Verifies the outer proof against the vk of the outer circuit, then checks the accumulator
+pub fn verify_aggregated(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  aggregated: &AggregatedProof,
+) -> bool {
+  let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&aggregated.proof[..]);
+  let proof_ok = verify_proof::<
+    KZGCommitmentScheme<Bn256>,
+    VerifierSHPLONK<'_, Bn256>,
+    Challenge255<G1Affine>,
+    Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+    SingleStrategy<'_, Bn256>,
+  >(
+    params.verifier_params(),
+    vk,
+    SingleStrategy::new(params),
+    &[&[&aggregated.instances[..]]],
+    &mut transcript,
+  )
+  .is_ok();
+  proof_ok && decide(params, &aggregated.instances)
+}
@@ -0,0 +1,42 @@
+use std::{fs::File, io::BufWriter};
+
+use zkml::utils::accuracy::{accuracy_report, load_dataset};
+
+// Reports the accuracy of the quantized model on a labeled dataset, and its error against the
+// outputs of the float model, without proving. See utils/accuracy.rs for the dataset format.
+// Usage: accuracy-report <config> <dataset json> [report json]
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let dataset_fname = std::env::args().nth(2).expect("dataset file path");
+  let report_fname = std::env::args().nth(3);
+
+  let samples = load_dataset(&dataset_fname).unwrap();
+  let report = accuracy_report(&config_fname, &samples).unwrap();
+
+  println!("{} samples", report.num_samples);
+  let percent = |x: Option<f64>| x.map_or("n/a".to_string(), |x| format!("{:.2}%", 100. * x));
+  println!(
+    "top-1: {}, top-5: {} ({} labeled)",
+    percent(report.top1),
+    percent(report.top5),
+    report.num_labeled
+  );
+  println!(
+    "top-1 agreement with the float model: {} ({} with reference outputs)",
+    percent(report.reference_top1),
+    report.num_with_reference
+  );
+  if let (Some(mae), Some(rmse), Some(max)) =
+    (report.mean_abs_error, report.rmse, report.max_abs_error)
+  {
+    println!(
+      "error vs the float model: mean abs {:.6}, rmse {:.6}, max abs {:.6}",
+      mae, rmse, max
+    );
+  }
+
+  if let Some(report_fname) = report_fname {
+    let writer = BufWriter::new(File::create(report_fname).unwrap());
+    serde_json::to_writer_pretty(writer, &report).unwrap();
+  }
+}
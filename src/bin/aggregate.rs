@@ -0,0 +1,63 @@
+use std::time::Instant;
+
+use halo2_proofs::{halo2curves::bn256::Fr, poly::commitment::Params};
+use zkml::{
+  aggregation::{aggregate, aggregation_vk, prove_for_aggregation, verify_aggregated},
+  model::ModelCircuit,
+  utils::{helpers::prover_rng, keygen::keygen_kzg, proving_kzg::get_kzg_params},
+};
+
+// Proves every input and aggregates the proofs into one
+// Usage: aggregate <params dir> <outer k> <config> <input> [<config> <input> ...]
+// The inner circuits use the SRS of the outer circuit, downsized to their k.
+fn main() {
+  let params_dir = std::env::args().nth(1).expect("params directory");
+  let outer_k = std::env::args()
+    .nth(2)
+    .expect("outer k")
+    .parse::<u32>()
+    .unwrap();
+  let args = std::env::args().skip(3).collect::<Vec<_>>();
+  assert!(
+    !args.is_empty() && args.len() % 2 == 0,
+    "expected pairs of config and input files"
+  );
+
+  let params = get_kzg_params(&params_dir, outer_k);
+  let mut proofs = vec![];
+  let mut vks = vec![];
+  for pair in args.chunks(2) {
+    let circuit = ModelCircuit::<Fr>::generate_from_file(&pair[0], &pair[1]);
+    let mut inner_params = params.clone();
+    inner_params.downsize(circuit.k as u32);
+    let pk = keygen_kzg(&inner_params, &circuit).unwrap();
+    let rng = prover_rng(circuit.zero_knowledge);
+    let proof = prove_for_aggregation(&inner_params, &pk, circuit, rng).unwrap();
+    println!("proved {} ({} bytes)", pair[1], proof.proof.len());
+    proofs.push(proof);
+    vks.push(pk.get_vk().clone());
+  }
+
+  let start = Instant::now();
+  let aggregated = aggregate(&params, &proofs, &vks).unwrap();
+  println!(
+    "aggregated {} proofs in {:?} ({} bytes)",
+    proofs.len(),
+    start.elapsed(),
+    aggregated.proof.len()
+  );
+
+  let vk = aggregation_vk(&params, &vks, &aggregated.num_instances).unwrap();
+  assert!(
+    verify_aggregated(&params, &vk, &aggregated),
+    "aggregated proof did not verify"
+  );
+  assert_eq!(
+    aggregated.inner_instances(),
+    proofs
+      .iter()
+      .map(|p| p.instances.clone())
+      .collect::<Vec<_>>()
+  );
+  println!("aggregated proof verified");
+}
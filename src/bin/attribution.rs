@@ -0,0 +1,74 @@
+use std::{fs::File, io::BufReader};
+
+use halo2_proofs::{circuit::Value, dev::MockProver, halo2curves::bn256::Fr};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    attribution::{attribution_model, top_features},
+    helpers::{convert_pos_int, instance_columns},
+    loader::{load_config_msgpack, save_model_msgpack, TensorMsgpack},
+    robustness::argmax,
+  },
+};
+
+// Builds the occlusion attribution circuit for a model and an input
+// Usage: attribution <config> <input> <k> <output dir> [features]
+// The features are comma separated flat input positions and default to every input element. The
+// class is the prediction of the model on the input. The attribution config and input are
+// written to the output dir, to be proven as usual.
+
+// The public values after the first `skip`, as integers
+fn public_values(config_fname: &str, inp_fname: &str, skip: usize) -> Vec<i64> {
+  let circuit = ModelCircuit::<Fr>::generate_from_file(config_fname, inp_fname);
+  let public_vals = circuit.compute_public_values().unwrap();
+  let prover = MockProver::run(
+    circuit.k as u32,
+    &circuit,
+    instance_columns(&public_vals, circuit.num_commitments()),
+  )
+  .unwrap();
+  assert_eq!(prover.verify(), Ok(()));
+  public_vals[skip..]
+    .iter()
+    .map(|x| convert_pos_int(Value::known(*x)) as i64)
+    .collect()
+}
+
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let k = std::env::args()
+    .nth(3)
+    .expect("number of top features")
+    .parse::<i64>()
+    .unwrap();
+  let outp_dir = std::env::args().nth(4).expect("output directory");
+
+  let mut reader = BufReader::new(File::open(&inp_fname).unwrap());
+  let inp: Vec<TensorMsgpack> = rmp_serde::from_read(&mut reader).unwrap();
+  let features = match std::env::args().nth(5) {
+    Some(features) => features
+      .split(',')
+      .map(|x| x.trim().parse::<i64>().unwrap())
+      .collect::<Vec<_>>(),
+    None => (0..inp[0].shape.iter().product::<i64>()).collect(),
+  };
+
+  // The class is the prediction on the input
+  let model = load_config_msgpack(&config_fname);
+  let num_commitments = model.commit_before.clone().unwrap_or(vec![]).len()
+    + model.commit_after.clone().unwrap_or(vec![]).len();
+  let logits = public_values(&config_fname, &inp_fname, num_commitments);
+  let class = argmax(&logits);
+  println!("class: {}", class);
+
+  let attribution = attribution_model(&model, &inp, &features, class as i64, k);
+  std::fs::create_dir_all(&outp_dir).unwrap();
+  let attr_config = format!("{}/attribution.msgpack", outp_dir);
+  let attr_inp = format!("{}/attribution_inp.msgpack", outp_dir);
+  save_model_msgpack(&attribution, &attr_config, &attr_inp);
+
+  // Skip the weight and input commitments
+  let mask = public_values(&attr_config, &attr_inp, 2);
+  println!("top {} features: {:?}", k, top_features(&features, &mask));
+}
@@ -0,0 +1,33 @@
+use std::{fs::File, io::BufWriter};
+
+use zkml::utils::float_lint::lint_floats;
+
+// Usage: audit_floats [crate root] [report.json]
+// Flags the floats in the sources of model loading and witness generation (see float_lint.rs),
+// and fails if any is not marked as harmless
+fn main() {
+  let root = std::env::args().nth(1).unwrap_or(".".to_string());
+  let report = lint_floats(&root).unwrap();
+
+  for float_use in report.findings.iter() {
+    println!(
+      "{}:{}: {} in {}",
+      float_use.path,
+      float_use.line,
+      float_use.kinds.join(", "),
+      float_use.text
+    );
+  }
+  println!(
+    "{} files, {} float uses allowed, {} flagged",
+    report.files,
+    report.allowed.len(),
+    report.findings.len()
+  );
+
+  if let Some(report_fname) = std::env::args().nth(2) {
+    let writer = BufWriter::new(File::create(report_fname).unwrap());
+    serde_json::to_writer_pretty(writer, &report).unwrap();
+  }
+  assert!(report.findings.is_empty(), "floats in the witness path");
+}
@@ -0,0 +1,41 @@
+use std::{collections::HashMap, fs::File, io::BufWriter};
+
+use zkml::utils::{audit::audit_model, loader::load_config_msgpack};
+
+// Usage: audit_model <config> <report.json> [input bounds json] [margin bits]
+// The input bounds file maps input tensor indices to max absolute values (in fixed point)
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let report_fname = std::env::args().nth(2).expect("report file path");
+  let inp_bounds: HashMap<i64, f64> = match std::env::args().nth(3) {
+    Some(fname) => {
+      let bounds: HashMap<String, f64> =
+        serde_json::from_reader(File::open(fname).unwrap()).unwrap();
+      bounds
+        .into_iter()
+        .map(|(k, v)| (k.parse::<i64>().expect("tensor index"), v))
+        .collect()
+    }
+    None => HashMap::new(),
+  };
+  let margin_bits = std::env::args()
+    .nth(4)
+    .map(|x| x.parse::<f64>().unwrap())
+    .unwrap_or(64.);
+
+  let config = load_config_msgpack(&config_fname);
+  let report = audit_model(&config, &inp_bounds, margin_bits);
+
+  for layer in report.layers.iter() {
+    for check in layer.checks.iter().filter(|c| !c.ok) {
+      println!(
+        "layer {} ({}): {} bound {:e} exceeds {:e}",
+        layer.layer_idx, layer.layer_type, check.name, check.bound, check.limit
+      );
+    }
+  }
+  println!("safe: {}", report.safe);
+
+  let writer = BufWriter::new(File::create(report_fname).unwrap());
+  serde_json::to_writer_pretty(writer, &report).unwrap();
+}
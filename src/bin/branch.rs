@@ -0,0 +1,43 @@
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    branches::{branch_predicates, choose_branches},
+    helpers::instance_columns,
+    loader::{load_config_msgpack, save_config_msgpack, TensorMsgpack},
+    storage::read_artifact,
+  },
+};
+
+// Chooses the branches a model takes on an input and writes the config to prove with
+// Usage: branch <config> <input> <output config>
+// Every combination of branches is a different circuit, so the output config has its own keys.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let outp_fname = std::env::args().nth(3).expect("output config file path");
+
+  let mut model = load_config_msgpack(&config_fname);
+  let inp: Vec<TensorMsgpack> = rmp_serde::from_slice(&read_artifact(&inp_fname).unwrap()).unwrap();
+  let taken = choose_branches(&mut model, &inp).unwrap();
+  for (i, taken) in taken.iter().enumerate() {
+    println!(
+      "branch {} takes the {} branch",
+      i,
+      if *taken { "then" } else { "else" }
+    );
+  }
+  save_config_msgpack(&model, &outp_fname);
+
+  let circuit = ModelCircuit::<Fr>::generate_from_file(&outp_fname, &inp_fname);
+  let public_vals = circuit.compute_public_values().unwrap();
+  let prover = MockProver::run(
+    circuit.k as u32,
+    &circuit,
+    instance_columns(&public_vals, circuit.num_commitments()),
+  )
+  .unwrap();
+  assert_eq!(prover.verify(), Ok(()));
+  assert_eq!(branch_predicates(&model, &public_vals).unwrap(), taken);
+  println!("the circuit reveals the branches taken");
+}
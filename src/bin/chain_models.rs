@@ -0,0 +1,81 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    chaining::{chained_inputs, check_linkage, link_models},
+    loader::{load_config_msgpack, load_model_msgpack, save_model_msgpack},
+  },
+};
+
+// Links two models so that the output commitment of the first is the input commitment of the
+// second, and checks the linkage of their public values
+// Usage:
+//   chain_models link <prev config> <prev input> <next config> <output dir>
+//   chain_models check <prev config> <prev public vals> <next config> <next public vals>
+// `link` writes the linked configs and the inputs of the second model (the outputs of the
+// first) to the output dir, which can then be proven separately.
+
+fn public_values(config_fname: &str, inp_fname: &str) -> Vec<Fr> {
+  let circuit = ModelCircuit::<Fr>::generate_from_file(config_fname, inp_fname);
+  circuit.compute_public_values().unwrap()
+}
+
+fn read_public_values(fname: &str) -> Vec<Fr> {
+  let public_vals_u8 = std::fs::read(fname).unwrap();
+  public_vals_u8
+    .chunks(32)
+    .map(|chunk| Fr::from_bytes(chunk.try_into().expect("conversion failed")).unwrap())
+    .collect()
+}
+
+fn link(prev_config: &str, prev_inp: &str, next_config: &str, outp_dir: &str) {
+  let mut prev = load_model_msgpack(prev_config, prev_inp);
+  let mut next = load_config_msgpack(next_config);
+  link_models(&mut prev, &mut next);
+
+  std::fs::create_dir_all(outp_dir).unwrap();
+  let prev_config = format!("{}/prev.msgpack", outp_dir);
+  let prev_inp = format!("{}/prev_inp.msgpack", outp_dir);
+  let next_config = format!("{}/next.msgpack", outp_dir);
+  let next_inp = format!("{}/next_inp.msgpack", outp_dir);
+  save_model_msgpack(&prev, &prev_config, &prev_inp);
+
+  let prev_public_vals = public_values(&prev_config, &prev_inp);
+  next
+    .tensors
+    .extend(chained_inputs(&prev, &prev_public_vals, &next));
+  save_model_msgpack(&next, &next_config, &next_inp);
+
+  let next_public_vals = public_values(&next_config, &next_inp);
+  match check_linkage(&prev, &prev_public_vals, &next, &next_public_vals) {
+    Ok(()) => println!("linked models written to {}", outp_dir),
+    Err(e) => panic!("linkage failed: {}", e),
+  }
+}
+
+fn check(prev_config: &str, prev_public: &str, next_config: &str, next_public: &str) {
+  let prev = load_config_msgpack(prev_config);
+  let next = load_config_msgpack(next_config);
+  let prev_public_vals = read_public_values(prev_public);
+  let next_public_vals = read_public_values(next_public);
+  match check_linkage(&prev, &prev_public_vals, &next, &next_public_vals) {
+    Ok(()) => println!("proofs are linked"),
+    Err(e) => {
+      println!("proofs are not linked: {}", e);
+      std::process::exit(1);
+    }
+  }
+}
+
+fn main() {
+  let mode = std::env::args().nth(1).expect("link or check");
+  let args = (2..6)
+    .map(|i| std::env::args().nth(i).expect("missing argument"))
+    .collect::<Vec<_>>();
+
+  match mode.as_str() {
+    "link" => link(&args[0], &args[1], &args[2], &args[3]),
+    "check" => check(&args[0], &args[1], &args[2], &args[3]),
+    _ => panic!("Must specify link or check"),
+  }
+}
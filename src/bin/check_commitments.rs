@@ -0,0 +1,45 @@
+use zkml::{
+  commitments::native::check_commitments,
+  utils::{
+    envelope::ProofEnvelope,
+    loader::{load_config_msgpack, ModelMsgpack, TensorMsgpack},
+    storage::read_artifact,
+  },
+};
+
+// Checks the revealed tensors against the commitments in the public values of a proof envelope,
+// off-circuit. The weights come from the config, if given, since the layout has no data.
+// Usage: check_commitments <envelope> <revealed tensors> [<config>]
+fn main() {
+  let envelope_fname = std::env::args().nth(1).expect("envelope path");
+  let revealed_fname = std::env::args().nth(2).expect("revealed tensors path");
+
+  let envelope = ProofEnvelope::read(&envelope_fname).unwrap();
+  let model = match std::env::args().nth(3) {
+    Some(config_fname) => load_config_msgpack(&config_fname),
+    None => rmp_serde::from_slice::<ModelMsgpack>(&envelope.layout).unwrap(),
+  };
+  let revealed: Vec<TensorMsgpack> =
+    rmp_serde::from_slice(&read_artifact(&revealed_fname).unwrap()).unwrap();
+
+  let checks = check_commitments(&model, &revealed, &envelope.public_vals().unwrap()).unwrap();
+  for check in checks.iter() {
+    println!(
+      "{} {} of tensors {:?}: {}",
+      check.kind,
+      check.position,
+      check.tensors,
+      if check.ok {
+        "matches"
+      } else {
+        "does not match"
+      }
+    );
+  }
+  if checks.is_empty() {
+    println!("none of the commitments are over the revealed tensors");
+  }
+  if !checks.iter().all(|check| check.ok) {
+    std::process::exit(1);
+  }
+}
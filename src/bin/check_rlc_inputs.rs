@@ -0,0 +1,31 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::utils::{
+  loader::{load_config_msgpack, TensorMsgpack},
+  rlc::check_rlc_inputs,
+  storage::read_artifact,
+};
+
+// Checks the revealed rlc inputs of a model against the public values of a verified proof
+// Usage: check_rlc_inputs <config> <input> <public vals>
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let public_vals_fname = std::env::args().nth(3).expect("public values path");
+
+  let config = load_config_msgpack(&config_fname);
+  let inputs: Vec<TensorMsgpack> =
+    rmp_serde::from_slice(&read_artifact(&inp_fname).unwrap()).unwrap();
+  let public_vals: Vec<Fr> = read_artifact(&public_vals_fname)
+    .unwrap()
+    .chunks(32)
+    .map(|chunk| Fr::from_bytes(chunk.try_into().expect("conversion failed")).unwrap())
+    .collect();
+
+  match check_rlc_inputs(&config, &inputs, &public_vals) {
+    Ok(()) => println!("the inputs match the proof"),
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  }
+}
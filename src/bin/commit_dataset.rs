@@ -0,0 +1,76 @@
+use std::{fs::File, io::BufReader};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::Serialize;
+use zkml::{
+  commitments::{
+    batch::{commit_batch, BatchOpenings},
+    merkle::{hash_tensors, verify_proof},
+  },
+  utils::loader::TensorMsgpack,
+};
+
+// Hashes a directory of input files (msgpack, as for the prover) into a Poseidon Merkle tree
+// Usage: commit_dataset <input dir> <output json>
+// Files are ordered by name, so the leaf index of a file is its position in that order. The leaves
+// are the input hashes of --hash_inputs (see input_hash.rs), and the output has the layout of the
+// batch openings, so open_sample checks a file of the dataset against the root, and a proof of a
+// model with hash_inputs on that file exposes its leaf.
+
+#[derive(Serialize)]
+struct DatasetCommitment {
+  #[serde(flatten)]
+  openings: BatchOpenings,
+  // The file of every leaf
+  files: Vec<String>,
+}
+
+fn main() {
+  let inp_dir = std::env::args().nth(1).expect("input directory");
+  let outp_fname = std::env::args().nth(2).expect("output file path");
+
+  let mut fnames = std::fs::read_dir(&inp_dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.is_file())
+    .collect::<Vec<_>>();
+  fnames.sort();
+  assert!(!fnames.is_empty(), "no input files in {}", inp_dir);
+
+  let leaves = fnames
+    .iter()
+    .map(|fname| {
+      let file = File::open(fname).unwrap();
+      let mut reader = BufReader::new(file);
+      let tensors: Vec<TensorMsgpack> = rmp_serde::from_read(&mut reader)
+        .unwrap_or_else(|e| panic!("failed to read {}: {:?}", fname.display(), e));
+      hash_tensors::<Fr>(&tensors)
+    })
+    .collect::<Vec<_>>();
+
+  let (commitment, tree) = commit_batch(leaves);
+  let openings = (0..fnames.len())
+    .map(|i| {
+      let proof = tree.proof(i);
+      assert!(verify_proof(&tree.root(), tree.depth(), &proof));
+      proof
+    })
+    .collect::<Vec<_>>();
+  let files = fnames
+    .iter()
+    .map(|fname| fname.file_name().unwrap().to_string_lossy().to_string())
+    .collect::<Vec<_>>();
+
+  let dataset = DatasetCommitment {
+    openings: BatchOpenings {
+      commitment,
+      openings,
+    },
+    files,
+  };
+  let outp = File::create(&outp_fname).unwrap();
+  serde_json::to_writer_pretty(outp, &dataset).unwrap();
+
+  println!("dataset root: {}", dataset.openings.commitment.root);
+  println!("wrote {} proofs to {}", dataset.files.len(), outp_fname);
+}
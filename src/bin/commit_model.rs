@@ -0,0 +1,49 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  commitments::{
+    commit_model,
+    merkle::field_to_string,
+    native::{commit_tensors, PackingParams},
+  },
+  model::ModelCircuit,
+  utils::loader::load_config_msgpack,
+};
+
+// Prints the commitment to the weights of a model, to be published
+// Usage: commit_model <config> [<input>]
+// With an input, also checks that the circuit exposes the same commitment as its first public
+// value, which needs the config to set commit_weights, and that the native helper recomputes it.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let commitment = commit_model(&config_fname);
+  println!("model commitment: {}", field_to_string(&commitment));
+
+  if let Some(inp_fname) = std::env::args().nth(2) {
+    let config = load_config_msgpack(&config_fname);
+    assert!(
+      config.commit_weights.unwrap_or(false),
+      "the config does not commit to its weights"
+    );
+    let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
+    let public_vals = circuit.compute_public_values().unwrap();
+    assert_eq!(
+      public_vals[0], commitment,
+      "the weight commitment does not match"
+    );
+    println!("the circuit exposes the model commitment");
+
+    let weights = &config.commit_before.as_ref().unwrap()[0];
+    let tensors = config
+      .tensors
+      .iter()
+      .filter(|tensor| weights.contains(&tensor.idx))
+      .collect::<Vec<_>>();
+    let params = PackingParams::from_model(&config).unwrap();
+    assert_eq!(
+      commit_tensors(&tensors, &params).unwrap(),
+      commitment,
+      "the native commitment does not match"
+    );
+    println!("the native helper recomputes the model commitment");
+  }
+}
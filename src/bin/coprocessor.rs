@@ -0,0 +1,85 @@
+use zkml::utils::{
+  coprocessor::{coprocessor_claims, coprocessor_outputs, parse_coprocessor_verifier},
+  envelope::ProofEnvelope,
+  loader::{load_config_msgpack, ModelMsgpack, TensorMsgpack},
+  storage::{read_artifact, write_artifact},
+};
+
+// Co-processor mode (see coprocessor.rs)
+// Usage: coprocessor outputs <config> <input> <output input>
+//        coprocessor claims <envelope> [<config>]
+//        coprocessor verify <envelope> <verifier> <proof>... [--config <config>]
+// outputs adds the outputs of the coprocessor layers to the input, computed by the prover. claims
+// prints what the external proofs must show, and verify checks one proof per coprocessor layer,
+// in order, with opening or command:<program>.
+fn main() {
+  let args = std::env::args().collect::<Vec<_>>();
+  let command = args.get(1).expect("command");
+  match command.as_str() {
+    "outputs" => {
+      let model = load_config_msgpack(args.get(2).expect("config file path"));
+      let inp_fname = args.get(3).expect("input file path");
+      let outp_fname = args.get(4).expect("output input path");
+      let mut inp: Vec<TensorMsgpack> =
+        rmp_serde::from_slice(&read_artifact(inp_fname).unwrap()).unwrap();
+      let outputs = coprocessor_outputs(&model, &inp).unwrap();
+      println!("{} coprocessor outputs", outputs.len());
+      inp.extend(outputs);
+      let mut buf = vec![];
+      rmp_serde::encode::write_named(&mut buf, &inp).unwrap();
+      write_artifact(outp_fname, &buf).unwrap();
+    }
+    "claims" | "verify" => {
+      let envelope = ProofEnvelope::read(args.get(2).expect("envelope path")).unwrap();
+      let mut rest = args[3..].to_vec();
+      let config_fname = match rest.iter().position(|x| x == "--config") {
+        Some(pos) => {
+          let fname = rest.get(pos + 1).expect("config file path").clone();
+          rest.drain(pos..pos + 2);
+          Some(fname)
+        }
+        None if command == "claims" => rest.pop(),
+        None => None,
+      };
+      // The layout has no tensor data, which the claims don't need
+      let model = match config_fname {
+        Some(fname) => load_config_msgpack(&fname),
+        None => rmp_serde::from_slice::<ModelMsgpack>(&envelope.layout).unwrap(),
+      };
+      let claims = coprocessor_claims(&model, &envelope.public_vals().unwrap()).unwrap();
+      if command == "claims" {
+        println!("{}", serde_json::to_string_pretty(&claims).unwrap());
+        return;
+      }
+
+      let verifier = parse_coprocessor_verifier(rest.get(0).expect("verifier"), &model).unwrap();
+      let proof_fnames = &rest[1..];
+      assert_eq!(
+        proof_fnames.len(),
+        claims.len(),
+        "expected one proof per coprocessor layer"
+      );
+      let mut all_valid = true;
+      for (claim, proof_fname) in claims.iter().zip(proof_fnames.iter()) {
+        let valid = verifier
+          .verify(claim, &read_artifact(proof_fname).unwrap())
+          .unwrap();
+        println!(
+          "layer {} ({}) with {}: {}",
+          claim.layer,
+          claim.layer_type,
+          verifier.name(),
+          if valid { "valid" } else { "invalid" }
+        );
+        all_valid &= valid;
+      }
+      if !all_valid {
+        std::process::exit(1);
+      }
+    }
+    _ => panic!(
+      "unknown command {}, expected outputs, claims, or verify",
+      command
+    ),
+  }
+}
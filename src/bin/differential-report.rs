@@ -0,0 +1,47 @@
+use std::{fs::File, io::BufWriter};
+
+use zkml::utils::differential::{differential_report, load_diff_dataset, ToleranceSpec};
+
+// Finds the first op where the quantized model diverges from the float model, on every sample of
+// a dataset, and names the op type responsible for the drift. See utils/differential.rs for the
+// dataset and the tolerance formats.
+// Usage: differential-report <config> <dataset json> [tolerances json] [report json]
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let dataset_fname = std::env::args().nth(2).expect("dataset file path");
+  let spec = match std::env::args().nth(3) {
+    Some(fname) => ToleranceSpec::load(&fname).unwrap(),
+    None => ToleranceSpec::default(),
+  };
+  let report_fname = std::env::args().nth(4);
+
+  let samples = load_diff_dataset(&dataset_fname).unwrap();
+  let report = differential_report(&config_fname, &samples, &spec).unwrap();
+
+  println!(
+    "{} of {} samples diverge from the float model",
+    report.num_diverged, report.num_samples
+  );
+  for sample in report.samples.iter() {
+    if let Some(first) = &sample.first {
+      println!(
+        "{}: first diverges at layer {} ({}), max abs error {:.6} > {:.6}",
+        sample.input, first.layer, first.layer_type, first.max_abs_error, first.allowed
+      );
+    }
+  }
+  for op in report.ops.iter() {
+    println!(
+      "{}: {} first divergences over {} checked layers, lift {:.2}",
+      op.layer_type, op.num_first_divergences, op.num_layers, op.lift
+    );
+  }
+  if let Some(op) = &report.responsible_op {
+    println!("the drift is mostly caused by {}", op);
+  }
+
+  if let Some(report_fname) = report_fname {
+    let writer = BufWriter::new(File::create(report_fname).unwrap());
+    serde_json::to_writer_pretty(writer, &report).unwrap();
+  }
+}
@@ -0,0 +1,52 @@
+use zkml::utils::{
+  cost_model::{load_hardware, load_snapshots, CostModel},
+  loader::load_config_msgpack,
+};
+
+// Forecasts the proving time and memory of a model on a target machine, using a cost model fit
+// on the perf snapshots (time_circuit --perf-report) in the snapshots directory.
+// Usage: estimate --hardware <profile json> [--snapshots <dir>] [--json] <config>
+// The hardware profile can be a perf report from the target machine.
+fn main() {
+  let args = std::env::args().skip(1).collect::<Vec<_>>();
+  let arg = |name: &str| {
+    let pos = args.iter().position(|arg| arg == name)?;
+    Some(args.get(pos + 1).expect("missing value").clone())
+  };
+  let hardware_fname = arg("--hardware").expect("--hardware <profile json> is required");
+  let snapshots_dir = arg("--snapshots").unwrap_or("./perf".to_string());
+  let json = args.iter().any(|arg| arg == "--json");
+  let config_fname = args
+    .iter()
+    .enumerate()
+    .filter(|(i, arg)| {
+      let is_value = *i > 0 && ["--hardware", "--snapshots"].contains(&args[*i - 1].as_str());
+      !arg.starts_with("--") && !is_value
+    })
+    .map(|(_, arg)| arg.clone())
+    .next()
+    .expect("config file path");
+
+  let config = load_config_msgpack(&config_fname);
+  let hardware = load_hardware(&hardware_fname).unwrap();
+  let snapshots = load_snapshots(&snapshots_dir).unwrap();
+  let cost_model = CostModel::fit(&snapshots).unwrap();
+  let estimate = cost_model.estimate(&config, &hardware);
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&estimate).unwrap());
+    return;
+  }
+  println!(
+    "k = {}, {} columns, {} CPUs, fit on {} snapshots",
+    estimate.k, estimate.num_cols, hardware.num_cpus, cost_model.num_snapshots
+  );
+  for (stage, seconds) in estimate.stages.iter() {
+    println!("{}: {:.1}s", stage, seconds);
+  }
+  println!("total: {:.1}s", estimate.total_seconds);
+  match estimate.peak_memory_kb {
+    Some(kb) => println!("peak memory: {:.2} GB", kb as f64 / (1024. * 1024.)),
+    None => println!("peak memory: unknown, no snapshot has a peak memory"),
+  }
+}
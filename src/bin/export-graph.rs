@@ -0,0 +1,12 @@
+use zkml::utils::{graph::model_to_dot, loader::load_config_msgpack, storage::write_artifact};
+
+// Usage: export-graph <config> <output dot>
+// Render with, e.g., dot -Tsvg model.dot -o model.svg
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let outp_fname = std::env::args().nth(2).expect("output file path");
+
+  let config = load_config_msgpack(&config_fname);
+  write_artifact(&outp_fname, model_to_dot(&config).as_bytes()).unwrap();
+  println!("wrote {} ({} layers)", outp_fname, config.layers.len());
+}
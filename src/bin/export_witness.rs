@@ -0,0 +1,30 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    loader::{load_config_msgpack, load_model_msgpack},
+    witness::{export_witness, write_witness},
+  },
+};
+
+// Usage: export_witness <config> <input> <witness out>
+// Runs the model and writes its witness, which prove_witness proves on another machine (see
+// witness.rs). The witness holds the inputs and the weights.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let witness_fname = std::env::args().nth(3).expect("witness output file path");
+
+  // The hash of the model is over the config without the input tensors
+  let config = load_config_msgpack(&config_fname);
+  let circuit =
+    ModelCircuit::<Fr>::generate_from_msgpack(load_model_msgpack(&config_fname, &inp_fname), true);
+  let witness = export_witness(&circuit, &config).unwrap();
+  write_witness(&witness_fname, &witness).unwrap();
+  println!(
+    "Wrote {} cells and {} public values to {}",
+    witness.columns.len(),
+    witness.public_vals.len() / 32,
+    witness_fname
+  );
+}
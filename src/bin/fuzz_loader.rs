@@ -0,0 +1,198 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use zkml::utils::{
+  loader::{add_inputs, parse_config_msgpack, parse_inputs_msgpack, ModelMsgpack, TensorMsgpack},
+  storage::read_artifact,
+  validate::LoaderLimits,
+};
+
+// Fuzzes the checked loader (see validate.rs) with mutations of a valid model and input: random
+// bytes, and malicious values in the fields the loader allocates or indexes from. The loader must
+// return an error or a valid model, and never panic.
+// Usage: fuzz_loader [<config> <input> [iterations] [seed]]
+
+const HOSTILE: [i64; 8] = [-1, 0, 1, 7, 1 << 20, 1 << 40, i64::MAX, i64::MIN];
+
+fn load(config: &[u8], inp: &[u8]) -> Result<ModelMsgpack, String> {
+  let limits = LoaderLimits::default();
+  let config = parse_config_msgpack(config, &limits)?;
+  let inp = parse_inputs_msgpack(inp, &limits)?;
+  add_inputs(config, inp, &limits)
+}
+
+fn encode<T: serde::Serialize>(x: &T) -> Vec<u8> {
+  let mut buf = vec![];
+  rmp_serde::encode::write_named(&mut buf, x).unwrap();
+  buf
+}
+
+fn mutate_bytes(rng: &mut StdRng, buf: &[u8]) -> Vec<u8> {
+  let mut buf = buf.to_vec();
+  match rng.gen_range(0..4) {
+    0 => {
+      for _ in 0..rng.gen_range(1..8) {
+        let i = rng.gen_range(0..buf.len());
+        buf[i] = rng.gen();
+      }
+    }
+    1 => buf.truncate(rng.gen_range(0..buf.len())),
+    2 => {
+      let i = rng.gen_range(0..buf.len());
+      let len = rng.gen_range(0..64.min(buf.len() - i));
+      let chunk = buf[i..i + len].to_vec();
+      buf.splice(i..i, chunk);
+    }
+    _ => {
+      // Array and map headers with 32 bit lengths, the largest allocations a length can ask for
+      let i = rng.gen_range(0..buf.len());
+      let header = [
+        [0xdd, 0xff, 0xff, 0xff, 0xff],
+        [0xdf, 0x7f, 0xff, 0xff, 0xff],
+      ];
+      buf.splice(i..i, header[rng.gen_range(0..2)]);
+    }
+  }
+  buf
+}
+
+fn hostile(rng: &mut StdRng) -> i64 {
+  HOSTILE[rng.gen_range(0..HOSTILE.len())]
+}
+
+fn mutate_model(
+  rng: &mut StdRng,
+  model: &mut ModelMsgpack,
+  inp: &mut Vec<TensorMsgpack>,
+) -> String {
+  let num_layers = model.layers.len();
+  let layer = rng.gen_range(0..num_layers);
+  match rng.gen_range(0..12) {
+    0 => {
+      model.k = hostile(rng);
+      format!("k = {}", model.k)
+    }
+    1 => {
+      model.num_cols = hostile(rng);
+      format!("num_cols = {}", model.num_cols)
+    }
+    2 => {
+      let shapes = &mut model.layers[layer].inp_shapes;
+      let shape = &mut shapes[0];
+      let dim = rng.gen_range(0..shape.len().max(1));
+      shape.resize(shape.len().max(dim + 1), 1);
+      shape[dim] = hostile(rng);
+      format!("inp shape of layer {} is {:?}", layer, shape)
+    }
+    3 => {
+      let shape = &mut model.layers[layer].out_shapes[0];
+      if rng.gen() {
+        shape.extend(vec![2; 64]);
+      } else {
+        shape[0] = hostile(rng);
+      }
+      format!("out shape of layer {} is {:?}", layer, shape)
+    }
+    4 => {
+      let idx = hostile(rng);
+      model.layers[layer].inp_idxes[0] = idx;
+      format!("layer {} reads tensor {}", layer, idx)
+    }
+    5 => {
+      model.layers[layer].inp_shapes.pop();
+      format!("layer {} lost an inp shape", layer)
+    }
+    6 => {
+      model.layers[layer].layer_type = "Bogus".to_string();
+      format!("layer {} is bogus", layer)
+    }
+    7 => {
+      let tensor = &mut inp[0];
+      tensor.shape[0] = hostile(rng);
+      format!("input shape is {:?}", tensor.shape)
+    }
+    8 => {
+      let tensor = &mut inp[0];
+      tensor.data.truncate(rng.gen_range(0..tensor.data.len()));
+      format!("input has {} values", tensor.data.len())
+    }
+    9 => {
+      let tensor = &mut inp[0];
+      tensor.data[0] = hostile(rng);
+      tensor.dtype =
+        [None, Some("int8".to_string()), Some("float".to_string())][rng.gen_range(0..3)].clone();
+      format!(
+        "input value {} with dtype {:?}",
+        tensor.data[0], tensor.dtype
+      )
+    }
+    10 => {
+      let idx = hostile(rng);
+      model.out_idxes.push(idx);
+      model.commit_after = Some(vec![vec![idx]]);
+      format!("output and commitment {}", idx)
+    }
+    _ => {
+      model.exit = Some(hostile(rng));
+      model.rlc_inputs = Some(vec![hostile(rng)]);
+      format!(
+        "exit {:?} and rlc inputs {:?}",
+        model.exit, model.rlc_inputs
+      )
+    }
+  }
+}
+
+fn main() {
+  let args = std::env::args().collect::<Vec<_>>();
+  let config_fname = args.get(1).map_or("examples/mnist/model.msgpack", |x| x);
+  let inp_fname = args.get(2).map_or("examples/mnist/inp.msgpack", |x| x);
+  let iterations = args.get(3).map_or(2000, |x| x.parse().expect("iterations"));
+  let seed = args.get(4).map_or(0, |x| x.parse().expect("seed"));
+
+  let config_buf = read_artifact(config_fname).unwrap();
+  let inp_buf = read_artifact(inp_fname).unwrap();
+  load(&config_buf, &inp_buf).expect("the seed model must load");
+  let limits = LoaderLimits::default();
+  let model = parse_config_msgpack(&config_buf, &limits).unwrap();
+  let inp = parse_inputs_msgpack(&inp_buf, &limits).unwrap();
+
+  let mut rng = StdRng::seed_from_u64(seed);
+  let (mut num_accepted, mut num_panics) = (0, 0);
+  for i in 0..iterations {
+    let (config, inp, what) = match rng.gen_range(0..3) {
+      0 => (
+        mutate_bytes(&mut rng, &config_buf),
+        inp_buf.clone(),
+        "config bytes".to_string(),
+      ),
+      1 => (
+        config_buf.clone(),
+        mutate_bytes(&mut rng, &inp_buf),
+        "input bytes".to_string(),
+      ),
+      _ => {
+        let (mut model, mut inp) = (model.clone(), inp.clone());
+        let what = mutate_model(&mut rng, &mut model, &mut inp);
+        (encode(&model), encode(&inp), what)
+      }
+    };
+    match catch_unwind(AssertUnwindSafe(|| load(&config, &inp))) {
+      Ok(Ok(_)) => num_accepted += 1,
+      Ok(Err(_)) => {}
+      Err(_) => {
+        println!("iteration {}: the loader panicked on {}", i, what);
+        num_panics += 1;
+      }
+    }
+  }
+
+  println!(
+    "{} iterations, {} accepted, {} rejected, {} panics",
+    iterations,
+    num_accepted,
+    iterations - num_accepted - num_panics,
+    num_panics
+  );
+  assert_eq!(num_panics, 0, "the loader panicked");
+}
@@ -0,0 +1,33 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  commitments::{hash_input, input_hash::num_input_hash_vals, merkle::field_to_string},
+  model::ModelCircuit,
+  utils::{
+    loader::load_config_msgpack,
+    rlc::{num_rlc_vals, rlc_position},
+  },
+};
+
+// Prints the hash of an input file, to be published
+// Usage: hash_input <input> [<config>]
+// With a config, also checks that the circuit exposes the same hash, which needs the config to
+// set hash_inputs.
+fn main() {
+  let inp_fname = std::env::args().nth(1).expect("input file path");
+  let hash = hash_input(&inp_fname);
+  println!("input hash: {}", field_to_string(&hash));
+
+  if let Some(config_fname) = std::env::args().nth(2) {
+    let config = load_config_msgpack(&config_fname);
+    assert_eq!(
+      num_input_hash_vals(&config),
+      1,
+      "the config does not hash its inputs"
+    );
+    let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
+    let public_vals = circuit.compute_public_values().unwrap();
+    let pos = rlc_position(&config) + num_rlc_vals(&config);
+    assert_eq!(public_vals[pos], hash, "the input hash does not match");
+    println!("the circuit exposes the input hash");
+  }
+}
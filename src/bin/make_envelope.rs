@@ -0,0 +1,57 @@
+use zkml::utils::{
+  artifacts::{artifacts_arg, content_hash, Manifest, DEFAULT_ARTIFACTS_DIR},
+  envelope::ProofEnvelope,
+  loader::load_config_msgpack,
+  signer::{parse_signer, sign_envelope, signature_path},
+  storage::read_artifact,
+};
+
+// Bundles the artifacts written by time_circuit into a proof envelope for zkml-verify
+// Usage: make_envelope <config> <vkey> <proof> <public vals> <output> [--artifacts <dir>]
+//   [--srs <params file>] [--sign <signer>]
+// The artifacts can also be hashes from the artifacts directory (./artifacts by default). --sign
+// signs the envelope with the prover identity key in a KMS or HSM, kms:<key> or
+// pkcs11:<module>:<key id> (see signer.rs), into <output>.sig.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let vkey_fname = std::env::args().nth(2).expect("verification key file path");
+  let proof_fname = std::env::args().nth(3).expect("proof file path");
+  let public_vals_fname = std::env::args().nth(4).expect("public values file path");
+  let outp_fname = std::env::args().nth(5).expect("output file path");
+
+  let args = std::env::args().collect::<Vec<_>>();
+  let srs_arg = args
+    .iter()
+    .position(|arg| arg == "--srs")
+    .map(|pos| args.get(pos + 1).expect("--srs needs a file path").clone());
+  let signer = args
+    .iter()
+    .position(|arg| arg == "--sign")
+    .map(|pos| parse_signer(args.get(pos + 1).expect("--sign needs a signer")).unwrap());
+  let artifacts_dir = artifacts_arg().unwrap_or(DEFAULT_ARTIFACTS_DIR.to_string());
+  let manifest = Manifest::load(&artifacts_dir).unwrap();
+  let read = |reference: &str| {
+    let fname = manifest.resolve(&artifacts_dir, reference).unwrap();
+    read_artifact(&fname).unwrap()
+  };
+
+  let config = load_config_msgpack(&config_fname);
+  let mut envelope = ProofEnvelope::new(
+    &config,
+    read(&vkey_fname),
+    read(&proof_fname),
+    read(&public_vals_fname),
+  );
+  if let Some(srs_fname) = srs_arg {
+    envelope = envelope.with_srs_hash(content_hash(&read(&srs_fname)));
+  }
+  manifest
+    .check_link(&envelope.proof, "vkey", &envelope.vkey)
+    .unwrap();
+  envelope.write(&outp_fname);
+  println!("wrote {}", outp_fname);
+  if let Some(signer) = signer {
+    sign_envelope(&outp_fname, signer.as_ref()).unwrap();
+    println!("wrote {}", signature_path(&outp_fname));
+  }
+}
@@ -0,0 +1,26 @@
+use zkml::utils::{
+  loader::{load_config_msgpack, TensorMsgpack},
+  randomness::mc_dropout,
+  storage::read_artifact,
+};
+
+// MC dropout estimates (see randomness.rs)
+// Usage: mc_dropout <config> <input> <num samples> [<first seed>]
+// Prints the outputs with the seeds first, first + 1, ..., which the proofs with these seeds
+// reveal, and their mean and standard deviation.
+fn main() {
+  let args = std::env::args().collect::<Vec<_>>();
+  let model = load_config_msgpack(args.get(1).expect("config file path"));
+  let inp: Vec<TensorMsgpack> =
+    rmp_serde::from_slice(&read_artifact(args.get(2).expect("input file path")).unwrap()).unwrap();
+  let num_samples = args
+    .get(3)
+    .expect("number of samples")
+    .parse::<i64>()
+    .unwrap();
+  let first_seed = args.get(4).map_or(0, |x| x.parse::<i64>().unwrap());
+
+  let seeds = (first_seed..first_seed + num_samples).collect::<Vec<_>>();
+  let report = mc_dropout(&model, &inp, &seeds).unwrap();
+  println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
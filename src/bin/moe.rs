@@ -0,0 +1,69 @@
+use std::{fs::File, io::BufReader};
+
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    helpers::instance_columns,
+    loader::{load_config_msgpack, save_model_msgpack, TensorMsgpack},
+    moe::{check_routing, expert_commitments, load_expert_sets, prepare_moe, route},
+  },
+};
+
+// Routes an input through the MoE layers of a model and builds the circuit with the chosen experts
+// Usage: moe <config> <input> <expert sets> <output dir>
+// The expert sets are a msgpack list of {layer, experts}. The commitments of the full expert sets
+// are written to experts.json, to be published, and the config with the dispatched input to
+// moe.msgpack and moe_inp.msgpack, to be proven as usual.
+
+fn read_tensors(fname: &str) -> Vec<TensorMsgpack> {
+  let mut reader = BufReader::new(File::open(fname).unwrap());
+  rmp_serde::from_read(&mut reader).unwrap()
+}
+
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let experts_fname = std::env::args().nth(3).expect("expert sets file path");
+  let outp_dir = std::env::args().nth(4).expect("output directory");
+
+  let mut model = load_config_msgpack(&config_fname);
+  prepare_moe(&mut model);
+  let inp = read_tensors(&inp_fname);
+  let mut sets = load_expert_sets(&experts_fname).unwrap();
+  sets.sort_by_key(|set| set.layer);
+
+  let commitments = expert_commitments(&model, &inp, &sets).unwrap();
+  std::fs::create_dir_all(&outp_dir).unwrap();
+  std::fs::write(
+    format!("{}/experts.json", outp_dir),
+    serde_json::to_string_pretty(&commitments).unwrap(),
+  )
+  .unwrap();
+  for set in commitments.iter() {
+    println!("layer {} expert set root: {}", set.layer, set.root);
+  }
+
+  let (dispatched, routes) = route(&model, &inp, &sets).unwrap();
+  for (set, chosen) in sets.iter().zip(routes.iter()) {
+    println!("layer {} routes to experts {:?}", set.layer, chosen);
+  }
+  model.tensors.extend(inp);
+  model.tensors.extend(dispatched);
+  let moe_config = format!("{}/moe.msgpack", outp_dir);
+  let moe_inp = format!("{}/moe_inp.msgpack", outp_dir);
+  save_model_msgpack(&model, &moe_config, &moe_inp);
+
+  let circuit = ModelCircuit::<Fr>::generate_from_file(&moe_config, &moe_inp);
+  let public_vals = circuit.compute_public_values().unwrap();
+  let prover = MockProver::run(
+    circuit.k as u32,
+    &circuit,
+    instance_columns(&public_vals, circuit.num_commitments()),
+  )
+  .unwrap();
+  assert_eq!(prover.verify(), Ok(()), "the routing is not the top k");
+  let checked = check_routing(&model, &public_vals, &commitments).unwrap();
+  assert_eq!(checked, routes);
+  println!("every slot commits to its expert in the expert set");
+}
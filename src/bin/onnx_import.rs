@@ -0,0 +1,30 @@
+use zkml::utils::{
+  loader::save_model_msgpack,
+  onnx::{load_onnx, OnnxOptions},
+};
+
+// Converts an ONNX model to the msgpack config and inputs, like python/converter.py does for
+// TFLite models. The inputs are a JSON list of flattened float arrays in the ONNX layout.
+// Usage: onnx_import <model.onnx> <inputs.json> <output config> <output inputs> [scale factor] [k]
+fn main() {
+  let onnx_fname = std::env::args().nth(1).expect("onnx file path");
+  let inp_fname = std::env::args().nth(2).expect("inputs file path");
+  let config_fname = std::env::args().nth(3).expect("output config path");
+  let outp_inp_fname = std::env::args().nth(4).expect("output inputs path");
+
+  let mut options = OnnxOptions::default();
+  if let Some(sf) = std::env::args().nth(5) {
+    options.scale_factor = sf.parse().expect("scale factor");
+  }
+  if let Some(k) = std::env::args().nth(6) {
+    options.k = k.parse().expect("k");
+  }
+
+  let model = load_onnx(&onnx_fname, &inp_fname, &options).unwrap();
+  println!(
+    "imported {} layers and {} tensors",
+    model.layers.len(),
+    model.tensors.len()
+  );
+  save_model_msgpack(&model, &config_fname, &outp_inp_fname);
+}
@@ -0,0 +1,40 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  commitments::batch::{verify_opening, BatchOpenings},
+  utils::{loader::TensorMsgpack, storage::read_artifact},
+};
+
+// Checks a revealed sample of a batch proof against the batch openings written by prov_cli
+// (batch_openings), e.g., to audit the samples behind an aggregate claim, or a file against the
+// dataset commitment of commit_dataset. The root is checked
+// against the proof by verifying it with batch_root. KZG proofs only, whose hashes are over Fr.
+// Usage: open_sample <batch openings json> <sample> <input>
+fn main() {
+  let openings_fname = std::env::args().nth(1).expect("batch openings path");
+  let sample: usize = std::env::args()
+    .nth(2)
+    .expect("sample")
+    .parse()
+    .expect("sample");
+  let inp_fname = std::env::args().nth(3).expect("input file path");
+
+  let openings: BatchOpenings =
+    serde_json::from_slice(&read_artifact(&openings_fname).unwrap()).unwrap();
+  let inputs: Vec<TensorMsgpack> =
+    rmp_serde::from_slice(&read_artifact(&inp_fname).unwrap()).unwrap();
+  let opening = openings
+    .openings
+    .get(sample)
+    .unwrap_or_else(|| panic!("no opening for sample {}", sample));
+
+  match verify_opening::<Fr>(&openings.commitment, opening, &inputs) {
+    Ok(()) => println!(
+      "sample {} is in the batch with root {}",
+      sample, openings.commitment.root
+    ),
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  }
+}
@@ -0,0 +1,38 @@
+use zkml::utils::{
+  loader::{read_config_msgpack, save_config_msgpack},
+  optimizer::{default_gemm_tile_size, fuse_requantization},
+};
+
+// Fuses consecutive rescales of a model into single requantization steps, and sets the matmul
+// tile size if the config does not
+// Usage: optimize <config> <output config>
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let outp_fname = std::env::args().nth(2).expect("output config path");
+
+  let mut config = read_config_msgpack(&config_fname);
+  let num_layers = config.layers.len();
+  let fusions = fuse_requantization(&mut config);
+  for fusion in fusions.iter() {
+    println!(
+      "fused {} into {} (tensor {}), removes {} divisions",
+      fusion.fused_type, fusion.layer_type, fusion.tensor_idx, fusion.removed_divisions
+    );
+  }
+  let removed_divisions = fusions
+    .iter()
+    .map(|fusion| fusion.removed_divisions)
+    .sum::<usize>();
+  println!(
+    "{} -> {} layers, {} divisions removed",
+    num_layers,
+    config.layers.len(),
+    removed_divisions
+  );
+  if config.gemm_tile_size.is_none() {
+    config.gemm_tile_size = Some(default_gemm_tile_size(&config));
+  }
+  println!("gemm tile size: {}", config.gemm_tile_size.unwrap());
+
+  save_config_msgpack(&config, &outp_fname);
+}
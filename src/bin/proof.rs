@@ -0,0 +1,52 @@
+use zkml::utils::envelope::ProofEnvelope;
+
+// Usage: proof info <envelope> [--json]
+// Prints the metadata of a proof envelope without verifying it
+fn main() {
+  let command = std::env::args().nth(1).expect("command");
+  let envelope_fname = std::env::args().nth(2).expect("envelope file path");
+  let json = std::env::args().any(|arg| arg == "--json");
+  if command != "info" {
+    panic!("Unknown command {}, must be info", command);
+  }
+
+  let envelope = ProofEnvelope::read(&envelope_fname).unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(2);
+  });
+  let info = envelope.info().unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(2);
+  });
+  if json {
+    println!("{}", info);
+    return;
+  }
+
+  let keys = [
+    "version",
+    "k",
+    "model_hash",
+    "vkey_hash",
+    "srs_hash",
+    "transcript",
+    "created",
+    "num_layers",
+    "scale_factor",
+  ];
+  for key in keys {
+    println!("{}: {}", key, info[key]);
+  }
+  for (name, size) in info["sizes"].as_object().unwrap() {
+    println!("{} size: {} bytes", name, size);
+  }
+  for (i, commitment) in info["commitments"].as_array().unwrap().iter().enumerate() {
+    println!("commitment {}: {}", i, commitment.as_str().unwrap());
+  }
+  for (i, output) in info["outputs"].as_array().unwrap().iter().enumerate() {
+    match output["int"].as_str() {
+      Some(int) => println!("output {}: {} ({})", i, int, output["value"]),
+      None => println!("output {}: not a small integer", i),
+    }
+  }
+}
@@ -1,50 +1,208 @@
 use std::{
   fs::File,
-  io::{BufReader, Read},
+  io::{BufReader, BufWriter, Read},
+  time::Instant,
 };
 
 use anyhow::Result;
 use circuit_cli::CliOperator;
 use halo2_proofs::{
-  dev::MockProver,
-  halo2curves::bn256::{Bn256, Fr, G1Affine},
-  plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
-  poly::{
-    commitment::Params,
-    kzg::{
-      commitment::{KZGCommitmentScheme, ParamsKZG},
-      multiopen::{ProverSHPLONK, VerifierSHPLONK},
-      strategy::SingleStrategy,
-    },
+  halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    ff::{FromUniformBytes, PrimeField},
+    pasta::{EqAffine, Fp},
   },
-  transcript::{
-    Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+  plonk::{keygen_vk, ProvingKey, VerifyingKey},
+  poly::{
+    commitment::{Params, ParamsProver},
+    ipa::commitment::ParamsIPA,
+    kzg::commitment::ParamsKZG,
   },
+  SerdeFormat,
 };
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 use serde_derive::{Deserialize, Serialize};
+#[cfg(feature = "disk-pk")]
+use zkml::utils::disk_pk::DiskBackedAlloc;
+#[cfg(feature = "evm")]
+use zkml::utils::{
+  evm_verifier::{check_evm, evm_calldata, gen_evm_verifier, prove_evm},
+  storage::join_url,
+};
 use zkml::{
+  commitments::batch::{check_batch_root, open_batch},
   model::ModelCircuit,
-  utils::{helpers::get_public_values, proving_kzg::verify_kzg},
+  utils::{
+    artifacts::{check_pinned, content_hash},
+    cancel::{record_result, record_stage, write_stages},
+    config_file::{check_transcript, ZkmlConfig},
+    disk_pk::set_disk_pk_dir,
+    envelope::circuit_layout,
+    errors::{check_input_shapes, find_overflow, ErrorKind, ZkmlError},
+    gpu::init_gpu,
+    helpers::prover_rng,
+    keygen::{keygen_ipa, keygen_kzg},
+    loader::{
+      add_inputs, model_to_msgpack, parse_inputs_msgpack, try_load_config_msgpack, ModelMsgpack,
+    },
+    pk_cache::load_or_keygen_pk,
+    proof_stream::{proof_reader, proof_writer, BoundedWriter, DEFAULT_MAX_PROOF_BYTES},
+    proving_ipa::{check_batch_ipa_from, prove_batch_ipa, prove_batch_ipa_to},
+    proving_kzg::{check_batch_kzg_from, prove_batch_kzg, prove_batch_kzg_to},
+    srs::{fit_ipa_params, fit_kzg_params, indexed_kzg_params, read_kzg_params, SrsIndex},
+    storage::{local_path, read_artifact, write_artifact},
+    validate::LoaderLimits,
+  },
 };
 
+// The model and input paths override the ones in the config file
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CliArgs {
+  pub config: Option<String>,
   pub config_fname: Option<String>,
   pub inp_fname: Option<String>,
+  // Several inputs to prove in a single proof, instead of the one input
+  pub inp_fnames: Option<Vec<String>>,
+  // A serialized vk to verify with instead of the one in the params bundle
+  pub vkey_fname: Option<String>,
+  // The hash of the vk to verify with (see artifacts.rs content_hash), which prove prints. Pins
+  // the vk of the params bundle, which is written by the prover.
+  pub vkey_hash: Option<String>,
+  // Verifies with the vk of the bundle without a pinned vk or vk hash. This only shows that the
+  // proof fits the vk the prover chose, not that it is a proof of the model.
+  pub trust_bundle_vk: Option<bool>,
+  // The SRS to verify with, by default the SRS in the config file. For IPA, the params are derived
+  // for the k of the circuit without one.
+  pub srs_fname: Option<String>,
+  // The hash of the SRS in the params bundle, which prove prints. Pins the SRS of the bundle when
+  // the verifier has no SRS of its own, since a prover who knows the tau of the SRS can open any
+  // commitment.
+  pub srs_hash: Option<String>,
+  // Verifies with the SRS of the bundle without an SRS or SRS hash, see trust_bundle_vk
+  pub trust_bundle_srs: Option<bool>,
+  // Reuses the proving key in this file, or writes it there after keygen (see pk_cache.rs)
+  pub pkey_fname: Option<String>,
+  // Verifies the proof after proving, also set by self_verify in the config file
+  pub self_verify: Option<bool>,
+  // Overrides the transcript of the config file, "evm" for on-chain verification
+  pub transcript: Option<String>,
+  // Where the EVM proofs write Verifier.yul and calldata, defaults to the working directory
+  pub evm_dir: Option<String>,
+  // Truncates a larger SRS to the k of the circuit (the default). Without it, the circuit is
+  // proven at the k of the SRS, e.g., for a vk generated at that size.
+  pub downsize: Option<bool>,
+  // "kzg" (the default) or "ipa", which needs no trusted setup but can't be verified on the EVM
+  pub commitment: Option<String>,
+  // Writes the commitment to the inputs of the batch and the opening of every sample there (see
+  // commitments/batch.rs), for models with hash_inputs
+  pub batch_openings: Option<String>,
+  // Also checks that the proof commits to the inputs of the batch with this root
+  pub batch_root: Option<String>,
+  // Streams the proof to this file as it is made instead of holding it in memory, and the CLI
+  // gets an empty proof. At verify time, the proof is read from this file as the verifier goes.
+  pub proof_fname: Option<String>,
+  // The bound on the proof in bytes when proving and verifying (see proof_stream.rs)
+  pub max_proof_bytes: Option<u64>,
+  // Backs the proving key with files in this directory, for keys larger than the RAM (see
+  // disk_pk.rs, needs the disk-pk feature)
+  pub disk_pk: Option<String>,
 }
 
 struct Operator;
 
-struct MlParams {
-  params: ParamsKZG<Bn256>,
-  public_vals: Vec<Fr>,
+#[cfg(feature = "disk-pk")]
+#[global_allocator]
+static GLOBAL: DiskBackedAlloc = DiskBackedAlloc;
+
+// The bundle the prover hands to the verifier, with the serialized vk and the circuit layout so
+// that the verifier needs neither keygen nor the model and input files
+// The public values are those of every proven circuit, in proving order
+// The params are kept serialized, a bundle read by the verifier comes from the prover and its
+// params are only read once they are pinned (see CliArgs::bundle_params)
+struct MlParams<P: BundleParams> {
+  params: Vec<u8>,
+  public_vals: Vec<Vec<P::Scalar>>,
+  vkey: Option<Vec<u8>>,
+  layout: Option<Vec<u8>>,
+}
+
+// The params of a commitment scheme, as written in a bundle
+trait BundleParams: Sized {
+  type Scalar: PrimeField;
+  const COMMITMENT: &'static str;
+  fn read_params(buf: &[u8]) -> std::io::Result<Self>;
+  fn write_params(&self, buf: &mut Vec<u8>) -> std::io::Result<()>;
 }
 
+impl BundleParams for ParamsKZG<Bn256> {
+  type Scalar = Fr;
+  const COMMITMENT: &'static str = "kzg";
+
+  fn read_params(mut buf: &[u8]) -> std::io::Result<Self> {
+    Params::read(&mut buf)
+  }
+
+  fn write_params(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    self.write(buf)
+  }
+}
+
+impl BundleParams for ParamsIPA<EqAffine> {
+  type Scalar = Fp;
+  const COMMITMENT: &'static str = "ipa";
+
+  fn read_params(mut buf: &[u8]) -> std::io::Result<Self> {
+    Params::read(&mut buf)
+  }
+
+  fn write_params(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    self.write(buf)
+  }
+}
+
+// The public values of the circuits are concatenated, and all have the same length
 #[derive(Debug, Serialize, Deserialize)]
 struct MlParamsSerde {
   params: Vec<u8>,
   public_vals: Vec<u8>,
+  vkey: Vec<u8>,
+  layout: Vec<u8>,
+  num_circuits: u64,
+  commitment: String,
+}
+
+// Bundles from before the IPA commitments, which are all KZG
+#[derive(Debug, Serialize, Deserialize)]
+struct MlParamsSerdeV3 {
+  params: Vec<u8>,
+  public_vals: Vec<u8>,
+  vkey: Vec<u8>,
+  layout: Vec<u8>,
+  num_circuits: u64,
+}
+
+// Bundles from before several circuits could be proven at once
+#[derive(Debug, Serialize, Deserialize)]
+struct MlParamsSerdeV2 {
+  params: Vec<u8>,
+  public_vals: Vec<u8>,
+  vkey: Vec<u8>,
+  layout: Vec<u8>,
+}
+
+// Bundles from before the layout was included
+#[derive(Debug, Serialize, Deserialize)]
+struct MlParamsSerdeV1 {
+  params: Vec<u8>,
+  public_vals: Vec<u8>,
+  vkey: Vec<u8>,
+}
+
+// Bundles from before the vk was included
+#[derive(Debug, Serialize, Deserialize)]
+struct MlParamsSerdeV0 {
+  params: Vec<u8>,
+  public_vals: Vec<u8>,
 }
 
 fn main() -> Result<()> {
@@ -52,19 +210,307 @@ fn main() -> Result<()> {
   Ok(())
 }
 
+// The CLI only has a string error, which carries the kind and the suggestion (see errors.rs)
+fn cli_error(e: ZkmlError) -> circuit_cli::Error {
+  circuit_cli::Error::CliLogicError(e.to_string())
+}
+
+fn loader_error(e: String) -> circuit_cli::Error {
+  cli_error(ZkmlError::loader(e))
+}
+
+fn srs_error(e: String) -> circuit_cli::Error {
+  cli_error(ZkmlError::srs(e))
+}
+
+fn verifier_error(e: String) -> circuit_cli::Error {
+  cli_error(ZkmlError::verifier(e))
+}
+
+// A proof that fails self-verification usually overflows, which the audit can point at
+fn self_verify_error(configs: Vec<ModelMsgpack>) -> circuit_cli::Error {
+  let overflow = configs.iter().find_map(find_overflow);
+  cli_error(overflow.unwrap_or_else(|| ZkmlError::verifier("the proof failed self-verification")))
+}
+
 impl CliArgs {
-  pub fn gen_circuit(&self) -> ModelCircuit<Fr> {
-    let config_fname = self
-      .config_fname
-      .as_ref()
-      .map(|s| s.as_str())
-      .unwrap_or("/data/model.msgpack");
-    let inp_fname = self
-      .inp_fname
-      .as_ref()
-      .map(|s| s.as_str())
-      .unwrap_or("/data/inp.msgpack");
-    ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname)
+  pub fn file_config(&self) -> circuit_cli::Result<ZkmlConfig> {
+    ZkmlConfig::load(self.config.as_deref()).map_err(loader_error)
+  }
+
+  // The model config with each input to prove
+  pub fn gen_configs(&self) -> circuit_cli::Result<Vec<ModelMsgpack>> {
+    let file_config = self.file_config()?;
+    let config_fname = self.config_fname.clone().unwrap_or(file_config.model);
+    let inp_fnames = match &self.inp_fnames {
+      Some(inp_fnames) if !inp_fnames.is_empty() => inp_fnames.clone(),
+      _ => vec![self.inp_fname.clone().unwrap_or(file_config.input)],
+    };
+    let limits = LoaderLimits::default();
+    let model_config = try_load_config_msgpack(&config_fname).map_err(loader_error)?;
+    inp_fnames
+      .iter()
+      .map(|inp_fname| {
+        let inp = read_artifact(inp_fname)
+          .and_then(|buf| parse_inputs_msgpack(&buf, &limits))
+          .map_err(|e| loader_error(format!("input {}: {}", inp_fname, e)))?;
+        check_input_shapes(&model_config, &inp).map_err(cli_error)?;
+        let mut config = add_inputs(model_config.clone(), inp, &limits).map_err(loader_error)?;
+        if let Some(k) = file_config.k {
+          config.k = k as i64;
+        }
+        if file_config.column_profile.is_some() {
+          config.column_profile = file_config.column_profile.clone();
+        }
+        Ok(config)
+      })
+      .collect()
+  }
+
+  pub fn gen_circuit<F: PrimeField + Ord + FromUniformBytes<64>>(
+    &self,
+  ) -> circuit_cli::Result<ModelCircuit<F>> {
+    let config = self.gen_configs()?.remove(0);
+    Ok(ModelCircuit::<F>::generate_from_msgpack(config, true))
+  }
+
+  // The layout in the bundle, or else the layout of the model config
+  pub fn bundle_layout(&self, layout: &Option<Vec<u8>>) -> circuit_cli::Result<ModelMsgpack> {
+    match layout {
+      Some(layout) => rmp_serde::from_slice(layout)
+        .map_err(|e| verifier_error(format!("malformed layout: {}", e))),
+      None => self.gen_layout(),
+    }
+  }
+
+  // The layout of the model config alone, for verifying bundles without a layout
+  pub fn gen_layout(&self) -> circuit_cli::Result<ModelMsgpack> {
+    let file_config = self.file_config()?;
+    let config_fname = self.config_fname.clone().unwrap_or(file_config.model);
+    let mut layout = circuit_layout(&try_load_config_msgpack(&config_fname).map_err(loader_error)?);
+    if let Some(k) = file_config.k {
+      layout.k = k as i64;
+    }
+    if file_config.column_profile.is_some() {
+      layout.column_profile = file_config.column_profile;
+    }
+    Ok(layout)
+  }
+
+  // The vk from the vkey file, if one is given
+  pub fn vkey(&self) -> circuit_cli::Result<Option<Vec<u8>>> {
+    match &self.vkey_fname {
+      Some(vkey_fname) => Ok(Some(std::fs::read(
+        local_path(vkey_fname).map_err(loader_error)?,
+      )?)),
+      None => Ok(None),
+    }
+  }
+
+  // The vk to verify with: the vkey file, or else the vk of the bundle, which must match the
+  // pinned vk hash unless trust_bundle_vk is set. Without either vk, the vk is generated from the
+  // model.
+  pub fn pinned_vkey(&self, bundle_vkey: &Option<Vec<u8>>) -> circuit_cli::Result<Option<Vec<u8>>> {
+    let (vkey, trust) = match (self.vkey()?, bundle_vkey) {
+      (Some(vkey), _) if self.vkey_hash.is_none() => return Ok(Some(vkey)),
+      (Some(vkey), _) => (vkey, false),
+      (None, Some(vkey)) => (vkey.clone(), self.trust_bundle_vk.unwrap_or(false)),
+      (None, None) => return Ok(None),
+    };
+    check_pinned("vk", &vkey, self.vkey_hash.as_deref(), trust).map_err(|e| {
+      let e = ZkmlError::verifier(e);
+      cli_error(match self.vkey_hash {
+        Some(_) => e,
+        None => e.with_suggestion(
+          "pass vkey_fname or vkey_hash, or trust_bundle_vk to verify with the vk of the prover",
+        ),
+      })
+    })?;
+    Ok(Some(vkey))
+  }
+
+  // The params of the bundle, which must match the pinned SRS hash unless trust_bundle_srs is set
+  fn bundle_params<P: BundleParams>(&self, params: &[u8]) -> circuit_cli::Result<P> {
+    let trust = self.trust_bundle_srs.unwrap_or(false);
+    check_pinned("SRS", params, self.srs_hash.as_deref(), trust).map_err(|e| {
+      let e = ZkmlError::srs(e);
+      cli_error(match self.srs_hash {
+        Some(_) => e,
+        None => e.with_suggestion(
+          "pass srs_fname or srs_hash, or trust_bundle_srs to verify with the SRS of the prover",
+        ),
+      })
+    })?;
+    Ok(P::read_params(params)?)
+  }
+
+  // The SRS to verify a circuit of size k with: srs_fname, or else the SRS in the config file or a
+  // larger one from the index next to it. Without either, the pinned SRS of the bundle.
+  pub fn verifier_kzg_params(
+    &self,
+    bundle_params: &[u8],
+    k: u32,
+  ) -> circuit_cli::Result<ParamsKZG<Bn256>> {
+    let srs = match &self.srs_fname {
+      Some(srs_fname) => Some(srs_fname.clone()),
+      None => self.file_config()?.srs,
+    };
+    let srs = match srs {
+      Some(srs) => srs,
+      None => {
+        let mut params = self.bundle_params::<ParamsKZG<Bn256>>(bundle_params)?;
+        fit_kzg_params(&mut params, k).map_err(srs_error)?;
+        return Ok(params);
+      }
+    };
+    let path = local_path(&srs).map_err(srs_error)?;
+    match read_kzg_params(&mut BufReader::new(File::open(path)?), k) {
+      Ok(params) => Ok(params),
+      Err(e) if self.srs_fname.is_none() => self.larger_params(k)?.ok_or_else(|| srs_error(e)),
+      Err(e) => Err(srs_error(e)),
+    }
+  }
+
+  // The params to verify a circuit of size k with: srs_fname, or else the params derived for k,
+  // which the verifier derives itself. The params of the bundle are used only when they are
+  // pinned or trusted.
+  pub fn verifier_ipa_params(
+    &self,
+    bundle_params: &[u8],
+    k: u32,
+  ) -> circuit_cli::Result<ParamsIPA<EqAffine>> {
+    let mut params = match &self.srs_fname {
+      Some(srs_fname) => {
+        let path = local_path(srs_fname).map_err(srs_error)?;
+        ParamsIPA::<EqAffine>::read(&mut BufReader::new(File::open(path)?))
+          .map_err(|e| srs_error(format!("malformed params: {}", e)))?
+      }
+      None if self.srs_hash.is_some() || self.trust_bundle_srs.unwrap_or(false) => {
+        self.bundle_params::<ParamsIPA<EqAffine>>(bundle_params)?
+      }
+      None => return Ok(ParamsIPA::<EqAffine>::new(k)),
+    };
+    fit_ipa_params(&mut params, k).map_err(srs_error)?;
+    Ok(params)
+  }
+
+  pub fn commitment(&self) -> circuit_cli::Result<String> {
+    let commitment = self.commitment.clone().unwrap_or("kzg".to_string());
+    if commitment != "kzg" && commitment != "ipa" {
+      return Err(loader_error(format!(
+        "unknown commitment {}, expected kzg or ipa",
+        commitment
+      )));
+    }
+    Ok(commitment)
+  }
+
+  pub fn transcript(&self) -> circuit_cli::Result<String> {
+    let transcript = match &self.transcript {
+      Some(transcript) => transcript.clone(),
+      None => self.file_config()?.transcript,
+    };
+    check_transcript(&transcript).map_err(loader_error)?;
+    if transcript == "evm" && self.proof_fname.is_some() {
+      return Err(loader_error(
+        "the evm transcript keeps the proof in memory for the calldata, without proof_fname"
+          .to_string(),
+      ));
+    }
+    Ok(transcript)
+  }
+
+  pub fn max_proof_bytes(&self) -> u64 {
+    self.max_proof_bytes.unwrap_or(DEFAULT_MAX_PROOF_BYTES)
+  }
+
+  // The proof file to prove into, if one is given
+  pub fn proof_writer(&self) -> circuit_cli::Result<Option<BoundedWriter<BufWriter<File>>>> {
+    match &self.proof_fname {
+      Some(proof_fname) => Ok(Some(
+        proof_writer(proof_fname, self.max_proof_bytes()).map_err(loader_error)?,
+      )),
+      None => Ok(None),
+    }
+  }
+
+  // The proof to verify, from the proof file if one is given, or else the proof from the CLI
+  pub fn proof_stream<'a>(&self, proof: &'a [u8]) -> circuit_cli::Result<Box<dyn Read + 'a>> {
+    let max_bytes = self.max_proof_bytes();
+    match &self.proof_fname {
+      Some(proof_fname) => Ok(Box::new(
+        proof_reader(proof_fname, max_bytes).map_err(verifier_error)?,
+      )),
+      None if proof.len() as u64 > max_bytes => Err(verifier_error(format!(
+        "the proof has {} bytes, over the bound of {}",
+        proof.len(),
+        max_bytes
+      ))),
+      None => Ok(Box::new(proof)),
+    }
+  }
+
+  // The openings of the samples, if requested
+  pub fn write_batch_openings<F: PrimeField + Ord + FromUniformBytes<64>>(
+    &self,
+    layout: &ModelMsgpack,
+    public_vals: &[Vec<F>],
+  ) -> circuit_cli::Result<()> {
+    if let Some(path) = &self.batch_openings {
+      let openings = open_batch(layout, public_vals).map_err(loader_error)?;
+      println!("batch root: {}", openings.commitment.root);
+      let buf = serde_json::to_vec_pretty(&openings).unwrap();
+      write_artifact(path, &buf).map_err(loader_error)?;
+    }
+    Ok(())
+  }
+
+  pub fn check_batch_root<F: PrimeField + Ord + FromUniformBytes<64>>(
+    &self,
+    layout: &Option<Vec<u8>>,
+    public_vals: &[Vec<F>],
+  ) -> circuit_cli::Result<bool> {
+    match &self.batch_root {
+      Some(root) => {
+        let layout = self.bundle_layout(layout)?;
+        Ok(check_batch_root(&layout, public_vals, root).is_ok())
+      }
+      None => Ok(true),
+    }
+  }
+
+  // A large enough SRS from the index next to the SRS in the config file, if there is one
+  pub fn larger_params(&self, k: u32) -> circuit_cli::Result<Option<ParamsKZG<Bn256>>> {
+    let srs = match self.file_config()?.srs {
+      Some(srs) => srs,
+      None => return Ok(None),
+    };
+    let dir = match srs.rsplit_once('/') {
+      Some((dir, _)) => dir.to_string(),
+      None => ".".to_string(),
+    };
+    let index = SrsIndex::load(&dir).map_err(srs_error)?;
+    if index.select("kzg", k).is_none() {
+      return Ok(None);
+    }
+    Ok(Some(indexed_kzg_params(&dir, k)))
+  }
+
+  // The params reader from the CLI, or the SRS in the config file
+  pub fn params_reader(
+    &self,
+    params_reader: Option<BufReader<File>>,
+  ) -> circuit_cli::Result<Option<BufReader<File>>> {
+    if params_reader.is_some() {
+      return Ok(params_reader);
+    }
+    match self.file_config()?.srs {
+      Some(srs) => {
+        let path = local_path(&srs).map_err(srs_error)?;
+        Ok(Some(BufReader::new(File::open(path)?)))
+      }
+      None => Ok(None),
+    }
   }
 }
 
@@ -74,7 +520,15 @@ impl CliOperator<CliArgs, CliArgs> for Operator {
     args: CliArgs,
     params_reader: Option<BufReader<File>>,
   ) -> circuit_cli::Result<(Vec<u8>, Vec<u8>)> {
-    self.generate_ml_proof(args, params_reader, rand::thread_rng())
+    if let Some(dir) = &args.disk_pk {
+      set_disk_pk_dir(dir).map_err(loader_error)?;
+    }
+    // The SRS in the config file is for KZG
+    if args.commitment()? == "ipa" {
+      return self.generate_ipa_proof(args, params_reader);
+    }
+    let params_reader = args.params_reader(params_reader)?;
+    self.generate_ml_proof(args, params_reader)
   }
 
   fn verify_proof(
@@ -83,13 +537,18 @@ impl CliOperator<CliArgs, CliArgs> for Operator {
     params_reader: Option<BufReader<File>>,
     proof: &[u8],
   ) -> circuit_cli::Result<bool> {
-    self.verify_ml_proof(
-      args,
-      params_reader.ok_or(circuit_cli::Error::CliLogicError(
-        "params reader is none".to_string(),
-      ))?,
-      proof,
-    )
+    let params_reader = args.params_reader(params_reader)?;
+    let raw = read_bundle(params_reader.ok_or_else(|| {
+      cli_error(
+        ZkmlError::srs("there are no params to verify with")
+          .with_suggestion("pass the params bundle written by prove"),
+      )
+    })?)?;
+    // The bundle records its commitment scheme
+    if raw.commitment == "ipa" {
+      return self.verify_ipa_proof(args, MlParams::from_raw(raw)?, proof);
+    }
+    self.verify_ml_proof(args, MlParams::from_raw(raw)?, proof)
   }
 }
 
@@ -98,138 +557,480 @@ impl Operator {
     &self,
     args: CliArgs,
     params_reader: Option<BufReader<File>>,
-    rng: ThreadRng,
   ) -> circuit_cli::Result<(Vec<u8>, Vec<u8>)> {
-    let circuit = args.gen_circuit();
-    let k = circuit.k as u32;
-
-    let params: ParamsKZG<Bn256>;
-    if let Some(mut params_r) = params_reader {
-      params = Params::read::<_>(&mut params_r)?;
-    } else {
-      params = ParamsKZG::<Bn256>::setup(k, rng.clone());
-    }
-
-    let vk = keygen_vk(&params, &circuit)
-      .map_err(|e| circuit_cli::Error::CliLogicError(format!("keygen vk failed: {}", e)))?;
-    let pk = keygen_pk(&params, vk, &circuit)
-      .map_err(|e| circuit_cli::Error::CliLogicError(format!("keygen pk failed: {}", e)))?;
-
-    let _prover = MockProver::run(k, &circuit, vec![vec![]]).unwrap();
-    let public_vals: Vec<Fr> = get_public_values();
-
-    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
-    create_proof::<
-      KZGCommitmentScheme<Bn256>,
-      ProverSHPLONK<'_, Bn256>,
-      Challenge255<G1Affine>,
-      _,
-      Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
-      ModelCircuit<Fr>,
-    >(
-      &params,
-      &pk,
-      &[circuit],
-      &[&[&public_vals]],
-      rng,
-      &mut transcript,
-    )
-    .unwrap();
+    let configs = args.gen_configs()?;
+    let layout_config = circuit_layout(&configs[0]);
+    let layout = model_to_msgpack(&layout_config);
+    let pk_config = args.pkey_fname.as_ref().map(|_| configs[0].clone());
+    // The inputs are for the same model, so the circuits share the gadget config and the keys
+    let circuits = configs
+      .into_iter()
+      .map(|config| ModelCircuit::<Fr>::generate_from_msgpack(config, true))
+      .collect::<Vec<_>>();
+    let k = circuits[0].k as u32;
+    let num_commitments = circuits[0].num_commitments();
+    let rng = prover_rng(circuits[0].zero_knowledge);
+    if init_gpu() {
+      println!("proving with the MSMs and FFTs on the GPU");
+    }
+
+    // Only the part of the SRS the circuit needs is read, unless it isn't downsized
+    let params = match params_reader {
+      Some(mut params_r) if args.downsize.unwrap_or(true) => read_kzg_params(&mut params_r, k),
+      Some(mut params_r) => ParamsKZG::<Bn256>::read(&mut params_r)
+        .map_err(|e| format!("malformed params: {}", e))
+        .and_then(|params| {
+          if params.k() < k {
+            return Err(format!(
+              "the SRS has k = {}, the circuit needs {}",
+              params.k(),
+              k
+            ));
+          }
+          Ok(params)
+        }),
+      None => Ok(ParamsKZG::<Bn256>::setup(k, rand::thread_rng())),
+    };
+    let params = match params {
+      Ok(params) => params,
+      Err(e) => args.larger_params(k)?.ok_or_else(|| srs_error(e))?,
+    };
+
+    let pk = match (&args.pkey_fname, &pk_config) {
+      (Some(pkey_fname), Some(pk_config)) => {
+        load_or_keygen_pk(pkey_fname, pk_config, &params, &circuits[0])
+          .map_err(|e| cli_error(ZkmlError::new(ErrorKind::Prover, e)))?
+      }
+      _ => {
+        keygen_kzg(&params, &circuits[0]).map_err(|e| cli_error(ZkmlError::prover("keygen", &e)))?
+      }
+    };
+
+    if args.transcript()? == "evm" {
+      return generate_evm_proof(&args, params, pk, circuits, layout, rng);
+    }
 
-    let proof = transcript.finalize();
+    let (proof, public_vals) = match args.proof_writer()? {
+      Some(writer) => {
+        let (writer, public_vals) =
+          prove_batch_kzg_to(&params, &pk, circuits, rng, writer).map_err(cli_error)?;
+        (finish_proof(writer)?, public_vals)
+      }
+      None => prove_batch_kzg(&params, &pk, circuits, rng).map_err(cli_error)?,
+    };
 
-    let strategy = SingleStrategy::new(&params);
-    let transcript_read = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-    verify_kzg(
-      &params,
-      &pk.get_vk(),
-      strategy,
-      &public_vals,
-      transcript_read,
-    );
+    // The self-check roughly doubles the verifier work, so it is opt-in
+    let self_verify = args.self_verify.unwrap_or(args.file_config()?.self_verify);
+    if self_verify {
+      let start = Instant::now();
+      let ok = check_batch_kzg_from(
+        &params,
+        pk.get_vk(),
+        &public_vals,
+        num_commitments,
+        args.proof_stream(&proof)?,
+      );
+      record_stage("self_verify", start.elapsed());
+      record_result("self_verify", serde_json::json!(ok));
+      write_stages("metrics.json", false);
+      if !ok {
+        return Err(self_verify_error(args.gen_configs()?));
+      }
+    }
 
-    Ok((proof, MlParams::new(params, public_vals).to_vec()?))
+    args.write_batch_openings(&layout_config, &public_vals)?;
+    let vkey = pk.get_vk().to_bytes(SerdeFormat::RawBytes);
+    println!("vk hash: {}", content_hash(&vkey));
+    Ok((
+      proof,
+      MlParams::new(&params, public_vals, Some(vkey), Some(layout))?.to_vec()?,
+    ))
   }
 
   fn verify_ml_proof(
     &self,
     args: CliArgs,
-    params_reader: BufReader<File>,
+    bundle: MlParams<ParamsKZG<Bn256>>,
     proof: &[u8],
   ) -> circuit_cli::Result<bool> {
-    let circuit = args.gen_circuit();
-    let params = MlParams::from_reader(params_reader)?;
-
-    let vk = keygen_vk(&params.params, &circuit)
-      .map_err(|e| circuit_cli::Error::CliLogicError(format!("keygen vk failed: {}", e)))?;
-
-    let strategy = SingleStrategy::new(&params.params);
-    let mut transcript_read = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-
-    let ok = verify_proof::<
-      KZGCommitmentScheme<Bn256>,
-      VerifierSHPLONK<'_, Bn256>,
-      Challenge255<G1Affine>,
-      Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
-      halo2_proofs::poly::kzg::strategy::SingleStrategy<'_, Bn256>,
-    >(
-      &params.params,
-      &vk,
-      strategy,
-      &[&[&params.public_vals]],
-      &mut transcript_read,
-    )
-    .is_ok();
-    Ok(ok)
+    let vkey = args.pinned_vkey(&bundle.vkey)?;
+
+    // The circuit sets up the gadget config, which is also needed to read the vk. With a vk, the
+    // layout is enough to build it, otherwise keygen needs the full model and input. The circuit
+    // also splits the public values into the instance columns.
+    let (params, vk, num_commitments) = match vkey {
+      Some(vkey) => {
+        let layout = args.bundle_layout(&bundle.layout)?;
+        let circuit = ModelCircuit::<Fr>::generate_from_msgpack(layout, false);
+        let vk =
+          VerifyingKey::read::<&[u8], ModelCircuit<Fr>>(&mut &vkey[..], SerdeFormat::RawBytes, ())
+            .map_err(|e| verifier_error(format!("malformed vk: {}", e)))?;
+        // The k of the vk, which is the k of the circuit unless the proof wasn't downsized
+        let params = args.verifier_kzg_params(&bundle.params, vk.get_domain().k())?;
+        (params, vk, circuit.num_commitments())
+      }
+      None => {
+        let circuit = args.gen_circuit::<Fr>()?;
+        let params = args.verifier_kzg_params(&bundle.params, circuit.k as u32)?;
+        let vk = keygen_vk(&params, &circuit)
+          .map_err(|e| cli_error(ZkmlError::prover("keygen vk", &e)))?;
+        (params, vk, circuit.num_commitments())
+      }
+    };
+
+    if args.transcript()? == "evm" {
+      return check_evm_proof(&params, &bundle.public_vals, &vk, num_commitments, proof);
+    }
+    let proof = args.proof_stream(proof)?;
+    let ok = check_batch_kzg_from(&params, &vk, &bundle.public_vals, num_commitments, proof);
+    Ok(ok && args.check_batch_root(&bundle.layout, &bundle.public_vals)?)
   }
-}
 
-impl MlParams {
-  pub fn new(params: ParamsKZG<Bn256>, public_vals: Vec<Fr>) -> Self {
-    Self {
-      params,
-      public_vals,
+  // IPA over the Pasta curves. The params are derived from a hash to the curve, so without a
+  // params file they are generated for the k of the circuit. The pk cache and the evm transcript
+  // are for KZG only.
+  fn generate_ipa_proof(
+    &self,
+    args: CliArgs,
+    params_reader: Option<BufReader<File>>,
+  ) -> circuit_cli::Result<(Vec<u8>, Vec<u8>)> {
+    if args.transcript()? == "evm" {
+      return Err(loader_error(
+        "the evm transcript needs KZG commitments".to_string(),
+      ));
+    }
+    if args.pkey_fname.is_some() {
+      return Err(loader_error(
+        "pkey_fname only caches KZG proving keys".to_string(),
+      ));
     }
+    let configs = args.gen_configs()?;
+    let layout_config = circuit_layout(&configs[0]);
+    let layout = model_to_msgpack(&layout_config);
+    let circuits = configs
+      .into_iter()
+      .map(|config| ModelCircuit::<Fp>::generate_from_msgpack(config, true))
+      .collect::<Vec<_>>();
+    let k = circuits[0].k as u32;
+    let num_commitments = circuits[0].num_commitments();
+    let rng = prover_rng(circuits[0].zero_knowledge);
+
+    let params = match params_reader {
+      Some(mut params_r) => {
+        let mut params = ParamsIPA::<EqAffine>::read(&mut params_r)
+          .map_err(|e| srs_error(format!("malformed params: {}", e)))?;
+        fit_ipa_params(&mut params, k).map_err(srs_error)?;
+        params
+      }
+      None => ParamsIPA::<EqAffine>::new(k),
+    };
+    let pk =
+      keygen_ipa(&params, &circuits[0]).map_err(|e| cli_error(ZkmlError::prover("keygen", &e)))?;
+    let (proof, public_vals) = match args.proof_writer()? {
+      Some(writer) => {
+        let (writer, public_vals) =
+          prove_batch_ipa_to(&params, &pk, circuits, rng, writer).map_err(cli_error)?;
+        (finish_proof(writer)?, public_vals)
+      }
+      None => prove_batch_ipa(&params, &pk, circuits, rng).map_err(cli_error)?,
+    };
+
+    let self_verify = args.self_verify.unwrap_or(args.file_config()?.self_verify);
+    if self_verify {
+      let start = Instant::now();
+      let ok = check_batch_ipa_from(
+        &params,
+        pk.get_vk(),
+        &public_vals,
+        num_commitments,
+        args.proof_stream(&proof)?,
+      );
+      record_stage("self_verify", start.elapsed());
+      record_result("self_verify", serde_json::json!(ok));
+      write_stages("metrics.json", false);
+      if !ok {
+        return Err(self_verify_error(args.gen_configs()?));
+      }
+    }
+
+    args.write_batch_openings(&layout_config, &public_vals)?;
+    let vkey = pk.get_vk().to_bytes(SerdeFormat::RawBytes);
+    println!("vk hash: {}", content_hash(&vkey));
+    Ok((
+      proof,
+      MlParams::new(&params, public_vals, Some(vkey), Some(layout))?.to_vec()?,
+    ))
   }
 
-  pub fn from_reader(mut reader: BufReader<File>) -> circuit_cli::Result<Self> {
-    let bin_buf = {
-      let mut buf = Vec::new();
-      reader.read_to_end(&mut buf)?;
-      buf
+  fn verify_ipa_proof(
+    &self,
+    args: CliArgs,
+    bundle: MlParams<ParamsIPA<EqAffine>>,
+    proof: &[u8],
+  ) -> circuit_cli::Result<bool> {
+    let vkey = args.pinned_vkey(&bundle.vkey)?;
+    let (params, vk, num_commitments) = match vkey {
+      Some(vkey) => {
+        let layout = args.bundle_layout(&bundle.layout)?;
+        let circuit = ModelCircuit::<Fp>::generate_from_msgpack(layout, false);
+        let vk =
+          VerifyingKey::read::<&[u8], ModelCircuit<Fp>>(&mut &vkey[..], SerdeFormat::RawBytes, ())
+            .map_err(|e| verifier_error(format!("malformed vk: {}", e)))?;
+        let params = args.verifier_ipa_params(&bundle.params, vk.get_domain().k())?;
+        (params, vk, circuit.num_commitments())
+      }
+      None => {
+        let circuit = args.gen_circuit::<Fp>()?;
+        let params = args.verifier_ipa_params(&bundle.params, circuit.k as u32)?;
+        let vk = keygen_vk(&params, &circuit)
+          .map_err(|e| cli_error(ZkmlError::prover("keygen vk", &e)))?;
+        (params, vk, circuit.num_commitments())
+      }
     };
+    let proof = args.proof_stream(proof)?;
+    let ok = check_batch_ipa_from(&params, &vk, &bundle.public_vals, num_commitments, proof);
+    Ok(ok && args.check_batch_root(&bundle.layout, &bundle.public_vals)?)
+  }
+}
+
+// Proves with the Keccak transcript, and writes the verifier contract and the calldata of the
+// proof to the EVM directory (see evm_verifier.rs)
+#[cfg(feature = "evm")]
+fn generate_evm_proof(
+  args: &CliArgs,
+  params: ParamsKZG<Bn256>,
+  pk: ProvingKey<G1Affine>,
+  mut circuits: Vec<ModelCircuit<Fr>>,
+  layout: Vec<u8>,
+  rng: StdRng,
+) -> circuit_cli::Result<(Vec<u8>, Vec<u8>)> {
+  if circuits.len() != 1 {
+    return Err(cli_error(
+      ZkmlError::new(
+        ErrorKind::Shape,
+        "the EVM verifier checks a single input per proof",
+      )
+      .with_suggestion("prove one input at a time with the evm transcript"),
+    ));
+  }
+  let num_commitments = circuits[0].num_commitments();
+  let (proof, public_vals) = prove_evm(&params, &pk, circuits.remove(0), rng).map_err(cli_error)?;
 
-    let raw: MlParamsSerde = bincode::deserialize(&bin_buf)
-      .map_err(|e| circuit_cli::Error::CliLogicError(format!("deserialize params error: {e}")))?;
+  let self_verify = args.self_verify.unwrap_or(args.file_config()?.self_verify);
+  if self_verify && !check_evm(&params, pk.get_vk(), &public_vals, num_commitments, &proof) {
+    return Err(self_verify_error(args.gen_configs()?));
+  }
 
-    let params = Params::read(&mut raw.params.as_slice())?;
-    let mut public_vals = Vec::new();
-    for i in 0..raw.public_vals.len() / 32 {
-      let mut buf = [0u8; 32];
-      buf.copy_from_slice(&raw.public_vals[i * 32..(i + 1) * 32]);
-      public_vals.push(Fr::from_bytes(&buf).unwrap());
+  let dir = args.evm_dir.clone().unwrap_or(".".to_string());
+  let verifier = gen_evm_verifier(&params, pk.get_vk(), public_vals.len(), num_commitments);
+  write_artifact(&join_url(&dir, "Verifier.yul"), verifier.as_bytes()).map_err(loader_error)?;
+  write_artifact(
+    &join_url(&dir, "calldata"),
+    &evm_calldata(&public_vals, num_commitments, &proof),
+  )
+  .map_err(loader_error)?;
+
+  let vkey = pk.get_vk().to_bytes(SerdeFormat::RawBytes);
+  println!("vk hash: {}", content_hash(&vkey));
+  Ok((
+    proof,
+    MlParams::new(&params, vec![public_vals], Some(vkey), Some(layout))?.to_vec()?,
+  ))
+}
+
+#[cfg(not(feature = "evm"))]
+fn generate_evm_proof(
+  _args: &CliArgs,
+  _params: ParamsKZG<Bn256>,
+  _pk: ProvingKey<G1Affine>,
+  _circuits: Vec<ModelCircuit<Fr>>,
+  _layout: Vec<u8>,
+  _rng: StdRng,
+) -> circuit_cli::Result<(Vec<u8>, Vec<u8>)> {
+  Err(loader_error(
+    "the evm transcript needs the evm feature".to_string(),
+  ))
+}
+
+#[cfg(feature = "evm")]
+fn check_evm_proof(
+  params: &ParamsKZG<Bn256>,
+  public_vals: &[Vec<Fr>],
+  vk: &VerifyingKey<G1Affine>,
+  num_commitments: usize,
+  proof: &[u8],
+) -> circuit_cli::Result<bool> {
+  match public_vals {
+    [public_vals] => Ok(check_evm(params, vk, public_vals, num_commitments, proof)),
+    _ => Err(verifier_error("EVM proofs have a single input".to_string())),
+  }
+}
+
+#[cfg(not(feature = "evm"))]
+fn check_evm_proof(
+  _params: &ParamsKZG<Bn256>,
+  _public_vals: &[Vec<Fr>],
+  _vk: &VerifyingKey<G1Affine>,
+  _num_commitments: usize,
+  _proof: &[u8],
+) -> circuit_cli::Result<bool> {
+  Err(loader_error(
+    "the evm transcript needs the evm feature".to_string(),
+  ))
+}
+
+// Flushes the end of a streamed proof. The proof is in the file, so the CLI gets an empty one.
+fn finish_proof(writer: BoundedWriter<BufWriter<File>>) -> circuit_cli::Result<Vec<u8>> {
+  let written = writer.written();
+  writer.finish().map_err(|e| {
+    cli_error(ZkmlError::new(
+      ErrorKind::Prover,
+      format!("writing the proof failed: {}", e),
+    ))
+  })?;
+  println!("proof size: {} bytes", written);
+  Ok(vec![])
+}
+
+// Reads any version of the bundle
+fn read_bundle(mut reader: BufReader<File>) -> circuit_cli::Result<MlParamsSerde> {
+  let bin_buf = {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    buf
+  };
+
+  // The older bundles are prefixes of the newer ones, so the newest format is tried first
+  let kzg = || <ParamsKZG<Bn256> as BundleParams>::COMMITMENT.to_string();
+  let raw: MlParamsSerde = if let Ok(raw) = bincode::deserialize(&bin_buf) {
+    raw
+  } else if let Ok(raw) = bincode::deserialize::<MlParamsSerdeV3>(&bin_buf) {
+    MlParamsSerde {
+      params: raw.params,
+      public_vals: raw.public_vals,
+      vkey: raw.vkey,
+      layout: raw.layout,
+      num_circuits: raw.num_circuits,
+      commitment: kzg(),
+    }
+  } else if let Ok(raw) = bincode::deserialize::<MlParamsSerdeV2>(&bin_buf) {
+    MlParamsSerde {
+      params: raw.params,
+      public_vals: raw.public_vals,
+      vkey: raw.vkey,
+      layout: raw.layout,
+      num_circuits: 1,
+      commitment: kzg(),
+    }
+  } else if let Ok(raw) = bincode::deserialize::<MlParamsSerdeV1>(&bin_buf) {
+    MlParamsSerde {
+      params: raw.params,
+      public_vals: raw.public_vals,
+      vkey: raw.vkey,
+      layout: vec![],
+      num_circuits: 1,
+      commitment: kzg(),
     }
+  } else {
+    let raw: MlParamsSerdeV0 = bincode::deserialize(&bin_buf)
+      .map_err(|e| verifier_error(format!("deserialize params error: {e}")))?;
+    MlParamsSerde {
+      params: raw.params,
+      public_vals: raw.public_vals,
+      vkey: vec![],
+      layout: vec![],
+      num_circuits: 1,
+      commitment: kzg(),
+    }
+  };
+  Ok(raw)
+}
+
+impl<P: BundleParams> MlParams<P> {
+  // Also prints the hash of the params, which the verifier can pin with srs_hash
+  pub fn new(
+    params: &P,
+    public_vals: Vec<Vec<P::Scalar>>,
+    vkey: Option<Vec<u8>>,
+    layout: Option<Vec<u8>>,
+  ) -> circuit_cli::Result<Self> {
+    let mut buf = Vec::new();
+    params.write_params(&mut buf)?;
+    println!("srs hash: {}", content_hash(&buf));
     Ok(Self {
-      params,
+      params: buf,
       public_vals,
+      vkey,
+      layout,
     })
   }
 
-  pub fn to_vec(&self) -> circuit_cli::Result<Vec<u8>> {
-    let mut params = Vec::new();
-    self.params.write(&mut params)?;
+  // The params, vk and layout of the bundle are those of the prover, none of them is trusted here
+  pub fn from_raw(raw: MlParamsSerde) -> circuit_cli::Result<Self> {
+    if raw.commitment != P::COMMITMENT {
+      return Err(verifier_error(format!(
+        "the bundle is for {} commitments, not {}",
+        raw.commitment,
+        P::COMMITMENT
+      )));
+    }
+    let mut flat_public_vals = Vec::new();
+    for chunk in raw.public_vals.chunks_exact(32) {
+      let mut repr = <P::Scalar as PrimeField>::Repr::default();
+      repr.as_mut().copy_from_slice(chunk);
+      let val = Option::from(P::Scalar::from_repr(repr))
+        .ok_or_else(|| verifier_error("a public value is not in the field".to_string()))?;
+      flat_public_vals.push(val);
+    }
+    let num_circuits = raw.num_circuits as usize;
+    if num_circuits == 0 || flat_public_vals.len() % num_circuits != 0 {
+      return Err(verifier_error(format!(
+        "{} public values can't be split between {} circuits",
+        flat_public_vals.len(),
+        num_circuits
+      )));
+    }
+    let circuit_len = flat_public_vals.len() / num_circuits;
+    let public_vals = (0..num_circuits)
+      .map(|i| flat_public_vals[i * circuit_len..(i + 1) * circuit_len].to_vec())
+      .collect();
+    Ok(Self {
+      params: raw.params,
+      public_vals,
+      vkey: if raw.vkey.is_empty() {
+        None
+      } else {
+        Some(raw.vkey)
+      },
+      layout: if raw.layout.is_empty() {
+        None
+      } else {
+        Some(raw.layout)
+      },
+    })
+  }
 
+  pub fn to_vec(&self) -> circuit_cli::Result<Vec<u8>> {
     let mut public_vals = Vec::new();
-    for val in &self.public_vals {
-      public_vals.extend_from_slice(&val.to_bytes());
+    for val in self.public_vals.iter().flatten() {
+      public_vals.extend_from_slice(val.to_repr().as_ref());
     }
 
     Ok(
       bincode::serialize(&MlParamsSerde {
-        params,
+        params: self.params.clone(),
         public_vals,
+        vkey: self.vkey.clone().unwrap_or(vec![]),
+        layout: self.layout.clone().unwrap_or(vec![]),
+        num_circuits: self.public_vals.len() as u64,
+        commitment: P::COMMITMENT.to_string(),
       })
-      .map_err(|e| circuit_cli::Error::CliLogicError(format!("serialize params error: {e}")))?,
+      .map_err(|e| {
+        cli_error(ZkmlError::new(
+          ErrorKind::Prover,
+          format!("serialize params error: {e}"),
+        ))
+      })?,
     )
   }
 }
@@ -0,0 +1,55 @@
+use halo2_proofs::{halo2curves::bn256::Fr, SerdeFormat};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    helpers::prover_rng,
+    loader::load_config_msgpack,
+    pk_cache::read_pk,
+    proof_stream::{proof_writer, DEFAULT_MAX_PROOF_BYTES},
+    proving_kzg::{create_proof_kzg_to, get_kzg_params, serialize},
+    witness::{read_witness, WitnessCircuit},
+  },
+};
+
+// Usage: prove_witness <config> <witness> <pkey>
+// Proves a witness written by export_witness with the model's cached proving key, without running
+// the model. Writes vkey, proof, and public_vals to the current directory, which verify_circuit
+// checks as usual.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let witness_fname = std::env::args().nth(2).expect("witness file path");
+  let pk_fname = std::env::args().nth(3).expect("proving key file path");
+
+  let config = load_config_msgpack(&config_fname);
+  let witness = read_witness(&witness_fname).unwrap();
+  let (circuit, public_vals) = WitnessCircuit::<Fr>::from_witness(&witness, &config).unwrap();
+
+  // Sets the gadget config, which configures the witness circuit and reads the key
+  let model = ModelCircuit::<Fr>::generate_from_msgpack(config.clone(), false);
+  let params = get_kzg_params("./params_kzg", witness.k);
+  let pk = read_pk(&pk_fname, &config, &params)
+    .unwrap()
+    .unwrap_or_else(|| panic!("{} is not a proving key of this model", pk_fname));
+  serialize(&pk.get_vk().to_bytes(SerdeFormat::RawBytes), "vkey");
+
+  let public_vals_u8 = public_vals
+    .iter()
+    .flat_map(|v| v.to_bytes().to_vec())
+    .collect::<Vec<_>>();
+  serialize(&public_vals_u8, "public_vals");
+
+  let writer = proof_writer("proof", DEFAULT_MAX_PROOF_BYTES).unwrap();
+  let writer = create_proof_kzg_to(
+    &params,
+    &pk,
+    &[circuit],
+    &[public_vals],
+    model.num_commitments(),
+    prover_rng(model.zero_knowledge),
+    writer,
+  )
+  .unwrap();
+  let proof_size = writer.written();
+  writer.finish().unwrap();
+  println!("Wrote a proof of {} bytes", proof_size);
+}
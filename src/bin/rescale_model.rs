@@ -0,0 +1,33 @@
+use zkml::utils::{
+  loader::{load_model_msgpack, read_config_msgpack, save_config_msgpack, save_model_msgpack},
+  precision::rescale_model,
+};
+
+// Writes the model at 2^frac_bits, e.g., to audit or estimate it at that precision. Setting
+// frac_bits in the config instead rescales it when the circuit is built. The inputs are at the
+// scale of the config, so they are rescaled with it.
+// Usage: rescale_model <config> <frac bits> <output config> [<input> <output input>]
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let frac_bits = std::env::args()
+    .nth(2)
+    .expect("frac bits")
+    .parse::<i64>()
+    .expect("frac bits is not an integer");
+  let outp_fname = std::env::args().nth(3).expect("output config path");
+  let inp_fnames = std::env::args().nth(4).zip(std::env::args().nth(5));
+
+  let mut model = match &inp_fnames {
+    Some((inp_fname, _)) => load_model_msgpack(&config_fname, inp_fname),
+    None => read_config_msgpack(&config_fname),
+  };
+  let global_sf = model.global_sf;
+  model.frac_bits = Some(frac_bits);
+  let model = rescale_model(&model).unwrap();
+  println!("scale factor {} -> {}", global_sf, model.global_sf);
+
+  match &inp_fnames {
+    Some((_, outp_inp_fname)) => save_model_msgpack(&model, &outp_fname, outp_inp_fname),
+    None => save_config_msgpack(&model, &outp_fname),
+  }
+}
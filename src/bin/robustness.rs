@@ -0,0 +1,94 @@
+use std::{fs::File, io::BufReader};
+
+use halo2_proofs::{circuit::Value, dev::MockProver, halo2curves::bn256::Fr};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    helpers::{convert_pos_int, instance_columns},
+    loader::{load_config_msgpack, save_model_msgpack, TensorMsgpack},
+    robustness::{argmax, check_perturbations, robustness_model},
+  },
+};
+
+// Builds the robustness check circuit for a model, an input, and a directory of perturbed inputs
+// Usage: robustness <config> <input> <perturbed dir> <epsilon> <output dir>
+// Epsilon is in the scaled input units. The class is the prediction of the model on the input.
+// The robustness config and inputs are written to the output dir, to be proven as usual.
+
+fn read_tensors(fname: &str) -> Vec<TensorMsgpack> {
+  let mut reader = BufReader::new(File::open(fname).unwrap());
+  rmp_serde::from_read(&mut reader).unwrap()
+}
+
+fn public_values(config_fname: &str, inp_fname: &str) -> Vec<Fr> {
+  let circuit = ModelCircuit::<Fr>::generate_from_file(config_fname, inp_fname);
+  circuit.compute_public_values().unwrap()
+}
+
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let perturbed_dir = std::env::args().nth(3).expect("perturbed input directory");
+  let epsilon = std::env::args()
+    .nth(4)
+    .expect("epsilon")
+    .parse::<i64>()
+    .unwrap();
+  let outp_dir = std::env::args().nth(5).expect("output directory");
+
+  let mut fnames = std::fs::read_dir(&perturbed_dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.is_file())
+    .collect::<Vec<_>>();
+  fnames.sort();
+  let inp = read_tensors(&inp_fname);
+  let perturbed = fnames
+    .iter()
+    .map(|fname| read_tensors(fname.to_str().unwrap()))
+    .collect::<Vec<_>>();
+
+  let outside = check_perturbations(&inp, &perturbed, epsilon);
+  assert!(
+    outside.is_empty(),
+    "perturbed inputs {:?} are not within epsilon of the input",
+    outside
+  );
+
+  // The class is the prediction on the unperturbed input
+  let model = load_config_msgpack(&config_fname);
+  let num_commitments = model.commit_before.clone().unwrap_or(vec![]).len()
+    + model.commit_after.clone().unwrap_or(vec![]).len();
+  let logits = public_values(&config_fname, &inp_fname)[num_commitments..]
+    .iter()
+    .map(|x| convert_pos_int(Value::known(*x)) as i64)
+    .collect::<Vec<_>>();
+  let class = argmax(&logits);
+  println!("class: {}", class);
+
+  let robust = robustness_model(&model, &inp, &perturbed, epsilon, class as i64);
+  std::fs::create_dir_all(&outp_dir).unwrap();
+  let robust_config = format!("{}/robustness.msgpack", outp_dir);
+  let robust_inp = format!("{}/robustness_inp.msgpack", outp_dir);
+  save_model_msgpack(&robust, &robust_config, &robust_inp);
+
+  // Fails if the prediction changes on any of the perturbed inputs
+  let public_vals = public_values(&robust_config, &robust_inp);
+  let circuit = ModelCircuit::<Fr>::generate_from_file(&robust_config, &robust_inp);
+  let prover = MockProver::run(
+    circuit.k as u32,
+    &circuit,
+    instance_columns(&public_vals, circuit.num_commitments()),
+  )
+  .unwrap();
+  assert_eq!(prover.verify(), Ok(()), "the prediction is not robust");
+  println!("weight commitment: {:?}", public_vals[0]);
+  println!("input commitment: {:?}", public_vals[1]);
+  println!("perturbed input commitment: {:?}", public_vals[2]);
+  println!(
+    "class {} is unchanged on {} inputs within epsilon {}",
+    class,
+    perturbed.len(),
+    epsilon
+  );
+}
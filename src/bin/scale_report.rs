@@ -0,0 +1,58 @@
+use std::{collections::HashMap, fs::File, io::BufWriter};
+
+use zkml::utils::{loader::load_config_msgpack, scales::scale_report};
+
+// Usage: scale_report <config> <report.json> [input bounds json] [min bits]
+// The input bounds file is the same as for audit_model
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let report_fname = std::env::args().nth(2).expect("report file path");
+  let inp_bounds: HashMap<i64, f64> = match std::env::args().nth(3) {
+    Some(fname) => {
+      let bounds: HashMap<String, f64> =
+        serde_json::from_reader(File::open(fname).unwrap()).unwrap();
+      bounds
+        .into_iter()
+        .map(|(k, v)| (k.parse::<i64>().expect("tensor index"), v))
+        .collect()
+    }
+    None => HashMap::new(),
+  };
+  let min_bits = std::env::args()
+    .nth(4)
+    .map(|x| x.parse::<f64>().unwrap())
+    .unwrap_or(4.);
+
+  let config = load_config_msgpack(&config_fname);
+  let report = scale_report(&config, &inp_bounds, min_bits);
+
+  for layer in report.layers.iter() {
+    let outputs = layer
+      .outputs
+      .iter()
+      .map(|edge| {
+        format!(
+          "t{} {:?} sf {}",
+          edge.tensor_idx, edge.shape, edge.scale_factor
+        )
+      })
+      .collect::<Vec<_>>();
+    print!(
+      "layer {} ({}): {}, rounds {:.1} bits, keeps {:.1} bits",
+      layer.layer_idx,
+      layer.layer_type,
+      outputs.join(", "),
+      layer.rounded_bits,
+      layer.out_bits
+    );
+    match &layer.reason {
+      Some(reason) => println!(" <- {}", reason),
+      None => println!(),
+    }
+  }
+  let num_flagged = report.layers.iter().filter(|layer| layer.flagged).count();
+  println!("{} layers lose precision", num_flagged);
+
+  let writer = BufWriter::new(File::create(report_fname).unwrap());
+  serde_json::to_writer_pretty(writer, &report).unwrap();
+}
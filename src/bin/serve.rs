@@ -0,0 +1,35 @@
+use zkml::utils::{
+  sandbox::{Sandbox, SandboxProfile},
+  serve::Server,
+};
+
+// Usage: serve --models <dir> [--params <params dir>] [--socket <socket path>]
+//   [--hardened | --sandbox <profile json>] [--data <dir>]
+// The hardened mode needs the data directory of the requests, --data or data_dir in the profile.
+fn main() {
+  let args = std::env::args().collect::<Vec<_>>();
+  let arg = |name: &str| {
+    let pos = args.iter().position(|arg| arg == name)?;
+    Some(args.get(pos + 1).expect("missing value").clone())
+  };
+  let models_dir = arg("--models").expect("--models <dir> is required");
+  let params_dir = arg("--params").unwrap_or("./params_kzg".to_string());
+  let socket_path = arg("--socket").unwrap_or("/tmp/zkml.sock".to_string());
+
+  let data_dir = arg("--data");
+
+  let sandbox = match arg("--sandbox") {
+    Some(profile_fname) => Some(Sandbox::load(&profile_fname, data_dir).unwrap()),
+    None if args.iter().any(|arg| arg == "--hardened") => {
+      let profile = SandboxProfile {
+        data_dir,
+        ..SandboxProfile::default()
+      };
+      Some(Sandbox::new(profile).unwrap())
+    }
+    None => None,
+  };
+
+  let server = Server::load(&models_dir, &params_dir, sandbox);
+  server.serve(&socket_path);
+}
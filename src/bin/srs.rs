@@ -0,0 +1,74 @@
+use halo2_proofs::{
+  halo2curves::{bn256::Bn256, pasta::EqAffine},
+  poly::{
+    commitment::{Params, ParamsProver},
+    ipa::commitment::ParamsIPA,
+    kzg::commitment::ParamsKZG,
+  },
+};
+use zkml::utils::{
+  srs::SrsIndex,
+  storage::{is_remote, read_artifact},
+};
+
+// Manages the SRS index of a params directory, see utils::srs
+// Usage:
+//   srs list <params dir>
+//   srs add <params dir> <kzg or ipa> <params file>
+//   srs gen <params dir> <kzg or ipa> <k>
+fn main() {
+  let command = std::env::args().nth(1).expect("command");
+  let dir = std::env::args().nth(2).expect("params directory");
+  let mut index = SrsIndex::load(&dir).unwrap();
+
+  match command.as_str() {
+    "list" => {
+      for entry in index.entries.iter() {
+        println!(
+          "{} {} k = {}: {} ({})",
+          entry.scheme,
+          entry.curve,
+          entry.k,
+          entry.file,
+          &entry.hash[..16]
+        );
+      }
+      return;
+    }
+    "add" | "gen" => {}
+    _ => panic!("Unknown command {}, must be list, add, or gen", command),
+  }
+
+  let scheme = std::env::args().nth(3).expect("kzg or ipa");
+  let arg = std::env::args().nth(4).expect("params file or k");
+  let (k, buf) = if command == "add" {
+    let buf = read_artifact(&arg).unwrap();
+    let k = match scheme.as_str() {
+      "kzg" => ParamsKZG::<Bn256>::read(&mut &buf[..]).unwrap().k(),
+      "ipa" => {
+        let params: ParamsIPA<EqAffine> = Params::read::<_>(&mut &buf[..]).unwrap();
+        params.k()
+      }
+      _ => panic!("Must specify kzg or ipa"),
+    };
+    (k, buf)
+  } else {
+    let k = arg.parse::<u32>().expect("k");
+    let mut buf = vec![];
+    match scheme.as_str() {
+      "kzg" => ParamsKZG::<Bn256>::setup(k, rand::thread_rng())
+        .write(&mut buf)
+        .unwrap(),
+      "ipa" => ParamsIPA::<EqAffine>::new(k).write(&mut buf).unwrap(),
+      _ => panic!("Must specify kzg or ipa"),
+    }
+    (k, buf)
+  };
+
+  if !is_remote(&dir) {
+    std::fs::create_dir_all(&dir).unwrap();
+  }
+  let entry = index.add(&dir, &scheme, k, &buf).unwrap();
+  index.save(&dir).unwrap();
+  println!("added {} (k = {})", entry.file, entry.k);
+}
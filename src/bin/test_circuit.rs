@@ -2,7 +2,7 @@ use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
 use zkml::{
   model::ModelCircuit,
   utils::{
-    helpers::get_public_values,
+    helpers::instance_columns,
     loader::{load_model_msgpack, ModelMsgpack},
   },
 };
@@ -15,9 +15,9 @@ fn main() {
 
   let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
 
-  let _prover = MockProver::run(config.k.try_into().unwrap(), &circuit, vec![vec![]]).unwrap();
-  let public_vals = get_public_values();
+  let public_vals = circuit.compute_public_values().unwrap();
 
-  let prover = MockProver::run(config.k.try_into().unwrap(), &circuit, vec![public_vals]).unwrap();
+  let instances = instance_columns(&public_vals, circuit.num_commitments());
+  let prover = MockProver::run(config.k.try_into().unwrap(), &circuit, instances).unwrap();
   assert_eq!(prover.verify(), Ok(()));
 }
@@ -0,0 +1,85 @@
+use std::{fs::File, path::Path};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  model::ModelCircuit,
+  utils::stats::{circuit_stats, CircuitStats},
+};
+
+// Fails if the row or lookup counts of a model grow beyond the pinned counts
+// Usage: test_constraint_counts <config> <input> <pinned stats json> [tolerance]
+// A missing pin fails, so a gate can't pass by pinning its own counts. With ZKML_BLESS=1, the
+// current counts are written to a missing pin instead, to be reviewed and committed.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let pinned_fname = std::env::args().nth(3).expect("pinned stats file path");
+  let tolerance = std::env::args()
+    .nth(4)
+    .map(|x| x.parse::<f64>().unwrap())
+    .unwrap_or(0.);
+
+  let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
+  let stats = circuit_stats(&circuit);
+  println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+
+  if !Path::new(&pinned_fname).exists() {
+    if std::env::var("ZKML_BLESS").map_or(false, |v| v == "1") {
+      let f = File::create(&pinned_fname).unwrap();
+      serde_json::to_writer_pretty(f, &stats).unwrap();
+      println!("no pinned counts found, wrote {}", pinned_fname);
+      return;
+    }
+    panic!(
+      "no pinned counts in {}, run with ZKML_BLESS=1 to pin them",
+      pinned_fname
+    );
+  }
+
+  let pinned: CircuitStats = serde_json::from_reader(File::open(&pinned_fname).unwrap()).unwrap();
+  let grew = |name: &str, current: usize, expected: usize| {
+    let limit = (expected as f64 * (1. + tolerance)).floor() as usize;
+    if current > limit {
+      println!(
+        "{} grew: {} -> {} (limit {})",
+        name, expected, current, limit
+      );
+      true
+    } else {
+      if current < expected {
+        println!(
+          "{} shrank: {} -> {}, consider updating the pin",
+          name, expected, current
+        );
+      }
+      false
+    }
+  };
+
+  let mut regressions = 0;
+  regressions += grew("k", stats.k, pinned.k) as usize;
+  regressions += grew(
+    "advice columns",
+    stats.num_advice_columns,
+    pinned.num_advice_columns,
+  ) as usize;
+  regressions += grew(
+    "fixed columns",
+    stats.num_fixed_columns,
+    pinned.num_fixed_columns,
+  ) as usize;
+  regressions += grew("gates", stats.num_gates, pinned.num_gates) as usize;
+  regressions += grew("lookups", stats.num_lookups, pinned.num_lookups) as usize;
+  regressions += grew("degree", stats.degree, pinned.degree) as usize;
+  regressions += grew(
+    "gadget rows",
+    stats.total_gadget_rows,
+    pinned.total_gadget_rows,
+  ) as usize;
+  for (name, rows) in stats.gadget_rows.iter() {
+    let expected = pinned.gadget_rows.get(name).cloned().unwrap_or(0);
+    regressions += grew(&format!("{} rows", name), *rows, expected) as usize;
+  }
+
+  assert_eq!(regressions, 0, "constraint counts regressed");
+}
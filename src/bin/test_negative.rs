@@ -0,0 +1,974 @@
+use halo2_proofs::{
+  dev::MockProver,
+  halo2curves::{bn256::Fr, ff::Field},
+};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    coprocessor::coprocessor_outputs,
+    envelope::decode_signed,
+    helpers::instance_columns,
+    loader::{BranchMsgpack, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  },
+};
+
+// Runs small models on (mostly) negative inputs through the MockProver. The outputs are checked
+// against a float reference where there is one, and the circuit must reject a wrong output and,
+// where given, a tampered witness.
+// Usage: test_negative [case name filter]
+
+const K: i64 = 15;
+const SF: i64 = 256;
+
+// The data of the tensors of a model, in the order of their idx
+type Inputs = Vec<Vec<i64>>;
+
+// The expected outputs in fixed point
+type Reference = Box<dyn Fn(&Inputs) -> Vec<f64>>;
+
+struct Case {
+  name: &'static str,
+  model: Box<dyn Fn() -> ModelMsgpack>,
+  verifies: bool,
+  // With the tolerance, in units of the last place
+  reference: Option<(Reference, f64)>,
+  // An element (tensor idx, position) that is off by sf in the witness, so the circuit can't match
+  // the honest outputs any more
+  tamper: Option<(i64, usize)>,
+}
+
+impl Case {
+  fn verifies(name: &'static str, model: impl Fn() -> ModelMsgpack + 'static) -> Self {
+    Case {
+      name,
+      model: Box::new(model),
+      verifies: true,
+      reference: None,
+      tamper: None,
+    }
+  }
+
+  fn fails(name: &'static str, model: impl Fn() -> ModelMsgpack + 'static) -> Self {
+    Case {
+      verifies: false,
+      ..Self::verifies(name, model)
+    }
+  }
+
+  fn reference(self, reference: impl Fn(&Inputs) -> Vec<f64> + 'static, tol: f64) -> Self {
+    Case {
+      reference: Some((Box::new(reference), tol)),
+      ..self
+    }
+  }
+
+  fn tamper(self, idx: i64, pos: usize) -> Self {
+    Case {
+      tamper: Some((idx, pos)),
+      ..self
+    }
+  }
+}
+
+fn tensor(idx: i64, shape: Vec<i64>, data: Vec<i64>) -> TensorMsgpack {
+  TensorMsgpack {
+    idx,
+    shape,
+    data,
+    dtype: None,
+  }
+}
+
+// Deterministic values in [-3 sf, sf), two thirds of which are negative
+fn negative_data(len: i64) -> Vec<i64> {
+  (0..len).map(|i| (i * 97) % (4 * SF) - 3 * SF).collect()
+}
+
+fn real(x: i64) -> f64 {
+  x as f64 / SF as f64
+}
+
+// f of the first input, elementwise
+fn pointwise(f: fn(f64) -> f64) -> impl Fn(&Inputs) -> Vec<f64> {
+  move |inp: &Inputs| inp[0].iter().map(|x| f(real(*x)) * SF as f64).collect()
+}
+
+// f of the first two inputs, elementwise
+fn pairwise(f: fn(f64, f64) -> f64) -> impl Fn(&Inputs) -> Vec<f64> {
+  move |inp: &Inputs| {
+    inp[0]
+      .iter()
+      .zip(inp[1].iter())
+      .map(|(a, b)| f(real(*a), real(*b)) * SF as f64)
+      .collect()
+  }
+}
+
+fn identity(inp: &Inputs) -> Vec<f64> {
+  inp[0].iter().map(|x| *x as f64).collect()
+}
+
+fn mean(data: &[i64]) -> f64 {
+  data.iter().sum::<i64>() as f64 / data.len() as f64
+}
+
+// The model of a single layer over the tensors, which are also the inputs of the layer. Every
+// other model here is this one with some fields replaced.
+fn layer_model(
+  layer_type: &str,
+  params: Vec<i64>,
+  tensors: Vec<TensorMsgpack>,
+  out_shape: Vec<i64>,
+) -> ModelMsgpack {
+  let out_idx = tensors.len() as i64;
+  let layer = LayerMsgpack {
+    layer_type: layer_type.to_string(),
+    params,
+    inp_idxes: tensors.iter().map(|t| t.idx).collect(),
+    inp_shapes: tensors.iter().map(|t| t.shape.clone()).collect(),
+    out_idxes: vec![out_idx],
+    out_shapes: vec![out_shape],
+    mask: vec![],
+  };
+  ModelMsgpack {
+    global_sf: SF,
+    k: K,
+    num_cols: 10,
+    inp_idxes: vec![0],
+    out_idxes: vec![out_idx],
+    tensors,
+    layers: vec![layer],
+    use_selectors: Some(true),
+    ..Default::default()
+  }
+}
+
+// The model requantized to 2^frac_bits when the circuit is built (see precision.rs)
+fn with_frac_bits(model: ModelMsgpack, frac_bits: i64) -> ModelMsgpack {
+  ModelMsgpack {
+    frac_bits: Some(frac_bits),
+    ..model
+  }
+}
+
+fn unary(layer_type: &str, params: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 8], negative_data(8));
+  layer_model(layer_type, params, vec![inp], vec![1, 8])
+}
+
+fn binary(layer_type: &str, params: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 8], negative_data(8));
+  let other = tensor(
+    1,
+    vec![1, 8],
+    negative_data(8).iter().rev().cloned().collect(),
+  );
+  layer_model(layer_type, params, vec![inp, other], vec![1, 8])
+}
+
+fn arithmetic_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("add", || binary("Add", vec![0]))
+      .reference(pairwise(|a, b| a + b), 0.)
+      .tamper(0, 7),
+    Case::verifies("sub", || binary("Sub", vec![]))
+      .reference(pairwise(|a, b| a - b), 0.)
+      .tamper(0, 7),
+    Case::verifies("mul", || binary("Mul", vec![]))
+      .reference(pairwise(|a, b| a * b), 0.5)
+      .tamper(0, 7),
+    Case::verifies("square", || unary("Square", vec![]))
+      .reference(pointwise(|x| x * x), 0.5)
+      .tamper(0, 7),
+    Case::verifies("squared_difference", || binary("SquaredDifference", vec![]))
+      .reference(pairwise(|a, b| (a - b) * (a - b)), 0.5)
+      .tamper(0, 7),
+  ]
+}
+
+// Rounds the inputs to table_bits fractional bits before the lookup
+fn activation_table_bits(layer_type: &str, table_bits: i64) -> ModelMsgpack {
+  ModelMsgpack {
+    activation_table_bits: Some(table_bits),
+    ..unary(layer_type, vec![])
+  }
+}
+
+// The tanh approximation, within 1e-3 of the exact (erf) form of the tables
+fn gelu(x: f64) -> f64 {
+  0.5 * x * (1. + ((2. / std::f64::consts::PI).sqrt() * (x + 0.044715 * x.powi(3))).tanh())
+}
+
+// The tables are rounded, so they are within half a unit of the last place. Ln and the reciprocal
+// clamp the negative inputs to their first entry, so they have no reference.
+fn activation_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("tanh", || unary("Tanh", vec![]))
+      .reference(pointwise(f64::tanh), 0.5)
+      .tamper(0, 7),
+    Case::verifies("gelu", || unary("Gelu", vec![]))
+      .reference(pointwise(gelu), 1.)
+      .tamper(0, 7),
+    Case::verifies("silu", || unary("Silu", vec![]))
+      .reference(pointwise(|x| x / (1. + (-x).exp())), 0.5)
+      .tamper(0, 7),
+    // Inputs rounded to sf / 16
+    Case::verifies("gelu_coarse", || activation_table_bits("Gelu", 4))
+      .reference(pointwise(gelu), 10.)
+      .tamper(0, 7),
+    Case::verifies("logistic", || unary("Logistic", vec![]))
+      .reference(pointwise(|x| 1. / (1. + (-x).exp())), 0.5)
+      .tamper(0, 7),
+    Case::verifies("exp", || unary("Exp", vec![]))
+      .reference(pointwise(f64::exp), 0.5)
+      .tamper(0, 7),
+    Case::verifies("exp_table_size", || unary("Exp", vec![8 * SF]))
+      .reference(pointwise(f64::exp), 0.5)
+      .tamper(0, 7),
+    Case::verifies("ln", || unary("Ln", vec![])).tamper(0, 7),
+    Case::verifies("reciprocal", || unary("Reciprocal", vec![])).tamper(0, 7),
+  ]
+}
+
+fn fully_connected(activation: i64) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 4], negative_data(4));
+  let weights = tensor(
+    1,
+    vec![3, 4],
+    negative_data(12).iter().map(|x| x / 3).collect(),
+  );
+  let bias = tensor(2, vec![3], vec![-SF, 0, SF]);
+  layer_model(
+    "FullyConnected",
+    vec![activation],
+    vec![inp, weights, bias],
+    vec![1, 3],
+  )
+}
+
+// x W^T + b of an input of [1, d]
+fn fully_connected_reference(relu: bool) -> impl Fn(&Inputs) -> Vec<f64> {
+  move |inp: &Inputs| {
+    let (x, weights, bias) = (&inp[0], &inp[1], &inp[2]);
+    weights
+      .chunks(x.len())
+      .zip(bias.iter())
+      .map(|(row, b)| {
+        let y = x
+          .iter()
+          .zip(row.iter())
+          .map(|(x, w)| real(*x) * real(*w))
+          .sum::<f64>()
+          + real(*b);
+        let y = if relu { y.max(0.) } else { y };
+        y * SF as f64
+      })
+      .collect()
+  }
+}
+
+// The fully connected layer is proven outside of the circuit, and its output squared inside, with
+// the output supplied by the prover (see coprocessor.rs)
+fn coprocessor() -> ModelMsgpack {
+  let mut model = fully_connected(0);
+  model.layers.push(LayerMsgpack {
+    layer_type: "Square".to_string(),
+    params: vec![],
+    inp_idxes: vec![3],
+    inp_shapes: vec![vec![1, 3]],
+    out_idxes: vec![4],
+    out_shapes: vec![vec![1, 3]],
+    mask: vec![],
+  });
+  let mut model = ModelMsgpack {
+    out_idxes: vec![4],
+    coprocessor_layers: Some(vec![0]),
+    ..model
+  };
+  let outputs = coprocessor_outputs(&model, &vec![]).unwrap();
+  model.tensors.extend(outputs);
+  model
+}
+
+fn conv_2d() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 3, 1], negative_data(9));
+  let weights = tensor(1, vec![1, 2, 2, 1], vec![SF / 2, -SF / 2, SF / 4, -SF]);
+  let bias = tensor(2, vec![1], vec![-SF]);
+  layer_model(
+    "Conv2D",
+    vec![0, 1, 0, 1, 1],
+    vec![inp, weights, bias],
+    vec![1, 2, 2, 1],
+  )
+}
+
+// Two groups of two input and two output channels
+fn conv_2d_grouped() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 3, 4], negative_data(36));
+  let weights = tensor(
+    1,
+    vec![4, 2, 2, 2],
+    negative_data(32).iter().map(|x| x / 4).collect(),
+  );
+  let bias = tensor(2, vec![4], vec![-SF, 0, SF, 0]);
+  layer_model(
+    "Conv2D",
+    vec![0, 1, 0, 1, 1, 2],
+    vec![inp, weights, bias],
+    vec![1, 2, 2, 4],
+  )
+}
+
+// Two output channels per input channel
+fn depthwise_multiplier() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 3, 2], negative_data(18));
+  let weights = tensor(
+    1,
+    vec![1, 2, 2, 4],
+    negative_data(16).iter().map(|x| x / 4).collect(),
+  );
+  let bias = tensor(2, vec![4], vec![-SF, 0, SF, 0]);
+  layer_model(
+    "Conv2D",
+    vec![1, 1, 0, 1, 1],
+    vec![inp, weights, bias],
+    vec![1, 2, 2, 4],
+  )
+}
+
+// A 3x3x3 volume with two channels, SAME padded on every axis
+fn conv_3d() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 3, 3, 2], negative_data(54));
+  let weights = tensor(
+    1,
+    vec![2, 2, 2, 2, 2],
+    negative_data(32).iter().map(|x| x / 8).collect(),
+  );
+  let bias = tensor(2, vec![2], vec![-SF, SF]);
+  layer_model(
+    "Conv3D",
+    vec![0, 1, 1, 1, 1],
+    vec![inp, weights, bias],
+    vec![1, 3, 3, 3, 2],
+  )
+}
+
+// The attention scores of 2 heads, q k^T over 3 tokens with heads of size 2
+fn attention_scores() -> ModelMsgpack {
+  let q = tensor(0, vec![1, 2, 3, 2], negative_data(12));
+  let k = tensor(
+    1,
+    vec![1, 2, 3, 2],
+    negative_data(12).iter().rev().map(|x| x / 2).collect(),
+  );
+  layer_model("BatchMatMul", vec![0, 1], vec![q, k], vec![1, 2, 3, 3])
+}
+
+fn attention_scores_reference(inp: &Inputs) -> Vec<f64> {
+  let (q, k) = (&inp[0], &inp[1]);
+  let mut scores = vec![];
+  for (q, k) in q.chunks(6).zip(k.chunks(6)) {
+    for q in q.chunks(2) {
+      for k in k.chunks(2) {
+        let score = q
+          .iter()
+          .zip(k.iter())
+          .map(|(a, b)| real(*a) * real(*b))
+          .sum::<f64>();
+        scores.push(score * SF as f64);
+      }
+    }
+  }
+  scores
+}
+
+// The convolutions have their tampered element in every window. The one of conv_3d is behind a
+// ReLU, so it has none.
+fn linear_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("fully_connected", || fully_connected(0))
+      .reference(fully_connected_reference(false), 1.)
+      .tamper(0, 0),
+    Case::verifies("fully_connected_relu", || fully_connected(1))
+      .reference(fully_connected_reference(true), 1.)
+      .tamper(0, 0),
+    Case::verifies("fully_connected_frac_bits", || {
+      with_frac_bits(fully_connected(1), 10)
+    })
+    .tamper(0, 0),
+    Case::verifies("coprocessor", coprocessor).tamper(3, 0),
+    Case::verifies("conv_2d", conv_2d).tamper(0, 4),
+    Case::verifies("conv_2d_grouped", conv_2d_grouped).tamper(0, 16),
+    Case::verifies("depthwise_multiplier", depthwise_multiplier).tamper(0, 8),
+    Case::verifies("conv_3d", conv_3d),
+    Case::verifies("attention_scores", attention_scores)
+      .reference(attention_scores_reference, 1.)
+      .tamper(0, 0),
+  ]
+}
+
+fn pool_2d(layer_type: &str) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 2, 2, 1], vec![-3 * SF, -SF, -2 * SF, -SF / 2]);
+  layer_model(layer_type, vec![2, 2, 2, 2], vec![inp], vec![1, 1, 1, 1])
+}
+
+// A window size that is not a power of two
+fn avg_pool_3x3() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 3, 1], negative_data(9));
+  layer_model(
+    "AveragePool2D",
+    vec![3, 3, 3, 3],
+    vec![inp],
+    vec![1, 1, 1, 1],
+  )
+}
+
+// Windows of 2x2x2 with a stride of 2 over depth 3, so the last slice is dropped
+fn pool_3d(layer_type: &str) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3, 2, 2, 2], negative_data(24));
+  layer_model(
+    layer_type,
+    vec![2, 2, 2, 2, 2, 2],
+    vec![inp],
+    vec![1, 1, 1, 1, 2],
+  )
+}
+
+// The window of every channel is the first 8 pixels
+fn pool_3d_reference(pool: fn(&[i64]) -> f64) -> impl Fn(&Inputs) -> Vec<f64> {
+  move |inp: &Inputs| {
+    (0..2)
+      .map(|c| pool(&(0..8).map(|i| inp[0][2 * i + c]).collect::<Vec<_>>()))
+      .collect()
+  }
+}
+
+fn max(data: &[i64]) -> f64 {
+  *data.iter().max().unwrap() as f64
+}
+
+// Order statistics of 8 elements, so the median is interpolated
+fn quantile(quantiles: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 8], negative_data(8));
+  let num_quantiles = quantiles.len() as i64;
+  layer_model("Quantile", quantiles, vec![inp], vec![num_quantiles])
+}
+
+// Interpolated linearly like numpy. The tampered element moves across the median.
+fn quantile_case(name: &'static str, quantiles: Vec<i64>) -> Case {
+  let model_quantiles = quantiles.clone();
+  Case::verifies(name, move || quantile(model_quantiles.clone()))
+    .reference(
+      move |inp: &Inputs| {
+        let mut sorted = inp[0].clone();
+        sorted.sort();
+        quantiles
+          .iter()
+          .map(|q| {
+            let pos = real(*q) * (sorted.len() - 1) as f64;
+            let (i, w) = (pos.floor() as usize, pos - pos.floor());
+            let next = sorted.get(i + 1).unwrap_or(&sorted[i]);
+            sorted[i] as f64 + w * (next - sorted[i]) as f64
+          })
+          .collect()
+      },
+      0.5,
+    )
+    .tamper(0, 3)
+}
+
+// The tampered elements are in the windows, and the largest one for the max pools
+fn reduction_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("max_pool_2d", || pool_2d("MaxPool2D"))
+      .reference(|inp: &Inputs| vec![max(&inp[0])], 0.)
+      .tamper(0, 3),
+    Case::verifies("avg_pool_2d", || pool_2d("AveragePool2D"))
+      .reference(|inp: &Inputs| vec![mean(&inp[0])], 0.5)
+      .tamper(0, 0),
+    Case::verifies("avg_pool_2d_3x3", avg_pool_3x3)
+      .reference(|inp: &Inputs| vec![mean(&inp[0])], 0.5)
+      .tamper(0, 0),
+    Case::verifies("max_pool_3d", || pool_3d("MaxPool3D"))
+      .reference(pool_3d_reference(max), 0.)
+      .tamper(0, 10),
+    Case::verifies("avg_pool_3d", || pool_3d("AveragePool3D"))
+      .reference(pool_3d_reference(mean), 0.5)
+      .tamper(0, 10),
+    quantile_case("median", vec![SF / 2]),
+    quantile_case("quantiles", vec![0, SF / 10, 3 * SF / 4, SF]),
+  ]
+}
+
+fn reshape() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 4], negative_data(4));
+  layer_model("Reshape", vec![], vec![inp], vec![2, 2])
+}
+
+fn gather() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 8], negative_data(8));
+  layer_model("Gather", vec![1, 7, 0, 7], vec![inp], vec![1, 3])
+}
+
+// Rows of a 5x3 embedding table, looked up by runtime token ids
+fn embedding(ids: Vec<i64>) -> ModelMsgpack {
+  let table = tensor(0, vec![5, 3], negative_data(15));
+  let num_ids = ids.len() as i64;
+  let ids = tensor(1, vec![1, num_ids], ids);
+  layer_model("Gather", vec![0], vec![table, ids], vec![1, num_ids, 3])
+}
+
+fn embedding_reference(inp: &Inputs) -> Vec<f64> {
+  let (table, ids) = (&inp[0], &inp[1]);
+  ids
+    .iter()
+    .flat_map(|id| table[3 * *id as usize..][..3].iter().map(|x| *x as f64))
+    .collect()
+}
+
+// Replaces the rows (k = 1) or the elements (k = 2) at the index tuples of a 3x4 input
+fn scatter_nd(k: i64, indices: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![3, 4], negative_data(12));
+  let num_updates = indices.len() as i64 / k * if k == 1 { 4 } else { 1 };
+  let updates = tensor(1, vec![num_updates], vec![SF; num_updates as usize]);
+  let params = [vec![k], indices].concat();
+  layer_model("ScatterND", params, vec![inp, updates], vec![3, 4])
+}
+
+// The tampered element at (0, 0) is not replaced
+fn scatter_nd_case(name: &'static str, k: i64, indices: Vec<i64>) -> Case {
+  let model_indices = indices.clone();
+  Case::verifies(name, move || scatter_nd(k, model_indices.clone()))
+    .reference(
+      move |inp: &Inputs| {
+        let (mut outp, mut updates) = (inp[0].clone(), inp[1].iter());
+        for index in indices.chunks(k as usize) {
+          let (start, len) = match index {
+            [row] => (4 * *row as usize, 4),
+            [row, col] => (4 * *row as usize + *col as usize, 1),
+            _ => unreachable!(),
+          };
+          for x in outp[start..start + len].iter_mut() {
+            *x = *updates.next().unwrap();
+          }
+        }
+        outp.iter().map(|x| *x as f64).collect()
+      },
+      0.,
+    )
+    .tamper(0, 0)
+}
+
+fn shape_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("reshape", reshape)
+      .reference(identity, 0.)
+      .tamper(0, 0),
+    Case::verifies("gather", gather)
+      .reference(
+        |inp: &Inputs| [7, 0, 7].iter().map(|i| inp[0][*i] as f64).collect(),
+        0.,
+      )
+      .tamper(0, 7),
+    Case::verifies("embedding", || embedding(vec![3, 0, 3]))
+      .reference(embedding_reference, 0.)
+      .tamper(0, 0),
+    Case::fails("embedding_out_of_range", || embedding(vec![1, 5])),
+    scatter_nd_case("scatter_nd_rows", 1, vec![1]),
+    scatter_nd_case("scatter_nd_elements", 2, vec![0, 1, 2, 3]),
+  ]
+}
+
+// Negative values and ties of halves for a divisor of 4, and of thirds for a divisor of 3 on the
+// second channel
+fn div_mod(mode: i64, output: i64, divisors: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![4, 2], vec![-6, -5, -2, -1, 0, 2, 6, 7]);
+  let params = [vec![mode, output], divisors].concat();
+  layer_model("DivMod", params, vec![inp], vec![4, 2])
+}
+
+// The quotients (output 0) or the remainders x - q d (output 1) of the modes floor (0), round half
+// to even (1) and trunc (2)
+fn div_mod_case(name: &'static str, mode: i64, output: i64, divisors: Vec<i64>) -> Case {
+  let model_divisors = divisors.clone();
+  Case::verifies(name, move || div_mod(mode, output, model_divisors.clone()))
+    .reference(
+      move |inp: &Inputs| {
+        inp[0]
+          .iter()
+          .enumerate()
+          .map(|(i, x)| {
+            let d = divisors[i % divisors.len()] as f64;
+            let y = *x as f64 / d;
+            let q = match mode {
+              0 => y.floor(),
+              1 if y - y.floor() == 0.5 => 2. * (y / 2.).round(),
+              1 => y.round(),
+              _ => y.trunc(),
+            };
+            if output == 0 {
+              q
+            } else {
+              *x as f64 - q * d
+            }
+          })
+          .collect()
+      },
+      0.,
+    )
+    .tamper(0, 1)
+}
+
+fn range_check(data: Vec<i64>) -> ModelMsgpack {
+  let len = data.len() as i64;
+  let inp = tensor(0, vec![1, len], data);
+  layer_model("RangeCheck", vec![8], vec![inp], vec![1, len])
+}
+
+// A 20 bit range check, wider than the input lookup, split into 8 bit limbs
+fn range_check_limbs(data: Vec<i64>) -> ModelMsgpack {
+  let len = data.len() as i64;
+  let inp = tensor(0, vec![1, len], data);
+  ModelMsgpack {
+    range_check_limb_bits: Some(8),
+    ..layer_model("RangeCheck", vec![20], vec![inp], vec![1, len])
+  }
+}
+
+// The tampered range checks are out of the range
+fn integer_cases() -> Vec<Case> {
+  vec![
+    div_mod_case("div_floor", 0, 0, vec![4]),
+    div_mod_case("div_round_half_even", 1, 0, vec![4]),
+    div_mod_case("mod_trunc", 2, 1, vec![4]),
+    div_mod_case("mod_per_channel", 1, 1, vec![4, 3]),
+    Case::verifies("range_check", || {
+      range_check(vec![-128, -127, -1, 0, 1, 127])
+    })
+    .reference(identity, 0.)
+    .tamper(0, 5),
+    Case::verifies("range_check_frac_bits", || {
+      with_frac_bits(range_check(vec![-128, 127]), 9)
+    })
+    .tamper(0, 1),
+    Case::fails("range_check_frac_bits_above", || {
+      with_frac_bits(range_check(vec![0, 128]), 9)
+    }),
+    Case::fails("range_check_below", || range_check(vec![-129, 0])),
+    Case::fails("range_check_above", || range_check(vec![0, 128])),
+    Case::verifies("range_check_limbs", || {
+      range_check_limbs(vec![-(1 << 19), -1, 0, 255, 256, (1 << 19) - 1])
+    })
+    .reference(identity, 0.)
+    .tamper(0, 5),
+    Case::fails("range_check_limbs_below", || {
+      range_check_limbs(vec![-(1 << 19) - 1, 0])
+    }),
+    Case::fails("range_check_limbs_above", || {
+      range_check_limbs(vec![0, 1 << 19])
+    }),
+  ]
+}
+
+// Two trees with two outputs: x[0] <= -sf ? (x[1] <= 0 ? l0 : l1) : l2, and x[2] <= sf ? l3 : l4
+fn tree_ensemble() -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 3], vec![-2 * SF, SF / 2, SF]);
+  let thresholds = tensor(1, vec![3], vec![-SF, 0, SF]);
+  let leaf_values = tensor(
+    2,
+    vec![5, 2],
+    vec![SF, -SF, -2 * SF, SF, 0, 3 * SF, -SF, -SF, SF, 0],
+  );
+  #[rustfmt::skip]
+  let params = vec![
+    2, 2,
+    5, 1, 4, 0, 2, 3, 1, 0, 0, -1, 0, 0, -1, 0, 0, -1,
+    3, 1, 2, 2, 0, 0, -1, 0, 0, -1,
+  ];
+  layer_model(
+    "TreeEnsemble",
+    params,
+    vec![inp, thresholds, leaf_values],
+    vec![1, 2],
+  )
+}
+
+// Three experts over a token of size 2, the top 2 of the gate logits are experts 0 and 2
+fn moe(chosen: Vec<i64>) -> ModelMsgpack {
+  let inp = tensor(0, vec![1, 2], vec![SF, -SF / 2]);
+  let logits = tensor(1, vec![1, 3], vec![SF, -2 * SF, SF / 2]);
+  let chosen = tensor(2, vec![2], chosen);
+  let mut tensors = vec![inp, logits, chosen];
+  for r in 0..2 {
+    let idx = 3 + 4 * r;
+    tensors.push(tensor(idx, vec![2, 2], vec![SF, -SF, SF / 2, SF]));
+    tensors.push(tensor(idx + 1, vec![2], vec![0, SF / 4]));
+    tensors.push(tensor(idx + 2, vec![2, 2], vec![-SF, SF, SF, 0]));
+    tensors.push(tensor(idx + 3, vec![2], vec![SF / 2, -SF]));
+  }
+  let mut model = layer_model("MoE", vec![3, 2], tensors, vec![1, 2]);
+  let chosen_idx = model.out_idxes[0] + 1;
+  model.layers[0].out_idxes.push(chosen_idx);
+  model.layers[0].out_shapes.push(vec![2]);
+  model.out_idxes.push(chosen_idx);
+  model
+}
+
+// A recurrent cell over 3 timesteps of size 2, with a hidden state of size 2
+fn recurrent(layer_type: &str, num_gates: i64, return_sequences: i64) -> ModelMsgpack {
+  let num_rows = 2 * num_gates;
+  let quarter = |data: Vec<i64>| data.iter().map(|x| x / 4).collect::<Vec<_>>();
+  let inp = tensor(0, vec![3, 2], negative_data(6));
+  let weights = tensor(1, vec![num_rows, 2], quarter(negative_data(2 * num_rows)));
+  let mut recurrent_data = quarter(negative_data(2 * num_rows));
+  recurrent_data.reverse();
+  let recurrent = tensor(2, vec![num_rows, 2], recurrent_data);
+  let bias = tensor(
+    3,
+    vec![num_rows],
+    (0..num_rows).map(|i| (i % 3 - 1) * SF / 4).collect(),
+  );
+  let out_shape = if return_sequences == 1 {
+    vec![3, 2]
+  } else {
+    vec![1, 2]
+  };
+  layer_model(
+    layer_type,
+    vec![return_sequences],
+    vec![inp, weights, recurrent, bias],
+    out_shape,
+  )
+}
+
+// Normalizes 2 rows of 4
+fn layer_norm() -> ModelMsgpack {
+  let inp = tensor(0, vec![2, 4], negative_data(8));
+  let gamma = tensor(1, vec![4], vec![SF, SF / 2, -SF, 2 * SF]);
+  let beta = tensor(2, vec![4], vec![0, -SF, SF / 4, SF]);
+  layer_model("LayerNorm", vec![1], vec![inp, gamma, beta], vec![2, 4])
+}
+
+// With the eps of the model, 1 / sf
+fn layer_norm_reference(inp: &Inputs) -> Vec<f64> {
+  let (gamma, beta) = (&inp[1], &inp[2]);
+  let mut outp = vec![];
+  for row in inp[0].chunks(gamma.len()) {
+    let row = row.iter().map(|x| real(*x)).collect::<Vec<_>>();
+    let mean = row.iter().sum::<f64>() / row.len() as f64;
+    let var = row.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / row.len() as f64;
+    for ((x, g), b) in row.iter().zip(gamma.iter()).zip(beta.iter()) {
+      let y = (x - mean) / (var + 1. / SF as f64).sqrt() * real(*g) + real(*b);
+      outp.push(y * SF as f64);
+    }
+  }
+  outp
+}
+
+// The softmax over the last axis of the scores of 2 heads
+fn attention_softmax() -> ModelMsgpack {
+  let inp = tensor(0, vec![2, 2, 3], negative_data(12));
+  layer_model("Softmax", vec![], vec![inp], vec![2, 2, 3])
+}
+
+// MC dropout with its masks derived from the seed (see randomness.rs)
+fn dropout(keep: i64, seed: i64) -> ModelMsgpack {
+  let model = unary("Dropout", vec![keep]);
+  let tensors = [model.tensors.clone(), vec![tensor(2, vec![1], vec![seed])]].concat();
+  ModelMsgpack {
+    tensors,
+    inp_idxes: vec![0, 2],
+    random_seed: Some(2),
+    ..model
+  }
+}
+
+// The softmax is too coarse at this scale factor for a reference, and the masks of the dropout
+// are random. The rsqrt of the layer norm is rounded, so it is within a few units of the last
+// place.
+fn composite_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("tree_ensemble", tree_ensemble)
+      .reference(|_: &Inputs| vec![-3. * SF as f64, 0.], 0.)
+      .tamper(0, 2),
+    Case::verifies("moe", || moe(vec![0, 2])).tamper(0, 0),
+    Case::verifies("moe_reordered", || moe(vec![2, 0])).tamper(0, 0),
+    Case::fails("moe_not_top_k", || moe(vec![0, 1])),
+    Case::fails("moe_repeated", || moe(vec![0, 0])),
+    Case::verifies("lstm", || recurrent("LSTM", 4, 1)).tamper(0, 0),
+    Case::verifies("lstm_last", || recurrent("LSTM", 4, 0)).tamper(0, 4),
+    Case::verifies("gru", || recurrent("GRU", 3, 1)).tamper(0, 0),
+    Case::verifies("layer_norm", layer_norm)
+      .reference(layer_norm_reference, 8.)
+      .tamper(0, 0),
+    Case::verifies("attention_softmax", attention_softmax).tamper(0, 0),
+    Case::verifies("dropout", || dropout(SF / 2, 7)),
+    Case::verifies("dropout_keep_all", || dropout(SF, -3))
+      .reference(identity, 0.)
+      .tamper(0, 7),
+  ]
+}
+
+// Adds the inputs if the condition is positive and subtracts them otherwise
+fn branch(cond: i64, taken: bool) -> ModelMsgpack {
+  let mut model = binary("Add", vec![0]);
+  model.layers.push(LayerMsgpack {
+    layer_type: "Sub".to_string(),
+    out_idxes: vec![3],
+    ..model.layers[0].clone()
+  });
+  model.tensors.push(tensor(4, vec![1], vec![cond]));
+  ModelMsgpack {
+    out_idxes: vec![5],
+    branches: Some(vec![BranchMsgpack {
+      cond: 4,
+      then_outs: vec![2],
+      else_outs: vec![3],
+      out_idxes: vec![5],
+      taken: Some(taken),
+    }]),
+    ..model
+  }
+}
+
+// Reveals whether the argmax of the 8 sums is the class, or whether a sum is over a threshold
+fn predicate(predicate: Vec<i64>) -> ModelMsgpack {
+  ModelMsgpack {
+    output_predicate: Some(predicate),
+    ..binary("Add", vec![0])
+  }
+}
+
+// Reveals the indices of the largest or smallest of the 8 sums
+fn top_k(top_k: Vec<i64>) -> ModelMsgpack {
+  ModelMsgpack {
+    output_top_k: Some(top_k),
+    ..binary("Add", vec![0])
+  }
+}
+
+// The 8 sums are all equal, so the revealed predicates and indices are the order of the ties.
+// A tampered sum may or may not move them, so only a wrong output is rejected.
+fn output_cases() -> Vec<Case> {
+  vec![
+    Case::verifies("branch_then", || branch(SF, true))
+      .reference(pairwise(|a, b| a + b), 0.)
+      .tamper(0, 7),
+    Case::verifies("branch_else", || branch(-SF, false))
+      .reference(pairwise(|a, b| a - b), 0.)
+      .tamper(0, 7),
+    Case::verifies("branch_zero", || branch(0, false))
+      .reference(pairwise(|a, b| a - b), 0.)
+      .tamper(0, 7),
+    Case::fails("branch_not_taken", || branch(SF, false)),
+    Case::verifies("predicate_argmax", || predicate(vec![0, 2, 0])),
+    Case::verifies("predicate_argmax_last", || predicate(vec![0, 2, 7])),
+    Case::verifies("predicate_threshold", || predicate(vec![1, 2, 3, -SF])),
+    Case::verifies("argmax", || top_k(vec![0, 2, 1])),
+    Case::verifies("argmin", || top_k(vec![1, 2, 1])),
+    Case::verifies("top_k", || top_k(vec![0, 2, 3])),
+  ]
+}
+
+// Ok if the case verifies or fails as expected, and the outputs and the tampered witnesses are
+// checked
+fn run(case: &Case) -> Result<(), String> {
+  let model = (case.model)();
+  let k = model.k as u32;
+  let mut tensors = model.tensors.clone();
+  tensors.sort_by_key(|t| t.idx);
+  let inputs = tensors.into_iter().map(|t| t.data).collect::<Inputs>();
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack(model, true);
+  let num_commitments = circuit.num_commitments();
+  let verify = |circuit: &ModelCircuit<Fr>, public_vals: &Vec<Fr>| {
+    MockProver::run(k, circuit, instance_columns(public_vals, num_commitments))
+      .unwrap()
+      .verify()
+  };
+
+  // The values the layers can't assign fail already when the public values are computed
+  let public_vals = match circuit.compute_public_values() {
+    Ok(public_vals) => public_vals,
+    Err(err) if !case.verifies => {
+      println!("{}: {}", case.name, err);
+      return Ok(());
+    }
+    Err(err) => return Err(err),
+  };
+  match (verify(&circuit, &public_vals), case.verifies) {
+    (Ok(()), true) => {}
+    (Ok(()), false) => return Err("verified".to_string()),
+    (Err(errs), verifies) => {
+      let msg = format!("{} failures, first: {:?}", errs.len(), errs[0]);
+      if verifies {
+        return Err(msg);
+      }
+      println!("{}: {}", case.name, msg);
+      return Ok(());
+    }
+  }
+
+  let mut wrong_vals = public_vals.clone();
+  *wrong_vals.last_mut().unwrap() += Fr::ONE;
+  if verify(&circuit, &wrong_vals).is_ok() {
+    return Err("verified a wrong output".to_string());
+  }
+
+  if let Some((idx, pos)) = case.tamper {
+    let mut tampered = circuit.clone();
+    let elem = tampered
+      .tensors
+      .get_mut(&idx)
+      .unwrap()
+      .iter_mut()
+      .nth(pos)
+      .unwrap();
+    *elem += Fr::from(SF as u64);
+    if verify(&tampered, &public_vals).is_ok() {
+      return Err(format!("verified tensor {} tampered at {}", idx, pos));
+    }
+  }
+
+  // The outputs are the last public values
+  if let Some((reference, tol)) = &case.reference {
+    let expected = reference(&inputs);
+    assert!(expected.len() <= public_vals.len());
+    let outputs = &public_vals[public_vals.len() - expected.len()..];
+    for (i, (out, expected)) in outputs.iter().zip(expected.iter()).enumerate() {
+      let out = decode_signed(out).ok_or(format!("output {} out of range", i))? as f64;
+      if (out - expected).abs() > *tol {
+        return Err(format!("output {} is {}, expected {:.2}", i, out, expected));
+      }
+    }
+  }
+  Ok(())
+}
+
+fn main() {
+  let filter = std::env::args().nth(1).unwrap_or("".to_string());
+
+  let cases = vec![
+    arithmetic_cases(),
+    activation_cases(),
+    linear_cases(),
+    reduction_cases(),
+    shape_cases(),
+    integer_cases(),
+    composite_cases(),
+    output_cases(),
+  ];
+  let mut num_failed = 0;
+  for case in cases.iter().flatten() {
+    if !case.name.contains(filter.as_str()) {
+      continue;
+    }
+    match run(case) {
+      Ok(()) => println!("{}: ok", case.name),
+      Err(err) => {
+        println!("{}: FAILED, {}", case.name, err);
+        num_failed += 1;
+      }
+    }
+  }
+  assert_eq!(num_failed, 0, "{} negative value tests failed", num_failed);
+}
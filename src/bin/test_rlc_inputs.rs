@@ -0,0 +1,232 @@
+use halo2_proofs::{
+  dev::MockProver,
+  halo2curves::{
+    bn256::Fr,
+    ff::{Field, PrimeField},
+  },
+};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    envelope::ProofEnvelope,
+    helpers::instance_columns,
+    loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+    rlc::{check_rlc_inputs, tensors_rlc, NUM_RLC_VALS},
+  },
+};
+
+// Checks the rlc inputs (see rlc.rs): the circuit binds the combination in the public values to
+// the assigned inputs, and check_rlc_inputs binds it to the revealed inputs.
+// Usage: test_rlc_inputs [case name filter]
+
+const K: i64 = 15;
+
+struct Fixture {
+  model: ModelMsgpack,
+  circuit: ModelCircuit<Fr>,
+  public_vals: Vec<Fr>,
+}
+
+fn tensor(idx: i64, data: Vec<i64>) -> TensorMsgpack {
+  TensorMsgpack {
+    idx,
+    shape: vec![1, 4],
+    data,
+    dtype: None,
+  }
+}
+
+// An Add of two revealed inputs
+fn add_model() -> ModelMsgpack {
+  ModelMsgpack {
+    global_sf: 256,
+    k: K,
+    num_cols: 10,
+    inp_idxes: vec![0, 1],
+    out_idxes: vec![2],
+    tensors: vec![
+      tensor(0, vec![256, -512, 3, 0]),
+      tensor(1, vec![1, 2, -3, 4]),
+    ],
+    layers: vec![LayerMsgpack {
+      layer_type: "Add".to_string(),
+      params: vec![0],
+      inp_idxes: vec![0, 1],
+      inp_shapes: vec![vec![1, 4], vec![1, 4]],
+      out_idxes: vec![2],
+      out_shapes: vec![vec![1, 4]],
+      mask: vec![],
+    }],
+    use_selectors: Some(true),
+    rlc_inputs: Some(vec![0, 1]),
+    ..Default::default()
+  }
+}
+
+fn fixture() -> Fixture {
+  let model = add_model();
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack(model.clone(), true);
+  let public_vals = circuit.compute_public_values().unwrap();
+  Fixture {
+    model,
+    circuit,
+    public_vals,
+  }
+}
+
+fn verify(circuit: &ModelCircuit<Fr>, public_vals: &Vec<Fr>) -> Result<(), String> {
+  let instances = instance_columns(public_vals, circuit.num_commitments());
+  match MockProver::run(K as u32, circuit, instances)
+    .unwrap()
+    .verify()
+  {
+    Ok(()) => Ok(()),
+    Err(errs) => Err(format!("{} failures, first: {:?}", errs.len(), errs[0])),
+  }
+}
+
+fn rejects(result: Result<(), String>) -> Result<(), String> {
+  match result {
+    Ok(()) => Err("accepted".to_string()),
+    Err(e) => {
+      println!("  {}", e);
+      Ok(())
+    }
+  }
+}
+
+// The challenge and the combination are the first public values, and the inputs are not public
+fn rlc_public_vals(f: &Fixture) -> Result<(), String> {
+  if f.circuit.num_commitments() != NUM_RLC_VALS {
+    return Err(format!("{} commitments", f.circuit.num_commitments()));
+  }
+  if f.public_vals.len() != NUM_RLC_VALS + 4 {
+    return Err(format!("{} public values", f.public_vals.len()));
+  }
+  let inputs = f.model.tensors.iter().collect();
+  if tensors_rlc(&inputs, f.public_vals[0]) != f.public_vals[1] {
+    return Err("the combination is not over the inputs".to_string());
+  }
+  Ok(())
+}
+
+fn rlc_verifies(f: &Fixture) -> Result<(), String> {
+  verify(&f.circuit, &f.public_vals)?;
+  check_rlc_inputs(&f.model, &f.model.tensors, &f.public_vals)
+}
+
+fn rlc_wrong_challenge(f: &Fixture) -> Result<(), String> {
+  let mut public_vals = f.public_vals.clone();
+  public_vals[0] += Fr::ONE;
+  rejects(verify(&f.circuit, &public_vals))
+}
+
+fn rlc_wrong_combination(f: &Fixture) -> Result<(), String> {
+  let mut public_vals = f.public_vals.clone();
+  public_vals[1] += Fr::ONE;
+  rejects(verify(&f.circuit, &public_vals))
+}
+
+// A witness with other inputs doesn't match the honest combination
+fn rlc_tampered_witness(f: &Fixture) -> Result<(), String> {
+  let mut tampered = f.circuit.clone();
+  *tampered
+    .tensors
+    .get_mut(&0)
+    .unwrap()
+    .iter_mut()
+    .nth(2)
+    .unwrap() += Fr::ONE;
+  rejects(verify(&tampered, &f.public_vals))
+}
+
+fn rlc_wrong_input(f: &Fixture) -> Result<(), String> {
+  let mut inputs = f.model.tensors.clone();
+  inputs[1].data[3] += 1;
+  rejects(check_rlc_inputs(&f.model, &inputs, &f.public_vals))
+}
+
+// The values are ordered by tensor index, so the inputs can't be swapped
+fn rlc_swapped_inputs(f: &Fixture) -> Result<(), String> {
+  let mut inputs = f.model.tensors.clone();
+  let data = inputs[0].data.clone();
+  inputs[0].data = inputs[1].data.clone();
+  inputs[1].data = data;
+  rejects(check_rlc_inputs(&f.model, &inputs, &f.public_vals))
+}
+
+// The order of the revealed tensors doesn't matter
+fn rlc_input_order(f: &Fixture) -> Result<(), String> {
+  let mut inputs = f.model.tensors.clone();
+  inputs.reverse();
+  check_rlc_inputs(&f.model, &inputs, &f.public_vals)
+}
+
+fn rlc_missing_input(f: &Fixture) -> Result<(), String> {
+  let inputs = vec![f.model.tensors[0].clone()];
+  rejects(check_rlc_inputs(&f.model, &inputs, &f.public_vals))
+}
+
+fn rlc_no_rlc_inputs(f: &Fixture) -> Result<(), String> {
+  let model = ModelMsgpack {
+    rlc_inputs: None,
+    ..f.model.clone()
+  };
+  rejects(check_rlc_inputs(&model, &model.tensors, &f.public_vals))
+}
+
+// The envelope prints the rlc values with the commitments, and only the sums as outputs
+fn rlc_envelope_info(f: &Fixture) -> Result<(), String> {
+  let public_vals = f
+    .public_vals
+    .iter()
+    .flat_map(|x| x.to_repr().as_ref().to_vec())
+    .collect();
+  let info = ProofEnvelope::new(&f.model, vec![], vec![], public_vals).info()?;
+  let outputs = info["outputs"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|x| x["int"].as_str().unwrap().to_string())
+    .collect::<Vec<_>>();
+  if info["commitments"].as_array().unwrap().len() != NUM_RLC_VALS {
+    return Err(format!("commitments {}", info["commitments"]));
+  }
+  if outputs != vec!["257", "-510", "0", "4"] {
+    return Err(format!("outputs {:?}", outputs));
+  }
+  Ok(())
+}
+
+fn main() {
+  let filter = std::env::args().nth(1).unwrap_or("".to_string());
+  let fixture = fixture();
+
+  let cases: Vec<(&str, fn(&Fixture) -> Result<(), String>)> = vec![
+    ("rlc_public_vals", rlc_public_vals),
+    ("rlc_verifies", rlc_verifies),
+    ("rlc_wrong_challenge", rlc_wrong_challenge),
+    ("rlc_wrong_combination", rlc_wrong_combination),
+    ("rlc_tampered_witness", rlc_tampered_witness),
+    ("rlc_wrong_input", rlc_wrong_input),
+    ("rlc_swapped_inputs", rlc_swapped_inputs),
+    ("rlc_input_order", rlc_input_order),
+    ("rlc_missing_input", rlc_missing_input),
+    ("rlc_no_rlc_inputs", rlc_no_rlc_inputs),
+    ("rlc_envelope_info", rlc_envelope_info),
+  ];
+  let mut num_failed = 0;
+  for (name, case) in cases.iter() {
+    if !name.contains(filter.as_str()) {
+      continue;
+    }
+    match case(&fixture) {
+      Ok(()) => println!("{}: ok", name),
+      Err(err) => {
+        println!("{}: FAILED, {}", name, err);
+        num_failed += 1;
+      }
+    }
+  }
+  assert_eq!(num_failed, 0, "{} rlc tests failed", num_failed);
+}
@@ -0,0 +1,264 @@
+use halo2_proofs::{
+  halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    ff::PrimeField,
+  },
+  plonk::{keygen_vk, VerifyingKey},
+  poly::{
+    commitment::{Params, ParamsProver},
+    kzg::commitment::ParamsKZG,
+  },
+  SerdeFormat,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    artifacts::{check_pinned, content_hash},
+    envelope::{srs_hash, ProofEnvelope, VerifyOutcome},
+    keygen::keygen_kzg,
+    loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+    proving_kzg::{check_batch_kzg, prove_batch_kzg},
+  },
+};
+
+// Checks the verifiers against a prover who picks the vk or the SRS of the proof: the pinning of
+// prov_cli and zkml-verify (see check_pinned in artifacts.rs and ProofEnvelope::verify), and that
+// the SRS of the verifier rejects a proof made with an SRS of a known tau.
+// Usage: test_verify [case name filter]
+
+const K: i64 = 15;
+
+// The proof of an Add, made by a prover who chose the SRS and so knows its tau
+struct Fixture {
+  prover_params: ParamsKZG<Bn256>,
+  prover_vk: VerifyingKey<G1Affine>,
+  proof: Vec<u8>,
+  public_vals: Vec<Vec<Fr>>,
+  num_commitments: usize,
+  // The SRS of the verifier, with the vk of the same model under it
+  params: ParamsKZG<Bn256>,
+  vk: VerifyingKey<G1Affine>,
+}
+
+fn add_model() -> ModelMsgpack {
+  let tensor = |idx, data| TensorMsgpack {
+    idx,
+    shape: vec![1, 4],
+    data,
+    dtype: None,
+  };
+  ModelMsgpack {
+    global_sf: 256,
+    k: K,
+    num_cols: 10,
+    inp_idxes: vec![0, 1],
+    out_idxes: vec![2],
+    tensors: vec![
+      tensor(0, vec![256, -512, 3, 0]),
+      tensor(1, vec![1, 2, -3, 4]),
+    ],
+    layers: vec![LayerMsgpack {
+      layer_type: "Add".to_string(),
+      params: vec![0],
+      inp_idxes: vec![0, 1],
+      inp_shapes: vec![vec![1, 4], vec![1, 4]],
+      out_idxes: vec![2],
+      out_shapes: vec![vec![1, 4]],
+      mask: vec![],
+    }],
+    use_selectors: Some(true),
+    ..Default::default()
+  }
+}
+
+fn fixture() -> Fixture {
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack(add_model(), true);
+  let num_commitments = circuit.num_commitments();
+  let prover_params = ParamsKZG::<Bn256>::setup(K as u32, StdRng::seed_from_u64(1));
+  let pk = keygen_kzg(&prover_params, &circuit).unwrap();
+  let (proof, public_vals) = prove_batch_kzg(
+    &prover_params,
+    &pk,
+    vec![circuit.clone()],
+    StdRng::seed_from_u64(0),
+  )
+  .unwrap();
+  let params = ParamsKZG::<Bn256>::setup(K as u32, StdRng::seed_from_u64(2));
+  let vk = keygen_vk(&params, &circuit).unwrap();
+  Fixture {
+    prover_params,
+    prover_vk: pk.get_vk().clone(),
+    proof,
+    public_vals,
+    num_commitments,
+    params,
+    vk,
+  }
+}
+
+fn vkey_bytes(vk: &VerifyingKey<G1Affine>) -> Vec<u8> {
+  vk.to_bytes(SerdeFormat::RawBytes)
+}
+
+fn params_bytes(params: &ParamsKZG<Bn256>) -> Vec<u8> {
+  let mut buf = vec![];
+  params.write(&mut buf).unwrap();
+  buf
+}
+
+// The envelope of the proof, which records the SRS of the prover
+fn envelope(f: &Fixture) -> ProofEnvelope {
+  let public_vals = f.public_vals[0]
+    .iter()
+    .flat_map(|x| x.to_repr().as_ref().to_vec())
+    .collect();
+  ProofEnvelope::new(
+    &add_model(),
+    vkey_bytes(&f.prover_vk),
+    f.proof.clone(),
+    public_vals,
+  )
+  .with_srs_hash(srs_hash(&f.prover_params))
+}
+
+fn outcome(outcome: VerifyOutcome, expected: &str) -> Result<(), String> {
+  println!("  {:?}", outcome);
+  if outcome.name() != expected {
+    return Err(format!("{}, expected {}", outcome.name(), expected));
+  }
+  Ok(())
+}
+
+fn rejects(result: Result<(), String>) -> Result<(), String> {
+  match result {
+    Ok(()) => Err("accepted".to_string()),
+    Err(e) => {
+      println!("  {}", e);
+      Ok(())
+    }
+  }
+}
+
+// The vk hash that prove prints pins the vk of the bundle
+fn vk_pinned(f: &Fixture) -> Result<(), String> {
+  let vkey = vkey_bytes(&f.prover_vk);
+  check_pinned("vk", &vkey, Some(&content_hash(&vkey)), false)
+}
+
+// The prover's vk of the model under its own SRS is not the vk the verifier pinned
+fn vk_hash_mismatch(f: &Fixture) -> Result<(), String> {
+  let pinned = content_hash(&vkey_bytes(&f.vk));
+  rejects(check_pinned(
+    "vk",
+    &vkey_bytes(&f.prover_vk),
+    Some(&pinned),
+    false,
+  ))
+}
+
+fn vk_unpinned(f: &Fixture) -> Result<(), String> {
+  rejects(check_pinned("vk", &vkey_bytes(&f.prover_vk), None, false))
+}
+
+// trust_bundle_vk, which only warns
+fn vk_trusted(f: &Fixture) -> Result<(), String> {
+  check_pinned("vk", &vkey_bytes(&f.prover_vk), None, true)
+}
+
+fn srs_pinned(f: &Fixture) -> Result<(), String> {
+  let srs = params_bytes(&f.prover_params);
+  check_pinned("SRS", &srs, Some(&content_hash(&srs)), false)
+}
+
+fn srs_hash_mismatch(f: &Fixture) -> Result<(), String> {
+  let pinned = content_hash(&params_bytes(&f.params));
+  rejects(check_pinned(
+    "SRS",
+    &params_bytes(&f.prover_params),
+    Some(&pinned),
+    false,
+  ))
+}
+
+fn srs_unpinned(f: &Fixture) -> Result<(), String> {
+  rejects(check_pinned(
+    "SRS",
+    &params_bytes(&f.prover_params),
+    None,
+    false,
+  ))
+}
+
+// The proof verifies with the SRS and the vk of the prover, which is what the verifiers used to
+// check, but not with the SRS of the verifier
+fn verifier_srs(f: &Fixture) -> Result<(), String> {
+  let check = |params: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>| {
+    check_batch_kzg(params, vk, &f.public_vals, f.num_commitments, &f.proof)
+  };
+  if !check(&f.prover_params, &f.prover_vk) {
+    return Err("the proof doesn't verify with the SRS of the prover".to_string());
+  }
+  if check(&f.params, &f.vk) {
+    return Err("the proof verifies with the SRS of the verifier".to_string());
+  }
+  Ok(())
+}
+
+// zkml-verify with the hash of the vk the verifier expects, and the SRS the proof was made with
+fn envelope_pinned(f: &Fixture) -> Result<(), String> {
+  let vkey_hash = content_hash(&vkey_bytes(&f.prover_vk));
+  outcome(
+    envelope(f).verify(&f.prover_params, None, &vkey_hash),
+    "valid",
+  )
+}
+
+fn envelope_vk_mismatch(f: &Fixture) -> Result<(), String> {
+  let vkey_hash = content_hash(&vkey_bytes(&f.vk));
+  outcome(
+    envelope(f).verify(&f.prover_params, None, &vkey_hash),
+    "vk_mismatch",
+  )
+}
+
+// The params file of the verifier is not the SRS recorded in the envelope
+fn envelope_srs_mismatch(f: &Fixture) -> Result<(), String> {
+  let vkey_hash = content_hash(&vkey_bytes(&f.prover_vk));
+  let params_hash = content_hash(&params_bytes(&f.params));
+  let verified = envelope(f).verify(&f.params, Some(&params_hash), &vkey_hash);
+  outcome(verified, "srs_mismatch")
+}
+
+fn main() {
+  let filter = std::env::args().nth(1).unwrap_or("".to_string());
+  let fixture = fixture();
+
+  let cases: Vec<(&str, fn(&Fixture) -> Result<(), String>)> = vec![
+    ("vk_pinned", vk_pinned),
+    ("vk_hash_mismatch", vk_hash_mismatch),
+    ("vk_unpinned", vk_unpinned),
+    ("vk_trusted", vk_trusted),
+    ("srs_pinned", srs_pinned),
+    ("srs_hash_mismatch", srs_hash_mismatch),
+    ("srs_unpinned", srs_unpinned),
+    ("verifier_srs", verifier_srs),
+    ("envelope_pinned", envelope_pinned),
+    ("envelope_vk_mismatch", envelope_vk_mismatch),
+    ("envelope_srs_mismatch", envelope_srs_mismatch),
+  ];
+  let mut num_failed = 0;
+  for (name, case) in cases.iter() {
+    if !name.contains(filter.as_str()) {
+      continue;
+    }
+    match case(&fixture) {
+      Ok(()) => println!("{}: ok", name),
+      Err(err) => {
+        println!("{}: FAILED, {}", name, err);
+        num_failed += 1;
+      }
+    }
+  }
+  assert_eq!(num_failed, 0, "{} verifier tests failed", num_failed);
+}
@@ -0,0 +1,66 @@
+use std::{fs::File, path::Path};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    loader::{load_config_msgpack, load_model_msgpack},
+    witness::{export_witness, witness_digest, WitnessDigest},
+  },
+};
+
+// Fails if the witness of a model differs from the pinned one, e.g., pinned on x86-64 Linux and
+// checked on aarch64 or macOS (see testing/witness_repro.sh)
+// Usage: test_witness_repro <config> <input> <pinned digest json>
+// A missing pin fails. With ZKML_BLESS=1, the current digest is written to a missing pin instead,
+// on the target the digests are pinned on.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let inp_fname = std::env::args().nth(2).expect("input file path");
+  let pinned_fname = std::env::args().nth(3).expect("pinned digest file path");
+
+  let config = load_config_msgpack(&config_fname);
+  let circuit =
+    ModelCircuit::<Fr>::generate_from_msgpack(load_model_msgpack(&config_fname, &inp_fname), true);
+  let digest = witness_digest(&export_witness(&circuit, &config).unwrap());
+  println!(
+    "{} on {}-{}: {} cells, {}",
+    config_fname,
+    std::env::consts::ARCH,
+    std::env::consts::OS,
+    digest.num_cells,
+    digest.cells_hash
+  );
+
+  if !Path::new(&pinned_fname).exists() {
+    if std::env::var("ZKML_BLESS").map_or(false, |v| v == "1") {
+      let f = File::create(&pinned_fname).unwrap();
+      serde_json::to_writer_pretty(f, &digest).unwrap();
+      println!("no pinned digest found, wrote {}", pinned_fname);
+      return;
+    }
+    panic!(
+      "no pinned digest in {}, run with ZKML_BLESS=1 on the pinning target",
+      pinned_fname
+    );
+  }
+
+  let pinned: WitnessDigest = serde_json::from_reader(File::open(&pinned_fname).unwrap()).unwrap();
+  if digest == pinned {
+    return;
+  }
+  if digest.num_cells != pinned.num_cells {
+    println!("cells: {} -> {}", pinned.num_cells, digest.num_cells);
+  }
+  for (i, (expected, current)) in pinned
+    .public_vals
+    .iter()
+    .zip(digest.public_vals.iter())
+    .enumerate()
+  {
+    if expected != current {
+      println!("public value {}: {} -> {}", i, expected, current);
+    }
+  }
+  panic!("the witness differs from {}", pinned_fname);
+}
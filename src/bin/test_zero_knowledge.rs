@@ -0,0 +1,175 @@
+use halo2_proofs::{
+  halo2curves::bn256::{Bn256, Fr, G1Affine},
+  plonk::ProvingKey,
+  poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG},
+  SerdeFormat,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    envelope::ProofEnvelope,
+    helpers::prover_rng,
+    keygen::keygen_kzg,
+    loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+    proving_kzg::{check_batch_kzg, prove_batch_kzg},
+  },
+};
+
+// Checks the zero_knowledge option (see prover_rng in helpers.rs): without it the proofs are
+// reproducible, with it (the default) they are randomized, and both verify under the same vk.
+// Usage: test_zero_knowledge [case name filter]
+
+const K: i64 = 15;
+
+struct Fixture {
+  params: ParamsKZG<Bn256>,
+  // With and without zero knowledge
+  zk: (ModelMsgpack, ProvingKey<G1Affine>),
+  no_zk: (ModelMsgpack, ProvingKey<G1Affine>),
+}
+
+fn add_model(zero_knowledge: Option<bool>) -> ModelMsgpack {
+  let tensor = |idx, data| TensorMsgpack {
+    idx,
+    shape: vec![1, 4],
+    data,
+    dtype: None,
+  };
+  ModelMsgpack {
+    global_sf: 256,
+    k: K,
+    num_cols: 10,
+    inp_idxes: vec![0, 1],
+    out_idxes: vec![2],
+    tensors: vec![
+      tensor(0, vec![256, -512, 3, 0]),
+      tensor(1, vec![1, 2, -3, 4]),
+    ],
+    layers: vec![LayerMsgpack {
+      layer_type: "Add".to_string(),
+      params: vec![0],
+      inp_idxes: vec![0, 1],
+      inp_shapes: vec![vec![1, 4], vec![1, 4]],
+      out_idxes: vec![2],
+      out_shapes: vec![vec![1, 4]],
+      mask: vec![],
+    }],
+    use_selectors: Some(true),
+    zero_knowledge,
+    ..Default::default()
+  }
+}
+
+fn circuit(model: &ModelMsgpack) -> ModelCircuit<Fr> {
+  ModelCircuit::<Fr>::generate_from_msgpack(model.clone(), true)
+}
+
+fn fixture() -> Fixture {
+  let params = ParamsKZG::<Bn256>::setup(K as u32, StdRng::seed_from_u64(1));
+  let keygen = |model: ModelMsgpack| {
+    let pk = keygen_kzg(&params, &circuit(&model)).unwrap();
+    (model, pk)
+  };
+  Fixture {
+    zk: keygen(add_model(None)),
+    no_zk: keygen(add_model(Some(false))),
+    params,
+  }
+}
+
+// Proves with the randomness of the option of the model, like the provers do
+fn prove(
+  f: &Fixture,
+  (model, pk): &(ModelMsgpack, ProvingKey<G1Affine>),
+) -> Result<Vec<u8>, String> {
+  let circuit = circuit(model);
+  let num_commitments = circuit.num_commitments();
+  let rng = prover_rng(circuit.zero_knowledge);
+  let (proof, public_vals) =
+    prove_batch_kzg(&f.params, pk, vec![circuit], rng).map_err(|e| e.to_string())?;
+  if !check_batch_kzg(
+    &f.params,
+    pk.get_vk(),
+    &public_vals,
+    num_commitments,
+    &proof,
+  ) {
+    return Err("the proof doesn't verify".to_string());
+  }
+  Ok(proof)
+}
+
+fn recorded(model: &ModelMsgpack) -> Result<bool, String> {
+  let info = ProofEnvelope::new(model, vec![], vec![], vec![]).info()?;
+  info["zero_knowledge"]
+    .as_bool()
+    .ok_or("no zero_knowledge in the envelope".to_string())
+}
+
+fn zk_default(f: &Fixture) -> Result<(), String> {
+  if !circuit(&f.zk.0).zero_knowledge {
+    return Err("zero knowledge is off by default".to_string());
+  }
+  match recorded(&f.zk.0)? {
+    true => Ok(()),
+    false => Err("the envelope records no zero knowledge".to_string()),
+  }
+}
+
+fn zk_off_recorded(f: &Fixture) -> Result<(), String> {
+  match recorded(&f.no_zk.0)? {
+    false => Ok(()),
+    true => Err("the envelope records zero knowledge".to_string()),
+  }
+}
+
+// The option only changes the randomness of the prover, not the circuit
+fn zk_same_vk(f: &Fixture) -> Result<(), String> {
+  let vkey = |pk: &ProvingKey<G1Affine>| pk.get_vk().to_bytes(SerdeFormat::RawBytes);
+  if vkey(&f.zk.1) != vkey(&f.no_zk.1) {
+    return Err("the vks differ".to_string());
+  }
+  Ok(())
+}
+
+fn zk_randomized(f: &Fixture) -> Result<(), String> {
+  if prove(f, &f.zk)? == prove(f, &f.zk)? {
+    return Err("two proofs are the same".to_string());
+  }
+  Ok(())
+}
+
+fn zk_off_reproducible(f: &Fixture) -> Result<(), String> {
+  if prove(f, &f.no_zk)? != prove(f, &f.no_zk)? {
+    return Err("two proofs differ".to_string());
+  }
+  Ok(())
+}
+
+fn main() {
+  let filter = std::env::args().nth(1).unwrap_or("".to_string());
+  let fixture = fixture();
+
+  let cases: Vec<(&str, fn(&Fixture) -> Result<(), String>)> = vec![
+    ("zk_default", zk_default),
+    ("zk_off_recorded", zk_off_recorded),
+    ("zk_same_vk", zk_same_vk),
+    ("zk_randomized", zk_randomized),
+    ("zk_off_reproducible", zk_off_reproducible),
+  ];
+  let mut num_failed = 0;
+  for (name, case) in cases.iter() {
+    if !name.contains(filter.as_str()) {
+      continue;
+    }
+    match case(&fixture) {
+      Ok(()) => println!("{}: ok", name),
+      Err(err) => {
+        println!("{}: FAILED, {}", name, err);
+        num_failed += 1;
+      }
+    }
+  }
+  assert_eq!(num_failed, 0, "{} zero knowledge tests failed", num_failed);
+}
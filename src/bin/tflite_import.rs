@@ -0,0 +1,39 @@
+use zkml::{
+  model::tflite::{load_tflite, TfliteOptions},
+  utils::loader::save_model_msgpack,
+};
+
+// Converts a TFLite model to the msgpack config and inputs without the Python converter, e.g., to
+// inspect or optimize the config. The inputs are a JSON list of flattened float arrays, or of the
+// quantized values with --parity, which computes the TFLite integer kernels (see model/tflite.rs).
+// Usage: tflite_import [--parity] <model.tflite> <inputs.json> <output config> <output inputs>
+//   [sf] [k]
+fn main() {
+  let parity = std::env::args().any(|arg| arg == "--parity");
+  let args = std::env::args()
+    .filter(|arg| arg != "--parity")
+    .collect::<Vec<_>>();
+  let tflite_fname = args.get(1).expect("tflite file path");
+  let inp_fname = args.get(2).expect("inputs file path");
+  let config_fname = args.get(3).expect("output config path");
+  let outp_inp_fname = args.get(4).expect("output inputs path");
+
+  let mut options = TfliteOptions {
+    parity,
+    ..TfliteOptions::default()
+  };
+  if let Some(sf) = args.get(5) {
+    options.scale_factor = sf.parse().expect("scale factor");
+  }
+  if let Some(k) = args.get(6) {
+    options.k = k.parse().expect("k");
+  }
+
+  let model = load_tflite(&tflite_fname, &inp_fname, &options).unwrap();
+  println!(
+    "imported {} layers and {} tensors",
+    model.layers.len(),
+    model.tensors.len()
+  );
+  save_model_msgpack(&model, &config_fname, &outp_inp_fname);
+}
@@ -0,0 +1,223 @@
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use serde_derive::Deserialize;
+use zkml::{
+  model::{
+    tflite::{
+      multiply_by_quantized_multiplier, quantize_multiplier, tflite_to_msgpack, TfliteOptions,
+    },
+    ModelCircuit,
+  },
+  utils::{
+    envelope::decode_signed,
+    helpers::instance_columns,
+    loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+    storage::read_artifact,
+  },
+};
+
+// Checks that the circuit computes the TFLite integer kernels bit for bit (see model/tflite.rs).
+// Usage: tflite_parity [<model.tflite> <reference.json> [k]]
+// Always checks the requantization against the TFLite arithmetic, on ties, negative values, and
+// large accumulators. With a model, also checks the outputs of its parity import against the
+// outputs of the TFLite interpreter, from python/tflite_reference.py.
+
+const K: i64 = 15;
+
+#[derive(Deserialize)]
+struct Reference {
+  inputs: Vec<Vec<f64>>,
+  outputs: Vec<Vec<i64>>,
+  output_zero_points: Vec<i64>,
+}
+
+// Runs the model through the MockProver and returns the outputs
+fn run(model: ModelMsgpack) -> Result<Vec<i64>, String> {
+  let k = model.k as u32;
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack(model, true);
+  let public_vals = circuit.compute_public_values()?;
+  let prover = MockProver::run(
+    k,
+    &circuit,
+    instance_columns(&public_vals, circuit.num_commitments()),
+  )
+  .unwrap();
+  if let Err(errs) = prover.verify() {
+    return Err(format!("{} failures, first: {:?}", errs.len(), errs[0]));
+  }
+  public_vals
+    .iter()
+    .map(|x| decode_signed(x).map(|x| x as i64))
+    .collect::<Option<Vec<_>>>()
+    .ok_or_else(|| "an output is not an integer".to_string())
+}
+
+fn requantize_model(params: Vec<i64>, shape: Vec<i64>, data: Vec<i64>) -> ModelMsgpack {
+  let layer = LayerMsgpack {
+    layer_type: "Requantize".to_string(),
+    params,
+    inp_idxes: vec![0],
+    inp_shapes: vec![shape.clone()],
+    out_idxes: vec![1],
+    out_shapes: vec![shape.clone()],
+    mask: vec![],
+  };
+  ModelMsgpack {
+    global_sf: 1,
+    k: K,
+    num_cols: 10,
+    inp_idxes: vec![0],
+    out_idxes: vec![1],
+    tensors: vec![TensorMsgpack {
+      idx: 0,
+      shape,
+      data,
+    }],
+    layers: vec![layer],
+    use_selectors: Some(true),
+    ..Default::default()
+  }
+}
+
+// Requantizes the accumulators with the real multipliers, one per channel of the last axis
+fn check_requantize(name: &str, reals: &Vec<f64>, lo: i64, hi: i64, accs: &Vec<i64>) -> bool {
+  let channels = reals
+    .iter()
+    .map(|x| quantize_multiplier(*x))
+    .collect::<Vec<_>>();
+  let mut params = vec![lo, hi];
+  for (multiplier, shift) in channels.iter() {
+    params.extend([*multiplier, *shift]);
+  }
+  let mut data = vec![];
+  for acc in accs.iter() {
+    data.extend(vec![*acc; channels.len()]);
+  }
+  let expected = data
+    .iter()
+    .enumerate()
+    .map(|(i, x)| {
+      let (multiplier, shift) = channels[i % channels.len()];
+      multiply_by_quantized_multiplier(*x, multiplier, shift).clamp(lo, hi)
+    })
+    .collect::<Vec<_>>();
+
+  let shape = vec![accs.len() as i64, channels.len() as i64];
+  match run(requantize_model(params, shape, data)) {
+    Ok(outputs) if outputs == expected => true,
+    Ok(outputs) => {
+      println!(
+        "{}: got {:?}, TFLite computes {:?}",
+        name, outputs, expected
+      );
+      false
+    }
+    Err(e) => {
+      println!("{}: {}", name, e);
+      false
+    }
+  }
+}
+
+fn check_model(tflite_fname: &str, reference_fname: &str, k: i64) -> bool {
+  let reference: Reference =
+    serde_json::from_slice(&read_artifact(reference_fname).unwrap()).unwrap();
+  let options = TfliteOptions {
+    k,
+    parity: true,
+    ..TfliteOptions::default()
+  };
+  let model = tflite_to_msgpack(
+    &read_artifact(tflite_fname).unwrap(),
+    &reference.inputs,
+    &options,
+  )
+  .unwrap();
+  let expected = reference
+    .outputs
+    .iter()
+    .zip(reference.output_zero_points.iter())
+    .flat_map(|(outp, zero_point)| outp.iter().map(move |x| x - zero_point))
+    .collect::<Vec<_>>();
+  match run(model) {
+    Ok(outputs) if outputs == expected => true,
+    Ok(outputs) => {
+      let num_diffs = outputs
+        .iter()
+        .zip(expected.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+      println!(
+        "{} of {} outputs differ from the interpreter",
+        num_diffs.max(outputs.len().abs_diff(expected.len())),
+        expected.len()
+      );
+      false
+    }
+    Err(e) => {
+      println!("{}: {}", tflite_fname, e);
+      false
+    }
+  }
+}
+
+fn main() {
+  // Ties of the high mul and of the shift, on both signs
+  let halves = (-24..=24).collect::<Vec<_>>();
+  let ties = (-12..=12).map(|x| x * (1 << 10) / 2).collect::<Vec<_>>();
+  let accs = vec![
+    -(1 << 20) - 1,
+    -99_999,
+    -4097,
+    -1,
+    0,
+    1,
+    4095,
+    77_777,
+    1 << 20,
+  ];
+  // (name, real multipliers, lo, hi, accumulators)
+  let cases: Vec<(&str, Vec<f64>, i64, i64, Vec<i64>)> = vec![
+    ("high_mul_ties", vec![0.5], -4096, 4096, halves),
+    ("shift_ties", vec![1. / 1024.], -128, 127, ties.clone()),
+    ("odd_multiplier_ties", vec![0.75 / 256.], -128, 127, ties),
+    (
+      "left_shift",
+      vec![3.],
+      -1 << 12,
+      1 << 12,
+      vec![-700, -3, -1, 0, 1, 5, 700],
+    ),
+    ("small", vec![0.000_123_4], -128, 127, accs.clone()),
+    ("clamp", vec![0.01], -128, 127, accs.clone()),
+    ("relu", vec![0.003], 0, 127, accs.clone()),
+    (
+      "per_channel",
+      vec![0.002, 0.000_7, 0.011, 1. / 4096.],
+      -128,
+      127,
+      accs,
+    ),
+  ];
+
+  let mut num_failed = 0;
+  for (name, reals, lo, hi, accs) in cases.iter() {
+    let passed = check_requantize(name, reals, *lo, *hi, accs);
+    println!("{}: {}", name, if passed { "ok" } else { "FAILED" });
+    if !passed {
+      num_failed += 1;
+    }
+  }
+
+  if let Some(tflite_fname) = std::env::args().nth(1) {
+    let reference_fname = std::env::args().nth(2).expect("reference file path");
+    let k = std::env::args()
+      .nth(3)
+      .map_or(19, |k| k.parse().expect("k"));
+    let passed = check_model(&tflite_fname, &reference_fname, k);
+    println!("{}: {}", tflite_fname, if passed { "ok" } else { "FAILED" });
+    if !passed {
+      num_failed += 1;
+    }
+  }
+  assert_eq!(num_failed, 0, "{} parity tests failed", num_failed);
+}
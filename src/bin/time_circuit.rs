@@ -1,23 +1,111 @@
 use halo2_proofs::halo2curves::{bn256::Fr, pasta::Fp};
 use zkml::{
   model::ModelCircuit,
-  utils::{proving_ipa::time_circuit_ipa, proving_kzg::time_circuit_kzg},
+  utils::{
+    artifacts::{artifact_name, artifacts_arg, Manifest},
+    cancel::{run_with_timeout, timeout_arg, write_stages},
+    disk_pk::set_disk_pk_dir,
+    loader::load_config_msgpack,
+    perf::PerfReport,
+    proving_ipa::time_circuit_ipa_cancellable,
+    proving_kzg::time_circuit_kzg_cancellable,
+  },
 };
 
+// Usage: time_circuit <config> <input> <kzg or ipa> [--timeout <seconds>] [--artifacts <dir>]
+//   [--perf-report <output json>] [--disk-pk <dir>]
+// The time of each stage is written to metrics.json. On timeout, the stages finished so far are
+// written and the process exits with 4. With --artifacts, the KZG params, keys, proof, and public
+// values are also stored under their content hashes in the given directory. --perf-report writes
+// the hardware, circuit shape, and timings, see PerfReport. --disk-pk backs the proving key with
+// files in the given directory (see disk_pk.rs), with the disk-pk feature.
+#[cfg(feature = "disk-pk")]
+#[global_allocator]
+static GLOBAL: zkml::utils::disk_pk::DiskBackedAlloc = zkml::utils::disk_pk::DiskBackedAlloc;
+
 fn main() {
   let config_fname = std::env::args().nth(1).expect("config file path");
   let inp_fname = std::env::args().nth(2).expect("input file path");
   let kzg_or_ipa = std::env::args().nth(3).expect("kzg or ipa");
+  let timeout = timeout_arg();
+  let artifacts_dir = artifacts_arg();
+  let args = std::env::args().collect::<Vec<_>>();
+  let perf_report_fname = args
+    .iter()
+    .position(|arg| arg == "--perf-report")
+    .map(|pos| {
+      args
+        .get(pos + 1)
+        .expect("--perf-report needs a file path")
+        .clone()
+    });
+  if let Some(pos) = args.iter().position(|arg| arg == "--disk-pk") {
+    let dir = args.get(pos + 1).expect("--disk-pk needs a directory");
+    set_disk_pk_dir(dir).unwrap();
+  }
 
   if kzg_or_ipa != "kzg" && kzg_or_ipa != "ipa" {
     panic!("Must specify kzg or ipa");
   }
 
-  if kzg_or_ipa == "kzg" {
-    let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
-    time_circuit_kzg(circuit);
-  } else {
-    let circuit = ModelCircuit::<Fp>::generate_from_file(&config_fname, &inp_fname);
-    time_circuit_ipa(circuit);
+  let is_kzg = kzg_or_ipa == "kzg";
+  let perf_config = perf_report_fname
+    .as_ref()
+    .map(|_| load_config_msgpack(&config_fname));
+  let timed = run_with_timeout(timeout, "metrics.json", move |token| {
+    if is_kzg {
+      let circuit = ModelCircuit::<Fr>::generate_from_file(&config_fname, &inp_fname);
+      let k = circuit.k;
+      time_circuit_kzg_cancellable(circuit, &token).map(|()| k)
+    } else {
+      let circuit = ModelCircuit::<Fp>::generate_from_file(&config_fname, &inp_fname);
+      let k = circuit.k;
+      time_circuit_ipa_cancellable(circuit, &token).map(|()| k)
+    }
+  });
+  write_stages("metrics.json", false);
+  let k = timed.unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  });
+  if let (Some(fname), Some(config)) = (perf_report_fname, perf_config) {
+    PerfReport::new(&config, &kzg_or_ipa).write(&fname);
+  }
+
+  if let Some(dir) = artifacts_dir {
+    // The IPA prover does not write its artifacts
+    assert!(is_kzg, "--artifacts is only supported for kzg");
+    store_artifacts(&dir, k).unwrap();
+  }
+}
+
+fn store_artifacts(dir: &str, k: usize) -> Result<(), String> {
+  let read = |fname: &str| std::fs::read(fname).map_err(|e| format!("{}: {}", fname, e));
+  let mut manifest = Manifest::load(dir)?;
+  let params = manifest.store(
+    dir,
+    "params",
+    &read(&format!("./params_kzg/{}.params", k))?,
+    &[],
+  )?;
+  let vkey = manifest.store(dir, "vkey", &read("vkey")?, &[params.clone()])?;
+  let pkey = manifest.store(dir, "pkey", &read("pkey")?, &[vkey.clone()])?;
+  let public_vals = manifest.store(dir, "public_vals", &read("public_vals")?, &[])?;
+  let proof = manifest.store(
+    dir,
+    "proof",
+    &read("proof")?,
+    &[vkey.clone(), params, public_vals.clone()],
+  )?;
+  manifest.save(dir)?;
+  let stored = [
+    ("vkey", vkey),
+    ("pkey", pkey),
+    ("public_vals", public_vals),
+    ("proof", proof),
+  ];
+  for (kind, hash) in stored {
+    println!("{}: {}", kind, artifact_name(kind, &hash));
   }
+  Ok(())
 }
@@ -1,25 +1,61 @@
-use halo2_proofs::halo2curves::bn256::Fr;
-use zkml::{
-  model::ModelCircuit,
-  utils::{loader::load_config_msgpack, proving_kzg::verify_circuit_kzg},
+use zkml::utils::{
+  artifacts::{artifacts_arg, content_hash, Manifest, DEFAULT_ARTIFACTS_DIR},
+  cancel::{run_with_timeout, timeout_arg},
+  envelope::{ProofEnvelope, VerifyOutcome},
+  loader::load_config_msgpack,
+  proving_kzg::get_kzg_params,
+  storage::read_artifact,
 };
 
+// Usage: verify_circuit <config> <vkey> <proof> <public vals> <kzg or ipa> [--json]
+//   [--timeout <seconds>] [--artifacts <dir>]
+// The files can also be artifact hashes from the artifacts directory (./artifacts by default).
+// Exits with 0 if the proof is valid, 1 if it is invalid, 2 if the artifacts are malformed, 3 if
+// the verification key does not match the config, and 4 on timeout.
 fn main() {
   let config_fname = std::env::args().nth(1).expect("config file path");
   let vkey_fname = std::env::args().nth(2).expect("verification key file path");
   let proof_fname = std::env::args().nth(3).expect("proof file path");
   let public_vals_fname = std::env::args().nth(4).expect("public values file path");
   let kzg_or_ipa = std::env::args().nth(5).expect("kzg or ipa");
+  let json = std::env::args().any(|arg| arg == "--json");
+  let timeout = timeout_arg();
+  let artifacts_dir = artifacts_arg().unwrap_or(DEFAULT_ARTIFACTS_DIR.to_string());
 
   if kzg_or_ipa != "kzg" && kzg_or_ipa != "ipa" {
     panic!("Must specify kzg or ipa");
   }
 
   if kzg_or_ipa == "kzg" {
+    let manifest =
+      Manifest::load(&artifacts_dir).unwrap_or_else(|e| VerifyOutcome::Malformed(e).report(json));
+    let read = |reference: &str| {
+      manifest
+        .resolve(&artifacts_dir, reference)
+        .and_then(|fname| read_artifact(&fname))
+        .unwrap_or_else(|e| VerifyOutcome::Malformed(e).report(json))
+    };
     let config = load_config_msgpack(&config_fname);
-    let circuit = ModelCircuit::<Fr>::generate_from_msgpack(config, false);
-    println!("Loaded configuration");
-    verify_circuit_kzg(circuit, &vkey_fname, &proof_fname, &public_vals_fname);
+    if !json {
+      println!("Loaded configuration");
+    }
+    let envelope = ProofEnvelope::new(
+      &config,
+      read(&vkey_fname),
+      read(&proof_fname),
+      read(&public_vals_fname),
+    );
+    // Catches proofs paired with the vkey of another run
+    if let Err(e) = manifest.check_link(&envelope.proof, "vkey", &envelope.vkey) {
+      VerifyOutcome::VkMismatch(e).report(json);
+    }
+    let outcome = run_with_timeout(timeout, "metrics.json", move |_| {
+      let params = get_kzg_params("./params_kzg", envelope.k);
+      // The vkey is the verifier's own, from the arguments
+      let vkey_hash = content_hash(&envelope.vkey);
+      envelope.verify(&params, None, &vkey_hash)
+    });
+    outcome.report(json);
   } else {
     // Serialization of the verification key doesn't seem to be supported for IPA
     panic!("Not implemented");
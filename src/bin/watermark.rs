@@ -0,0 +1,70 @@
+use std::{fs::File, io::BufReader};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use zkml::{
+  model::ModelCircuit,
+  utils::{
+    loader::{load_config_msgpack, save_model_msgpack, TensorMsgpack},
+    watermark::{check_watermark, watermark_model, watermark_outputs},
+  },
+};
+
+// Builds the watermark verification circuit for a model and a directory of trigger inputs
+// Usage: watermark <config> <trigger dir> <output dir> [expected outputs json] [tolerance]
+// The expected outputs are a list with one list of (scaled) outputs per trigger, in order of the
+// trigger file names. The watermark config and the (secret) triggers are written to the output
+// dir, to be proven as usual.
+fn main() {
+  let config_fname = std::env::args().nth(1).expect("config file path");
+  let trigger_dir = std::env::args().nth(2).expect("trigger directory");
+  let outp_dir = std::env::args().nth(3).expect("output directory");
+  let expected_fname = std::env::args().nth(4);
+  let tolerance = std::env::args()
+    .nth(5)
+    .map(|x| x.parse::<i64>().unwrap())
+    .unwrap_or(0);
+
+  let mut fnames = std::fs::read_dir(&trigger_dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.is_file())
+    .collect::<Vec<_>>();
+  fnames.sort();
+  let triggers = fnames
+    .iter()
+    .map(|fname| {
+      let mut reader = BufReader::new(File::open(fname).unwrap());
+      let tensors: Vec<TensorMsgpack> = rmp_serde::from_read(&mut reader).unwrap();
+      tensors
+    })
+    .collect::<Vec<_>>();
+
+  let model = load_config_msgpack(&config_fname);
+  let watermark = watermark_model(&model, &triggers);
+
+  std::fs::create_dir_all(&outp_dir).unwrap();
+  let wm_config = format!("{}/watermark.msgpack", outp_dir);
+  let wm_inp = format!("{}/triggers.msgpack", outp_dir);
+  save_model_msgpack(&watermark, &wm_config, &wm_inp);
+
+  let circuit = ModelCircuit::<Fr>::generate_from_file(&wm_config, &wm_inp);
+  let public_vals = circuit.compute_public_values().unwrap();
+  let outputs = watermark_outputs(&model, triggers.len(), &public_vals);
+  println!("weight commitment: {:?}", public_vals[0]);
+  println!("trigger commitment: {:?}", public_vals[1]);
+  for (fname, outp) in fnames.iter().zip(outputs.iter()) {
+    println!("{}: {:?}", fname.display(), outp);
+  }
+
+  if let Some(expected_fname) = expected_fname {
+    let expected: Vec<Vec<i64>> =
+      serde_json::from_reader(BufReader::new(File::open(&expected_fname).unwrap())).unwrap();
+    let mismatched = check_watermark(&outputs, &expected, tolerance);
+    assert!(
+      mismatched.is_empty(),
+      "outputs do not match for triggers {:?}",
+      mismatched
+    );
+    println!("all {} triggers match the expected outputs", triggers.len());
+  }
+}
@@ -0,0 +1,108 @@
+use halo2_proofs::{
+  halo2curves::bn256::Bn256,
+  poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use zkml::utils::{
+  artifacts::{artifacts_arg, content_hash, Manifest, DEFAULT_ARTIFACTS_DIR},
+  cancel::{run_with_timeout, timeout_arg},
+  envelope::{ProofEnvelope, VerifyOutcome},
+  signer::{parse_signer, verify_envelope_signature},
+  storage::read_artifact,
+};
+
+// Verifies a proof envelope against an existing SRS. Does not load models, generate keys, or
+// generate parameters.
+// Usage: zkml-verify <envelope> <params file> (--vkey <trusted vkey> | --vkey-hash <hash>)
+//   [--json] [--timeout <seconds>] [--artifacts <dir>] [--signed-by <signer>]
+// The files can also be artifact hashes from the artifacts directory (./artifacts by default).
+// The vk of the envelope is the prover's, so the verifier passes the vk it trusts or its hash (see
+// artifacts.rs content_hash).
+// --signed-by also requires <envelope>.sig to be a signature of the envelope by the signer's key
+// (see signer.rs).
+// Exits with 0 if the proof is valid, 1 if it is invalid, 2 if the envelope or parameters are
+// malformed, 3 if the verification key does not match or none is given, 4 on timeout, and 5 if the
+// envelope was made with another SRS.
+fn main() {
+  let args = std::env::args().skip(1).collect::<Vec<_>>();
+  let json = args.iter().any(|arg| arg == "--json");
+  let timeout = timeout_arg();
+  let artifacts_dir = artifacts_arg().unwrap_or(DEFAULT_ARTIFACTS_DIR.to_string());
+  let trusted_vkey_fname = args
+    .iter()
+    .position(|arg| arg == "--vkey")
+    .map(|pos| args.get(pos + 1).expect("--vkey needs a file path").clone());
+  let trusted_vkey_hash = args
+    .iter()
+    .position(|arg| arg == "--vkey-hash")
+    .map(|pos| args.get(pos + 1).expect("--vkey-hash needs a hash").clone());
+  let signer = args
+    .iter()
+    .position(|arg| arg == "--signed-by")
+    .map(|pos| args.get(pos + 1).expect("--signed-by needs a signer"))
+    .map(|spec| parse_signer(spec).unwrap_or_else(|e| VerifyOutcome::Malformed(e).report(json)));
+  let positional = args
+    .iter()
+    .enumerate()
+    .filter(|(i, arg)| {
+      let flags = [
+        "--vkey",
+        "--vkey-hash",
+        "--timeout",
+        "--artifacts",
+        "--signed-by",
+      ];
+      let is_value = *i > 0 && flags.contains(&args[*i - 1].as_str());
+      !arg.starts_with("--") && !is_value
+    })
+    .map(|(_, arg)| arg.clone())
+    .collect::<Vec<_>>();
+  let envelope_fname = positional.get(0).expect("envelope file path");
+  let params_fname = positional.get(1).expect("params file path");
+
+  let manifest =
+    Manifest::load(&artifacts_dir).unwrap_or_else(|e| VerifyOutcome::Malformed(e).report(json));
+  let resolve = |reference: &str| {
+    manifest
+      .resolve(&artifacts_dir, reference)
+      .unwrap_or_else(|e| VerifyOutcome::Malformed(e).report(json))
+  };
+
+  if let Some(signer) = signer {
+    match verify_envelope_signature(&resolve(envelope_fname), signer.as_ref()) {
+      Ok(true) => {}
+      Ok(false) => VerifyOutcome::Invalid.report(json),
+      Err(e) => VerifyOutcome::Malformed(e).report(json),
+    }
+  }
+  let envelope = match ProofEnvelope::read(&resolve(envelope_fname)) {
+    Ok(envelope) => envelope,
+    Err(e) => VerifyOutcome::Malformed(e).report(json),
+  };
+  let (params, params_hash) = match read_artifact(&resolve(params_fname)).and_then(|buf| {
+    let params = ParamsKZG::<Bn256>::read(&mut &buf[..]).map_err(|e| e.to_string())?;
+    Ok((params, content_hash(&buf)))
+  }) {
+    Ok(params) => params,
+    Err(e) => VerifyOutcome::Malformed(format!("could not read params: {}", e)).report(json),
+  };
+  let trusted_vkey_hash = match (trusted_vkey_fname, trusted_vkey_hash) {
+    (Some(fname), _) => match read_artifact(&resolve(&fname)) {
+      Ok(vkey) => content_hash(&vkey),
+      Err(e) => VerifyOutcome::Malformed(e).report(json),
+    },
+    (None, Some(hash)) => hash,
+    (None, None) => {
+      let msg = "no trusted verification key, pass --vkey or --vkey-hash".to_string();
+      VerifyOutcome::VkMismatch(msg).report(json)
+    }
+  };
+
+  // Catches proofs paired with the vkey of another run
+  if let Err(e) = manifest.check_link(&envelope.proof, "vkey", &envelope.vkey) {
+    VerifyOutcome::VkMismatch(e).report(json);
+  }
+  let outcome = run_with_timeout(timeout, "metrics.json", move |_| {
+    envelope.verify(&params, Some(&params_hash), &trusted_vkey_hash)
+  });
+  outcome.report(json);
+}
@@ -1,3 +1,15 @@
+pub mod batch;
 pub mod commit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod input_hash;
+pub mod merkle;
+pub mod model_commit;
+pub mod native;
 pub mod packer;
 pub mod poseidon_commit;
+pub mod poseidon_params;
+pub mod prf;
+
+pub use input_hash::hash_input;
+pub use model_commit::commit_model;
@@ -0,0 +1,141 @@
+use halo2_proofs::halo2curves::ff::{FromUniformBytes, PrimeField};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  loader::{ModelMsgpack, TensorMsgpack},
+  rlc::num_rlc_vals,
+};
+
+use super::{
+  input_hash::num_input_hash_vals,
+  merkle::{
+    field_from_string, field_to_string, hash_tensors, verify_proof, MerkleProof, MerkleTree,
+  },
+  model_commit::commit_weights,
+};
+
+// The commitment to the inputs of a batch proof, i.e., several inputs proven at once (inp_fnames
+// in prov_cli): a Merkle tree (see merkle.rs) over the input hashes of the samples, in proving
+// order. With hash_inputs, every circuit exposes the hash of its input (see input_hash.rs), so the
+// leaves are public values of the proof, and the root binds the proof to the batch while the
+// samples stay private. A sample is opened later, e.g., to audit an aggregate claim, with its
+// Merkle proof: the verifier checks the revealed input against the leaf and the leaf against the
+// root, which it checks against the public values of the proof.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchCommitment {
+  pub root: String,
+  pub depth: usize,
+  pub num_samples: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchOpenings {
+  pub commitment: BatchCommitment,
+  // The leaf index of an opening is the position of the sample in the batch
+  pub openings: Vec<MerkleProof>,
+}
+
+// The position of the input hash in the public values of every circuit of the batch
+pub fn input_hash_position(model: &ModelMsgpack) -> Result<usize, String> {
+  if num_input_hash_vals(model) == 0 {
+    return Err("the batch commitment needs a model with hash_inputs".to_string());
+  }
+  let mut model = model.clone();
+  commit_weights(&mut model);
+  let num_commits = model.commit_before.as_ref().map_or(0, |x| x.len())
+    + model.commit_after.as_ref().map_or(0, |x| x.len())
+    + num_rlc_vals(&model);
+  Ok(num_commits)
+}
+
+// The input hashes of the samples, from the public values of the batch proof
+pub fn batch_leaves<F: PrimeField>(
+  model: &ModelMsgpack,
+  public_vals: &[Vec<F>],
+) -> Result<Vec<F>, String> {
+  let pos = input_hash_position(model)?;
+  public_vals
+    .iter()
+    .enumerate()
+    .map(|(sample, vals)| {
+      vals
+        .get(pos)
+        .copied()
+        .ok_or_else(|| format!("sample {} has no input hash", sample))
+    })
+    .collect()
+}
+
+pub fn commit_batch<F: PrimeField + Ord + FromUniformBytes<64>>(
+  leaves: Vec<F>,
+) -> (BatchCommitment, MerkleTree<F>) {
+  let num_samples = leaves.len();
+  let tree = MerkleTree::new(leaves);
+  let commitment = BatchCommitment {
+    root: field_to_string(&tree.root()),
+    depth: tree.depth(),
+    num_samples,
+  };
+  (commitment, tree)
+}
+
+// The commitment and the opening of every sample, from the public values of the batch proof
+pub fn open_batch<F: PrimeField + Ord + FromUniformBytes<64>>(
+  model: &ModelMsgpack,
+  public_vals: &[Vec<F>],
+) -> Result<BatchOpenings, String> {
+  let (commitment, tree) = commit_batch(batch_leaves(model, public_vals)?);
+  let openings = (0..commitment.num_samples)
+    .map(|sample| tree.proof(sample))
+    .collect();
+  Ok(BatchOpenings {
+    commitment,
+    openings,
+  })
+}
+
+// Checks that the batch proof with these public values commits to the root
+pub fn check_batch_root<F: PrimeField + Ord + FromUniformBytes<64>>(
+  model: &ModelMsgpack,
+  public_vals: &[Vec<F>],
+  root: &str,
+) -> Result<(), String> {
+  let (commitment, _) = commit_batch(batch_leaves::<F>(model, public_vals)?);
+  if commitment.root != root {
+    return Err(format!(
+      "the proof commits to the batch root {}, not {}",
+      commitment.root, root
+    ));
+  }
+  Ok(())
+}
+
+// Checks a revealed sample against its opening and the batch commitment
+pub fn verify_opening<F: PrimeField + Ord + FromUniformBytes<64>>(
+  commitment: &BatchCommitment,
+  opening: &MerkleProof,
+  inputs: &Vec<TensorMsgpack>,
+) -> Result<(), String> {
+  if opening.leaf_idx >= commitment.num_samples || opening.siblings.len() != commitment.depth {
+    return Err(format!(
+      "sample {} is not in the batch of {}",
+      opening.leaf_idx, commitment.num_samples
+    ));
+  }
+  let leaf: F = hash_tensors(inputs);
+  if field_to_string(&leaf) != opening.leaf {
+    return Err(format!(
+      "the input doesn't match the hash of sample {}",
+      opening.leaf_idx
+    ));
+  }
+  let root = field_from_string(&commitment.root);
+  if !verify_proof::<F>(&root, commitment.depth, opening) {
+    return Err(format!(
+      "the opening of sample {} doesn't match the root",
+      opening.leaf_idx
+    ));
+  }
+  Ok(())
+}
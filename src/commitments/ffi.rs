@@ -0,0 +1,124 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_json::json;
+
+use crate::utils::{
+  loader::{parse_config_msgpack, parse_inputs_msgpack},
+  validate::LoaderLimits,
+};
+
+use super::{
+  merkle::field_to_string,
+  native::{check_commitments, commit_tensors, PackingParams},
+};
+
+// The C ABI of the native commitment helpers (see native.rs), for verifiers in other languages,
+// and for WebAssembly, where the functions are the exports of the module. Models and tensors are
+// the msgpack files, public values are 32 bytes each, little endian, as in the envelopes. The
+// functions write JSON to the output buffer and return its length; if the buffer is too small,
+// nothing is written and the caller retries with the returned length.
+
+unsafe fn slice<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+  if len == 0 {
+    &[]
+  } else {
+    std::slice::from_raw_parts(ptr, len)
+  }
+}
+
+fn public_vals(buf: &[u8]) -> Result<Vec<Fr>, String> {
+  if buf.len() % 32 != 0 {
+    return Err("malformed public values".to_string());
+  }
+  buf
+    .chunks(32)
+    .map(|chunk| {
+      Option::from(Fr::from_bytes(chunk.try_into().unwrap()))
+        .ok_or_else(|| "public value is not a field element".to_string())
+    })
+    .collect()
+}
+
+unsafe fn respond(
+  result: impl FnOnce() -> Result<serde_json::Value, String>,
+  out: *mut u8,
+  out_len: usize,
+) -> usize {
+  let response = match catch_unwind(AssertUnwindSafe(result)) {
+    Ok(Ok(response)) => response,
+    Ok(Err(e)) => json!({ "error": e }),
+    Err(_) => json!({ "error": "the helper panicked" }),
+  };
+  let buf = response.to_string().into_bytes();
+  if buf.len() <= out_len {
+    std::ptr::copy_nonoverlapping(buf.as_ptr(), out, buf.len());
+  }
+  buf.len()
+}
+
+/// Checks the commitments of a proof of the model against the revealed tensors, see
+/// native::check_commitments. Writes `{"ok": bool, "checks": [...]}` or `{"error": ...}`.
+///
+/// # Safety
+/// Every pointer must be valid for its length, and the output must not overlap the inputs.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn zkml_check_commitments(
+  model: *const u8,
+  model_len: usize,
+  revealed: *const u8,
+  revealed_len: usize,
+  public_vals_buf: *const u8,
+  public_vals_len: usize,
+  out: *mut u8,
+  out_len: usize,
+) -> usize {
+  let (model, revealed) = (slice(model, model_len), slice(revealed, revealed_len));
+  let public_vals_buf = slice(public_vals_buf, public_vals_len);
+  respond(
+    || {
+      let limits = LoaderLimits::default();
+      let model = parse_config_msgpack(model, &limits)?;
+      let revealed = match revealed.len() {
+        0 => vec![],
+        _ => parse_inputs_msgpack(revealed, &limits)?,
+      };
+      let checks = check_commitments(&model, &revealed, &public_vals(public_vals_buf)?)?;
+      Ok(json!({
+        "ok": checks.iter().all(|check| check.ok),
+        "checks": checks,
+      }))
+    },
+    out,
+    out_len,
+  )
+}
+
+/// The commitment of the circuit of the model to the tensors, as one group of commit_before or
+/// commit_after. Writes `{"commitment": decimal string}` or `{"error": ...}`.
+///
+/// # Safety
+/// Every pointer must be valid for its length, and the output must not overlap the inputs.
+#[no_mangle]
+pub unsafe extern "C" fn zkml_commit_tensors(
+  model: *const u8,
+  model_len: usize,
+  tensors: *const u8,
+  tensors_len: usize,
+  out: *mut u8,
+  out_len: usize,
+) -> usize {
+  let (model, tensors) = (slice(model, model_len), slice(tensors, tensors_len));
+  respond(
+    || {
+      let limits = LoaderLimits::default();
+      let params = PackingParams::from_model(&parse_config_msgpack(model, &limits)?)?;
+      let tensors = parse_inputs_msgpack(tensors, &limits)?;
+      let commitment = commit_tensors(&tensors.iter().collect::<Vec<_>>(), &params)?;
+      Ok(json!({ "commitment": field_to_string(&commitment) }))
+    },
+    out,
+    out_len,
+  )
+}
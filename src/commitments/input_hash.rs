@@ -0,0 +1,86 @@
+use std::rc::Rc;
+
+use halo2_gadgets::poseidon::{primitives::ConstantLength, Hash, Pow5Chip, Pow5Config};
+use halo2_proofs::{
+  circuit::Layouter,
+  halo2curves::{
+    bn256::Fr,
+    ff::{FromUniformBytes, PrimeField},
+  },
+  plonk::{Advice, Column, Error},
+};
+
+use crate::{
+  layers::layer::{AssignedTensor, CellRc},
+  utils::{
+    loader::{ModelMsgpack, TensorMsgpack},
+    storage::read_artifact,
+  },
+};
+
+use super::{
+  merkle::{hash_tensors, i64_to_field},
+  poseidon_params::{Spec3, RATE, WIDTH},
+};
+
+// Keeps the inputs private but exposes their hash, for proofs about data whose hash the verifier
+// knows. The hash is merkle::hash_tensors of the input file, computed in the circuit, so it does
+// not depend on the model and is also the leaf of the file in a dataset commitment. It is the
+// last commitment, after the random linear combination. Chaining the hash takes one Poseidon
+// permutation per input element, so it is meant for small inputs.
+
+pub fn hash_input(inp_path: &str) -> Fr {
+  let buf = read_artifact(inp_path).unwrap();
+  let tensors: Vec<TensorMsgpack> = rmp_serde::from_slice(&buf).unwrap();
+  hash_tensors(&tensors)
+}
+
+pub fn num_input_hash_vals(model: &ModelMsgpack) -> usize {
+  model.hash_inputs.unwrap_or(false) as usize
+}
+
+// hash_tensors over the assigned input tensors, in order of the tensor index. The indices and
+// shapes are fixed by the circuit.
+pub fn hash_assigned_tensors<F: PrimeField + Ord + FromUniformBytes<64>>(
+  mut layouter: impl Layouter<F>,
+  poseidon_config: &Pow5Config<F, WIDTH, RATE>,
+  column: Column<Advice>,
+  tensors: &Vec<(i64, &AssignedTensor<F>)>,
+) -> Result<CellRc<F>, Error> {
+  let mut header = vec![F::from(tensors.len() as u64)];
+  for (idx, tensor) in tensors.iter() {
+    header.push(i64_to_field(*idx));
+    header.push(F::from(tensor.ndim() as u64));
+    header.extend(tensor.shape().iter().map(|dim| F::from(*dim as u64)));
+  }
+  let header = layouter.assign_region(
+    || "input hash header",
+    |mut region| {
+      header
+        .iter()
+        .enumerate()
+        .map(|(i, x)| region.assign_advice_from_constant(|| "", column, i, *x))
+        .collect::<Result<Vec<_>, _>>()
+    },
+  )?;
+
+  let mut words = vec![];
+  let mut header = header.into_iter();
+  let mut acc = header.next().unwrap();
+  for (_, tensor) in tensors.iter() {
+    words.extend(header.by_ref().take(2 + tensor.ndim()));
+    words.extend(tensor.iter().map(|x| x.as_ref().clone()));
+  }
+  for (i, word) in words.into_iter().enumerate() {
+    let chip = Pow5Chip::construct(poseidon_config.clone());
+    let hasher = Hash::<_, _, Spec3<F>, ConstantLength<2>, WIDTH, RATE>::init(
+      chip,
+      layouter.namespace(|| format!("input hash init {}", i)),
+    )?;
+    acc = hasher.hash(
+      layouter.namespace(|| format!("input hash {}", i)),
+      [acc, word],
+    )?;
+  }
+  Ok(Rc::new(acc))
+}
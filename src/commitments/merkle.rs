@@ -0,0 +1,155 @@
+use halo2_proofs::halo2curves::ff::{FromUniformBytes, PrimeField};
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::loader::TensorMsgpack;
+
+use super::poseidon_params::hash;
+
+// Off-circuit Poseidon Merkle trees over input files, using the same Poseidon parameters as the
+// in-circuit commitments. Leaves are H(LEAF_TAG, x) and nodes H(NODE_TAG, left, right), so a node
+// can't be opened as a leaf, and the tree is padded to a power of two with EMPTY_LEAF, which is not
+// the hash of any leaf.
+
+const LEAF_TAG: u64 = 1;
+const NODE_TAG: u64 = 2;
+const EMPTY_TAG: u64 = 3;
+
+pub fn hash_leaf<F: PrimeField + Ord + FromUniformBytes<64>>(x: F) -> F {
+  hash([F::from(LEAF_TAG), x])
+}
+
+pub fn hash_node<F: PrimeField + Ord + FromUniformBytes<64>>(left: F, right: F) -> F {
+  hash([F::from(NODE_TAG), left, right])
+}
+
+pub fn empty_leaf<F: PrimeField + Ord + FromUniformBytes<64>>() -> F {
+  hash([F::from(EMPTY_TAG)])
+}
+
+// The chaining hash of hash_tensors, which the circuit recomputes (see input_hash.rs)
+pub fn hash_pair<F: PrimeField + Ord + FromUniformBytes<64>>(left: F, right: F) -> F {
+  hash([left, right])
+}
+
+pub fn i64_to_field<F: PrimeField>(x: i64) -> F {
+  if x < 0 {
+    -F::from(x.unsigned_abs())
+  } else {
+    F::from(x as u64)
+  }
+}
+
+pub fn field_to_string<F: PrimeField>(x: &F) -> String {
+  BigUint::from_bytes_le(x.to_repr().as_ref()).to_str_radix(10)
+}
+
+pub fn field_from_string<F: PrimeField>(x: &str) -> F {
+  F::from_str_vartime(x).expect("invalid field element")
+}
+
+// The leaf of an input file chains the hash over the tensor indices, shapes, and data, in order
+// of the tensor index
+pub fn hash_tensors<F: PrimeField + Ord + FromUniformBytes<64>>(tensors: &Vec<TensorMsgpack>) -> F {
+  let mut tensors = tensors.clone();
+  tensors.sort_by_key(|tensor| tensor.idx);
+
+  let mut acc = F::from(tensors.len() as u64);
+  for tensor in tensors.iter() {
+    acc = hash_pair(acc, i64_to_field(tensor.idx));
+    acc = hash_pair(acc, F::from(tensor.shape.len() as u64));
+    for dim in tensor.shape.iter() {
+      acc = hash_pair(acc, i64_to_field(*dim));
+    }
+    for x in tensor.data.iter() {
+      acc = hash_pair(acc, i64_to_field(*x));
+    }
+  }
+  acc
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MerkleProof {
+  pub leaf_idx: usize,
+  pub leaf: String,
+  // From the leaf to the root
+  pub siblings: Vec<String>,
+}
+
+pub struct MerkleTree<F: PrimeField + Ord + FromUniformBytes<64>> {
+  pub leaves: Vec<F>,
+  // layers[0] are the hashed and padded leaves, the last layer is the root
+  pub layers: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField + Ord + FromUniformBytes<64>> MerkleTree<F> {
+  pub fn new(leaves: Vec<F>) -> Self {
+    assert!(
+      !leaves.is_empty(),
+      "cannot build a Merkle tree without leaves"
+    );
+    let mut hashed = leaves.iter().map(|x| hash_leaf(*x)).collect::<Vec<_>>();
+    while !hashed.len().is_power_of_two() {
+      hashed.push(empty_leaf());
+    }
+
+    let mut layers = vec![hashed];
+    while layers[layers.len() - 1].len() > 1 {
+      let prev = &layers[layers.len() - 1];
+      let next = prev
+        .chunks(2)
+        .map(|pair| hash_node(pair[0], pair[1]))
+        .collect::<Vec<_>>();
+      layers.push(next);
+    }
+
+    Self { leaves, layers }
+  }
+
+  pub fn root(&self) -> F {
+    self.layers[self.layers.len() - 1][0]
+  }
+
+  pub fn depth(&self) -> usize {
+    self.layers.len() - 1
+  }
+
+  pub fn proof(&self, leaf_idx: usize) -> MerkleProof {
+    assert!(leaf_idx < self.leaves.len(), "leaf {} is padding", leaf_idx);
+    let mut idx = leaf_idx;
+    let mut siblings = vec![];
+    for layer in self.layers[..self.depth()].iter() {
+      siblings.push(field_to_string(&layer[idx ^ 1]));
+      idx /= 2;
+    }
+    MerkleProof {
+      leaf_idx,
+      leaf: field_to_string(&self.leaves[leaf_idx]),
+      siblings,
+    }
+  }
+}
+
+// Checks the proof against the root of a tree of the given depth. The depth is part of the
+// commitment: a shorter proof would open an inner node as a leaf.
+pub fn verify_proof<F: PrimeField + Ord + FromUniformBytes<64>>(
+  root: &F,
+  depth: usize,
+  proof: &MerkleProof,
+) -> bool {
+  if proof.siblings.len() != depth {
+    return false;
+  }
+  let mut idx = proof.leaf_idx;
+  let mut cur: F = hash_leaf(field_from_string(&proof.leaf));
+  for sibling in proof.siblings.iter() {
+    let sibling: F = field_from_string(sibling);
+    cur = if idx % 2 == 0 {
+      hash_node(cur, sibling)
+    } else {
+      hash_node(sibling, cur)
+    };
+    idx /= 2;
+  }
+  idx == 0 && cur == *root
+}
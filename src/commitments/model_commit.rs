@@ -0,0 +1,56 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    loader::{load_config_msgpack, sub_model, ModelMsgpack},
+    watermark::weight_idxes,
+  },
+};
+
+// Binds proofs to a model: with commit_weights, every weight tensor is committed in the circuit
+// as the first commit_before group, so the first public value is a Poseidon commitment to the
+// weights. commit_model recomputes it from the config alone, so a verifier can check which model
+// a proof was made with.
+
+// Makes the weights the first commit_before group. Applied when the config is loaded, so that
+// everything that counts commitments sees the group.
+pub fn commit_weights(model: &mut ModelMsgpack) {
+  if !model.commit_weights.unwrap_or(false) {
+    return;
+  }
+  let weights = weight_idxes(model);
+  let mut commit_before = model.commit_before.clone().unwrap_or(vec![]);
+  commit_before.retain(|group| *group != weights);
+  commit_before.insert(0, weights);
+  model.commit_before = Some(commit_before);
+}
+
+// The commitment to the weights of the model, as the circuit computes it. The packing depends on
+// k, num_cols, and bits_per_elem, so these are kept, and the circuit only commits to the weights.
+pub fn model_commitment(model: &ModelMsgpack) -> Fr {
+  let mut model = ModelMsgpack {
+    commit_weights: Some(true),
+    ..model.clone()
+  };
+  commit_weights(&mut model);
+  let weights = model.commit_before.as_ref().unwrap()[0].clone();
+  let model = ModelMsgpack {
+    inp_idxes: vec![],
+    tensors: model
+      .tensors
+      .iter()
+      .filter(|tensor| weights.contains(&tensor.idx))
+      .cloned()
+      .collect(),
+    layers: vec![],
+    commit_before: Some(vec![weights]),
+    branches: None,
+    ..sub_model(&model, vec![])
+  };
+  ModelCircuit::<Fr>::public_values(&model, &vec![]).unwrap()[0]
+}
+
+pub fn commit_model(config_path: &str) -> Fr {
+  model_commitment(&load_config_msgpack(config_path))
+}
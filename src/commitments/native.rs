@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use halo2_proofs::halo2curves::{
+  bn256::Fr,
+  ff::{FromUniformBytes, PrimeField},
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  loader::{ModelMsgpack, TensorMsgpack},
+  profiles::column_profile,
+  rlc::num_rlc_vals,
+};
+
+use super::{
+  input_hash::num_input_hash_vals,
+  merkle::{field_to_string, hash_tensors, i64_to_field},
+  model_commit::commit_weights,
+  packer::PackerChip,
+  poseidon_commit::L,
+  poseidon_params::sponge_hash,
+};
+
+// Off-circuit recomputation of the commitments in the public values, from the raw tensors, for
+// verifiers that are shown the committed data, e.g., the weights of a published model or the
+// outputs a commit_after group keeps private, without synthesizing the circuit. A commitment packs
+// the values of its tensors, in order of the tensor index, as PackerChip does, and hashes the
+// words with the sponge of PoseidonCommitChip. The input hash is merkle::hash_tensors.
+#[derive(Clone, Debug)]
+pub struct PackingParams {
+  pub num_cols: usize,
+  pub bits_per_elem: usize,
+}
+
+impl PackingParams {
+  // The params of the circuit of the model, or of its layout
+  pub fn from_model(model: &ModelMsgpack) -> Result<Self, String> {
+    let num_cols = match &model.column_profile {
+      Some(name) => column_profile(name)?.num_cols,
+      None => model.num_cols,
+    };
+    let bits_per_elem = model.bits_per_elem.unwrap_or(model.k);
+    if num_cols < 2 || !(1..64).contains(&bits_per_elem) {
+      return Err(format!(
+        "can't pack {} bit values in {} columns",
+        bits_per_elem, num_cols
+      ));
+    }
+    Ok(Self {
+      num_cols: num_cols as usize,
+      bits_per_elem: bits_per_elem as usize,
+    })
+  }
+}
+
+// Every packed word holds num_elem_per_packed values, each shifted to be non-negative, and the
+// last word is padded with zeros
+pub fn pack<F: PrimeField>(values: &[F], params: &PackingParams) -> Result<Vec<F>, String> {
+  let bits = params.bits_per_elem;
+  let num_elem_per_packed = PackerChip::<F>::num_elem_per_packed(bits, params.num_cols);
+  // The circuit packs several words per row with more than ~500 columns, which isn't mirrored
+  if params.num_cols / (num_elem_per_packed * (bits + 1)) > 1 {
+    return Err(format!(
+      "{} columns pack several words per row",
+      params.num_cols
+    ));
+  }
+  let exponents = PackerChip::<F>::get_exponents(bits, num_elem_per_packed);
+  let shift = F::from(1u64 << (bits - 1));
+  Ok(
+    values
+      .chunks(num_elem_per_packed)
+      .map(|chunk| {
+        let padding = std::iter::repeat(&F::ZERO).take(num_elem_per_packed - chunk.len());
+        chunk
+          .iter()
+          .chain(padding)
+          .zip(exponents.iter())
+          .fold(F::ZERO, |acc, (x, exp)| acc + (*x + shift) * exp)
+      })
+      .collect(),
+  )
+}
+
+// The commitment of PoseidonCommitChip to the values: the packed words, then the blinding (zero),
+// padded with it to a multiple of L words
+pub fn commit_values<F: PrimeField + Ord + FromUniformBytes<64>>(
+  values: &[F],
+  params: &PackingParams,
+) -> Result<F, String> {
+  let mut words = pack(values, params)?;
+  words.push(F::ZERO);
+  while words.len() % L != 0 {
+    words.push(F::ZERO);
+  }
+  Ok(sponge_hash(&words, L))
+}
+
+pub fn commit_tensors(tensors: &[&TensorMsgpack], params: &PackingParams) -> Result<Fr, String> {
+  let mut tensors = tensors.to_vec();
+  tensors.sort_by_key(|tensor| tensor.idx);
+  let values = tensors
+    .iter()
+    .flat_map(|tensor| tensor.data.iter().map(|x| i64_to_field(*x)))
+    .collect::<Vec<Fr>>();
+  commit_values(&values, params)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitmentCheck {
+  // "commit_before", "commit_after", or "input_hash"
+  pub kind: String,
+  pub position: usize,
+  pub tensors: Vec<i64>,
+  pub expected: String,
+  pub recomputed: String,
+  pub ok: bool,
+}
+
+// Checks every commitment whose tensors are known, from the tensors of the model with data and
+// the revealed ones, against the public values of a proof of the model
+pub fn check_commitments(
+  model: &ModelMsgpack,
+  revealed: &[TensorMsgpack],
+  public_vals: &[Fr],
+) -> Result<Vec<CommitmentCheck>, String> {
+  let mut model = model.clone();
+  commit_weights(&mut model);
+  let params = PackingParams::from_model(&model)?;
+
+  let mut known = BTreeMap::new();
+  for tensor in model
+    .tensors
+    .iter()
+    .filter(|tensor| !tensor.data.is_empty())
+  {
+    known.insert(tensor.idx, tensor);
+  }
+  for tensor in revealed.iter() {
+    known.insert(tensor.idx, tensor);
+  }
+  let lookup = |idxes: &Vec<i64>| idxes.iter().map(|idx| known.get(idx).copied()).collect();
+
+  let commit_before = model.commit_before.clone().unwrap_or(vec![]);
+  let commit_after = model.commit_after.clone().unwrap_or(vec![]);
+  let num_commits =
+    commit_before.len() + commit_after.len() + num_rlc_vals(&model) + num_input_hash_vals(&model);
+  if public_vals.len() < num_commits {
+    return Err(format!(
+      "{} public values, but the model has {} commitments",
+      public_vals.len(),
+      num_commits
+    ));
+  }
+
+  let groups = commit_before
+    .iter()
+    .map(|group| ("commit_before", group))
+    .chain(commit_after.iter().map(|group| ("commit_after", group)));
+  let mut checks = vec![];
+  let mut check = |kind: &str, position: usize, tensors: Vec<i64>, recomputed: Fr| {
+    checks.push(CommitmentCheck {
+      kind: kind.to_string(),
+      position,
+      tensors,
+      expected: field_to_string(&public_vals[position]),
+      recomputed: field_to_string(&recomputed),
+      ok: public_vals[position] == recomputed,
+    })
+  };
+  for (position, (kind, group)) in groups.enumerate() {
+    let tensors: Option<Vec<&TensorMsgpack>> = lookup(group);
+    if let Some(tensors) = tensors {
+      check(
+        kind,
+        position,
+        group.clone(),
+        commit_tensors(&tensors, &params)?,
+      );
+    }
+  }
+  if model.hash_inputs.unwrap_or(false) {
+    let inputs: Option<Vec<&TensorMsgpack>> = lookup(&model.inp_idxes);
+    if let Some(inputs) = inputs {
+      let inputs = inputs.into_iter().cloned().collect();
+      check(
+        "input_hash",
+        num_commits - 1,
+        model.inp_idxes.clone(),
+        hash_tensors(&inputs),
+      );
+    }
+  }
+  Ok(checks)
+}
@@ -42,15 +42,19 @@ impl<F: PrimeField> PackerChip<F> {
     exponents
   }
 
-  pub fn construct(num_bits_per_elem: usize, gadget_config: &GadgetConfig) -> PackerConfig<F> {
-    let columns = &gadget_config.columns;
-
-    let num_elem_per_packed = if NUM_BITS_PER_FIELD_ELEM / num_bits_per_elem > columns.len() - 1 {
-      columns.len() - 1
+  pub fn num_elem_per_packed(num_bits_per_elem: usize, num_cols: usize) -> usize {
+    if NUM_BITS_PER_FIELD_ELEM / num_bits_per_elem > num_cols - 1 {
+      num_cols - 1
     } else {
       // TODO: for many columns, pack many in a single row
       NUM_BITS_PER_FIELD_ELEM / num_bits_per_elem
-    };
+    }
+  }
+
+  pub fn construct(num_bits_per_elem: usize, gadget_config: &GadgetConfig) -> PackerConfig<F> {
+    let columns = &gadget_config.columns;
+
+    let num_elem_per_packed = Self::num_elem_per_packed(num_bits_per_elem, columns.len());
     info!("column len: {}", columns.len());
     info!("num_bits_per_elem: {}", num_bits_per_elem);
     info!("NUM_BITS_PER_FIELD_ELEM: {}", NUM_BITS_PER_FIELD_ELEM);
@@ -1,7 +1,7 @@
-use std::{collections::HashMap, marker::PhantomData, rc::Rc};
+use std::{collections::HashMap, rc::Rc};
 
 use halo2_gadgets::poseidon::{
-  primitives::{generate_constants, Absorbing, ConstantLength, Domain, Mds, Spec},
+  primitives::{Absorbing, ConstantLength, Domain, Spec},
   PaddedWord, PoseidonSpongeInstructions, Pow5Chip, Pow5Config, Sponge,
 };
 use halo2_proofs::{
@@ -12,10 +12,11 @@ use halo2_proofs::{
 
 use crate::{gadgets::gadget::GadgetConfig, layers::layer::CellRc};
 
-use super::commit::Commit;
+use super::{
+  commit::Commit,
+  poseidon_params::{configure_poseidon, Spec3, RATE, WIDTH},
+};
 
-pub const WIDTH: usize = 3;
-pub const RATE: usize = 2;
 pub const L: usize = 8 - WIDTH - 1;
 
 #[derive(Clone, Debug)]
@@ -29,39 +30,6 @@ pub struct PoseidonCommitChip<
   pub poseidon_config: Pow5Config<F, WIDTH, RATE>,
 }
 
-#[derive(Debug)]
-pub struct P128Pow5T3Gen<F: PrimeField, const SECURE_MDS: usize>(PhantomData<F>);
-
-impl<F: PrimeField, const SECURE_MDS: usize> P128Pow5T3Gen<F, SECURE_MDS> {
-  pub fn new() -> Self {
-    P128Pow5T3Gen(PhantomData::default())
-  }
-}
-
-impl<F: FromUniformBytes<64> + Ord, const SECURE_MDS: usize> Spec<F, 3, 2>
-  for P128Pow5T3Gen<F, SECURE_MDS>
-{
-  fn full_rounds() -> usize {
-    8
-  }
-
-  fn partial_rounds() -> usize {
-    56
-  }
-
-  fn sbox(val: F) -> F {
-    val.pow_vartime([5])
-  }
-
-  fn secure_mds() -> usize {
-    SECURE_MDS
-  }
-
-  fn constants() -> (Vec<[F; 3]>, Mds<F, 3>, Mds<F, 3>) {
-    generate_constants::<_, Self, 3, 2>()
-  }
-}
-
 /// A Poseidon hash function, built around a sponge.
 #[derive(Debug)]
 pub struct MyHash<
@@ -83,19 +51,8 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> PoseidonCommitChip<F, WIDTH, RA
     state: [Column<Advice>; WIDTH],
     partial_sbox: Column<Advice>,
   ) -> PoseidonCommitChip<F, WIDTH, RATE, L> {
-    let rc_a = (0..WIDTH).map(|_| meta.fixed_column()).collect::<Vec<_>>();
-    let rc_b = (0..WIDTH).map(|_| meta.fixed_column()).collect::<Vec<_>>();
-
-    meta.enable_constant(rc_b[0]);
-
     PoseidonCommitChip {
-      poseidon_config: Pow5Chip::configure::<P128Pow5T3Gen<F, 0>>(
-        meta,
-        state.try_into().unwrap(),
-        partial_sbox,
-        rc_a.try_into().unwrap(),
-        rc_b.try_into().unwrap(),
-      ),
+      poseidon_config: configure_poseidon(meta, state, partial_sbox),
     }
   }
 }
@@ -112,7 +69,7 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Commit<F>
     blinding: CellRc<F>,
   ) -> Result<Vec<CellRc<F>>, Error> {
     let chip = Pow5Chip::construct(self.poseidon_config.clone());
-    let mut hasher: MyHash<F, Pow5Chip<F, 3, 2>, P128Pow5T3Gen<F, 0>, ConstantLength<L>, 3, 2> =
+    let mut hasher: MyHash<F, Pow5Chip<F, WIDTH, RATE>, Spec3<F>, ConstantLength<L>, WIDTH, RATE> =
       Sponge::new(chip, layouter.namespace(|| "sponge"))
         .map(|sponge| MyHash { sponge })
         .unwrap();
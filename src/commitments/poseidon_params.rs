@@ -0,0 +1,139 @@
+use std::marker::PhantomData;
+
+use halo2_gadgets::poseidon::{
+  primitives::{generate_constants, ConstantLength, Hash, Mds, Spec},
+  Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+  halo2curves::ff::{FromUniformBytes, PrimeField},
+  plonk::{Advice, Column, ConstraintSystem},
+};
+
+// The Poseidon parameters of every hash in zkml, over the BN254 scalar field, with the x^5 S-box
+// and 8 full rounds. The round constants and the MDS matrix are generated by the Grain LFSR of the
+// Poseidon reference implementation (generate_constants in halo2_gadgets), with the first secure
+// MDS matrix, so the hashes can be reproduced outside of zkml from these numbers alone:
+// - width 3 (rate 2) with 56 partial rounds, for the commitments, the Merkle trees over datasets,
+//   the input hashes, and the PRF (all with ConstantLength domains)
+// - width 5 (rate 4) with 60 partial rounds, for the transcript of the proofs for aggregation,
+//   whose constants snark-verifier generates from the same rounds
+pub const FULL_ROUNDS: usize = 8;
+pub const PARTIAL_ROUNDS_T3: usize = 56;
+pub const PARTIAL_ROUNDS_T5: usize = 60;
+
+pub const WIDTH: usize = 3;
+pub const RATE: usize = 2;
+pub const TRANSCRIPT_WIDTH: usize = 5;
+pub const TRANSCRIPT_RATE: usize = 4;
+
+#[derive(Debug)]
+pub struct PoseidonSpec<F: PrimeField, const T: usize, const RATE: usize>(PhantomData<F>);
+
+impl<F: FromUniformBytes<64> + Ord, const T: usize, const RATE: usize> Spec<F, T, RATE>
+  for PoseidonSpec<F, T, RATE>
+{
+  fn full_rounds() -> usize {
+    FULL_ROUNDS
+  }
+
+  fn partial_rounds() -> usize {
+    match T {
+      3 => PARTIAL_ROUNDS_T3,
+      5 => PARTIAL_ROUNDS_T5,
+      _ => panic!("no Poseidon parameters for width {}", T),
+    }
+  }
+
+  fn sbox(val: F) -> F {
+    val.pow_vartime([5])
+  }
+
+  fn secure_mds() -> usize {
+    0
+  }
+
+  fn constants() -> (Vec<[F; T]>, Mds<F, T>, Mds<F, T>) {
+    generate_constants::<_, Self, T, RATE>()
+  }
+}
+
+pub type Spec3<F> = PoseidonSpec<F, WIDTH, RATE>;
+pub type Spec5<F> = PoseidonSpec<F, TRANSCRIPT_WIDTH, TRANSCRIPT_RATE>;
+
+// The off-circuit hash of a fixed number of elements
+pub fn hash<F: PrimeField + Ord + FromUniformBytes<64>, const L: usize>(message: [F; L]) -> F {
+  Hash::<F, Spec3<F>, ConstantLength<L>, WIDTH, RATE>::init().hash(message)
+}
+
+// The permutation of the width 3 hashes, as in the reference: every round adds its constants, then
+// the full rounds apply the S-box to every word and the partial rounds to the first, and the MDS
+// matrix mixes the words
+fn permute<F: PrimeField + Ord + FromUniformBytes<64>>(
+  state: &mut [F; WIDTH],
+  mds: &Mds<F, WIDTH>,
+  round_constants: &[[F; WIDTH]],
+) {
+  let half_full_rounds = FULL_ROUNDS / 2;
+  for (round, rcs) in round_constants.iter().enumerate() {
+    for (word, rc) in state.iter_mut().zip(rcs.iter()) {
+      *word += rc;
+    }
+    if round < half_full_rounds || round >= half_full_rounds + PARTIAL_ROUNDS_T3 {
+      for word in state.iter_mut() {
+        *word = Spec3::<F>::sbox(*word);
+      }
+    } else {
+      state[0] = Spec3::<F>::sbox(state[0]);
+    }
+    let mixed =
+      std::array::from_fn(|i| (0..WIDTH).fold(F::ZERO, |acc, j| acc + mds[i][j] * state[j]));
+    *state = mixed;
+  }
+}
+
+// The sponge of the in-circuit hashes over any number of words, for the ConstantLength domain of
+// domain_len: the capacity starts at domain_len * 2^64, every rate of words is added to the state
+// and permuted, and the hash is the first word. The words must fill whole rates.
+pub fn sponge_hash<F: PrimeField + Ord + FromUniformBytes<64>>(
+  words: &[F],
+  domain_len: usize,
+) -> F {
+  assert_eq!(words.len() % RATE, 0, "the words don't fill whole rates");
+  let (round_constants, mds, _) = Spec3::<F>::constants();
+  let mut state = [F::ZERO; WIDTH];
+  state[RATE] = F::from_u128((domain_len as u128) << 64);
+  for chunk in words.chunks(RATE) {
+    for (word, x) in state.iter_mut().zip(chunk.iter()) {
+      *word += x;
+    }
+    permute(&mut state, &mds, &round_constants);
+  }
+  state[0]
+}
+
+// A PRF keyed by a field element. The message has three elements, so it is in another domain than
+// the pairs of the Merkle trees and the input hashes.
+pub fn prf<F: PrimeField + Ord + FromUniformBytes<64>>(key: F, input: F) -> F {
+  hash([key, input, F::ZERO])
+}
+
+// The width 3 permutation in the circuit, on the state columns and the partial S-box column.
+// Every chip that hashes in the circuit shares this config.
+pub fn configure_poseidon<F: PrimeField + Ord + FromUniformBytes<64>>(
+  meta: &mut ConstraintSystem<F>,
+  state: [Column<Advice>; WIDTH],
+  partial_sbox: Column<Advice>,
+) -> Pow5Config<F, WIDTH, RATE> {
+  let rc_a = (0..WIDTH).map(|_| meta.fixed_column()).collect::<Vec<_>>();
+  let rc_b = (0..WIDTH).map(|_| meta.fixed_column()).collect::<Vec<_>>();
+
+  meta.enable_constant(rc_b[0]);
+
+  Pow5Chip::configure::<Spec3<F>>(
+    meta,
+    state,
+    partial_sbox,
+    rc_a.try_into().unwrap(),
+    rc_b.try_into().unwrap(),
+  )
+}
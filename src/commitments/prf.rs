@@ -0,0 +1,90 @@
+use std::rc::Rc;
+
+use halo2_gadgets::poseidon::{primitives::ConstantLength, Hash, Pow5Chip, Pow5Config};
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::{FromUniformBytes, PrimeField},
+  plonk::{Advice, Column, Error},
+};
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+
+use crate::gadgets::{
+  gadget::GadgetConfig,
+  prf_limbs::{uniform_bits, uniforms_per_hash, PrfLimbsChip},
+};
+
+use super::{
+  merkle::i64_to_field,
+  poseidon_params::{prf, Spec3, RATE, WIDTH},
+};
+
+// The randomness of the stochastic layers (see randomness.rs) is the PRF keyed by the seed over a
+// counter: the j-th hash is prf(seed, j), and every hash gives uniforms_per_hash uniforms of
+// uniform_bits(k) bits, its lowest limbs first. The circuit computes the same hashes from the
+// seed cell and constrains the limbs, so for a public seed, the witness has no freedom in them.
+
+// The first num uniforms of the seed, as the circuit derives them at k
+pub fn prf_uniforms<F: PrimeField + Ord + FromUniformBytes<64>>(
+  seed: i64,
+  num: usize,
+  k: usize,
+) -> Vec<i64> {
+  let bits = uniform_bits(k);
+  let n = uniforms_per_hash(bits);
+  let mask = (BigUint::from(1u64) << bits) - 1u64;
+  let mut uniforms = vec![];
+  for j in 0..(num + n - 1) / n {
+    let r: F = prf(i64_to_field(seed), F::from(j as u64));
+    let r = BigUint::from_bytes_le(r.to_repr().as_ref());
+    for i in 0..n {
+      uniforms.push(((&r >> (i * bits)) & &mask).to_i64().unwrap());
+    }
+  }
+  uniforms.truncate(num);
+  uniforms
+}
+
+// prf_uniforms over the assigned seed
+pub fn assign_prf_uniforms<F: PrimeField + Ord + FromUniformBytes<64>>(
+  mut layouter: impl Layouter<F>,
+  poseidon_config: &Pow5Config<F, WIDTH, RATE>,
+  gadget_config: Rc<GadgetConfig>,
+  column: Column<Advice>,
+  seed: &AssignedCell<F, F>,
+  zero: &AssignedCell<F, F>,
+  num: usize,
+) -> Result<Vec<AssignedCell<F, F>>, Error> {
+  let n = uniforms_per_hash(uniform_bits(gadget_config.k));
+  let num_hashes = (num + n - 1) / n;
+  let counters = layouter.assign_region(
+    || "prf counters",
+    |mut region| {
+      (0..num_hashes)
+        .map(|j| region.assign_advice_from_constant(|| "", column, j, F::from(j as u64)))
+        .collect::<Result<Vec<_>, _>>()
+    },
+  )?;
+
+  let mut hashes = vec![];
+  for (j, counter) in counters.into_iter().enumerate() {
+    let chip = Pow5Chip::construct(poseidon_config.clone());
+    let hasher = Hash::<_, _, Spec3<F>, ConstantLength<3>, WIDTH, RATE>::init(
+      chip,
+      layouter.namespace(|| format!("prf init {}", j)),
+    )?;
+    hashes.push(hasher.hash(
+      layouter.namespace(|| format!("prf {}", j)),
+      [seed.clone(), counter, zero.clone()],
+    )?);
+  }
+
+  let limbs_chip = PrfLimbsChip::<F>::construct(gadget_config);
+  let mut uniforms = limbs_chip.forward(
+    layouter.namespace(|| "prf limbs"),
+    &hashes.iter().collect(),
+    zero,
+  )?;
+  uniforms.truncate(num);
+  Ok(uniforms)
+}
@@ -2,11 +2,16 @@ pub mod add_pairs;
 pub mod adder;
 pub mod bias_div_floor_relu6;
 pub mod bias_div_round_relu6;
+pub mod comparator;
 pub mod dot_prod;
 pub mod gadget;
 pub mod input_lookup;
+pub mod int_div;
 pub mod max;
 pub mod mul_pairs;
+pub mod prf_limbs;
+pub mod signed_range_check;
+pub mod sorting_network;
 pub mod sqrt_big;
 pub mod square;
 pub mod squared_diff;
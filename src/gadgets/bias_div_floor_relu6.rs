@@ -7,7 +7,7 @@ use halo2_proofs::{
   poly::Rotation,
 };
 
-use crate::gadgets::gadget::convert_to_u64;
+use crate::gadgets::gadget::{convert_to_i128_shifted, convert_to_u64, known_or_error};
 
 use super::gadget::{Gadget, GadgetConfig, GadgetType};
 
@@ -149,7 +149,7 @@ impl<F: PrimeField> Gadget<F> for BiasDivFloorRelu6Chip<F> {
 
     let div_outp_min_val_i64 = -self.config.div_outp_min_val;
 
-    let div_inp_min_val_pos_i64 = -SHIFT_MIN_VAL;
+    let div_inp_min_val_pos_i64 = -self.config.shift_min_val;
     let div_inp_min_val_pos = F::from(div_inp_min_val_pos_i64 as u64);
 
     let inp = &vec_inputs[0];
@@ -182,15 +182,20 @@ impl<F: PrimeField> Gadget<F> for BiasDivFloorRelu6Chip<F> {
         let a = convert_to_u64(&a) as i64 - div_inp_min_val_pos_i64;
         a
       });
-      let div_mod_res = inp_f.map(|x: F| {
-        let x_pos = x + div_inp_min_val_pos;
-        let inp = convert_to_u64(&x_pos);
+      let div_mod_res = inp_f.map(|x: F| -> Result<(i64, i64), String> {
+        // Widened to avoid overflowing on large dot products
+        let inp = convert_to_i128_shifted(&x, self.config.max_accumulator())?
+          + div_inp_min_val_pos_i64 as i128;
+        let div_val = div_val as i128;
         // info!("inp: {:?}, bias: {:?}, x_pos: {:?}", inp, bias, x_pos);
-        let div_res = inp as i64 / div_val - (div_inp_min_val_pos_i64 / div_val);
-        let mod_res = inp as i64 % div_val;
+        let div_res = inp / div_val - (div_inp_min_val_pos_i64 as i128 / div_val);
+        let mod_res = inp % div_val;
         // info!("div_res: {:?}, mod_res: {:?}", div_res, mod_res);
-        (div_res, mod_res)
+        let div_res = i64::try_from(div_res)
+          .map_err(|_| format!("division result {} overflows i64", div_res))?;
+        Ok((div_res, mod_res as i64))
       });
+      let div_mod_res = known_or_error(div_mod_res)?;
       let div_res = div_mod_res.map(|x: (i64, i64)| x.0) + bias_f;
       let mod_res = div_mod_res.map(|x: (i64, i64)| x.1);
 
@@ -7,7 +7,7 @@ use halo2_proofs::{
   poly::Rotation,
 };
 
-use crate::gadgets::gadget::convert_to_u64;
+use crate::gadgets::gadget::{convert_to_i128_shifted, convert_to_u64, known_or_error};
 
 use super::gadget::{Gadget, GadgetConfig, GadgetType};
 
@@ -15,8 +15,11 @@ type BiasDivRoundRelu6Config = GadgetConfig;
 
 const NUM_COLS_PER_OP: usize = 5;
 
+// The same gadget also computes the plain ReLU (BiasDivRoundRelu), so that convs with a ReLU
+// don't need a separate pass over their outputs. The two only differ in the output table.
 pub struct BiasDivRoundRelu6Chip<F: PrimeField> {
   config: Rc<BiasDivRoundRelu6Config>,
+  gadget_type: GadgetType,
   _marker: PhantomData<F>,
 }
 
@@ -24,23 +27,55 @@ impl<F: PrimeField> BiasDivRoundRelu6Chip<F> {
   pub fn construct(config: Rc<BiasDivRoundRelu6Config>) -> Self {
     Self {
       config,
+      gadget_type: GadgetType::BiasDivRoundRelu6,
       _marker: PhantomData,
     }
   }
 
-  pub fn get_map(scale_factor: u64, min_val: i64, num_rows: i64) -> HashMap<i64, i64> {
+  pub fn construct_relu(config: Rc<BiasDivRoundRelu6Config>) -> Self {
+    Self {
+      config,
+      gadget_type: GadgetType::BiasDivRoundRelu,
+      _marker: PhantomData,
+    }
+  }
+
+  pub fn get_map(
+    scale_factor: u64,
+    min_val: i64,
+    num_rows: i64,
+    gadget_type: GadgetType,
+  ) -> HashMap<i64, i64> {
     let div_val = scale_factor;
 
     let mut map = HashMap::new();
     for i in 0..num_rows {
       let shifted = i + min_val;
-      let val = shifted.clamp(0, 6 * div_val as i64);
+      let val = match gadget_type {
+        GadgetType::BiasDivRoundRelu => shifted.max(0),
+        _ => shifted.clamp(0, 6 * div_val as i64),
+      };
       map.insert(i as i64, val);
     }
     map
   }
 
   pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    Self::configure_type(meta, gadget_config, GadgetType::BiasDivRoundRelu6)
+  }
+
+  pub fn configure_relu(
+    meta: &mut ConstraintSystem<F>,
+    gadget_config: GadgetConfig,
+  ) -> GadgetConfig {
+    Self::configure_type(meta, gadget_config, GadgetType::BiasDivRoundRelu)
+  }
+
+  fn configure_type(
+    meta: &mut ConstraintSystem<F>,
+    gadget_config: GadgetConfig,
+    gadget_type: GadgetType,
+  ) -> GadgetConfig {
     let selector = meta.complex_selector();
     let sf = Expression::Constant(F::from(gadget_config.scale_factor));
     let two = Expression::Constant(F::from(2));
@@ -89,7 +124,7 @@ impl<F: PrimeField> BiasDivRoundRelu6Chip<F> {
         let div_outp_min_val = gadget_config.div_outp_min_val;
         let div_outp_min_val = Expression::Constant(F::from((-div_outp_min_val) as u64));
 
-        // Constrains that output \in [0, 6 * SF]
+        // Constrains that output = relu6(div) (or relu(div))
         vec![
           (s.clone() * (div + div_outp_min_val), div_lookup),
           (s.clone() * outp, relu_lookup),
@@ -98,17 +133,18 @@ impl<F: PrimeField> BiasDivRoundRelu6Chip<F> {
     }
 
     let mut selectors = gadget_config.selectors;
-    selectors.insert(GadgetType::BiasDivRoundRelu6, vec![selector]);
+    selectors.insert(gadget_type, vec![selector]);
 
-    tables.insert(GadgetType::BiasDivRoundRelu6, vec![relu_lookup]);
+    tables.insert(gadget_type, vec![relu_lookup]);
 
     let mut maps = gadget_config.maps;
     let relu_map = Self::get_map(
       gadget_config.scale_factor,
       gadget_config.min_val,
       gadget_config.num_rows as i64,
+      gadget_type,
     );
-    maps.insert(GadgetType::BiasDivRoundRelu6, vec![relu_map]);
+    maps.insert(gadget_type, vec![relu_map]);
 
     GadgetConfig {
       columns,
@@ -122,7 +158,10 @@ impl<F: PrimeField> BiasDivRoundRelu6Chip<F> {
 
 impl<F: PrimeField> Gadget<F> for BiasDivRoundRelu6Chip<F> {
   fn name(&self) -> String {
-    "BiasDivRelu6".to_string()
+    match self.gadget_type {
+      GadgetType::BiasDivRoundRelu => "BiasDivRelu".to_string(),
+      _ => "BiasDivRelu6".to_string(),
+    }
   }
 
   fn num_cols_per_op(&self) -> usize {
@@ -138,9 +177,9 @@ impl<F: PrimeField> Gadget<F> for BiasDivRoundRelu6Chip<F> {
   }
 
   fn load_lookups(&self, mut layouter: impl Layouter<F>) -> Result<(), Error> {
-    let map = &self.config.maps[&GadgetType::BiasDivRoundRelu6][0];
+    let map = &self.config.maps[&self.gadget_type][0];
 
-    let relu_lookup = self.config.tables[&GadgetType::BiasDivRoundRelu6][0];
+    let relu_lookup = self.config.tables[&self.gadget_type][0];
 
     layouter
       .assign_table(
@@ -185,18 +224,10 @@ impl<F: PrimeField> Gadget<F> for BiasDivRoundRelu6Chip<F> {
     assert_eq!(inp.len(), bias.len());
     assert_eq!(inp.len() % self.num_inputs_per_row(), 0);
 
-    let relu_map = &self
-      .config
-      .maps
-      .get(&GadgetType::BiasDivRoundRelu6)
-      .unwrap()[0];
+    let relu_map = &self.config.maps.get(&self.gadget_type).unwrap()[0];
 
     if self.config.use_selectors {
-      let selector = self
-        .config
-        .selectors
-        .get(&GadgetType::BiasDivRoundRelu6)
-        .unwrap()[0];
+      let selector = self.config.selectors.get(&self.gadget_type).unwrap()[0];
       selector.enable(region, row_offset).unwrap();
     }
 
@@ -210,14 +241,19 @@ impl<F: PrimeField> Gadget<F> for BiasDivRoundRelu6Chip<F> {
         let a = convert_to_u64(&a) as i64 - div_inp_min_val_pos_i64;
         a
       });
-      let div_mod_res = inp_f.map(|x: F| {
-        let x_pos = x + div_inp_min_val_pos;
-        let inp = convert_to_u64(&x_pos) as i64;
+      let div_mod_res = inp_f.map(|x: F| -> Result<(i64, i64), String> {
+        // Widened to avoid overflowing on large dot products
+        let inp = convert_to_i128_shifted(&x, self.config.max_accumulator())?
+          + div_inp_min_val_pos_i64 as i128;
+        let div_val = div_val as i128;
         let div_inp = 2 * inp + div_val;
-        let div_res = div_inp / (2 * div_val) - div_inp_min_val_pos_i64 / div_val;
+        let div_res = div_inp / (2 * div_val) - div_inp_min_val_pos_i64 as i128 / div_val;
         let mod_res = div_inp % (2 * div_val);
-        (div_res, mod_res)
+        let div_res = i64::try_from(div_res)
+          .map_err(|_| format!("division result {} overflows i64", div_res))?;
+        Ok((div_res, mod_res as i64))
       });
+      let div_mod_res = known_or_error(div_mod_res)?;
       let div_res = div_mod_res.map(|x: (i64, i64)| x.0) + bias_f;
       let mod_res = div_mod_res.map(|x: (i64, i64)| x.1);
 
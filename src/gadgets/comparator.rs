@@ -0,0 +1,163 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region},
+  halo2curves::ff::PrimeField,
+  plonk::{ConstraintSystem, Error, Expression},
+  poly::Rotation,
+};
+
+use crate::gadgets::gadget::convert_to_u64;
+
+use super::gadget::{Gadget, GadgetConfig, GadgetType};
+
+// Computes out = [a <= b]. out must be a bit, and
+// out * (b - a) + (1 - out) * (a - b - 1)
+// must be in the input lookup, so |a - b| must be less than the number of rows
+pub struct ComparatorChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> ComparatorChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>) -> Self {
+    Self {
+      config,
+      _marker: PhantomData,
+    }
+  }
+
+  pub fn num_cols_per_op() -> usize {
+    3
+  }
+
+  pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    let selector = meta.complex_selector();
+    let columns = gadget_config.columns;
+    let tables = gadget_config.tables;
+
+    let inp_lookup = tables.get(&GadgetType::InputLookup).unwrap()[0];
+
+    meta.create_gate("comparator bit", |meta| {
+      let s = meta.query_selector(selector);
+      let mut constraints = vec![];
+      for i in 0..columns.len() / Self::num_cols_per_op() {
+        let offset = i * Self::num_cols_per_op();
+        let outp = meta.query_advice(columns[offset + 2], Rotation::cur());
+        let one = Expression::Constant(F::ONE);
+
+        constraints.push(s.clone() * outp.clone() * (one - outp));
+      }
+      constraints
+    });
+
+    for idx in 0..columns.len() / Self::num_cols_per_op() {
+      meta.lookup("comparator", |meta| {
+        let s = meta.query_selector(selector);
+        let offset = idx * Self::num_cols_per_op();
+        let a = meta.query_advice(columns[offset + 0], Rotation::cur());
+        let b = meta.query_advice(columns[offset + 1], Rotation::cur());
+        let outp = meta.query_advice(columns[offset + 2], Rotation::cur());
+        let one = Expression::Constant(F::ONE);
+
+        let le = outp.clone() * (b.clone() - a.clone());
+        let gt = (one.clone() - outp) * (a - b - one);
+        vec![(s * (le + gt), inp_lookup)]
+      });
+    }
+
+    let mut selectors = gadget_config.selectors;
+    selectors.insert(GadgetType::Comparator, vec![selector]);
+
+    GadgetConfig {
+      columns,
+      selectors,
+      tables,
+      ..gadget_config
+    }
+  }
+}
+
+impl<F: PrimeField> Gadget<F> for ComparatorChip<F> {
+  fn name(&self) -> String {
+    "comparator".to_string()
+  }
+
+  fn num_cols_per_op(&self) -> usize {
+    3
+  }
+
+  fn num_inputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  fn num_outputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  fn op_row_region(
+    &self,
+    region: &mut Region<F>,
+    row_offset: usize,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    _single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    assert_eq!(vec_inputs.len(), 2);
+    let inp1 = &vec_inputs[0];
+    let inp2 = &vec_inputs[1];
+    assert_eq!(inp1.len(), inp2.len());
+
+    if self.config.use_selectors {
+      let selector = self.config.selectors.get(&GadgetType::Comparator).unwrap()[0];
+      selector.enable(region, row_offset)?;
+    }
+
+    let min_val_pos = F::from((-self.config.shift_min_val) as u64);
+
+    let mut outp = vec![];
+    for (idx, (a, b)) in inp1.iter().zip(inp2.iter()).enumerate() {
+      let offset = idx * self.num_cols_per_op();
+      a.copy_advice(|| "", region, self.config.columns[offset + 0], row_offset)?;
+      b.copy_advice(|| "", region, self.config.columns[offset + 1], row_offset)?;
+
+      let le = a.value().zip(b.value()).map(|(a, b)| {
+        let a = convert_to_u64(&(*a + min_val_pos));
+        let b = convert_to_u64(&(*b + min_val_pos));
+        if a <= b {
+          F::ONE
+        } else {
+          F::ZERO
+        }
+      });
+
+      let res = region.assign_advice(|| "", self.config.columns[offset + 2], row_offset, || le)?;
+      outp.push(res);
+    }
+
+    Ok(outp)
+  }
+
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = &single_inputs[0];
+    let mut inp1 = vec_inputs[0].clone();
+    let mut inp2 = vec_inputs[1].clone();
+    let initial_len = inp1.len();
+    while inp1.len() % self.num_inputs_per_row() != 0 {
+      inp1.push(zero);
+      inp2.push(zero);
+    }
+
+    let vec_inputs = vec![inp1, inp2];
+    let res = self.op_aligned_rows(
+      layouter.namespace(|| format!("forward row {}", self.name())),
+      &vec_inputs,
+      single_inputs,
+    )?;
+    Ok(res[0..initial_len].to_vec())
+  }
+}
@@ -7,7 +7,7 @@ use halo2_proofs::{
   poly::Rotation,
 };
 
-use crate::gadgets::adder::AdderChip;
+use crate::{gadgets::adder::AdderChip, utils::stats::record_gadget_rows};
 
 use super::gadget::{Gadget, GadgetConfig, GadgetType};
 
@@ -194,6 +194,7 @@ impl<F: PrimeField> Gadget<F> for DotProductChip<F> {
         },
       )
       .unwrap();
+    record_gadget_rows(&self.name(), inputs.len() / self.num_inputs_per_row());
 
     let adder_chip = AdderChip::<F>::construct(self.config.clone());
     let tmp = outputs.iter().map(|x| x).collect::<Vec<_>>();
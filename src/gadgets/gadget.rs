@@ -4,31 +4,44 @@ use std::{
 };
 
 use halo2_proofs::{
-  circuit::{AssignedCell, Layouter, Region},
+  circuit::{AssignedCell, Layouter, Region, Value},
   halo2curves::group::ff::PrimeField,
   plonk::{Advice, Column, Error, Fixed, Selector, TableColumn},
 };
 use num_bigint::{BigUint, ToBigUint};
 use num_traits::cast::ToPrimitive;
 
+use crate::utils::stats::record_gadget_rows;
+
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub enum GadgetType {
   AddPairs,
   Adder,
   BiasDivRoundRelu6,
+  BiasDivRoundRelu,
   BiasDivFloorRelu6,
+  Comparator,
   DotProduct,
   Exp,
+  Gelu,
+  IntDivFloor,
+  IntDivRoundHalfEven,
+  IntDivTrunc,
+  IntDivRoundHalfAway,
   Logistic,
   Max,
   Pow,
+  PrfLimbs,
   Relu,
   Rsqrt,
+  SignedRangeCheck,
+  Silu,
   Sqrt,
   SqrtBig,
   Square,
   SquaredDiff,
   SubPairs,
+  Tabulated,
   Tanh,
   MulPairs,
   VarDivRound,
@@ -52,14 +65,44 @@ pub struct GadgetConfig {
   pub num_rows: usize,
   pub num_cols: usize,
   pub k: usize,
-  pub eta: f64,
+  pub eta: f64, // float-ok: quantized by the update gadget
   pub min_val: i64,
   pub max_val: i64,
   pub div_outp_min_val: i64,
   pub use_selectors: bool,
   pub commit_before: Vec<Vec<i64>>,
   pub commit_after: Vec<Vec<i64>>,
+  pub rlc_inputs: Vec<i64>,
+  pub hash_inputs: bool, // The hash of the inputs is the last commitment (see input_hash.rs)
+  pub random_seed: bool, // The stochastic layers hash the seed (see randomness.rs)
   pub num_bits_per_elem: i64,
+  pub tabulated_fns: Vec<Vec<i64>>,
+  pub signed_range_bits: Vec<i64>,
+  pub range_check_limb_bits: i64, // Wider range checks are split into limbs, 0 to never split
+  pub softmax_top_k: usize,       // 0 to compute the full softmax
+  pub gemm_tile_size: usize,      // Rows per tile of the matmul outputs, 0 for a single tile
+  pub activation_div: i64,        // Divides the GELU and SiLU inputs, see activation_div
+}
+
+// The shift of the accumulators of a model at the scale factor, see max_accumulator
+pub fn default_shift_min_val(scale_factor: i64) -> i64 {
+  -(scale_factor * scale_factor * (1 << 17))
+}
+
+impl GadgetConfig {
+  // Accumulators are shifted by -shift_min_val before the division gadgets, so they must lie in
+  // [shift_min_val, -shift_min_val]. With the default shift of sf^2 * 2^17, this is the bound the
+  // converter must respect for every dot product.
+  pub fn max_accumulator(&self) -> i128 {
+    -(self.shift_min_val as i128)
+  }
+
+  // Max number of multiply-accumulates (including the bias) with the given per-element bounds
+  // (in fixed point) that stay within max_accumulator()
+  pub fn max_macs(&self, max_abs_inp: i64, max_abs_weight: i64) -> u128 {
+    let per_mac = (max_abs_inp as i128 * max_abs_weight as i128).max(1);
+    (self.max_accumulator() / per_mac) as u128
+  }
 }
 
 // TODO: refactor
@@ -83,6 +126,39 @@ pub fn convert_to_u128<F: PrimeField>(x: &F) -> u128 {
   big.to_biguint().unwrap().to_u128().unwrap()
 }
 
+// Widened conversion for accumulators (e.g., dot products before the division by the scale
+// factor). The division gadgets shift them by max_accumulator (see GadgetConfig) into the
+// non-negative range, so anything outside of [-max_accumulator, max_accumulator], including a
+// value that wrapped around the field, would give a wrong witness and is an error.
+pub fn convert_to_i128_shifted<F: PrimeField>(
+  x: &F,
+  max_accumulator: i128,
+) -> Result<i128, String> {
+  assert!(max_accumulator >= 0);
+  let x_pos = *x + F::from_u128(max_accumulator as u128);
+  let big = BigUint::from_bytes_le(x_pos.to_repr().as_ref());
+  match big.to_u128() {
+    Some(val) if val <= 2 * max_accumulator as u128 => Ok(val as i128 - max_accumulator),
+    _ => Err(format!(
+      "accumulator out of range [{}, {}]",
+      -max_accumulator, max_accumulator
+    )),
+  }
+}
+
+// The value of a witness computation that can fail. halo2 errors have no message, so the error is
+// logged.
+pub fn known_or_error<V>(x: Value<Result<V, String>>) -> Result<Value<V>, Error> {
+  x.error_if_known_and(|x| match x {
+    Ok(_) => false,
+    Err(err) => {
+      error!("{}", err);
+      true
+    }
+  })?;
+  Ok(x.map(|x| x.unwrap()))
+}
+
 pub trait Gadget<F: PrimeField> {
   fn name(&self) -> String;
 
@@ -134,6 +210,10 @@ pub trait Gadget<F: PrimeField> {
         Ok(outputs)
       },
     )?;
+    record_gadget_rows(
+      &self.name(),
+      vec_inputs[0].len() / self.num_inputs_per_row(),
+    );
 
     Ok(outputs)
   }
@@ -0,0 +1,385 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region},
+  halo2curves::ff::PrimeField,
+  plonk::{Advice, Column, ConstraintSystem, Error, Expression, VirtualCells},
+  poly::Rotation,
+};
+
+use super::gadget::{convert_to_i128_shifted, known_or_error, Gadget, GadgetConfig, GadgetType};
+
+// Integer division a = q * d + r by a positive d, with the rounding of q and the sign of r of the
+// mode. Every mode range checks the remainder and the quotient with the input lookup, so the pair
+// is unique:
+//   floor:           a | q | r | ... | d, with r, d - 1 - r, and q - div_outp_min_val in the lookup
+//   trunc:           a | q | r | t | ... | d, where the bit t is 1 on the non-positive side:
+//                    |r| = r * (1 - 2t) and |q| = q * (1 - 2t), with |r|, d - 1 - |r|, and |q| in
+//                    the lookup, so r and q have the sign of a
+//   round half even: a | q | r | h | p | ... | d, where q = 2h + p and p is a bit: 2r + d - p and
+//                    d - 2r - p are in the lookup, i.e., |r| <= d / 2, strictly for an odd q, so
+//                    ties go to the even quotient, and h - div_outp_min_val / 2 is in the lookup.
+//   round half away: a | q | r | t | c | m | ... | d, with the sign bit t of trunc, s = 1 - 2t,
+//                    and the bit c that q is rounded away from the truncated quotient q - cs,
+//                    whose remainder is r + csd: m = |r + csd| = sr + cd, d - 1 - m, and
+//                    |q - cs| = sq - c are in the lookup, as is 2m - d for c = 1 and d - 1 - 2m
+//                    for c = 0, i.e., ties go away from zero (TFLite).
+// d is in [1, num_rows / 2) and a is in the accumulator range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntDivMode {
+  Floor,
+  RoundHalfEven,
+  Trunc,
+  RoundHalfAway,
+}
+
+impl IntDivMode {
+  pub fn from_param(mode: i64) -> Self {
+    match mode {
+      0 => IntDivMode::Floor,
+      1 => IntDivMode::RoundHalfEven,
+      2 => IntDivMode::Trunc,
+      3 => IntDivMode::RoundHalfAway,
+      _ => panic!("invalid division rounding mode {}", mode),
+    }
+  }
+
+  pub fn gadget_type(&self) -> GadgetType {
+    match self {
+      IntDivMode::Floor => GadgetType::IntDivFloor,
+      IntDivMode::RoundHalfEven => GadgetType::IntDivRoundHalfEven,
+      IntDivMode::Trunc => GadgetType::IntDivTrunc,
+      IntDivMode::RoundHalfAway => GadgetType::IntDivRoundHalfAway,
+    }
+  }
+
+  pub fn num_cols_per_op(&self) -> usize {
+    match self {
+      IntDivMode::Floor => 3,
+      IntDivMode::Trunc => 4,
+      IntDivMode::RoundHalfEven => 5,
+      IntDivMode::RoundHalfAway => 6,
+    }
+  }
+
+  pub fn num_range_checks(&self) -> usize {
+    match self {
+      IntDivMode::RoundHalfAway => 4,
+      _ => 3,
+    }
+  }
+
+  // (q, r) of a / d
+  pub fn div_rem(&self, a: i128, d: i128) -> (i128, i128) {
+    let q = match self {
+      IntDivMode::Floor => a.div_euclid(d),
+      IntDivMode::Trunc => a / d,
+      IntDivMode::RoundHalfEven => {
+        let (q, r) = (a.div_euclid(d), a.rem_euclid(d));
+        if 2 * r > d || (2 * r == d && q % 2 != 0) {
+          q + 1
+        } else {
+          q
+        }
+      }
+      IntDivMode::RoundHalfAway => {
+        let q = a / d;
+        if 2 * (a % d).abs() >= d {
+          q + a.signum()
+        } else {
+          q
+        }
+      }
+    };
+    (q, a - q * d)
+  }
+}
+
+fn to_field<F: PrimeField>(x: i128) -> F {
+  if x < 0 {
+    -F::from_u128(x.unsigned_abs())
+  } else {
+    F::from_u128(x as u128)
+  }
+}
+
+pub struct IntDivChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  mode: IntDivMode,
+  _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> IntDivChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>, mode: IntDivMode) -> Self {
+    Self {
+      config,
+      mode,
+      _marker: PhantomData,
+    }
+  }
+
+  // The values of an op that must be in [0, num_rows)
+  fn range_checked(
+    meta: &mut VirtualCells<F>,
+    columns: &Vec<Column<Advice>>,
+    offset: usize,
+    mode: IntDivMode,
+    div_outp_min_val: i64,
+  ) -> Vec<Expression<F>> {
+    let one = Expression::Constant(F::ONE);
+    let two = Expression::Constant(F::from(2));
+    let d = meta.query_advice(columns[columns.len() - 1], Rotation::cur());
+    let q = meta.query_advice(columns[offset + 1], Rotation::cur());
+    let r = meta.query_advice(columns[offset + 2], Rotation::cur());
+    match mode {
+      IntDivMode::Floor => {
+        let q_shift = Expression::Constant(F::from(-div_outp_min_val as u64));
+        vec![r.clone(), d - one - r, q + q_shift]
+      }
+      IntDivMode::Trunc => {
+        let t = meta.query_advice(columns[offset + 3], Rotation::cur());
+        let sign = one.clone() - two * t;
+        let abs_r = r * sign.clone();
+        vec![abs_r.clone(), d - one - abs_r, q * sign]
+      }
+      IntDivMode::RoundHalfEven => {
+        let h = meta.query_advice(columns[offset + 3], Rotation::cur());
+        let p = meta.query_advice(columns[offset + 4], Rotation::cur());
+        let h_shift = Expression::Constant(F::from(-div_outp_min_val as u64 / 2));
+        vec![
+          two.clone() * r.clone() + d.clone() - p.clone(),
+          d - two * r - p,
+          h + h_shift,
+        ]
+      }
+      IntDivMode::RoundHalfAway => {
+        let t = meta.query_advice(columns[offset + 3], Rotation::cur());
+        let c = meta.query_advice(columns[offset + 4], Rotation::cur());
+        let m = meta.query_advice(columns[offset + 5], Rotation::cur());
+        let sign = one.clone() - two.clone() * t;
+        let rounded = (two.clone() * c.clone() - one.clone()) * (two * m.clone() - d.clone())
+          + c.clone()
+          - one.clone();
+        vec![m.clone(), d - one - m, sign * q - c, rounded]
+      }
+    }
+  }
+
+  pub fn configure(
+    meta: &mut ConstraintSystem<F>,
+    gadget_config: GadgetConfig,
+    mode: IntDivMode,
+  ) -> GadgetConfig {
+    let columns = gadget_config.columns;
+    let selector = meta.complex_selector();
+    let one = Expression::Constant(F::ONE);
+    let two = Expression::Constant(F::from(2));
+    let min_val = gadget_config.div_outp_min_val;
+
+    let tables = gadget_config.tables;
+    let lookup = tables.get(&GadgetType::InputLookup).unwrap()[0];
+    let num_ops = (columns.len() - 1) / mode.num_cols_per_op();
+    let d_col = columns[columns.len() - 1];
+
+    meta.create_gate("int_div_arithm", |meta| {
+      let s = meta.query_selector(selector);
+      let d = meta.query_advice(d_col, Rotation::cur());
+      let mut constraints = vec![];
+      for i in 0..num_ops {
+        let offset = i * mode.num_cols_per_op();
+        let a = meta.query_advice(columns[offset], Rotation::cur());
+        let q = meta.query_advice(columns[offset + 1], Rotation::cur());
+        let r = meta.query_advice(columns[offset + 2], Rotation::cur());
+        constraints.push(s.clone() * (a - q.clone() * d.clone() - r.clone()));
+
+        match mode {
+          IntDivMode::Floor => {}
+          IntDivMode::Trunc => {
+            let t = meta.query_advice(columns[offset + 3], Rotation::cur());
+            constraints.push(s.clone() * t.clone() * (one.clone() - t));
+          }
+          IntDivMode::RoundHalfAway => {
+            let t = meta.query_advice(columns[offset + 3], Rotation::cur());
+            let c = meta.query_advice(columns[offset + 4], Rotation::cur());
+            let m = meta.query_advice(columns[offset + 5], Rotation::cur());
+            let sign = one.clone() - two.clone() * t.clone();
+            constraints.push(s.clone() * t.clone() * (one.clone() - t));
+            constraints.push(s.clone() * c.clone() * (one.clone() - c.clone()));
+            constraints.push(s.clone() * (m - sign * r.clone() - c * d.clone()));
+          }
+          IntDivMode::RoundHalfEven => {
+            let h = meta.query_advice(columns[offset + 3], Rotation::cur());
+            let p = meta.query_advice(columns[offset + 4], Rotation::cur());
+            constraints.push(s.clone() * (q - two.clone() * h - p.clone()));
+            constraints.push(s.clone() * p.clone() * (one.clone() - p));
+          }
+        }
+      }
+      constraints
+    });
+
+    for i in 0..num_ops {
+      let offset = i * mode.num_cols_per_op();
+      for j in 0..mode.num_range_checks() {
+        meta.lookup("int div range checks", |meta| {
+          let s = meta.query_selector(selector);
+          let checked = Self::range_checked(meta, &columns, offset, mode, min_val);
+          vec![(s * checked[j].clone(), lookup)]
+        });
+      }
+    }
+
+    let mut selectors = gadget_config.selectors;
+    selectors.insert(mode.gadget_type(), vec![selector]);
+
+    GadgetConfig {
+      columns,
+      tables,
+      selectors,
+      ..gadget_config
+    }
+  }
+}
+
+impl<F: PrimeField> Gadget<F> for IntDivChip<F> {
+  fn name(&self) -> String {
+    format!("IntDivChip {:?}", self.mode)
+  }
+
+  fn num_cols_per_op(&self) -> usize {
+    self.mode.num_cols_per_op()
+  }
+
+  fn num_inputs_per_row(&self) -> usize {
+    (self.config.columns.len() - 1) / self.num_cols_per_op()
+  }
+
+  // The quotient and the remainder of every input
+  fn num_outputs_per_row(&self) -> usize {
+    2 * self.num_inputs_per_row()
+  }
+
+  fn op_row_region(
+    &self,
+    region: &mut Region<F>,
+    row_offset: usize,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let columns = &self.config.columns;
+    let d = &single_inputs[1];
+    let max_accumulator = self.config.max_accumulator();
+
+    if self.config.use_selectors {
+      let selector = self.config.selectors.get(&self.mode.gadget_type()).unwrap()[0];
+      selector.enable(region, row_offset)?;
+    }
+
+    d.copy_advice(|| "", region, columns[columns.len() - 1], row_offset)?;
+
+    let mut outp = vec![];
+    for (i, a) in vec_inputs[0].iter().enumerate() {
+      let offset = i * self.num_cols_per_op();
+      a.copy_advice(|| "", region, columns[offset], row_offset)?;
+
+      let div_rem = a.value().zip(d.value()).map(|(a, d)| -> Result<_, String> {
+        let a = convert_to_i128_shifted(a, max_accumulator)?;
+        let d = convert_to_i128_shifted(d, max_accumulator)?;
+        Ok(self.mode.div_rem(a, d))
+      });
+      let div_rem = known_or_error(div_rem)?;
+      let q = region.assign_advice(
+        || "",
+        columns[offset + 1],
+        row_offset,
+        || div_rem.map(|(q, _)| to_field::<F>(q)),
+      )?;
+      let r = region.assign_advice(
+        || "",
+        columns[offset + 2],
+        row_offset,
+        || div_rem.map(|(_, r)| to_field::<F>(r)),
+      )?;
+      match self.mode {
+        IntDivMode::Floor => {}
+        IntDivMode::Trunc => {
+          region.assign_advice(
+            || "",
+            columns[offset + 3],
+            row_offset,
+            || div_rem.map(|(q, r)| F::from((q < 0 || r < 0) as u64)),
+          )?;
+        }
+        IntDivMode::RoundHalfEven => {
+          region.assign_advice(
+            || "",
+            columns[offset + 3],
+            row_offset,
+            || div_rem.map(|(q, _)| to_field::<F>(q.div_euclid(2))),
+          )?;
+          region.assign_advice(
+            || "",
+            columns[offset + 4],
+            row_offset,
+            || div_rem.map(|(q, _)| F::from(q.rem_euclid(2) as u64)),
+          )?;
+        }
+        IntDivMode::RoundHalfAway => {
+          // q has the sign of a unless it is zero, then r has it
+          region.assign_advice(
+            || "",
+            columns[offset + 3],
+            row_offset,
+            || div_rem.map(|(q, r)| F::from((q < 0 || (q == 0 && r < 0)) as u64)),
+          )?;
+          // Rounded away iff r is on the other side of zero than q, then the truncated
+          // remainder is r + sd, and m = d - |r|
+          let away = div_rem.map(|(q, r)| q != 0 && r != 0 && (q < 0) != (r < 0));
+          region.assign_advice(
+            || "",
+            columns[offset + 4],
+            row_offset,
+            || away.map(|away| F::from(away as u64)),
+          )?;
+          region.assign_advice(
+            || "",
+            columns[offset + 5],
+            row_offset,
+            || {
+              div_rem.zip(away).zip(d.value()).map(|(((_, r), away), d)| {
+                let d = convert_to_i128_shifted(d, max_accumulator).unwrap();
+                to_field::<F>(if away { d - r.abs() } else { r.abs() })
+              })
+            },
+          )?;
+        }
+      }
+      outp.push(q);
+      outp.push(r);
+    }
+
+    Ok(outp)
+  }
+
+  // Returns the quotients, then the remainders
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let mut inps = vec_inputs[0].clone();
+    let initial_len = inps.len();
+
+    // Pads with zero, whose quotient and remainder are zero
+    let default = &single_inputs[0];
+    while inps.len() % self.num_inputs_per_row() != 0 {
+      inps.push(&default);
+    }
+
+    let res = self.op_aligned_rows(layouter.namespace(|| "int_div"), &vec![inps], single_inputs)?;
+    let quotients = res.iter().step_by(2).take(initial_len);
+    let remainders = res.iter().skip(1).step_by(2).take(initial_len);
+    Ok(quotients.chain(remainders).cloned().collect())
+  }
+}
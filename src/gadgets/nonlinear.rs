@@ -1,8 +1,13 @@
 pub mod exp;
+pub mod gelu;
 pub mod logistic;
 pub mod non_linearity;
 pub mod pow;
+pub mod pwl;
 pub mod relu;
 pub mod rsqrt;
+pub mod silu;
 pub mod sqrt;
+pub mod tabulated;
 pub mod tanh;
+pub mod unary;
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region},
+  halo2curves::ff::PrimeField,
+  plonk::{ConstraintSystem, Error},
+};
+
+use super::{
+  super::gadget::{Gadget, GadgetConfig, GadgetType},
+  non_linearity::{activation_map, NonLinearGadget},
+};
+
+// GELU, x * Phi(x), with the exact (erf) form of PyTorch and ONNX
+pub struct GeluGadgetChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  _marker: PhantomData<F>,
+}
+
+// Abramowitz and Stegun 7.1.26, within 1.5e-7, well below the resolution of the tables
+fn erf(x: f64) -> f64 {
+  let t = 1. / (1. + 0.3275911 * x.abs());
+  let poly = t
+    * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+  (1. - poly * (-x * x).exp()).copysign(x)
+}
+
+impl<F: PrimeField> GeluGadgetChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>) -> Self {
+    Self {
+      config,
+      _marker: PhantomData,
+    }
+  }
+
+  pub fn gelu(x: f64) -> f64 {
+    0.5 * x * (1. + erf(x / std::f64::consts::SQRT_2))
+  }
+
+  // The table of the configured precision replaces the full precision one
+  pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    let div = gadget_config.activation_div.max(1);
+    let mut gadget_config =
+      <GeluGadgetChip<F> as NonLinearGadget<F>>::configure(meta, gadget_config, GadgetType::Gelu);
+    let map = activation_map(
+      Self::gelu,
+      gadget_config.scale_factor,
+      gadget_config.min_val,
+      gadget_config.num_rows as i64,
+      div,
+    );
+    gadget_config.maps.insert(GadgetType::Gelu, vec![map]);
+    gadget_config
+  }
+}
+
+impl<F: PrimeField> NonLinearGadget<F> for GeluGadgetChip<F> {
+  fn generate_map(scale_factor: u64, min_val: i64, num_rows: i64) -> HashMap<i64, i64> {
+    activation_map(Self::gelu, scale_factor, min_val, num_rows, 1)
+  }
+
+  fn get_map(&self) -> &HashMap<i64, i64> {
+    &self.config.maps.get(&GadgetType::Gelu).unwrap()[0]
+  }
+
+  fn get_selector(&self) -> halo2_proofs::plonk::Selector {
+    self.config.selectors.get(&GadgetType::Gelu).unwrap()[0]
+  }
+}
+
+impl<F: PrimeField> Gadget<F> for GeluGadgetChip<F> {
+  fn name(&self) -> String {
+    "GeluGadgetChip".to_string()
+  }
+
+  fn num_cols_per_op(&self) -> usize {
+    <GeluGadgetChip<F> as NonLinearGadget<F>>::num_cols_per_op()
+  }
+
+  fn num_inputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  fn num_outputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  fn load_lookups(&self, layouter: impl Layouter<F>) -> Result<(), Error> {
+    NonLinearGadget::load_lookups(self, layouter, self.config.clone(), GadgetType::Gelu)?;
+    Ok(())
+  }
+
+  fn op_row_region(
+    &self,
+    region: &mut Region<F>,
+    row_offset: usize,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    NonLinearGadget::op_row_region(
+      self,
+      region,
+      row_offset,
+      vec_inputs,
+      single_inputs,
+      self.config.clone(),
+    )
+  }
+
+  fn forward(
+    &self,
+    layouter: impl halo2_proofs::circuit::Layouter<F>,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    NonLinearGadget::forward(self, layouter, vec_inputs, single_inputs)
+  }
+}
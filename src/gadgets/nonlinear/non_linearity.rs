@@ -14,6 +14,40 @@ use super::super::gadget::{GadgetConfig, GadgetType};
 
 const NUM_COLS_PER_OP: usize = 2;
 
+// The divisor of the GELU and SiLU inputs before their lookups, which rounds them to table_bits
+// fractional bits, so the tables cover 2^(log2(sf) - table_bits) times the input range. The scale
+// factor must be a multiple of 2^table_bits, and the default is the full precision.
+pub fn activation_div(scale_factor: i64, table_bits: Option<i64>) -> Result<i64, String> {
+  let table_bits = match table_bits {
+    Some(table_bits) => table_bits,
+    None => return Ok(1),
+  };
+  if !(0..63).contains(&table_bits) || scale_factor % (1 << table_bits) != 0 {
+    return Err(format!(
+      "the scale factor {} has no {} bit activation tables",
+      scale_factor, table_bits
+    ));
+  }
+  Ok(scale_factor >> table_bits)
+}
+
+// The table of f over the inputs rounded by div, with the outputs at the scale factor
+pub fn activation_map(
+  f: impl Fn(f64) -> f64,
+  scale_factor: u64,
+  min_val: i64,
+  num_rows: i64,
+  div: i64,
+) -> HashMap<i64, i64> {
+  let scale_factor = scale_factor as f64;
+  let mut map = HashMap::new();
+  for i in 0..num_rows {
+    let x = ((i + min_val) * div) as f64 / scale_factor;
+    map.insert(i, (f(x) * scale_factor).round() as i64);
+  }
+  map
+}
+
 pub trait NonLinearGadget<F: PrimeField>: Gadget<F> {
   fn generate_map(scale_factor: u64, min_val: i64, num_rows: i64) -> HashMap<i64, i64>;
 
@@ -0,0 +1,84 @@
+// The PiecewiseLinear layer: a scalar function given by its knots, which the converter fits to an
+// error bound (_fit_pwl in converter.py). It has no constraints of its own: the knots are compiled
+// into the table of the Tabulated layer when the circuit is built, so the layer costs a lookup
+// over the fixed-point grid between the first and the last knot.
+#[derive(Clone, Debug, Default)]
+pub struct PiecewiseLinear {
+  pub knots_x: Vec<f64>,
+  pub knots_y: Vec<f64>,
+}
+
+impl PiecewiseLinear {
+  // Params are [num_knots, x_0, ..., x_{n-1}, y_0, ..., y_{n-1}] in fixed-point
+  pub fn from_params(params: &Vec<i64>, scale_factor: u64) -> Result<Self, String> {
+    let n = match params.first() {
+      Some(n) if *n >= 2 => *n as usize,
+      _ => return Err("a piecewise linear function needs at least two knots".to_string()),
+    };
+    if params.len() != 1 + 2 * n {
+      return Err(format!(
+        "a piecewise linear function with {} knots needs {} params, not {}",
+        n,
+        1 + 2 * n,
+        params.len()
+      ));
+    }
+    if params[1..1 + n].windows(2).any(|w| w[0] >= w[1]) {
+      return Err("the knots must be increasing".to_string());
+    }
+    let sf = scale_factor as f64;
+    let knots_x = params[1..1 + n]
+      .iter()
+      .map(|x| *x as f64 / sf)
+      .collect::<Vec<_>>();
+    let knots_y = params[1 + n..]
+      .iter()
+      .map(|y| *y as f64 / sf)
+      .collect::<Vec<_>>();
+    Ok(Self { knots_x, knots_y })
+  }
+
+  // Constant extension outside of the knots
+  pub fn eval(&self, x: f64) -> f64 {
+    let n = self.knots_x.len();
+    if x <= self.knots_x[0] {
+      return self.knots_y[0];
+    }
+    if x >= self.knots_x[n - 1] {
+      return self.knots_y[n - 1];
+    }
+    let idx = self.knots_x.partition_point(|k| *k <= x);
+    let (x0, x1) = (self.knots_x[idx - 1], self.knots_x[idx]);
+    let (y0, y1) = (self.knots_y[idx - 1], self.knots_y[idx]);
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+  }
+
+  fn eval_fixed(&self, x: i64, scale_factor: u64) -> i64 {
+    let sf = scale_factor as f64;
+    (self.eval(x as f64 / sf) * sf).round() as i64
+  }
+
+  // The fixed-point inputs covered by the table
+  fn domain(&self, scale_factor: u64) -> (i64, i64) {
+    let sf = scale_factor as f64;
+    let n = self.knots_x.len();
+    let x_start = (self.knots_x[0] * sf).ceil() as i64;
+    let x_end = (self.knots_x[n - 1] * sf).floor() as i64;
+    (x_start, x_end)
+  }
+
+  pub fn table_len(&self, scale_factor: u64) -> u64 {
+    let (x_start, x_end) = self.domain(scale_factor);
+    (x_end as i128 - x_start as i128 + 1) as u64
+  }
+
+  // Same layout as the Tabulated layer params, covering the knots
+  pub fn to_table(&self, scale_factor: u64) -> Vec<i64> {
+    let (x_start, x_end) = self.domain(scale_factor);
+    let mut table = vec![x_start];
+    for x in x_start..x_end + 1 {
+      table.push(self.eval_fixed(x, scale_factor));
+    }
+    table
+  }
+}
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region, Value},
+  halo2curves::ff::PrimeField,
+  plonk::{ConstraintSystem, Error, Expression, Selector},
+  poly::Rotation,
+};
+
+use crate::gadgets::gadget::convert_to_u128;
+
+use super::super::gadget::{Gadget, GadgetConfig, GadgetType};
+
+const NUM_COLS_PER_OP: usize = 2;
+
+// A pointwise function given explicitly by the converter as [x_start, y_0, y_1, ...], where
+// y_i = f(x_start + i). Inputs outside of the table are clamped to the first/last entry.
+// Every distinct table gets its own selector and lookup table column, indexed by table_idx.
+pub struct TabulatedGadgetChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  table_idx: usize,
+  _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> TabulatedGadgetChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>, table_idx: usize) -> Self {
+    Self {
+      config,
+      table_idx,
+      _marker: PhantomData,
+    }
+  }
+
+  pub fn generate_map(table: &Vec<i64>, min_val: i64, num_rows: i64) -> HashMap<i64, i64> {
+    assert!(
+      table.len() >= 2,
+      "tabulated function must have at least one entry"
+    );
+    let x_start = table[0];
+    let ys = &table[1..];
+
+    let mut map = HashMap::new();
+    for i in 0..num_rows {
+      let shifted = i + min_val;
+      let idx = (shifted - x_start).max(0).min(ys.len() as i64 - 1);
+      map.insert(i as i64, ys[idx as usize]);
+    }
+
+    map
+  }
+
+  pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    let columns = gadget_config.columns;
+
+    let mut tables = gadget_config.tables;
+    let inp_lookup = tables.get(&GadgetType::InputLookup).unwrap()[0];
+
+    let mut selectors = vec![];
+    let mut table_cols = vec![inp_lookup];
+    let mut maps = vec![];
+    for table in gadget_config.tabulated_fns.iter() {
+      let selector = meta.complex_selector();
+      let outp_lookup = meta.lookup_table_column();
+
+      for op_idx in 0..columns.len() / NUM_COLS_PER_OP {
+        let offset = op_idx * NUM_COLS_PER_OP;
+        meta.lookup("tabulated lookup", |meta| {
+          let s = meta.query_selector(selector);
+          let inp = meta.query_advice(columns[offset + 0], Rotation::cur());
+          let outp = meta.query_advice(columns[offset + 1], Rotation::cur());
+          let shift_val = gadget_config.min_val;
+          let shift_val_pos = Expression::Constant(F::from((-shift_val) as u64));
+
+          vec![
+            (s.clone() * (inp + shift_val_pos), inp_lookup),
+            (s.clone() * outp, outp_lookup),
+          ]
+        });
+      }
+
+      selectors.push(selector);
+      table_cols.push(outp_lookup);
+      maps.push(Self::generate_map(
+        table,
+        gadget_config.min_val,
+        gadget_config.num_rows as i64,
+      ));
+    }
+
+    let mut all_selectors = gadget_config.selectors;
+    all_selectors.insert(GadgetType::Tabulated, selectors);
+
+    tables.insert(GadgetType::Tabulated, table_cols);
+
+    let mut all_maps = gadget_config.maps;
+    all_maps.insert(GadgetType::Tabulated, maps);
+
+    GadgetConfig {
+      columns,
+      selectors: all_selectors,
+      tables,
+      maps: all_maps,
+      ..gadget_config
+    }
+  }
+
+  fn get_map(&self, table_idx: usize) -> &HashMap<i64, i64> {
+    &self.config.maps.get(&GadgetType::Tabulated).unwrap()[table_idx]
+  }
+
+  fn get_selector(&self) -> Selector {
+    self.config.selectors.get(&GadgetType::Tabulated).unwrap()[self.table_idx]
+  }
+
+  fn to_field(val: i64, shift_pos_i64: i64) -> F {
+    if val >= 0 {
+      F::from(val as u64)
+    } else {
+      F::from((val + shift_pos_i64) as u64) - F::from(shift_pos_i64 as u64)
+    }
+  }
+}
+
+impl<F: PrimeField> Gadget<F> for TabulatedGadgetChip<F> {
+  fn name(&self) -> String {
+    format!("TabulatedGadgetChip {}", self.table_idx)
+  }
+
+  fn num_cols_per_op(&self) -> usize {
+    NUM_COLS_PER_OP
+  }
+
+  fn num_inputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  fn num_outputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.num_cols_per_op()
+  }
+
+  // Loads all of the tables at once, so only needs to be called on one chip
+  fn load_lookups(&self, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+    let config = &self.config;
+    let shift_pos_i64 = -config.shift_min_val;
+    let num_tables = config.tabulated_fns.len();
+
+    for table_idx in 0..num_tables {
+      let map = self.get_map(table_idx);
+      let table_col = config.tables.get(&GadgetType::Tabulated).unwrap()[table_idx + 1];
+      layouter.assign_table(
+        || format!("tabulated table {}", table_idx),
+        |mut table| {
+          for i in 0..config.num_rows {
+            let i = i as i64;
+            let val = if i == 0 {
+              F::ZERO
+            } else {
+              Self::to_field(*map.get(&i).unwrap(), shift_pos_i64)
+            };
+            table.assign_cell(
+              || "tabulated cell",
+              table_col,
+              i as usize,
+              || Value::known(val),
+            )?;
+          }
+          Ok(())
+        },
+      )?;
+    }
+    Ok(())
+  }
+
+  fn op_row_region(
+    &self,
+    region: &mut Region<F>,
+    row_offset: usize,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    _single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let columns = &self.config.columns;
+    let inp = &vec_inputs[0];
+    let map = self.get_map(self.table_idx);
+    let shift_val_pos_i64 = -self.config.shift_min_val;
+    let shift_val_pos = F::from(shift_val_pos_i64 as u64);
+    let min_val = self.config.min_val;
+
+    if self.config.use_selectors {
+      let selector = self.get_selector();
+      selector.enable(region, row_offset)?;
+    }
+
+    let mut outps = vec![];
+    for i in 0..inp.len() {
+      let offset = i * NUM_COLS_PER_OP;
+      inp[i].copy_advice(|| "", region, columns[offset + 0], row_offset)?;
+      let outp = inp[i].value().map(|x: &F| {
+        let pos = convert_to_u128(&(*x + shift_val_pos)) as i128 - shift_val_pos_i64 as i128;
+        let x = pos as i64 - min_val;
+        if x == 0 {
+          F::ZERO
+        } else {
+          Self::to_field(*map.get(&x).unwrap(), shift_val_pos_i64)
+        }
+      });
+
+      let outp = region.assign_advice(|| "tabulated", columns[offset + 1], row_offset, || outp)?;
+      outps.push(outp);
+    }
+
+    Ok(outps)
+  }
+
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = &single_inputs[0];
+    let inp_len = vec_inputs[0].len();
+    let mut inp = vec_inputs[0].clone();
+
+    while inp.len() % self.num_inputs_per_row() != 0 {
+      inp.push(zero);
+    }
+
+    let vec_inputs = vec![inp];
+    let outp = self.op_aligned_rows(
+      layouter.namespace(|| format!("forward row {}", self.name())),
+      &vec_inputs,
+      &single_inputs,
+    )?;
+
+    Ok(outp[0..inp_len].to_vec())
+  }
+}
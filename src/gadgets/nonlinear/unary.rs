@@ -0,0 +1,61 @@
+// Pointwise functions given as f64 -> f64 closures, e.g., exp for the softmax, and ln and the
+// reciprocal for normalizations. Like the piecewise linear functions (see pwl.rs), they are
+// compiled into tables for the Tabulated layer when the circuit is built, so any function only
+// needs a domain and a table size. Params: [table size], or [] for a table over the whole input
+// lookup. The table starts at the smallest positive input for the functions of positive inputs,
+// and is centered at zero otherwise; inputs outside of it are clamped to the first/last entry.
+// The outputs saturate at the range of the input lookup, so later layers can take them.
+pub struct UnaryFunction {
+  f: Box<dyn Fn(f64) -> f64>,
+  positive: bool,
+}
+
+impl UnaryFunction {
+  pub fn new(f: impl Fn(f64) -> f64 + 'static, positive: bool) -> Self {
+    Self {
+      f: Box::new(f),
+      positive,
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "Exp" => Some(Self::new(f64::exp, false)),
+      "Ln" => Some(Self::new(f64::ln, true)),
+      "Reciprocal" => Some(Self::new(|x| 1. / x, true)),
+      _ => None,
+    }
+  }
+
+  // Same layout as the Tabulated layer params. The input lookup covers [-2^(k - 1), 2^(k - 1)).
+  pub fn to_table(&self, params: &Vec<i64>, scale_factor: u64, k: usize) -> Vec<i64> {
+    let half = 1i64 << (k - 1);
+    let (lo, hi) = if self.positive {
+      (1, half)
+    } else {
+      (-half, half)
+    };
+    let size = match params.get(0) {
+      Some(size) => {
+        assert!(*size >= 1, "the table needs at least one entry");
+        (*size).min(hi - lo)
+      }
+      None => hi - lo,
+    };
+    let x_start = if self.positive { lo } else { -(size / 2) };
+
+    let sf = scale_factor as f64;
+    let mut table = vec![x_start];
+    for x in x_start..x_start + size {
+      let y = ((self.f)(x as f64 / sf) * sf).round();
+      // The infinities saturate too, NaN is zero
+      let y = if y.is_nan() {
+        0
+      } else {
+        y.clamp(-half as f64, (half - 1) as f64) as i64
+      };
+      table.push(y);
+    }
+    table
+  }
+}
@@ -0,0 +1,235 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region},
+  halo2curves::ff::PrimeField,
+  plonk::{ConstraintSystem, Error, Expression},
+  poly::Rotation,
+};
+use num_bigint::BigUint;
+
+use super::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  sub_pairs::SubPairsChip,
+};
+
+// Splits the outputs of the PRF (see prf.rs) into uniforms of b = k - 1 bits, the widest that the
+// input lookup bounds. A hash r gives the n = 128 / b lowest limbs of its canonical representative:
+// r = l_0 + 2^b l_1 + ... + 2^(nb) q, with every limb in [0, 2^b) and q < Q = p >> (nb), which
+// makes the limbs unique. q < Q holds if q and Q - 1 - q both have at most top_limbs(b, n) limbs.
+// A hash with q = Q can't be split, which happens with a probability below 2^(nb - 253).
+// A decomposition is a running sum over rows of [z, limbs...]:
+// z = sum_j l_j 2^(jb) + 2^(mb) z_next, and the last row has only the remaining limbs. The z after the last row is the rest.
+pub struct PrfLimbsChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  _marker: PhantomData<F>,
+}
+
+pub fn uniform_bits(k: usize) -> usize {
+  k - 1
+}
+
+pub fn uniforms_per_hash(bits: usize) -> usize {
+  (128 / bits).max(1)
+}
+
+fn modulus<F: PrimeField>() -> BigUint {
+  BigUint::from_bytes_le((-F::ONE).to_repr().as_ref()) + 1u64
+}
+
+fn top_limbs<F: PrimeField>(bits: usize, n: usize) -> usize {
+  (F::NUM_BITS as usize - n * bits + bits - 1) / bits
+}
+
+fn to_field<F: PrimeField>(x: &BigUint) -> F {
+  F::from_str_vartime(&x.to_str_radix(10)).unwrap()
+}
+
+fn pow2<F: PrimeField>(exp: usize) -> F {
+  F::from(2).pow_vartime([exp as u64])
+}
+
+impl<F: PrimeField> PrfLimbsChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>) -> Self {
+    Self {
+      config,
+      _marker: PhantomData,
+    }
+  }
+
+  // The limbs of the two decompositions: the uniforms of a hash, and q or Q - 1 - q
+  fn limb_counts(config: &GadgetConfig) -> [usize; 2] {
+    let bits = uniform_bits(config.k);
+    let n = uniforms_per_hash(bits);
+    [n, top_limbs::<F>(bits, n)]
+  }
+
+  pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    let columns = &gadget_config.columns;
+    let inp_lookup = gadget_config.tables.get(&GadgetType::InputLookup).unwrap()[0];
+    let bits = uniform_bits(gadget_config.k);
+    let n = uniforms_per_hash(bits);
+    let limbs_per_row = columns.len() - 1;
+    assert!(limbs_per_row >= 1, "the PRF limbs need two columns");
+    assert!((1usize << bits) <= gadget_config.num_rows);
+    // Q - 1 - q can't wrap around the field
+    let max_top = BigUint::from(1u64) << (top_limbs::<F>(bits, n) * bits);
+    assert!(max_top + (modulus::<F>() >> (n * bits)) < modulus::<F>());
+
+    // The first selector is on the full rows, the others on the last row of a decomposition
+    let mut selectors = vec![meta.complex_selector()];
+    let mut row_limbs = vec![limbs_per_row];
+    for count in Self::limb_counts(&gadget_config) {
+      selectors.push(meta.complex_selector());
+      row_limbs.push((count - 1) % limbs_per_row + 1);
+    }
+
+    for (selector, num_limbs) in selectors.iter().zip(row_limbs.iter()) {
+      let num_limbs = *num_limbs;
+      meta.create_gate("prf limbs", |meta| {
+        let s = meta.query_selector(*selector);
+        let z = meta.query_advice(columns[0], Rotation::cur());
+        let z_next = meta.query_advice(columns[0], Rotation::next());
+        let mut sum = z_next * Expression::Constant(pow2::<F>(num_limbs * bits));
+        for (j, col) in columns[1..1 + num_limbs].iter().enumerate() {
+          let limb = meta.query_advice(*col, Rotation::cur());
+          sum = sum + limb * Expression::Constant(pow2::<F>(j * bits));
+        }
+        vec![s * (z - sum)]
+      });
+    }
+
+    // limb and limb + num_rows - 2^b are both in [0, num_rows) iff limb is in [0, 2^b)
+    let shift = F::from(gadget_config.num_rows as u64 - (1u64 << bits));
+    for (j, col) in columns[1..].iter().enumerate() {
+      let active = selectors
+        .iter()
+        .zip(row_limbs.iter())
+        .filter(|(_, num_limbs)| **num_limbs > j)
+        .map(|(selector, _)| *selector)
+        .collect::<Vec<_>>();
+      meta.lookup("prf limb lower", |meta| {
+        let s = active.iter().fold(Expression::Constant(F::ZERO), |acc, x| {
+          acc + meta.query_selector(*x)
+        });
+        let limb = meta.query_advice(*col, Rotation::cur());
+        vec![(s * limb, inp_lookup)]
+      });
+      meta.lookup("prf limb upper", |meta| {
+        let s = active.iter().fold(Expression::Constant(F::ZERO), |acc, x| {
+          acc + meta.query_selector(*x)
+        });
+        let limb = meta.query_advice(*col, Rotation::cur());
+        vec![(s * (limb + Expression::Constant(shift)), inp_lookup)]
+      });
+    }
+
+    let mut selectors_map = gadget_config.selectors;
+    selectors_map.insert(GadgetType::PrfLimbs, selectors);
+
+    GadgetConfig {
+      selectors: selectors_map,
+      ..gadget_config
+    }
+  }
+
+  // Decomposes x from the row into the limbs of the count_idx-th limb count. Returns the limbs,
+  // the rest, and the next free row.
+  fn decompose(
+    &self,
+    region: &mut Region<F>,
+    row: usize,
+    x: &AssignedCell<F, F>,
+    count_idx: usize,
+  ) -> Result<(Vec<AssignedCell<F, F>>, AssignedCell<F, F>, usize), Error> {
+    let columns = &self.config.columns;
+    let bits = uniform_bits(self.config.k);
+    let num_limbs = Self::limb_counts(&self.config)[count_idx];
+    let limbs_per_row = columns.len() - 1;
+    let num_rows = (num_limbs + limbs_per_row - 1) / limbs_per_row;
+    let selectors = self.config.selectors.get(&GadgetType::PrfLimbs).unwrap();
+
+    let big = x
+      .value()
+      .map(|x| BigUint::from_bytes_le(x.to_repr().as_ref()));
+    let mask = (BigUint::from(1u64) << bits) - 1u64;
+    x.copy_advice(|| "", region, columns[0], row)?;
+    let mut limbs = vec![];
+    for i in 0..num_rows {
+      if self.config.use_selectors {
+        let selector = if i + 1 < num_rows {
+          selectors[0]
+        } else {
+          selectors[1 + count_idx]
+        };
+        selector.enable(region, row + i)?;
+      }
+      if i > 0 {
+        let z = big
+          .as_ref()
+          .map(|x| to_field::<F>(&(x >> (i * limbs_per_row * bits))));
+        region.assign_advice(|| "", columns[0], row + i, || z)?;
+      }
+      for j in 0..limbs_per_row.min(num_limbs - i * limbs_per_row) {
+        let pos = i * limbs_per_row + j;
+        let limb = big
+          .as_ref()
+          .map(|x| to_field::<F>(&((x >> (pos * bits)) & &mask)));
+        limbs.push(region.assign_advice(|| "", columns[1 + j], row + i, || limb)?);
+      }
+    }
+    let rest = big.map(|x| to_field::<F>(&(x >> (num_limbs * bits))));
+    let rest = region.assign_advice(|| "", columns[0], row + num_rows, || rest)?;
+    Ok((limbs, rest, row + num_rows + 1))
+  }
+
+  // The uniforms of the hashes, uniforms_per_hash from each, lowest first
+  pub fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    hashes: &Vec<&AssignedCell<F, F>>,
+    zero: &AssignedCell<F, F>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let bits = uniform_bits(self.config.k);
+    let n = uniforms_per_hash(bits);
+    let bound = to_field::<F>(&((modulus::<F>() >> (n * bits)) - 1u64));
+
+    let (uniforms, tops, bound) = layouter.assign_region(
+      || "prf limbs",
+      |mut region| {
+        let (mut uniforms, mut tops, mut row) = (vec![], vec![], 0);
+        for hash in hashes.iter() {
+          let (limbs, top, next_row) = self.decompose(&mut region, row, hash, 0)?;
+          uniforms.extend(limbs);
+          tops.push(top);
+          row = next_row;
+        }
+        let bound =
+          region.assign_advice_from_constant(|| "", self.config.columns[0], row, bound)?;
+        Ok((uniforms, tops, bound))
+      },
+    )?;
+
+    let sub_pairs_chip = SubPairsChip::<F>::construct(self.config.clone());
+    let slacks = sub_pairs_chip.forward(
+      layouter.namespace(|| "prf limbs slack"),
+      &vec![vec![&bound; tops.len()], tops.iter().collect()],
+      &vec![zero],
+    )?;
+
+    layouter.assign_region(
+      || "prf limbs bound",
+      |mut region| {
+        let mut row = 0;
+        for x in tops.iter().chain(slacks.iter()) {
+          let (_, rest, next_row) = self.decompose(&mut region, row, x, 1)?;
+          region.constrain_equal(rest.cell(), zero.cell())?;
+          row = next_row;
+        }
+        Ok(())
+      },
+    )?;
+
+    Ok(uniforms)
+  }
+}
@@ -0,0 +1,261 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter, Region},
+  halo2curves::ff::PrimeField,
+  plonk::{ConstraintSystem, Error, Expression, Selector},
+  poly::Rotation,
+};
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+
+use super::gadget::{Gadget, GadgetConfig, GadgetType};
+
+// Checks that x is in [-2^(b-1), 2^(b-1)), i.e., that x is a b-bit two's complement integer.
+// Uses two lookups into the input lookup: x + 2^(b-1) and 2^(b-1) - 1 - x must both be in
+// [0, num_rows). There is one selector per bit width in signed_range_bits.
+// With range_check_limb_bits = l, wider checks are decomposed instead: x + 2^(b-1) is the sum of
+// ceil(b / l) limbs of l bits (the top limb has the remaining bits), and each limb is bounded
+// with the same pair of lookups. The limbs sit next to x, so a row checks fewer elements, but b
+// can exceed k.
+pub struct SignedRangeCheckChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  bits_idx: usize,
+  _marker: PhantomData<F>,
+}
+
+// The number of limbs of a b-bit range check, 1 if it uses a single lookup
+fn num_limbs(config: &GadgetConfig, num_bits: i64) -> usize {
+  let limb_bits = config.range_check_limb_bits;
+  if limb_bits == 0 || num_bits <= limb_bits {
+    1
+  } else {
+    ((num_bits + limb_bits - 1) / limb_bits) as usize
+  }
+}
+
+impl<F: PrimeField> SignedRangeCheckChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>, num_bits: i64) -> Self {
+    let bits_idx = config
+      .signed_range_bits
+      .iter()
+      .position(|x| *x == num_bits)
+      .expect("range check bit width was not configured");
+    Self {
+      config,
+      bits_idx,
+      _marker: PhantomData,
+    }
+  }
+
+  fn num_limbs(&self) -> usize {
+    num_limbs(&self.config, self.config.signed_range_bits[self.bits_idx])
+  }
+
+  // The columns per checked element: the element, then its limbs if it is decomposed
+  fn group_size(&self) -> usize {
+    match self.num_limbs() {
+      1 => 1,
+      limbs => limbs + 1,
+    }
+  }
+
+  fn configure_limbs(
+    meta: &mut ConstraintSystem<F>,
+    gadget_config: &GadgetConfig,
+    num_bits: i64,
+  ) -> Selector {
+    let columns = &gadget_config.columns;
+    let inp_lookup = gadget_config.tables.get(&GadgetType::InputLookup).unwrap()[0];
+    let limb_bits = gadget_config.range_check_limb_bits;
+    let limbs = num_limbs(gadget_config, num_bits);
+    let top_bits = num_bits - limb_bits * (limbs as i64 - 1);
+    assert!(
+      (1 << limb_bits) <= gadget_config.num_rows,
+      "{} bit range check limbs do not fit in the input lookup",
+      limb_bits
+    );
+    assert!(
+      columns.len() > limbs,
+      "a {} bit range check needs more than {} columns",
+      num_bits,
+      limbs
+    );
+    let half = F::from_u128(1u128 << (num_bits - 1));
+    let selector = meta.complex_selector();
+
+    for group in columns.chunks_exact(limbs + 1) {
+      meta.create_gate("signed range check limbs", |meta| {
+        let s = meta.query_selector(selector);
+        let x = meta.query_advice(group[0], Rotation::cur());
+        let mut sum = Expression::Constant(F::ZERO);
+        for (i, col) in group[1..].iter().enumerate() {
+          let limb = meta.query_advice(*col, Rotation::cur());
+          let base = F::from_u128(1u128 << (limb_bits * i as i64));
+          sum = sum + limb * Expression::Constant(base);
+        }
+        vec![s * (x + Expression::Constant(half) - sum)]
+      });
+      for (i, col) in group[1..].iter().enumerate() {
+        let bits = if i == limbs - 1 { top_bits } else { limb_bits };
+        // limb and limb + num_rows - 2^bits are both in [0, num_rows) iff limb is in [0, 2^bits)
+        let shift = F::from(gadget_config.num_rows as u64 - (1u64 << bits));
+        meta.lookup("signed range check limb lower", |meta| {
+          let s = meta.query_selector(selector);
+          let limb = meta.query_advice(*col, Rotation::cur());
+          vec![(s * limb, inp_lookup)]
+        });
+        meta.lookup("signed range check limb upper", |meta| {
+          let s = meta.query_selector(selector);
+          let limb = meta.query_advice(*col, Rotation::cur());
+          vec![(s * (limb + Expression::Constant(shift)), inp_lookup)]
+        });
+      }
+    }
+    selector
+  }
+
+  pub fn configure(meta: &mut ConstraintSystem<F>, gadget_config: GadgetConfig) -> GadgetConfig {
+    let columns = &gadget_config.columns;
+    let inp_lookup = gadget_config.tables.get(&GadgetType::InputLookup).unwrap()[0];
+
+    let mut range_selectors = vec![];
+    for num_bits in gadget_config.signed_range_bits.iter() {
+      assert!(*num_bits >= 1);
+      if num_limbs(&gadget_config, *num_bits) > 1 {
+        range_selectors.push(Self::configure_limbs(meta, &gadget_config, *num_bits));
+        continue;
+      }
+      assert!(
+        (1 << num_bits) <= gadget_config.num_rows,
+        "{} bit range check does not fit in the input lookup",
+        num_bits
+      );
+      let half = 1u64 << (num_bits - 1);
+      let selector = meta.complex_selector();
+
+      for col in columns.iter() {
+        meta.lookup("signed range check lower", |meta| {
+          let s = meta.query_selector(selector);
+          let x = meta.query_advice(*col, Rotation::cur());
+          vec![(s * (x + Expression::Constant(F::from(half))), inp_lookup)]
+        });
+        meta.lookup("signed range check upper", |meta| {
+          let s = meta.query_selector(selector);
+          let x = meta.query_advice(*col, Rotation::cur());
+          vec![(
+            s * (Expression::Constant(F::from(half - 1)) - x),
+            inp_lookup,
+          )]
+        });
+      }
+      range_selectors.push(selector);
+    }
+
+    let mut selectors = gadget_config.selectors;
+    selectors.insert(GadgetType::SignedRangeCheck, range_selectors);
+
+    GadgetConfig {
+      selectors,
+      ..gadget_config
+    }
+  }
+}
+
+impl<F: PrimeField> Gadget<F> for SignedRangeCheckChip<F> {
+  fn name(&self) -> String {
+    "SignedRangeCheck".to_string()
+  }
+
+  fn num_cols_per_op(&self) -> usize {
+    self.group_size()
+  }
+
+  fn num_inputs_per_row(&self) -> usize {
+    self.config.columns.len() / self.group_size()
+  }
+
+  fn num_outputs_per_row(&self) -> usize {
+    self.num_inputs_per_row()
+  }
+
+  fn op_row_region(
+    &self,
+    region: &mut Region<F>,
+    row_offset: usize,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    _single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let inp = &vec_inputs[0];
+
+    if self.config.use_selectors {
+      let selector = self
+        .config
+        .selectors
+        .get(&GadgetType::SignedRangeCheck)
+        .unwrap()[self.bits_idx];
+      selector.enable(region, row_offset)?;
+    }
+
+    let group_size = self.group_size();
+    let outp = inp
+      .iter()
+      .enumerate()
+      .map(|(i, cell)| {
+        let col = self.config.columns[i * group_size];
+        cell.copy_advice(|| "", region, col, row_offset)
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    if group_size > 1 {
+      let num_bits = self.config.signed_range_bits[self.bits_idx];
+      let limb_bits = self.config.range_check_limb_bits as usize;
+      let half = F::from_u128(1u128 << (num_bits - 1));
+      let mask = (BigUint::from(1u64) << limb_bits) - 1u64;
+      for (i, cell) in inp.iter().enumerate() {
+        // Out of range values get limbs that don't add up or don't pass the lookups
+        let limbs = cell.value().map(|x| {
+          let shifted = BigUint::from_bytes_le((*x + half).to_repr().as_ref());
+          (0..self.num_limbs())
+            .map(|j| ((&shifted >> (limb_bits * j)) & &mask).to_u64().unwrap())
+            .collect::<Vec<_>>()
+        });
+        for j in 0..self.num_limbs() {
+          let limb = limbs.as_ref().map(|limbs| F::from(limbs[j]));
+          region.assign_advice(
+            || "",
+            self.config.columns[i * group_size + 1 + j],
+            row_offset,
+            || limb,
+          )?;
+        }
+      }
+    }
+
+    Ok(outp)
+  }
+
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    vec_inputs: &Vec<Vec<&AssignedCell<F, F>>>,
+    single_inputs: &Vec<&AssignedCell<F, F>>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = &single_inputs[0];
+    let inp_len = vec_inputs[0].len();
+    let mut inp = vec_inputs[0].clone();
+
+    while inp.len() % self.num_inputs_per_row() != 0 {
+      inp.push(zero);
+    }
+
+    let vec_inputs = vec![inp];
+    let outp = self.op_aligned_rows(
+      layouter.namespace(|| format!("forward row {}", self.name())),
+      &vec_inputs,
+      single_inputs,
+    )?;
+
+    Ok(outp[0..inp_len].to_vec())
+  }
+}
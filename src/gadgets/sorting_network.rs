@@ -0,0 +1,155 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+
+use super::{
+  add_pairs::AddPairsChip,
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  sub_pairs::SubPairsChip,
+};
+
+// Sorts keys in increasing order with Batcher's odd-even merge sort, so the witness has no freedom
+// in the order. Every compare-exchange of (a, b) is c = [a <= b], t = c (a - b), min = b + t and
+// max = a - t, batched over the comparators of a stage. The payloads, e.g., the indices of
+// the keys, are exchanged with the same bits. The keys are compared through the input lookup, so
+// their differences must be within it. A network over n keys has O(n log^2 n) comparators in
+// O(log^2 n) stages, so the number of keys is bounded by MAX_SORT_LEN.
+pub const MAX_SORT_LEN: usize = 1024;
+
+pub fn sort_gadgets() -> Vec<GadgetType> {
+  vec![
+    GadgetType::AddPairs,
+    GadgetType::Comparator,
+    GadgetType::MulPairs,
+    GadgetType::SubPairs,
+  ]
+}
+
+pub struct SortingNetworkChip<F: PrimeField> {
+  config: Rc<GadgetConfig>,
+  _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> SortingNetworkChip<F> {
+  pub fn construct(config: Rc<GadgetConfig>) -> Self {
+    Self {
+      config,
+      _marker: PhantomData,
+    }
+  }
+
+  // The comparators (i, j), i < j, of every stage. The network for n is the one for the next power
+  // of two without the comparators past n, which only touch the (virtual) keys of +inf.
+  pub fn stages(n: usize) -> Vec<Vec<(usize, usize)>> {
+    let mut stages = vec![];
+    let mut p = 1;
+    while p < n {
+      let mut k = p;
+      while k >= 1 {
+        let mut stage = vec![];
+        let mut j = k % p;
+        while j + k < n {
+          for i in 0..k.min(n - j - k) {
+            if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+              stage.push((i + j, i + j + k));
+            }
+          }
+          j += 2 * k;
+        }
+        if !stage.is_empty() {
+          stages.push(stage);
+        }
+        k /= 2;
+      }
+      p *= 2;
+    }
+    stages
+  }
+
+  // Returns the sorted keys and the payloads in the same order
+  pub fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    keys: &Vec<&AssignedCell<F, F>>,
+    payloads: &Vec<Vec<&AssignedCell<F, F>>>,
+    zero: &AssignedCell<F, F>,
+  ) -> Result<(Vec<AssignedCell<F, F>>, Vec<Vec<AssignedCell<F, F>>>), Error> {
+    let n = keys.len();
+    assert!(
+      n <= MAX_SORT_LEN,
+      "sorting {} keys, at most {}",
+      n,
+      MAX_SORT_LEN
+    );
+    for payload in payloads.iter() {
+      assert_eq!(payload.len(), n);
+    }
+    let add_pairs_chip = AddPairsChip::<F>::construct(self.config.clone());
+    let comparator_chip = ComparatorChip::<F>::construct(self.config.clone());
+    let mul_pairs_chip = MulPairsChip::<F>::construct(self.config.clone());
+    let sub_pairs_chip = SubPairsChip::<F>::construct(self.config.clone());
+
+    let mut keys = keys.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
+    let mut payloads = payloads
+      .iter()
+      .map(|payload| payload.iter().map(|x| (*x).clone()).collect::<Vec<_>>())
+      .collect::<Vec<_>>();
+    for (s, stage) in Self::stages(n).iter().enumerate() {
+      let lhs = stage.iter().map(|(i, _)| &keys[*i]).collect::<Vec<_>>();
+      let rhs = stage.iter().map(|(_, j)| &keys[*j]).collect::<Vec<_>>();
+      let bits = comparator_chip.forward(
+        layouter.namespace(|| format!("sort stage {} comparisons", s)),
+        &vec![lhs, rhs],
+        &vec![zero],
+      )?;
+
+      // The keys and the payloads are exchanged with the same bits
+      let mut exchanged = vec![];
+      for (name, values) in [("keys", &keys)]
+        .into_iter()
+        .chain(payloads.iter().map(|payload| ("payload", payload)))
+      {
+        let lhs = stage.iter().map(|(i, _)| &values[*i]).collect::<Vec<_>>();
+        let rhs = stage.iter().map(|(_, j)| &values[*j]).collect::<Vec<_>>();
+        let diffs = sub_pairs_chip.forward(
+          layouter.namespace(|| format!("sort stage {} {} diffs", s, name)),
+          &vec![lhs.clone(), rhs.clone()],
+          &vec![zero],
+        )?;
+        let moved = mul_pairs_chip.forward(
+          layouter.namespace(|| format!("sort stage {} {} moved", s, name)),
+          &vec![bits.iter().collect(), diffs.iter().collect()],
+          &vec![zero],
+        )?;
+        let mins = add_pairs_chip.forward(
+          layouter.namespace(|| format!("sort stage {} {} mins", s, name)),
+          &vec![rhs, moved.iter().collect()],
+          &vec![zero],
+        )?;
+        let maxs = sub_pairs_chip.forward(
+          layouter.namespace(|| format!("sort stage {} {} maxs", s, name)),
+          &vec![lhs, moved.iter().collect()],
+          &vec![zero],
+        )?;
+        exchanged.push((mins, maxs));
+      }
+
+      let mut exchanged = exchanged.into_iter();
+      for values in [&mut keys].into_iter().chain(payloads.iter_mut()) {
+        let (mins, maxs) = exchanged.next().unwrap();
+        for (((i, j), min), max) in stage.iter().zip(mins).zip(maxs) {
+          values[*i] = min;
+          values[*j] = max;
+        }
+      }
+    }
+
+    Ok((keys, payloads))
+  }
+}
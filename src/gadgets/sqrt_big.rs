@@ -138,8 +138,8 @@ impl<F: PrimeField> Gadget<F> for SqrtBigChip<F> {
 
       let outp = inp.value().map(|x: &F| {
         let inp_val = convert_to_u64(x) as i64;
-        let fsqrt = (inp_val as f64).sqrt();
-        let sqrt = fsqrt.round() as i64;
+        // float-ok: the IEEE square root is correctly rounded, and the gadget checks the remainder
+        let sqrt = (inp_val as f64).sqrt().round() as i64;
         let rem = inp_val - sqrt * sqrt;
         (sqrt, rem)
       });
@@ -39,6 +39,7 @@ impl<F: PrimeField> UpdateGadgetChip<F> {
     let selector = meta.complex_selector();
 
     let div_val = gadget_config.scale_factor;
+    // float-ok: the learning rate is quantized once, with a correctly rounded product
     let eta: u64 = (gadget_config.scale_factor as f64 * gadget_config.eta) as u64;
 
     meta.create_gate("updater_arith", |meta| {
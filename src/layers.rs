@@ -5,22 +5,41 @@ pub mod arithmetic;
 pub mod shape;
 
 // Concrete implementations
+pub mod activation;
+pub mod attribution;
 pub mod avg_pool_2d;
+pub mod avg_pool_3d;
 pub mod batch_mat_mul;
+pub mod branch;
 pub mod conv2d;
+pub mod conv3d;
 pub mod div_fixed;
+pub mod div_mod;
+pub mod dropout;
 pub mod fully_connected;
+pub mod layer_norm;
 pub mod logistic;
 pub mod max_pool_2d;
+pub mod max_pool_3d;
 pub mod mean;
+pub mod moe;
 pub mod noop;
 pub mod pow;
+pub mod predicate;
+pub mod quantile;
+pub mod range_check;
+pub mod recurrent;
+pub mod requantize;
+pub mod robustness;
 pub mod rsqrt;
 pub mod softmax;
 pub mod sqrt;
 pub mod square;
 pub mod squared_diff;
+pub mod tabulated;
 pub mod tanh;
+pub mod top_k;
+pub mod tree_ensemble;
 pub mod update;
 
 // Special: dag
@@ -0,0 +1,87 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  nonlinear::{gelu::GeluGadgetChip, silu::SiluGadgetChip},
+  var_div::VarDivRoundChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+  Gelu,
+  Silu,
+}
+
+// The smooth activations of transformers and modern CNNs, each a lookup. With
+// activation_table_bits, the inputs are first rounded to that precision (see activation_div in
+// non_linearity.rs), which trades the resolution of the table for its range.
+#[derive(Clone, Debug)]
+pub struct ActivationChip {
+  pub activation: Activation,
+}
+
+impl<F: PrimeField> Layer<F> for ActivationChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    _layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let inp_vec = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+
+    let div = gadget_config.activation_div;
+    let rounded = if div > 1 {
+      let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
+      let div = constants.get(&div).unwrap().as_ref();
+      Some(var_div_chip.forward(
+        layouter.namespace(|| "activation round"),
+        &vec![inp_vec.clone()],
+        &vec![zero, div],
+      )?)
+    } else {
+      None
+    };
+    let vec_inps = match &rounded {
+      Some(rounded) => vec![rounded.iter().collect()],
+      None => vec![inp_vec],
+    };
+
+    let constants = vec![zero];
+    let out = match self.activation {
+      Activation::Gelu => GeluGadgetChip::<F>::construct(gadget_config.clone()).forward(
+        layouter.namespace(|| "gelu chip"),
+        &vec_inps,
+        &constants,
+      )?,
+      Activation::Silu => SiluGadgetChip::<F>::construct(gadget_config.clone()).forward(
+        layouter.namespace(|| "silu chip"),
+        &vec_inps,
+        &constants,
+      )?,
+    };
+
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(inp.shape()), out).unwrap();
+
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for ActivationChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    let gadget = match self.activation {
+      Activation::Gelu => GadgetType::Gelu,
+      Activation::Silu => GadgetType::Silu,
+    };
+    vec![gadget, GadgetType::VarDivRound, GadgetType::InputLookup]
+  }
+}
@@ -0,0 +1,120 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  adder::AdderChip,
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  sub_pairs::SubPairsChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Occlusion attribution. Params: [class, k]
+// Inputs are the logits on the input, then the logits with each feature occluded. The
+// attribution of feature f is logits[class] - logits_f[class]. Outputs a [num_features] mask that
+// is one for the features in the top k, i.e., where at least num_features - k other attributions
+// are less than or equal to it. Ties can put more than k features in the mask.
+#[derive(Clone, Debug)]
+pub struct AttributionChip {}
+
+impl<F: PrimeField> Layer<F> for AttributionChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let class = layer_config.layer_params[0] as usize;
+    let k = layer_config.layer_params[1] as usize;
+    let num_features = tensors.len() - 1;
+    assert!(k >= 1 && k <= num_features);
+
+    let logit = |tensor: &AssignedTensor<F>| tensor.iter().nth(class).unwrap().clone();
+    let base = logit(&tensors[0]);
+    let occluded = tensors[1..].iter().map(|t| logit(t)).collect::<Vec<_>>();
+
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+    let attributions = sub_pairs_chip.forward(
+      layouter.namespace(|| "attributions"),
+      &vec![
+        vec![base.as_ref(); num_features],
+        occluded.iter().map(|x| x.as_ref()).collect(),
+      ],
+      &vec![zero],
+    )?;
+
+    // le[f * (n - 1) + j] = [a_g <= a_f] over the other features g
+    let mut lhs = vec![];
+    let mut rhs = vec![];
+    for f in 0..num_features {
+      for g in (0..num_features).filter(|g| *g != f) {
+        lhs.push(&attributions[g]);
+        rhs.push(&attributions[f]);
+      }
+    }
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let le = comparator_chip.forward(
+      layouter.namespace(|| "attribution ranks"),
+      &vec![lhs, rhs],
+      &vec![zero],
+    )?;
+
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let mut num_le = vec![];
+    for f in 0..num_features {
+      let bits = le[f * (num_features - 1)..(f + 1) * (num_features - 1)]
+        .iter()
+        .collect::<Vec<_>>();
+      let count = if bits.is_empty() {
+        zero.clone()
+      } else {
+        adder_chip.forward(
+          layouter.namespace(|| format!("attribution count {}", f)),
+          &vec![bits],
+          &vec![zero],
+        )?[0]
+          .clone()
+      };
+      num_le.push(count);
+    }
+
+    // The threshold is built from ones so that it is constrained
+    let threshold = if num_features == k {
+      zero.clone()
+    } else {
+      adder_chip.forward(
+        layouter.namespace(|| "attribution threshold"),
+        &vec![vec![one; num_features - k]],
+        &vec![zero],
+      )?[0]
+        .clone()
+    };
+    let in_top = comparator_chip.forward(
+      layouter.namespace(|| "attribution top k"),
+      &vec![vec![&threshold; num_features], num_le.iter().collect()],
+      &vec![zero],
+    )?;
+
+    let out = in_top.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(&[num_features]), out).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for AttributionChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::SubPairs,
+      GadgetType::Comparator,
+      GadgetType::InputLookup,
+    ]
+  }
+}
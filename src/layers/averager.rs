@@ -7,7 +7,12 @@ use halo2_proofs::{
 };
 
 use crate::gadgets::gadget::Gadget;
-use crate::gadgets::{adder::AdderChip, gadget::GadgetConfig, var_div::VarDivRoundChip};
+use crate::gadgets::{
+  adder::AdderChip,
+  gadget::GadgetConfig,
+  int_div::{IntDivChip, IntDivMode},
+  var_div::VarDivRoundChip,
+};
 
 use super::layer::{AssignedTensor, CellRc, LayerConfig};
 
@@ -18,10 +23,28 @@ pub trait Averager<F: PrimeField> {
     &self,
     layouter: impl Layouter<F>,
     tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
     gadget_config: Rc<GadgetConfig>,
     layer_config: &LayerConfig,
   ) -> Result<AssignedCell<F, F>, Error>;
 
+  // Divides the sums by the divisor, rounding half up
+  fn divide(
+    &self,
+    mut layouter: impl Layouter<F>,
+    sums: &Vec<&AssignedCell<F, F>>,
+    div: &AssignedCell<F, F>,
+    zero: &AssignedCell<F, F>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config);
+    var_div_chip.forward(
+      layouter.namespace(|| "average div"),
+      &vec![sums.clone()],
+      &vec![zero, div],
+    )
+  }
+
   fn avg_forward(
     &self,
     mut layouter: impl Layouter<F>,
@@ -53,20 +76,39 @@ pub trait Averager<F: PrimeField> {
     let div = self.get_div_val(
       layouter.namespace(|| "average div"),
       tensors,
+      constants,
       gadget_config.clone(),
       layer_config,
     )?;
-    let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
-
-    let single_inputs = vec![zero, &div];
     let added = added.iter().map(|x| x).collect::<Vec<_>>();
-    let dived = var_div_chip.forward(
+    let dived = self.divide(
       layouter.namespace(|| "average div"),
-      &vec![added],
-      &single_inputs,
+      &added,
+      &div,
+      zero,
+      gadget_config,
     )?;
     let dived = dived.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
 
     Ok(dived)
   }
 }
+
+// The division of the average pools, rounding half away from zero like TFLite. The remainder is
+// range checked, so the quotient is the only one.
+pub fn div_round_half_away<F: PrimeField>(
+  mut layouter: impl Layouter<F>,
+  sums: &Vec<&AssignedCell<F, F>>,
+  div: &AssignedCell<F, F>,
+  zero: &AssignedCell<F, F>,
+  gadget_config: Rc<GadgetConfig>,
+) -> Result<Vec<AssignedCell<F, F>>, Error> {
+  let int_div_chip = IntDivChip::<F>::construct(gadget_config, IntDivMode::RoundHalfAway);
+  let mut quotients = int_div_chip.forward(
+    layouter.namespace(|| "average div round half away"),
+    &vec![sums.clone()],
+    &vec![zero, div],
+  )?;
+  quotients.truncate(sums.len());
+  Ok(quotients)
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, rc::Rc};
 
 use halo2_proofs::{
-  circuit::{AssignedCell, Layouter, Value},
+  circuit::{AssignedCell, Layouter},
   halo2curves::ff::PrimeField,
   plonk::Error,
 };
@@ -13,12 +13,21 @@ use crate::{
 };
 
 use super::{
-  averager::Averager,
+  averager::{div_round_half_away, Averager},
   layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig},
 };
 
+// The window sum is divided exactly by the window size with the integer division gadget, which
+// rounds half away from zero like TFLite, for any window size. The window size is a constant, so
+// it is fixed by the circuit.
 pub struct AvgPool2DChip {}
 
+impl AvgPool2DChip {
+  pub fn constants(layer_params: &Vec<i64>) -> Vec<i64> {
+    vec![layer_params[0] * layer_params[1]]
+  }
+}
+
 impl<F: PrimeField> Averager<F> for AvgPool2DChip {
   fn splat(&self, input: &AssignedTensor<F>, layer_config: &LayerConfig) -> Vec<Vec<CellRc<F>>> {
     assert_eq!(input.shape().len(), 4);
@@ -31,32 +40,26 @@ impl<F: PrimeField> Averager<F> for AvgPool2DChip {
 
   fn get_div_val(
     &self,
-    mut layouter: impl Layouter<F>,
+    _layouter: impl Layouter<F>,
     _tensors: &Vec<AssignedTensor<F>>,
-    gadget_config: Rc<GadgetConfig>,
+    constants: &HashMap<i64, CellRc<F>>,
+    _gadget_config: Rc<GadgetConfig>,
     layer_config: &LayerConfig,
   ) -> Result<AssignedCell<F, F>, Error> {
-    // FIXME: this needs to be revealed
-    let div = layer_config.layer_params[0] * layer_config.layer_params[1];
-    let div = F::from(div as u64);
-    let div = layouter
-      .assign_region(
-        || "avg pool 2d div",
-        |mut region| {
-          let div = region
-            .assign_advice(
-              || "avg pool 2d div",
-              gadget_config.columns[0],
-              0,
-              || Value::known(div),
-            )
-            .unwrap();
-          Ok(div)
-        },
-      )
-      .unwrap();
+    let div = Self::constants(&layer_config.layer_params)[0];
+    assert!(div > 0);
+    Ok(constants.get(&div).unwrap().as_ref().clone())
+  }
 
-    Ok(div)
+  fn divide(
+    &self,
+    layouter: impl Layouter<F>,
+    sums: &Vec<&AssignedCell<F, F>>,
+    div: &AssignedCell<F, F>,
+    zero: &AssignedCell<F, F>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    div_round_half_away(layouter, sums, div, zero, gadget_config)
   }
 }
 
@@ -88,7 +91,7 @@ impl GadgetConsumer for AvgPool2DChip {
   fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
     vec![
       GadgetType::Adder,
-      GadgetType::VarDivRound,
+      GadgetType::IntDivRoundHalfAway,
       GadgetType::InputLookup,
     ]
   }
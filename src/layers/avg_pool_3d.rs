@@ -0,0 +1,87 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, IxDyn};
+
+use crate::{
+  gadgets::gadget::{GadgetConfig, GadgetType},
+  layers::max_pool_3d::MaxPool3DChip,
+};
+
+use super::{
+  averager::{div_round_half_away, Averager},
+  layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig},
+};
+
+// Params: [filter d, filter h, filter w, stride d, stride h, stride w]
+// The windows are those of MaxPool3D, divided by the window size as in AvgPool2D
+pub struct AvgPool3DChip {}
+
+impl AvgPool3DChip {
+  pub fn constants(layer_params: &Vec<i64>) -> Vec<i64> {
+    vec![layer_params[..3].iter().product()]
+  }
+}
+
+impl<F: PrimeField> Averager<F> for AvgPool3DChip {
+  fn splat(&self, input: &AssignedTensor<F>, layer_config: &LayerConfig) -> Vec<Vec<CellRc<F>>> {
+    MaxPool3DChip::splat(input, layer_config)
+  }
+
+  fn get_div_val(
+    &self,
+    _layouter: impl Layouter<F>,
+    _tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    _gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<AssignedCell<F, F>, Error> {
+    let div = Self::constants(&layer_config.layer_params)[0];
+    assert!(div > 0);
+    Ok(constants.get(&div).unwrap().as_ref().clone())
+  }
+
+  fn divide(
+    &self,
+    layouter: impl Layouter<F>,
+    sums: &Vec<&AssignedCell<F, F>>,
+    div: &AssignedCell<F, F>,
+    zero: &AssignedCell<F, F>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    div_round_half_away(layouter, sums, div, zero, gadget_config)
+  }
+}
+
+impl<F: PrimeField> Layer<F> for AvgPool3DChip {
+  fn forward(
+    &self,
+    layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let dived = self.avg_forward(layouter, tensors, constants, gadget_config, layer_config)?;
+
+    let inp = &tensors[0];
+    let [od, oh, ow] = MaxPool3DChip::shape(inp, layer_config);
+    let out_shape = vec![1, od, oh, ow, inp.shape()[4]];
+    let out = Array::from_shape_vec(IxDyn(&out_shape), dived).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for AvgPool3DChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::IntDivRoundHalfAway,
+      GadgetType::InputLookup,
+    ]
+  }
+}
@@ -29,22 +29,37 @@ impl<F: PrimeField> Layer<F> for BatchMatMulChip {
     info!("inp1: {:?}", inp1.shape());
     info!("inp2: {:?}", inp2.shape());
 
-    assert_eq!(inp1.ndim(), 3);
-    assert_eq!(inp2.ndim(), 3);
-    assert_eq!(inp1.shape()[0], inp2.shape()[0]);
+    // The leading axes are batch axes, e.g., [batch, heads] for attention, and 2D inputs are a
+    // batch of one
+    assert!(inp1.ndim() >= 2);
+    assert_eq!(inp1.ndim(), inp2.ndim());
+    let ndim = inp1.ndim();
+    let batch_shape = inp1.shape()[..ndim - 2].to_vec();
+    assert_eq!(batch_shape, inp2.shape()[..ndim - 2].to_vec());
+    let num_batch = batch_shape.iter().product::<usize>();
+    // Flattened in the logical order, since the inputs may be transposed views
+    let to_mats = |inp: &AssignedTensor<F>| {
+      let shape = [num_batch, inp.shape()[ndim - 2], inp.shape()[ndim - 1]];
+      Array::from_shape_vec(IxDyn(&shape), inp.iter().cloned().collect()).unwrap()
+    };
+    let (inp1, inp2) = (to_mats(inp1), to_mats(inp2));
 
-    let adj_y = layer_config.layer_params[1] == 1;
-    if adj_y {
-      assert_eq!(inp1.shape()[2], inp2.shape()[2]);
+    // adj_x and adj_y transpose the last two axes of the inputs
+    let adj_x = layer_config.layer_params.first() == Some(&1);
+    let adj_y = layer_config.layer_params.get(1) == Some(&1);
+    let (rows, inner) = if adj_x {
+      (inp1.shape()[2], inp1.shape()[1])
     } else {
-      assert_eq!(inp1.shape()[2], inp2.shape()[1]);
-    }
-
-    let out_shape = if adj_y {
-      vec![inp1.shape()[0], inp1.shape()[1], inp2.shape()[1]]
+      (inp1.shape()[1], inp1.shape()[2])
+    };
+    let (cols, inner2) = if adj_y {
+      (inp2.shape()[1], inp2.shape()[2])
     } else {
-      vec![inp1.shape()[0], inp1.shape()[1], inp2.shape()[2]]
+      (inp2.shape()[2], inp2.shape()[1])
     };
+    assert_eq!(inner, inner2);
+
+    let out_shape = [batch_shape, vec![rows, cols]].concat();
 
     let fc_chip = FullyConnectedChip::<F> {
       _marker: PhantomData,
@@ -52,8 +67,12 @@ impl<F: PrimeField> Layer<F> for BatchMatMulChip {
     };
 
     let mut outp: Vec<CellRc<F>> = vec![];
-    for i in 0..inp1.shape()[0] {
-      let inp1_slice = inp1.index_axis(Axis(0), i).to_owned();
+    for i in 0..num_batch {
+      let inp1_slice = if adj_x {
+        inp1.index_axis(Axis(0), i).t().to_owned()
+      } else {
+        inp1.index_axis(Axis(0), i).to_owned()
+      };
       // Due to tensorflow BS, transpose the "weights"
       let inp2_slice = if adj_y {
         inp2.index_axis(Axis(0), i).to_owned()
@@ -0,0 +1,60 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// The predicate of a conditional branch. Params: [taken]
+// The input is the single element condition, and the predicate is [condition > 0]. The branch
+// taken is fixed by the circuit (see branches.rs), so the predicate is constrained to be equal to
+// it. Outputs the predicate ([1]), to be revealed.
+#[derive(Clone, Debug)]
+pub struct BranchChip {}
+
+impl<F: PrimeField> Layer<F> for BranchChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let taken = layer_config.layer_params[0];
+    assert!(taken == 0 || taken == 1);
+    assert_eq!(tensors[0].len(), 1, "the condition must have one element");
+    let cond = tensors[0].iter().next().unwrap().as_ref();
+
+    // Integers, so cond > 0 iff 1 <= cond
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let predicate = comparator_chip.forward(
+      layouter.namespace(|| "branch predicate"),
+      &vec![vec![one], vec![cond]],
+      &vec![zero],
+    )?[0]
+      .clone();
+
+    let taken = constants.get(&taken).unwrap();
+    layouter.assign_region(
+      || "branch taken",
+      |mut region| region.constrain_equal(predicate.cell(), taken.cell()),
+    )?;
+
+    let outp = Array::from_shape_vec(IxDyn(&[1]), vec![Rc::new(predicate)]).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for BranchChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![GadgetType::Comparator, GadgetType::InputLookup]
+  }
+}
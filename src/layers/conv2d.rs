@@ -7,14 +7,13 @@ use halo2_proofs::{
   halo2curves::ff::PrimeField,
   plonk::Error,
 };
-use ndarray::{Array, IxDyn};
+use ndarray::{s, Array, IxDyn};
 
 use crate::{
   gadgets::{
     bias_div_round_relu6::BiasDivRoundRelu6Chip,
     dot_prod::DotProductChip,
     gadget::{Gadget, GadgetConfig, GadgetType},
-    nonlinear::relu::ReluChip,
   },
   layers::{
     fully_connected::{FullyConnectedChip, FullyConnectedConfig},
@@ -22,7 +21,7 @@ use crate::{
   },
 };
 
-use super::layer::{ActivationType, AssignedTensor, GadgetConsumer, Layer, LayerConfig};
+use super::layer::{ActivationType, AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
 
 #[derive(Default, Clone, Copy, Eq, PartialEq)]
 pub enum PaddingEnum {
@@ -43,6 +42,10 @@ pub struct Conv2DConfig {
   pub padding: PaddingEnum,
   pub activation: ActivationType,
   pub stride: (usize, usize),
+  // Grouped convolutions split the input channels into groups, each convolved with its share of
+  // the output channels. The weights are [O, H, W, I / groups]. Depthwise convolutions are the
+  // DepthwiseConv2D type instead, with any channel multiplier.
+  pub groups: usize,
 }
 
 pub struct Conv2DChip<F: PrimeField> {
@@ -70,11 +73,14 @@ impl<F: PrimeField> Conv2DChip<F> {
       _ => panic!("Invalid activation type"),
     };
     let stride = (layer_params[3] as usize, layer_params[4] as usize);
+    let groups = layer_params.get(5).map_or(1, |groups| *groups as usize);
+    assert!(groups >= 1, "Invalid number of groups");
     Conv2DConfig {
       conv_type,
       padding,
       activation,
       stride,
+      groups,
     }
   }
 
@@ -243,6 +249,9 @@ impl<F: PrimeField> Conv2DChip<F> {
     let cw: usize = weights.shape()[2];
     let (si, sj) = conv_config.stride;
     let (oh, ow) = Self::out_hw(h, w, si, sj, ch, cw, conv_config.padding);
+    // Every input channel has multiplier output channels, next to each other
+    let multiplier = weights.shape()[3] / input.shape()[3];
+    assert_eq!(weights.shape()[3], input.shape()[3] * multiplier);
 
     let (ph, pw) = if conv_config.padding == PaddingEnum::Same {
       Self::get_padding(h, w, si, sj, ch, cw)
@@ -271,7 +280,7 @@ impl<F: PrimeField> Conv2DChip<F> {
               let idx_i = i * strides.0 + ci;
               let idx_j = j * strides.1 + cj;
 
-              inp_cells[row_idx].push(inp_pad[[0, idx_i, idx_j, chan_out]].clone());
+              inp_cells[row_idx].push(inp_pad[[0, idx_i, idx_j, chan_out / multiplier]].clone());
               weight_cells[row_idx].push(weights[[0, ci, cj, chan_out]].clone());
             }
           }
@@ -283,6 +292,103 @@ impl<F: PrimeField> Conv2DChip<F> {
 
     (inp_cells, weight_cells, biases_cells)
   }
+
+  // The input, weights, and biases of every group
+  pub fn group_tensors<G: Clone>(
+    tensors: &Vec<Array<Rc<G>, IxDyn>>,
+    groups: usize,
+  ) -> Vec<Vec<Array<Rc<G>, IxDyn>>> {
+    if groups == 1 {
+      return vec![tensors.clone()];
+    }
+    let inp = &tensors[0];
+    let weights = &tensors[1];
+    let group_inp = weights.shape()[3];
+    let group_out = weights.shape()[0] / groups;
+    assert_eq!(inp.shape()[3], group_inp * groups);
+    assert_eq!(weights.shape()[0], group_out * groups);
+
+    (0..groups)
+      .map(|g| {
+        let (inp_range, out_range) = (
+          g * group_inp..(g + 1) * group_inp,
+          g * group_out..(g + 1) * group_out,
+        );
+        let mut group = vec![
+          inp.slice(s![.., .., .., inp_range]).into_owned().into_dyn(),
+          weights
+            .slice(s![out_range.clone(), .., .., ..])
+            .into_owned()
+            .into_dyn(),
+        ];
+        if let Some(biases) = tensors.get(2) {
+          group.push(biases.slice(s![out_range]).into_owned().into_dyn());
+        }
+        group
+      })
+      .collect()
+  }
+
+  // Merges the outputs of the groups at every output position, so that the channels of the groups
+  // are next to each other
+  fn merge_groups<T: Clone>(group_outs: Vec<Vec<T>>, positions: usize) -> Vec<T> {
+    if group_outs.len() == 1 {
+      return group_outs.into_iter().next().unwrap();
+    }
+    let mut chunks = group_outs
+      .iter()
+      .map(|x| x.chunks(x.len() / positions))
+      .collect::<Vec<_>>();
+    let mut outp = vec![];
+    for _ in 0..positions {
+      for chunk in chunks.iter_mut() {
+        outp.extend_from_slice(chunk.next().unwrap());
+      }
+    }
+    outp
+  }
+
+  // Adds the biases to the products, rescales them, and applies the activation
+  pub fn bias_div_activation(
+    mut layouter: impl Layouter<F>,
+    outp_flat: Vec<&AssignedCell<F, F>>,
+    biases: Vec<&AssignedCell<F, F>>,
+    activation: &ActivationType,
+    zero: &AssignedCell<F, F>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<CellRc<F>>, Error> {
+    // The ReLU uses the same rows as the ReLU6, with another table
+    let bdr_chip = if *activation == ActivationType::Relu {
+      BiasDivRoundRelu6Chip::<F>::construct_relu(gadget_config.clone())
+    } else {
+      BiasDivRoundRelu6Chip::<F>::construct(gadget_config.clone())
+    };
+    let outp = bdr_chip.forward(
+      layouter.namespace(|| "bias_div_relu"),
+      &vec![outp_flat, biases],
+      &vec![zero],
+    )?;
+
+    // TODO: this is also horrible. The bdr chip outputs interleaved [(relu'd, div'd), (relu'd, div'd), ...]
+    // Uninterleave depending on whether or not we're doing the relu
+    let outp = if *activation == ActivationType::Relu6 || *activation == ActivationType::Relu {
+      outp
+        .into_iter()
+        .step_by(2)
+        .map(|x| Rc::new(x))
+        .collect::<Vec<_>>()
+    } else if *activation == ActivationType::None {
+      outp
+        .into_iter()
+        .skip(1)
+        .step_by(2)
+        .map(|x| Rc::new(x))
+        .collect::<Vec<_>>()
+    } else {
+      panic!("Unsupported activation type");
+    };
+    Ok(outp)
+  }
 }
 
 impl<F: PrimeField> Layer<F> for Conv2DChip<F> {
@@ -310,51 +416,61 @@ impl<F: PrimeField> Layer<F> for Conv2DChip<F> {
       conv_config.padding,
     );
     let batch_size = inp.shape()[0];
+    let positions = batch_size * oh * ow;
 
-    let (splat_inp, splat_weights, splat_biases) = match conv_config.conv_type {
-      ConvLayerEnum::Conv2D => self.splat(tensors, zero.clone()),
-      ConvLayerEnum::DepthwiseConv2D => self.splat_depthwise(tensors, zero.clone()),
-    };
-
-    let outp_flat: Vec<AssignedCell<F, F>> = match conv_config.conv_type {
+    let (outp_flat, splat_biases): (Vec<AssignedCell<F, F>>, _) = match conv_config.conv_type {
       ConvLayerEnum::Conv2D => {
         let fc_chip = FullyConnectedChip::<F> {
           _marker: PhantomData,
           config: FullyConnectedConfig::construct(false),
         };
 
-        let conv_size = splat_inp[0].len();
-        let flattened_inp: Vec<_> = splat_inp.into_iter().flat_map(|x| x.into_iter()).collect();
-        let flattened_weights = splat_weights
-          .into_iter()
-          .flat_map(|x| x.into_iter())
-          .collect::<Vec<_>>();
-
-        let out_channels = weights.shape()[0];
-        let inp_array =
-          Array::from_shape_vec(IxDyn(&vec![batch_size * oh * ow, conv_size]), flattened_inp)
+        // Every group is its own matrix product
+        let mut group_outs = vec![];
+        let mut group_biases = vec![];
+        let group_tensors = Self::group_tensors(tensors, conv_config.groups);
+        for (g, group) in group_tensors.iter().enumerate() {
+          let (splat_inp, splat_weights, splat_biases) = self.splat(group, zero.clone());
+          let conv_size = splat_inp[0].len();
+          let flattened_inp: Vec<_> = splat_inp.into_iter().flat_map(|x| x.into_iter()).collect();
+          let flattened_weights = splat_weights
+            .into_iter()
+            .flat_map(|x| x.into_iter())
+            .collect::<Vec<_>>();
+
+          let out_channels = group[1].shape()[0];
+          let inp_array =
+            Array::from_shape_vec(IxDyn(&vec![positions, conv_size]), flattened_inp).unwrap();
+          let weights_array =
+            Array::from_shape_vec(IxDyn(&vec![out_channels, conv_size]), flattened_weights)
+              .unwrap();
+
+          let outp_slice = fc_chip
+            .forward(
+              layouter.namespace(|| format!("group {}", g)),
+              &vec![weights_array, inp_array],
+              constants,
+              gadget_config.clone(),
+              layer_config,
+            )
             .unwrap();
-        let weights_array =
-          Array::from_shape_vec(IxDyn(&vec![out_channels, conv_size]), flattened_weights).unwrap();
-
-        let outp_slice = fc_chip
-          .forward(
-            layouter.namespace(|| ""),
-            &vec![weights_array, inp_array],
-            constants,
-            gadget_config.clone(),
-            layer_config,
-          )
-          .unwrap();
-
-        let outp_flat = outp_slice[0]
-          .t()
-          .into_iter()
-          .map(|x| (**x).clone())
-          .collect::<Vec<_>>();
-        outp_flat
+
+          group_outs.push(
+            outp_slice[0]
+              .t()
+              .into_iter()
+              .map(|x| (**x).clone())
+              .collect::<Vec<_>>(),
+          );
+          group_biases.push(splat_biases);
+        }
+        (
+          Self::merge_groups(group_outs, positions),
+          Self::merge_groups(group_biases, positions),
+        )
       }
       ConvLayerEnum::DepthwiseConv2D => {
+        let (splat_inp, splat_weights, splat_biases) = self.splat_depthwise(tensors, zero.clone());
         // Do the dot products
         let dot_prod_chip = DotProductChip::<F>::construct(gadget_config.clone());
         let mut outp_flat = vec![];
@@ -370,56 +486,18 @@ impl<F: PrimeField> Layer<F> for Conv2DChip<F> {
         }
         // info!("outp_flat: {:?}", outp_flat.len());
 
-        outp_flat
+        (outp_flat, splat_biases)
       }
     };
 
-    let mut biases = vec![];
-    for bias in splat_biases.iter() {
-      biases.push(bias.as_ref());
-    }
-
-    // Compute the bias + div + relu
-    let bdr_chip = BiasDivRoundRelu6Chip::<F>::construct(gadget_config.clone());
-    let tmp = vec![zero.as_ref()];
-    let outp_flat = outp_flat.iter().map(|x| x).collect::<Vec<_>>();
-    let outp = bdr_chip
-      .forward(
-        layouter.namespace(|| "bias_div_relu"),
-        &vec![outp_flat, biases],
-        &tmp,
-      )
-      .unwrap();
-
-    // TODO: this is also horrible. The bdr chip outputs interleaved [(relu'd, div'd), (relu'd, div'd), ...]
-    // Uninterleave depending on whether or not we're doing the relu
-    let outp = if conv_config.activation == ActivationType::Relu6 {
-      outp
-        .into_iter()
-        .step_by(2)
-        .map(|x| Rc::new(x))
-        .collect::<Vec<_>>()
-    } else if conv_config.activation == ActivationType::None {
-      outp
-        .into_iter()
-        .skip(1)
-        .step_by(2)
-        .map(|x| Rc::new(x))
-        .collect::<Vec<_>>()
-    } else if conv_config.activation == ActivationType::Relu {
-      let dived = outp.iter().skip(1).step_by(2).collect::<Vec<_>>();
-      let relu_chip = ReluChip::<F>::construct(gadget_config.clone());
-      let relu_outp = relu_chip
-        .forward(layouter.namespace(|| "relu"), &vec![dived], &tmp)
-        .unwrap();
-      let relu_outp = relu_outp
-        .into_iter()
-        .map(|x| Rc::new(x))
-        .collect::<Vec<_>>();
-      relu_outp
-    } else {
-      panic!("Unsupported activation type");
-    };
+    let outp = Self::bias_div_activation(
+      layouter.namespace(|| "conv 2d bias"),
+      outp_flat.iter().collect(),
+      splat_biases.iter().map(|x| x.as_ref()).collect(),
+      &conv_config.activation,
+      zero.as_ref(),
+      gadget_config.clone(),
+    )?;
 
     let oc = match conv_config.conv_type {
       ConvLayerEnum::Conv2D => weights.shape()[0],
@@ -440,11 +518,12 @@ impl<F: PrimeField> GadgetConsumer for Conv2DChip<F> {
       GadgetType::Adder,
       GadgetType::DotProduct,
       GadgetType::InputLookup,
-      GadgetType::BiasDivRoundRelu6,
     ];
 
     if conv_config.activation == ActivationType::Relu {
-      outp.push(GadgetType::Relu);
+      outp.push(GadgetType::BiasDivRoundRelu);
+    } else {
+      outp.push(GadgetType::BiasDivRoundRelu6);
     }
 
     outp
@@ -0,0 +1,241 @@
+use std::{collections::HashMap, marker::PhantomData, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{s, Array, IxDyn};
+
+use crate::{
+  gadgets::gadget::{GadgetConfig, GadgetType},
+  layers::{
+    conv2d::{Conv2DChip, PaddingEnum},
+    fully_connected::{FullyConnectedChip, FullyConnectedConfig},
+    shape::pad::pad,
+  },
+};
+
+use super::layer::{ActivationType, AssignedTensor, GadgetConsumer, Layer, LayerConfig};
+
+// Params: [padding, activation, stride d, stride h, stride w]
+// For video and volumetric models. The input is [1, D, H, W, C], the weights [O, KD, KH, KW, I],
+// and the optional biases [O]. As in Conv2D, every output position is a row of the im2col matrix,
+// so the convolution is a single matrix product, followed by the bias, rescaling, and activation.
+pub struct Conv3DConfig {
+  pub padding: PaddingEnum,
+  pub activation: ActivationType,
+  pub stride: [usize; 3],
+}
+
+pub struct Conv3DChip<F: PrimeField> {
+  pub config: LayerConfig,
+  pub _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> Conv3DChip<F> {
+  pub fn param_vec_to_config(layer_params: Vec<i64>) -> Conv3DConfig {
+    let padding = match layer_params[0] {
+      0 => PaddingEnum::Same,
+      1 => PaddingEnum::Valid,
+      _ => panic!("Invalid padding"),
+    };
+    let activation = match layer_params[1] {
+      0 => ActivationType::None,
+      1 => ActivationType::Relu,
+      3 => ActivationType::Relu6,
+      _ => panic!("Invalid activation type"),
+    };
+    let stride = [
+      layer_params[2] as usize,
+      layer_params[3] as usize,
+      layer_params[4] as usize,
+    ];
+    assert!(stride.iter().all(|s| *s > 0), "Invalid stride");
+    Conv3DConfig {
+      padding,
+      activation,
+      stride,
+    }
+  }
+
+  // The TF style SAME padding (begin, end) of an axis, which puts the extra row at the end
+  pub fn same_padding(len: usize, stride: usize, kernel: usize) -> (usize, usize) {
+    let total = if len % stride == 0 {
+      kernel.saturating_sub(stride)
+    } else {
+      kernel.saturating_sub(len % stride)
+    };
+    (total / 2, total - total / 2)
+  }
+
+  pub fn out_len(len: usize, stride: usize, kernel: usize, padding: PaddingEnum) -> usize {
+    match padding {
+      PaddingEnum::Same => (len + stride - 1) / stride,
+      PaddingEnum::Valid => (len - kernel) / stride + 1,
+    }
+  }
+
+  // The output depth, height, and width
+  pub fn out_dhw(&self, inp_shape: &[usize], weights_shape: &[usize]) -> [usize; 3] {
+    let conv_config = Self::param_vec_to_config(self.config.layer_params.clone());
+    let mut outp = [0; 3];
+    for axis in 0..3 {
+      outp[axis] = Self::out_len(
+        inp_shape[axis + 1],
+        conv_config.stride[axis],
+        weights_shape[axis + 1],
+        conv_config.padding,
+      );
+    }
+    outp
+  }
+
+  pub fn splat<G: Clone>(
+    &self,
+    tensors: &Vec<Array<Rc<G>, IxDyn>>,
+    zero: Rc<G>,
+  ) -> (Vec<Vec<Rc<G>>>, Vec<Vec<Rc<G>>>, Vec<Rc<G>>) {
+    assert!(tensors.len() == 2 || tensors.len() == 3);
+    let conv_config = Self::param_vec_to_config(self.config.layer_params.clone());
+
+    let inp = &tensors[0];
+    let weights = &tensors[1];
+    // B, D, H, W, C
+    assert_eq!(inp.shape().len(), 5);
+    assert_eq!(weights.shape().len(), 5);
+    // Only support batch size 1 for now
+    assert_eq!(inp.shape()[0], 1);
+    assert_eq!(inp.shape()[4], weights.shape()[4]);
+
+    let (kd, kh, kw) = (weights.shape()[1], weights.shape()[2], weights.shape()[3]);
+    let [sd, sh, sw] = conv_config.stride;
+    let mut padding = vec![[0, 0]; 5];
+    if conv_config.padding == PaddingEnum::Same {
+      for axis in 0..3 {
+        let (lo, hi) = Self::same_padding(
+          inp.shape()[axis + 1],
+          conv_config.stride[axis],
+          weights.shape()[axis + 1],
+        );
+        padding[axis + 1] = [lo, hi];
+      }
+    }
+    let inp_pad = pad(inp, padding, &zero);
+    let [od, oh, ow] = self.out_dhw(inp.shape(), weights.shape());
+
+    // (output channels x KD * KH * KW * input channels)
+    let weights_cells = (0..weights.shape()[0])
+      .map(|chan_out| {
+        weights
+          .slice(s![chan_out, .., .., .., ..])
+          .iter()
+          .cloned()
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    // (OD * OH * OW x KD * KH * KW * input channels)
+    let mut inp_cells = vec![];
+    for i in 0..od {
+      for j in 0..oh {
+        for k in 0..ow {
+          let window = inp_pad.slice(s![
+            0,
+            i * sd..i * sd + kd,
+            j * sh..j * sh + kh,
+            k * sw..k * sw + kw,
+            ..
+          ]);
+          inp_cells.push(window.iter().cloned().collect::<Vec<_>>());
+        }
+      }
+    }
+
+    let biases_cells = (0..od * oh * ow)
+      .flat_map(|_| {
+        (0..weights.shape()[0]).map(|chan_out| match tensors.get(2) {
+          Some(biases) => biases[chan_out].clone(),
+          None => zero.clone(),
+        })
+      })
+      .collect::<Vec<_>>();
+
+    (inp_cells, weights_cells, biases_cells)
+  }
+}
+
+impl<F: PrimeField> Layer<F> for Conv3DChip<F> {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, Rc<AssignedCell<F, F>>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let conv_config = Self::param_vec_to_config(self.config.layer_params.clone());
+    let zero = constants.get(&0).unwrap();
+    let inp = &tensors[0];
+    let weights = &tensors[1];
+    let [od, oh, ow] = self.out_dhw(inp.shape(), weights.shape());
+    let out_channels = weights.shape()[0];
+
+    let (splat_inp, splat_weights, splat_biases) = self.splat(tensors, zero.clone());
+    let positions = splat_inp.len();
+    let conv_size = splat_inp[0].len();
+    let flattened_inp = splat_inp.into_iter().flatten().collect::<Vec<_>>();
+    let flattened_weights = splat_weights.into_iter().flatten().collect::<Vec<_>>();
+    let inp_array =
+      Array::from_shape_vec(IxDyn(&vec![positions, conv_size]), flattened_inp).unwrap();
+    let weights_array =
+      Array::from_shape_vec(IxDyn(&vec![out_channels, conv_size]), flattened_weights).unwrap();
+
+    let fc_chip = FullyConnectedChip::<F> {
+      _marker: PhantomData,
+      config: FullyConnectedConfig::construct(false),
+    };
+    let outp_slice = fc_chip.forward(
+      layouter.namespace(|| "conv 3d matmul"),
+      &vec![weights_array, inp_array],
+      constants,
+      gadget_config.clone(),
+      layer_config,
+    )?;
+    let outp_flat = outp_slice[0]
+      .t()
+      .into_iter()
+      .map(|x| (**x).clone())
+      .collect::<Vec<_>>();
+
+    let outp = Conv2DChip::<F>::bias_div_activation(
+      layouter.namespace(|| "conv 3d bias"),
+      outp_flat.iter().collect(),
+      splat_biases.iter().map(|x| x.as_ref()).collect(),
+      &conv_config.activation,
+      zero.as_ref(),
+      gadget_config.clone(),
+    )?;
+
+    let out_shape = vec![1, od, oh, ow, out_channels];
+    let outp = Array::from_shape_vec(IxDyn(&out_shape), outp).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl<F: PrimeField> GadgetConsumer for Conv3DChip<F> {
+  fn used_gadgets(&self, layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    let conv_config = Self::param_vec_to_config(layer_params);
+    let bdr = if conv_config.activation == ActivationType::Relu {
+      GadgetType::BiasDivRoundRelu
+    } else {
+      GadgetType::BiasDivRoundRelu6
+    };
+    vec![
+      GadgetType::Adder,
+      GadgetType::DotProduct,
+      GadgetType::InputLookup,
+      bdr,
+    ]
+  }
+}
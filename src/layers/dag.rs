@@ -5,27 +5,44 @@ use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error}
 use crate::{
   gadgets::gadget::{convert_to_u64, GadgetConfig},
   layers::{
+    activation::{Activation, ActivationChip},
     arithmetic::{add::AddChip, div_var::DivVarChip, mul::MulChip, sub::SubChip},
+    attribution::AttributionChip,
     batch_mat_mul::BatchMatMulChip,
+    branch::BranchChip,
     div_fixed::DivFixedChip,
+    div_mod::DivModChip,
+    dropout::DropoutChip,
     fully_connected::{FullyConnectedChip, FullyConnectedConfig},
+    layer_norm::LayerNormChip,
     logistic::LogisticChip,
     max_pool_2d::MaxPool2DChip,
+    max_pool_3d::MaxPool3DChip,
     mean::MeanChip,
+    moe::MoEChip,
     noop::NoopChip,
     pow::PowChip,
+    predicate::PredicateChip,
+    quantile::QuantileChip,
+    range_check::RangeCheckChip,
+    recurrent::{RecurrentCell, RecurrentChip},
+    requantize::RequantizeChip,
+    robustness::RobustnessChip,
     rsqrt::RsqrtChip,
     shape::{
-      broadcast::BroadcastChip, concatenation::ConcatenationChip, mask_neg_inf::MaskNegInfChip,
-      pack::PackChip, pad::PadChip, permute::PermuteChip, reshape::ReshapeChip,
-      resize_nn::ResizeNNChip, rotate::RotateChip, slice::SliceChip, split::SplitChip,
-      transpose::TransposeChip,
+      broadcast::BroadcastChip, concatenation::ConcatenationChip, gather::GatherChip,
+      mask_neg_inf::MaskNegInfChip, occlude::OccludeChip, pack::PackChip, pad::PadChip,
+      permute::PermuteChip, reshape::ReshapeChip, resize_nn::ResizeNNChip, rotate::RotateChip,
+      scatter_nd::ScatterNDChip, slice::SliceChip, split::SplitChip, transpose::TransposeChip,
     },
     softmax::SoftmaxChip,
     sqrt::SqrtChip,
     square::SquareChip,
     squared_diff::SquaredDiffChip,
+    tabulated::TabulatedChip,
     tanh::TanhChip,
+    top_k::TopKChip,
+    tree_ensemble::TreeEnsembleChip,
     update::UpdateChip,
   },
   utils::helpers::print_assigned_arr,
@@ -33,7 +50,9 @@ use crate::{
 
 use super::{
   avg_pool_2d::AvgPool2DChip,
+  avg_pool_3d::AvgPool3DChip,
   conv2d::Conv2DChip,
+  conv3d::Conv3DChip,
   layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig, LayerType},
 };
 
@@ -120,6 +139,28 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::AvgPool3D => {
+          let avg_pool_3d_chip = AvgPool3DChip {};
+          avg_pool_3d_chip.forward(
+            layouter.namespace(|| "dag avg pool 3d"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::MaxPool3D => {
+          let max_pool_3d_chip = MaxPool3DChip {
+            marker: PhantomData::<F>,
+          };
+          max_pool_3d_chip.forward(
+            layouter.namespace(|| "dag max pool 3d"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::BatchMatMul => {
           let batch_mat_mul_chip = BatchMatMulChip {};
           batch_mat_mul_chip.forward(
@@ -153,6 +194,19 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::Conv3D => {
+          let conv_3d_chip = Conv3DChip {
+            config: layer_config.clone(),
+            _marker: PhantomData,
+          };
+          conv_3d_chip.forward(
+            layouter.namespace(|| "dag conv 3d"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::DivFixed => {
           let div_fixed_chip = DivFixedChip {};
           div_fixed_chip.forward(
@@ -163,6 +217,26 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::DivMod => {
+          let div_mod_chip = DivModChip {};
+          div_mod_chip.forward(
+            layouter.namespace(|| "dag div mod"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Dropout => {
+          let dropout_chip = DropoutChip {};
+          dropout_chip.forward(
+            layouter.namespace(|| "dag dropout"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::DivVar => {
           let div_var_chip = DivVarChip {};
           div_var_chip.forward(
@@ -206,6 +280,16 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::MoE => {
+          let moe_chip = MoEChip {};
+          moe_chip.forward(
+            layouter.namespace(|| "dag moe"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::Pad => {
           let pad_chip = PadChip {};
           pad_chip.forward(
@@ -236,6 +320,16 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::LayerNorm => {
+          let layer_norm_chip = LayerNormChip {};
+          layer_norm_chip.forward(
+            layouter.namespace(|| "dag layer norm"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::Rsqrt => {
           let rsqrt_chip = RsqrtChip {};
           rsqrt_chip.forward(
@@ -276,6 +370,56 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::Predicate => {
+          let predicate_chip = PredicateChip {};
+          predicate_chip.forward(
+            layouter.namespace(|| "dag predicate"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Quantile => {
+          let quantile_chip = QuantileChip {};
+          quantile_chip.forward(
+            layouter.namespace(|| "dag quantile"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Gelu | LayerType::Silu => {
+          let activation = if *layer_type == LayerType::Gelu {
+            Activation::Gelu
+          } else {
+            Activation::Silu
+          };
+          let activation_chip = ActivationChip { activation };
+          activation_chip.forward(
+            layouter.namespace(|| "dag activation"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Lstm | LayerType::Gru => {
+          let cell = if *layer_type == LayerType::Lstm {
+            RecurrentCell::Lstm
+          } else {
+            RecurrentCell::Gru
+          };
+          let recurrent_chip = RecurrentChip { cell };
+          recurrent_chip.forward(
+            layouter.namespace(|| "dag recurrent"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::Tanh => {
           let tanh_chip = TanhChip {};
           tanh_chip.forward(
@@ -286,6 +430,46 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::TopK => {
+          let top_k_chip = TopKChip {};
+          top_k_chip.forward(
+            layouter.namespace(|| "dag top k"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::RangeCheck => {
+          let range_check_chip = RangeCheckChip {};
+          range_check_chip.forward(
+            layouter.namespace(|| "dag range check"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Requantize => {
+          let requantize_chip = RequantizeChip {};
+          requantize_chip.forward(
+            layouter.namespace(|| "dag requantize"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Tabulated => {
+          let tabulated_chip = TabulatedChip {};
+          tabulated_chip.forward(
+            layouter.namespace(|| "dag tabulated"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::Mul => {
           let mul_chip = MulChip {};
           mul_chip.forward(
@@ -326,6 +510,16 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::TreeEnsemble => {
+          let tree_ensemble_chip = TreeEnsembleChip {};
+          tree_ensemble_chip.forward(
+            layouter.namespace(|| "dag tree ensemble"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::Reshape => {
           let reshape_chip = ReshapeChip {};
           reshape_chip.forward(
@@ -336,6 +530,16 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::Robustness => {
+          let robustness_chip = RobustnessChip {};
+          robustness_chip.forward(
+            layouter.namespace(|| "dag robustness"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::ResizeNN => {
           let resize_nn_chip = ResizeNNChip {};
           resize_nn_chip.forward(
@@ -406,6 +610,56 @@ impl<F: PrimeField + Ord> DAGLayerChip<F> {
             &layer_config,
           )?
         }
+        LayerType::Gather => {
+          let gather_chip = GatherChip {};
+          gather_chip.forward(
+            layouter.namespace(|| "dag gather"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::ScatterND => {
+          let scatter_nd_chip = ScatterNDChip {};
+          scatter_nd_chip.forward(
+            layouter.namespace(|| "dag scatter nd"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Occlude => {
+          let occlude_chip = OccludeChip {};
+          occlude_chip.forward(
+            layouter.namespace(|| "dag occlude"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Attribution => {
+          let attribution_chip = AttributionChip {};
+          attribution_chip.forward(
+            layouter.namespace(|| "dag attribution"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
+        LayerType::Branch => {
+          let branch_chip = BranchChip {};
+          branch_chip.forward(
+            layouter.namespace(|| "dag branch"),
+            &vec_inps,
+            constants,
+            gadget_config.clone(),
+            &layer_config,
+          )?
+        }
         LayerType::MaskNegInf => {
           let mask_neg_inf_chip = MaskNegInfChip {};
           mask_neg_inf_chip.forward(
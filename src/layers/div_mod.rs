@@ -0,0 +1,106 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  int_div::{IntDivChip, IntDivMode},
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Integer division and modulo of the raw values by constant divisors, with the rounding of the
+// framework being matched, e.g., floor for Python and NumPy, truncation for C and ONNX Mod with
+// fmod, round half even for the requantization of some runtimes, and round half away from zero.
+// Params: [mode, output, divisor...], with the mode 0 for floor, 1 for round half even, 2 for
+// truncation, and 3 for round half away, and the output 0 for the quotient, 1 for the remainder,
+// and 2 for both, as two outputs. There is one divisor for the tensor or one per channel of the
+// last axis. The divisors are constants, so they are fixed by the circuit.
+#[derive(Clone, Debug)]
+pub struct DivModChip {}
+
+impl DivModChip {
+  pub fn constants(layer_params: &Vec<i64>) -> Vec<i64> {
+    layer_params[2..].to_vec()
+  }
+}
+
+impl<F: PrimeField> Layer<F> for DivModChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let params = &layer_config.layer_params;
+    assert!(params.len() >= 3, "malformed div mod params");
+    let mode = IntDivMode::from_param(params[0]);
+    let output = params[1];
+    assert!(
+      output >= 0 && output <= 2,
+      "invalid div mod output {}",
+      output
+    );
+    let divisors = &params[2..];
+    for d in divisors.iter() {
+      assert!(
+        *d > 0 && 2 * *d < gadget_config.num_rows as i64,
+        "divisor {} out of the lookup range",
+        d
+      );
+    }
+
+    let zero = constants.get(&0).unwrap().as_ref();
+    let inp = &tensors[0];
+    let num_channels = divisors.len();
+    assert!(
+      num_channels == 1 || inp.shape().last() == Some(&num_channels),
+      "{} divisors for a last axis of {:?}",
+      num_channels,
+      inp.shape().last()
+    );
+
+    let chip = IntDivChip::<F>::construct(gadget_config.clone(), mode);
+    let flat = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let mut quotients = vec![None; flat.len()];
+    let mut remainders = vec![None; flat.len()];
+    for (c, d) in divisors.iter().enumerate() {
+      let idxes = (c..flat.len()).step_by(num_channels).collect::<Vec<_>>();
+      let x = idxes.iter().map(|i| flat[*i]).collect::<Vec<_>>();
+      let d = constants.get(d).unwrap().as_ref();
+      let res = chip.forward(
+        layouter.namespace(|| format!("div mod channel {}", c)),
+        &vec![x],
+        &vec![zero, d],
+      )?;
+      let (q, r) = res.split_at(idxes.len());
+      for (j, i) in idxes.iter().enumerate() {
+        quotients[*i] = Some(Rc::new(q[j].clone()));
+        remainders[*i] = Some(Rc::new(r[j].clone()));
+      }
+    }
+
+    let to_tensor = |cells: Vec<Option<CellRc<F>>>| {
+      let cells = cells.into_iter().map(|x| x.unwrap()).collect::<Vec<_>>();
+      Array::from_shape_vec(IxDyn(inp.shape()), cells).unwrap()
+    };
+    let outp = match output {
+      0 => vec![to_tensor(quotients)],
+      1 => vec![to_tensor(remainders)],
+      _ => vec![to_tensor(quotients), to_tensor(remainders)],
+    };
+    Ok(outp)
+  }
+}
+
+impl GadgetConsumer for DivModChip {
+  fn used_gadgets(&self, layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      IntDivMode::from_param(layer_params[0]).gadget_type(),
+      GadgetType::InputLookup,
+    ]
+  }
+}
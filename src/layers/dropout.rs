@@ -0,0 +1,113 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  prf_limbs::uniform_bits,
+  var_div::VarDivRoundChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Dropout at test time, for MC-dropout uncertainty. Params: [keep probability in fixed point].
+// The inputs are x and a uniform u of uniform_bits(k) bits per element, which the circuit derives
+// from the seed (see randomness.rs). An element is kept if u < t = keep * 2^b, and scaled by
+// 1 / keep: y = round(x * [u < t] * m / sf), with m = round(sf / keep) in fixed point. t - 1 and
+// m are constants, so they are fixed by the circuit.
+#[derive(Clone, Debug)]
+pub struct DropoutChip {}
+
+impl DropoutChip {
+  // (t - 1, m)
+  pub fn thresholds(layer_params: &Vec<i64>, sf: i64, k: usize) -> (i64, i64) {
+    let keep = layer_params[0];
+    assert!(
+      keep > 0 && keep <= sf,
+      "keep probability {} out of range",
+      keep
+    );
+    let range = 1i64 << uniform_bits(k);
+    let threshold = ((keep as i128 * range as i128 + sf as i128 / 2) / sf as i128) as i64;
+    let multiplier = (sf * sf + keep / 2) / keep;
+    (threshold.min(range) - 1, multiplier)
+  }
+
+  pub fn constants(layer_params: &Vec<i64>, gadget_config: &GadgetConfig) -> Vec<i64> {
+    let sf = gadget_config.scale_factor as i64;
+    let (max_kept, multiplier) = Self::thresholds(layer_params, sf, gadget_config.k);
+    vec![max_kept, multiplier]
+  }
+}
+
+impl<F: PrimeField> Layer<F> for DropoutChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    assert_eq!(tensors.len(), 2, "dropout takes the input and its uniforms");
+    let (inp, uniforms) = (&tensors[0], &tensors[1]);
+    assert_eq!(inp.len(), uniforms.len());
+    let zero = constants.get(&0).unwrap().as_ref();
+    let sf = gadget_config.scale_factor as i64;
+    let (max_kept, multiplier) =
+      DropoutChip::thresholds(&layer_config.layer_params, sf, gadget_config.k);
+    let max_kept = constants.get(&max_kept).unwrap().as_ref();
+    let multiplier = constants.get(&multiplier).unwrap().as_ref();
+    let div = constants.get(&sf).unwrap().as_ref();
+
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let kept = comparator_chip.forward(
+      layouter.namespace(|| "dropout mask"),
+      &vec![
+        uniforms.iter().map(|x| x.as_ref()).collect(),
+        vec![max_kept; inp.len()],
+      ],
+      &vec![zero],
+    )?;
+
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let scales = mul_pairs_chip.forward(
+      layouter.namespace(|| "dropout scales"),
+      &vec![kept.iter().collect(), vec![multiplier; inp.len()]],
+      &vec![zero],
+    )?;
+    let out = mul_pairs_chip.forward(
+      layouter.namespace(|| "dropout mul"),
+      &vec![
+        inp.iter().map(|x| x.as_ref()).collect(),
+        scales.iter().collect(),
+      ],
+      &vec![zero],
+    )?;
+
+    let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
+    let out = var_div_chip.forward(
+      layouter.namespace(|| "dropout div"),
+      &vec![out.iter().collect()],
+      &vec![zero, div],
+    )?;
+
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(inp.shape()), out).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for DropoutChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Comparator,
+      GadgetType::MulPairs,
+      GadgetType::VarDivRound,
+      GadgetType::InputLookup,
+    ]
+  }
+}
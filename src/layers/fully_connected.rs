@@ -7,6 +7,8 @@ use halo2_proofs::{
 };
 use ndarray::{Array, ArrayView, Axis, IxDyn};
 
+#[cfg(feature = "parallel-witness")]
+use crate::utils::parallel::{known_values, matmul};
 use crate::{
   gadgets::{
     add_pairs::AddPairsChip,
@@ -45,6 +47,18 @@ impl<F: PrimeField> FullyConnectedChip<F> {
     assert_eq!(input.ndim(), 2);
     assert_eq!(weight.ndim(), 2);
     assert_eq!(input.shape()[1], weight.shape()[0]);
+    let out_shape = [input.shape()[0], weight.shape()[1]];
+
+    // The values are computed on the witness pool, unless they're unknown
+    #[cfg(feature = "parallel-witness")]
+    if let (Some(lhs), Some(rhs)) = (
+      known_values(input.iter().map(|x| x.as_ref())),
+      known_values(weight.iter().map(|x| x.as_ref())),
+    ) {
+      let outp = matmul(&lhs, &rhs, out_shape[0], input.shape()[1], out_shape[1]);
+      let outp = outp.into_iter().map(Value::known).collect();
+      return Array::from_shape_vec(IxDyn(out_shape.as_slice()), outp).unwrap();
+    }
 
     let mut outp = vec![];
     for i in 0..input.shape()[0] {
@@ -57,21 +71,37 @@ impl<F: PrimeField> FullyConnectedChip<F> {
       }
     }
 
-    let out_shape = [input.shape()[0], weight.shape()[1]];
     Array::from_shape_vec(IxDyn(out_shape.as_slice()), outp).unwrap()
   }
 
+  // The array is assigned in tiles of tile_size rows (0 for a single tile). Within a tile, every
+  // column of the array goes down a single advice column, so the copies into the column dot
+  // products of the Freivalds check stay within a column. A tile size of 1 is row major.
   pub fn assign_array(
     columns: &Vec<Column<Advice>>,
     region: &mut Region<F>,
     array: &Array<Value<F>, IxDyn>,
+    tile_size: usize,
   ) -> Result<Array<AssignedCell<F, F>, IxDyn>, Error> {
     assert_eq!(array.ndim(), 2);
+    let (num_rows, num_cols) = (array.shape()[0], array.shape()[1]);
+    let tile_size = if tile_size == 0 {
+      num_rows
+    } else {
+      tile_size.min(num_rows)
+    };
+    let num_groups = (num_cols + columns.len() - 1) / columns.len();
 
     let mut outp = vec![];
-    for (idx, val) in array.iter().enumerate() {
-      let row_idx = idx / columns.len();
-      let col_idx = idx % columns.len();
+    for ((i, j), val) in array
+      .iter()
+      .enumerate()
+      .map(|(idx, val)| ((idx / num_cols, idx % num_cols), val))
+    {
+      let tile = i / tile_size;
+      let tile_rows = tile_size.min(num_rows - tile * tile_size);
+      let row_idx = tile * tile_size * num_groups + (j / columns.len()) * tile_rows + i % tile_size;
+      let col_idx = j % columns.len();
       let cell = region
         .assign_advice(|| "assign array", columns[col_idx], row_idx, || *val)
         .unwrap();
@@ -137,8 +167,13 @@ impl<F: PrimeField> Layer<F> for FullyConnectedChip<F> {
         || "compute and assign mm",
         |mut region| {
           let mm_result = Self::compute_mm(&input, weight);
-          let mm_result =
-            Self::assign_array(&gadget_config.columns, &mut region, &mm_result).unwrap();
+          let mm_result = Self::assign_array(
+            &gadget_config.columns,
+            &mut region,
+            &mm_result,
+            gadget_config.gemm_tile_size,
+          )
+          .unwrap();
 
           Ok(mm_result)
         },
@@ -12,29 +12,50 @@ use crate::gadgets::gadget::{GadgetConfig, GadgetType};
 #[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
 pub enum LayerType {
   Add,
+  Attribution,
   AvgPool2D,
+  AvgPool3D,
   BatchMatMul,
+  Branch,
   Broadcast,
   Concatenation,
   Conv2D,
+  Conv3D,
   DivVar,
   DivFixed,
+  DivMod,
+  Dropout,
   FullyConnected,
+  Gather,
+  Gelu,
+  Gru,
+  LayerNorm,
   Logistic,
+  Lstm,
   MaskNegInf,
   MaxPool2D,
+  MaxPool3D,
   Mean,
+  MoE,
   Mul,
   #[default]
   Noop,
+  Occlude,
   Pack,
   Pad,
   Pow,
   Permute,
+  Predicate,
+  Quantile,
+  RangeCheck,
+  Requantize,
   Reshape,
   ResizeNN,
+  Robustness,
   Rotate,
   Rsqrt,
+  ScatterND,
+  Silu,
   Slice,
   Softmax,
   Split,
@@ -42,8 +63,11 @@ pub enum LayerType {
   Square,
   SquaredDifference,
   Sub,
+  Tabulated,
   Tanh,
+  TopK,
   Transpose,
+  TreeEnsemble,
   Update,
 }
 
@@ -0,0 +1,208 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  add_pairs::AddPairsChip,
+  adder::AdderChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  nonlinear::rsqrt::RsqrtGadgetChip,
+  sub_pairs::SubPairsChip,
+  var_div::VarDivRoundChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Normalizes over the last axis: (x - mean) * rsqrt(var + eps) * gamma + beta, as in transformers.
+// The inputs are x, gamma, and beta, the last two with one value per element of the last axis.
+// The only param is eps, quantized (at least 1, since the rsqrt of zero is out of range). Every
+// product is rescaled by sf, and the variance is the mean of the rescaled squares.
+#[derive(Clone, Debug)]
+pub struct LayerNormChip {}
+
+impl LayerNormChip {
+  // The length of the last axis (the divisor of the means) and eps are constants of the circuit
+  pub fn constants(layer_config: &LayerConfig) -> Vec<i64> {
+    let row_len = *layer_config.inp_shapes[0].last().unwrap() as i64;
+    vec![row_len, layer_config.layer_params[0]]
+  }
+
+  // Products of pairs, rescaled by sf
+  fn mul_rescale<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    a: Vec<&AssignedCell<F, F>>,
+    b: Vec<&AssignedCell<F, F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
+    let zero = constants.get(&0).unwrap().as_ref();
+    let sf = constants
+      .get(&(gadget_config.scale_factor as i64))
+      .unwrap()
+      .as_ref();
+
+    let prod = mul_pairs_chip.forward(layouter.namespace(|| "mul"), &vec![a, b], &vec![zero])?;
+    var_div_chip.forward(
+      layouter.namespace(|| "rescale"),
+      &vec![prod.iter().collect()],
+      &vec![zero, sf],
+    )
+  }
+
+  // The means of the rows
+  fn row_means<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    rows: &Vec<Vec<&AssignedCell<F, F>>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
+    let zero = constants.get(&0).unwrap().as_ref();
+    let row_len = constants.get(&(rows[0].len() as i64)).unwrap().as_ref();
+
+    let mut sums = vec![];
+    for (i, row) in rows.iter().enumerate() {
+      let sum = adder_chip.forward(
+        layouter.namespace(|| format!("sum {}", i)),
+        &vec![row.clone()],
+        &vec![zero],
+      )?;
+      sums.push(sum[0].clone());
+    }
+    var_div_chip.forward(
+      layouter.namespace(|| "mean"),
+      &vec![sums.iter().collect()],
+      &vec![zero, row_len],
+    )
+  }
+}
+
+impl<F: PrimeField> Layer<F> for LayerNormChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let gamma = tensors[1].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let beta = tensors[2].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let row_len = *inp.shape().last().unwrap();
+    assert_eq!(gamma.len(), row_len);
+    assert_eq!(beta.len(), row_len);
+
+    let zero = constants.get(&0).unwrap().as_ref();
+    let eps = constants
+      .get(&layer_config.layer_params[0])
+      .unwrap()
+      .as_ref();
+    let min_val = constants.get(&gadget_config.min_val).unwrap().as_ref();
+    let max_val = constants.get(&gadget_config.max_val).unwrap().as_ref();
+
+    let add_pairs_chip = AddPairsChip::<F>::construct(gadget_config.clone());
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+    let rsqrt_chip = RsqrtGadgetChip::<F>::construct(gadget_config.clone());
+
+    let inp_flat = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let rows = inp_flat
+      .chunks(row_len)
+      .map(|row| row.to_vec())
+      .collect::<Vec<_>>();
+    let num_rows = rows.len();
+    // Repeats one value per row over the row
+    let splat = |vals: &Vec<AssignedCell<F, F>>| {
+      vals
+        .iter()
+        .flat_map(|x| vec![x; row_len])
+        .collect::<Vec<_>>()
+    };
+
+    let mean = Self::row_means(
+      layouter.namespace(|| "layer norm mean"),
+      &rows,
+      constants,
+      gadget_config.clone(),
+    )?;
+    let centered = sub_pairs_chip.forward(
+      layouter.namespace(|| "layer norm center"),
+      &vec![inp_flat.clone(), splat(&mean)],
+      &vec![zero],
+    )?;
+
+    let sq = Self::mul_rescale(
+      layouter.namespace(|| "layer norm square"),
+      centered.iter().collect(),
+      centered.iter().collect(),
+      constants,
+      gadget_config.clone(),
+    )?;
+    let sq_rows = sq.chunks(row_len).map(|row| row.iter().collect()).collect();
+    let var = Self::row_means(
+      layouter.namespace(|| "layer norm var"),
+      &sq_rows,
+      constants,
+      gadget_config.clone(),
+    )?;
+    let var_eps = add_pairs_chip.forward(
+      layouter.namespace(|| "layer norm eps"),
+      &vec![var.iter().collect(), vec![eps; num_rows]],
+      &vec![zero],
+    )?;
+    let inv_std = rsqrt_chip.forward(
+      layouter.namespace(|| "layer norm rsqrt"),
+      &vec![var_eps.iter().collect()],
+      &vec![zero, min_val, max_val],
+    )?;
+
+    let normed = Self::mul_rescale(
+      layouter.namespace(|| "layer norm normalize"),
+      centered.iter().collect(),
+      splat(&inv_std),
+      constants,
+      gadget_config.clone(),
+    )?;
+    let gamma = gamma.iter().cycle().take(inp_flat.len()).cloned().collect();
+    let scaled = Self::mul_rescale(
+      layouter.namespace(|| "layer norm gamma"),
+      normed.iter().collect(),
+      gamma,
+      constants,
+      gadget_config.clone(),
+    )?;
+    let beta = beta.iter().cycle().take(inp_flat.len()).cloned().collect();
+    let outp = add_pairs_chip.forward(
+      layouter.namespace(|| "layer norm beta"),
+      &vec![scaled.iter().collect(), beta],
+      &vec![zero],
+    )?;
+
+    let outp = outp.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let outp = Array::from_shape_vec(IxDyn(inp.shape()), outp).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for LayerNormChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::AddPairs,
+      GadgetType::SubPairs,
+      GadgetType::MulPairs,
+      GadgetType::VarDivRound,
+      GadgetType::Rsqrt,
+      GadgetType::InputLookup,
+    ]
+  }
+}
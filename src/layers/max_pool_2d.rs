@@ -5,19 +5,93 @@ use ndarray::{Array, IxDyn};
 
 use crate::{
   gadgets::{
+    add_pairs::AddPairsChip,
+    comparator::ComparatorChip,
     gadget::{Gadget, GadgetConfig, GadgetType},
     max::MaxChip,
+    mul_pairs::MulPairsChip,
+    sub_pairs::SubPairsChip,
   },
   layers::conv2d::{Conv2DChip, PaddingEnum},
 };
 
 use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
 
+// Params: [filter h, filter w, stride h, stride w, (export argmax)]
+// With the optional export flag, the layer has a second output with the position of the max in
+// every window, row major within the window. Ties go to the first position. The positions are
+// plain integers, not fixed point.
 pub struct MaxPool2DChip<F: PrimeField> {
   pub marker: std::marker::PhantomData<F>,
 }
 
 impl<F: PrimeField> MaxPool2DChip<F> {
+  pub fn exports_argmax(layer_params: &Vec<i64>) -> bool {
+    layer_params.get(4).map_or(false, |x| *x != 0)
+  }
+
+  // Marks the first position equal to the max, which the max is known to bound, and counts the
+  // positions up to it: with not_found_i = 1 until the max is found at i and 0 after,
+  // argmax = sum_{i >= 1} not_found_i
+  pub fn argmax(
+    mut layouter: impl Layouter<F>,
+    splat: &Vec<Vec<CellRc<F>>>,
+    maxes: &Vec<CellRc<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<CellRc<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let window = splat[0].len();
+    assert!(splat.iter().all(|inps| inps.len() == window));
+
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+    let add_pairs_chip = AddPairsChip::<F>::construct(gadget_config.clone());
+
+    // is_max[i][w] = [max_w <= x_w,i]
+    let mut is_max = vec![];
+    for i in 0..window {
+      let inps = splat
+        .iter()
+        .map(|inps| inps[i].as_ref())
+        .collect::<Vec<_>>();
+      let maxes = maxes.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+      is_max.push(comparator_chip.forward(
+        layouter.namespace(|| format!("argmax is max {}", i)),
+        &vec![maxes, inps],
+        &vec![zero],
+      )?);
+    }
+
+    let mut not_found = vec![one.clone(); splat.len()];
+    let mut argmax: Option<Vec<_>> = None;
+    for i in 0..window - 1 {
+      let first = mul_pairs_chip.forward(
+        layouter.namespace(|| format!("argmax first {}", i)),
+        &vec![is_max[i].iter().collect(), not_found.iter().collect()],
+        &vec![zero],
+      )?;
+      not_found = sub_pairs_chip.forward(
+        layouter.namespace(|| format!("argmax not found {}", i)),
+        &vec![not_found.iter().collect(), first.iter().collect()],
+        &vec![zero],
+      )?;
+      argmax = Some(match argmax {
+        None => not_found.clone(),
+        Some(argmax) => add_pairs_chip.forward(
+          layouter.namespace(|| format!("argmax count {}", i)),
+          &vec![argmax.iter().collect(), not_found.iter().collect()],
+          &vec![zero],
+        )?,
+      });
+    }
+
+    let argmax = argmax.unwrap_or(vec![zero.clone(); splat.len()]);
+    Ok(argmax.into_iter().map(|x| Rc::new(x)).collect())
+  }
+
   pub fn shape(inp: &AssignedTensor<F>, layer_config: &LayerConfig) -> (usize, usize) {
     let params = &layer_config.layer_params;
     let (fx, fy) = (params[0], params[1]);
@@ -84,7 +158,7 @@ impl<F: PrimeField> Layer<F> for MaxPool2DChip<F> {
     &self,
     mut layouter: impl Layouter<F>,
     tensors: &Vec<AssignedTensor<F>>,
-    _constants: &HashMap<i64, CellRc<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
     gadget_config: Rc<GadgetConfig>,
     layer_config: &LayerConfig,
   ) -> Result<Vec<AssignedTensor<F>>, Error> {
@@ -105,12 +179,25 @@ impl<F: PrimeField> Layer<F> for MaxPool2DChip<F> {
         .unwrap();
       out.push(max[0].clone());
     }
-    let out = out.into_iter().map(|x| Rc::new(x)).collect();
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
 
     // TODO: refactor this
     let out_xy = Self::shape(inp, layer_config);
     let out_shape = vec![1, out_xy.0, out_xy.1, inp.shape()[3]];
 
+    if Self::exports_argmax(&layer_config.layer_params) {
+      let argmax = Self::argmax(
+        layouter.namespace(|| "argmax"),
+        &splat,
+        &out,
+        constants,
+        gadget_config.clone(),
+      )?;
+      let out = Array::from_shape_vec(IxDyn(&out_shape), out).unwrap();
+      let argmax = Array::from_shape_vec(IxDyn(&out_shape), argmax).unwrap();
+      return Ok(vec![out, argmax]);
+    }
+
     let out = Array::from_shape_vec(IxDyn(&out_shape), out).unwrap();
 
     Ok(vec![out])
@@ -118,7 +205,16 @@ impl<F: PrimeField> Layer<F> for MaxPool2DChip<F> {
 }
 
 impl<F: PrimeField> GadgetConsumer for MaxPool2DChip<F> {
-  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<GadgetType> {
-    vec![GadgetType::Max, GadgetType::InputLookup]
+  fn used_gadgets(&self, layer_params: Vec<i64>) -> Vec<GadgetType> {
+    let mut outp = vec![GadgetType::Max, GadgetType::InputLookup];
+    if Self::exports_argmax(&layer_params) {
+      outp.extend(vec![
+        GadgetType::Comparator,
+        GadgetType::MulPairs,
+        GadgetType::SubPairs,
+        GadgetType::AddPairs,
+      ]);
+    }
+    outp
   }
 }
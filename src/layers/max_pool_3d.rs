@@ -0,0 +1,99 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{s, Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  max::MaxChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Params: [filter d, filter h, filter w, stride d, stride h, stride w]
+// The input is [1, D, H, W, C], and the windows are VALID like in MaxPool2D
+pub struct MaxPool3DChip<F: PrimeField> {
+  pub marker: std::marker::PhantomData<F>,
+}
+
+impl<F: PrimeField> MaxPool3DChip<F> {
+  // The output depth, height, and width
+  pub fn shape(inp: &AssignedTensor<F>, layer_config: &LayerConfig) -> [usize; 3] {
+    let params = &layer_config.layer_params;
+    // Only support batch size 1 for now
+    assert_eq!(inp.shape().len(), 5);
+    assert_eq!(inp.shape()[0], 1);
+
+    let mut outp = [0; 3];
+    for axis in 0..3 {
+      let (filter, stride) = (params[axis] as usize, params[axis + 3] as usize);
+      assert!(filter <= inp.shape()[axis + 1] && stride > 0);
+      outp[axis] = (inp.shape()[axis + 1] - filter) / stride + 1;
+    }
+    outp
+  }
+
+  // The window of every output, in the order of the output
+  pub fn splat(inp: &AssignedTensor<F>, layer_config: &LayerConfig) -> Vec<Vec<CellRc<F>>> {
+    let params = &layer_config.layer_params;
+    let (fd, fh, fw) = (params[0] as usize, params[1] as usize, params[2] as usize);
+    let (sd, sh, sw) = (params[3] as usize, params[4] as usize, params[5] as usize);
+    let [od, oh, ow] = Self::shape(inp, layer_config);
+
+    let mut splat = vec![];
+    for i in 0..od {
+      for j in 0..oh {
+        for k in 0..ow {
+          for c in 0..inp.shape()[4] {
+            let window = inp.slice(s![
+              0,
+              i * sd..i * sd + fd,
+              j * sh..j * sh + fh,
+              k * sw..k * sw + fw,
+              c
+            ]);
+            splat.push(window.iter().cloned().collect());
+          }
+        }
+      }
+    }
+    splat
+  }
+}
+
+impl<F: PrimeField> Layer<F> for MaxPool3DChip<F> {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    _constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let splat = Self::splat(inp, layer_config);
+
+    let max_chip = MaxChip::<F>::construct(gadget_config.clone());
+    let mut out = vec![];
+    for (i, inps) in splat.iter().enumerate() {
+      let inps = inps.iter().map(|x| x.as_ref()).collect();
+      let max = max_chip.forward(
+        layouter.namespace(|| format!("max 3d {}", i)),
+        &vec![inps],
+        &vec![],
+      )?;
+      out.push(Rc::new(max[0].clone()));
+    }
+
+    let [od, oh, ow] = Self::shape(inp, layer_config);
+    let out_shape = vec![1, od, oh, ow, inp.shape()[4]];
+    let out = Array::from_shape_vec(IxDyn(&out_shape), out).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl<F: PrimeField> GadgetConsumer for MaxPool3DChip<F> {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<GadgetType> {
+    vec![GadgetType::Max, GadgetType::InputLookup]
+  }
+}
@@ -0,0 +1,297 @@
+use std::{collections::HashMap, marker::PhantomData, rc::Rc, vec};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  adder::AdderChip,
+  comparator::ComparatorChip,
+  dot_prod::DotProductChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+};
+
+use super::{
+  fully_connected::{FullyConnectedChip, FullyConnectedConfig},
+  layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig},
+  softmax::SoftmaxChip,
+};
+
+// Mixture of experts over a single token. Params: [num_experts, top_k]
+// Inputs are the token x ([1, d]), the gate logits ([1, num_experts]), the experts chosen by the
+// prover ([top_k]), then (w1 [h, d], b1 [h], w2 [d, h], b2 [d]) for every chosen expert, which
+// computes w2 * relu(w1 * x + b1) + b2. Only the chosen experts are computed. The chip constrains
+// the chosen experts to be exactly the top k of the gate logits, so ties at the k-th logit can't
+// be proven, and combines the expert outputs with the softmax of their logits.
+// Outputs the combined output ([1, d]) and the chosen experts, so the verifier can check the
+// commitments of the expert weights against the commitments of the full expert set.
+#[derive(Clone, Debug)]
+pub struct MoEChip {}
+
+impl MoEChip {
+  fn fc<F: PrimeField>(
+    layouter: impl Layouter<F>,
+    tensors: Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    activation: i64,
+  ) -> Result<AssignedTensor<F>, Error> {
+    let fc_chip = FullyConnectedChip::<F> {
+      _marker: PhantomData,
+      config: FullyConnectedConfig::construct(true),
+    };
+    let layer_config = LayerConfig {
+      layer_params: vec![activation],
+      ..LayerConfig::default()
+    };
+    let outp = fc_chip.forward(layouter, &tensors, constants, gadget_config, &layer_config)?;
+    Ok(outp[0].clone())
+  }
+
+  // mask[e] = [at least num_experts - k other logits are less than or equal to logits[e]]
+  fn top_k_mask<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    logits: &Vec<&AssignedCell<F, F>>,
+    top_k: usize,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let num_experts = logits.len();
+    if top_k == num_experts {
+      return Ok(vec![one.clone(); num_experts]);
+    }
+
+    let mut lhs = vec![];
+    let mut rhs = vec![];
+    for e in 0..num_experts {
+      for j in (0..num_experts).filter(|j| *j != e) {
+        lhs.push(logits[j]);
+        rhs.push(logits[e]);
+      }
+    }
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let le = comparator_chip.forward(
+      layouter.namespace(|| "moe gate ranks"),
+      &vec![lhs, rhs],
+      &vec![zero],
+    )?;
+
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let mut num_le = vec![];
+    for e in 0..num_experts {
+      let bits = le[e * (num_experts - 1)..(e + 1) * (num_experts - 1)]
+        .iter()
+        .collect::<Vec<_>>();
+      let count = adder_chip.forward(
+        layouter.namespace(|| format!("moe gate count {}", e)),
+        &vec![bits],
+        &vec![zero],
+      )?;
+      num_le.push(count[0].clone());
+    }
+
+    // The threshold is built from ones so that it is constrained
+    let threshold = adder_chip.forward(
+      layouter.namespace(|| "moe gate threshold"),
+      &vec![vec![one; num_experts - top_k]],
+      &vec![zero],
+    )?[0]
+      .clone();
+    comparator_chip.forward(
+      layouter.namespace(|| "moe gate top k"),
+      &vec![vec![&threshold; num_experts], num_le.iter().collect()],
+      &vec![zero],
+    )
+  }
+
+  // onehot[r][e] = [chosen[r] == e], from chosen[r] <= e and e <= chosen[r]
+  fn one_hot<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    chosen: &Vec<&AssignedCell<F, F>>,
+    num_experts: usize,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<Vec<AssignedCell<F, F>>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+
+    // The expert indices are built from ones so that they are constrained
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let mut expert_idxes = vec![zero.clone()];
+    for e in 1..num_experts {
+      let idx = adder_chip.forward(
+        layouter.namespace(|| format!("moe expert idx {}", e)),
+        &vec![vec![&expert_idxes[e - 1], one]],
+        &vec![zero],
+      )?;
+      expert_idxes.push(idx[0].clone());
+    }
+
+    let mut lhs = vec![];
+    let mut rhs = vec![];
+    for chosen in chosen.iter() {
+      for idx in expert_idxes.iter() {
+        lhs.extend([*chosen, idx]);
+        rhs.extend([idx, *chosen]);
+      }
+    }
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let le = comparator_chip.forward(
+      layouter.namespace(|| "moe chosen comparisons"),
+      &vec![lhs, rhs],
+      &vec![zero],
+    )?;
+
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let eq = mul_pairs_chip.forward(
+      layouter.namespace(|| "moe chosen one hot"),
+      &vec![
+        le.iter().step_by(2).collect(),
+        le.iter().skip(1).step_by(2).collect(),
+      ],
+      &vec![zero],
+    )?;
+    Ok(eq.chunks(num_experts).map(|x| x.to_vec()).collect())
+  }
+}
+
+impl<F: PrimeField> Layer<F> for MoEChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let num_experts = layer_config.layer_params[0] as usize;
+    let top_k = layer_config.layer_params[1] as usize;
+    assert!(top_k >= 1 && top_k <= num_experts);
+    assert_eq!(tensors.len(), 3 + 4 * top_k, "malformed moe inputs");
+
+    let inp = &tensors[0];
+    let logits = tensors[1].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let chosen = tensors[2].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    assert_eq!(logits.len(), num_experts);
+    assert_eq!(chosen.len(), top_k);
+
+    // Every expert in the top k is chosen exactly once. This also constrains the chosen experts
+    // to be in range and distinct.
+    let mask = Self::top_k_mask(
+      layouter.namespace(|| "moe top k"),
+      &logits,
+      top_k,
+      constants,
+      gadget_config.clone(),
+    )?;
+    let one_hot = Self::one_hot(
+      layouter.namespace(|| "moe one hot"),
+      &chosen,
+      num_experts,
+      constants,
+      gadget_config.clone(),
+    )?;
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let mut counts = vec![];
+    for e in 0..num_experts {
+      let count = adder_chip.forward(
+        layouter.namespace(|| format!("moe chosen count {}", e)),
+        &vec![one_hot.iter().map(|x| &x[e]).collect()],
+        &vec![zero],
+      )?;
+      counts.push(count[0].clone());
+    }
+    layouter.assign_region(
+      || "moe routing check",
+      |mut region| {
+        for (count, bit) in counts.iter().zip(mask.iter()) {
+          region.constrain_equal(count.cell(), bit.cell())?;
+        }
+        Ok(())
+      },
+    )?;
+
+    // The combination weights are the softmax of the logits of the chosen experts
+    let dot_prod_chip = DotProductChip::<F>::construct(gadget_config.clone());
+    let mut chosen_logits = vec![];
+    for (r, bits) in one_hot.iter().enumerate() {
+      let logit = dot_prod_chip.forward(
+        layouter.namespace(|| format!("moe chosen logit {}", r)),
+        &vec![bits.iter().collect(), logits.clone()],
+        &vec![zero],
+      )?;
+      chosen_logits.push(logit[0].clone());
+    }
+    let weights = SoftmaxChip::softmax_flat(
+      layouter.namespace(|| "moe weights"),
+      constants,
+      chosen_logits.iter().collect(),
+      gadget_config.clone(),
+      &vec![0; top_k],
+    )?;
+
+    // Dispatch to the chosen experts
+    let mut expert_outs = vec![];
+    for (r, expert) in tensors[3..].chunks(4).enumerate() {
+      let hidden = Self::fc(
+        layouter.namespace(|| format!("moe expert {} up", r)),
+        vec![inp.clone(), expert[0].clone(), expert[1].clone()],
+        constants,
+        gadget_config.clone(),
+        1,
+      )?;
+      let out = Self::fc(
+        layouter.namespace(|| format!("moe expert {} down", r)),
+        vec![hidden, expert[2].clone(), expert[3].clone()],
+        constants,
+        gadget_config.clone(),
+        0,
+      )?;
+      expert_outs.push(out.iter().cloned().collect::<Vec<_>>());
+    }
+
+    // Combine: out = weights * [expert outputs], as a [1, top_k] x [top_k, d] matmul
+    let out_dim = expert_outs[0].len();
+    let weights = weights.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let weights = Array::from_shape_vec(IxDyn(&[1, top_k]), weights).unwrap();
+    let combine = (0..out_dim)
+      .flat_map(|i| expert_outs.iter().map(move |out| out[i].clone()))
+      .collect::<Vec<_>>();
+    let combine = Array::from_shape_vec(IxDyn(&[out_dim, top_k]), combine).unwrap();
+    let outp = Self::fc(
+      layouter.namespace(|| "moe combine"),
+      vec![weights, combine],
+      constants,
+      gadget_config.clone(),
+      0,
+    )?;
+
+    Ok(vec![outp, tensors[2].clone()])
+  }
+}
+
+impl GadgetConsumer for MoEChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::AddPairs,
+      GadgetType::Comparator,
+      GadgetType::DotProduct,
+      GadgetType::MulPairs,
+      GadgetType::Relu,
+      GadgetType::VarDivRound,
+      GadgetType::Exp,
+      GadgetType::VarDivRoundBig3,
+      GadgetType::Max,
+      GadgetType::SubPairs,
+      GadgetType::InputLookup,
+    ]
+  }
+}
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  adder::AdderChip,
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  sub_pairs::SubPairsChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+pub const ARGMAX: i64 = 0;
+pub const THRESHOLD: i64 = 1;
+
+// A predicate of a tensor, so that the tensor can stay private (see predicate.rs).
+// Params: [ARGMAX, class] for [argmax == class], with the first max on ties, or
+// [THRESHOLD, i, threshold] for [x[i] > threshold], over the flattened input. The threshold + 1 is
+// a constant of the circuit. Outputs the predicate ([1]).
+#[derive(Clone, Debug)]
+pub struct PredicateChip {}
+
+impl PredicateChip {
+  pub fn constant(layer_params: &Vec<i64>) -> Option<i64> {
+    match layer_params[0] {
+      THRESHOLD => Some(layer_params[2] + 1),
+      _ => None,
+    }
+  }
+}
+
+impl<F: PrimeField> Layer<F> for PredicateChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let params = &layer_config.layer_params;
+    let inp = tensors[0].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+
+    let predicate = match params[0] {
+      ARGMAX => {
+        let class = params[1] as usize;
+        assert!(class < inp.len(), "class {} out of range", class);
+        let others = (0..inp.len()).filter(|j| *j != class).collect::<Vec<_>>();
+        if others.is_empty() {
+          one.clone()
+        } else {
+          // x[j] <= x[class] after the class, and x[class] <= x[j] before it, which is negated
+          let lhs = others
+            .iter()
+            .map(|j| if *j > class { inp[*j] } else { inp[class] })
+            .collect();
+          let rhs = others
+            .iter()
+            .map(|j| if *j > class { inp[class] } else { inp[*j] })
+            .collect();
+          let le = comparator_chip.forward(
+            layouter.namespace(|| "predicate argmax comparisons"),
+            &vec![lhs, rhs],
+            &vec![zero],
+          )?;
+          let num_before = class;
+          let mut bits = le[num_before..].to_vec();
+          if num_before > 0 {
+            let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+            let lt = sub_pairs_chip.forward(
+              layouter.namespace(|| "predicate argmax strict"),
+              &vec![vec![one; num_before], le[..num_before].iter().collect()],
+              &vec![zero],
+            )?;
+            bits.extend(lt);
+          }
+
+          // The count and the threshold are built from ones so that they are constrained
+          let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+          let count = adder_chip.forward(
+            layouter.namespace(|| "predicate argmax count"),
+            &vec![bits.iter().collect()],
+            &vec![zero],
+          )?[0]
+            .clone();
+          let threshold = adder_chip.forward(
+            layouter.namespace(|| "predicate argmax threshold"),
+            &vec![vec![one; others.len()]],
+            &vec![zero],
+          )?[0]
+            .clone();
+          comparator_chip.forward(
+            layouter.namespace(|| "predicate argmax"),
+            &vec![vec![&threshold], vec![&count]],
+            &vec![zero],
+          )?[0]
+            .clone()
+        }
+      }
+      THRESHOLD => {
+        let i = params[1] as usize;
+        assert!(i < inp.len(), "element {} out of range", i);
+        let bound = constants
+          .get(&PredicateChip::constant(params).unwrap())
+          .unwrap();
+        comparator_chip.forward(
+          layouter.namespace(|| "predicate threshold"),
+          &vec![vec![bound.as_ref()], vec![inp[i]]],
+          &vec![zero],
+        )?[0]
+          .clone()
+      }
+      kind => panic!("unknown predicate {}", kind),
+    };
+
+    let outp = Array::from_shape_vec(IxDyn(&[1]), vec![Rc::new(predicate)]).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for PredicateChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::Comparator,
+      GadgetType::SubPairs,
+      GadgetType::InputLookup,
+    ]
+  }
+}
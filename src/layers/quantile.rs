@@ -0,0 +1,136 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  add_pairs::AddPairsChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  sorting_network::{sort_gadgets, SortingNetworkChip},
+  sub_pairs::SubPairsChip,
+  var_div::VarDivRoundChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Order statistics of the flattened input, e.g., the median or the percentile scores of risk
+// models. Params: the quantiles in fixed point, in [0, sf]. The input is sorted by a sorting
+// network (see sorting_network.rs), and the quantile q is interpolated linearly at the position
+// q (n - 1), like numpy: y = x_i + round(w (x_(i+1) - x_i) / sf), with i = floor(q (n - 1) / sf)
+// and the remainder w, which is a constant. Outputs [num quantiles].
+#[derive(Clone, Debug)]
+pub struct QuantileChip {}
+
+impl QuantileChip {
+  // (i, w) of every quantile
+  pub fn positions(layer_params: &Vec<i64>, n: usize, sf: i64) -> Vec<(usize, i64)> {
+    assert!(n > 0, "the quantiles of an empty tensor");
+    layer_params
+      .iter()
+      .map(|q| {
+        assert!(*q >= 0 && *q <= sf, "quantile {} out of range", q);
+        let pos = *q * (n as i64 - 1);
+        ((pos / sf) as usize, pos % sf)
+      })
+      .collect()
+  }
+
+  pub fn constants(layer_params: &Vec<i64>, n: usize, sf: i64) -> Vec<i64> {
+    Self::positions(layer_params, n, sf)
+      .into_iter()
+      .map(|(_, w)| w)
+      .filter(|w| *w > 0)
+      .collect()
+  }
+}
+
+impl<F: PrimeField> Layer<F> for QuantileChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = tensors[0].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+    let sf = gadget_config.scale_factor as i64;
+    let positions = Self::positions(&layer_config.layer_params, inp.len(), sf);
+
+    let sorting_chip = SortingNetworkChip::<F>::construct(gadget_config.clone());
+    let (sorted, _) =
+      sorting_chip.forward(layouter.namespace(|| "quantile sort"), &inp, &vec![], zero)?;
+
+    // Only the quantiles between two elements are interpolated
+    let interpolated = positions
+      .iter()
+      .filter(|(_, w)| *w > 0)
+      .cloned()
+      .collect::<Vec<_>>();
+    let mut interpolated_outs = vec![];
+    if !interpolated.is_empty() {
+      let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+      let diffs = sub_pairs_chip.forward(
+        layouter.namespace(|| "quantile diffs"),
+        &vec![
+          interpolated.iter().map(|(i, _)| &sorted[i + 1]).collect(),
+          interpolated.iter().map(|(i, _)| &sorted[*i]).collect(),
+        ],
+        &vec![zero],
+      )?;
+      let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+      let weighted = mul_pairs_chip.forward(
+        layouter.namespace(|| "quantile weights"),
+        &vec![
+          diffs.iter().collect(),
+          interpolated
+            .iter()
+            .map(|(_, w)| constants.get(w).unwrap().as_ref())
+            .collect(),
+        ],
+        &vec![zero],
+      )?;
+      let var_div_chip = VarDivRoundChip::<F>::construct(gadget_config.clone());
+      let div = constants.get(&sf).unwrap().as_ref();
+      let steps = var_div_chip.forward(
+        layouter.namespace(|| "quantile div"),
+        &vec![weighted.iter().collect()],
+        &vec![zero, div],
+      )?;
+      let add_pairs_chip = AddPairsChip::<F>::construct(gadget_config.clone());
+      interpolated_outs = add_pairs_chip.forward(
+        layouter.namespace(|| "quantile interpolation"),
+        &vec![
+          interpolated.iter().map(|(i, _)| &sorted[*i]).collect(),
+          steps.iter().collect(),
+        ],
+        &vec![zero],
+      )?;
+    }
+
+    let mut interpolated_outs = interpolated_outs.into_iter();
+    let out = positions
+      .iter()
+      .map(|(i, w)| {
+        let cell = if *w > 0 {
+          interpolated_outs.next().unwrap()
+        } else {
+          sorted[*i].clone()
+        };
+        Rc::new(cell)
+      })
+      .collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(&[out.len()]), out).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for QuantileChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    let mut gadgets = sort_gadgets();
+    gadgets.extend([GadgetType::VarDivRound, GadgetType::InputLookup]);
+    gadgets
+  }
+}
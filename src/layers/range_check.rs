@@ -0,0 +1,48 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  signed_range_check::SignedRangeCheckChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Params: [num_bits]. Constrains every element to be a num_bits signed integer and passes the
+// tensor through.
+#[derive(Clone, Debug)]
+pub struct RangeCheckChip {}
+
+impl<F: PrimeField> Layer<F> for RangeCheckChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let inp_vec = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+    let num_bits = layer_config.layer_params[0];
+
+    let range_chip = SignedRangeCheckChip::<F>::construct(gadget_config.clone(), num_bits);
+    let vec_inps = vec![inp_vec];
+    let constants = vec![zero];
+    let out = range_chip.forward(layouter.namespace(|| "range check"), &vec_inps, &constants)?;
+
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(inp.shape()), out).unwrap();
+
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for RangeCheckChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![GadgetType::SignedRangeCheck, GadgetType::InputLookup]
+  }
+}
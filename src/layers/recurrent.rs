@@ -0,0 +1,424 @@
+use std::{collections::HashMap, marker::PhantomData, rc::Rc, vec};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{s, Array, Axis, IxDyn};
+
+use crate::gadgets::{
+  add_pairs::AddPairsChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  nonlinear::{logistic::LogisticGadgetChip, tanh::TanhGadgetChip},
+  sub_pairs::SubPairsChip,
+  var_div::VarDivRoundChip,
+};
+
+use super::{
+  fully_connected::{FullyConnectedChip, FullyConnectedConfig},
+  layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig},
+};
+
+// Recurrent cells over a sequence, unrolled into one cell per timestep. Params: [return_sequences]
+// Inputs are the sequence x ([T, d] or [1, T, d]), the input weights w ([G * h, d]), the
+// recurrent weights r ([G * h, h]), the bias ([G * h]), then optionally the initial hidden state
+// ([h]) and, for LSTM, the initial cell state ([h]), which are zero otherwise. The G gates are
+// stacked in the order of PyTorch, (i, f, g, o) for LSTM and (r, z, n) for GRU:
+//   LSTM: c' = f * c + i * g, h' = o * tanh(c')
+//   GRU:  n = tanh(w_n x + r_n (r * h) + b_n), h' = (1 - z) * n + z * h
+// i.e., the GRU resets the state before the recurrent weights (linear_before_reset = 0 in ONNX).
+// The input part of the gates is a single [T, d] x [d, G * h] product for all timesteps. Outputs
+// the hidden states of all timesteps ([T, h]) or of the last one ([1, h]), in the out shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecurrentCell {
+  Lstm,
+  Gru,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecurrentChip {
+  pub cell: RecurrentCell,
+}
+
+impl RecurrentChip {
+  fn num_gates(&self) -> usize {
+    match self.cell {
+      RecurrentCell::Lstm => 4,
+      RecurrentCell::Gru => 3,
+    }
+  }
+
+  // inp * weight^T (+ bias), rescaled
+  fn fc<F: PrimeField>(
+    layouter: impl Layouter<F>,
+    tensors: Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<AssignedTensor<F>, Error> {
+    let fc_chip = FullyConnectedChip::<F> {
+      _marker: PhantomData,
+      config: FullyConnectedConfig::construct(true),
+    };
+    let layer_config = LayerConfig {
+      layer_params: vec![0],
+      ..LayerConfig::default()
+    };
+    let outp = fc_chip.forward(layouter, &tensors, constants, gadget_config, &layer_config)?;
+    Ok(outp[0].clone())
+  }
+
+  // The state times the recurrent weights, as a [1, G * h] vector
+  fn recur<F: PrimeField>(
+    layouter: impl Layouter<F>,
+    state: &Vec<AssignedCell<F, F>>,
+    weights: AssignedTensor<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let state = state.iter().map(|x| Rc::new(x.clone())).collect::<Vec<_>>();
+    let state = Array::from_shape_vec(IxDyn(&[1, state.len()]), state).unwrap();
+    let outp = Self::fc(layouter, vec![state, weights], constants, gadget_config)?;
+    Ok(outp.iter().map(|x| x.as_ref().clone()).collect())
+  }
+
+  // Elementwise AddPairs, SubPairs, or MulPairs, where the products are rescaled
+  fn pairs<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    gadget: GadgetType,
+    lhs: &[AssignedCell<F, F>],
+    rhs: &[AssignedCell<F, F>],
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let inps = vec![lhs.iter().collect(), rhs.iter().collect()];
+    match gadget {
+      GadgetType::AddPairs => AddPairsChip::<F>::construct(gadget_config).forward(
+        layouter.namespace(|| "recurrent add"),
+        &inps,
+        &vec![zero],
+      ),
+      GadgetType::SubPairs => SubPairsChip::<F>::construct(gadget_config).forward(
+        layouter.namespace(|| "recurrent sub"),
+        &inps,
+        &vec![zero],
+      ),
+      GadgetType::MulPairs => {
+        let prod = MulPairsChip::<F>::construct(gadget_config.clone()).forward(
+          layouter.namespace(|| "recurrent mul"),
+          &inps,
+          &vec![zero],
+        )?;
+        let sf = constants
+          .get(&(gadget_config.scale_factor as i64))
+          .unwrap()
+          .as_ref();
+        VarDivRoundChip::<F>::construct(gadget_config).forward(
+          layouter.namespace(|| "recurrent mul div"),
+          &vec![prod.iter().collect()],
+          &vec![zero, sf],
+        )
+      }
+      _ => panic!("unsupported recurrent op {:?}", gadget),
+    }
+  }
+
+  fn activation<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    gadget: GadgetType,
+    inp: &[AssignedCell<F, F>],
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let inps = vec![inp.iter().collect()];
+    match gadget {
+      GadgetType::Logistic => LogisticGadgetChip::<F>::construct(gadget_config).forward(
+        layouter.namespace(|| "recurrent logistic"),
+        &inps,
+        &vec![zero],
+      ),
+      GadgetType::Tanh => TanhGadgetChip::<F>::construct(gadget_config).forward(
+        layouter.namespace(|| "recurrent tanh"),
+        &inps,
+        &vec![zero],
+      ),
+      _ => panic!("unsupported recurrent activation {:?}", gadget),
+    }
+  }
+
+  // Returns the next (h, c)
+  fn lstm_step<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    x_gates: &[AssignedCell<F, F>],
+    state: (&Vec<AssignedCell<F, F>>, &Vec<AssignedCell<F, F>>),
+    recurrent: &AssignedTensor<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<(Vec<AssignedCell<F, F>>, Vec<AssignedCell<F, F>>), Error> {
+    let (h, c) = state;
+    let n = h.len();
+    let cfg = || gadget_config.clone();
+    let h_gates = Self::recur(
+      layouter.namespace(|| "lstm recur"),
+      h,
+      recurrent.clone(),
+      constants,
+      cfg(),
+    )?;
+    let gates = Self::pairs(
+      layouter.namespace(|| "lstm gates"),
+      GadgetType::AddPairs,
+      x_gates,
+      &h_gates,
+      constants,
+      cfg(),
+    )?;
+
+    // i, f, and o in one lookup
+    let ifo = [&gates[..2 * n], &gates[3 * n..]].concat();
+    let ifo = Self::activation(
+      layouter.namespace(|| "lstm ifo"),
+      GadgetType::Logistic,
+      &ifo,
+      constants,
+      cfg(),
+    )?;
+    let (i, f, o) = (&ifo[..n], &ifo[n..2 * n], &ifo[2 * n..]);
+    let g = Self::activation(
+      layouter.namespace(|| "lstm g"),
+      GadgetType::Tanh,
+      &gates[2 * n..3 * n],
+      constants,
+      cfg(),
+    )?;
+
+    // c' = f * c + i * g, as one product of [f, i] with [c, g]
+    let prods = Self::pairs(
+      layouter.namespace(|| "lstm cell products"),
+      GadgetType::MulPairs,
+      &[f, i].concat(),
+      &[&c[..], &g[..]].concat(),
+      constants,
+      cfg(),
+    )?;
+    let c = Self::pairs(
+      layouter.namespace(|| "lstm cell"),
+      GadgetType::AddPairs,
+      &prods[..n],
+      &prods[n..],
+      constants,
+      cfg(),
+    )?;
+    let tanh_c = Self::activation(
+      layouter.namespace(|| "lstm tanh cell"),
+      GadgetType::Tanh,
+      &c,
+      constants,
+      cfg(),
+    )?;
+    let h = Self::pairs(
+      layouter.namespace(|| "lstm hidden"),
+      GadgetType::MulPairs,
+      o,
+      &tanh_c,
+      constants,
+      cfg(),
+    )?;
+    Ok((h, c))
+  }
+
+  fn gru_step<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    x_gates: &[AssignedCell<F, F>],
+    h: &Vec<AssignedCell<F, F>>,
+    recurrent: &AssignedTensor<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let n = h.len();
+    let cfg = || gadget_config.clone();
+    let h_gates = Self::recur(
+      layouter.namespace(|| "gru recur rz"),
+      h,
+      recurrent.slice(s![..2 * n, ..]).into_owned().into_dyn(),
+      constants,
+      cfg(),
+    )?;
+    let rz = Self::pairs(
+      layouter.namespace(|| "gru rz gates"),
+      GadgetType::AddPairs,
+      &x_gates[..2 * n],
+      &h_gates,
+      constants,
+      cfg(),
+    )?;
+    let rz = Self::activation(
+      layouter.namespace(|| "gru rz"),
+      GadgetType::Logistic,
+      &rz,
+      constants,
+      cfg(),
+    )?;
+    let (r, z) = (&rz[..n], &rz[n..]);
+
+    let reset = Self::pairs(
+      layouter.namespace(|| "gru reset"),
+      GadgetType::MulPairs,
+      r,
+      h,
+      constants,
+      cfg(),
+    )?;
+    let reset_gates = Self::recur(
+      layouter.namespace(|| "gru recur n"),
+      &reset,
+      recurrent.slice(s![2 * n.., ..]).into_owned().into_dyn(),
+      constants,
+      cfg(),
+    )?;
+    let candidate = Self::pairs(
+      layouter.namespace(|| "gru n gate"),
+      GadgetType::AddPairs,
+      &x_gates[2 * n..],
+      &reset_gates,
+      constants,
+      cfg(),
+    )?;
+    let candidate = Self::activation(
+      layouter.namespace(|| "gru n"),
+      GadgetType::Tanh,
+      &candidate,
+      constants,
+      cfg(),
+    )?;
+
+    // h' = n + z * (h - n)
+    let diff = Self::pairs(
+      layouter.namespace(|| "gru diff"),
+      GadgetType::SubPairs,
+      h,
+      &candidate,
+      constants,
+      cfg(),
+    )?;
+    let update = Self::pairs(
+      layouter.namespace(|| "gru update"),
+      GadgetType::MulPairs,
+      z,
+      &diff,
+      constants,
+      cfg(),
+    )?;
+    Self::pairs(
+      layouter.namespace(|| "gru hidden"),
+      GadgetType::AddPairs,
+      &candidate,
+      &update,
+      constants,
+      cfg(),
+    )
+  }
+}
+
+impl<F: PrimeField> Layer<F> for RecurrentChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let return_sequences = layer_config.layer_params[0] == 1;
+    let num_states = if self.cell == RecurrentCell::Lstm {
+      2
+    } else {
+      1
+    };
+    assert!(
+      tensors.len() >= 4 && tensors.len() <= 4 + num_states,
+      "malformed recurrent inputs"
+    );
+
+    let inp = &tensors[0];
+    let inp_shape = inp.shape()[inp.ndim() - 2..].to_vec();
+    let inp = inp.clone().into_shape(IxDyn(&inp_shape)).unwrap();
+    let (num_steps, hidden) = (inp_shape[0], tensors[2].shape()[1]);
+    assert_eq!(
+      tensors[1].shape(),
+      &[self.num_gates() * hidden, inp_shape[1]]
+    );
+    assert_eq!(tensors[2].shape(), &[self.num_gates() * hidden, hidden]);
+
+    let x_gates = Self::fc(
+      layouter.namespace(|| "recurrent input gates"),
+      vec![inp, tensors[1].clone(), tensors[3].clone()],
+      constants,
+      gadget_config.clone(),
+    )?;
+
+    let initial_state = |idx: usize| match tensors.get(idx) {
+      Some(state) => state.iter().map(|x| x.as_ref().clone()).collect(),
+      None => vec![zero.clone(); hidden],
+    };
+    let mut h: Vec<AssignedCell<F, F>> = initial_state(4);
+    let mut c: Vec<AssignedCell<F, F>> = initial_state(5);
+    let mut outp = vec![];
+    for step in 0..num_steps {
+      let x_step = x_gates
+        .index_axis(Axis(0), step)
+        .iter()
+        .map(|x| x.as_ref().clone())
+        .collect::<Vec<_>>();
+      let step_layouter = layouter.namespace(|| format!("recurrent step {}", step));
+      match self.cell {
+        RecurrentCell::Lstm => {
+          (h, c) = Self::lstm_step(
+            step_layouter,
+            &x_step,
+            (&h, &c),
+            &tensors[2],
+            constants,
+            gadget_config.clone(),
+          )?;
+        }
+        RecurrentCell::Gru => {
+          h = Self::gru_step(
+            step_layouter,
+            &x_step,
+            &h,
+            &tensors[2],
+            constants,
+            gadget_config.clone(),
+          )?;
+        }
+      }
+      if return_sequences || step + 1 == num_steps {
+        outp.extend(h.iter().map(|x| Rc::new(x.clone())));
+      }
+    }
+
+    let outp = Array::from_shape_vec(IxDyn(&layer_config.out_shapes[0]), outp).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for RecurrentChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Adder,
+      GadgetType::AddPairs,
+      GadgetType::SubPairs,
+      GadgetType::MulPairs,
+      GadgetType::DotProduct,
+      GadgetType::VarDivRound,
+      GadgetType::Logistic,
+      GadgetType::Tanh,
+      GadgetType::InputLookup,
+    ]
+  }
+}
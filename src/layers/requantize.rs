@@ -0,0 +1,224 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  add_pairs::AddPairsChip,
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  sub_pairs::SubPairsChip,
+  var_div_big3::VarDivRoundBig3Chip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// The requantization of the TFLite integer kernels (see model/tflite.rs), on integers.
+// Params: [lo, hi, multiplier, shift, ...], with one (multiplier, shift) for the tensor or one per
+// channel of the last axis, as TFLite computes them. Computes
+// clamp(MultiplyByQuantizedMultiplier(x, multiplier, shift), lo, hi):
+//   t = round_half_up(x * multiplier / 2^(31 - left shift)), the doubling high mul, then
+//   round_half_away(t / 2^right shift) = round_half_up((t - [t < 0]) / 2^right shift).
+// Where it matters, t < 0 iff round_half_up(t / 2^right shift) <= 0, which keeps the comparison in
+// the lookup range. The multipliers, the powers of two, and the bounds are constants.
+#[derive(Clone, Debug)]
+pub struct RequantizeChip {}
+
+impl RequantizeChip {
+  fn channels(layer_params: &Vec<i64>) -> Vec<(i64, i64)> {
+    assert!(
+      layer_params.len() >= 4 && layer_params.len() % 2 == 0,
+      "malformed requantize params"
+    );
+    layer_params[2..].chunks(2).map(|x| (x[0], x[1])).collect()
+  }
+
+  // (2^(31 - left shift), 2^right shift)
+  fn divisors(shift: i64) -> (i64, i64) {
+    assert!(shift <= 30 && shift >= -31, "shift {} out of range", shift);
+    (1 << (31 - shift.max(0)), 1 << (-shift).max(0))
+  }
+
+  pub fn constants(layer_params: &Vec<i64>) -> Vec<i64> {
+    let mut vals = vec![layer_params[0], layer_params[1]];
+    for (multiplier, shift) in Self::channels(layer_params) {
+      let (high, right) = Self::divisors(shift);
+      vals.extend([multiplier, high, right]);
+    }
+    vals
+  }
+
+  // bound + keep * (x - bound)
+  fn select<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    keep: &Vec<AssignedCell<F, F>>,
+    x: &Vec<&AssignedCell<F, F>>,
+    bound: &AssignedCell<F, F>,
+    zero: &AssignedCell<F, F>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let bound = vec![bound; x.len()];
+    let diff = SubPairsChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "diff"),
+      &vec![x.clone(), bound.clone()],
+      &vec![zero],
+    )?;
+    let diff = MulPairsChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "select"),
+      &vec![keep.iter().collect(), diff.iter().collect()],
+      &vec![zero],
+    )?;
+    AddPairsChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "add"),
+      &vec![bound, diff.iter().collect()],
+      &vec![zero],
+    )
+  }
+
+  fn requantize<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    inp: &Vec<&AssignedCell<F, F>>,
+    multiplier: i64,
+    shift: i64,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let (high, right) = Self::divisors(shift);
+    let multiplier = constants.get(&multiplier).unwrap().as_ref();
+    let high = constants.get(&high).unwrap().as_ref();
+    let var_div_chip = VarDivRoundBig3Chip::<F>::construct(gadget_config.clone());
+
+    let prod = MulPairsChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "requantize mul"),
+      &vec![inp.clone(), vec![multiplier; inp.len()]],
+      &vec![zero],
+    )?;
+    let t = var_div_chip.forward(
+      layouter.namespace(|| "requantize high mul"),
+      &vec![prod.iter().collect()],
+      &vec![zero, high],
+    )?;
+    if right == 1 {
+      return Ok(t);
+    }
+
+    let right = constants.get(&right).unwrap().as_ref();
+    let q = var_div_chip.forward(
+      layouter.namespace(|| "requantize sign"),
+      &vec![t.iter().collect()],
+      &vec![zero, right],
+    )?;
+    let non_pos = ComparatorChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "requantize non positive"),
+      &vec![q.iter().collect(), vec![zero; q.len()]],
+      &vec![zero],
+    )?;
+    let t = SubPairsChip::<F>::construct(gadget_config.clone()).forward(
+      layouter.namespace(|| "requantize away"),
+      &vec![t.iter().collect(), non_pos.iter().collect()],
+      &vec![zero],
+    )?;
+    var_div_chip.forward(
+      layouter.namespace(|| "requantize shift"),
+      &vec![t.iter().collect()],
+      &vec![zero, right],
+    )
+  }
+}
+
+impl<F: PrimeField> Layer<F> for RequantizeChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let params = &layer_config.layer_params;
+    let lo = constants.get(&params[0]).unwrap().as_ref();
+    let hi = constants.get(&params[1]).unwrap().as_ref();
+    let channels = Self::channels(params);
+    let inp = &tensors[0];
+    let num_channels = channels.len();
+    assert!(
+      num_channels == 1 || inp.shape().last() == Some(&num_channels),
+      "{} multipliers for a last axis of {:?}",
+      num_channels,
+      inp.shape().last()
+    );
+
+    let flat = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let mut outp = vec![None; flat.len()];
+    for (c, (multiplier, shift)) in channels.iter().enumerate() {
+      let idxes = (c..flat.len()).step_by(num_channels).collect::<Vec<_>>();
+      let x = idxes.iter().map(|i| flat[*i]).collect::<Vec<_>>();
+      let y = Self::requantize(
+        layouter.namespace(|| format!("requantize channel {}", c)),
+        &x,
+        *multiplier,
+        *shift,
+        constants,
+        gadget_config.clone(),
+      )?;
+
+      // min(y, hi), then max(., lo)
+      let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+      let y = y.iter().collect::<Vec<_>>();
+      let below_hi = comparator_chip.forward(
+        layouter.namespace(|| format!("requantize below hi {}", c)),
+        &vec![y.clone(), vec![hi; y.len()]],
+        &vec![zero],
+      )?;
+      let min = Self::select(
+        layouter.namespace(|| format!("requantize min {}", c)),
+        &below_hi,
+        &y,
+        hi,
+        zero,
+        gadget_config.clone(),
+      )?;
+      let min = min.iter().collect::<Vec<_>>();
+      let above_lo = comparator_chip.forward(
+        layouter.namespace(|| format!("requantize above lo {}", c)),
+        &vec![vec![lo; min.len()], min.clone()],
+        &vec![zero],
+      )?;
+      let max = Self::select(
+        layouter.namespace(|| format!("requantize max {}", c)),
+        &above_lo,
+        &min,
+        lo,
+        zero,
+        gadget_config.clone(),
+      )?;
+      for (i, cell) in idxes.iter().zip(max.into_iter()) {
+        outp[*i] = Some(Rc::new(cell));
+      }
+    }
+
+    let outp = outp.into_iter().map(|x| x.unwrap()).collect::<Vec<_>>();
+    let outp = Array::from_shape_vec(IxDyn(inp.shape()), outp).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for RequantizeChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::AddPairs,
+      GadgetType::Comparator,
+      GadgetType::MulPairs,
+      GadgetType::SubPairs,
+      GadgetType::VarDivRoundBig3,
+      GadgetType::InputLookup,
+    ]
+  }
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  comparator::ComparatorChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  sub_pairs::SubPairsChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// L-infinity robustness check. Params: [class]
+// Inputs are the input x, epsilon ([1]), the logits on x, then (x_j, logits on x_j) for every
+// perturbed input. Constrains |x_j - x| <= epsilon elementwise and that class is an argmax of
+// every set of logits. Outputs epsilon, so it is part of the public values.
+// The comparisons go through the input lookup, so the logit gaps must be less than the number
+// of rows.
+#[derive(Clone, Debug)]
+pub struct RobustnessChip {}
+
+impl<F: PrimeField> Layer<F> for RobustnessChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    assert!(tensors.len() >= 3 && tensors.len() % 2 == 1);
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let class = layer_config.layer_params[0] as usize;
+
+    let inp = tensors[0].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let eps = tensors[1].iter().next().unwrap().as_ref();
+    let mut logits = vec![&tensors[2]];
+    let mut perturbed = vec![];
+    for pair in tensors[3..].chunks(2) {
+      assert_eq!(pair[0].len(), inp.len());
+      perturbed.extend(pair[0].iter().map(|x| x.as_ref()));
+      logits.push(&pair[1]);
+    }
+    let num_perturbed = logits.len() - 1;
+
+    // d_j = x_j - x
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+    let inp_rep = inp.repeat(num_perturbed);
+    let diffs = sub_pairs_chip.forward(
+      layouter.namespace(|| "robustness diffs"),
+      &vec![perturbed, inp_rep],
+      &vec![zero],
+    )?;
+    let neg_eps = sub_pairs_chip.forward(
+      layouter.namespace(|| "robustness neg eps"),
+      &vec![vec![zero], vec![eps]],
+      &vec![zero],
+    )?;
+
+    // -eps <= d_j <= eps, and logits[i] <= logits[class]
+    let mut lhs = vec![];
+    let mut rhs = vec![];
+    for diff in diffs.iter() {
+      lhs.push(diff);
+      rhs.push(eps);
+      lhs.push(&neg_eps[0]);
+      rhs.push(diff);
+    }
+    for logit in logits.iter() {
+      let logit = logit.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+      assert!(class < logit.len(), "class out of range");
+      for (i, val) in logit.iter().enumerate() {
+        if i != class {
+          lhs.push(*val);
+          rhs.push(logit[class]);
+        }
+      }
+    }
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let bits = comparator_chip.forward(
+      layouter.namespace(|| "robustness comparisons"),
+      &vec![lhs, rhs],
+      &vec![zero],
+    )?;
+
+    layouter.assign_region(
+      || "robustness checks",
+      |mut region| {
+        for bit in bits.iter() {
+          region.constrain_equal(bit.cell(), one.cell())?;
+        }
+        Ok(())
+      },
+    )?;
+
+    let out =
+      Array::from_shape_vec(IxDyn(&[1]), vec![tensors[1].iter().next().unwrap().clone()]).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for RobustnessChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::SubPairs,
+      GadgetType::Comparator,
+      GadgetType::InputLookup,
+    ]
+  }
+}
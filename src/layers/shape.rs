@@ -1,12 +1,15 @@
 pub mod broadcast;
 pub mod concatenation;
+pub mod gather;
 pub mod mask_neg_inf;
+pub mod occlude;
 pub mod pack;
 pub mod pad;
 pub mod permute;
 pub mod reshape;
 pub mod resize_nn;
 pub mod rotate;
+pub mod scatter_nd;
 pub mod slice;
 pub mod split;
 pub mod transpose;
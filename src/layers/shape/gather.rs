@@ -0,0 +1,194 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{
+  circuit::{Layouter, Value},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, Axis, IxDyn};
+
+use crate::{
+  gadgets::{
+    adder::AdderChip,
+    dot_prod::DotProductChip,
+    gadget::{Gadget, GadgetConfig, GadgetType},
+    mul_pairs::MulPairsChip,
+  },
+  layers::layer::{AssignedTensor, CellRc, GadgetConsumer},
+  utils::helpers::convert_pos_int,
+};
+
+use super::super::layer::{Layer, LayerConfig};
+
+// Params: [axis, indices...]
+// Takes the given indices along the axis, as ONNX Gather, so the output shape is the input shape
+// with the axis replaced by the shape of the indices. With only the axis, the indices are the
+// second input, e.g., the token ids of an embedding lookup. They are then plain integers, not
+// fixed point, and every index is checked with a one-hot selector over the axis, so a lookup
+// costs about the size of the axis times the size of the rest of the input.
+pub struct GatherChip {}
+
+impl GatherChip {
+  // The positions along the axis weight the selectors of a runtime lookup
+  pub fn constants(layer_config: &LayerConfig) -> Vec<i64> {
+    if layer_config.layer_params.len() != 1 {
+      return vec![];
+    }
+    let axis = layer_config.layer_params[0] as usize;
+    (0..layer_config.inp_shapes[0][axis] as i64).collect()
+  }
+
+  // sel[j] is the one-hot selector of the j-th index, constrained to be bits that sum to one,
+  // with the position they pick equal to the index
+  fn gather_dynamic<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    data: &AssignedTensor<F>,
+    indices: &AssignedTensor<F>,
+    axis: usize,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<AssignedTensor<F>, Error> {
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let dot_prod_chip = DotProductChip::<F>::construct(gadget_config.clone());
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let shape = data.shape();
+    let num_pos = shape[axis];
+    let outer = shape[..axis].iter().product::<usize>();
+    let inner = shape[axis + 1..].iter().product::<usize>();
+    let data = Array::from_shape_vec(
+      IxDyn(&[outer, num_pos, inner]),
+      data.iter().cloned().collect(),
+    )
+    .unwrap();
+    let indices = indices.iter().collect::<Vec<_>>();
+
+    let columns = &gadget_config.columns;
+    let sel = layouter.assign_region(
+      || "gather selectors",
+      |mut region| {
+        let mut sel = vec![];
+        for (j, idx) in indices.iter().enumerate() {
+          let idx = convert_pos_int(idx.value().cloned());
+          let mut row = vec![];
+          for i in 0..num_pos {
+            let pos = j * num_pos + i;
+            let bit = if idx == i as i128 { F::ONE } else { F::ZERO };
+            row.push(region.assign_advice(
+              || "",
+              columns[pos % columns.len()],
+              pos / columns.len(),
+              || Value::known(bit),
+            )?);
+          }
+          sel.push(row);
+        }
+        Ok(sel)
+      },
+    )?;
+    let sel_flat = sel.iter().flatten().collect::<Vec<_>>();
+
+    // sel * sel = sel
+    let sel_sq = mul_pairs_chip.forward(
+      layouter.namespace(|| "gather selector bits"),
+      &vec![sel_flat.clone(), sel_flat.clone()],
+      &vec![zero],
+    )?;
+
+    let positions = (0..num_pos as i64)
+      .map(|i| constants.get(&i).unwrap().as_ref())
+      .collect::<Vec<_>>();
+    let mut sel_sums = vec![];
+    let mut picked = vec![];
+    for (j, row) in sel.iter().enumerate() {
+      let sum = adder_chip.forward(
+        layouter.namespace(|| format!("gather selector sum {}", j)),
+        &vec![row.iter().collect()],
+        &vec![zero],
+      )?;
+      sel_sums.push(sum[0].clone());
+      let pos = dot_prod_chip.forward(
+        layouter.namespace(|| format!("gather selector position {}", j)),
+        &vec![row.iter().collect(), positions.clone()],
+        &vec![zero],
+      )?;
+      picked.push(pos[0].clone());
+    }
+
+    layouter.assign_region(
+      || "gather checks",
+      |mut region| {
+        for (a, b) in sel_flat.iter().zip(sel_sq.iter()) {
+          region.constrain_equal(a.cell(), b.cell())?;
+        }
+        for x in sel_sums.iter() {
+          region.constrain_equal(x.cell(), one.cell())?;
+        }
+        for (idx, pos) in indices.iter().zip(picked.iter()) {
+          region.constrain_equal(idx.cell(), pos.cell())?;
+        }
+        Ok(())
+      },
+    )?;
+
+    let mut outp = vec![];
+    for o in 0..outer {
+      for (j, row) in sel.iter().enumerate() {
+        for i in 0..inner {
+          let col = (0..num_pos)
+            .map(|p| data[[o, p, i]].as_ref())
+            .collect::<Vec<_>>();
+          let val = dot_prod_chip.forward(
+            layouter.namespace(|| format!("gather {} {} {}", o, j, i)),
+            &vec![row.iter().collect(), col],
+            &vec![zero],
+          )?;
+          outp.push(Rc::new(val[0].clone()));
+        }
+      }
+    }
+    Ok(Array::from_vec(outp).into_dyn())
+  }
+}
+
+impl<F: PrimeField> Layer<F> for GatherChip {
+  fn forward(
+    &self,
+    layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let params = &layer_config.layer_params;
+    let axis = params[0] as usize;
+    let inp = &tensors[0];
+    let flat = if params.len() == 1 {
+      Self::gather_dynamic(layouter, inp, &tensors[1], axis, constants, gadget_config)?
+    } else {
+      let indices = params[1..].iter().map(|x| *x as usize).collect::<Vec<_>>();
+      inp.select(Axis(axis), &indices)
+    };
+
+    let shape = layer_config.out_shapes[0].clone();
+    let flat = flat.iter().cloned().collect::<Vec<_>>();
+    let outp = Array::from_shape_vec(IxDyn(&shape), flat).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for GatherChip {
+  fn used_gadgets(&self, layer_params: Vec<i64>) -> Vec<GadgetType> {
+    if layer_params.len() == 1 {
+      vec![
+        GadgetType::Adder,
+        GadgetType::DotProduct,
+        GadgetType::MulPairs,
+      ]
+    } else {
+      vec![]
+    }
+  }
+}
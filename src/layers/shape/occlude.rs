@@ -0,0 +1,42 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::Array;
+
+use crate::{
+  gadgets::gadget::GadgetConfig,
+  layers::layer::{AssignedTensor, CellRc, GadgetConsumer},
+};
+
+use super::super::layer::{Layer, LayerConfig};
+
+// Params: the flat positions to occlude. Replaces them with zero.
+pub struct OccludeChip {}
+
+impl<F: PrimeField> Layer<F> for OccludeChip {
+  fn forward(
+    &self,
+    _layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    _gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let zero = constants.get(&0).unwrap().clone();
+
+    let mut out_vec = inp.iter().cloned().collect::<Vec<_>>();
+    for pos in layer_config.layer_params.iter() {
+      out_vec[*pos as usize] = zero.clone();
+    }
+
+    let outp = Array::from_shape_vec(inp.raw_dim(), out_vec).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for OccludeChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![]
+  }
+}
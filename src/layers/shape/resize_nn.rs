@@ -34,8 +34,10 @@ impl<F: PrimeField> Layer<F> for ResizeNNChip {
     // The interpolation is over h and w
     for b in 0..inp.shape()[0] {
       for h in 0..output_shape[1] {
+        // float-ok: IEEE products and quotients are correctly rounded, the same on every target
         let h_in = (h as f64 * (inp.shape()[1] as f64 / output_shape[1] as f64)) as usize;
         for w in 0..output_shape[2] {
+          // float-ok: as for h
           let w_in = (w as f64 * (inp.shape()[2] as f64 / output_shape[2] as f64)) as usize;
           for c in 0..inp.shape()[3] {
             flat.push(inp[[b, h_in, w_in, c]].clone());
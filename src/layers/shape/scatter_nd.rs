@@ -0,0 +1,60 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::SliceInfoElem;
+
+use crate::{
+  gadgets::gadget::{GadgetConfig, GadgetType},
+  layers::layer::{AssignedTensor, CellRc, GadgetConsumer},
+};
+
+use super::super::layer::{Layer, LayerConfig};
+
+// Params: [index length k, indices...]
+// As ONNX ScatterND without reduction: the inputs are the data and the updates, and the flat
+// indices are a list of k-tuples into the first k axes of the data. The slice at each tuple is
+// replaced by the next slice of the updates, in order, so a repeated tuple takes the last update.
+pub struct ScatterNDChip {}
+
+impl<F: PrimeField> Layer<F> for ScatterNDChip {
+  fn forward(
+    &self,
+    _layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    _constants: &HashMap<i64, CellRc<F>>,
+    _gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let params = &layer_config.layer_params;
+    let k = params[0] as usize;
+    let indices = &params[1..];
+    assert!(k > 0 && indices.len() % k == 0);
+
+    let mut outp = tensors[0].clone();
+    assert!(k <= outp.ndim());
+    let updates = tensors[1].iter().collect::<Vec<_>>();
+    let slice_len = outp.shape()[k..].iter().product::<usize>();
+    assert_eq!(updates.len(), indices.len() / k * slice_len);
+
+    for (tuple, update) in indices.chunks(k).zip(updates.chunks(slice_len)) {
+      let info = (0..outp.ndim())
+        .map(|ax| match tuple.get(ax) {
+          Some(idx) => SliceInfoElem::Index(*idx as isize),
+          None => SliceInfoElem::from(..),
+        })
+        .collect::<Vec<_>>();
+      let mut slice = outp.slice_mut(info.as_slice());
+      for (dst, src) in slice.iter_mut().zip(update.iter()) {
+        *dst = (*src).clone();
+      }
+    }
+
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for ScatterNDChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<GadgetType> {
+    vec![]
+  }
+}
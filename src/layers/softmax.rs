@@ -1,19 +1,26 @@
 use std::{collections::HashMap, rc::Rc, vec};
 
 use halo2_proofs::{
-  circuit::{AssignedCell, Layouter},
+  circuit::{AssignedCell, Layouter, Value},
   halo2curves::ff::PrimeField,
   plonk::Error,
 };
-use ndarray::{s, Array, IxDyn};
-
-use crate::gadgets::{
-  adder::AdderChip,
-  gadget::{Gadget, GadgetConfig, GadgetType},
-  max::MaxChip,
-  nonlinear::exp::ExpGadgetChip,
-  sub_pairs::SubPairsChip,
-  var_div_big3::VarDivRoundBig3Chip,
+use ndarray::{Array, IxDyn};
+
+use crate::{
+  gadgets::{
+    add_pairs::AddPairsChip,
+    adder::AdderChip,
+    comparator::ComparatorChip,
+    dot_prod::DotProductChip,
+    gadget::{Gadget, GadgetConfig, GadgetType},
+    max::MaxChip,
+    mul_pairs::MulPairsChip,
+    nonlinear::exp::ExpGadgetChip,
+    sub_pairs::SubPairsChip,
+    var_div_big3::VarDivRoundBig3Chip,
+  },
+  utils::helpers::convert_pos_int,
 };
 
 use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
@@ -22,12 +29,11 @@ use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
 pub struct SoftmaxChip {}
 
 impl SoftmaxChip {
-  pub fn softmax_flat<F: PrimeField>(
+  fn softmax_dense<F: PrimeField>(
     mut layouter: impl Layouter<F>,
     constants: &HashMap<i64, CellRc<F>>,
-    inp_flat: Vec<&AssignedCell<F, F>>,
+    inp_take: Vec<&AssignedCell<F, F>>,
     gadget_config: Rc<GadgetConfig>,
-    mask: &Vec<i64>,
   ) -> Result<Vec<AssignedCell<F, F>>, Error> {
     let exp_chip = ExpGadgetChip::<F>::construct(gadget_config.clone());
     let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
@@ -41,14 +47,6 @@ impl SoftmaxChip {
       .unwrap()
       .as_ref();
 
-    // Mask the input for max computation and subtraction
-    let inp_take = inp_flat
-      .iter()
-      .enumerate()
-      .filter(|(i, _)| mask[*i] == 0) // Awkwardly, 1 = take negative infinity
-      .map(|(_, x)| *x)
-      .collect::<Vec<_>>();
-
     // Compute the max
     let max = max_chip
       .forward(
@@ -96,6 +94,221 @@ impl SoftmaxChip {
       &vec![zero, &sum_div_sf],
     )?;
 
+    Ok(dived)
+  }
+
+  pub fn top_k_gadgets() -> Vec<GadgetType> {
+    vec![
+      GadgetType::AddPairs,
+      GadgetType::Adder,
+      GadgetType::Comparator,
+      GadgetType::DotProduct,
+      GadgetType::MulPairs,
+      GadgetType::SubPairs,
+    ]
+  }
+
+  // Approximates the softmax by the softmax over the k largest inputs, the rest are zero. The
+  // top k are gathered with one-hot selectors (sel[j] picks the j-th largest), so only k exps are
+  // looked up. The selectors are constrained to be bits, to pick one input each, to pick distinct
+  // inputs, to be in decreasing order, and every input that is not picked must be at most the
+  // k-th largest. The comparisons go through the input lookup.
+  fn softmax_top_k<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    inp: Vec<&AssignedCell<F, F>>,
+    gadget_config: Rc<GadgetConfig>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let add_pairs_chip = AddPairsChip::<F>::construct(gadget_config.clone());
+    let adder_chip = AdderChip::<F>::construct(gadget_config.clone());
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let dot_prod_chip = DotProductChip::<F>::construct(gadget_config.clone());
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let top_k = gadget_config.softmax_top_k;
+    let num_inp = inp.len();
+
+    // Ties are broken by position
+    let vals = inp
+      .iter()
+      .map(|x| convert_pos_int(x.value().cloned()))
+      .collect::<Vec<_>>();
+    let mut order = (0..num_inp).collect::<Vec<_>>();
+    order.sort_by_key(|i| (-vals[*i], *i));
+
+    let columns = &gadget_config.columns;
+    let sel = layouter.assign_region(
+      || "top k selectors",
+      |mut region| {
+        let mut sel = vec![];
+        for j in 0..top_k {
+          let mut row = vec![];
+          for i in 0..num_inp {
+            let pos = j * num_inp + i;
+            let bit = if order[j] == i { F::ONE } else { F::ZERO };
+            row.push(region.assign_advice(
+              || "",
+              columns[pos % columns.len()],
+              pos / columns.len(),
+              || Value::known(bit),
+            )?);
+          }
+          sel.push(row);
+        }
+        Ok(sel)
+      },
+    )?;
+    let sel_flat = sel.iter().flatten().collect::<Vec<_>>();
+
+    // sel * sel = sel
+    let sel_sq = mul_pairs_chip.forward(
+      layouter.namespace(|| "top k selector bits"),
+      &vec![sel_flat.clone(), sel_flat.clone()],
+      &vec![zero],
+    )?;
+
+    // Every selector picks one input
+    let mut sel_sums = vec![];
+    for (j, row) in sel.iter().enumerate() {
+      let sum = adder_chip.forward(
+        layouter.namespace(|| format!("top k selector sum {}", j)),
+        &vec![row.iter().collect()],
+        &vec![zero],
+      )?;
+      sel_sums.push(sum[0].clone());
+    }
+
+    // Every input is picked at most once
+    let mut picked = sel[0].clone();
+    for (j, row) in sel.iter().enumerate().skip(1) {
+      picked = add_pairs_chip.forward(
+        layouter.namespace(|| format!("top k picked {}", j)),
+        &vec![picked.iter().collect(), row.iter().collect()],
+        &vec![zero],
+      )?;
+    }
+    let picked_sq = mul_pairs_chip.forward(
+      layouter.namespace(|| "top k picked bits"),
+      &vec![picked.iter().collect(), picked.iter().collect()],
+      &vec![zero],
+    )?;
+
+    // Gather the top k, which must be in decreasing order
+    let mut top = vec![];
+    for (j, row) in sel.iter().enumerate() {
+      let val = dot_prod_chip.forward(
+        layouter.namespace(|| format!("top k gather {}", j)),
+        &vec![inp.clone(), row.iter().collect()],
+        &vec![zero],
+      )?;
+      top.push(val[0].clone());
+    }
+    let mut order_bits = if top_k > 1 {
+      comparator_chip.forward(
+        layouter.namespace(|| "top k order"),
+        &vec![top[1..].iter().collect(), top[..top_k - 1].iter().collect()],
+        &vec![zero],
+      )?
+    } else {
+      vec![]
+    };
+
+    // x - picked * (x - kth) <= kth, which holds trivially for the picked inputs
+    let kth = &top[top_k - 1];
+    let diff = sub_pairs_chip.forward(
+      layouter.namespace(|| "top k diff"),
+      &vec![inp.clone(), vec![kth; num_inp]],
+      &vec![zero],
+    )?;
+    let picked_diff = mul_pairs_chip.forward(
+      layouter.namespace(|| "top k picked diff"),
+      &vec![picked.iter().collect(), diff.iter().collect()],
+      &vec![zero],
+    )?;
+    let unpicked = sub_pairs_chip.forward(
+      layouter.namespace(|| "top k unpicked"),
+      &vec![inp.clone(), picked_diff.iter().collect()],
+      &vec![zero],
+    )?;
+    order_bits.extend(comparator_chip.forward(
+      layouter.namespace(|| "top k bound"),
+      &vec![unpicked.iter().collect(), vec![kth; num_inp]],
+      &vec![zero],
+    )?);
+
+    layouter.assign_region(
+      || "top k checks",
+      |mut region| {
+        for (a, b) in sel_flat.iter().zip(sel_sq.iter()) {
+          region.constrain_equal(a.cell(), b.cell())?;
+        }
+        for (a, b) in picked.iter().zip(picked_sq.iter()) {
+          region.constrain_equal(a.cell(), b.cell())?;
+        }
+        for x in sel_sums.iter().chain(order_bits.iter()) {
+          region.constrain_equal(x.cell(), one.cell())?;
+        }
+        Ok(())
+      },
+    )?;
+
+    let probs = Self::softmax_dense(
+      layouter.namespace(|| "top k softmax"),
+      constants,
+      top.iter().collect(),
+      gadget_config.clone(),
+    )?;
+
+    // Scatter the probabilities back with the selectors
+    let mut outp = vec![zero.clone(); num_inp];
+    for (j, (row, prob)) in sel.iter().zip(probs.iter()).enumerate() {
+      let scattered = mul_pairs_chip.forward(
+        layouter.namespace(|| format!("top k scatter {}", j)),
+        &vec![row.iter().collect(), vec![prob; num_inp]],
+        &vec![zero],
+      )?;
+      outp = add_pairs_chip.forward(
+        layouter.namespace(|| format!("top k scatter sum {}", j)),
+        &vec![outp.iter().collect(), scattered.iter().collect()],
+        &vec![zero],
+      )?;
+    }
+
+    Ok(outp)
+  }
+
+  pub fn softmax_flat<F: PrimeField>(
+    mut layouter: impl Layouter<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    inp_flat: Vec<&AssignedCell<F, F>>,
+    gadget_config: Rc<GadgetConfig>,
+    mask: &Vec<i64>,
+  ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+    let zero = constants.get(&0).unwrap().as_ref();
+
+    // Mask the input for max computation and subtraction
+    let inp_take = inp_flat
+      .iter()
+      .enumerate()
+      .filter(|(i, _)| mask[*i] == 0) // Awkwardly, 1 = take negative infinity
+      .map(|(_, x)| *x)
+      .collect::<Vec<_>>();
+
+    let top_k = gadget_config.softmax_top_k;
+    let dived = if top_k > 0 && top_k < inp_take.len() {
+      Self::softmax_top_k(
+        layouter.namespace(|| "softmax top k"),
+        constants,
+        inp_take,
+        gadget_config.clone(),
+      )?
+    } else {
+      Self::softmax_dense(layouter, constants, inp_take, gadget_config.clone())?
+    };
+
     // Take either zero (softmax(-inf)) or the result
     let mut div_idx = 0;
     let dived = mask
@@ -125,10 +338,7 @@ impl<F: PrimeField> Layer<F> for SoftmaxChip {
     layer_config: &LayerConfig,
   ) -> Result<Vec<AssignedTensor<F>>, Error> {
     let inp = &tensors[0];
-    assert!(inp.ndim() == 2 || inp.ndim() == 3 || inp.ndim() == 4);
-    if inp.ndim() == 4 {
-      assert_eq!(inp.shape()[0], 1);
-    }
+    assert!(inp.ndim() >= 1);
 
     let inp_shape = inp.shape().iter().map(|x| *x).collect::<Vec<_>>();
     let mask = if layer_config.layer_params.len() == 0 {
@@ -145,55 +355,31 @@ impl<F: PrimeField> Layer<F> for SoftmaxChip {
       mask
     };
 
-    let shape = if inp.ndim() == 2 || inp.ndim() == 3 {
-      inp.shape().iter().map(|x| *x).collect::<Vec<_>>()
-    } else {
-      vec![inp.shape()[1], inp.shape()[2], inp.shape()[3]]
-    };
-    let inp = inp.to_owned().into_shape(shape.clone()).unwrap();
-    let mask = mask.into_shape(shape.clone()).unwrap();
+    // The softmax is over the last axis, so every other axis is a row, e.g., the rows of the
+    // attention scores of every head
+    let row_len = inp_shape[inp_shape.len() - 1];
+    let inp_flat = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let mask_flat = mask.iter().map(|x| *x as i64).collect::<Vec<_>>();
 
     let mut outp = vec![];
-    if inp.ndim() == 2 {
-      for i in 0..shape[0] {
-        let inp_slice = inp.slice(s![i, ..]);
-        let inp_flat = inp_slice.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
-        let mask_slice = mask.slice(s![i, ..]);
-        let mask_flat = mask_slice.iter().map(|x| *x as i64).collect::<Vec<_>>();
-        let dived = Self::softmax_flat(
-          layouter.namespace(|| format!("softmax {}", i)),
-          constants,
-          inp_flat,
-          gadget_config.clone(),
-          &mask_flat,
-        )
-        .unwrap();
-        outp.extend(dived);
-      }
-    } else if inp.ndim() == 3 {
-      for i in 0..shape[0] {
-        for j in 0..shape[1] {
-          let inp_slice = inp.slice(s![i, j, ..]);
-          let inp_flat = inp_slice.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
-          let mask_slice = mask.slice(s![i, j, ..]);
-          let mask_flat = mask_slice.iter().map(|x| *x as i64).collect::<Vec<_>>();
-          let dived = Self::softmax_flat(
-            layouter.namespace(|| format!("softmax {} {}", i, j)),
-            constants,
-            inp_flat,
-            gadget_config.clone(),
-            &mask_flat,
-          )
-          .unwrap();
-          outp.extend(dived);
-        }
-      }
-    } else {
-      panic!("Not implemented");
+    for (i, (inp_row, mask_row)) in inp_flat
+      .chunks(row_len)
+      .zip(mask_flat.chunks(row_len))
+      .enumerate()
+    {
+      let dived = Self::softmax_flat(
+        layouter.namespace(|| format!("softmax {}", i)),
+        constants,
+        inp_row.to_vec(),
+        gadget_config.clone(),
+        &mask_row.to_vec(),
+      )
+      .unwrap();
+      outp.extend(dived);
     }
 
     let outp = outp.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
-    let outp = Array::from_shape_vec(IxDyn(inp.shape()), outp).unwrap();
+    let outp = Array::from_shape_vec(IxDyn(&inp_shape), outp).unwrap();
     Ok(vec![outp])
   }
 }
@@ -0,0 +1,53 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  nonlinear::tabulated::TabulatedGadgetChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Fallback for pointwise ops without a dedicated gadget: the converter emits the input -> output
+// table, which is enforced via a lookup. At load time the table is moved into the gadget config
+// and the layer params are replaced by the index of the table.
+#[derive(Clone, Debug)]
+pub struct TabulatedChip {}
+
+impl<F: PrimeField> Layer<F> for TabulatedChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = &tensors[0];
+    let inp_vec = inp.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+    let table_idx = layer_config.layer_params[0] as usize;
+
+    let tabulated_chip = TabulatedGadgetChip::<F>::construct(gadget_config.clone(), table_idx);
+    let vec_inps = vec![inp_vec];
+    let constants = vec![zero];
+    let out = tabulated_chip.forward(
+      layouter.namespace(|| "tabulated chip"),
+      &vec_inps,
+      &constants,
+    )?;
+
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(inp.shape()), out).unwrap();
+
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for TabulatedChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![GadgetType::Tabulated, GadgetType::InputLookup]
+  }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, rc::Rc};
+
+use halo2_proofs::{circuit::Layouter, halo2curves::ff::PrimeField, plonk::Error};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  gadget::{GadgetConfig, GadgetType},
+  sorting_network::{sort_gadgets, SortingNetworkChip},
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+pub const LARGEST: i64 = 0;
+pub const SMALLEST: i64 = 1;
+
+// The indices of the k largest or smallest elements of the flattened input, e.g., the predicted
+// class (the argmax) of a classifier, so that the logits can stay private (see predicate.rs).
+// Params: [LARGEST or SMALLEST, k]. The input is sorted by a sorting network (see
+// sorting_network.rs) with the indices as the payload, which are constants of the circuit. The
+// order of equal elements is the one of the network. Outputs the indices ([k]), the largest (or
+// smallest) first.
+#[derive(Clone, Debug)]
+pub struct TopKChip {}
+
+impl TopKChip {
+  pub fn constants(inp_len: usize) -> Vec<i64> {
+    (0..inp_len as i64).collect()
+  }
+}
+
+impl<F: PrimeField> Layer<F> for TopKChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = tensors[0].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+    let (order, k) = (
+      layer_config.layer_params[0],
+      layer_config.layer_params[1] as usize,
+    );
+    assert!(
+      k >= 1 && k <= inp.len(),
+      "top {} of {} elements",
+      k,
+      inp.len()
+    );
+    let indices = Self::constants(inp.len())
+      .iter()
+      .map(|i| constants.get(i).unwrap().as_ref())
+      .collect::<Vec<_>>();
+
+    let sorting_chip = SortingNetworkChip::<F>::construct(gadget_config.clone());
+    let (_, sorted_indices) = sorting_chip.forward(
+      layouter.namespace(|| "top k sort"),
+      &inp,
+      &vec![indices],
+      zero,
+    )?;
+    let sorted_indices = sorted_indices.into_iter().next().unwrap();
+
+    let out = match order {
+      LARGEST => sorted_indices.into_iter().rev().take(k).collect::<Vec<_>>(),
+      SMALLEST => sorted_indices.into_iter().take(k).collect::<Vec<_>>(),
+      order => panic!("unknown top k order {}", order),
+    };
+    let out = out.into_iter().map(|x| Rc::new(x)).collect::<Vec<_>>();
+    let out = Array::from_shape_vec(IxDyn(&[k]), out).unwrap();
+    Ok(vec![out])
+  }
+}
+
+impl GadgetConsumer for TopKChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    let mut gadgets = sort_gadgets();
+    gadgets.push(GadgetType::InputLookup);
+    gadgets
+  }
+}
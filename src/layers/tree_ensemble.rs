@@ -0,0 +1,192 @@
+use std::{collections::HashMap, rc::Rc, vec};
+
+use halo2_proofs::{
+  circuit::{AssignedCell, Layouter},
+  halo2curves::ff::PrimeField,
+  plonk::Error,
+};
+use ndarray::{Array, IxDyn};
+
+use crate::gadgets::{
+  comparator::ComparatorChip,
+  dot_prod::DotProductChip,
+  gadget::{Gadget, GadgetConfig, GadgetType},
+  mul_pairs::MulPairsChip,
+  sub_pairs::SubPairsChip,
+};
+
+use super::layer::{AssignedTensor, CellRc, GadgetConsumer, Layer, LayerConfig};
+
+// Sum of decision trees over a single feature vector.
+// Params: [num_outputs, num_trees, then per tree: num_nodes, (left, right, feature) per node]
+// Node 0 is the root and leaves have feature -1. Inputs are the features, the thresholds (one
+// per internal node, in order) and the leaf values ([num_leaves, num_outputs], in order).
+// Internal nodes go left if x[feature] <= threshold. Each leaf gets an indicator that is the
+// product of the decisions along its path, so exactly one leaf per tree is one, and the outputs
+// are the dot products of the indicators with the leaf values.
+#[derive(Clone, Debug)]
+pub struct TreeEnsembleChip {}
+
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+  pub left: usize,
+  pub right: usize,
+  pub feature: i64,
+  // Index into the thresholds for internal nodes, into the leaf values for leaves
+  pub value_idx: usize,
+}
+
+impl TreeEnsembleChip {
+  pub fn parse_params(params: &Vec<i64>) -> (usize, Vec<Vec<TreeNode>>) {
+    let num_outputs = params[0] as usize;
+    let num_trees = params[1] as usize;
+
+    let mut trees = vec![];
+    let mut pos = 2;
+    let mut num_internal = 0;
+    let mut num_leaves = 0;
+    for _ in 0..num_trees {
+      let num_nodes = params[pos] as usize;
+      pos += 1;
+      let mut nodes = vec![];
+      for _ in 0..num_nodes {
+        let feature = params[pos + 2];
+        let value_idx = if feature < 0 {
+          num_leaves += 1;
+          num_leaves - 1
+        } else {
+          num_internal += 1;
+          num_internal - 1
+        };
+        nodes.push(TreeNode {
+          left: params[pos] as usize,
+          right: params[pos + 1] as usize,
+          feature,
+          value_idx,
+        });
+        pos += 3;
+      }
+      trees.push(nodes);
+    }
+    assert_eq!(pos, params.len(), "malformed tree ensemble params");
+
+    (num_outputs, trees)
+  }
+}
+
+impl<F: PrimeField> Layer<F> for TreeEnsembleChip {
+  fn forward(
+    &self,
+    mut layouter: impl Layouter<F>,
+    tensors: &Vec<AssignedTensor<F>>,
+    constants: &HashMap<i64, CellRc<F>>,
+    gadget_config: Rc<GadgetConfig>,
+    layer_config: &LayerConfig,
+  ) -> Result<Vec<AssignedTensor<F>>, Error> {
+    let inp = tensors[0].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let thresholds = tensors[1].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let leaf_values = tensors[2].iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+
+    let (num_outputs, trees) = Self::parse_params(&layer_config.layer_params);
+    let num_leaves = leaf_values.len() / num_outputs;
+
+    // Compare all of the internal nodes at once
+    let mut cmp_features = vec![];
+    let mut cmp_thresholds = vec![];
+    for node in trees.iter().flatten().filter(|node| node.feature >= 0) {
+      cmp_features.push(inp[node.feature as usize]);
+      cmp_thresholds.push(thresholds[node.value_idx]);
+    }
+    assert_eq!(cmp_thresholds.len(), thresholds.len());
+    let comparator_chip = ComparatorChip::<F>::construct(gadget_config.clone());
+    let decisions = comparator_chip.forward(
+      layouter.namespace(|| "tree comparisons"),
+      &vec![cmp_features, cmp_thresholds],
+      &vec![zero],
+    )?;
+
+    // Push the path indicators down one level of every tree at a time
+    let mul_pairs_chip = MulPairsChip::<F>::construct(gadget_config.clone());
+    let sub_pairs_chip = SubPairsChip::<F>::construct(gadget_config.clone());
+    let mut leaf_indicators: Vec<Option<AssignedCell<F, F>>> = vec![None; num_leaves];
+    let mut frontier = (0..trees.len())
+      .map(|tree_idx| (tree_idx, 0, one.clone()))
+      .collect::<Vec<_>>();
+    let mut depth = 0;
+    while !frontier.is_empty() {
+      let mut internal = vec![];
+      for (tree_idx, node_idx, indicator) in frontier.into_iter() {
+        let node = &trees[tree_idx][node_idx];
+        if node.feature < 0 {
+          leaf_indicators[node.value_idx] = Some(indicator);
+        } else {
+          internal.push((tree_idx, node_idx, indicator));
+        }
+      }
+      if internal.is_empty() {
+        break;
+      }
+
+      let parents = internal.iter().map(|(_, _, ind)| ind).collect::<Vec<_>>();
+      let bits = internal
+        .iter()
+        .map(|(tree_idx, node_idx, _)| &decisions[trees[*tree_idx][*node_idx].value_idx])
+        .collect::<Vec<_>>();
+      let lefts = mul_pairs_chip.forward(
+        layouter.namespace(|| format!("tree left {}", depth)),
+        &vec![parents.clone(), bits],
+        &vec![zero],
+      )?;
+      let rights = sub_pairs_chip.forward(
+        layouter.namespace(|| format!("tree right {}", depth)),
+        &vec![parents, lefts.iter().collect()],
+        &vec![zero],
+      )?;
+
+      frontier = vec![];
+      for (i, (tree_idx, node_idx, _)) in internal.iter().enumerate() {
+        let node = &trees[*tree_idx][*node_idx];
+        frontier.push((*tree_idx, node.left, lefts[i].clone()));
+        frontier.push((*tree_idx, node.right, rights[i].clone()));
+      }
+      depth += 1;
+    }
+    let leaf_indicators = leaf_indicators
+      .into_iter()
+      .map(|x| x.expect("unreachable leaf in tree ensemble"))
+      .collect::<Vec<_>>();
+    let leaf_indicators = leaf_indicators.iter().collect::<Vec<_>>();
+
+    let dot_prod_chip = DotProductChip::<F>::construct(gadget_config.clone());
+    let mut outp = vec![];
+    for out_idx in 0..num_outputs {
+      let values = (0..num_leaves)
+        .map(|leaf| leaf_values[leaf * num_outputs + out_idx])
+        .collect::<Vec<_>>();
+      let res = dot_prod_chip.forward(
+        layouter.namespace(|| format!("tree output {}", out_idx)),
+        &vec![leaf_indicators.clone(), values],
+        &vec![zero],
+      )?;
+      outp.push(Rc::new(res[0].clone()));
+    }
+
+    let outp = Array::from_shape_vec(IxDyn(&[1, num_outputs]), outp).unwrap();
+    Ok(vec![outp])
+  }
+}
+
+impl GadgetConsumer for TreeEnsembleChip {
+  fn used_gadgets(&self, _layer_params: Vec<i64>) -> Vec<crate::gadgets::gadget::GadgetType> {
+    vec![
+      GadgetType::Comparator,
+      GadgetType::MulPairs,
+      GadgetType::SubPairs,
+      GadgetType::DotProduct,
+      GadgetType::Adder,
+      GadgetType::InputLookup,
+    ]
+  }
+}
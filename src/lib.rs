@@ -1,5 +1,7 @@
 #![feature(int_roundings)]
 
+#[cfg(feature = "aggregation")]
+pub mod aggregation;
 pub mod commitments;
 pub mod gadgets;
 pub mod layers;
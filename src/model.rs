@@ -17,20 +17,29 @@ use num_bigint::BigUint;
 use crate::{
   commitments::{
     commit::Commit,
+    input_hash::hash_assigned_tensors,
     packer::PackerChip,
-    poseidon_commit::{PoseidonCommitChip, L, RATE, WIDTH},
+    poseidon_commit::{PoseidonCommitChip, L},
+    poseidon_params::{RATE, WIDTH},
+    prf::assign_prf_uniforms,
   },
   gadgets::{
     add_pairs::AddPairsChip,
     adder::AdderChip,
     bias_div_round_relu6::BiasDivRoundRelu6Chip,
+    comparator::ComparatorChip,
     dot_prod::DotProductChip,
-    gadget::{Gadget, GadgetConfig, GadgetType},
+    gadget::{default_shift_min_val, Gadget, GadgetConfig, GadgetType},
     input_lookup::InputLookupChip,
+    int_div::{IntDivChip, IntDivMode},
     max::MaxChip,
     mul_pairs::MulPairsChip,
     nonlinear::{exp::ExpGadgetChip, pow::PowGadgetChip, relu::ReluChip, tanh::TanhGadgetChip},
+    nonlinear::{gelu::GeluGadgetChip, non_linearity::activation_div, silu::SiluGadgetChip},
     nonlinear::{logistic::LogisticGadgetChip, rsqrt::RsqrtGadgetChip, sqrt::SqrtGadgetChip},
+    nonlinear::{pwl::PiecewiseLinear, tabulated::TabulatedGadgetChip, unary::UnaryFunction},
+    prf_limbs::PrfLimbsChip,
+    signed_range_check::SignedRangeCheckChip,
     sqrt_big::SqrtBigChip,
     square::SquareGadgetChip,
     squared_diff::SquaredDiffGadgetChip,
@@ -41,38 +50,139 @@ use crate::{
     var_div_big3::VarDivRoundBig3Chip,
   },
   layers::{
+    activation::{Activation, ActivationChip},
     arithmetic::{add::AddChip, div_var::DivVarChip, mul::MulChip, sub::SubChip},
+    attribution::AttributionChip,
     avg_pool_2d::AvgPool2DChip,
+    avg_pool_3d::AvgPool3DChip,
     batch_mat_mul::BatchMatMulChip,
+    branch::BranchChip,
     conv2d::Conv2DChip,
+    conv3d::Conv3DChip,
     dag::{DAGLayerChip, DAGLayerConfig},
+    div_mod::DivModChip,
+    dropout::DropoutChip,
     fully_connected::{FullyConnectedChip, FullyConnectedConfig},
     layer::{AssignedTensor, CellRc, GadgetConsumer, LayerConfig, LayerType},
+    layer_norm::LayerNormChip,
     logistic::LogisticChip,
     max_pool_2d::MaxPool2DChip,
+    max_pool_3d::MaxPool3DChip,
     mean::MeanChip,
+    moe::MoEChip,
     noop::NoopChip,
     pow::PowChip,
+    predicate::PredicateChip,
+    quantile::QuantileChip,
+    range_check::RangeCheckChip,
+    recurrent::{RecurrentCell, RecurrentChip},
+    requantize::RequantizeChip,
+    robustness::RobustnessChip,
     rsqrt::RsqrtChip,
     shape::{
-      broadcast::BroadcastChip, concatenation::ConcatenationChip, mask_neg_inf::MaskNegInfChip,
-      pack::PackChip, pad::PadChip, permute::PermuteChip, reshape::ReshapeChip,
-      resize_nn::ResizeNNChip, rotate::RotateChip, slice::SliceChip, split::SplitChip,
-      transpose::TransposeChip,
+      broadcast::BroadcastChip, concatenation::ConcatenationChip, gather::GatherChip,
+      mask_neg_inf::MaskNegInfChip, occlude::OccludeChip, pack::PackChip, pad::PadChip,
+      permute::PermuteChip, reshape::ReshapeChip, resize_nn::ResizeNNChip, rotate::RotateChip,
+      scatter_nd::ScatterNDChip, slice::SliceChip, split::SplitChip, transpose::TransposeChip,
     },
     softmax::SoftmaxChip,
     sqrt::SqrtChip,
     square::SquareChip,
     squared_diff::SquaredDiffChip,
+    tabulated::TabulatedChip,
     tanh::TanhChip,
+    top_k::TopKChip,
+    tree_ensemble::TreeEnsembleChip,
     update::UpdateChip,
   },
   utils::{
+    branches::select_branches,
+    coprocessor::select_coprocessor_layers,
+    exits::select_exit,
+    head::drop_final_softmax,
     helpers::{convert_to_bigint, RAND_START_IDX},
-    loader::{load_model_msgpack, ModelMsgpack},
+    loader::{load_model_msgpack, set_defaults, ModelMsgpack, TensorMsgpack},
+    precision::apply_frac_bits,
+    predicate::{apply_output_predicate, apply_output_top_k},
+    profiles::apply_column_profile,
+    randomness::apply_random_seed,
+    rlc::NUM_RLC_VALS,
+    tensor::Tensor,
+    witness::synthesize_public_values,
   },
 };
 
+pub mod tflite;
+
+// The layer type of a layer name in the msgpack
+pub fn layer_type_from_name(name: &str) -> Option<LayerType> {
+  let layer_type = match name {
+    "AveragePool2D" => LayerType::AvgPool2D,
+    "AveragePool3D" => LayerType::AvgPool3D,
+    "Add" => LayerType::Add,
+    "Attribution" => LayerType::Attribution,
+    "BatchMatMul" => LayerType::BatchMatMul,
+    "Branch" => LayerType::Branch,
+    "Broadcast" => LayerType::Broadcast,
+    "Concatenation" => LayerType::Concatenation,
+    "Conv2D" => LayerType::Conv2D,
+    "Conv3D" => LayerType::Conv3D,
+    "Div" => LayerType::DivFixed, // TODO: rename to DivFixed
+    "DivMod" => LayerType::DivMod,
+    "DivVar" => LayerType::DivVar,
+    "Dropout" => LayerType::Dropout,
+    "Exp" => LayerType::Tabulated,
+    "FullyConnected" => LayerType::FullyConnected,
+    "Gather" => LayerType::Gather,
+    "Gelu" => LayerType::Gelu,
+    "GRU" => LayerType::Gru,
+    "LayerNorm" => LayerType::LayerNorm,
+    "Ln" => LayerType::Tabulated,
+    "Logistic" => LayerType::Logistic,
+    "LSTM" => LayerType::Lstm,
+    "MaskNegInf" => LayerType::MaskNegInf,
+    "MaxPool2D" => LayerType::MaxPool2D,
+    "MaxPool3D" => LayerType::MaxPool3D,
+    "Mean" => LayerType::Mean,
+    "MoE" => LayerType::MoE,
+    "Mul" => LayerType::Mul,
+    "Noop" => LayerType::Noop,
+    "Occlude" => LayerType::Occlude,
+    "Pack" => LayerType::Pack,
+    "Pad" => LayerType::Pad,
+    "Pow" => LayerType::Pow,
+    "PiecewiseLinear" => LayerType::Tabulated,
+    "Permute" => LayerType::Permute,
+    "Predicate" => LayerType::Predicate,
+    "Quantile" => LayerType::Quantile,
+    "RangeCheck" => LayerType::RangeCheck,
+    "Reciprocal" => LayerType::Tabulated,
+    "Requantize" => LayerType::Requantize,
+    "Reshape" => LayerType::Reshape,
+    "ResizeNearestNeighbor" => LayerType::ResizeNN,
+    "Robustness" => LayerType::Robustness,
+    "Rotate" => LayerType::Rotate,
+    "Rsqrt" => LayerType::Rsqrt,
+    "ScatterND" => LayerType::ScatterND,
+    "Silu" | "Swish" => LayerType::Silu,
+    "Slice" => LayerType::Slice,
+    "Softmax" => LayerType::Softmax,
+    "Split" => LayerType::Split,
+    "Sqrt" => LayerType::Sqrt,
+    "Square" => LayerType::Square,
+    "SquaredDifference" => LayerType::SquaredDifference,
+    "Sub" => LayerType::Sub,
+    "Tabulated" => LayerType::Tabulated,
+    "Tanh" => LayerType::Tanh,
+    "TopK" => LayerType::TopK,
+    "Transpose" => LayerType::Transpose,
+    "TreeEnsemble" => LayerType::TreeEnsemble,
+    "Update" => LayerType::Update,
+    _ => return None,
+  };
+  Some(layer_type)
+}
+
 lazy_static! {
   pub static ref GADGET_CONFIG: Mutex<GadgetConfig> = Mutex::new(GadgetConfig::default());
   pub static ref PUBLIC_VALS: Mutex<Vec<BigUint>> = Mutex::new(vec![]);
@@ -85,16 +195,24 @@ pub struct ModelCircuit<F: PrimeField> {
   pub tensors: BTreeMap<i64, Array<F, IxDyn>>,
   pub commit_before: Vec<Vec<i64>>,
   pub commit_after: Vec<Vec<i64>>,
+  pub rlc_inputs: Vec<i64>,
+  pub hash_inputs: bool,
   pub k: usize,
   pub bits_per_elem: usize,
   pub inp_idxes: Vec<i64>,
   pub num_random: i64,
+  pub zero_knowledge: bool,
+  pub exit: Option<i64>, // The exit taken, revealed as the first output (see exits.rs)
+  pub random_seed: Option<i64>,
+  pub random_tensors: Vec<(i64, Vec<usize>)>, // Filled from the seed (see randomness.rs)
 }
 
 #[derive(Clone, Debug)]
 pub struct ModelConfig<F: PrimeField + Ord + FromUniformBytes<64>> {
   pub gadget_config: Rc<GadgetConfig>,
-  pub public_col: Column<Instance>,
+  // The commitments and the outputs are in separate instance columns
+  pub commitment_col: Column<Instance>,
+  pub output_col: Column<Instance>,
   pub hasher: Option<PoseidonCommitChip<F, WIDTH, RATE, L>>,
   pub _marker: PhantomData<F>,
 }
@@ -179,21 +297,110 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
     self.tensor_map_to_vec(&tensor_map)
   }
 
+  // The exit index, the predicate thresholds, the requantization multipliers, the divisors and
+  // eps of the layer norms, the positions of the runtime gathers, the integer divisors, the
+  // dropout thresholds and scales, the interpolation weights of the quantiles, the indices of the
+  // top k, the window sizes of the average pools, and the divisor of the activation inputs are
+  // constants so that they are fixed by the circuit
+  fn constant_vals(&self, gadget_config: &GadgetConfig) -> Vec<i64> {
+    let sf = gadget_config.scale_factor as i64;
+    let (min_val, max_val) = (gadget_config.min_val, gadget_config.max_val);
+    let mut vals = vec![0 as i64, 1, sf, min_val, max_val];
+    let predicates = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::Predicate)
+      .filter_map(|op| PredicateChip::constant(&op.layer_params));
+    let requantizations = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::Requantize)
+      .flat_map(|op| RequantizeChip::constants(&op.layer_params));
+    let layer_norms = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::LayerNorm)
+      .flat_map(LayerNormChip::constants);
+    let gathers = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::Gather)
+      .flat_map(GatherChip::constants);
+    let int_divisors = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::DivMod)
+      .flat_map(|op| DivModChip::constants(&op.layer_params));
+    let dropouts = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::Dropout)
+      .flat_map(|op| DropoutChip::constants(&op.layer_params, gadget_config));
+    let quantiles = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::Quantile)
+      .flat_map(|op| {
+        let len = op.inp_shapes[0].iter().product();
+        QuantileChip::constants(&op.layer_params, len, sf)
+      });
+    let pool_sizes = self
+      .dag_config
+      .ops
+      .iter()
+      .filter_map(|op| match op.layer_type {
+        LayerType::AvgPool2D => Some(AvgPool2DChip::constants(&op.layer_params)),
+        LayerType::AvgPool3D => Some(AvgPool3DChip::constants(&op.layer_params)),
+        _ => None,
+      })
+      .flatten();
+    let top_k_indices = self
+      .dag_config
+      .ops
+      .iter()
+      .filter(|op| op.layer_type == LayerType::TopK)
+      .flat_map(|op| TopKChip::constants(op.inp_shapes[0].iter().product()));
+    for val in self
+      .exit
+      .into_iter()
+      .chain(predicates)
+      .chain(requantizations)
+      .chain(layer_norms)
+      .chain(gathers)
+      .chain(int_divisors)
+      .chain(dropouts)
+      .chain(quantiles)
+      .chain(top_k_indices)
+      .chain(pool_sizes)
+      .chain(Some(gadget_config.activation_div).filter(|div| *div > 1))
+    {
+      if !vals.contains(&val) {
+        vals.push(val);
+      }
+    }
+    vals
+  }
+
   pub fn assign_constants(
     &self,
     mut layouter: impl Layouter<F>,
     gadget_config: Rc<GadgetConfig>,
   ) -> Result<HashMap<i64, CellRc<F>>, Error> {
-    let sf = gadget_config.scale_factor;
     let min_val = gadget_config.min_val;
-    let max_val = gadget_config.max_val;
 
     let constants = layouter.assign_region(
       || "constants",
       |mut region| {
         let mut constants: HashMap<i64, CellRc<F>> = HashMap::new();
 
-        let vals = vec![0 as i64, 1, sf as i64, min_val, max_val];
+        let vals = self.constant_vals(&gadget_config);
         let shift_val_i64 = -min_val * 2; // FIXME
         let shift_val_f = F::from(shift_val_i64 as u64);
         for (i, val) in vals.iter().enumerate() {
@@ -236,16 +443,14 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
     gadget_config: Rc<GadgetConfig>,
     fixed_constants: &HashMap<i64, CellRc<F>>,
   ) -> Result<HashMap<i64, CellRc<F>>, Error> {
-    let sf = gadget_config.scale_factor;
     let min_val = gadget_config.min_val;
-    let max_val = gadget_config.max_val;
 
     let constants = layouter.assign_region(
       || "constants",
       |mut region| {
         let mut constants: HashMap<i64, CellRc<F>> = HashMap::new();
 
-        let vals = vec![0 as i64, 1, sf as i64, min_val, max_val];
+        let vals = self.constant_vals(&gadget_config);
         let shift_val_i64 = -min_val * 2; // FIXME
         let shift_val_f = F::from(shift_val_i64 as u64);
         for (i, val) in vals.iter().enumerate() {
@@ -289,74 +494,127 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
     Ok(constants)
   }
 
+  // The public values (the commitments, then the outputs) of the model on the input, by a
+  // synthesis that keeps no cells (see synthesize_public_values): nothing is proven. An input the
+  // layers can't assign, e.g., out of a lookup, is an error.
+  pub fn public_values(config: &ModelMsgpack, inp: &Vec<TensorMsgpack>) -> Result<Vec<F>, String> {
+    let mut config = config.clone();
+    config.tensors.extend(inp.iter().cloned());
+    set_defaults(&mut config);
+    Self::generate_from_msgpack(config, true).compute_public_values()
+  }
+
+  pub fn compute_public_values(&self) -> Result<Vec<F>, String> {
+    synthesize_public_values(self)
+  }
+
+  // The number of public values in the commitment column: the commitments before, then after,
+  // then the rlc values and the input hash (see instance_columns)
+  pub fn num_commitments(&self) -> usize {
+    let mut num_commitments = self.commit_before.len() + self.commit_after.len();
+    if !self.rlc_inputs.is_empty() {
+      num_commitments += NUM_RLC_VALS;
+    }
+    if self.hash_inputs {
+      num_commitments += 1;
+    }
+    num_commitments
+  }
+
   pub fn generate_from_file(config_file: &str, inp_file: &str) -> ModelCircuit<F> {
     let config = load_model_msgpack(config_file, inp_file);
     Self::generate_from_msgpack(config, true)
   }
 
+  // Loads the TFLite model with the converter defaults, see model/tflite.rs
+  pub fn from_tflite(tflite_path: &str, inp_path: &str) -> ModelCircuit<F> {
+    let options = tflite::TfliteOptions::default();
+    let config = tflite::load_tflite(tflite_path, inp_path, &options).unwrap();
+    Self::generate_from_msgpack(config, true)
+  }
+
+  // Imports the ONNX model with the converter defaults, see utils/onnx.rs
+  #[cfg(feature = "onnx")]
+  pub fn from_onnx(onnx_path: &str, inp_path: &str) -> ModelCircuit<F> {
+    let options = crate::utils::onnx::OnnxOptions::default();
+    let config = crate::utils::onnx::load_onnx(onnx_path, inp_path, &options).unwrap();
+    Self::generate_from_msgpack(config, true)
+  }
+
   pub fn generate_from_msgpack(config: ModelMsgpack, panic_empty_tensor: bool) -> ModelCircuit<F> {
+    Self::generate_from_msgpack_with_weights(config, panic_empty_tensor, &BTreeMap::new())
+  }
+
+  // Takes the tensors that are already in the field from `weights` instead of converting them
+  pub fn generate_from_msgpack_with_weights(
+    config: ModelMsgpack,
+    panic_empty_tensor: bool,
+    weights: &BTreeMap<i64, Array<F, IxDyn>>,
+  ) -> ModelCircuit<F> {
+    let mut config = config;
+    apply_column_profile(&mut config).unwrap();
+    apply_frac_bits(&mut config).unwrap();
+    if config.coprocessor_layers.is_some() {
+      let dropped = select_coprocessor_layers(&mut config).unwrap();
+      info!("layers {:?} are proven by the coprocessor", dropped);
+    }
+    if config.branches.is_some() {
+      let dropped = select_branches(&mut config).unwrap();
+      info!("dropped layers {:?} of the untaken branches", dropped);
+    }
+    if config.exit.is_some() {
+      let dropped = select_exit(&mut config).unwrap();
+      info!(
+        "dropped layers {:?} after exit {}",
+        dropped,
+        config.exit.unwrap()
+      );
+    }
+    if config.drop_final_softmax.unwrap_or(false) {
+      let dropped = drop_final_softmax(&mut config).unwrap();
+      info!("dropped the final softmax at layers {:?}", dropped);
+    }
+    if config.output_predicate.is_some() {
+      apply_output_predicate(&mut config).unwrap();
+    }
+    if config.output_top_k.is_some() {
+      apply_output_top_k(&mut config).unwrap();
+    }
+    let random_tensors = apply_random_seed(&mut config).unwrap();
+
     let to_field = |x: i64| {
       let bias = 1 << 31;
       let x_pos = x + bias;
       F::from(x_pos as u64) - F::from(bias as u64)
     };
 
-    let match_layer = |x: &str| match x {
-      "AveragePool2D" => LayerType::AvgPool2D,
-      "Add" => LayerType::Add,
-      "BatchMatMul" => LayerType::BatchMatMul,
-      "Broadcast" => LayerType::Broadcast,
-      "Concatenation" => LayerType::Concatenation,
-      "Conv2D" => LayerType::Conv2D,
-      "Div" => LayerType::DivFixed, // TODO: rename to DivFixed
-      "DivVar" => LayerType::DivVar,
-      "FullyConnected" => LayerType::FullyConnected,
-      "Logistic" => LayerType::Logistic,
-      "MaskNegInf" => LayerType::MaskNegInf,
-      "MaxPool2D" => LayerType::MaxPool2D,
-      "Mean" => LayerType::Mean,
-      "Mul" => LayerType::Mul,
-      "Noop" => LayerType::Noop,
-      "Pack" => LayerType::Pack,
-      "Pad" => LayerType::Pad,
-      "Pow" => LayerType::Pow,
-      "Permute" => LayerType::Permute,
-      "Reshape" => LayerType::Reshape,
-      "ResizeNearestNeighbor" => LayerType::ResizeNN,
-      "Rotate" => LayerType::Rotate,
-      "Rsqrt" => LayerType::Rsqrt,
-      "Slice" => LayerType::Slice,
-      "Softmax" => LayerType::Softmax,
-      "Split" => LayerType::Split,
-      "Sqrt" => LayerType::Sqrt,
-      "Square" => LayerType::Square,
-      "SquaredDifference" => LayerType::SquaredDifference,
-      "Sub" => LayerType::Sub,
-      "Tanh" => LayerType::Tanh,
-      "Transpose" => LayerType::Transpose,
-      "Update" => LayerType::Update,
-      _ => panic!("unknown op: {}", x),
-    };
+    let match_layer =
+      |x: &str| layer_type_from_name(x).unwrap_or_else(|| panic!("unknown op: {}", x));
 
     let mut tensors = BTreeMap::new();
-    for flat in config.tensors {
-      let value_flat = flat.data.iter().map(|x| to_field(*x)).collect::<Vec<_>>();
-      let shape = flat.shape.iter().map(|x| *x as usize).collect::<Vec<_>>();
-      let num_el: usize = shape.iter().product();
-      if panic_empty_tensor && num_el != value_flat.len() {
-        panic!("tensor shape and data length mismatch");
+    for flat in config.tensors.iter() {
+      if let Some(tensor) = weights.get(&flat.idx) {
+        tensors.insert(flat.idx, tensor.clone());
+        continue;
+      }
+      match Tensor::<i64>::try_from(flat) {
+        Ok(tensor) => {
+          tensors.insert(flat.idx, tensor.map(|x| to_field(*x)).into_array());
+        }
+        Err(err) => {
+          if panic_empty_tensor {
+            panic!("{}", err);
+          }
+          // Do nothing here since we're loading the config
+        }
       }
-      if num_el == value_flat.len() {
-        let tensor = Array::from_shape_vec(IxDyn(&shape), value_flat).unwrap();
-        tensors.insert(flat.idx, tensor);
-      } else {
-        // Do nothing here since we're loading the config
-      };
     }
 
     let i64_to_usize = |x: &Vec<i64>| x.iter().map(|x| *x as usize).collect::<Vec<_>>();
 
     let mut used_gadgets = BTreeSet::new();
+    let mut tabulated_fns: Vec<Vec<i64>> = vec![];
+    let mut signed_range_bits: Vec<i64> = vec![];
 
     let dag_config = {
       let ops = config
@@ -366,36 +624,70 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
           let layer_type = match_layer(&layer.layer_type);
           let layer_gadgets = match layer_type {
             LayerType::Add => Box::new(AddChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Attribution => Box::new(AttributionChip {}) as Box<dyn GadgetConsumer>,
             LayerType::AvgPool2D => Box::new(AvgPool2DChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::AvgPool3D => Box::new(AvgPool3DChip {}) as Box<dyn GadgetConsumer>,
             LayerType::BatchMatMul => Box::new(BatchMatMulChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Branch => Box::new(BranchChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Broadcast => Box::new(BroadcastChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Concatenation => Box::new(ConcatenationChip {}) as Box<dyn GadgetConsumer>,
             LayerType::DivFixed => Box::new(ConcatenationChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::DivMod => Box::new(DivModChip {}) as Box<dyn GadgetConsumer>,
             LayerType::DivVar => Box::new(DivVarChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Dropout => Box::new(DropoutChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Conv2D => Box::new(Conv2DChip {
               config: LayerConfig::default(),
               _marker: PhantomData::<F>,
             }) as Box<dyn GadgetConsumer>,
+            LayerType::Conv3D => Box::new(Conv3DChip {
+              config: LayerConfig::default(),
+              _marker: PhantomData::<F>,
+            }) as Box<dyn GadgetConsumer>,
             LayerType::FullyConnected => Box::new(FullyConnectedChip {
               config: FullyConnectedConfig { normalize: true },
               _marker: PhantomData::<F>,
             }) as Box<dyn GadgetConsumer>,
+            LayerType::Gather => Box::new(GatherChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Gelu => Box::new(ActivationChip {
+              activation: Activation::Gelu,
+            }) as Box<dyn GadgetConsumer>,
+            LayerType::Gru => Box::new(RecurrentChip {
+              cell: RecurrentCell::Gru,
+            }) as Box<dyn GadgetConsumer>,
+            LayerType::LayerNorm => Box::new(LayerNormChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Logistic => Box::new(LogisticChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Lstm => Box::new(RecurrentChip {
+              cell: RecurrentCell::Lstm,
+            }) as Box<dyn GadgetConsumer>,
             LayerType::MaskNegInf => Box::new(MaskNegInfChip {}) as Box<dyn GadgetConsumer>,
             LayerType::MaxPool2D => Box::new(MaxPool2DChip {
               marker: PhantomData::<F>,
             }) as Box<dyn GadgetConsumer>,
+            LayerType::MaxPool3D => Box::new(MaxPool3DChip {
+              marker: PhantomData::<F>,
+            }) as Box<dyn GadgetConsumer>,
             LayerType::Mean => Box::new(MeanChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::MoE => Box::new(MoEChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Mul => Box::new(MulChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Noop => Box::new(NoopChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Occlude => Box::new(OccludeChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Pack => Box::new(PackChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Pad => Box::new(PadChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Pow => Box::new(PowChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Permute => Box::new(PermuteChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Predicate => Box::new(PredicateChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Quantile => Box::new(QuantileChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::RangeCheck => Box::new(RangeCheckChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Requantize => Box::new(RequantizeChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Reshape => Box::new(ReshapeChip {}) as Box<dyn GadgetConsumer>,
             LayerType::ResizeNN => Box::new(ResizeNNChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Robustness => Box::new(RobustnessChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Rotate => Box::new(RotateChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Rsqrt => Box::new(RsqrtChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::ScatterND => Box::new(ScatterNDChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Silu => Box::new(ActivationChip {
+              activation: Activation::Silu,
+            }) as Box<dyn GadgetConsumer>,
             LayerType::Slice => Box::new(SliceChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Softmax => Box::new(SoftmaxChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Split => Box::new(SplitChip {}) as Box<dyn GadgetConsumer>,
@@ -403,8 +695,11 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
             LayerType::Square => Box::new(SquareChip {}) as Box<dyn GadgetConsumer>,
             LayerType::SquaredDifference => Box::new(SquaredDiffChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Sub => Box::new(SubChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::Tabulated => Box::new(TabulatedChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Tanh => Box::new(TanhChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::TopK => Box::new(TopKChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Transpose => Box::new(TransposeChip {}) as Box<dyn GadgetConsumer>,
+            LayerType::TreeEnsemble => Box::new(TreeEnsembleChip {}) as Box<dyn GadgetConsumer>,
             LayerType::Update => Box::new(UpdateChip {}) as Box<dyn GadgetConsumer>,
           }
           .used_gadgets(layer.params.clone());
@@ -412,9 +707,45 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
             used_gadgets.insert(gadget);
           }
 
+          // The top k softmax gathers the largest inputs before the exp
+          let has_softmax = layer_type == LayerType::Softmax || layer_type == LayerType::MoE;
+          if has_softmax && config.softmax_top_k.is_some() {
+            used_gadgets.extend(SoftmaxChip::top_k_gadgets());
+          }
+
+          // The tables are moved into the gadget config, identical tables are shared
+          if layer_type == LayerType::RangeCheck && !signed_range_bits.contains(&layer.params[0]) {
+            signed_range_bits.push(layer.params[0]);
+          }
+
+          // Piecewise linear and unary functions are compiled to tables over their knots and
+          // domains. The loader checked the knots.
+          let layer_params = if layer_type == LayerType::Tabulated {
+            let sf = config.global_sf as u64;
+            let table = if layer.layer_type == "PiecewiseLinear" {
+              PiecewiseLinear::from_params(&layer.params, sf)
+                .unwrap_or_else(|e| panic!("layer {}: {}", layer.layer_type, e))
+                .to_table(sf)
+            } else if let Some(f) = UnaryFunction::from_name(&layer.layer_type) {
+              f.to_table(&layer.params, sf, config.k as usize)
+            } else {
+              layer.params.clone()
+            };
+            let table_idx = match tabulated_fns.iter().position(|x| *x == table) {
+              Some(idx) => idx,
+              None => {
+                tabulated_fns.push(table);
+                tabulated_fns.len() - 1
+              }
+            };
+            vec![table_idx as i64]
+          } else {
+            layer.params.clone()
+          };
+
           LayerConfig {
             layer_type,
-            layer_params: layer.params.clone(),
+            layer_params,
             inp_shapes: layer.inp_shapes.iter().map(|x| i64_to_usize(x)).collect(),
             out_shapes: layer.out_shapes.iter().map(|x| i64_to_usize(x)).collect(),
             mask: layer.mask.clone(),
@@ -446,12 +777,32 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
 
     // The input lookup is always used
     used_gadgets.insert(GadgetType::InputLookup);
+    // The combination of the rlc inputs multiplies out the powers of the challenge
+    let rlc_inputs = config.rlc_inputs.clone().unwrap_or(vec![]);
+    for idx in rlc_inputs.iter() {
+      assert!(
+        config.inp_idxes.contains(idx),
+        "rlc input {} is not an input",
+        idx
+      );
+    }
+    if !rlc_inputs.is_empty() {
+      used_gadgets.extend([
+        GadgetType::MulPairs,
+        GadgetType::DotProduct,
+        GadgetType::Adder,
+      ]);
+    }
+    // The uniforms are split from the hashes of the seed
+    if config.random_seed.is_some() {
+      used_gadgets.extend([GadgetType::PrfLimbs, GadgetType::SubPairs]);
+    }
     let used_gadgets = Arc::new(used_gadgets);
     let gadget = &GADGET_CONFIG;
     let cloned_gadget = gadget.lock().unwrap().clone();
     *gadget.lock().unwrap() = GadgetConfig {
       scale_factor: config.global_sf as u64,
-      shift_min_val: -(config.global_sf * config.global_sf * (1 << 17)),
+      shift_min_val: default_shift_min_val(config.global_sf),
       div_outp_min_val: -(1 << (config.k - 1)),
       min_val: -(1 << (config.k - 1)),
       max_val: (1 << (config.k - 1)) - 10,
@@ -461,8 +812,17 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
       used_gadgets: used_gadgets.clone(),
       commit_before: config.commit_before.clone().unwrap_or(vec![]),
       commit_after: config.commit_after.clone().unwrap_or(vec![]),
+      rlc_inputs: rlc_inputs.clone(),
+      hash_inputs: config.hash_inputs.unwrap_or(false),
+      random_seed: config.random_seed.is_some(),
       use_selectors: config.use_selectors.unwrap_or(true),
       num_bits_per_elem: config.bits_per_elem.unwrap_or(config.k),
+      tabulated_fns,
+      signed_range_bits,
+      range_check_limb_bits: config.range_check_limb_bits.unwrap_or(0),
+      softmax_top_k: config.softmax_top_k.unwrap_or(0) as usize,
+      gemm_tile_size: config.gemm_tile_size.unwrap_or(0) as usize,
+      activation_div: activation_div(config.global_sf, config.activation_table_bits).unwrap(),
       ..cloned_gadget
     };
 
@@ -475,7 +835,16 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
       inp_idxes: config.inp_idxes.clone(),
       commit_after: config.commit_after.unwrap_or(vec![]),
       commit_before: config.commit_before.unwrap_or(vec![]),
+      rlc_inputs,
+      hash_inputs: config.hash_inputs.unwrap_or(false),
       num_random: config.num_random.unwrap_or(0),
+      zero_knowledge: config.zero_knowledge.unwrap_or(true),
+      exit: config.exit,
+      random_seed: config.random_seed,
+      random_tensors: random_tensors
+        .into_iter()
+        .map(|(idx, shape)| (idx, i64_to_usize(&shape)))
+        .collect(),
     }
   }
 
@@ -554,6 +923,61 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> ModelCircuit<F> {
 
     commitments[0].clone()
   }
+
+  // The random linear combination sum_i x_i * c^i of the tensors, in order of the tensor index
+  pub fn rlc(
+    &self,
+    mut layouter: impl Layouter<F>,
+    constants: &HashMap<i64, CellRc<F>>,
+    config: &ModelConfig<F>,
+    tensors: &BTreeMap<i64, AssignedTensor<F>>,
+    challenge: &CellRc<F>,
+  ) -> CellRc<F> {
+    let zero = constants.get(&0).unwrap().as_ref();
+    let one = constants.get(&1).unwrap().as_ref();
+    let values = tensors
+      .values()
+      .flat_map(|tensor| tensor.iter())
+      .map(|cell| cell.as_ref())
+      .collect::<Vec<_>>();
+
+    // Every multiplication doubles the number of powers
+    let mul_pairs_chip = MulPairsChip::<F>::construct(config.gadget_config.clone());
+    let mut powers = vec![one.clone()];
+    let mut cur = challenge.as_ref().clone();
+    while powers.len() < values.len() {
+      // cur is c^powers.len()
+      if powers.len() > 1 {
+        let squared = mul_pairs_chip
+          .forward(
+            layouter.namespace(|| "rlc square"),
+            &vec![vec![&cur], vec![&cur]],
+            &vec![zero],
+          )
+          .unwrap();
+        cur = squared[0].clone();
+      }
+      let shifted = mul_pairs_chip
+        .forward(
+          layouter.namespace(|| "rlc powers"),
+          &vec![powers.iter().collect(), vec![&cur; powers.len()]],
+          &vec![zero],
+        )
+        .unwrap();
+      powers.extend(shifted);
+    }
+    powers.truncate(values.len());
+
+    let dot_prod_chip = DotProductChip::<F>::construct(config.gadget_config.clone());
+    let rlc = dot_prod_chip
+      .forward(
+        layouter.namespace(|| "rlc"),
+        &vec![values, powers.iter().collect()],
+        &vec![zero],
+      )
+      .unwrap();
+    Rc::new(rlc[0].clone())
+  }
 }
 
 impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F> {
@@ -575,8 +999,10 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
     }
     gadget_config.columns = columns;
 
-    let public_col = meta.instance_column();
-    meta.enable_equality(public_col);
+    let commitment_col = meta.instance_column();
+    meta.enable_equality(commitment_col);
+    let output_col = meta.instance_column();
+    meta.enable_equality(output_col);
 
     gadget_config.fixed_columns = vec![meta.fixed_column()];
     meta.enable_equality(gadget_config.fixed_columns[0]);
@@ -590,20 +1016,41 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
         GadgetType::AddPairs => AddPairsChip::<F>::configure(meta, gadget_config),
         GadgetType::Adder => AdderChip::<F>::configure(meta, gadget_config),
         GadgetType::BiasDivRoundRelu6 => BiasDivRoundRelu6Chip::<F>::configure(meta, gadget_config),
+        GadgetType::BiasDivRoundRelu => {
+          BiasDivRoundRelu6Chip::<F>::configure_relu(meta, gadget_config)
+        }
         GadgetType::BiasDivFloorRelu6 => panic!(),
+        GadgetType::Comparator => ComparatorChip::<F>::configure(meta, gadget_config),
         GadgetType::DotProduct => DotProductChip::<F>::configure(meta, gadget_config),
         GadgetType::Exp => ExpGadgetChip::<F>::configure(meta, gadget_config),
+        GadgetType::Gelu => GeluGadgetChip::<F>::configure(meta, gadget_config),
+        GadgetType::IntDivFloor => {
+          IntDivChip::<F>::configure(meta, gadget_config, IntDivMode::Floor)
+        }
+        GadgetType::IntDivRoundHalfEven => {
+          IntDivChip::<F>::configure(meta, gadget_config, IntDivMode::RoundHalfEven)
+        }
+        GadgetType::IntDivTrunc => {
+          IntDivChip::<F>::configure(meta, gadget_config, IntDivMode::Trunc)
+        }
+        GadgetType::IntDivRoundHalfAway => {
+          IntDivChip::<F>::configure(meta, gadget_config, IntDivMode::RoundHalfAway)
+        }
         GadgetType::Logistic => LogisticGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::Max => MaxChip::<F>::configure(meta, gadget_config),
         GadgetType::MulPairs => MulPairsChip::<F>::configure(meta, gadget_config),
         GadgetType::Pow => PowGadgetChip::<F>::configure(meta, gadget_config),
+        GadgetType::PrfLimbs => PrfLimbsChip::<F>::configure(meta, gadget_config),
         GadgetType::Relu => ReluChip::<F>::configure(meta, gadget_config),
         GadgetType::Rsqrt => RsqrtGadgetChip::<F>::configure(meta, gadget_config),
+        GadgetType::SignedRangeCheck => SignedRangeCheckChip::<F>::configure(meta, gadget_config),
+        GadgetType::Silu => SiluGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::Sqrt => SqrtGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::SqrtBig => SqrtBigChip::<F>::configure(meta, gadget_config),
         GadgetType::Square => SquareGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::SquaredDiff => SquaredDiffGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::SubPairs => SubPairsChip::<F>::configure(meta, gadget_config),
+        GadgetType::Tabulated => TabulatedGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::Tanh => TanhGadgetChip::<F>::configure(meta, gadget_config),
         GadgetType::VarDivRound => VarDivRoundChip::<F>::configure(meta, gadget_config),
         GadgetType::VarDivRoundBig => VarDivRoundBigChip::<F>::configure(meta, gadget_config),
@@ -614,7 +1061,12 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
       };
     }
 
-    let hasher = if gadget_config.commit_before.len() + gadget_config.commit_after.len() > 0 {
+    let needs_hasher = gadget_config.commit_before.len() > 0
+      || gadget_config.commit_after.len() > 0
+      || gadget_config.rlc_inputs.len() > 0
+      || gadget_config.hash_inputs
+      || gadget_config.random_seed;
+    let hasher = if needs_hasher {
       let packer_config =
         PackerChip::<F>::construct(gadget_config.num_bits_per_elem as usize, &gadget_config);
       gadget_config = PackerChip::<F>::configure(meta, packer_config, gadget_config);
@@ -635,7 +1087,8 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
 
     ModelConfig {
       gadget_config: gadget_config.into(),
-      public_col,
+      commitment_col,
+      output_col,
       hasher,
       _marker: PhantomData,
     }
@@ -658,6 +1111,10 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
           let chip = BiasDivRoundRelu6Chip::<F>::construct(gadget_rc.clone());
           chip.load_lookups(layouter.namespace(|| "bias div round relu6 lookup"))?;
         }
+        GadgetType::BiasDivRoundRelu => {
+          let chip = BiasDivRoundRelu6Chip::<F>::construct_relu(gadget_rc.clone());
+          chip.load_lookups(layouter.namespace(|| "bias div round relu lookup"))?;
+        }
         GadgetType::DotProduct => {
           let chip = DotProductChip::<F>::construct(gadget_rc.clone());
           chip.load_lookups(layouter.namespace(|| "dot product lookup"))?;
@@ -694,6 +1151,18 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
           let chip = LogisticGadgetChip::<F>::construct(gadget_rc.clone());
           chip.load_lookups(layouter.namespace(|| "logistic lookup"))?;
         }
+        GadgetType::Gelu => {
+          let chip = GeluGadgetChip::<F>::construct(gadget_rc.clone());
+          chip.load_lookups(layouter.namespace(|| "gelu lookup"))?;
+        }
+        GadgetType::Silu => {
+          let chip = SiluGadgetChip::<F>::construct(gadget_rc.clone());
+          chip.load_lookups(layouter.namespace(|| "silu lookup"))?;
+        }
+        GadgetType::Tabulated => {
+          let chip = TabulatedGadgetChip::<F>::construct(gadget_rc.clone(), 0);
+          chip.load_lookups(layouter.namespace(|| "tabulated lookup"))?;
+        }
         GadgetType::InputLookup => {
           let chip = InputLookupChip::<F>::construct(gadget_rc.clone());
           chip.load_lookups(layouter.namespace(|| "input lookup"))?;
@@ -701,8 +1170,15 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
         GadgetType::VarDivRoundBig => {}
         GadgetType::VarDivRoundBig3 => {}
         GadgetType::Max => {}
+        GadgetType::Comparator => {}
+        GadgetType::IntDivFloor => {}
+        GadgetType::IntDivRoundHalfEven => {}
+        GadgetType::IntDivTrunc => {}
+        GadgetType::IntDivRoundHalfAway => {}
         GadgetType::MulPairs => {}
+        GadgetType::PrfLimbs => {}
         GadgetType::SqrtBig => {}
+        GadgetType::SignedRangeCheck => {}
         GadgetType::Square => {}
         GadgetType::SquaredDiff => {}
         GadgetType::SubPairs => {}
@@ -728,7 +1204,8 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
       .unwrap();
 
     let mut commitments = vec![];
-    let tensors = if self.commit_before.len() > 0 {
+    let mut rlc_vals = vec![];
+    let tensors = if self.commit_before.len() > 0 || self.rlc_inputs.len() > 0 {
       // Commit to the tensors before the DAG
       let mut tensor_map = BTreeMap::new();
       let mut ignore_idxes: Vec<i64> = vec![];
@@ -749,6 +1226,32 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
         ignore_idxes.extend(commit_idxes.iter());
       }
 
+      // The rlc inputs are committed, and the commitment is the challenge of the combination
+      if self.rlc_inputs.len() > 0 {
+        let to_commit = BTreeMap::from_iter(
+          self
+            .rlc_inputs
+            .iter()
+            .map(|idx| (*idx, self.tensors.get(idx).unwrap().clone())),
+        );
+        let (mut rlc_tensors, challenge) = self.assign_and_commit(
+          layouter.namespace(|| "rlc commit"),
+          &constants,
+          &config,
+          &to_commit,
+        );
+        let rlc = self.rlc(
+          layouter.namespace(|| "rlc"),
+          &constants,
+          &config,
+          &rlc_tensors,
+          &challenge,
+        );
+        rlc_vals = vec![challenge, rlc];
+        tensor_map.append(&mut rlc_tensors);
+        ignore_idxes.extend(self.rlc_inputs.iter());
+      }
+
       // Assign the remainder of the tensors
       let mut assign_map = BTreeMap::new();
       for (idx, tensor) in self.tensors.iter() {
@@ -780,6 +1283,39 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
         .unwrap()
     };
 
+    // The uniforms of the stochastic layers, from the seed
+    let mut tensors = tensors;
+    if let Some(seed) = self.random_seed {
+      let seed = &tensors[seed as usize];
+      assert_eq!(seed.len(), 1, "the random seed has more than one element");
+      let seed = seed.iter().next().unwrap().clone();
+      let num = self
+        .random_tensors
+        .iter()
+        .map(|(_, shape)| shape.iter().product::<usize>())
+        .sum::<usize>();
+      let uniforms = assign_prf_uniforms(
+        layouter.namespace(|| "prf"),
+        &config.hasher.as_ref().unwrap().poseidon_config,
+        config.gadget_config.clone(),
+        config.gadget_config.columns[0],
+        seed.as_ref(),
+        constants.get(&0).unwrap().as_ref(),
+        num,
+      )?;
+      let mut uniforms = uniforms.into_iter().map(Rc::new);
+      for (idx, shape) in self.random_tensors.iter() {
+        let len = shape.iter().product::<usize>();
+        let cells = uniforms.by_ref().take(len).collect::<Vec<_>>();
+        let tensor = Array::from_shape_vec(IxDyn(shape), cells).unwrap();
+        let idx = *idx as usize;
+        while tensors.len() <= idx {
+          tensors.push(tensor.clone());
+        }
+        tensors[idx] = tensor;
+      }
+    }
+
     // Perform the dag
     let dag_chip = DAGLayerChip::<F>::construct(self.dag_config.clone());
     let (final_tensor_map, result) = dag_chip.forward(
@@ -807,26 +1343,49 @@ impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for ModelCircuit<F>
         commitments.push(commitment);
       }
     }
+    commitments.extend(rlc_vals);
+    if self.hash_inputs {
+      let mut inp_idxes = self.inp_idxes.clone();
+      inp_idxes.sort();
+      let inputs = inp_idxes
+        .iter()
+        .map(|idx| (*idx, &tensors[*idx as usize]))
+        .collect::<Vec<_>>();
+      let hash = hash_assigned_tensors(
+        layouter.namespace(|| "input hash"),
+        &config.hasher.as_ref().unwrap().poseidon_config,
+        config.gadget_config.columns[0],
+        &inputs,
+      )?;
+      commitments.push(hash);
+    }
 
     let mut pub_layouter = layouter.namespace(|| "public");
-    let mut total_idx = 0;
     let mut new_public_vals = vec![];
-    for cell in commitments.iter() {
+    for (idx, cell) in commitments.iter().enumerate() {
       pub_layouter
-        .constrain_instance(cell.as_ref().cell(), config.public_col, total_idx)
+        .constrain_instance(cell.as_ref().cell(), config.commitment_col, idx)
         .unwrap();
       let val = convert_to_bigint(cell.value().map(|x| x.to_owned()));
       new_public_vals.push(val);
-      total_idx += 1;
+    }
+    let mut output_idx = 0;
+    if let Some(exit) = self.exit {
+      let cell = constants.get(&exit).unwrap();
+      pub_layouter
+        .constrain_instance(cell.as_ref().cell(), config.output_col, output_idx)
+        .unwrap();
+      new_public_vals.push(convert_to_bigint(cell.value().map(|x| x.to_owned())));
+      output_idx += 1;
     }
     for tensor in result {
       for cell in tensor.iter() {
         pub_layouter
-          .constrain_instance(cell.as_ref().cell(), config.public_col, total_idx)
+          .constrain_instance(cell.as_ref().cell(), config.output_col, output_idx)
           .unwrap();
         let val = convert_to_bigint(cell.value().map(|x| x.to_owned()));
         new_public_vals.push(val);
-        total_idx += 1;
+        output_idx += 1;
       }
     }
     *PUBLIC_VALS.lock().unwrap() = new_public_vals;
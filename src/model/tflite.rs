@@ -0,0 +1,816 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::{
+  loader::{set_defaults, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  storage::read_artifact,
+};
+
+// Loads TFLite models without the Python converter. The flatbuffer is read by hand, since only
+// the tensors, buffers, operators, and a few builtin options of the first subgraph are needed.
+// The layers follow the TFLite layouts, so the ops map one to one onto the layers, with the
+// params of python/converter.py, and the tensor indices are the TFLite indices.
+// Every tensor is moved to the global scale factor: floats to round(x * sf), and quantized
+// tensors to round(scale * (q - zero_point) * sf), per channel if the tensor is quantized per
+// channel. Int8 weights and int32 biases thus keep their values, and the QUANTIZE and DEQUANTIZE
+// ops become no-ops, since the circuit computes every activation at the global scale.
+//
+// The parity mode instead computes the integer kernels of TFLite, so that the outputs are the
+// outputs of the TFLite interpreter, bit for bit. The scale factor is 1 and every quantized tensor
+// holds q - zero_point, which is what the kernels compute with. Convolutions and fully connected
+// layers accumulate on these, and a Requantize layer applies the multiplier and the shift that
+// TFLite derives from the scales, with its rounding, then clamps to the fused activation range.
+// Only the ops whose integer kernels are exact on these values are supported: the convolutions,
+// fully connected layers, max pooling, and the shape ops between tensors of the same
+// quantization. The model must have int8 inputs and outputs, the inputs are the quantized values,
+// and the outputs are q - zero_point. The accumulators go through the lookup of the convolutions,
+// so k must cover them.
+
+#[derive(Clone, Debug)]
+pub struct TfliteOptions {
+  pub scale_factor: i64,
+  pub k: i64,
+  pub num_cols: i64,
+  pub parity: bool,
+}
+
+// The defaults of the converter
+impl Default for TfliteOptions {
+  fn default() -> Self {
+    Self {
+      scale_factor: 1 << 16,
+      k: 19,
+      num_cols: 6,
+      parity: false,
+    }
+  }
+}
+
+// The TFLite fixed point arithmetic, as in kernels/internal/quantization_util.cc and common.h
+
+// The (multiplier, shift) of a real multiplier, with multiplier in [2^30, 2^31)
+pub fn quantize_multiplier(real: f64) -> (i64, i64) {
+  if real == 0. {
+    return (0, 0);
+  }
+  // frexp, the powers of two are exact
+  let (mut q, mut shift) = (real, 0);
+  while q >= 1. {
+    q /= 2.;
+    shift += 1;
+  }
+  while q < 0.5 {
+    q *= 2.;
+    shift -= 1;
+  }
+  let mut multiplier = (q * (1i64 << 31) as f64).round() as i64;
+  if multiplier == 1 << 31 {
+    multiplier /= 2;
+    shift += 1;
+  }
+  if shift < -31 {
+    return (0, 0);
+  }
+  (multiplier, shift)
+}
+
+// MultiplyByQuantizedMultiplier: SaturatingRoundingDoublingHighMul, then RoundingDivideByPOT
+pub fn multiply_by_quantized_multiplier(x: i64, multiplier: i64, shift: i64) -> i64 {
+  let (left, right) = (shift.max(0), (-shift).max(0));
+  let ab = ((x << left) as i128) * multiplier as i128;
+  let nudge = if ab >= 0 { 1 << 30 } else { 1 - (1 << 30) };
+  // Division truncates as in C++
+  let high = ((ab + nudge) / (1 << 31)) as i64;
+  let mask = (1 << right) - 1;
+  let threshold = (mask >> 1) + (high < 0) as i64;
+  (high >> right) + ((high & mask) > threshold) as i64
+}
+
+// Flatbuffers
+
+fn read_bytes<const N: usize>(buf: &[u8], pos: usize) -> Result<[u8; N], String> {
+  buf
+    .get(pos..pos + N)
+    .map(|bytes| bytes.try_into().unwrap())
+    .ok_or_else(|| "truncated flatbuffer".to_string())
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<usize, String> {
+  Ok(u32::from_le_bytes(read_bytes(buf, pos)?) as usize)
+}
+
+// Follows the offset at pos to the object it points to
+fn deref(buf: &[u8], pos: usize) -> Result<usize, String> {
+  Ok(pos + read_u32(buf, pos)?)
+}
+
+#[derive(Clone, Copy)]
+struct Table<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Table<'a> {
+  fn root(buf: &'a [u8]) -> Result<Self, String> {
+    Ok(Table {
+      buf,
+      pos: deref(buf, 0)?,
+    })
+  }
+
+  // The position of the field, or None if it is not set
+  fn field(&self, field: usize) -> Result<Option<usize>, String> {
+    let soffset = i32::from_le_bytes(read_bytes(self.buf, self.pos)?) as i64;
+    let vtable = (self.pos as i64 - soffset) as usize;
+    let vtable_size = u16::from_le_bytes(read_bytes(self.buf, vtable)?) as usize;
+    let entry = 4 + 2 * field;
+    if entry + 2 > vtable_size {
+      return Ok(None);
+    }
+    let offset = u16::from_le_bytes(read_bytes(self.buf, vtable + entry)?) as usize;
+    Ok(if offset == 0 {
+      None
+    } else {
+      Some(self.pos + offset)
+    })
+  }
+
+  fn u8(&self, field: usize, default: u8) -> Result<u8, String> {
+    match self.field(field)? {
+      Some(pos) => Ok(read_bytes::<1>(self.buf, pos)?[0]),
+      None => Ok(default),
+    }
+  }
+
+  fn i32(&self, field: usize, default: i32) -> Result<i32, String> {
+    match self.field(field)? {
+      Some(pos) => Ok(i32::from_le_bytes(read_bytes(self.buf, pos)?)),
+      None => Ok(default),
+    }
+  }
+
+  fn table(&self, field: usize) -> Result<Option<Table<'a>>, String> {
+    match self.field(field)? {
+      Some(pos) => Ok(Some(Table {
+        buf: self.buf,
+        pos: deref(self.buf, pos)?,
+      })),
+      None => Ok(None),
+    }
+  }
+
+  // The start and the length of a vector field, empty if it is not set
+  fn vector(&self, field: usize) -> Result<(usize, usize), String> {
+    match self.field(field)? {
+      Some(pos) => {
+        let start = deref(self.buf, pos)?;
+        Ok((start + 4, read_u32(self.buf, start)?))
+      }
+      None => Ok((0, 0)),
+    }
+  }
+
+  fn tables(&self, field: usize) -> Result<Vec<Table<'a>>, String> {
+    let (start, len) = self.vector(field)?;
+    (0..len)
+      .map(|i| {
+        Ok(Table {
+          buf: self.buf,
+          pos: deref(self.buf, start + 4 * i)?,
+        })
+      })
+      .collect()
+  }
+
+  fn bytes(&self, field: usize) -> Result<&'a [u8], String> {
+    let (start, len) = self.vector(field)?;
+    self
+      .buf
+      .get(start..start + len)
+      .ok_or_else(|| "truncated flatbuffer".to_string())
+  }
+
+  fn i32s(&self, field: usize) -> Result<Vec<i32>, String> {
+    Ok(
+      self
+        .elems::<4>(field)?
+        .into_iter()
+        .map(i32::from_le_bytes)
+        .collect(),
+    )
+  }
+
+  fn f32s(&self, field: usize) -> Result<Vec<f32>, String> {
+    Ok(
+      self
+        .elems::<4>(field)?
+        .into_iter()
+        .map(f32::from_le_bytes)
+        .collect(),
+    )
+  }
+
+  fn i64s(&self, field: usize) -> Result<Vec<i64>, String> {
+    Ok(
+      self
+        .elems::<8>(field)?
+        .into_iter()
+        .map(i64::from_le_bytes)
+        .collect(),
+    )
+  }
+
+  fn elems<const N: usize>(&self, field: usize) -> Result<Vec<[u8; N]>, String> {
+    let (start, len) = self.vector(field)?;
+    (0..len)
+      .map(|i| read_bytes(self.buf, start + N * i))
+      .collect()
+  }
+
+  fn string(&self, field: usize) -> Result<String, String> {
+    Ok(String::from_utf8_lossy(self.bytes(field)?).to_string())
+  }
+}
+
+// TFLite schema
+
+const FLOAT32: u8 = 0;
+const INT32: u8 = 2;
+const UINT8: u8 = 3;
+const INT64: u8 = 4;
+const INT16: u8 = 7;
+const INT8: u8 = 9;
+
+const ADD: i32 = 0;
+const AVERAGE_POOL_2D: i32 = 1;
+const CONCATENATION: i32 = 2;
+const CONV_2D: i32 = 3;
+const DEPTHWISE_CONV_2D: i32 = 4;
+const DEQUANTIZE: i32 = 6;
+const FULLY_CONNECTED: i32 = 9;
+const LOGISTIC: i32 = 14;
+const MAX_POOL_2D: i32 = 17;
+const MUL: i32 = 18;
+const RELU: i32 = 19;
+const RELU6: i32 = 21;
+const RESHAPE: i32 = 22;
+const SOFTMAX: i32 = 25;
+const TANH: i32 = 28;
+const PAD: i32 = 34;
+const TRANSPOSE: i32 = 39;
+const MEAN: i32 = 40;
+const SUB: i32 = 41;
+const SQUEEZE: i32 = 43;
+const EXP: i32 = 47;
+const LOG: i32 = 73;
+const SQRT: i32 = 75;
+const RSQRT: i32 = 76;
+const SQUARE: i32 = 92;
+const SQUARED_DIFFERENCE: i32 = 99;
+const QUANTIZE: i32 = 114;
+const BATCH_MATMUL: i32 = 126;
+const GELU: i32 = 150;
+
+// NONE, RELU, and RELU6 are fused into the layers
+fn check_activation(activation: u8) -> Result<i64, String> {
+  match activation {
+    0 | 1 | 3 => Ok(activation as i64),
+    _ => Err(format!("unsupported fused activation {}", activation)),
+  }
+}
+
+#[derive(Clone, Debug)]
+struct TfliteTensor {
+  shape: Vec<i64>,
+  dtype: u8,
+  data: Option<Vec<f64>>, // The dequantized values of the constants
+  quantized: bool,
+  // q - zero_point of the quantized constants, for the parity mode
+  centered: Option<Vec<f64>>,
+  // The quantization, per channel if there are several scales
+  scales: Vec<f32>,
+  zero_points: Vec<i64>,
+}
+
+fn parse_tensor(tensor: &Table, buffers: &[Table]) -> Result<TfliteTensor, String> {
+  let name = tensor.string(3)?;
+  let shape: Vec<i64> = tensor.i32s(0)?.iter().map(|x| *x as i64).collect();
+  let dtype = tensor.u8(1, FLOAT32)?;
+  let buffer = tensor.i32(2, 0)? as usize;
+  let scales = match tensor.table(4)? {
+    Some(quant) => (quant.f32s(2)?, quant.i64s(3)?, quant.i32(6, 0)? as usize),
+    None => (vec![], vec![], 0),
+  };
+  let raw = match buffers.get(buffer) {
+    Some(buffer) => buffer.bytes(0)?,
+    None => &[],
+  };
+  if raw.is_empty() {
+    return Ok(TfliteTensor {
+      shape,
+      dtype,
+      data: None,
+      quantized: false,
+      centered: None,
+      scales: scales.0,
+      zero_points: scales.1,
+    });
+  }
+
+  let values: Vec<f64> = match dtype {
+    FLOAT32 => raw
+      .chunks_exact(4)
+      .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+      .collect(),
+    INT32 => raw
+      .chunks_exact(4)
+      .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f64)
+      .collect(),
+    INT64 => raw
+      .chunks_exact(8)
+      .map(|b| i64::from_le_bytes(b.try_into().unwrap()) as f64)
+      .collect(),
+    INT16 => raw
+      .chunks_exact(2)
+      .map(|b| i16::from_le_bytes(b.try_into().unwrap()) as f64)
+      .collect(),
+    INT8 => raw.iter().map(|b| *b as i8 as f64).collect(),
+    UINT8 => raw.iter().map(|b| *b as f64).collect(),
+    t => return Err(format!("tensor {} has unsupported type {}", name, t)),
+  };
+
+  // Dequantizes, per channel along the quantized dimension if there are several scales
+  let (scales, zero_points, axis) = scales;
+  let quantized = !scales.is_empty();
+  let (data, centered) = if quantized {
+    let stride = shape
+      .get(axis + 1..)
+      .map_or(1, |s| s.iter().product::<i64>()) as usize;
+    let channel = |i: usize| {
+      if scales.len() == 1 {
+        0
+      } else {
+        (i / stride) % scales.len()
+      }
+    };
+    let centered = values
+      .iter()
+      .enumerate()
+      .map(|(i, q)| q - zero_points.get(channel(i)).cloned().unwrap_or(0) as f64)
+      .collect::<Vec<_>>();
+    let data = centered
+      .iter()
+      .enumerate()
+      .map(|(i, q)| scales[channel(i)] as f64 * q)
+      .collect();
+    (data, Some(centered))
+  } else {
+    (values, None)
+  };
+  Ok(TfliteTensor {
+    shape,
+    dtype,
+    data: Some(data),
+    quantized,
+    centered,
+    scales,
+    zero_points,
+  })
+}
+
+struct Loader {
+  options: TfliteOptions,
+  tensors: Vec<TfliteTensor>,
+  // The output of every QUANTIZE and DEQUANTIZE is its input
+  aliases: HashMap<i64, i64>,
+  layers: Vec<LayerMsgpack>,
+  // The next index of the tensors the loader adds, e.g., the accumulators of the parity mode
+  next_idx: i64,
+}
+
+impl Loader {
+  fn resolve(&self, idx: i64) -> i64 {
+    let mut idx = idx;
+    while let Some(src) = self.aliases.get(&idx) {
+      idx = *src;
+    }
+    idx
+  }
+
+  fn shape(&self, idx: i64) -> Vec<i64> {
+    let shape = self.tensors[idx as usize].shape.clone();
+    if shape.is_empty() {
+      vec![1]
+    } else {
+      shape
+    }
+  }
+
+  // The raw values of a constant, e.g., axes or paddings
+  fn constant(&self, idx: i64) -> Result<Vec<i64>, String> {
+    self.tensors[idx as usize]
+      .data
+      .as_ref()
+      .map(|data| data.iter().map(|x| *x as i64).collect())
+      .ok_or_else(|| format!("tensor {} must be a constant", idx))
+  }
+
+  // The shape ops of the parity mode copy q - zero_point, so the quantization must not change
+  fn check_same_quantization(&self, inputs: &[i64], output: i64) -> Result<(), String> {
+    let out = &self.tensors[output as usize];
+    for idx in inputs.iter() {
+      let inp = &self.tensors[*idx as usize];
+      if !inp.quantized || inp.scales != out.scales || inp.zero_points != out.zero_points {
+        return Err(format!(
+          "tensors {} and {} are quantized differently",
+          idx, output
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  // CalculateActivationRangeQuantized, minus the zero point
+  fn activation_range(&self, activation: i64, idx: i64) -> Result<(i64, i64), String> {
+    let tensor = &self.tensors[idx as usize];
+    let (q_min, q_max) = match tensor.dtype {
+      INT8 => (-128, 127),
+      UINT8 => (0, 255),
+      INT16 => (-32768, 32767),
+      t => return Err(format!("tensor {} has unsupported type {}", idx, t)),
+    };
+    let zero_point = tensor.zero_points.get(0).cloned().unwrap_or(0);
+    let scale = tensor.scales[0];
+    let quantize = |x: f32| zero_point + (x / scale).round() as i64;
+    let (lo, hi) = match activation {
+      0 => (q_min, q_max),
+      1 => (q_min.max(quantize(0.)), q_max),
+      _ => (q_min.max(quantize(0.)), q_max.min(quantize(6.))),
+    };
+    Ok((lo - zero_point, hi - zero_point))
+  }
+
+  // The Requantize params of a convolution or a fully connected layer, with the multipliers of
+  // input scale * filter scale / output scale, computed in doubles as TFLite does
+  fn requantize_params(
+    &self,
+    inp: i64,
+    filter: i64,
+    outp: i64,
+    activation: i64,
+  ) -> Result<Vec<i64>, String> {
+    let scale = |idx: i64| {
+      let tensor = &self.tensors[idx as usize];
+      match tensor.scales.as_slice() {
+        [scale] if tensor.quantized => Ok(*scale as f64),
+        _ => Err(format!("tensor {} must be quantized per tensor", idx)),
+      }
+    };
+    let (inp_scale, outp_scale) = (scale(inp)?, scale(outp)?);
+    let filter_scales = &self.tensors[filter as usize].scales;
+    let num_channels = *self.shape(outp).last().unwrap() as usize;
+    if filter_scales.is_empty() || (filter_scales.len() != 1 && filter_scales.len() != num_channels)
+    {
+      return Err(format!("filter {} is not quantized per channel", filter));
+    }
+
+    let (lo, hi) = self.activation_range(activation, outp)?;
+    let mut params = vec![lo, hi];
+    for filter_scale in filter_scales.iter() {
+      let (multiplier, shift) = quantize_multiplier(inp_scale * *filter_scale as f64 / outp_scale);
+      params.extend([multiplier, shift]);
+    }
+    Ok(params)
+  }
+
+  fn tabulate(&self, f: impl Fn(f64) -> f64) -> Vec<i64> {
+    let sf = self.options.scale_factor;
+    let x_max = (8 * sf).min((1 << (self.options.k - 1)) - 1);
+    let mut params = vec![-x_max];
+    params.extend((-x_max..=x_max).map(|x| (f(x as f64 / sf as f64) * sf as f64).round() as i64));
+    params
+  }
+
+  fn load_op(&mut self, op: &Table, op_code: i32) -> Result<(), String> {
+    let inputs = op
+      .i32s(1)?
+      .into_iter()
+      .filter(|idx| *idx >= 0)
+      .map(|idx| self.resolve(idx as i64))
+      .collect::<Vec<_>>();
+    let outputs = op.i32s(2)?.iter().map(|x| *x as i64).collect::<Vec<_>>();
+    let options = op.table(4)?;
+    let opt_u8 = |field: usize| options.map_or(Ok(0), |opt| opt.u8(field, 0));
+    let opt_i32 =
+      |field: usize, default: i32| options.map_or(Ok(default), |opt| opt.i32(field, default));
+
+    let (layer_type, mut params) = match op_code {
+      QUANTIZE | DEQUANTIZE if self.options.parity => {
+        return Err("convert with int8 inputs and outputs for the parity mode".to_string());
+      }
+      QUANTIZE | DEQUANTIZE => {
+        self.aliases.insert(outputs[0], inputs[0]);
+        return Ok(());
+      }
+      CONV_2D => {
+        if opt_i32(4, 1)? != 1 || opt_i32(5, 1)? != 1 {
+          return Err("dilation is not supported".to_string());
+        }
+        let activation = check_activation(opt_u8(3)?)?;
+        let (sh, sw) = (opt_i32(2, 1)? as i64, opt_i32(1, 1)? as i64);
+        let mut params = vec![0, opt_u8(0)? as i64, activation, sh, sw];
+        // The filters of a grouped convolution have the input channels of a group
+        let channels = *self.shape(inputs[0]).last().unwrap();
+        let groups = channels / *self.shape(inputs[1]).last().unwrap();
+        if groups > 1 {
+          params.push(groups);
+        }
+        ("Conv2D", params)
+      }
+      DEPTHWISE_CONV_2D => {
+        if opt_i32(5, 1)? != 1 || opt_i32(6, 1)? != 1 {
+          return Err("dilation is not supported".to_string());
+        }
+        let activation = check_activation(opt_u8(4)?)?;
+        let (sh, sw) = (opt_i32(2, 1)? as i64, opt_i32(1, 1)? as i64);
+        ("Conv2D", vec![1, opt_u8(0)? as i64, activation, sh, sw])
+      }
+      FULLY_CONNECTED => ("FullyConnected", vec![check_activation(opt_u8(0)?)?]),
+      BATCH_MATMUL => {
+        let (adj_x, adj_y) = (opt_u8(0)?, opt_u8(1)?);
+        ("BatchMatMul", vec![adj_x as i64, adj_y as i64])
+      }
+      AVERAGE_POOL_2D | MAX_POOL_2D => {
+        let is_max = op_code == MAX_POOL_2D;
+        if is_max && opt_u8(0)? == 0 {
+          return Err("SAME padding is not supported".to_string());
+        }
+        if is_max && opt_u8(5)? != 0 {
+          return Err("fused activation is not supported".to_string());
+        }
+        let params = vec![
+          opt_i32(4, 1)? as i64,
+          opt_i32(3, 1)? as i64,
+          opt_i32(2, 1)? as i64,
+          opt_i32(1, 1)? as i64,
+        ];
+        (if is_max { "MaxPool2D" } else { "AveragePool2D" }, params)
+      }
+      ADD => ("Add", vec![check_activation(opt_u8(0)?)?]),
+      MUL => ("Mul", vec![]),
+      SUB => ("Sub", vec![]),
+      SQUARE => ("Square", vec![]),
+      SQUARED_DIFFERENCE => ("SquaredDifference", vec![]),
+      SQRT => ("Sqrt", vec![]),
+      RSQRT => ("Rsqrt", vec![]),
+      EXP => ("Exp", vec![]),
+      LOG => ("Ln", vec![]),
+      LOGISTIC => ("Logistic", vec![]),
+      TANH => ("Tanh", vec![]),
+      GELU => {
+        if opt_u8(0)? != 0 {
+          return Err("the tanh approximation of GELU is not supported".to_string());
+        }
+        ("Gelu", vec![])
+      }
+      SOFTMAX => ("Softmax", vec![]),
+      RELU => ("Tabulated", self.tabulate(|x| x.max(0.))),
+      RELU6 => ("Tabulated", self.tabulate(|x| x.clamp(0., 6.))),
+      MEAN => {
+        let axes = self.constant(inputs[1])?;
+        if axes.len() + 2 != self.shape(inputs[0]).len() {
+          return Err("only the mean over all but one axis is supported".to_string());
+        }
+        ("Mean", axes)
+      }
+      PAD => ("Pad", self.constant(inputs[1])?),
+      RESHAPE | SQUEEZE => ("Reshape", vec![]),
+      TRANSPOSE => {
+        let mut params = self.shape(inputs[0]);
+        params.extend(self.constant(inputs[1])?);
+        ("Transpose", params)
+      }
+      CONCATENATION => {
+        check_activation(opt_u8(1)?)?;
+        ("Concatenation", vec![opt_i32(0, 0)? as i64])
+      }
+      code => return Err(format!("unsupported builtin operator {}", code)),
+    };
+
+    if self.options.parity {
+      let activation = match op_code {
+        CONV_2D | DEPTHWISE_CONV_2D => Some(std::mem::replace(&mut params[2], 0)),
+        FULLY_CONNECTED => Some(std::mem::replace(&mut params[0], 0)),
+        CONCATENATION => {
+          self.check_same_quantization(&inputs, outputs[0])?;
+          None
+        }
+        MAX_POOL_2D | PAD | RESHAPE | SQUEEZE | TRANSPOSE => {
+          self.check_same_quantization(&inputs[..1], outputs[0])?;
+          None
+        }
+        code => return Err(format!("operator {} has no parity kernel", code)),
+      };
+      // The layer accumulates, and the Requantize layer computes the output
+      if let Some(activation) = activation {
+        let requantize = self.requantize_params(inputs[0], inputs[1], outputs[0], activation)?;
+        let acc = self.next_idx;
+        self.next_idx += 1;
+        let shape = self.shape(outputs[0]);
+        self.layers.push(LayerMsgpack {
+          layer_type: layer_type.to_string(),
+          params,
+          inp_idxes: inputs.clone(),
+          inp_shapes: inputs.iter().map(|idx| self.shape(*idx)).collect(),
+          out_idxes: vec![acc],
+          out_shapes: vec![shape.clone()],
+          mask: vec![],
+        });
+        self.layers.push(LayerMsgpack {
+          layer_type: "Requantize".to_string(),
+          params: requantize,
+          inp_idxes: vec![acc],
+          inp_shapes: vec![shape.clone()],
+          out_idxes: outputs.clone(),
+          out_shapes: vec![shape],
+          mask: vec![],
+        });
+        return Ok(());
+      }
+    }
+
+    self.layers.push(LayerMsgpack {
+      layer_type: layer_type.to_string(),
+      params,
+      inp_idxes: inputs.clone(),
+      inp_shapes: inputs.iter().map(|idx| self.shape(*idx)).collect(),
+      out_idxes: outputs.clone(),
+      out_shapes: outputs.iter().map(|idx| self.shape(*idx)).collect(),
+      mask: vec![],
+    });
+    Ok(())
+  }
+}
+
+// Loads the first subgraph of the TFLite model and quantizes the inputs. The inputs are the float
+// values, one flattened array per subgraph input.
+pub fn tflite_to_msgpack(
+  tflite: &[u8],
+  inputs: &[Vec<f64>],
+  options: &TfliteOptions,
+) -> Result<ModelMsgpack, String> {
+  let model = Table::root(tflite)?;
+  let op_codes = model
+    .tables(1)?
+    .iter()
+    .map(|code| {
+      // Codes above 127 are only in builtin_code, older models only set deprecated_builtin_code
+      Ok(code.i32(3, 0)?.max(code.u8(0, 0)? as i8 as i32))
+    })
+    .collect::<Result<Vec<_>, String>>()?;
+  let subgraph = model
+    .tables(2)?
+    .into_iter()
+    .next()
+    .ok_or("the model has no subgraph")?;
+  let buffers = model.tables(4)?;
+  let tensors = subgraph
+    .tables(0)?
+    .iter()
+    .map(|tensor| parse_tensor(tensor, &buffers))
+    .collect::<Result<Vec<_>, String>>()?;
+
+  let mut loader = Loader {
+    options: options.clone(),
+    tensors,
+    aliases: HashMap::new(),
+    layers: vec![],
+    next_idx: 0,
+  };
+  loader.next_idx = loader.tensors.len() as i64;
+  for (i, op) in subgraph.tables(3)?.iter().enumerate() {
+    let op_code = *op_codes
+      .get(op.i32(0, 0)? as usize)
+      .ok_or("malformed operator code index")?;
+    loader
+      .load_op(op, op_code)
+      .map_err(|e| format!("operator {}: {}", i, e))?;
+  }
+
+  let sf = options.scale_factor as f64;
+  let inp_idxes = subgraph
+    .i32s(1)?
+    .iter()
+    .map(|x| *x as i64)
+    .collect::<Vec<_>>();
+  if inp_idxes.len() != inputs.len() {
+    return Err(format!(
+      "the model has {} inputs, but {} were given",
+      inp_idxes.len(),
+      inputs.len()
+    ));
+  }
+  let mut tensors = vec![];
+  for (idx, data) in inp_idxes.iter().zip(inputs.iter()) {
+    let shape = loader.shape(*idx);
+    if shape.iter().product::<i64>() != data.len() as i64 {
+      return Err(format!(
+        "input {} of shape {:?} got {} values",
+        idx,
+        shape,
+        data.len()
+      ));
+    }
+    // The parity mode takes the quantized values
+    let data = if options.parity {
+      let tensor = &loader.tensors[*idx as usize];
+      if !tensor.quantized || data.iter().any(|x| x.fract() != 0.) {
+        return Err(format!("input {} must be quantized values", idx));
+      }
+      let zero_point = tensor.zero_points.get(0).cloned().unwrap_or(0);
+      data.iter().map(|x| *x as i64 - zero_point).collect()
+    } else {
+      data.iter().map(|x| (x * sf).round() as i64).collect()
+    };
+    tensors.push(TensorMsgpack {
+      idx: *idx,
+      shape,
+      data,
+      dtype: None,
+    });
+  }
+
+  // The constants the layers use. Floats and quantized tensors are moved to the global scale,
+  // the other integers, e.g., shapes and paddings, are kept as is. The parity mode keeps
+  // q - zero_point of the quantized tensors, and has no floats.
+  let used = loader
+    .layers
+    .iter()
+    .flat_map(|layer| layer.inp_idxes.iter().cloned())
+    .collect::<HashSet<_>>();
+  for idx in used.iter().filter(|idx| !inp_idxes.contains(idx)) {
+    let tensor = match loader.tensors.get(*idx as usize) {
+      Some(tensor) => tensor,
+      None => continue,
+    };
+    let data = match &tensor.data {
+      Some(data) => data,
+      None => continue,
+    };
+    if options.parity && tensor.dtype == FLOAT32 {
+      return Err(format!("tensor {} is a float in the parity mode", idx));
+    }
+    let data = match &tensor.centered {
+      Some(centered) if options.parity => centered,
+      _ => data,
+    };
+    let scaled = !options.parity && (tensor.dtype == FLOAT32 || tensor.quantized);
+    tensors.push(TensorMsgpack {
+      idx: *idx,
+      shape: loader.shape(*idx),
+      data: data
+        .iter()
+        .map(|x| {
+          if scaled {
+            (x * sf).round() as i64
+          } else {
+            *x as i64
+          }
+        })
+        .collect(),
+      dtype: None,
+    });
+  }
+
+  let out_idxes = subgraph
+    .i32s(2)?
+    .iter()
+    .map(|x| loader.resolve(*x as i64))
+    .collect();
+  let mut model = ModelMsgpack {
+    global_sf: if options.parity {
+      1
+    } else {
+      options.scale_factor
+    },
+    k: options.k,
+    num_cols: options.num_cols,
+    inp_idxes,
+    out_idxes,
+    tensors,
+    layers: loader.layers,
+    ..Default::default()
+  };
+  set_defaults(&mut model);
+  Ok(model)
+}
+
+// The inputs are a JSON list with a flattened float array per subgraph input
+pub fn load_tflite(
+  tflite_path: &str,
+  inp_path: &str,
+  options: &TfliteOptions,
+) -> Result<ModelMsgpack, String> {
+  let inputs: Vec<Vec<f64>> = serde_json::from_slice(&read_artifact(inp_path)?)
+    .map_err(|e| format!("malformed inputs {}: {}", inp_path, e))?;
+  tflite_to_msgpack(&read_artifact(tflite_path)?, &inputs, options)
+}
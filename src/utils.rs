@@ -1,4 +1,53 @@
+pub mod accuracy;
+pub mod artifacts;
+pub mod attribution;
+pub mod audit;
+pub mod branches;
+pub mod cancel;
+pub mod chaining;
+pub mod config_file;
+pub mod coprocessor;
+pub mod cost_model;
+pub mod cpu;
+pub mod differential;
+pub mod disk_pk;
+pub mod envelope;
+pub mod errors;
+#[cfg(feature = "evm")]
+pub mod evm_verifier;
+pub mod exits;
+pub mod float_lint;
+pub mod gpu;
+pub mod graph;
+pub mod head;
 pub mod helpers;
+pub mod keygen;
 pub mod loader;
+pub mod moe;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod optimizer;
+pub mod parallel;
+pub mod perf;
+pub mod pk_cache;
+pub mod precision;
+pub mod predicate;
+pub mod profiles;
+pub mod proof_stream;
 pub mod proving_ipa;
 pub mod proving_kzg;
+pub mod randomness;
+pub mod rlc;
+pub mod robustness;
+pub mod sandbox;
+pub mod scales;
+pub mod serve;
+pub mod signer;
+pub mod srs;
+pub mod stats;
+pub mod storage;
+pub mod tensor;
+pub mod validate;
+pub mod watermark;
+pub mod weight_cache;
+pub mod witness;
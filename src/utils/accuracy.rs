@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+  envelope::decode_signed,
+  loader::{load_model_msgpack, ModelMsgpack},
+  precision::working_sf,
+  storage::{is_remote, join_url, read_artifact},
+};
+use crate::model::ModelCircuit;
+
+// Accuracy of the quantized model before proving. The fixed-point outputs are the public values
+// the witness generation computes, i.e., exactly what a proof would reveal, so every sample costs
+// a circuit synthesis but no keygen or proving.
+// The dataset is a JSON list of samples, with the input paths relative to the dataset file:
+//   [{"input": "inp0.msgpack", "label": 3, "reference": [0.1, 0.7, ...]}, ...]
+// The label gives the top-1 and top-5 accuracy. The reference outputs of the float model give the
+// regression errors, and the agreement of the top-1 class with the float model.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sample {
+  pub input: String,
+  pub label: Option<usize>,
+  pub reference: Option<Vec<f64>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SampleResult {
+  pub input: String,
+  pub outputs: Vec<f64>,
+  pub top1: Option<bool>,
+  pub top5: Option<bool>,
+  pub reference_top1: Option<bool>,
+  pub max_abs_error: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccuracyReport {
+  pub num_samples: usize,
+  pub num_labeled: usize,
+  pub num_with_reference: usize,
+  pub top1: Option<f64>,
+  pub top5: Option<f64>,
+  pub reference_top1: Option<f64>,
+  pub mean_abs_error: Option<f64>,
+  pub rmse: Option<f64>,
+  pub max_abs_error: Option<f64>,
+  pub samples: Vec<SampleResult>,
+}
+
+// The input paths of a dataset are relative to the dataset file
+pub fn resolve_input(dataset_path: &str, input: &str) -> String {
+  let dir = match dataset_path.rfind('/') {
+    Some(pos) => &dataset_path[..pos],
+    None => ".",
+  };
+  if !is_remote(input) && !Path::new(input).is_absolute() {
+    join_url(dir, input)
+  } else {
+    input.to_string()
+  }
+}
+
+pub fn load_dataset(path: &str) -> Result<Vec<Sample>, String> {
+  let mut samples: Vec<Sample> = serde_json::from_slice(&read_artifact(path)?)
+    .map_err(|e| format!("malformed dataset {}: {}", path, e))?;
+  for sample in samples.iter_mut() {
+    sample.input = resolve_input(path, &sample.input);
+  }
+  Ok(samples)
+}
+
+// The output values of the model at the scale of the circuit, after the commitments
+pub fn quantized_outputs(config: ModelMsgpack) -> Result<Vec<f64>, String> {
+  let sf = working_sf(&config) as f64;
+  let num_commits = config.commit_before.as_ref().map_or(0, |x| x.len())
+    + config.commit_after.as_ref().map_or(0, |x| x.len());
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack(config, true);
+  let public_vals = circuit.compute_public_values()?;
+  public_vals[num_commits.min(public_vals.len())..]
+    .iter()
+    .map(|x| {
+      decode_signed(x)
+        .map(|v| v as f64 / sf)
+        .ok_or_else(|| "an output is not a small integer".to_string())
+    })
+    .collect()
+}
+
+// The indices of the k largest outputs, the first on ties
+pub fn top_k(outputs: &[f64], k: usize) -> Vec<usize> {
+  let mut idxes = (0..outputs.len()).collect::<Vec<_>>();
+  idxes.sort_by(|a, b| outputs[*b].total_cmp(&outputs[*a]).then(a.cmp(b)));
+  idxes.truncate(k);
+  idxes
+}
+
+fn score(input: &str, outputs: Vec<f64>, sample: &Sample) -> Result<SampleResult, String> {
+  if let Some(reference) = &sample.reference {
+    if reference.len() != outputs.len() {
+      return Err(format!(
+        "{}: the model has {} outputs, but the reference has {}",
+        input,
+        outputs.len(),
+        reference.len()
+      ));
+    }
+  }
+  let top5 = top_k(&outputs, 5);
+  let max_abs_error = sample.reference.as_ref().map(|reference| {
+    outputs
+      .iter()
+      .zip(reference.iter())
+      .map(|(x, y)| (x - y).abs())
+      .fold(0., f64::max)
+  });
+  Ok(SampleResult {
+    input: input.to_string(),
+    top1: sample.label.map(|label| top5.first() == Some(&label)),
+    top5: sample.label.map(|label| top5.contains(&label)),
+    reference_top1: sample
+      .reference
+      .as_ref()
+      .map(|reference| top5.first() == top_k(reference, 1).first()),
+    max_abs_error,
+    outputs,
+  })
+}
+
+fn fraction(hits: impl Iterator<Item = bool>) -> Option<f64> {
+  let (num_hits, num) = hits.fold((0, 0), |(h, n), hit| (h + hit as usize, n + 1));
+  if num == 0 {
+    None
+  } else {
+    Some(num_hits as f64 / num as f64)
+  }
+}
+
+pub fn accuracy_report(config_path: &str, samples: &[Sample]) -> Result<AccuracyReport, String> {
+  let mut results = vec![];
+  let mut errors = vec![];
+  for (i, sample) in samples.iter().enumerate() {
+    let outputs = quantized_outputs(load_model_msgpack(config_path, &sample.input))?;
+    if let Some(reference) = &sample.reference {
+      errors.extend(outputs.iter().zip(reference.iter()).map(|(x, y)| x - y));
+    }
+    let result = score(&sample.input, outputs, sample)?;
+    info!("sample {}/{}: {}", i + 1, samples.len(), sample.input);
+    results.push(result);
+  }
+
+  let num_errors = errors.len() as f64;
+  let (mean_abs_error, rmse, max_abs_error) = if errors.is_empty() {
+    (None, None, None)
+  } else {
+    (
+      Some(errors.iter().map(|e| e.abs()).sum::<f64>() / num_errors),
+      Some((errors.iter().map(|e| e * e).sum::<f64>() / num_errors).sqrt()),
+      Some(errors.iter().map(|e| e.abs()).fold(0., f64::max)),
+    )
+  };
+  Ok(AccuracyReport {
+    num_samples: results.len(),
+    num_labeled: results.iter().filter(|r| r.top1.is_some()).count(),
+    num_with_reference: results
+      .iter()
+      .filter(|r| r.reference_top1.is_some())
+      .count(),
+    top1: fraction(results.iter().filter_map(|r| r.top1)),
+    top5: fraction(results.iter().filter_map(|r| r.top5)),
+    reference_top1: fraction(results.iter().filter_map(|r| r.reference_top1)),
+    mean_abs_error,
+    rmse,
+    max_abs_error,
+    samples: results,
+  })
+}
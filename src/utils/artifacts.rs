@@ -0,0 +1,194 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::storage::{artifact_exists, is_remote, join_url, read_artifact, write_artifact};
+
+// Content-addressed artifacts. Each artifact is stored as <kind>-<hash prefix> in an artifacts
+// directory, next to a manifest.json that lists the full hashes and which artifacts were made
+// together (e.g., the vkey and params a proof was made with). Commands take either a path or a
+// hash prefix, and refuse to pair a proof with a vkey that the manifest says it was not made with.
+pub const MANIFEST_NAME: &str = "manifest.json";
+pub const DEFAULT_ARTIFACTS_DIR: &str = "./artifacts";
+pub const HASH_PREFIX_LEN: usize = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+  pub kind: String,
+  pub hash: String,
+  pub file: String,
+  pub size: u64,
+  pub created: u64,
+  // The hashes of the artifacts this one was made with
+  pub links: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+  pub artifacts: Vec<ManifestEntry>,
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+  let hash = blake2b_simd::Params::new().hash_length(32).hash(data);
+  hash.to_hex().to_string()
+}
+
+// For hashing while streaming, finalizes to the same hash as content_hash
+pub fn content_hasher() -> blake2b_simd::State {
+  blake2b_simd::Params::new().hash_length(32).to_state()
+}
+
+// The vk and the SRS that come with a proof are chosen by the prover, who can prove anything with
+// the vk of another circuit or an SRS of a known tau. The verifier uses them only if they match
+// the hash it pinned, or if it opts in to trusting the prover.
+pub fn check_pinned(
+  kind: &str,
+  data: &[u8],
+  pinned_hash: Option<&str>,
+  trust_prover: bool,
+) -> Result<(), String> {
+  match pinned_hash {
+    Some(pinned_hash) => {
+      let hash = content_hash(data);
+      if hash != pinned_hash {
+        return Err(format!(
+          "the {} has hash {}, the pinned hash is {}",
+          kind, hash, pinned_hash
+        ));
+      }
+      Ok(())
+    }
+    None if trust_prover => {
+      eprintln!(
+        "WARNING: verifying with the unpinned {} that the prover chose. The proof is only \
+         checked against this {}, not against the model.",
+        kind, kind
+      );
+      Ok(())
+    }
+    None => Err(format!("the {} of the prover is not pinned", kind)),
+  }
+}
+
+pub fn artifact_name(kind: &str, hash: &str) -> String {
+  format!("{}-{}", kind, &hash[..HASH_PREFIX_LEN])
+}
+
+impl Manifest {
+  pub fn load(dir: &str) -> Result<Self, String> {
+    let path = join_url(dir, MANIFEST_NAME);
+    if !artifact_exists(&path) {
+      return Ok(Self::default());
+    }
+    serde_json::from_slice(&read_artifact(&path)?)
+      .map_err(|e| format!("malformed manifest {}: {}", path, e))
+  }
+
+  pub fn save(&self, dir: &str) -> Result<(), String> {
+    let buf = serde_json::to_vec_pretty(self).unwrap();
+    write_artifact(&join_url(dir, MANIFEST_NAME), &buf)
+  }
+
+  pub fn get(&self, hash: &str) -> Option<&ManifestEntry> {
+    self.artifacts.iter().find(|entry| entry.hash == hash)
+  }
+
+  // Stores the data under its hash and returns the hash. Storing the same data again only adds
+  // the new links.
+  pub fn store(
+    &mut self,
+    dir: &str,
+    kind: &str,
+    data: &[u8],
+    links: &[String],
+  ) -> Result<String, String> {
+    let hash = content_hash(data);
+    if let Some(entry) = self.artifacts.iter_mut().find(|entry| entry.hash == hash) {
+      for link in links {
+        if !entry.links.contains(link) {
+          entry.links.push(link.clone());
+        }
+      }
+      return Ok(hash);
+    }
+
+    let file = artifact_name(kind, &hash);
+    if !is_remote(dir) {
+      std::fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir, e))?;
+    }
+    write_artifact(&join_url(dir, &file), data)?;
+    self.artifacts.push(ManifestEntry {
+      kind: kind.to_string(),
+      hash: hash.clone(),
+      file,
+      size: data.len() as u64,
+      created: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs(),
+      links: links.to_vec(),
+    });
+    Ok(hash)
+  }
+
+  // A reference is a path, an artifact name (<kind>-<hash prefix>), or a hash prefix
+  pub fn resolve(&self, dir: &str, reference: &str) -> Result<String, String> {
+    if artifact_exists(reference) {
+      return Ok(reference.to_string());
+    }
+    let (kind, prefix) = match reference.rsplit_once('-') {
+      Some((kind, prefix)) => (Some(kind), prefix),
+      None => (None, reference),
+    };
+    let matches = self
+      .artifacts
+      .iter()
+      .filter(|entry| entry.hash.starts_with(prefix) && kind.map_or(true, |k| k == entry.kind))
+      .collect::<Vec<_>>();
+    match matches.len() {
+      0 => Err(format!(
+        "{} is not a file or an artifact in {}",
+        reference, dir
+      )),
+      1 => Ok(join_url(dir, &matches[0].file)),
+      _ => Err(format!("{} matches more than one artifact", reference)),
+    }
+  }
+
+  // Fails if the manifest links the artifact to a different artifact of the given kind
+  pub fn check_link(&self, data: &[u8], kind: &str, linked: &[u8]) -> Result<(), String> {
+    let entry = match self.get(&content_hash(data)) {
+      Some(entry) => entry,
+      None => return Ok(()),
+    };
+    let linked_hash = content_hash(linked);
+    let expected = entry
+      .links
+      .iter()
+      .filter(|hash| self.get(hash).map_or(false, |e| e.kind == kind))
+      .collect::<Vec<_>>();
+    if expected.is_empty() || expected.contains(&&linked_hash) {
+      Ok(())
+    } else {
+      Err(format!(
+        "{} was made with {} {}, not {}",
+        entry.file,
+        kind,
+        &expected[0][..HASH_PREFIX_LEN],
+        &linked_hash[..HASH_PREFIX_LEN]
+      ))
+    }
+  }
+}
+
+// Parses --artifacts <dir> from the arguments
+pub fn artifacts_arg() -> Option<String> {
+  let args = std::env::args().collect::<Vec<_>>();
+  let pos = args.iter().position(|arg| arg == "--artifacts")?;
+  Some(
+    args
+      .get(pos + 1)
+      .expect("--artifacts needs a directory")
+      .clone(),
+  )
+}
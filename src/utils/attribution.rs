@@ -0,0 +1,103 @@
+use crate::utils::{
+  loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  watermark::{max_tensor_idx, replicate_model, weight_idxes},
+};
+
+// Occlusion attributions: the model is evaluated on the input and on one copy of the input per
+// attributed feature, with the feature set to zero by an Occlude layer. An Attribution layer
+// ranks the drops in the logit of the class and outputs the top k mask, which is the only output.
+// The weights and the input are committed (the first two public values).
+
+pub fn attribution_model(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+  features: &Vec<i64>,
+  class: i64,
+  k: i64,
+) -> ModelMsgpack {
+  assert_eq!(
+    model.inp_idxes.len(),
+    1,
+    "only models with a single input are supported"
+  );
+  assert_eq!(
+    model.out_idxes.len(),
+    1,
+    "only models with a single output are supported"
+  );
+  assert!(
+    k >= 1 && k as usize <= features.len(),
+    "k must be between 1 and the number of features"
+  );
+
+  let num_elems = inp[0].shape.iter().product::<i64>();
+  for feature in features.iter() {
+    assert!(
+      *feature >= 0 && *feature < num_elems,
+      "feature {} out of range",
+      feature
+    );
+  }
+
+  // The occluded copies get their inputs from the Occlude layers
+  let mut inputs = vec![inp.clone()];
+  inputs.extend(features.iter().map(|_| vec![]));
+  let replicated = replicate_model(model, &inputs);
+  let inp_shape = inp[0].shape.clone();
+  let out_shape = replicated
+    .layers
+    .iter()
+    .find_map(|layer| {
+      let pos = layer
+        .out_idxes
+        .iter()
+        .position(|x| *x == replicated.out_idxes[0])?;
+      Some(layer.out_shapes[pos].clone())
+    })
+    .unwrap();
+
+  let mut layers = vec![];
+  for (feature, occluded_idx) in features.iter().zip(replicated.inp_idxes[1..].iter()) {
+    layers.push(LayerMsgpack {
+      layer_type: "Occlude".to_string(),
+      params: vec![*feature],
+      inp_shapes: vec![inp_shape.clone()],
+      inp_idxes: vec![replicated.inp_idxes[0]],
+      out_idxes: vec![*occluded_idx],
+      out_shapes: vec![inp_shape.clone()],
+      mask: vec![],
+    });
+  }
+  layers.extend(replicated.layers.iter().cloned());
+
+  let out_idx = max_tensor_idx(&replicated) + 1;
+  layers.push(LayerMsgpack {
+    layer_type: "Attribution".to_string(),
+    params: vec![class, k],
+    inp_shapes: vec![out_shape; replicated.out_idxes.len()],
+    inp_idxes: replicated.out_idxes.clone(),
+    out_idxes: vec![out_idx],
+    out_shapes: vec![vec![features.len() as i64]],
+    mask: vec![],
+  });
+
+  let inp_idx = replicated.inp_idxes[0];
+  ModelMsgpack {
+    inp_idxes: vec![inp_idx],
+    out_idxes: vec![out_idx],
+    layers,
+    commit_before: Some(vec![weight_idxes(model), vec![inp_idx]]),
+    commit_after: Some(vec![]),
+    ..replicated
+  }
+}
+
+// The features selected by the top k mask in the public values
+pub fn top_features(features: &Vec<i64>, mask: &Vec<i64>) -> Vec<i64> {
+  features
+    .iter()
+    .zip(mask.iter())
+    .filter(|(_, bit)| **bit == 1)
+    .map(|(feature, _)| *feature)
+    .collect()
+}
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  gadgets::{
+    gadget::{default_shift_min_val, GadgetConfig},
+    nonlinear::non_linearity::activation_div,
+  },
+  layers::layer::LayerType,
+  model::layer_type_from_name,
+};
+
+use super::{
+  loader::{LayerMsgpack, ModelMsgpack},
+  precision::{rescale_model, working_sf},
+};
+
+// Worst-case magnitude analysis of a model, for auditing field overflows
+// Bounds are propagated through the layers as max absolute values (in fixed point). Every layer
+// is checked against the field modulus (with a safety margin), the accumulator range of the
+// division gadgets, and the range of the lookup tables. The model is audited at the working scale
+// of the circuit (see precision.rs), with the input bounds given at the scale of the model.
+//
+// NOTE: the bounds are deliberately conservative (e.g., the bias is assumed to be at sf^2 and
+// every product attains the max), so a failed check is not necessarily reachable.
+
+// Both BN254 and Pasta have ~2^254 moduli
+pub const FIELD_BITS: f64 = 254.;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditCheck {
+  pub name: String,
+  pub bound: f64,
+  pub limit: f64,
+  pub ok: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayerAudit {
+  pub layer_idx: usize,
+  pub layer_type: String,
+  pub inp_bounds: Vec<f64>,
+  pub acc_bound: f64,
+  pub out_bound: f64,
+  pub checks: Vec<AuditCheck>,
+  pub exact: bool, // False if the layer is not modeled and the input bound is passed through
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditReport {
+  pub k: i64,
+  pub scale_factor: i64,
+  pub field_bits: f64,
+  pub margin_bits: f64,
+  pub min_val: i64,
+  pub max_val: i64,
+  pub max_accumulator: f64,
+  pub layers: Vec<LayerAudit>,
+  pub safe: bool,
+}
+
+fn check(name: &str, bound: f64, limit: f64) -> AuditCheck {
+  AuditCheck {
+    name: name.to_string(),
+    bound,
+    limit,
+    ok: bound <= limit,
+  }
+}
+
+fn activation_bound(activation: i64, bound: f64, sf: f64) -> f64 {
+  match activation {
+    // ReluN1To1
+    2 => bound.min(sf),
+    // Relu6
+    3 => bound.min(6. * sf),
+    _ => bound,
+  }
+}
+
+// `inp_bounds` maps input tensor indices to max absolute values; inputs without a bound are
+// assumed to span the input lookup
+pub fn audit_model(
+  model: &ModelMsgpack,
+  inp_bounds: &HashMap<i64, f64>,
+  margin_bits: f64,
+) -> AuditReport {
+  // The tensors and the params are at the working scale in the circuit. A model whose frac_bits
+  // can't be applied has no circuit, and is audited as it is.
+  let rescaled = rescale_model(model).unwrap_or_else(|_| model.clone());
+  let inp_scale = working_sf(&rescaled) as f64 / model.global_sf as f64;
+  let model = &rescaled;
+  let sf = working_sf(model) as f64;
+  let min_val = -(1 << (model.k - 1));
+  let max_val = (1 << (model.k - 1)) - 10;
+  let gadget_config = GadgetConfig {
+    scale_factor: sf as u64,
+    shift_min_val: default_shift_min_val(sf as i64),
+    ..GadgetConfig::default()
+  };
+  let max_accumulator = gadget_config.max_accumulator() as f64;
+  let field_limit = 2f64.powf(FIELD_BITS - 1. - margin_bits);
+  let lookup_limit = max_val as f64;
+
+  let mut bounds: HashMap<i64, f64> = HashMap::new();
+  for tensor in model.tensors.iter() {
+    let max_abs = tensor
+      .data
+      .iter()
+      .map(|x| x.unsigned_abs())
+      .max()
+      .unwrap_or(0);
+    bounds.insert(tensor.idx, max_abs as f64);
+  }
+  for idx in model.inp_idxes.iter() {
+    let bound = inp_bounds
+      .get(idx)
+      .map_or(-min_val as f64, |bound| bound * inp_scale);
+    bounds.insert(*idx, bound);
+  }
+
+  let mut layers = vec![];
+  for (layer_idx, layer) in model.layers.iter().enumerate() {
+    let inp = layer
+      .inp_idxes
+      .iter()
+      .map(|idx| *bounds.get(idx).unwrap_or(&lookup_limit))
+      .collect::<Vec<_>>();
+    let layer_type = layer_type_from_name(&layer.layer_type);
+    let (acc_bound, out_bound, exact) = layer_type
+      .and_then(|layer_type| audit_layer(layer, layer_type, &inp, sf))
+      .unwrap_or_else(|| {
+        let max_inp = inp.iter().cloned().fold(0., f64::max);
+        (max_inp, max_inp, false)
+      });
+    let mut checks = vec![];
+    checks.push(check("field", acc_bound.max(out_bound), field_limit));
+    if layer_type.map_or(false, uses_division) {
+      checks.push(check("accumulator", acc_bound, max_accumulator));
+    }
+    if let Some(num_macs) = layer_type.and_then(|layer_type| num_macs(layer, layer_type)) {
+      // The bias counts as one more
+      let num_macs = num_macs + (inp.len() > 2) as i64;
+      let max_macs = gadget_config.max_macs(inp[0] as i64, inp[1] as i64);
+      checks.push(check("macs", num_macs as f64, max_macs as f64));
+    }
+    if layer_type.map_or(false, uses_lookup) {
+      let inp_max = match layer_type.unwrap() {
+        // These compare differences of their inputs
+        LayerType::TreeEnsemble
+        | LayerType::Robustness
+        | LayerType::Attribution
+        | LayerType::MoE
+        | LayerType::Predicate
+        | LayerType::Quantile
+        | LayerType::TopK => acc_bound,
+        // The gates before the activations, and the cell state, which grows by up to sf a step
+        LayerType::Lstm | LayerType::Gru => {
+          let shape = &layer.inp_shapes[0];
+          let num_steps = shape[shape.len().max(2) - 2] as f64;
+          (acc_bound / sf).max(num_steps * sf)
+        }
+        // The inputs are rounded before the lookup
+        LayerType::Gelu | LayerType::Silu => {
+          let div = activation_div(sf as i64, model.activation_table_bits).unwrap_or(1);
+          inp[0] / div as f64
+        }
+        // The variance plus eps goes through the rsqrt
+        LayerType::LayerNorm => {
+          let centered = 2. * inp[0];
+          centered * centered / sf + layer.params[0] as f64
+        }
+        _ => inp.get(0).cloned().unwrap_or(0.),
+      };
+      checks.push(check("lookup input", inp_max, lookup_limit));
+    }
+    checks.push(check("output range", out_bound, lookup_limit));
+
+    for idx in layer.out_idxes.iter() {
+      bounds.insert(*idx, out_bound);
+    }
+    layers.push(LayerAudit {
+      layer_idx,
+      layer_type: layer.layer_type.clone(),
+      inp_bounds: inp,
+      acc_bound,
+      out_bound,
+      checks,
+      exact,
+    });
+  }
+
+  let safe = layers.iter().all(|l| l.checks.iter().all(|c| c.ok));
+  AuditReport {
+    k: model.k,
+    scale_factor: sf as i64,
+    field_bits: FIELD_BITS,
+    margin_bits,
+    min_val,
+    max_val,
+    max_accumulator,
+    layers,
+    safe,
+  }
+}
+
+fn uses_division(layer_type: LayerType) -> bool {
+  match layer_type {
+    LayerType::Conv2D
+    | LayerType::Conv3D
+    | LayerType::FullyConnected
+    | LayerType::BatchMatMul
+    | LayerType::Mul
+    | LayerType::Square
+    | LayerType::SquaredDifference
+    | LayerType::MoE
+    | LayerType::Lstm
+    | LayerType::Gru
+    | LayerType::LayerNorm
+    | LayerType::Dropout => true,
+    _ => false,
+  }
+}
+
+fn uses_lookup(layer_type: LayerType) -> bool {
+  match layer_type {
+    LayerType::Logistic
+    | LayerType::Tanh
+    | LayerType::Sqrt
+    | LayerType::Rsqrt
+    | LayerType::Pow
+    | LayerType::Softmax
+    | LayerType::Tabulated
+    | LayerType::TreeEnsemble
+    | LayerType::Robustness
+    | LayerType::Attribution
+    | LayerType::MoE
+    | LayerType::Branch
+    | LayerType::Predicate
+    | LayerType::Requantize
+    | LayerType::Lstm
+    | LayerType::Gru
+    | LayerType::LayerNorm
+    | LayerType::Gelu
+    | LayerType::Silu
+    | LayerType::DivMod
+    | LayerType::Dropout
+    | LayerType::Quantile
+    | LayerType::TopK => true,
+    _ => false,
+  }
+}
+
+// The multiply-accumulates of every output of the dot product layers, without the bias
+fn num_macs(layer: &LayerMsgpack, layer_type: LayerType) -> Option<i64> {
+  let num_macs = match layer_type {
+    LayerType::Conv2D => {
+      let w_shape = &layer.inp_shapes[1];
+      // Depthwise convolutions only sum over the window
+      if layer.params[0] == 1 {
+        w_shape[1] * w_shape[2]
+      } else {
+        w_shape[1..].iter().product()
+      }
+    }
+    LayerType::Conv3D => layer.inp_shapes[1][1..].iter().product(),
+    LayerType::FullyConnected | LayerType::BatchMatMul => *layer.inp_shapes[0].last().unwrap(),
+    _ => return None,
+  };
+  Some(num_macs)
+}
+
+// Returns (accumulator bound, output bound, exact), or None if the layer has no bounds
+fn audit_layer(
+  layer: &LayerMsgpack,
+  layer_type: LayerType,
+  inp: &Vec<f64>,
+  sf: f64,
+) -> Option<(f64, f64, bool)> {
+  let max_inp = inp.iter().cloned().fold(0., f64::max);
+  let params = &layer.params;
+  let bias = inp.get(2).cloned().unwrap_or(0.);
+  let pass = (max_inp, max_inp, true);
+
+  let bounds = match layer_type {
+    LayerType::Conv2D => {
+      let acc = num_macs(layer, layer_type)? as f64 * inp[0] * inp[1] + bias * sf;
+      (acc, activation_bound(params[2], acc / sf, sf), true)
+    }
+    LayerType::Conv3D => {
+      let acc = num_macs(layer, layer_type)? as f64 * inp[0] * inp[1] + bias * sf;
+      (acc, activation_bound(params[1], acc / sf, sf), true)
+    }
+    LayerType::FullyConnected => {
+      let acc = num_macs(layer, layer_type)? as f64 * inp[0] * inp[1] + bias * sf;
+      (acc, activation_bound(params[0], acc / sf, sf), true)
+    }
+    LayerType::BatchMatMul => {
+      let acc = num_macs(layer, layer_type)? as f64 * inp[0] * inp[1];
+      (acc, acc / sf, true)
+    }
+    LayerType::Add | LayerType::Sub => (inp[0] + inp[1], inp[0] + inp[1], true),
+    LayerType::Mul => (inp[0] * inp[1], inp[0] * inp[1] / sf, true),
+    LayerType::Square => (inp[0] * inp[0], inp[0] * inp[0] / sf, true),
+    LayerType::SquaredDifference => {
+      let diff = inp[0] + inp[1];
+      (diff * diff, diff * diff / sf, true)
+    }
+    LayerType::DivVar => (inp[0] * sf, inp[0] * sf, true),
+    // The kept elements are scaled by 1 / keep
+    LayerType::Dropout => {
+      let multiplier = sf * sf / params[0].max(1) as f64;
+      (inp[0] * multiplier, inp[0] * multiplier / sf, true)
+    }
+    LayerType::Logistic | LayerType::Tanh | LayerType::Softmax => (max_inp, sf, true),
+    // |gelu(x)| and |silu(x)| are at most |x|
+    LayerType::Gelu | LayerType::Silu => (max_inp, max_inp, true),
+    LayerType::Sqrt => (max_inp, (max_inp * sf).sqrt(), true),
+    LayerType::Rsqrt => (max_inp, sf * sf.sqrt(), true),
+    LayerType::Pow => {
+      let power = params.get(0).cloned().unwrap_or(3) as i32;
+      let out = inp[0].powi(power) / sf.powi(power - 1);
+      (out, out, true)
+    }
+    // The functions and the piecewise linear layers are compiled into tables (see unary.rs and
+    // pwl.rs) from their own params
+    LayerType::Tabulated => match layer.layer_type.as_str() {
+      "Exp" => (max_inp, (max_inp / sf).exp() * sf, true),
+      // The smallest positive input is 1 / sf
+      "Ln" => (max_inp, sf.ln().max((max_inp / sf).ln()) * sf, true),
+      "Reciprocal" => (max_inp, sf * sf, true),
+      "PiecewiseLinear" => {
+        let n = params[0] as usize;
+        let out = params[1 + n..]
+          .iter()
+          .map(|y| y.unsigned_abs())
+          .max()
+          .unwrap_or(0) as f64;
+        (max_inp, out, true)
+      }
+      _ => {
+        let out = params[1..]
+          .iter()
+          .map(|y| y.unsigned_abs())
+          .max()
+          .unwrap_or(0) as f64;
+        (max_inp, out, true)
+      }
+    },
+    LayerType::Update => (inp[0] * sf + inp[1] * sf, inp[0] + inp[1], true),
+    // The accumulator is the difference between the features and the thresholds
+    LayerType::TreeEnsemble => (inp[0] + inp[1], inp[2] * params[1] as f64, true),
+    // Compares differences of inputs and of logits, and outputs epsilon
+    LayerType::Robustness => (2. * max_inp, inp[1], true),
+    // Compares differences of logits and outputs a mask
+    LayerType::Attribution => (2. * max_inp, 1., true),
+    // Compares the condition to one and outputs a bit
+    LayerType::Branch => (max_inp + 1., 1., true),
+    // Compares the elements of the input, or one element with the threshold, and outputs a bit
+    LayerType::Predicate if params[0] == 1 => (max_inp + params[2].abs() as f64 + 1., 1., true),
+    LayerType::Predicate => (2. * max_inp, 1., true),
+    // Compares differences of inputs, and the interpolations are between two inputs
+    LayerType::Quantile => (2. * max_inp, max_inp, true),
+    // Compares differences of inputs and outputs their indices
+    LayerType::TopK => {
+      let len = layer.inp_shapes[0].iter().product::<i64>() as f64;
+      (2. * max_inp, len, true)
+    }
+    // Multiplies by the multipliers and clamps to the bounds
+    LayerType::Requantize => {
+      let multiplier = params[2..].iter().step_by(2).max().cloned().unwrap_or(0) as f64;
+      let bound = params[0].unsigned_abs().max(params[1].unsigned_abs()) as f64;
+      (max_inp * multiplier, bound, true)
+    }
+    // The quotient is at most the input over the smallest divisor, the remainder the largest
+    LayerType::DivMod => {
+      let min_div = params[2..].iter().min().cloned().unwrap_or(1).max(1) as f64;
+      let max_div = params[2..].iter().max().cloned().unwrap_or(1) as f64;
+      let out = match params[1] {
+        0 => max_inp / min_div,
+        1 => max_div,
+        _ => (max_inp / min_div).max(max_div),
+      };
+      (max_inp, out, true)
+    }
+    // The first expert bounds every expert, whose outputs are averaged by the gate weights
+    LayerType::MoE => {
+      let d = *layer.inp_shapes[0].last().unwrap() as f64;
+      let h = layer.inp_shapes[3][0] as f64;
+      let up = d * inp[0] * inp[3] + inp[4] * sf;
+      let down = h * (up / sf) * inp[5] + inp[6] * sf;
+      (up.max(down).max(2. * inp[1]), down / sf, true)
+    }
+    // The gates add both products and the bias; the hidden state stays within sf, or the initial
+    // state
+    LayerType::Lstm | LayerType::Gru => {
+      let d = *layer.inp_shapes[0].last().unwrap() as f64;
+      let h = *layer.inp_shapes[2].last().unwrap() as f64;
+      let state = inp.get(4).cloned().unwrap_or(0.).max(sf);
+      let acc = d * inp[0] * inp[1] + h * state * inp[2] + inp[3] * sf;
+      (acc, state, true)
+    }
+    // The centered inputs are squared and multiplied by the rsqrt, which is at most
+    // sf * sqrt(sf / eps); a normalized value is at most sqrt(d)
+    LayerType::LayerNorm => {
+      let d = *layer.inp_shapes[0].last().unwrap() as f64;
+      let centered = 2. * inp[0];
+      let inv_std = sf * (sf / params[0].max(1) as f64).sqrt();
+      let acc = (centered * centered).max(centered * inv_std);
+      let normed = (d.sqrt() * sf).min(centered * inv_std / sf);
+      (
+        acc.max(normed * inp[1]),
+        normed * inp[1] / sf + inp[2],
+        true,
+      )
+    }
+    // Averages, maxes, and shape operations do not increase the magnitude
+    LayerType::AvgPool2D
+    | LayerType::AvgPool3D
+    | LayerType::Gather
+    | LayerType::MaxPool2D
+    | LayerType::MaxPool3D
+    | LayerType::Mean
+    | LayerType::DivFixed
+    | LayerType::MaskNegInf
+    | LayerType::Noop
+    | LayerType::Occlude
+    | LayerType::Pack
+    | LayerType::Pad
+    | LayerType::Permute
+    | LayerType::Reshape
+    | LayerType::ResizeNN
+    | LayerType::Rotate
+    | LayerType::ScatterND
+    | LayerType::Slice
+    | LayerType::Split
+    | LayerType::Transpose
+    | LayerType::Broadcast
+    | LayerType::Concatenation => pass,
+    _ => (max_inp, max_inp, false),
+  };
+  Some(bounds)
+}
@@ -0,0 +1,201 @@
+use std::collections::{HashMap, HashSet};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+
+use crate::{commitments::input_hash::num_input_hash_vals, model::ModelCircuit};
+
+use super::{
+  envelope::decode_signed,
+  loader::{sub_model, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  rlc::{num_rlc_vals, rlc_position},
+  watermark::max_tensor_idx,
+};
+
+// Conditional subgraphs, as exported from frameworks with control flow (e.g., ONNX If). The layers
+// of both branches are in the model, and a branch maps its then or else outputs to its outputs
+// depending on the sign of a condition tensor. As with early exits, the branch taken determines
+// the circuit, so it is fixed at prove time and every combination of branches has its own keys.
+// Only the layers of the taken branches are evaluated. A Branch layer computes the predicate from
+// the condition and constrains it to the branch taken, and the predicates of all branches are
+// revealed as the first outputs, so a condition inside an untaken branch is still evaluated.
+// The weights of both branches are kept, so the weight commitment covers both branches and is the
+// same whichever is taken.
+
+fn tensor_shape(model: &ModelMsgpack, idx: i64) -> Option<Vec<i64>> {
+  let produced = model.layers.iter().find_map(|layer| {
+    let pos = layer.out_idxes.iter().position(|x| *x == idx)?;
+    Some(layer.out_shapes[pos].clone())
+  });
+  produced.or_else(|| {
+    model
+      .tensors
+      .iter()
+      .find(|tensor| tensor.idx == idx)
+      .map(|tensor| tensor.shape.clone())
+  })
+}
+
+// Reads the taken outputs of every branch in place of its outputs, drops the layers that are not
+// needed anymore, and adds the Branch layers. Returns the positions of the dropped layers.
+pub fn select_branches(model: &mut ModelMsgpack) -> Result<Vec<usize>, String> {
+  if model.exit.is_some() {
+    return Err("branches can't be combined with early exits".to_string());
+  }
+  let branches = model.branches.take().unwrap_or(vec![]);
+
+  let mut remap = HashMap::new();
+  for (i, branch) in branches.iter().enumerate() {
+    let taken = branch
+      .taken
+      .ok_or_else(|| format!("the branch taken at {} is not chosen", i))?;
+    let outs = if taken {
+      &branch.then_outs
+    } else {
+      &branch.else_outs
+    };
+    if branch.then_outs.len() != branch.out_idxes.len()
+      || branch.else_outs.len() != branch.out_idxes.len()
+    {
+      return Err(format!(
+        "branch {} has {} outputs, but {} then outputs and {} else outputs",
+        i,
+        branch.out_idxes.len(),
+        branch.then_outs.len(),
+        branch.else_outs.len()
+      ));
+    }
+    for (out, taken_out) in branch.out_idxes.iter().zip(outs.iter()) {
+      if model.layers.iter().any(|l| l.out_idxes.contains(out)) {
+        return Err(format!(
+          "output {} of branch {} is computed by a layer",
+          out, i
+        ));
+      }
+      // Nested branches can output the outputs of an earlier branch
+      let taken_out = *remap.get(taken_out).unwrap_or(taken_out);
+      remap.insert(*out, taken_out);
+    }
+  }
+  let remap_idx = |idx: &mut i64| {
+    if let Some(x) = remap.get(idx) {
+      *idx = *x;
+    }
+  };
+  for layer in model.layers.iter_mut() {
+    layer.inp_idxes.iter_mut().for_each(remap_idx);
+  }
+  model.out_idxes.iter_mut().for_each(remap_idx);
+  for group in model.commit_after.iter_mut().flatten() {
+    group.iter_mut().for_each(remap_idx);
+  }
+  let conds = branches
+    .iter()
+    .map(|branch| *remap.get(&branch.cond).unwrap_or(&branch.cond))
+    .collect::<Vec<_>>();
+
+  // Walks back from the outputs and the conditions
+  let mut needed = model
+    .out_idxes
+    .iter()
+    .chain(conds.iter())
+    .cloned()
+    .collect::<HashSet<_>>();
+  let mut keep = vec![false; model.layers.len()];
+  for (i, layer) in model.layers.iter().enumerate().rev() {
+    if layer.out_idxes.iter().any(|idx| needed.contains(idx)) {
+      keep[i] = true;
+      needed.extend(layer.inp_idxes.iter().cloned());
+    }
+  }
+  let dropped = (0..model.layers.len())
+    .filter(|i| !keep[*i])
+    .collect::<Vec<_>>();
+  let dropped_outs = dropped
+    .iter()
+    .flat_map(|i| model.layers[*i].out_idxes.iter().cloned())
+    .collect::<HashSet<_>>();
+  for group in model.commit_after.iter().flatten() {
+    if let Some(idx) = group.iter().find(|idx| dropped_outs.contains(idx)) {
+      return Err(format!(
+        "tensor {} is committed to but not computed by the taken branches",
+        idx
+      ));
+    }
+  }
+  let mut i = 0;
+  model.layers.retain(|_| {
+    i += 1;
+    keep[i - 1]
+  });
+
+  // The predicates are computed right after their conditions
+  let mut next_idx = max_tensor_idx(model) + 1;
+  let mut predicates = vec![];
+  for (i, (branch, cond)) in branches.iter().zip(conds.iter()).enumerate() {
+    let shape = tensor_shape(model, *cond)
+      .ok_or_else(|| format!("the condition {} of branch {} does not exist", cond, i))?;
+    let pos = model
+      .layers
+      .iter()
+      .position(|layer| layer.out_idxes.contains(cond))
+      .map_or(0, |pos| pos + 1);
+    model.layers.insert(
+      pos,
+      LayerMsgpack {
+        layer_type: "Branch".to_string(),
+        params: vec![branch.taken.unwrap() as i64],
+        inp_idxes: vec![*cond],
+        inp_shapes: vec![shape],
+        out_idxes: vec![next_idx],
+        out_shapes: vec![vec![1]],
+        mask: vec![],
+      },
+    );
+    predicates.push(next_idx);
+    next_idx += 1;
+  }
+  model.out_idxes = [predicates, model.out_idxes.clone()].concat();
+  Ok(dropped)
+}
+
+// Chooses the branch taken at every branch, in order, by evaluating its condition on the input
+pub fn choose_branches(
+  model: &mut ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+) -> Result<Vec<bool>, String> {
+  let mut branches = model.branches.clone().unwrap_or(vec![]);
+  for i in 0..branches.len() {
+    let cond_model = ModelMsgpack {
+      branches: Some(branches[..i].to_vec()),
+      ..sub_model(model, vec![branches[i].cond])
+    };
+    let public_vals = ModelCircuit::<Fr>::public_values(&cond_model, inp)?;
+    let cond = public_vals
+      .last()
+      .and_then(decode_signed)
+      .ok_or_else(|| format!("the condition of branch {} is not an integer", i))?;
+    branches[i].taken = Some(cond > 0);
+  }
+  let taken = branches
+    .iter()
+    .map(|branch| branch.taken.unwrap())
+    .collect();
+  model.branches = Some(branches);
+  Ok(taken)
+}
+
+// The revealed predicates, i.e., whether the then branch is taken at every branch
+pub fn branch_predicates(model: &ModelMsgpack, public_vals: &Vec<Fr>) -> Result<Vec<bool>, String> {
+  let num_branches = model.branches.as_ref().map_or(0, |branches| branches.len());
+  let start = rlc_position(model) + num_rlc_vals(model) + num_input_hash_vals(model);
+  public_vals
+    .get(start..start + num_branches)
+    .ok_or("too few public values")?
+    .iter()
+    .map(|x| match decode_signed(x) {
+      Some(0) => Ok(false),
+      Some(1) => Ok(true),
+      _ => Err("a predicate is not a bit".to_string()),
+    })
+    .collect()
+}
@@ -0,0 +1,134 @@
+use std::{
+  collections::BTreeMap,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc, Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+
+// Timeouts for long running commands. The work runs on a worker thread with a CancelToken that it
+// checks between stages. On timeout, the token is cancelled, the stages finished so far are
+// written out, and the process exits with TIMEOUT_EXIT_CODE (after the verify exit codes).
+pub const TIMEOUT_EXIT_CODE: i32 = 4;
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+  cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StageMetric {
+  pub stage: String,
+  pub seconds: f64,
+}
+
+lazy_static! {
+  pub static ref STAGES: Mutex<Vec<StageMetric>> = Mutex::new(vec![]);
+  // Other results for the metrics, e.g., whether the self-verification passed
+  pub static ref RESULTS: Mutex<BTreeMap<String, serde_json::Value>> = Mutex::new(BTreeMap::new());
+}
+
+pub fn record_stage(stage: &str, elapsed: Duration) {
+  STAGES.lock().unwrap().push(StageMetric {
+    stage: stage.to_string(),
+    seconds: elapsed.as_secs_f64(),
+  });
+}
+
+pub fn record_result(name: &str, value: serde_json::Value) {
+  RESULTS.lock().unwrap().insert(name.to_string(), value);
+}
+
+pub fn write_stages(path: &str, timed_out: bool) {
+  let metrics = serde_json::json!({
+    "timed_out": timed_out,
+    "stages": *STAGES.lock().unwrap(),
+    "results": *RESULTS.lock().unwrap(),
+  });
+  std::fs::write(path, serde_json::to_string_pretty(&metrics).unwrap()).unwrap();
+}
+
+// Runs f on a worker thread. Without a timeout this waits for f.
+pub fn run_with_timeout<T: Send + 'static>(
+  timeout: Option<Duration>,
+  metrics_path: &str,
+  f: impl FnOnce(CancelToken) -> T + Send + 'static,
+) -> T {
+  let token = CancelToken::new();
+  let worker_token = token.clone();
+  let (sender, receiver) = mpsc::channel();
+  let start = Instant::now();
+  thread::spawn(move || {
+    let res = f(worker_token);
+    // The receiver is gone if we timed out
+    let _ = sender.send(res);
+  });
+
+  match timeout {
+    Some(timeout) => match receiver.recv_timeout(timeout) {
+      Ok(res) => res,
+      Err(mpsc::RecvTimeoutError::Timeout) => {
+        token.cancel();
+        record_stage("cancelled", start.elapsed());
+        write_stages(metrics_path, true);
+        eprintln!(
+          "timed out after {:?}, wrote partial metrics to {}",
+          start.elapsed(),
+          metrics_path
+        );
+        std::process::exit(TIMEOUT_EXIT_CODE);
+      }
+      Err(mpsc::RecvTimeoutError::Disconnected) => panic!("the worker failed"),
+    },
+    None => receiver.recv().expect("the worker failed"),
+  }
+}
+
+// Runs f on a worker thread for at most timeout. Unlike run_with_timeout, a timeout doesn't exit
+// the process, e.g., for the server: the worker can't be stopped in the middle of create_proof,
+// so it is returned, to be joined before the next request.
+pub fn try_with_timeout<T: Send + 'static>(
+  timeout: Duration,
+  f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, thread::JoinHandle<()>> {
+  let (sender, receiver) = mpsc::channel();
+  let worker = thread::spawn(move || {
+    let _ = sender.send(f());
+  });
+  match receiver.recv_timeout(timeout) {
+    Ok(res) => Ok(res),
+    Err(mpsc::RecvTimeoutError::Timeout) => Err(worker),
+    Err(mpsc::RecvTimeoutError::Disconnected) => panic!("the worker failed"),
+  }
+}
+
+// Parses --timeout <seconds> from the arguments
+pub fn timeout_arg() -> Option<Duration> {
+  let args = std::env::args().collect::<Vec<_>>();
+  let pos = args.iter().position(|arg| arg == "--timeout")?;
+  let secs = args
+    .get(pos + 1)
+    .expect("--timeout needs a number of seconds")
+    .parse::<f64>()
+    .expect("invalid timeout");
+  Some(Duration::from_secs_f64(secs))
+}
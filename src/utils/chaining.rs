@@ -0,0 +1,184 @@
+use halo2_proofs::{
+  circuit::Value,
+  halo2curves::{bn256::Fr, ff::PrimeField},
+};
+
+use crate::{
+  commitments::{input_hash::num_input_hash_vals, packer::PackerChip},
+  utils::{
+    helpers::convert_pos_int,
+    loader::{ModelMsgpack, TensorMsgpack},
+    rlc::{num_rlc_vals, rlc_position},
+  },
+};
+
+// Proof chaining: the output commitment of one model is used as the input commitment of the
+// next, so multi-stage pipelines can be proven as separate proofs that are linked by comparing
+// the public values. The commitments hash the packed values only, so both models must pack the
+// same way.
+
+// The public values are the commit_before commitments, the commit_after commitments, then the
+// outputs
+pub fn commitment_position(model: &ModelMsgpack, idxes: &Vec<i64>, after: bool) -> Option<usize> {
+  let before = model.commit_before.clone().unwrap_or(vec![]);
+  let commit_after = model.commit_after.clone().unwrap_or(vec![]);
+  if after {
+    commit_after
+      .iter()
+      .position(|group| group == idxes)
+      .map(|pos| before.len() + pos)
+  } else {
+    before.iter().position(|group| group == idxes)
+  }
+}
+
+pub fn output_shapes(model: &ModelMsgpack) -> Vec<Vec<i64>> {
+  model
+    .out_idxes
+    .iter()
+    .map(|idx| {
+      model
+        .layers
+        .iter()
+        .find_map(|layer| {
+          let pos = layer.out_idxes.iter().position(|x| x == idx)?;
+          Some(layer.out_shapes[pos].clone())
+        })
+        .expect("output is not produced by any layer")
+    })
+    .collect()
+}
+
+fn packing(model: &ModelMsgpack) -> (i64, usize) {
+  let bits_per_elem = model.bits_per_elem.unwrap_or(model.k);
+  let num_elem_per_packed =
+    PackerChip::<Fr>::num_elem_per_packed(bits_per_elem as usize, model.num_cols as usize);
+  (bits_per_elem, num_elem_per_packed)
+}
+
+// Commits to the outputs of `prev` and the inputs of `next`
+pub fn link_models(prev: &mut ModelMsgpack, next: &mut ModelMsgpack) {
+  let prev_shapes = output_shapes(prev);
+  let next_shapes = next
+    .inp_idxes
+    .iter()
+    .map(|idx| {
+      next
+        .layers
+        .iter()
+        .find_map(|layer| {
+          let pos = layer.inp_idxes.iter().position(|x| x == idx)?;
+          Some(layer.inp_shapes[pos].clone())
+        })
+        .expect("input is not used by any layer")
+    })
+    .collect::<Vec<_>>();
+  let num_elems = |shapes: &Vec<Vec<i64>>| -> Vec<i64> {
+    shapes.iter().map(|shape| shape.iter().product()).collect()
+  };
+  assert_eq!(
+    num_elems(&prev_shapes),
+    num_elems(&next_shapes),
+    "outputs of the first model do not match the inputs of the second"
+  );
+
+  // Both models must pack the committed values the same way. The packed values are range checked
+  // with the input lookup, so this cannot exceed either k.
+  let bits_per_elem = prev
+    .bits_per_elem
+    .unwrap_or(prev.k)
+    .min(next.bits_per_elem.unwrap_or(next.k));
+  prev.bits_per_elem = Some(bits_per_elem);
+  next.bits_per_elem = Some(bits_per_elem);
+  assert_eq!(
+    packing(prev),
+    packing(next),
+    "models pack differently, use the same number of columns"
+  );
+
+  let mut prev_out = prev.out_idxes.clone();
+  prev_out.sort();
+  let mut commit_after = prev.commit_after.clone().unwrap_or(vec![]);
+  if !commit_after.contains(&prev_out) {
+    commit_after.push(prev_out);
+  }
+  prev.commit_after = Some(commit_after);
+
+  let mut next_inp = next.inp_idxes.clone();
+  next_inp.sort();
+  let mut commit_before = next.commit_before.clone().unwrap_or(vec![]);
+  if !commit_before.contains(&next_inp) {
+    commit_before.push(next_inp);
+  }
+  next.commit_before = Some(commit_before);
+}
+
+// The outputs of `prev`, from its public values, as the inputs of `next`
+pub fn chained_inputs<F: PrimeField>(
+  prev: &ModelMsgpack,
+  prev_public_vals: &Vec<F>,
+  next: &ModelMsgpack,
+) -> Vec<TensorMsgpack> {
+  let num_commitments = rlc_position(prev) + num_rlc_vals(prev) + num_input_hash_vals(prev);
+  let mut vals = prev_public_vals[num_commitments..]
+    .iter()
+    .map(|x| convert_pos_int(Value::known(*x)) as i64);
+
+  // The outputs are public in the order of out_idxes, but are committed in the order of the
+  // tensor indices
+  let mut outputs = prev
+    .out_idxes
+    .iter()
+    .zip(output_shapes(prev).into_iter())
+    .map(|(idx, shape)| {
+      let len = shape.iter().product::<i64>() as usize;
+      (*idx, shape, (&mut vals).take(len).collect::<Vec<_>>())
+    })
+    .collect::<Vec<_>>();
+  outputs.sort_by_key(|(idx, _, _)| *idx);
+
+  let mut next_inp = next.inp_idxes.clone();
+  next_inp.sort();
+  next_inp
+    .iter()
+    .zip(outputs.into_iter())
+    .map(|(idx, (_, shape, data))| TensorMsgpack {
+      idx: *idx,
+      shape,
+      data,
+      dtype: None,
+    })
+    .collect()
+}
+
+// Checks that the output commitment of `prev` is the input commitment of `next`
+pub fn check_linkage<F: PrimeField>(
+  prev: &ModelMsgpack,
+  prev_public_vals: &Vec<F>,
+  next: &ModelMsgpack,
+  next_public_vals: &Vec<F>,
+) -> Result<(), String> {
+  let mut prev_out = prev.out_idxes.clone();
+  prev_out.sort();
+  let mut next_inp = next.inp_idxes.clone();
+  next_inp.sort();
+
+  let prev_pos = commitment_position(prev, &prev_out, true)
+    .ok_or_else(|| "the first model does not commit to its outputs".to_string())?;
+  let next_pos = commitment_position(next, &next_inp, false)
+    .ok_or_else(|| "the second model does not commit to its inputs".to_string())?;
+  if packing(prev) != packing(next) {
+    return Err("the models pack their commitments differently".to_string());
+  }
+
+  let prev_commitment = prev_public_vals
+    .get(prev_pos)
+    .ok_or_else(|| "missing public values for the first model".to_string())?;
+  let next_commitment = next_public_vals
+    .get(next_pos)
+    .ok_or_else(|| "missing public values for the second model".to_string())?;
+  if prev_commitment != next_commitment {
+    return Err("the output commitment does not match the input commitment".to_string());
+  }
+  Ok(())
+}
@@ -0,0 +1,98 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{profiles::column_profile, storage::read_artifact};
+
+// zkml.toml: the paths and options for the CLI, so containers only need to mount one file.
+// Every field is optional and falls back to the defaults below.
+//
+//   model = "/data/model.msgpack"
+//   input = "/data/inp.msgpack"
+//   srs = "/data/params/17.params"
+//   proof = "/data/proof"
+//   public_vals = "/data/public_vals"
+//   vkey = "/data/vkey"
+//   k = 17
+//   transcript = "blake2b" # or "evm"
+//   column_profile = "low-memory"
+//   self_verify = false
+pub const DEFAULT_CONFIG_PATH: &str = "/data/zkml.toml";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZkmlConfig {
+  pub model: String,
+  pub input: String,
+  pub srs: Option<String>,
+  pub proof: String,
+  pub public_vals: String,
+  pub vkey: String,
+  // Overrides the k of the model config
+  pub k: Option<u32>,
+  pub transcript: String,
+  // Overrides the column profile of the model config (see profiles.rs)
+  pub column_profile: Option<String>,
+  // Verifies the proof after proving
+  pub self_verify: bool,
+}
+
+impl Default for ZkmlConfig {
+  fn default() -> Self {
+    Self {
+      model: "/data/model.msgpack".to_string(),
+      input: "/data/inp.msgpack".to_string(),
+      srs: None,
+      proof: "/data/proof".to_string(),
+      public_vals: "/data/public_vals".to_string(),
+      vkey: "/data/vkey".to_string(),
+      k: None,
+      transcript: "blake2b".to_string(),
+      column_profile: None,
+      self_verify: false,
+    }
+  }
+}
+
+// Blake2b, or the Keccak transcript of the EVM verifier with the evm feature
+pub fn check_transcript(transcript: &str) -> Result<(), String> {
+  match transcript {
+    "blake2b" => Ok(()),
+    "evm" if cfg!(feature = "evm") => Ok(()),
+    "evm" => Err("the evm transcript needs the evm feature".to_string()),
+    _ => Err(format!("unsupported transcript: {}", transcript)),
+  }
+}
+
+impl ZkmlConfig {
+  pub fn from_file(path: &str) -> Result<Self, String> {
+    let contents = String::from_utf8(read_artifact(path)?)
+      .map_err(|e| format!("{} is not utf-8: {}", path, e))?;
+    let config: Self =
+      toml::from_str(&contents).map_err(|e| format!("malformed config {}: {}", path, e))?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  // Reads the given config, or the default config if it exists
+  pub fn load(path: Option<&str>) -> Result<Self, String> {
+    match path {
+      Some(path) => Self::from_file(path),
+      None if std::path::Path::new(DEFAULT_CONFIG_PATH).exists() => {
+        Self::from_file(DEFAULT_CONFIG_PATH)
+      }
+      None => Ok(Self::default()),
+    }
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    check_transcript(&self.transcript)?;
+    if let Some(k) = self.k {
+      if k < 1 || k > 28 {
+        return Err(format!("k out of range: {}", k));
+      }
+    }
+    if let Some(name) = &self.column_profile {
+      column_profile(name)?;
+    }
+    Ok(())
+  }
+}
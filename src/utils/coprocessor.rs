@@ -0,0 +1,316 @@
+use std::{
+  io::Write,
+  process::{Command, Stdio},
+};
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  commitments::{
+    merkle::field_to_string,
+    native::{commit_tensors, PackingParams},
+  },
+  model::ModelCircuit,
+};
+
+use super::{
+  chaining::commitment_position,
+  envelope::decode_signed,
+  loader::{sub_model, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  precision::rescale_model,
+};
+
+// Co-processor mode: the layers in coprocessor_layers, e.g., a giant matmul, are not constrained
+// in the circuit, but proven by another proof system. The circuit commits to the inputs of every
+// such layer (a commit_after group) and takes its outputs as advice that it commits to (a
+// commit_before group), so the proof shows that the rest of the model was computed from outputs
+// with these commitments. The external proof must then show that the committed outputs are the
+// layer applied to the committed inputs. The commitments are those of commit_tensors, over the
+// tensors in order of their index, so the other system can compute them natively.
+// The groups are added when the config is loaded, so that everything that counts commitments
+// sees them, and the layers are dropped when the circuit is built. The prover supplies the
+// outputs with the inputs (see coprocessor_outputs), at the precision of the circuit.
+
+// The (inputs, outputs) of the layer, as committed
+fn layer_groups(layer: &LayerMsgpack) -> (Vec<i64>, Vec<i64>) {
+  let mut inputs = layer.inp_idxes.clone();
+  inputs.sort();
+  inputs.dedup();
+  let mut outputs = layer.out_idxes.clone();
+  outputs.sort();
+  (inputs, outputs)
+}
+
+pub fn check_coprocessor_layers(model: &ModelMsgpack) -> Result<(), String> {
+  for pos in model.coprocessor_layers.iter().flatten() {
+    let layer = usize::try_from(*pos)
+      .ok()
+      .and_then(|pos| model.layers.get(pos))
+      .ok_or_else(|| format!("coprocessor layer {} is out of range", pos))?;
+    if let Some(idx) = layer.out_idxes.iter().find(|x| model.out_idxes.contains(x)) {
+      return Err(format!(
+        "the output {} of coprocessor layer {} is an output of the model",
+        idx, pos
+      ));
+    }
+  }
+  Ok(())
+}
+
+// Adds the commitment groups of the coprocessor layers. Applied when the config is loaded.
+pub fn commit_coprocessor_layers(model: &mut ModelMsgpack) {
+  let positions = model.coprocessor_layers.clone().unwrap_or(vec![]);
+  if positions.is_empty() {
+    return;
+  }
+  let mut commit_before = model.commit_before.clone().unwrap_or(vec![]);
+  let mut commit_after = model.commit_after.clone().unwrap_or(vec![]);
+  for pos in positions.iter() {
+    let layer = match model.layers.get(*pos as usize) {
+      Some(layer) => layer,
+      None => continue,
+    };
+    let (inputs, outputs) = layer_groups(layer);
+    if !commit_after.contains(&inputs) {
+      commit_after.push(inputs);
+    }
+    if !commit_before.contains(&outputs) {
+      commit_before.push(outputs);
+    }
+  }
+  model.commit_before = Some(commit_before);
+  model.commit_after = Some(commit_after);
+}
+
+// Drops the coprocessor layers, whose outputs become inputs. Returns the dropped layers.
+pub fn select_coprocessor_layers(model: &mut ModelMsgpack) -> Result<Vec<usize>, String> {
+  check_coprocessor_layers(model)?;
+  commit_coprocessor_layers(model);
+  let mut positions = model
+    .coprocessor_layers
+    .take()
+    .unwrap_or(vec![])
+    .iter()
+    .map(|pos| *pos as usize)
+    .collect::<Vec<_>>();
+  positions.sort();
+  positions.dedup();
+  for pos in positions.iter().rev() {
+    let layer = model.layers.remove(*pos);
+    model.inp_idxes.extend(layer.out_idxes.iter());
+  }
+  Ok(positions)
+}
+
+// The model up to the layer, with the outputs of the layer as the outputs, and without the
+// commitments
+fn prefix_model(model: &ModelMsgpack, pos: usize) -> ModelMsgpack {
+  ModelMsgpack {
+    layers: model.layers[..=pos].to_vec(),
+    branches: None,
+    ..sub_model(model, model.layers[pos].out_idxes.clone())
+  }
+}
+
+// The values of the outputs of the layer, from the public values of a model that outputs them
+fn output_tensors(layer: &LayerMsgpack, public_vals: &[Fr]) -> Result<Vec<TensorMsgpack>, String> {
+  let mut vals = public_vals.iter();
+  let mut tensors = vec![];
+  for (idx, shape) in layer.out_idxes.iter().zip(layer.out_shapes.iter()) {
+    let len = shape.iter().product::<i64>() as usize;
+    let data = (&mut vals)
+      .take(len)
+      .map(|x| decode_signed(x).map(|x| x as i64))
+      .collect::<Option<Vec<_>>>()
+      .ok_or_else(|| format!("an output of {} is not an integer", layer.layer_type))?;
+    if data.len() != len {
+      return Err(format!("missing outputs of {}", layer.layer_type));
+    }
+    tensors.push(TensorMsgpack {
+      idx: *idx,
+      shape: shape.clone(),
+      data,
+      dtype: None,
+    });
+  }
+  Ok(tensors)
+}
+
+// The outputs of the coprocessor layers on the input, computed with the witness of the model, for
+// a prover that doesn't get them from the other system. They are added to the input.
+pub fn coprocessor_outputs(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+) -> Result<Vec<TensorMsgpack>, String> {
+  check_coprocessor_layers(model)?;
+  let mut outputs = vec![];
+  for pos in model.coprocessor_layers.iter().flatten() {
+    let prefix = prefix_model(model, *pos as usize);
+    let public_vals = ModelCircuit::<Fr>::public_values(&prefix, inp)?;
+    outputs.extend(output_tensors(&model.layers[*pos as usize], &public_vals)?);
+  }
+  Ok(outputs)
+}
+
+// What the external proof of a coprocessor layer must show, over the commitments in the public
+// values of a proof of the model (as decimal field elements)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CoprocessorClaim {
+  pub layer: usize,
+  pub layer_type: String,
+  pub params: Vec<i64>,
+  pub inp_idxes: Vec<i64>,
+  pub inp_shapes: Vec<Vec<i64>>,
+  pub out_idxes: Vec<i64>,
+  pub out_shapes: Vec<Vec<i64>>,
+  pub scale_factor: i64,
+  pub inp_commitment: String,
+  pub out_commitment: String,
+}
+
+pub fn coprocessor_claims(
+  model: &ModelMsgpack,
+  public_vals: &[Fr],
+) -> Result<Vec<CoprocessorClaim>, String> {
+  check_coprocessor_layers(model)?;
+  let mut model = rescale_model(model)?;
+  commit_coprocessor_layers(&mut model);
+  let commitment = |group: &Vec<i64>, after: bool| {
+    commitment_position(&model, group, after)
+      .and_then(|pos| public_vals.get(pos))
+      .map(field_to_string)
+      .ok_or_else(|| format!("no commitment to tensors {:?}", group))
+  };
+  let mut claims = vec![];
+  for pos in model.coprocessor_layers.iter().flatten() {
+    let layer = &model.layers[*pos as usize];
+    let (inputs, outputs) = layer_groups(layer);
+    claims.push(CoprocessorClaim {
+      layer: *pos as usize,
+      layer_type: layer.layer_type.clone(),
+      params: layer.params.clone(),
+      inp_idxes: layer.inp_idxes.clone(),
+      inp_shapes: layer.inp_shapes.clone(),
+      out_idxes: layer.out_idxes.clone(),
+      out_shapes: layer.out_shapes.clone(),
+      scale_factor: model.global_sf,
+      inp_commitment: commitment(&inputs, true)?,
+      out_commitment: commitment(&outputs, false)?,
+    });
+  }
+  Ok(claims)
+}
+
+// Checks the external proof of a claim. Implementations verify the proofs of another system, or
+// run its verifier.
+pub trait CoprocessorVerifier {
+  fn name(&self) -> String;
+  fn verify(&self, claim: &CoprocessorClaim, proof: &[u8]) -> Result<bool, String>;
+}
+
+// Runs `<program> <claim json>` with the proof on stdin, e.g., the verifier of a matmul proof
+// system. The claim is valid if the program exits successfully.
+pub struct CommandVerifier {
+  pub program: String,
+}
+
+impl CoprocessorVerifier for CommandVerifier {
+  fn name(&self) -> String {
+    format!("command:{}", self.program)
+  }
+
+  fn verify(&self, claim: &CoprocessorClaim, proof: &[u8]) -> Result<bool, String> {
+    let mut child = Command::new(&self.program)
+      .arg(serde_json::to_string(claim).unwrap())
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .spawn()
+      .map_err(|e| format!("could not run {}: {}", self.program, e))?;
+    let mut pipe = child.stdin.take().unwrap();
+    // A verifier can reject without reading the whole proof
+    pipe.write_all(proof).ok();
+    drop(pipe);
+    let status = child.wait().map_err(|e| e.to_string())?;
+    Ok(status.success())
+  }
+}
+
+// The proof is the opening: the input and output tensors of the layer (msgpack, as an input
+// file). They must have the committed values, and the outputs are recomputed from the inputs.
+// Not zero knowledge, for testing and for layers whose data can be shown to the verifier.
+pub struct OpeningVerifier {
+  // The config or the layout of the model
+  pub model: ModelMsgpack,
+}
+
+impl CoprocessorVerifier for OpeningVerifier {
+  fn name(&self) -> String {
+    "opening".to_string()
+  }
+
+  fn verify(&self, claim: &CoprocessorClaim, proof: &[u8]) -> Result<bool, String> {
+    let opened: Vec<TensorMsgpack> =
+      rmp_serde::from_slice(proof).map_err(|e| format!("malformed opening: {}", e))?;
+    let find = |idxes: &Vec<i64>| {
+      idxes
+        .iter()
+        .map(|idx| {
+          opened
+            .iter()
+            .find(|tensor| tensor.idx == *idx)
+            .ok_or_else(|| format!("the opening has no tensor {}", idx))
+        })
+        .collect::<Result<Vec<_>, _>>()
+    };
+    // The opened values are at the precision of the circuit
+    let model = rescale_model(&self.model)?;
+    let layer = model
+      .layers
+      .get(claim.layer)
+      .ok_or_else(|| format!("the model has no layer {}", claim.layer))?;
+    let (inputs, outputs) = layer_groups(layer);
+    let params = PackingParams::from_model(&model)?;
+    let (inp_tensors, out_tensors) = (find(&inputs)?, find(&outputs)?);
+    if field_to_string(&commit_tensors(&inp_tensors, &params)?) != claim.inp_commitment
+      || field_to_string(&commit_tensors(&out_tensors, &params)?) != claim.out_commitment
+    {
+      return Ok(false);
+    }
+
+    let layer_model = ModelMsgpack {
+      inp_idxes: inputs,
+      tensors: inp_tensors.into_iter().cloned().collect(),
+      layers: vec![layer.clone()],
+      ..prefix_model(&model, claim.layer)
+    };
+    let public_vals = ModelCircuit::<Fr>::public_values(&layer_model, &vec![])?;
+    let recomputed = output_tensors(layer, &public_vals)?;
+    Ok(recomputed.iter().all(|tensor| {
+      out_tensors
+        .iter()
+        .any(|x| x.idx == tensor.idx && x.data == tensor.data)
+    }))
+  }
+}
+
+// opening, or command:<program>
+pub fn parse_coprocessor_verifier(
+  spec: &str,
+  model: &ModelMsgpack,
+) -> Result<Box<dyn CoprocessorVerifier>, String> {
+  if spec == "opening" {
+    return Ok(Box::new(OpeningVerifier {
+      model: model.clone(),
+    }));
+  }
+  if let Some(program) = spec.strip_prefix("command:") {
+    return Ok(Box::new(CommandVerifier {
+      program: program.to_string(),
+    }));
+  }
+  Err(format!(
+    "unknown coprocessor verifier {}, expected opening or command:<program>",
+    spec
+  ))
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  loader::ModelMsgpack,
+  perf::{HardwareInfo, PerfReport},
+  profiles::apply_column_profile,
+  storage::{list_artifacts, read_artifact},
+};
+
+// A cost model fit on perf snapshots. The work of every stage is assumed to be proportional to
+// the number of cells times log(rows) (the FFTs and MSMs dominate) and to be split over the CPUs,
+// so each stage gets one coefficient:
+//   seconds = coef * 2^k * num_cols * k / num_cpus
+// The snapshots of a scaling sweep use the threads they ran on instead of the CPUs.
+// The peak memory is assumed to be proportional to the number of cells.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CostModel {
+  pub stage_coefs: BTreeMap<String, f64>,
+  pub memory_coef: Option<f64>,
+  pub num_snapshots: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Estimate {
+  pub k: i64,
+  pub num_cols: i64,
+  pub stages: BTreeMap<String, f64>,
+  pub total_seconds: f64,
+  pub peak_memory_kb: Option<u64>,
+}
+
+fn cells(k: i64, num_cols: i64) -> f64 {
+  (1u64 << k) as f64 * num_cols as f64
+}
+
+fn work(k: i64, num_cols: i64, num_cpus: usize) -> f64 {
+  cells(k, num_cols) * k as f64 / num_cpus.max(1) as f64
+}
+
+// Least squares through the origin
+fn fit_coef(points: &[(f64, f64)]) -> Option<f64> {
+  let xx = points.iter().map(|(x, _)| x * x).sum::<f64>();
+  if xx == 0. {
+    return None;
+  }
+  Some(points.iter().map(|(x, y)| x * y).sum::<f64>() / xx)
+}
+
+// Reads every perf report in a directory, skipping the files that are not reports
+pub fn load_snapshots(dir: &str) -> Result<Vec<PerfReport>, String> {
+  let mut snapshots = vec![];
+  for url in list_artifacts(dir)?
+    .iter()
+    .filter(|url| url.ends_with(".json"))
+  {
+    match serde_json::from_slice::<PerfReport>(&read_artifact(url)?) {
+      Ok(snapshot) => snapshots.push(snapshot),
+      Err(e) => eprintln!("skipping {}: {}", url, e),
+    }
+  }
+  Ok(snapshots)
+}
+
+// Takes either a hardware profile or a perf report, which contains one
+pub fn load_hardware(path: &str) -> Result<HardwareInfo, String> {
+  let value: serde_json::Value = serde_json::from_slice(&read_artifact(path)?)
+    .map_err(|e| format!("malformed hardware profile {}: {}", path, e))?;
+  let value = value.get("hardware").cloned().unwrap_or(value);
+  serde_json::from_value(value).map_err(|e| format!("malformed hardware profile {}: {}", path, e))
+}
+
+impl CostModel {
+  pub fn fit(snapshots: &Vec<PerfReport>) -> Result<Self, String> {
+    if snapshots.is_empty() {
+      return Err("no perf snapshots to fit the cost model on".to_string());
+    }
+
+    let mut stage_points: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+    let mut memory_points = vec![];
+    for snapshot in snapshots.iter() {
+      let threads = snapshot.threads.unwrap_or(snapshot.hardware.num_cpus);
+      let x = work(snapshot.k, snapshot.num_cols, threads);
+      for stage in snapshot
+        .stages
+        .iter()
+        .filter(|stage| stage.stage != "cancelled")
+      {
+        let points = stage_points.entry(stage.stage.clone()).or_insert(vec![]);
+        points.push((x, stage.seconds));
+      }
+      if let Some(peak) = snapshot.peak_memory_kb {
+        memory_points.push((cells(snapshot.k, snapshot.num_cols), peak as f64));
+      }
+    }
+
+    let stage_coefs = stage_points
+      .iter()
+      .filter_map(|(stage, points)| Some((stage.clone(), fit_coef(points)?)))
+      .collect();
+    Ok(Self {
+      stage_coefs,
+      memory_coef: fit_coef(&memory_points),
+      num_snapshots: snapshots.len(),
+    })
+  }
+
+  pub fn estimate(&self, config: &ModelMsgpack, hardware: &HardwareInfo) -> Estimate {
+    let mut config = config.clone();
+    apply_column_profile(&mut config).unwrap();
+    let x = work(config.k, config.num_cols, hardware.num_cpus);
+    let stages = self
+      .stage_coefs
+      .iter()
+      .map(|(stage, coef)| (stage.clone(), coef * x))
+      .collect::<BTreeMap<_, _>>();
+    let total_seconds = stages.values().sum();
+    let peak_memory_kb = self
+      .memory_coef
+      .map(|coef| (coef * cells(config.k, config.num_cols)) as u64);
+
+    Estimate {
+      k: config.k,
+      num_cols: config.num_cols,
+      stages,
+      total_seconds,
+      peak_memory_kb,
+    }
+  }
+}
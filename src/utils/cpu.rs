@@ -0,0 +1,75 @@
+use serde_derive::{Deserialize, Serialize};
+
+// The pools default to one thread per performance core. halo2 splits its FFTs and MSMs into one
+// equal chunk per thread of the pool (parallelize in halo2_proofs), so on big.LITTLE machines,
+// e.g., Apple M-series or some Arm servers, the chunks on the efficiency cores finish last and the
+// whole stage waits for them. With one kind of core, every CPU is a performance core.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreTopology {
+  pub performance_cores: usize,
+  pub efficiency_cores: usize,
+}
+
+#[cfg(target_os = "macos")]
+fn sysctl(name: &str) -> Option<String> {
+  let out = std::process::Command::new("sysctl")
+    .args(["-n", name])
+    .output()
+    .ok()?;
+  if !out.status.success() {
+    return None;
+  }
+  Some(String::from_utf8(out.stdout).ok()?.trim().to_string())
+}
+
+// The brand string of the CPU, which /proc/cpuinfo has on Linux
+#[cfg(target_os = "macos")]
+pub fn cpu_model() -> Option<String> {
+  sysctl("machdep.cpu.brand_string")
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn cpu_model() -> Option<String> {
+  None
+}
+
+// macOS numbers the kinds of cores from the fastest, as perflevels
+#[cfg(target_os = "macos")]
+pub fn core_topology() -> Option<CoreTopology> {
+  let count = |name: &str| sysctl(name)?.parse::<usize>().ok();
+  if count("hw.nperflevels")? < 2 {
+    return None;
+  }
+  Some(CoreTopology {
+    performance_cores: count("hw.perflevel0.logicalcpu")?,
+    efficiency_cores: count("hw.perflevel1.logicalcpu")?,
+  })
+}
+
+// Linux gives the relative capacity of every core on heterogeneous Arm machines, where the
+// performance cores are the ones with the largest
+#[cfg(not(target_os = "macos"))]
+pub fn core_topology() -> Option<CoreTopology> {
+  let capacities = (0..)
+    .map(|cpu| std::fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/cpu_capacity", cpu)))
+    .take_while(|capacity| capacity.is_ok())
+    .filter_map(|capacity| capacity.ok()?.trim().parse::<u64>().ok())
+    .collect::<Vec<_>>();
+  let max_capacity = *capacities.iter().max()?;
+  let performance_cores = capacities.iter().filter(|x| **x == max_capacity).count();
+  if performance_cores == capacities.len() {
+    return None;
+  }
+  Some(CoreTopology {
+    performance_cores,
+    efficiency_cores: capacities.len() - performance_cores,
+  })
+}
+
+// One thread per performance core, or per CPU
+pub fn default_threads() -> usize {
+  match core_topology() {
+    Some(topology) if topology.performance_cores > 0 => topology.performance_cores,
+    _ => std::thread::available_parallelism().map_or(1, |n| n.get()),
+  }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+  accuracy::resolve_input,
+  envelope::decode_signed,
+  loader::{load_model_msgpack, sub_model, ModelMsgpack},
+  precision::working_sf,
+  storage::read_artifact,
+};
+use crate::model::ModelCircuit;
+
+// Differential testing of the quantized model against the float model, op by op. The dataset has
+// the float values of the intermediate tensors, keyed by tensor index, e.g., from the TFLite
+// interpreter with experimental_preserve_all_tensors:
+//   [{"input": "inp0.msgpack", "activations": {"12": [0.1, ...], "15": [...]}}, ...]
+// A tensor diverges when its max abs error exceeds the tolerance of the op that computes it,
+// abs + rel * max |reference|, from a spec of per-op tolerances:
+//   {"default": {"abs": 0.05}, "ops": {"Softmax": {"abs": 0.01}, "Exp": {"rel": 0.02}}}
+// Errors compound, so once a tensor diverges the later ones mostly do too. The first divergence
+// of a sample is found by bisection over the checked layers, with the model truncated after the
+// layer, so a sample costs a logarithmic number of witness generations instead of one per layer.
+// Over the dataset, the op type with the most first divergences is named as the cause of the
+// drift, with its lift over the share of the checked layers it has.
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tolerance {
+  pub abs: f64,
+  pub rel: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToleranceSpec {
+  pub default: Tolerance,
+  pub ops: BTreeMap<String, Tolerance>,
+}
+
+impl Default for ToleranceSpec {
+  fn default() -> Self {
+    Self {
+      default: Tolerance { abs: 0.05, rel: 0. },
+      ops: BTreeMap::new(),
+    }
+  }
+}
+
+impl ToleranceSpec {
+  pub fn load(path: &str) -> Result<Self, String> {
+    serde_json::from_slice(&read_artifact(path)?)
+      .map_err(|e| format!("malformed tolerances {}: {}", path, e))
+  }
+
+  // The allowed max abs error of the output of an op
+  pub fn allowed(&self, layer_type: &str, reference: &[f64]) -> f64 {
+    let tolerance = self.ops.get(layer_type).unwrap_or(&self.default);
+    let max_ref = reference.iter().map(|x| x.abs()).fold(0., f64::max);
+    tolerance.abs + tolerance.rel * max_ref
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffSample {
+  pub input: String,
+  pub activations: BTreeMap<String, Vec<f64>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Divergence {
+  pub layer: usize,
+  pub layer_type: String,
+  pub tensor: i64,
+  pub max_abs_error: f64,
+  pub allowed: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SampleDivergence {
+  pub input: String,
+  pub first: Option<Divergence>,
+  // The number of truncated models the bisection ran
+  pub num_runs: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OpDrift {
+  pub layer_type: String,
+  pub num_layers: usize,
+  pub num_first_divergences: usize,
+  // The share of the first divergences over the share of the checked layers
+  pub lift: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DifferentialReport {
+  pub num_samples: usize,
+  pub num_diverged: usize,
+  pub responsible_op: Option<String>,
+  pub ops: Vec<OpDrift>,
+  pub samples: Vec<SampleDivergence>,
+}
+
+pub fn load_diff_dataset(path: &str) -> Result<Vec<DiffSample>, String> {
+  let mut samples: Vec<DiffSample> = serde_json::from_slice(&read_artifact(path)?)
+    .map_err(|e| format!("malformed dataset {}: {}", path, e))?;
+  for sample in samples.iter_mut() {
+    sample.input = resolve_input(path, &sample.input);
+  }
+  Ok(samples)
+}
+
+// The model up to and including the layer, with its first output as the output
+fn truncate(model: &ModelMsgpack, pos: usize) -> ModelMsgpack {
+  ModelMsgpack {
+    layers: model.layers[..=pos].to_vec(),
+    branches: None,
+    ..sub_model(model, vec![model.layers[pos].out_idxes[0]])
+  }
+}
+
+// The divergence of the output of the layer, if it exceeds its tolerance
+fn check_layer(
+  model: &ModelMsgpack,
+  pos: usize,
+  reference: &[f64],
+  spec: &ToleranceSpec,
+) -> Result<Option<Divergence>, String> {
+  let layer = &model.layers[pos];
+  let sf = working_sf(model) as f64;
+  let outputs = ModelCircuit::<Fr>::public_values(&truncate(model, pos), &vec![])?
+    .iter()
+    .map(|x| decode_signed(x).map(|x| x as f64 / sf))
+    .collect::<Option<Vec<_>>>()
+    .ok_or_else(|| format!("an output of layer {} is not an integer", pos))?;
+  if outputs.len() != reference.len() {
+    return Err(format!(
+      "layer {} has {} outputs, but the reference has {}",
+      pos,
+      outputs.len(),
+      reference.len()
+    ));
+  }
+  let max_abs_error = outputs
+    .iter()
+    .zip(reference.iter())
+    .map(|(x, y)| (x - y).abs())
+    .fold(0., f64::max);
+  let allowed = spec.allowed(&layer.layer_type, reference);
+  Ok((max_abs_error > allowed).then(|| Divergence {
+    layer: pos,
+    layer_type: layer.layer_type.clone(),
+    tensor: layer.out_idxes[0],
+    max_abs_error,
+    allowed,
+  }))
+}
+
+// The layers whose first output has a reference, in order
+fn checked_layers(model: &ModelMsgpack, sample: &DiffSample) -> Vec<usize> {
+  (0..model.layers.len())
+    .filter(|pos| {
+      let idx = model.layers[*pos].out_idxes[0];
+      sample.activations.contains_key(&idx.to_string())
+    })
+    .collect()
+}
+
+// Bisects for the first checked layer that diverges, assuming that the layers after it diverge
+pub fn first_divergence(
+  model: &ModelMsgpack,
+  sample: &DiffSample,
+  spec: &ToleranceSpec,
+) -> Result<SampleDivergence, String> {
+  let checked = checked_layers(model, sample);
+  let reference = |i: usize| {
+    let idx = model.layers[checked[i]].out_idxes[0];
+    &sample.activations[&idx.to_string()]
+  };
+
+  let mut num_runs = 0;
+  let mut check = |i: usize| {
+    num_runs += 1;
+    check_layer(model, checked[i], reference(i), spec)
+  };
+  let mut first = match checked.len() {
+    0 => None,
+    n => check(n - 1)?,
+  };
+  if first.is_some() {
+    let (mut lo, mut hi) = (0, checked.len() - 1);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      match check(mid)? {
+        Some(divergence) => {
+          first = Some(divergence);
+          hi = mid;
+        }
+        None => lo = mid + 1,
+      }
+    }
+  }
+  Ok(SampleDivergence {
+    input: sample.input.clone(),
+    first,
+    num_runs,
+  })
+}
+
+pub fn differential_report(
+  config_path: &str,
+  samples: &[DiffSample],
+  spec: &ToleranceSpec,
+) -> Result<DifferentialReport, String> {
+  let mut results = vec![];
+  let mut num_layers = BTreeMap::new();
+  let mut num_firsts = BTreeMap::new();
+  for (i, sample) in samples.iter().enumerate() {
+    let model = load_model_msgpack(config_path, &sample.input);
+    for pos in checked_layers(&model, sample) {
+      *num_layers
+        .entry(model.layers[pos].layer_type.clone())
+        .or_insert(0) += 1;
+    }
+    let result = first_divergence(&model, sample, spec)?;
+    if let Some(first) = &result.first {
+      *num_firsts.entry(first.layer_type.clone()).or_insert(0) += 1;
+    }
+    info!(
+      "sample {}/{}: {} diverges at {:?}",
+      i + 1,
+      samples.len(),
+      sample.input,
+      result.first.as_ref().map(|x| x.layer)
+    );
+    results.push(result);
+  }
+
+  let num_diverged = results.iter().filter(|r| r.first.is_some()).count();
+  let total_layers = num_layers.values().sum::<usize>();
+  let mut ops = num_layers
+    .iter()
+    .map(|(layer_type, num)| {
+      let num_firsts = *num_firsts.get(layer_type).unwrap_or(&0);
+      let share = num_firsts as f64 / num_diverged.max(1) as f64;
+      OpDrift {
+        layer_type: layer_type.clone(),
+        num_layers: *num,
+        num_first_divergences: num_firsts,
+        lift: share / (*num as f64 / total_layers as f64),
+      }
+    })
+    .collect::<Vec<_>>();
+  ops.sort_by(|a, b| {
+    (b.num_first_divergences, b.lift)
+      .partial_cmp(&(a.num_first_divergences, a.lift))
+      .unwrap()
+  });
+  let responsible_op = ops
+    .first()
+    .filter(|op| op.num_first_divergences > 0)
+    .map(|op| op.layer_type.clone());
+
+  Ok(DifferentialReport {
+    num_samples: results.len(),
+    num_diverged,
+    responsible_op,
+    ops,
+    samples: results,
+  })
+}
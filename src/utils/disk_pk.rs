@@ -0,0 +1,209 @@
+// The proving key of a k >= 22 circuit can be larger than the RAM of the machine. halo2 keeps
+// every polynomial of the key in a Vec, and create_proof borrows all of them, so the key can't be
+// read piece by piece. With the disk-pk feature, the binary uses DiskBackedAlloc as its global
+// allocator. While the key is read or generated (with_disk_pk), every allocation of at least
+// DISK_PK_MIN_BYTES is then a shared mapping of an unlinked file in the chosen directory. The
+// prover touches the key one polynomial at a time, and the kernel pages the polynomials in from
+// the files and evicts them back to the files without swap. Proofs are slower, since the key is
+// read from disk on every pass, and the directory needs room for the whole key. Every allocation
+// made by other threads in the meantime is also backed by a file.
+
+#[cfg(feature = "disk-pk")]
+mod mapped {
+  use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ffi::CString,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+  };
+
+  use once_cell::sync::OnceCell;
+
+  pub const DISK_PK_MIN_BYTES: usize = 1 << 20;
+  // The mappings are recorded in a fixed table, since the allocator can't allocate for it. With a
+  // full table, the allocations go to RAM again.
+  const MAX_MAPPINGS: usize = 1 << 14;
+  const PAGE_SIZE: usize = 4096;
+
+  pub static ENABLED: AtomicBool = AtomicBool::new(false);
+  // The mkstemp template of the files, <dir>/zkml_pk_XXXXXX
+  pub static TEMPLATE: OnceCell<CString> = OnceCell::new();
+  #[allow(clippy::declare_interior_mutable_const)]
+  const UNMAPPED: AtomicUsize = AtomicUsize::new(0);
+  static MAPPINGS: [AtomicUsize; MAX_MAPPINGS] = [UNMAPPED; MAX_MAPPINGS];
+
+  fn record(ptr: *mut u8) -> bool {
+    MAPPINGS.iter().any(|slot| {
+      slot
+        .compare_exchange(0, ptr as usize, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+    })
+  }
+
+  fn forget(ptr: *mut u8) -> bool {
+    MAPPINGS.iter().any(|slot| {
+      slot
+        .compare_exchange(ptr as usize, 0, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+    })
+  }
+
+  pub fn is_mapped(ptr: *const u8) -> bool {
+    MAPPINGS
+      .iter()
+      .any(|slot| slot.load(Ordering::Acquire) == ptr as usize)
+  }
+
+  // A zeroed shared mapping of a new file. The file is unlinked right away, so it is only
+  // reachable through the mapping and is freed with it, also if the process is killed.
+  unsafe fn map_file(len: usize) -> Option<*mut u8> {
+    let template = TEMPLATE.get()?.as_bytes_with_nul();
+    let mut path = [0u8; 4096];
+    if template.len() > path.len() {
+      return None;
+    }
+    path[..template.len()].copy_from_slice(template);
+    let fd = libc::mkstemp(path.as_mut_ptr() as *mut libc::c_char);
+    if fd < 0 {
+      return None;
+    }
+    libc::unlink(path.as_ptr() as *const libc::c_char);
+    let ptr = if libc::ftruncate(fd, len as libc::off_t) == 0 {
+      libc::mmap(
+        std::ptr::null_mut(),
+        len,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_SHARED,
+        fd,
+        0,
+      )
+    } else {
+      libc::MAP_FAILED
+    };
+    libc::close(fd);
+    if ptr == libc::MAP_FAILED {
+      None
+    } else {
+      Some(ptr as *mut u8)
+    }
+  }
+
+  fn on_disk(layout: &Layout) -> bool {
+    layout.size() >= DISK_PK_MIN_BYTES
+      && layout.align() <= PAGE_SIZE
+      && ENABLED.load(Ordering::Relaxed)
+  }
+
+  // A recorded mapping for the allocation, or None if it goes to RAM: it is small, the disk is
+  // off, the file can't be mapped, or the table is full
+  unsafe fn alloc_mapped(layout: &Layout) -> Option<*mut u8> {
+    if !on_disk(layout) {
+      return None;
+    }
+    let ptr = map_file(layout.size())?;
+    if record(ptr) {
+      return Some(ptr);
+    }
+    libc::munmap(ptr as *mut libc::c_void, layout.size());
+    None
+  }
+
+  pub struct DiskBackedAlloc;
+
+  unsafe impl GlobalAlloc for DiskBackedAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+      match alloc_mapped(&layout) {
+        Some(ptr) => ptr,
+        None => System.alloc(layout),
+      }
+    }
+
+    // The files are created zeroed, the fallback to RAM is not
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+      match alloc_mapped(&layout) {
+        Some(ptr) => ptr,
+        None => System.alloc_zeroed(layout),
+      }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+      if layout.size() >= DISK_PK_MIN_BYTES && forget(ptr) {
+        libc::munmap(ptr as *mut libc::c_void, layout.size());
+        return;
+      }
+      System.dealloc(ptr, layout)
+    }
+
+    // A mapping moves to a new one, as does an allocation that grows onto the disk
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+      let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+      let mapped = layout.size() >= DISK_PK_MIN_BYTES && is_mapped(ptr);
+      if !mapped && !on_disk(&new_layout) {
+        return System.realloc(ptr, layout, new_size);
+      }
+      let new_ptr = self.alloc(new_layout);
+      if !new_ptr.is_null() {
+        std::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+        self.dealloc(ptr, layout);
+      }
+      new_ptr
+    }
+  }
+}
+
+#[cfg(feature = "disk-pk")]
+pub use mapped::{DiskBackedAlloc, DISK_PK_MIN_BYTES};
+
+// Backs the proving keys with files in dir from now on. Fails if the binary doesn't use
+// DiskBackedAlloc, which would silently keep the key in RAM.
+#[cfg(feature = "disk-pk")]
+pub fn set_disk_pk_dir(dir: &str) -> Result<(), String> {
+  use std::sync::atomic::Ordering;
+
+  if !std::path::Path::new(dir).is_dir() {
+    return Err(format!("{} is not a directory", dir));
+  }
+  let template = std::ffi::CString::new(format!("{}/zkml_pk_XXXXXX", dir))
+    .map_err(|_| format!("{} is not a valid directory name", dir))?;
+  let set = mapped::TEMPLATE.get_or_init(|| template.clone());
+  if *set != template {
+    return Err(format!("the proving keys are already backed by {:?}", set));
+  }
+
+  let was_enabled = mapped::ENABLED.swap(true, Ordering::Relaxed);
+  let probe = std::hint::black_box(Vec::<u8>::with_capacity(DISK_PK_MIN_BYTES));
+  let installed = mapped::is_mapped(probe.as_ptr());
+  drop(probe);
+  mapped::ENABLED.store(was_enabled, Ordering::Relaxed);
+  if !installed {
+    return Err(format!(
+      "couldn't map a file in {}, or the binary doesn't allocate with DiskBackedAlloc",
+      dir
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(not(feature = "disk-pk"))]
+pub fn set_disk_pk_dir(_dir: &str) -> Result<(), String> {
+  Err("the disk-backed proving key needs the disk-pk feature".to_string())
+}
+
+// Runs f, which reads or generates a proving key, with its large allocations backed by files if
+// a directory was set
+#[cfg(feature = "disk-pk")]
+pub fn with_disk_pk<T>(f: impl FnOnce() -> T) -> T {
+  use std::sync::atomic::Ordering;
+
+  if mapped::TEMPLATE.get().is_none() {
+    return f();
+  }
+  let was_enabled = mapped::ENABLED.swap(true, Ordering::Relaxed);
+  let outp = f();
+  mapped::ENABLED.store(was_enabled, Ordering::Relaxed);
+  outp
+}
+
+#[cfg(not(feature = "disk-pk"))]
+pub fn with_disk_pk<T>(f: impl FnOnce() -> T) -> T {
+  f()
+}
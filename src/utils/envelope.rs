@@ -0,0 +1,355 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use halo2_proofs::{
+  halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    ff::PrimeField,
+  },
+  plonk::{verify_proof, VerifyingKey},
+  poly::{
+    commitment::Params,
+    kzg::{
+      commitment::{KZGCommitmentScheme, ParamsKZG},
+      multiopen::VerifierSHPLONK,
+      strategy::SingleStrategy,
+    },
+  },
+  transcript::{Blake2bRead, Challenge255, TranscriptReadBuffer},
+  SerdeFormat,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  commitments::input_hash::num_input_hash_vals,
+  model::ModelCircuit,
+  utils::{
+    artifacts::content_hash,
+    helpers::{instance_columns, instance_slices},
+    loader::{model_to_msgpack, set_defaults, ModelMsgpack},
+    precision::working_sf,
+    rlc::{num_rlc_vals, rlc_position},
+    srs::fit_kzg_params,
+    storage::{read_artifact, write_artifact},
+  },
+};
+
+// A proof envelope bundles everything needed to verify a KZG proof besides the SRS: the circuit
+// layout (the model config without any tensor data), the verification key, the proof, and the
+// public values. The layout is needed to read the verification key. Version 2 added the metadata
+// after the public values; version 1 envelopes are still read.
+pub const ENVELOPE_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProofEnvelope {
+  pub version: u32,
+  pub k: u32,
+  pub layout: Vec<u8>,
+  pub vkey: Vec<u8>,
+  pub proof: Vec<u8>,
+  pub public_vals: Vec<u8>, // 32 bytes per value
+  pub transcript: String,
+  pub created: u64, // Seconds since the epoch
+  pub srs_hash: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ProofEnvelopeV1 {
+  version: u32,
+  k: u32,
+  layout: Vec<u8>,
+  vkey: Vec<u8>,
+  proof: Vec<u8>,
+  public_vals: Vec<u8>,
+}
+
+// The hash of the serialized SRS, which is the hash of the params file
+pub fn srs_hash(params: &ParamsKZG<Bn256>) -> String {
+  let mut buf = vec![];
+  params.write(&mut buf).unwrap();
+  content_hash(&buf)
+}
+
+// The config with the defaults used when proving, and without tensor data
+pub fn circuit_layout(config: &ModelMsgpack) -> ModelMsgpack {
+  let mut layout = config.clone();
+  set_defaults(&mut layout);
+  for tensor in layout.tensors.iter_mut() {
+    tensor.data = vec![];
+  }
+  layout
+}
+
+impl ProofEnvelope {
+  pub fn new(config: &ModelMsgpack, vkey: Vec<u8>, proof: Vec<u8>, public_vals: Vec<u8>) -> Self {
+    Self {
+      version: ENVELOPE_VERSION,
+      k: config.k as u32,
+      layout: model_to_msgpack(&circuit_layout(config)),
+      vkey,
+      proof,
+      public_vals,
+      transcript: "blake2b".to_string(),
+      created: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs(),
+      srs_hash: None,
+    }
+  }
+
+  // Records the SRS the proof was made with, see srs_hash
+  pub fn with_srs_hash(mut self, srs_hash: String) -> Self {
+    self.srs_hash = Some(srs_hash);
+    self
+  }
+
+  pub fn read(path: &str) -> Result<Self, String> {
+    let buf = read_artifact(path)?;
+    let version: u32 =
+      bincode::deserialize(&buf).map_err(|e| format!("malformed envelope: {}", e))?;
+    let malformed = |e: bincode::Error| format!("malformed envelope: {}", e);
+    match version {
+      1 => {
+        let v1: ProofEnvelopeV1 = bincode::deserialize(&buf).map_err(malformed)?;
+        Ok(Self {
+          version: v1.version,
+          k: v1.k,
+          layout: v1.layout,
+          vkey: v1.vkey,
+          proof: v1.proof,
+          public_vals: v1.public_vals,
+          transcript: "blake2b".to_string(),
+          created: 0,
+          srs_hash: None,
+        })
+      }
+      ENVELOPE_VERSION => bincode::deserialize(&buf).map_err(malformed),
+      _ => Err(format!("unsupported envelope version {}", version)),
+    }
+  }
+
+  pub fn write(&self, path: &str) {
+    write_artifact(path, &bincode::serialize(self).unwrap()).unwrap();
+  }
+
+  pub fn public_vals(&self) -> Result<Vec<Fr>, String> {
+    if self.public_vals.len() % 32 != 0 {
+      return Err("malformed public values".to_string());
+    }
+    self
+      .public_vals
+      .chunks(32)
+      .map(|chunk| {
+        Option::from(Fr::from_bytes(chunk.try_into().unwrap()))
+          .ok_or_else(|| "public value is not a field element".to_string())
+      })
+      .collect()
+  }
+
+  // The metadata of the envelope, without verifying it. Commitments are printed as hex and the
+  // outputs as integers and scaled values.
+  pub fn info(&self) -> Result<serde_json::Value, String> {
+    let layout: ModelMsgpack =
+      rmp_serde::from_slice(&self.layout).map_err(|e| format!("malformed layout: {}", e))?;
+    let public_vals = self.public_vals()?;
+    // The rlc values and the input hash come after the commitments, and are printed with them
+    let num_commits = rlc_position(&layout) + num_rlc_vals(&layout) + num_input_hash_vals(&layout);
+    let num_commits = num_commits.min(public_vals.len());
+
+    let commitments = public_vals[..num_commits]
+      .iter()
+      .map(|x| {
+        let bytes = x.to_repr();
+        bytes
+          .as_ref()
+          .iter()
+          .rev()
+          .map(|b| format!("{:02x}", b))
+          .collect::<String>()
+      })
+      .collect::<Vec<_>>();
+    let outputs = public_vals[num_commits..]
+      .iter()
+      .map(|x| {
+        let val = decode_signed(x);
+        serde_json::json!({
+          "int": val.map(|v| v.to_string()),
+          "value": val.map(|v| v as f64 / working_sf(&layout) as f64),
+        })
+      })
+      .collect::<Vec<_>>();
+
+    Ok(serde_json::json!({
+      "version": self.version,
+      "k": self.k,
+      "model_hash": content_hash(&self.layout),
+      "vkey_hash": content_hash(&self.vkey),
+      "srs_hash": self.srs_hash,
+      "transcript": self.transcript,
+      "created": self.created,
+      "sizes": {
+        "layout": self.layout.len(),
+        "vkey": self.vkey.len(),
+        "proof": self.proof.len(),
+        "public_vals": self.public_vals.len(),
+      },
+      "num_layers": layout.layers.len(),
+      "scale_factor": working_sf(&layout),
+      "zero_knowledge": layout.zero_knowledge.unwrap_or(true),
+      "commitments": commitments,
+      "outputs": outputs,
+    }))
+  }
+
+  // The vk of the envelope is the prover's, so it is checked against the hash of the vk the
+  // verifier expects, e.g., from an audit. The params are the verifier's, params_hash is the hash
+  // of their file. The SRS hash of the envelope, if any, must be of the same SRS, either of the
+  // file or of the SRS downsized to k (see serve.rs).
+  pub fn verify(
+    &self,
+    params: &ParamsKZG<Bn256>,
+    params_hash: Option<&str>,
+    trusted_vkey_hash: &str,
+  ) -> VerifyOutcome {
+    if content_hash(&self.vkey) != trusted_vkey_hash {
+      let msg = "the envelope has a different verification key".to_string();
+      return VerifyOutcome::VkMismatch(msg);
+    }
+    let mut params = params.clone();
+    if let Err(e) = fit_kzg_params(&mut params, self.k) {
+      return VerifyOutcome::Malformed(e);
+    }
+    if let Some(envelope_hash) = &self.srs_hash {
+      if params_hash != Some(envelope_hash.as_str()) && srs_hash(&params) != *envelope_hash {
+        return VerifyOutcome::SrsMismatch(format!(
+          "the proof was made with the SRS with hash {}",
+          envelope_hash
+        ));
+      }
+    }
+
+    let layout: ModelMsgpack = match rmp_serde::from_slice(&self.layout) {
+      Ok(layout) => layout,
+      Err(e) => return VerifyOutcome::Malformed(format!("malformed layout: {}", e)),
+    };
+    if layout.k as u32 != self.k {
+      return VerifyOutcome::VkMismatch("the layout does not match the envelope".to_string());
+    }
+    // Sets up the gadget config, which the verification key needs to rebuild the constraints. The
+    // circuit of the layout also splits the public values into the instance columns.
+    let circuit = ModelCircuit::<Fr>::generate_from_msgpack(layout, false);
+    let vk = match VerifyingKey::read::<&[u8], ModelCircuit<Fr>>(
+      &mut &self.vkey[..],
+      SerdeFormat::RawBytes,
+      (),
+    ) {
+      Ok(vk) => vk,
+      // The key does not fit the constraint system of the layout
+      Err(e) => return VerifyOutcome::VkMismatch(format!("could not read the vkey: {}", e)),
+    };
+    let public_vals = match self.public_vals() {
+      Ok(public_vals) => public_vals,
+      Err(e) => return VerifyOutcome::Malformed(e),
+    };
+    let instances = instance_columns(&public_vals, circuit.num_commitments());
+
+    let strategy = SingleStrategy::new(&params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&self.proof[..]);
+    let ok = verify_proof::<
+      KZGCommitmentScheme<Bn256>,
+      VerifierSHPLONK<'_, Bn256>,
+      Challenge255<G1Affine>,
+      Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+      SingleStrategy<'_, Bn256>,
+    >(
+      &params,
+      &vk,
+      strategy,
+      &[&instance_slices(&instances)[..]],
+      &mut transcript,
+    )
+    .is_ok();
+    if ok {
+      VerifyOutcome::Valid
+    } else {
+      VerifyOutcome::Invalid
+    }
+  }
+}
+
+// Field elements within i128 of zero, with the upper half of the field as negative numbers
+pub fn decode_signed(x: &Fr) -> Option<i128> {
+  let small = |x: &Fr| {
+    let bytes = x.to_repr();
+    let bytes = bytes.as_ref();
+    if bytes[16..].iter().any(|b| *b != 0) || bytes[15] >= 0x80 {
+      return None;
+    }
+    Some(i128::from_le_bytes(bytes[..16].try_into().unwrap()))
+  };
+  small(x).or_else(|| small(&-*x).map(|v| -v))
+}
+
+// Each outcome has its own exit code, so scripts can branch on it
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyOutcome {
+  Valid,
+  Invalid,
+  Malformed(String),
+  VkMismatch(String),
+  SrsMismatch(String),
+}
+
+impl VerifyOutcome {
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      VerifyOutcome::Valid => 0,
+      VerifyOutcome::Invalid => 1,
+      VerifyOutcome::Malformed(_) => 2,
+      VerifyOutcome::VkMismatch(_) => 3,
+      VerifyOutcome::SrsMismatch(_) => 5,
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      VerifyOutcome::Valid => "valid",
+      VerifyOutcome::Invalid => "invalid",
+      VerifyOutcome::Malformed(_) => "malformed",
+      VerifyOutcome::VkMismatch(_) => "vk_mismatch",
+      VerifyOutcome::SrsMismatch(_) => "srs_mismatch",
+    }
+  }
+
+  pub fn to_json(&self) -> serde_json::Value {
+    let message = match self {
+      VerifyOutcome::Malformed(msg)
+      | VerifyOutcome::VkMismatch(msg)
+      | VerifyOutcome::SrsMismatch(msg) => Some(msg.clone()),
+      _ => None,
+    };
+    serde_json::json!({
+      "result": self.name(),
+      "valid": *self == VerifyOutcome::Valid,
+      "exit_code": self.exit_code(),
+      "message": message,
+    })
+  }
+
+  // Prints the outcome and exits with its code
+  pub fn report(&self, json: bool) -> ! {
+    if json {
+      println!("{}", self.to_json());
+    } else {
+      match self {
+        VerifyOutcome::Malformed(msg)
+        | VerifyOutcome::VkMismatch(msg)
+        | VerifyOutcome::SrsMismatch(msg) => {
+          println!("{}: {}", self.name(), msg)
+        }
+        _ => println!("{}", self.name()),
+      }
+    }
+    std::process::exit(self.exit_code());
+  }
+}
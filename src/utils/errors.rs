@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fmt};
+
+use halo2_proofs::plonk::Error;
+
+use super::{
+  audit::audit_model,
+  loader::{ModelMsgpack, TensorMsgpack},
+  validate::{checked_len, LoaderLimits},
+};
+
+// The errors of the prover CLI, by what the user has to fix, each with a suggestion. The CLI
+// framework only has a string error, so they reach the user through their Display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+  // The config file, the model, or the input can't be read
+  Loader,
+  // The tensors don't have the shapes the layers expect
+  Shape,
+  // The SRS is missing or too small
+  Srs,
+  // Keygen or proving failed
+  Prover,
+  // The proof or the params bundle doesn't verify
+  Verifier,
+  // A value leaves the range of the field or the lookups
+  Overflow,
+}
+
+impl ErrorKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ErrorKind::Loader => "LoaderError",
+      ErrorKind::Shape => "ShapeError",
+      ErrorKind::Srs => "SrsError",
+      ErrorKind::Prover => "ProverError",
+      ErrorKind::Verifier => "VerifierError",
+      ErrorKind::Overflow => "OverflowError",
+    }
+  }
+
+  fn default_suggestion(&self) -> &'static str {
+    match self {
+      ErrorKind::Loader => "check the paths in the config file and that the model was converted",
+      ErrorKind::Shape => "convert the input with the same converter and options as the model",
+      ErrorKind::Srs => "generate a larger SRS with `srs gen` or point the config at one",
+      ErrorKind::Prover => "run test_circuit on the model to find the failing constraint",
+      ErrorKind::Verifier => "verify with the vk and params bundle written by the same prove",
+      ErrorKind::Overflow => "lower the scale factor or raise k, audit_model prints the bounds",
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct ZkmlError {
+  pub kind: ErrorKind,
+  pub message: String,
+  pub suggestion: String,
+}
+
+impl ZkmlError {
+  pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+    Self {
+      kind,
+      message: message.into(),
+      suggestion: kind.default_suggestion().to_string(),
+    }
+  }
+
+  pub fn with_suggestion(self, suggestion: impl Into<String>) -> Self {
+    Self {
+      suggestion: suggestion.into(),
+      ..self
+    }
+  }
+
+  pub fn loader(message: impl Into<String>) -> Self {
+    Self::new(ErrorKind::Loader, message)
+  }
+
+  pub fn srs(message: impl Into<String>) -> Self {
+    Self::new(ErrorKind::Srs, message)
+  }
+
+  pub fn verifier(message: impl Into<String>) -> Self {
+    Self::new(ErrorKind::Verifier, message)
+  }
+
+  // The layers can't compute the witness of the input (see ModelCircuit::compute_public_values)
+  pub fn public_values(message: impl Into<String>) -> Self {
+    Self::new(ErrorKind::Prover, message).with_suggestion(
+      "the input leaves the range of a layer, run test_circuit or audit_model on it",
+    )
+  }
+
+  // A halo2 error of keygen or proving, with the fix the error variant points at
+  pub fn prover(context: &str, e: &Error) -> Self {
+    let err = Self::new(ErrorKind::Prover, format!("{} failed: {}", context, e));
+    match e {
+      Error::NotEnoughRowsAvailable { current_k } => err.with_suggestion(format!(
+        "the circuit doesn't fit in 2^{} rows, raise k in the config file",
+        current_k
+      )),
+      Error::InstanceTooLarge => err.with_suggestion(
+        "there are more public values than rows, raise k or commit to the outputs",
+      ),
+      Error::NotEnoughColumnsForConstants => {
+        err.with_suggestion("the model needs more columns, raise num_cols in the converter")
+      }
+      Error::Synthesis => err.with_suggestion(
+        "a layer failed to assign its witness, run test_circuit for the failing layer",
+      ),
+      _ => err,
+    }
+  }
+}
+
+impl fmt::Display for ZkmlError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}: {}\n  suggestion: {}",
+      self.kind.name(),
+      self.message,
+      self.suggestion
+    )
+  }
+}
+
+impl std::error::Error for ZkmlError {}
+
+// Every input has as many values as its shape, and as the layers that read it expect. Checked
+// before the inputs are added to the config, which the loader then checks as a whole.
+pub fn check_input_shapes(config: &ModelMsgpack, inp: &[TensorMsgpack]) -> Result<(), ZkmlError> {
+  let shape_error = |message: String| ZkmlError::new(ErrorKind::Shape, message);
+  let limits = LoaderLimits::default();
+  for idx in config.inp_idxes.iter() {
+    let tensor = inp
+      .iter()
+      .find(|tensor| tensor.idx == *idx)
+      .ok_or_else(|| {
+        shape_error(format!("input {} is missing", idx))
+          .with_suggestion("pass the input file the model was converted with")
+      })?;
+    let len = checked_len(&tensor.shape, &limits).map_err(shape_error)?;
+    if tensor.data.len() as u64 != len {
+      return Err(shape_error(format!(
+        "input {} has {} values for shape {:?}",
+        idx,
+        tensor.data.len(),
+        tensor.shape
+      )));
+    }
+    for (pos, layer) in config.layers.iter().enumerate() {
+      for (inp_idx, inp_shape) in layer.inp_idxes.iter().zip(layer.inp_shapes.iter()) {
+        if inp_idx == idx && checked_len(inp_shape, &limits).ok() != Some(len) {
+          return Err(shape_error(format!(
+            "input {} has shape {:?}, layer {} ({}) expects {:?}",
+            idx, tensor.shape, pos, layer.layer_type, inp_shape
+          )));
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+// Names the first layer the audit can't bound within the field or the lookups, with the bounds of
+// the inputs at their actual values. Proofs that overflow fail verification, not proving.
+pub fn find_overflow(model: &ModelMsgpack) -> Option<ZkmlError> {
+  let inp_bounds = model
+    .tensors
+    .iter()
+    .filter(|tensor| model.inp_idxes.contains(&tensor.idx))
+    .map(|tensor| {
+      let max_abs = tensor.data.iter().map(|x| x.unsigned_abs()).max();
+      (tensor.idx, max_abs.unwrap_or(0) as f64)
+    })
+    .collect::<HashMap<_, _>>();
+  let report = audit_model(model, &inp_bounds, 0.);
+  report.layers.iter().find_map(|layer| {
+    let check = layer.checks.iter().find(|check| !check.ok)?;
+    Some(ZkmlError::new(
+      ErrorKind::Overflow,
+      format!(
+        "layer {} ({}) may exceed the {} range: bound 2^{:.1}, limit 2^{:.1}",
+        layer.layer_idx,
+        layer.layer_type,
+        check.name,
+        check.bound.log2(),
+        check.limit.log2()
+      ),
+    ))
+  })
+}
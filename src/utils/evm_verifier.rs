@@ -0,0 +1,127 @@
+use std::rc::Rc;
+
+use halo2_proofs::{
+  halo2curves::bn256::{Bn256, Fq, Fr, G1Affine},
+  plonk::{create_proof, verify_proof, ProvingKey, VerifyingKey},
+  poly::{
+    commitment::{Params, ParamsProver},
+    kzg::{
+      commitment::{KZGCommitmentScheme, ParamsKZG},
+      multiopen::{ProverSHPLONK, VerifierSHPLONK},
+      strategy::SingleStrategy,
+    },
+  },
+  transcript::{TranscriptReadBuffer, TranscriptWriterBuffer},
+};
+use rand::RngCore;
+use snark_verifier::{
+  loader::evm::{encode_calldata, EvmLoader},
+  pcs::kzg::{Bdfg21, KzgAs, KzgDecidingKey},
+  system::halo2::{compile, transcript::evm::EvmTranscript, Config},
+  verifier::{self, SnarkVerifier},
+};
+
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    errors::ZkmlError,
+    helpers::{instance_columns, instance_slices},
+  },
+};
+
+// Proofs for on-chain verification. The EVM transcript hashes with Keccak256, which the EVM has a
+// cheap opcode for, instead of Blake2b. The verifier contract is generated by snark-verifier for
+// the SHPLONK proofs the prover makes, and is specific to the vk, the SRS, and the number of
+// public values of each instance column. It is emitted as Yul, which solc compiles to the
+// deployment bytecode with `solc --yul`.
+// The calldata is the encoded public values followed by the proof, as the verifier reads them.
+type PlonkVerifier = verifier::plonk::PlonkVerifier<KzgAs<Bn256, Bdfg21>>;
+
+pub fn prove_evm<R: RngCore>(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  circuit: ModelCircuit<Fr>,
+  rng: R,
+) -> Result<(Vec<u8>, Vec<Fr>), ZkmlError> {
+  let public_vals = circuit
+    .compute_public_values()
+    .map_err(ZkmlError::public_values)?;
+  let instances = instance_columns(&public_vals, circuit.num_commitments());
+  let mut transcript = TranscriptWriterBuffer::<_, G1Affine, _>::init(vec![]);
+  create_proof::<
+    KZGCommitmentScheme<Bn256>,
+    ProverSHPLONK<'_, Bn256>,
+    _,
+    _,
+    EvmTranscript<_, _, _, _>,
+    ModelCircuit<Fr>,
+  >(
+    params,
+    pk,
+    &[circuit],
+    &[&instance_slices(&instances)[..]],
+    rng,
+    &mut transcript,
+  )
+  .map_err(|e| ZkmlError::prover("proving", &e))?;
+  Ok((transcript.finalize(), public_vals))
+}
+
+// Verifies an EVM proof natively, with the number of commitments of the verified circuit
+pub fn check_evm(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  public_vals: &[Fr],
+  num_commitments: usize,
+  proof: &[u8],
+) -> bool {
+  let instances = instance_columns(public_vals, num_commitments);
+  let mut transcript = TranscriptReadBuffer::<_, G1Affine, _>::init(proof);
+  verify_proof::<
+    KZGCommitmentScheme<Bn256>,
+    VerifierSHPLONK<'_, Bn256>,
+    _,
+    EvmTranscript<_, _, _, _>,
+    SingleStrategy<'_, Bn256>,
+  >(
+    params.verifier_params(),
+    vk,
+    SingleStrategy::new(params),
+    &[&instance_slices(&instances)[..]],
+    &mut transcript,
+  )
+  .is_ok()
+}
+
+// The Yul source of the verifier contract, for proofs with num_public_vals public values, of
+// which num_commitments are commitments
+pub fn gen_evm_verifier(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  num_public_vals: usize,
+  num_commitments: usize,
+) -> String {
+  let num_instance = instance_columns(&vec![Fr::from(0); num_public_vals], num_commitments)
+    .iter()
+    .map(|col| col.len())
+    .collect::<Vec<_>>();
+  let protocol = compile(
+    params,
+    vk,
+    Config::kzg().with_num_instance(num_instance.clone()),
+  );
+  let dk: KzgDecidingKey<Bn256> = (params.get_g()[0], params.g2(), params.s_g2()).into();
+
+  // Running the verifier on the EVM loader records the verification as Yul
+  let loader = EvmLoader::new::<Fq, Fr>();
+  let protocol = protocol.loaded(&loader);
+  let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+  let instances = transcript.load_instances(num_instance);
+  let proof = PlonkVerifier::read_proof(&dk, &protocol, &instances, &mut transcript).unwrap();
+  PlonkVerifier::verify(&dk, &protocol, &instances, &proof).unwrap();
+  loader.yul_code()
+}
+
+pub fn evm_calldata(public_vals: &[Fr], num_commitments: usize, proof: &[u8]) -> Vec<u8> {
+  encode_calldata(&instance_columns(public_vals, num_commitments), proof)
+}
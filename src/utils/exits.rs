@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+
+use super::loader::ModelMsgpack;
+
+// Early-exit networks have several heads, and an input that a cheap early head is confident about
+// doesn't need the rest of the network. The config lists the output tensors of every exit, in
+// order, and the exit to prove with; the circuit then only has the layers the outputs of that exit
+// depend on, and reveals the exit index as the first output.
+// The exit is fixed at prove time, since it determines the circuit, so every exit has its own keys.
+// The index is a fixed cell, so the vk also binds it. The weights of the dropped layers are kept,
+// so that the weight commitment is the same for every exit.
+
+// Makes the outputs of the chosen exit the model outputs and drops the layers they don't depend on.
+// Returns the positions of the dropped layers.
+pub fn select_exit(model: &mut ModelMsgpack) -> Result<Vec<usize>, String> {
+  let exits = model.exits.clone().unwrap_or(vec![]);
+  let exit = model.exit.ok_or("no exit is chosen")?;
+  let outputs = exits
+    .get(exit as usize)
+    .filter(|_| exit >= 0)
+    .ok_or_else(|| {
+      format!(
+        "exit {} out of range, the model has {} exits",
+        exit,
+        exits.len()
+      )
+    })?
+    .clone();
+
+  // Walks back from the outputs of the exit
+  let mut needed = outputs.iter().cloned().collect::<HashSet<_>>();
+  let mut keep = vec![false; model.layers.len()];
+  for (i, layer) in model.layers.iter().enumerate().rev() {
+    if layer.out_idxes.iter().any(|idx| needed.contains(idx)) {
+      keep[i] = true;
+      needed.extend(layer.inp_idxes.iter().cloned());
+    }
+  }
+  for idx in outputs.iter() {
+    let produced = model
+      .layers
+      .iter()
+      .any(|layer| layer.out_idxes.contains(idx));
+    if !produced {
+      return Err(format!(
+        "output {} of exit {} is not computed by a layer",
+        idx, exit
+      ));
+    }
+  }
+
+  let dropped = (0..model.layers.len())
+    .filter(|i| !keep[*i])
+    .collect::<Vec<_>>();
+  let dropped_outs = dropped
+    .iter()
+    .flat_map(|i| model.layers[*i].out_idxes.iter().cloned())
+    .collect::<HashSet<_>>();
+  for group in model.commit_after.iter().flatten() {
+    if let Some(idx) = group.iter().find(|idx| dropped_outs.contains(idx)) {
+      return Err(format!(
+        "tensor {} is committed to but not computed for exit {}",
+        idx, exit
+      ));
+    }
+  }
+
+  let mut i = 0;
+  model.layers.retain(|_| {
+    i += 1;
+    keep[i - 1]
+  });
+  model.out_idxes = outputs;
+  Ok(dropped)
+}
@@ -0,0 +1,250 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use serde_derive::{Deserialize, Serialize};
+
+// Floats in the witness path. The importers quantize every float to fixed point, and from there
+// on, loading the model and generating the witness must be integer only: a float computed there
+// can round differently on another target or with another libm, so the prover's witness and
+// public values stop matching the ones everyone else computes. This scans the sources of that
+// path for float types, float literals, and float only methods, skipping comments and strings.
+// A use that is known to be harmless is allowed with a `float-ok: <reason>` comment on its line
+// or the line above.
+
+// The sources from the quantized model to the witness, relative to the crate root. The importers
+// (onnx.rs and model/tflite.rs) and the reports (audit, scales) are before or outside of it.
+pub const WITNESS_PATH: &[&str] = &[
+  "src/model.rs",
+  "src/layers",
+  "src/gadgets",
+  "src/commitments",
+  "src/utils/helpers.rs",
+  "src/utils/loader.rs",
+  "src/utils/tensor.rs",
+  "src/utils/witness.rs",
+];
+
+// The lookup tables of the nonlinearities are computed with floats, but they are fixed columns,
+// so they are in the keys: a target that rounds an entry differently can't prove with the keys of
+// another, rather than proving a different witness
+const ALLOWED_PATHS: &[&str] = &["src/gadgets/nonlinear"];
+
+// Methods of f32 and f64 that the integers don't have
+const FLOAT_METHODS: &[&str] = &[
+  "acos", "asin", "atan", "atan2", "cbrt", "ceil", "copysign", "cos", "cosh", "exp", "exp2",
+  "exp_m1", "floor", "fract", "hypot", "ln", "ln_1p", "log", "log10", "log2", "mul_add", "powf",
+  "powi", "recip", "round", "sin", "sinh", "sqrt", "tan", "tanh", "trunc",
+];
+
+const INT_SUFFIXES: &[&str] = &[
+  "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FloatUse {
+  pub path: String,
+  pub line: usize,
+  pub kinds: Vec<String>,
+  pub text: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FloatLintReport {
+  pub files: usize,
+  pub findings: Vec<FloatUse>,
+  pub allowed: Vec<FloatUse>,
+}
+
+// Blanks out the comments and the contents of the string and char literals, keeping the columns,
+// and returns whether the line ends inside a block comment
+fn strip_line(line: &str, mut in_comment: bool) -> (String, bool) {
+  let chars = line.chars().collect::<Vec<_>>();
+  let mut out = String::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let next = chars.get(i + 1).cloned();
+    if in_comment {
+      if chars[i] == '*' && next == Some('/') {
+        in_comment = false;
+        out.push_str("  ");
+        i += 2;
+      } else {
+        out.push(' ');
+        i += 1;
+      }
+      continue;
+    }
+    match chars[i] {
+      '/' if next == Some('/') => break,
+      '/' if next == Some('*') => {
+        in_comment = true;
+        out.push_str("  ");
+        i += 2;
+      }
+      '"' => {
+        out.push('"');
+        i += 1;
+        while i < chars.len() && chars[i] != '"' {
+          i += if chars[i] == '\\' { 2 } else { 1 };
+          out.push(' ');
+        }
+        out.push('"');
+        i += 1;
+      }
+      // A char literal, not a lifetime
+      '\'' if chars.get(i + 2) == Some(&'\'') || next == Some('\\') => {
+        out.push_str("' '");
+        i += 1;
+        while i < chars.len() && chars[i] != '\'' {
+          i += if chars[i] == '\\' { 2 } else { 1 };
+        }
+        i += 1;
+      }
+      c => {
+        out.push(c);
+        i += 1;
+      }
+    }
+  }
+  (out, in_comment)
+}
+
+fn is_ident(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_'
+}
+
+// The float uses of a stripped line
+fn float_kinds(code: &str) -> BTreeSet<String> {
+  let chars = code.chars().collect::<Vec<_>>();
+  let mut kinds = BTreeSet::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let prev = if i > 0 { Some(chars[i - 1]) } else { None };
+    if c.is_ascii_alphabetic() || c == '_' {
+      let start = i;
+      while i < chars.len() && is_ident(chars[i]) {
+        i += 1;
+      }
+      let ident = chars[start..i].iter().collect::<String>();
+      if ident == "f32" || ident == "f64" {
+        kinds.insert(ident);
+      } else if prev == Some('.')
+        && chars.get(i) == Some(&'(')
+        && FLOAT_METHODS.contains(&ident.as_str())
+      {
+        kinds.insert(format!("{}()", ident));
+      }
+      continue;
+    }
+    if !c.is_ascii_digit() {
+      i += 1;
+      continue;
+    }
+
+    // A number: tuple fields (x.0) and prefixed integers (0x1e) are not floats
+    let start = i;
+    while i < chars.len() && is_ident(chars[i]) {
+      i += 1;
+    }
+    let digits = chars[start..i].iter().collect::<String>();
+    if prev == Some('.') {
+      continue;
+    }
+    let prefixed = ["0x", "0o", "0b"].iter().any(|p| digits.starts_with(p));
+    // The exponent of 1e5, but not the e of 1usize
+    let unsuffixed = INT_SUFFIXES
+      .iter()
+      .find_map(|suffix| digits.strip_suffix(suffix))
+      .unwrap_or(&digits);
+    let mut float = !prefixed
+      && (digits.ends_with("f32")
+        || digits.ends_with("f64")
+        || unsuffixed.contains(|c: char| c == 'e' || c == 'E'));
+    // 1.5, and 1. but not 1..n or 1.max(2)
+    if !prefixed && chars.get(i) == Some(&'.') {
+      match chars.get(i + 1) {
+        Some(d) if d.is_ascii_digit() => {
+          float = true;
+          i += 1;
+          while i < chars.len() && (is_ident(chars[i]) || chars[i] == '.') {
+            i += 1;
+          }
+        }
+        Some(d) if *d == '.' || is_ident(*d) => {}
+        _ => float = true,
+      }
+    }
+    if float {
+      kinds.insert("float literal".to_string());
+    }
+  }
+  kinds
+}
+
+// The (disallowed, allowed) float uses of a source file
+pub fn lint_source(path: &str, source: &str) -> (Vec<FloatUse>, Vec<FloatUse>) {
+  let (mut findings, mut allowed) = (vec![], vec![]);
+  let file_ok = ALLOWED_PATHS.iter().any(|x| path.starts_with(x));
+  let lines = source.lines().collect::<Vec<_>>();
+  let mut in_comment = false;
+  for (i, line) in lines.iter().enumerate() {
+    let (code, still_in_comment) = strip_line(line, in_comment);
+    in_comment = still_in_comment;
+    let kinds = float_kinds(&code);
+    if kinds.is_empty() {
+      continue;
+    }
+    let float_use = FloatUse {
+      path: path.to_string(),
+      line: i + 1,
+      kinds: kinds.into_iter().collect(),
+      text: line.trim().to_string(),
+    };
+    let marked = line.contains("float-ok:") || (i > 0 && lines[i - 1].contains("float-ok:"));
+    if file_ok || marked {
+      allowed.push(float_use);
+    } else {
+      findings.push(float_use);
+    }
+  }
+  (findings, allowed)
+}
+
+fn rust_files(path: &Path, files: &mut Vec<String>) -> Result<(), String> {
+  if path.is_file() {
+    if path.extension().map_or(false, |ext| ext == "rs") {
+      files.push(path.to_string_lossy().to_string());
+    }
+    return Ok(());
+  }
+  let entries = fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  let mut paths = entries
+    .map(|entry| entry.map(|x| x.path()))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+  paths.sort();
+  for path in paths.iter() {
+    rust_files(path, files)?;
+  }
+  Ok(())
+}
+
+// Lints the witness path of the crate at root
+pub fn lint_floats(root: &str) -> Result<FloatLintReport, String> {
+  let mut files = vec![];
+  for path in WITNESS_PATH.iter() {
+    rust_files(&Path::new(root).join(path), &mut files)?;
+  }
+  let mut report = FloatLintReport::default();
+  for file in files.iter() {
+    let source = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+    let rel = Path::new(file)
+      .strip_prefix(root)
+      .map_or(file.clone(), |x| x.to_string_lossy().to_string());
+    let (findings, allowed) = lint_source(&rel, &source);
+    report.files += 1;
+    report.findings.extend(findings);
+    report.allowed.extend(allowed);
+  }
+  Ok(report)
+}
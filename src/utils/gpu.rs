@@ -0,0 +1,30 @@
+// The MSMs and FFTs of the prover dominate the proving time of large models. halo2_proofs has no
+// hook for them, so the gpu feature builds against a halo2_proofs with the icicle_gpu feature,
+// which runs them on a CUDA device through icicle. The fork is patched in over the halo2 git
+// source of Cargo.toml, e.g., in .cargo/config.toml:
+//   [patch."https://github.com/privacy-scaling-explorations/halo2"]
+//   halo2_proofs = { git = "<the icicle fork>", rev = "<a rev on 17e9765>" }
+// icicle only has CUDA backends for BN254, so IPA proofs and machines without a CUDA device stay
+// on the CPU.
+
+// The fork takes the GPU path only while this is set
+pub const ICICLE_ENV: &str = "ENABLE_ICICLE_GPU";
+// Set to 0 to prove on the CPU with a GPU build
+pub const GPU_ENV: &str = "ZKML_GPU";
+
+// Routes the KZG MSMs and FFTs of the process to the GPU, unless ZKML_GPU=0. Returns whether they
+// go to the GPU.
+#[cfg(feature = "gpu")]
+pub fn init_gpu() -> bool {
+  if std::env::var(GPU_ENV).map_or(false, |v| v == "0") {
+    std::env::remove_var(ICICLE_ENV);
+    return false;
+  }
+  std::env::set_var(ICICLE_ENV, "1");
+  true
+}
+
+#[cfg(not(feature = "gpu"))]
+pub fn init_gpu() -> bool {
+  false
+}
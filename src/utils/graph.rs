@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::utils::loader::ModelMsgpack;
+
+// Exports the layer DAG as a Graphviz DOT graph. Layers are boxes, the inputs and weights are
+// ellipses and notes, and every edge is labeled with the tensor index, its shape, and its scale.
+// Every tensor is at the global scale factor, since the layers rescale their outputs.
+
+fn shape_str(shape: &Vec<i64>) -> String {
+  let dims = shape.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+  format!("[{}]", dims.join(", "))
+}
+
+fn escape(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn model_to_dot(model: &ModelMsgpack) -> String {
+  let mut lines = vec![
+    "digraph model {".to_string(),
+    "  rankdir=TB;".to_string(),
+    format!(
+      "  label=\"k = {}, {} columns, scale factor {}\";",
+      model.k, model.num_cols, model.global_sf
+    ),
+    "  node [fontname=\"monospace\"];".to_string(),
+  ];
+
+  // The node that produces each tensor, and its shape
+  let mut producers: HashMap<i64, (String, Vec<i64>)> = HashMap::new();
+  for tensor in model.tensors.iter() {
+    let is_inp = model.inp_idxes.contains(&tensor.idx);
+    let node = if is_inp {
+      format!("inp{}", tensor.idx)
+    } else {
+      format!("w{}", tensor.idx)
+    };
+    producers.insert(tensor.idx, (node, tensor.shape.clone()));
+  }
+  for (i, layer) in model.layers.iter().enumerate() {
+    for (idx, shape) in layer.out_idxes.iter().zip(layer.out_shapes.iter()) {
+      producers.insert(*idx, (format!("l{}", i), shape.clone()));
+    }
+  }
+
+  for idx in model.inp_idxes.iter() {
+    let shape = producers
+      .get(idx)
+      .map_or("?".to_string(), |(_, shape)| shape_str(shape));
+    lines.push(format!(
+      "  inp{} [shape=ellipse, label=\"input {}\\n{}\"];",
+      idx, idx, shape
+    ));
+  }
+  // Only the weights that are used, since the config can contain unused tensors
+  let used = model
+    .layers
+    .iter()
+    .flat_map(|layer| layer.inp_idxes.iter())
+    .collect::<Vec<_>>();
+  for tensor in model.tensors.iter() {
+    if model.inp_idxes.contains(&tensor.idx) || !used.contains(&&tensor.idx) {
+      continue;
+    }
+    lines.push(format!(
+      "  w{} [shape=note, label=\"weight {}\\n{}\"];",
+      tensor.idx,
+      tensor.idx,
+      shape_str(&tensor.shape)
+    ));
+  }
+
+  for (i, layer) in model.layers.iter().enumerate() {
+    let params = if layer.params.is_empty() {
+      String::new()
+    } else {
+      let params = layer
+        .params
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+      format!("\\nparams: {}", params.join(", "))
+    };
+    lines.push(format!(
+      "  l{} [shape=box, label=\"{}: {}{}\"];",
+      i,
+      i,
+      escape(&layer.layer_type),
+      params
+    ));
+    for idx in layer.inp_idxes.iter() {
+      let (src, shape) = match producers.get(idx) {
+        Some((src, shape)) => (src.clone(), shape.clone()),
+        None => {
+          // Dangling inputs show up as red nodes, which usually means a conversion bug
+          lines.push(format!(
+            "  missing{} [shape=ellipse, color=red, label=\"missing {}\"];",
+            idx, idx
+          ));
+          (format!("missing{}", idx), vec![])
+        }
+      };
+      lines.push(format!(
+        "  {} -> l{} [label=\"t{} {}\\nsf {}\"];",
+        src,
+        i,
+        idx,
+        shape_str(&shape),
+        model.global_sf
+      ));
+    }
+  }
+
+  for (i, idx) in model.out_idxes.iter().enumerate() {
+    lines.push(format!(
+      "  out{} [shape=ellipse, style=bold, label=\"output {}\"];",
+      i, i
+    ));
+    if let Some((src, shape)) = producers.get(idx) {
+      lines.push(format!(
+        "  {} -> out{} [label=\"t{} {}\\nsf {}\"];",
+        src,
+        i,
+        idx,
+        shape_str(shape),
+        model.global_sf
+      ));
+    }
+  }
+
+  lines.push("}".to_string());
+  lines.join("\n") + "\n"
+}
@@ -0,0 +1,47 @@
+use super::loader::{LayerMsgpack, ModelMsgpack};
+
+// Softmax is monotone, so a classifier that only needs the argmax or a threshold on the scores
+// can output the logits instead, which saves the exp lookups and the division of the softmax.
+// A softmax is only dropped if its output is a model output that no other layer uses and that is
+// not committed to, since anything else needs the probabilities.
+
+// Replaces the softmaxes that produce the model outputs with a Noop, and returns their positions
+pub fn drop_final_softmax(model: &mut ModelMsgpack) -> Result<Vec<usize>, String> {
+  let committed = model.commit_after.clone().unwrap_or(vec![]).concat();
+  let mut dropped = vec![];
+  for (i, layer) in model.layers.iter().enumerate() {
+    let is_output = layer.out_idxes.iter().any(|x| model.out_idxes.contains(x));
+    if layer.layer_type != "Softmax" || !is_output {
+      continue;
+    }
+    for out in layer.out_idxes.iter() {
+      if let Some(consumer) = model.layers.iter().position(|l| l.inp_idxes.contains(out)) {
+        return Err(format!(
+          "the softmax at layer {} is not final, its output is used by layer {} ({})",
+          i, consumer, model.layers[consumer].layer_type
+        ));
+      }
+      if committed.contains(out) {
+        return Err(format!(
+          "the softmax at layer {} is not final, its output is committed to",
+          i
+        ));
+      }
+    }
+    dropped.push(i);
+  }
+  if dropped.is_empty() {
+    return Err("no model output is produced by a softmax".to_string());
+  }
+
+  for i in dropped.iter() {
+    let layer = &model.layers[*i];
+    model.layers[*i] = LayerMsgpack {
+      layer_type: "Noop".to_string(),
+      params: vec![0],
+      mask: vec![],
+      ..layer.clone()
+    };
+  }
+  Ok(dropped)
+}
@@ -4,6 +4,7 @@ use halo2_proofs::{
 };
 use ndarray::{Array, IxDyn};
 use num_bigint::BigUint;
+use rand::{rngs::StdRng, SeedableRng};
 
 use crate::{gadgets::gadget::convert_to_u128, model::PUBLIC_VALS};
 
@@ -34,6 +35,7 @@ pub fn convert_pos_int<F: PrimeField>(x: Value<F>) -> i128 {
 
 pub fn print_pos_int<F: PrimeField>(prefix: &str, x: Value<F>, scale_factor: u64) {
   let tmp = convert_pos_int(x);
+  // float-ok: only logged
   let tmp_float = tmp as f64 / scale_factor as f64;
   info!("{} x: {} ({})", prefix, tmp, tmp_float);
 }
@@ -52,6 +54,20 @@ pub fn print_assigned_arr<F: PrimeField>(
   }
 }
 
+// The randomness of create_proof, which fills the blinding rows and the blinding factors of the
+// commitments and so hides the witness. Without zero knowledge, e.g. when the inputs and weights
+// are public anyway, it is a fixed seed instead, which makes the proofs reproducible but leaks
+// information about the witness. This only controls the randomness, not the blinding rows: the
+// number of blinding rows is fixed by the constraint system at this version of halo2, so proving
+// is not faster. Every prover proves with it; the SRS setup must not.
+pub fn prover_rng(zero_knowledge: bool) -> StdRng {
+  if zero_knowledge {
+    StdRng::from_entropy()
+  } else {
+    StdRng::seed_from_u64(0)
+  }
+}
+
 // Get the public values
 pub fn get_public_values<F: PrimeField>() -> Vec<F> {
   let mut public_vals = vec![];
@@ -62,6 +78,26 @@ pub fn get_public_values<F: PrimeField>() -> Vec<F> {
   public_vals
 }
 
+// The instance columns: the commitments (before, then after, then the rlc values), then the
+// outputs. The public values are stored and serialized flat, in the same order.
+pub const COMMITMENT_COL: usize = 0;
+pub const OUTPUT_COL: usize = 1;
+pub const NUM_INSTANCE_COLS: usize = 2;
+
+// Splits the flat public values into the instance columns, with the number of commitments of the
+// circuit that is proven or verified (see ModelCircuit::num_commitments)
+pub fn instance_columns<F: Clone>(public_vals: &[F], num_commitments: usize) -> Vec<Vec<F>> {
+  let num_commits = num_commitments.min(public_vals.len());
+  vec![
+    public_vals[..num_commits].to_vec(),
+    public_vals[num_commits..].to_vec(),
+  ]
+}
+
+pub fn instance_slices<F>(columns: &Vec<Vec<F>>) -> Vec<&[F]> {
+  columns.iter().map(|col| &col[..]).collect()
+}
+
 // Broadcast
 fn shape_dominates(s1: &[usize], s2: &[usize]) -> bool {
   if s1.len() != s2.len() {
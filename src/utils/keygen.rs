@@ -0,0 +1,93 @@
+use std::time::Instant;
+
+use halo2_proofs::{
+  halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    pasta::{EqAffine, Fp},
+  },
+  plonk::{keygen_pk, keygen_vk, Error, ProvingKey},
+  poly::{ipa::commitment::ParamsIPA, kzg::commitment::ParamsKZG},
+};
+
+use crate::{
+  model::ModelCircuit,
+  utils::{cancel::record_stage, cpu::default_threads, disk_pk::with_disk_pk},
+};
+
+// Keygen spends most of its time in halo2 committing to the fixed columns and building the
+// permutation polynomials, which run on the rayon pool. With the parallel-keygen feature, the pool
+// is sized before the first keygen, to ZKML_KEYGEN_THREADS threads or one per performance core
+// (see cpu.rs). The pool is rayon's global pool, so proving then runs on it as well. The circuit
+// synthesis inside keygen is single threaded since the gadget config is global.
+pub const KEYGEN_THREADS_VAR: &str = "ZKML_KEYGEN_THREADS";
+
+pub fn keygen_threads() -> usize {
+  std::env::var(KEYGEN_THREADS_VAR)
+    .ok()
+    .and_then(|x| x.parse().ok())
+    .unwrap_or_else(default_threads)
+}
+
+// The size of rayon's global pool, which halo2 proves on: the keygen pool, or else rayon's default
+pub fn proving_threads() -> usize {
+  if cfg!(feature = "parallel-keygen") {
+    return keygen_threads();
+  }
+  std::env::var("RAYON_NUM_THREADS")
+    .ok()
+    .and_then(|x| x.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+#[cfg(feature = "parallel-keygen")]
+pub fn init_keygen_threads() {
+  static INIT: std::sync::Once = std::sync::Once::new();
+  INIT.call_once(|| {
+    let num_threads = keygen_threads();
+    // Fails if the global pool is already running, which then keeps its size
+    match rayon::ThreadPoolBuilder::new()
+      .num_threads(num_threads)
+      .build_global()
+    {
+      Ok(()) => info!("keygen uses {} threads", num_threads),
+      Err(e) => info!("couldn't resize the thread pool for keygen: {}", e),
+    }
+  });
+}
+
+#[cfg(not(feature = "parallel-keygen"))]
+pub fn init_keygen_threads() {}
+
+// Generates the keys, and records the vkey and pkey stages for the metrics
+pub fn keygen_kzg(
+  params: &ParamsKZG<Bn256>,
+  circuit: &ModelCircuit<Fr>,
+) -> Result<ProvingKey<G1Affine>, Error> {
+  init_keygen_threads();
+
+  let start = Instant::now();
+  let vk = keygen_vk(params, circuit)?;
+  record_stage("vkey", start.elapsed());
+
+  let start = Instant::now();
+  let pk = with_disk_pk(|| keygen_pk(params, vk, circuit))?;
+  record_stage("pkey", start.elapsed());
+  Ok(pk)
+}
+
+pub fn keygen_ipa(
+  params: &ParamsIPA<EqAffine>,
+  circuit: &ModelCircuit<Fp>,
+) -> Result<ProvingKey<EqAffine>, Error> {
+  init_keygen_threads();
+
+  let start = Instant::now();
+  let vk = keygen_vk(params, circuit)?;
+  record_stage("vkey", start.elapsed());
+
+  let start = Instant::now();
+  let pk = with_disk_pk(|| keygen_pk(params, vk, circuit))?;
+  record_stage("pkey", start.elapsed());
+  Ok(pk)
+}
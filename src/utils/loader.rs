@@ -1,12 +1,24 @@
-use std::{fs::File, io::BufReader};
-
 use serde_derive::{Deserialize, Serialize};
 
+use crate::{
+  commitments::model_commit::commit_weights,
+  utils::{
+    coprocessor::commit_coprocessor_layers,
+    storage::{read_artifact, write_artifact},
+    validate::{check_file_size, validate_model, LoaderLimits},
+  },
+};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TensorMsgpack {
   pub idx: i64,
   pub shape: Vec<i64>,
   pub data: Vec<i64>,
+  // Inputs can declare an integer dtype (int8, int32, or int64), for pipelines that are already
+  // quantized. The values are used as is, without a float to fixed point conversion, and must fit
+  // the dtype.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub dtype: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -20,7 +32,19 @@ pub struct LayerMsgpack {
   pub mask: Vec<i64>,
 }
 
+// A conditional subgraph: the outputs are the then outputs if the condition is positive, and the
+// else outputs otherwise (see branches.rs)
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BranchMsgpack {
+  pub cond: i64,
+  pub then_outs: Vec<i64>,
+  pub else_outs: Vec<i64>,
+  pub out_idxes: Vec<i64>,
+  // Whether the then branch is taken, set at prove time
+  pub taken: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ModelMsgpack {
   pub global_sf: i64,
   pub k: i64,
@@ -34,28 +58,154 @@ pub struct ModelMsgpack {
   pub commit_after: Option<Vec<Vec<i64>>>,
   pub bits_per_elem: Option<i64>, // Specifically for packing for the commitments
   pub num_random: Option<i64>,
+  // Approximates every softmax by the softmax over its k largest inputs
+  pub softmax_top_k: Option<i64>,
+  // Public input tensors that are bound by a random linear combination instead of the instance
+  // (see rlc.rs)
+  pub rlc_inputs: Option<Vec<i64>>,
+  // Outputs the logits instead of the final softmax, for argmax or thresholds (see head.rs)
+  pub drop_final_softmax: Option<bool>,
+  // Advanced: the rows per tile of the matmul output layout (see fully_connected.rs)
+  pub gemm_tile_size: Option<i64>,
+  // Defaults to true, see prover_rng in helpers.rs
+  pub zero_knowledge: Option<bool>,
+  // Splits range checks wider than this into several lookups (see signed_range_check.rs)
+  pub range_check_limb_bits: Option<i64>,
+  // Overrides num_cols with a named budget (see profiles.rs)
+  pub column_profile: Option<String>,
+  // The output tensors of every exit of an early-exit network, and the exit to prove with (see
+  // exits.rs)
+  pub exits: Option<Vec<Vec<i64>>>,
+  pub exit: Option<i64>,
+  // Commits to all weight tensors as the first public value (see model_commit.rs)
+  pub commit_weights: Option<bool>,
+  // Conditional subgraphs, in order
+  pub branches: Option<Vec<BranchMsgpack>>,
+  // Exposes the Poseidon hash of the input file, the inputs stay private (see input_hash.rs)
+  pub hash_inputs: Option<bool>,
+  // Reveals a predicate of an output instead of the output (see predicate.rs)
+  pub output_predicate: Option<Vec<i64>>,
+  // Reveals the indices of the top k elements of an output instead of the output (see
+  // predicate.rs)
+  pub output_top_k: Option<Vec<i64>>,
+  // Rounds the GELU and SiLU inputs to this many fractional bits before the lookups (see
+  // activation.rs)
+  pub activation_table_bits: Option<i64>,
+  // Requantizes the model and the inputs from global_sf to 2^frac_bits (see precision.rs)
+  pub frac_bits: Option<i64>,
+  // Layers proven by another proof system, bound by commitments to their inputs and outputs (see
+  // coprocessor.rs)
+  pub coprocessor_layers: Option<Vec<i64>>,
+  // The input tensor with the seed of the Dropout layers, which is revealed (see randomness.rs)
+  pub random_seed: Option<i64>,
 }
 
 pub fn load_config_msgpack(config_path: &str) -> ModelMsgpack {
-  let model: ModelMsgpack = {
-    let file = File::open(config_path).unwrap();
-    let mut reader = BufReader::new(file);
-    rmp_serde::from_read(&mut reader).unwrap()
-  };
+  let mut model = read_config_msgpack(config_path);
+  commit_weights(&mut model);
+  commit_coprocessor_layers(&mut model);
   model
 }
 
+// The config as written, without the commitment groups that load_config_msgpack adds for
+// commit_weights and the coprocessor layers. Tools that edit and save a config load it with this,
+// so that saving an unedited config only canonicalizes it.
+pub fn read_config_msgpack(config_path: &str) -> ModelMsgpack {
+  let buf = read_artifact(config_path).unwrap();
+  rmp_serde::from_slice(&buf).unwrap()
+}
+
 pub fn load_model_msgpack(config_path: &str, inp_path: &str) -> ModelMsgpack {
   let mut model = load_config_msgpack(config_path);
-  let inp: Vec<TensorMsgpack> = {
-    let file = File::open(inp_path).unwrap();
-    let mut reader = BufReader::new(file);
-    rmp_serde::from_read(&mut reader).unwrap()
-  };
+  let inp: Vec<TensorMsgpack> = rmp_serde::from_slice(&read_artifact(inp_path).unwrap()).unwrap();
   for tensor in inp {
+    check_dtype(&tensor);
     model.tensors.push(tensor);
   }
+  set_defaults(&mut model);
+
+  model
+}
+
+// The try_ loaders don't panic on malformed or malicious files, the model is checked against the
+// limits before it is used (see validate.rs)
+pub fn try_load_config_msgpack(config_path: &str) -> Result<ModelMsgpack, String> {
+  parse_config_msgpack(&read_artifact(config_path)?, &LoaderLimits::default())
+    .map_err(|e| format!("model config {}: {}", config_path, e))
+}
+
+pub fn try_load_model_msgpack(config_path: &str, inp_path: &str) -> Result<ModelMsgpack, String> {
+  let limits = LoaderLimits::default();
+  let model = try_load_config_msgpack(config_path)?;
+  let inp = parse_inputs_msgpack(&read_artifact(inp_path)?, &limits)
+    .map_err(|e| format!("input {}: {}", inp_path, e))?;
+  add_inputs(model, inp, &limits)
+}
+
+pub fn parse_config_msgpack(buf: &[u8], limits: &LoaderLimits) -> Result<ModelMsgpack, String> {
+  check_file_size(buf.len(), limits)?;
+  let mut model: ModelMsgpack =
+    rmp_serde::from_slice(buf).map_err(|e| format!("malformed model: {}", e))?;
+  validate_model(&model, limits, false)?;
+  commit_weights(&mut model);
+  commit_coprocessor_layers(&mut model);
+  Ok(model)
+}
+
+pub fn parse_inputs_msgpack(
+  buf: &[u8],
+  limits: &LoaderLimits,
+) -> Result<Vec<TensorMsgpack>, String> {
+  check_file_size(buf.len(), limits)?;
+  let inp: Vec<TensorMsgpack> =
+    rmp_serde::from_slice(buf).map_err(|e| format!("malformed inputs: {}", e))?;
+  for tensor in inp.iter() {
+    dtype_error(tensor)?;
+  }
+  Ok(inp)
+}
+
+// Adds the inputs to a checked config, and checks the complete model
+pub fn add_inputs(
+  mut model: ModelMsgpack,
+  inp: Vec<TensorMsgpack>,
+  limits: &LoaderLimits,
+) -> Result<ModelMsgpack, String> {
+  model.tensors.extend(inp);
+  validate_model(&model, limits, true)?;
+  set_defaults(&mut model);
+  Ok(model)
+}
 
+pub fn check_dtype(tensor: &TensorMsgpack) {
+  dtype_error(tensor).unwrap();
+}
+
+pub fn dtype_error(tensor: &TensorMsgpack) -> Result<(), String> {
+  let (min, max) = match tensor.dtype.as_deref() {
+    None | Some("int64") => return Ok(()),
+    Some("int8") => (i8::MIN as i64, i8::MAX as i64),
+    Some("int32") => (i32::MIN as i64, i32::MAX as i64),
+    Some(dtype) => {
+      return Err(format!(
+        "tensor {} has unsupported dtype {}",
+        tensor.idx, dtype
+      ))
+    }
+  };
+  match tensor.data.iter().find(|x| **x < min || **x > max) {
+    Some(x) => Err(format!(
+      "tensor {} has value {} out of range for {}",
+      tensor.idx,
+      x,
+      tensor.dtype.as_ref().unwrap()
+    )),
+    None => Ok(()),
+  }
+}
+
+// The defaults used when proving, so that the verifier rebuilds the same circuit
+pub fn set_defaults(model: &mut ModelMsgpack) {
   // Default to using selectors, commit if use_selectors is not specified
   if model.use_selectors.is_none() {
     model.use_selectors = Some(true)
@@ -72,6 +222,80 @@ pub fn load_model_msgpack(config_path: &str, inp_path: &str) -> ModelMsgpack {
   if model.num_random.is_none() {
     model.num_random = Some(20001)
   };
+  if model.zero_knowledge.is_none() {
+    model.zero_knowledge = Some(true)
+  };
+}
 
-  model
+// Sorts everything whose order does not affect the circuit, so that writing the same model
+// always produces the same bytes. Two tensors with the same index are an error, since which one
+// the circuit takes depends on the order.
+pub fn canonicalize_model(model: &mut ModelMsgpack) -> Result<(), String> {
+  model.tensors.sort_by_key(|tensor| tensor.idx);
+  match model
+    .tensors
+    .windows(2)
+    .find(|pair| pair[0].idx == pair[1].idx)
+  {
+    Some(pair) => Err(format!("tensor {} is defined twice", pair[0].idx)),
+    None => Ok(()),
+  }
+}
+
+// A config from load_config_msgpack is written with the groups it added (see
+// read_config_msgpack). Loading it again adds nothing, so the circuit is the same.
+pub fn model_to_msgpack(model: &ModelMsgpack) -> Vec<u8> {
+  let mut model = model.clone();
+  canonicalize_model(&mut model).unwrap();
+  let mut buf = vec![];
+  rmp_serde::encode::write_named(&mut buf, &model).unwrap();
+  buf
+}
+
+// Inverse of load_model_msgpack: the input tensors are written to a separate file
+pub fn save_model_msgpack(model: &ModelMsgpack, config_path: &str, inp_path: &str) {
+  let (config, inp) = split_inputs(model);
+  save_config_msgpack(&config, config_path);
+
+  let mut buf = vec![];
+  rmp_serde::encode::write_named(&mut buf, &inp).unwrap();
+  write_artifact(inp_path, &buf).unwrap();
+}
+
+pub fn save_config_msgpack(model: &ModelMsgpack, config_path: &str) {
+  let buf = model_to_msgpack(model);
+  write_artifact(config_path, &buf).unwrap();
+}
+
+// The model with these outputs and without the options that add or change public values, e.g.,
+// to evaluate a prefix of it. The options that change what the layers compute (the scale, the
+// softmax and activation approximations) are kept, so the outputs are the values of the model.
+pub fn sub_model(model: &ModelMsgpack, out_idxes: Vec<i64>) -> ModelMsgpack {
+  ModelMsgpack {
+    out_idxes,
+    commit_before: Some(vec![]),
+    commit_after: Some(vec![]),
+    rlc_inputs: None,
+    drop_final_softmax: None,
+    exits: None,
+    exit: None,
+    commit_weights: None,
+    hash_inputs: None,
+    output_predicate: None,
+    output_top_k: None,
+    coprocessor_layers: None,
+    random_seed: None,
+    ..model.clone()
+  }
+}
+
+pub fn split_inputs(model: &ModelMsgpack) -> (ModelMsgpack, Vec<TensorMsgpack>) {
+  let mut config = model.clone();
+  canonicalize_model(&mut config).unwrap();
+  let (inp, tensors): (Vec<_>, Vec<_>) = config
+    .tensors
+    .into_iter()
+    .partition(|tensor| model.inp_idxes.contains(&tensor.idx));
+  config.tensors = tensors;
+  (config, inp)
 }
@@ -0,0 +1,323 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  commitments::{
+    input_hash::num_input_hash_vals,
+    merkle::{field_from_string, field_to_string, MerkleTree},
+  },
+  model::ModelCircuit,
+  utils::{
+    chaining::{commitment_position, output_shapes},
+    envelope::decode_signed,
+    loader::{sub_model, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+    rlc::{num_rlc_vals, rlc_position},
+    storage::read_artifact,
+  },
+};
+
+// Sparse mixture-of-experts blocks: a MoE layer only computes the top k experts of its gate, so
+// the circuit has slots for k experts instead of all of them. The chosen experts and their weights
+// are dispatched into the slots at prove time, as inputs, so the proving key doesn't depend on the
+// routing. The circuit constrains the chosen experts to be the top k and reveals them, and every
+// slot is committed on its own. The verifier checks the commitment of every slot against the
+// commitment of the chosen expert in the published expert set, which is bound by a Merkle root.
+
+// The experts of a MoE layer, each (w1, b1, w2, b2). The tensor indices are ignored.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExpertSet {
+  // The position of the MoE layer in the model
+  pub layer: usize,
+  pub experts: Vec<Vec<TensorMsgpack>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExpertCommitments {
+  pub layer: usize,
+  pub commitments: Vec<String>,
+  // Merkle root over the commitments, in expert order
+  pub root: String,
+}
+
+pub fn load_expert_sets(path: &str) -> Result<Vec<ExpertSet>, String> {
+  rmp_serde::from_slice(&read_artifact(path)?)
+    .map_err(|e| format!("malformed expert sets {}: {}", path, e))
+}
+
+pub fn moe_layer(model: &ModelMsgpack, pos: usize) -> Result<&LayerMsgpack, String> {
+  model
+    .layers
+    .get(pos)
+    .filter(|layer| layer.layer_type == "MoE")
+    .ok_or_else(|| format!("layer {} is not a MoE layer", pos))
+}
+
+// The (w1, b1, w2, b2) tensors of every slot
+pub fn slot_idxes(layer: &LayerMsgpack) -> Vec<Vec<i64>> {
+  layer.inp_idxes[3..].chunks(4).map(|x| x.to_vec()).collect()
+}
+
+fn slot_group(slot: &Vec<i64>) -> Vec<i64> {
+  let mut group = slot.clone();
+  group.sort();
+  group
+}
+
+// Makes the chosen experts and the slots inputs, commits to every slot on its own, and reveals
+// the chosen experts
+pub fn prepare_moe(model: &mut ModelMsgpack) {
+  let mut dispatched = vec![];
+  let mut groups = vec![];
+  for layer in model.layers.iter().filter(|l| l.layer_type == "MoE") {
+    dispatched.push(layer.inp_idxes[2]);
+    for slot in slot_idxes(layer) {
+      dispatched.extend(slot.iter());
+      groups.push(slot_group(&slot));
+    }
+    if !model.out_idxes.contains(&layer.out_idxes[1]) {
+      model.out_idxes.push(layer.out_idxes[1]);
+    }
+  }
+  for idx in dispatched.iter() {
+    if !model.inp_idxes.contains(idx) {
+      model.inp_idxes.push(*idx);
+    }
+  }
+
+  let mut commit_before = model.commit_before.clone().unwrap_or(vec![]);
+  for group in commit_before.iter_mut() {
+    group.retain(|idx| !dispatched.contains(idx));
+  }
+  commit_before.retain(|group| !group.is_empty());
+  for group in groups {
+    if !commit_before.contains(&group) {
+      commit_before.push(group);
+    }
+  }
+  model.commit_before = Some(commit_before);
+}
+
+// The top k experts by decreasing logit. A tie at the k-th logit makes the top k ambiguous.
+pub fn top_k_experts(logits: &Vec<i64>, k: usize) -> Result<Vec<usize>, String> {
+  let mut order = (0..logits.len()).collect::<Vec<_>>();
+  order.sort_by_key(|i| (-logits[*i], *i));
+  if k < logits.len() && logits[order[k - 1]] == logits[order[k]] {
+    return Err(format!(
+      "experts {} and {} tie in the gate",
+      order[k - 1],
+      order[k]
+    ));
+  }
+  Ok(order[..k].to_vec())
+}
+
+// The tensors that dispatch the chosen experts into the slots of the layer
+pub fn dispatch(
+  model: &ModelMsgpack,
+  set: &ExpertSet,
+  chosen: &Vec<usize>,
+) -> Result<Vec<TensorMsgpack>, String> {
+  let layer = moe_layer(model, set.layer)?;
+  let slots = slot_idxes(layer);
+  if chosen.len() != slots.len() {
+    return Err(format!(
+      "{} experts are chosen for {} slots",
+      chosen.len(),
+      slots.len()
+    ));
+  }
+
+  let mut outp = vec![TensorMsgpack {
+    idx: layer.inp_idxes[2],
+    shape: vec![chosen.len() as i64],
+    data: chosen.iter().map(|x| *x as i64).collect(),
+    dtype: None,
+  }];
+  for (r, (slot, expert_idx)) in slots.iter().zip(chosen.iter()).enumerate() {
+    let expert = set
+      .experts
+      .get(*expert_idx)
+      .ok_or_else(|| format!("expert {} out of range", expert_idx))?;
+    if expert.len() != slot.len() {
+      return Err(format!("expert {} is not (w1, b1, w2, b2)", expert_idx));
+    }
+    for (j, (idx, tensor)) in slot.iter().zip(expert.iter()).enumerate() {
+      if tensor.shape != layer.inp_shapes[3 + 4 * r + j] {
+        return Err(format!(
+          "tensor {} of expert {} has shape {:?}, the slot has {:?}",
+          j,
+          expert_idx,
+          tensor.shape,
+          layer.inp_shapes[3 + 4 * r + j]
+        ));
+      }
+      outp.push(TensorMsgpack {
+        idx: *idx,
+        ..tensor.clone()
+      });
+    }
+  }
+  Ok(outp)
+}
+
+// The model up to the MoE layer, with the gate logits as the output
+fn gate_model(model: &ModelMsgpack, pos: usize, inp: &Vec<TensorMsgpack>) -> ModelMsgpack {
+  let layer = &model.layers[pos];
+  let mut inp_idxes = model.inp_idxes.clone();
+  inp_idxes.retain(|idx| inp.iter().any(|tensor| tensor.idx == *idx));
+  ModelMsgpack {
+    inp_idxes,
+    layers: model.layers[..pos].to_vec(),
+    branches: None,
+    ..sub_model(model, vec![layer.inp_idxes[1]])
+  }
+}
+
+// Routes the input through the MoE layers in order, the gate of a layer sees the experts chosen
+// by the earlier layers. Returns the dispatched tensors, to be added to the input, and the chosen
+// experts of every layer.
+pub fn route(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+  sets: &Vec<ExpertSet>,
+) -> Result<(Vec<TensorMsgpack>, Vec<Vec<usize>>), String> {
+  let mut sets = sets.clone();
+  sets.sort_by_key(|set| set.layer);
+
+  let mut dispatched = vec![];
+  let mut routes = vec![];
+  for set in sets.iter() {
+    let top_k = moe_layer(model, set.layer)?.params[1] as usize;
+    let inputs = [inp.clone(), dispatched.clone()].concat();
+    let gate = gate_model(model, set.layer, &inputs);
+    let logits = ModelCircuit::<Fr>::public_values(&gate, &inputs)?
+      .iter()
+      .map(|x| decode_signed(x).map(|x| x as i64))
+      .collect::<Option<Vec<_>>>()
+      .ok_or_else(|| format!("a gate logit of layer {} is not an integer", set.layer))?;
+    let chosen = top_k_experts(&logits, top_k)?;
+    dispatched.extend(dispatch(model, set, &chosen)?);
+    routes.push(chosen);
+  }
+  Ok((dispatched, routes))
+}
+
+// The commitments of every expert, as a slot commits to it: the experts are dispatched k at a time
+// and the slot commitments are read from the public values. The routing isn't checked, so this
+// only generates the witness of the model once per k experts.
+pub fn expert_commitments(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+  sets: &Vec<ExpertSet>,
+) -> Result<Vec<ExpertCommitments>, String> {
+  let (routed, _) = route(model, inp, sets)?;
+
+  let mut outp = vec![];
+  for set in sets.iter() {
+    let slots = slot_idxes(moe_layer(model, set.layer)?);
+    let positions = slots
+      .iter()
+      .map(|slot| {
+        commitment_position(model, &slot_group(slot), false)
+          .ok_or_else(|| format!("a slot of layer {} is not committed", set.layer))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let mut commitments = vec![];
+    let experts = (0..set.experts.len()).collect::<Vec<_>>();
+    for batch in experts.chunks(slots.len()) {
+      let mut chosen = batch.to_vec();
+      chosen.resize(slots.len(), batch[0]);
+      let dispatched = dispatch(model, set, &chosen)?;
+      let mut inputs = inp.clone();
+      inputs.extend(
+        routed
+          .iter()
+          .filter(|x| !dispatched.iter().any(|y| y.idx == x.idx))
+          .cloned(),
+      );
+      inputs.extend(dispatched);
+      let public_vals = ModelCircuit::<Fr>::public_values(model, &inputs)?;
+      for pos in positions[..batch.len()].iter() {
+        commitments.push(public_vals[*pos]);
+      }
+    }
+
+    let root = MerkleTree::new(commitments.clone()).root();
+    outp.push(ExpertCommitments {
+      layer: set.layer,
+      commitments: commitments.iter().map(field_to_string).collect(),
+      root: field_to_string(&root),
+    });
+  }
+  Ok(outp)
+}
+
+// The values of an output tensor in the public values
+fn output_values(
+  model: &ModelMsgpack,
+  public_vals: &Vec<Fr>,
+  idx: i64,
+) -> Result<Vec<i64>, String> {
+  let mut start = rlc_position(model)
+    + num_rlc_vals(model)
+    + num_input_hash_vals(model)
+    + model.exit.map_or(0, |_| 1);
+  for (out_idx, shape) in model.out_idxes.iter().zip(output_shapes(model).iter()) {
+    let len = shape.iter().product::<i64>() as usize;
+    if *out_idx == idx {
+      return public_vals
+        .get(start..start + len)
+        .ok_or("too few public values")?
+        .iter()
+        .map(|x| decode_signed(x).map(|x| x as i64))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("output {} is not an integer", idx));
+    }
+    start += len;
+  }
+  Err(format!("tensor {} is not an output", idx))
+}
+
+// Checks that every slot commits to the expert chosen for it, in the committed expert sets.
+// Returns the chosen experts of every layer.
+pub fn check_routing(
+  model: &ModelMsgpack,
+  public_vals: &Vec<Fr>,
+  expert_commitments: &Vec<ExpertCommitments>,
+) -> Result<Vec<Vec<usize>>, String> {
+  let mut routes = vec![];
+  for set in expert_commitments.iter() {
+    let leaves = set
+      .commitments
+      .iter()
+      .map(|x| field_from_string(x))
+      .collect::<Vec<Fr>>();
+    if field_to_string(&MerkleTree::new(leaves).root()) != set.root {
+      return Err(format!(
+        "the expert commitments of layer {} don't match the root",
+        set.layer
+      ));
+    }
+
+    let layer = moe_layer(model, set.layer)?;
+    let chosen = output_values(model, public_vals, layer.out_idxes[1])?;
+    for (slot, expert_idx) in slot_idxes(layer).iter().zip(chosen.iter()) {
+      let pos = commitment_position(model, &slot_group(slot), false)
+        .ok_or_else(|| format!("a slot of layer {} is not committed", set.layer))?;
+      let commitment = set
+        .commitments
+        .get(*expert_idx as usize)
+        .filter(|_| *expert_idx >= 0)
+        .ok_or_else(|| format!("expert {} out of range", expert_idx))?;
+      if field_to_string(&public_vals[pos]) != *commitment {
+        return Err(format!(
+          "the slot of expert {} in layer {} doesn't commit to it",
+          expert_idx, set.layer
+        ));
+      }
+    }
+    routes.push(chosen.iter().map(|x| *x as usize).collect());
+  }
+  Ok(routes)
+}
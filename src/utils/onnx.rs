@@ -0,0 +1,1724 @@
+use std::collections::{HashMap, HashSet};
+
+use ndarray::{Array, IxDyn};
+
+use super::{
+  loader::{set_defaults, BranchMsgpack, LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  storage::read_artifact,
+};
+
+// Imports ONNX graphs without the Python converter. The protobuf is decoded by hand, since only a
+// few fields of the model, graph, node, tensor, and value info messages are needed.
+// ONNX is NCHW while the layers are NHWC, so 4D (and 5D, NCDHW) activations and conv weights are
+// transposed on import, and a Transpose is inserted where the order of the elements matters, i.e.,
+// before flattening and for the outputs. Every float is quantized to round(x * sf), like the
+// converter. Supported: Conv (SAME or VALID padding, depthwise, 2D or 3D), MatMul and Gemm with
+// constant weights, Relu (fused into the previous layer when its output is not used elsewhere),
+// Add, Softmax over the last axis, MaxPool, AveragePool, and GlobalAveragePool (2D or 3D),
+// Flatten, Reshape, Identity, If, whose subgraphs are both imported into a branch (see
+// branches.rs), and Loop and Scan with a static trip count, which are unrolled. For attention:
+// MatMul of two activations, Transpose, Mul, Div by a constant, LayerNormalization over the last
+// axis, Gelu, Exp, Log, Sqrt, and Reciprocal. Gather (e.g., embedding lookups, whose token id
+// inputs are not quantized), Slice with constant bounds and unit steps, ScatterND with constant
+// indices, and Mod by a constant. Dropout is the identity, or MC dropout in training mode (see
+// randomness.rs).
+
+#[derive(Clone, Debug)]
+pub struct OnnxOptions {
+  pub scale_factor: i64,
+  pub k: i64,
+  pub num_cols: i64,
+}
+
+// The defaults of the converter
+impl Default for OnnxOptions {
+  fn default() -> Self {
+    Self {
+      scale_factor: 1 << 16,
+      k: 19,
+      num_cols: 6,
+    }
+  }
+}
+
+// Protobuf wire format
+
+enum Wire<'a> {
+  Varint(u64),
+  Fixed64(u64),
+  Bytes(&'a [u8]),
+  Fixed32(u32),
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+  let mut x = 0u64;
+  for shift in (0..64).step_by(7) {
+    let byte = *buf.get(*pos).ok_or("truncated varint")?;
+    *pos += 1;
+    x |= ((byte & 0x7f) as u64) << shift;
+    if byte & 0x80 == 0 {
+      return Ok(x);
+    }
+  }
+  Err("varint is too long".to_string())
+}
+
+fn take<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+  let end = pos
+    .checked_add(len)
+    .filter(|end| *end <= buf.len())
+    .ok_or("truncated field")?;
+  let bytes = &buf[*pos..end];
+  *pos = end;
+  Ok(bytes)
+}
+
+fn fields(buf: &[u8]) -> Result<Vec<(u64, Wire<'_>)>, String> {
+  let mut pos = 0;
+  let mut out = vec![];
+  while pos < buf.len() {
+    let key = read_varint(buf, &mut pos)?;
+    let wire = match key & 7 {
+      0 => Wire::Varint(read_varint(buf, &mut pos)?),
+      1 => Wire::Fixed64(u64::from_le_bytes(
+        take(buf, &mut pos, 8)?.try_into().unwrap(),
+      )),
+      2 => {
+        let len = read_varint(buf, &mut pos)? as usize;
+        Wire::Bytes(take(buf, &mut pos, len)?)
+      }
+      5 => Wire::Fixed32(u32::from_le_bytes(
+        take(buf, &mut pos, 4)?.try_into().unwrap(),
+      )),
+      t => return Err(format!("unsupported wire type {}", t)),
+    };
+    out.push((key >> 3, wire));
+  }
+  Ok(out)
+}
+
+fn varint(wire: &Wire) -> Result<i64, String> {
+  match wire {
+    Wire::Varint(x) => Ok(*x as i64),
+    _ => Err("expected a varint".to_string()),
+  }
+}
+
+fn bytes<'a>(wire: &Wire<'a>) -> Result<&'a [u8], String> {
+  match wire {
+    Wire::Bytes(bytes) => Ok(bytes),
+    _ => Err("expected bytes".to_string()),
+  }
+}
+
+fn string(wire: &Wire) -> Result<String, String> {
+  String::from_utf8(bytes(wire)?.to_vec()).map_err(|e| format!("malformed string: {}", e))
+}
+
+// Repeated fields can be packed or not
+fn push_ints(wire: &Wire, out: &mut Vec<i64>) -> Result<(), String> {
+  match wire {
+    Wire::Varint(x) => out.push(*x as i64),
+    Wire::Bytes(bytes) => {
+      let mut pos = 0;
+      while pos < bytes.len() {
+        out.push(read_varint(bytes, &mut pos)? as i64);
+      }
+    }
+    _ => return Err("malformed repeated integer".to_string()),
+  }
+  Ok(())
+}
+
+fn push_floats(wire: &Wire, out: &mut Vec<f64>) -> Result<(), String> {
+  match wire {
+    Wire::Fixed32(x) => out.push(f32::from_bits(*x) as f64),
+    Wire::Bytes(bytes) => out.extend(
+      bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64),
+    ),
+    _ => return Err("malformed repeated float".to_string()),
+  }
+  Ok(())
+}
+
+fn push_doubles(wire: &Wire, out: &mut Vec<f64>) -> Result<(), String> {
+  match wire {
+    Wire::Fixed64(x) => out.push(f64::from_bits(*x)),
+    Wire::Bytes(bytes) => out.extend(
+      bytes
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+    ),
+    _ => return Err("malformed repeated double".to_string()),
+  }
+  Ok(())
+}
+
+// ONNX messages
+
+const FLOAT: i64 = 1;
+const INT32: i64 = 6;
+const INT64: i64 = 7;
+const BOOL: i64 = 9;
+const DOUBLE: i64 = 11;
+
+#[derive(Clone, Debug, Default)]
+struct OnnxTensor {
+  name: String,
+  dims: Vec<i64>,
+  data: Vec<f64>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct OnnxAttribute {
+  name: String,
+  f: f64,
+  i: i64,
+  s: String,
+  t: Option<OnnxTensor>,
+  g: Option<OnnxGraph>,
+  ints: Vec<i64>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct OnnxNode {
+  inputs: Vec<String>,
+  outputs: Vec<String>,
+  op_type: String,
+  attributes: Vec<OnnxAttribute>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct OnnxGraph {
+  nodes: Vec<OnnxNode>,
+  initializers: Vec<OnnxTensor>,
+  inputs: Vec<(String, Vec<i64>)>, // Unknown dims are -1
+  outputs: Vec<String>,
+}
+
+fn parse_tensor(buf: &[u8]) -> Result<OnnxTensor, String> {
+  let mut tensor = OnnxTensor::default();
+  let mut data_type = 0;
+  let mut raw = None;
+  for (field, wire) in fields(buf)? {
+    match field {
+      1 => push_ints(&wire, &mut tensor.dims)?,
+      2 => data_type = varint(&wire)?,
+      4 => push_floats(&wire, &mut tensor.data)?,
+      5 | 7 => {
+        let mut ints = vec![];
+        push_ints(&wire, &mut ints)?;
+        tensor.data.extend(ints.iter().map(|x| *x as f64));
+      }
+      8 => tensor.name = string(&wire)?,
+      9 => raw = Some(bytes(&wire)?),
+      10 => push_doubles(&wire, &mut tensor.data)?,
+      _ => {}
+    }
+  }
+  if let Some(raw) = raw {
+    tensor.data = match data_type {
+      FLOAT => raw
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+        .collect(),
+      INT32 => raw
+        .chunks_exact(4)
+        .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f64)
+        .collect(),
+      INT64 => raw
+        .chunks_exact(8)
+        .map(|b| i64::from_le_bytes(b.try_into().unwrap()) as f64)
+        .collect(),
+      DOUBLE => raw
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+        .collect(),
+      BOOL => raw.iter().map(|b| *b as f64).collect(),
+      t => {
+        return Err(format!(
+          "tensor {} has unsupported data type {}",
+          tensor.name, t
+        ))
+      }
+    };
+  } else if ![FLOAT, INT32, INT64, BOOL, DOUBLE].contains(&data_type) {
+    return Err(format!(
+      "tensor {} has unsupported data type {}",
+      tensor.name, data_type
+    ));
+  }
+  if tensor.data.len() as i64 != tensor.dims.iter().product::<i64>() {
+    return Err(format!(
+      "tensor {} has {} values for shape {:?}",
+      tensor.name,
+      tensor.data.len(),
+      tensor.dims
+    ));
+  }
+  Ok(tensor)
+}
+
+fn parse_attribute(buf: &[u8]) -> Result<OnnxAttribute, String> {
+  let mut attr = OnnxAttribute::default();
+  for (field, wire) in fields(buf)? {
+    match field {
+      1 => attr.name = string(&wire)?,
+      2 => match wire {
+        Wire::Fixed32(x) => attr.f = f32::from_bits(x) as f64,
+        _ => return Err("malformed float attribute".to_string()),
+      },
+      3 => attr.i = varint(&wire)?,
+      4 => attr.s = string(&wire)?,
+      5 => attr.t = Some(parse_tensor(bytes(&wire)?)?),
+      6 => attr.g = Some(parse_graph(bytes(&wire)?)?),
+      8 => push_ints(&wire, &mut attr.ints)?,
+      _ => {}
+    }
+  }
+  Ok(attr)
+}
+
+fn parse_node(buf: &[u8]) -> Result<OnnxNode, String> {
+  let mut node = OnnxNode::default();
+  for (field, wire) in fields(buf)? {
+    match field {
+      1 => node.inputs.push(string(&wire)?),
+      2 => node.outputs.push(string(&wire)?),
+      4 => node.op_type = string(&wire)?,
+      5 => node.attributes.push(parse_attribute(bytes(&wire)?)?),
+      _ => {}
+    }
+  }
+  Ok(node)
+}
+
+// The name and shape of a ValueInfoProto
+fn parse_value_info(buf: &[u8]) -> Result<(String, Vec<i64>), String> {
+  let mut name = String::new();
+  let mut shape = vec![];
+  for (field, wire) in fields(buf)? {
+    match field {
+      1 => name = string(&wire)?,
+      // TypeProto.tensor_type.shape.dim.dim_value
+      2 => {
+        for (field, wire) in fields(bytes(&wire)?)? {
+          if field != 1 {
+            continue;
+          }
+          for (field, wire) in fields(bytes(&wire)?)? {
+            if field != 2 {
+              continue;
+            }
+            for (field, wire) in fields(bytes(&wire)?)? {
+              if field != 1 {
+                continue;
+              }
+              let mut dim = -1;
+              for (field, wire) in fields(bytes(&wire)?)? {
+                if field == 1 {
+                  dim = varint(&wire)?;
+                }
+              }
+              shape.push(dim);
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok((name, shape))
+}
+
+fn parse_graph(buf: &[u8]) -> Result<OnnxGraph, String> {
+  let mut graph = OnnxGraph::default();
+  for (field, wire) in fields(buf)? {
+    match field {
+      1 => graph.nodes.push(parse_node(bytes(&wire)?)?),
+      5 => graph.initializers.push(parse_tensor(bytes(&wire)?)?),
+      11 => graph.inputs.push(parse_value_info(bytes(&wire)?)?),
+      12 => graph.outputs.push(parse_value_info(bytes(&wire)?)?.0),
+      _ => {}
+    }
+  }
+  Ok(graph)
+}
+
+// Subgraphs can use the values of the outer graph, so their nodes and outputs are uses too
+fn count_uses(graph: &OnnxGraph, num_uses: &mut HashMap<String, usize>) {
+  for node in graph.nodes.iter() {
+    for name in node.inputs.iter() {
+      *num_uses.entry(name.clone()).or_insert(0) += 1;
+    }
+    for subgraph in node.attributes.iter().filter_map(|attr| attr.g.as_ref()) {
+      count_uses(subgraph, num_uses);
+      for name in subgraph.outputs.iter() {
+        *num_uses.entry(name.clone()).or_insert(0) += 1;
+      }
+    }
+  }
+}
+
+fn parse_model(buf: &[u8]) -> Result<OnnxGraph, String> {
+  for (field, wire) in fields(buf)? {
+    if field == 7 {
+      return parse_graph(bytes(&wire)?);
+    }
+  }
+  Err("the model has no graph".to_string())
+}
+
+impl OnnxNode {
+  fn attr(&self, name: &str) -> Option<&OnnxAttribute> {
+    self.attributes.iter().find(|attr| attr.name == name)
+  }
+
+  fn attr_i(&self, name: &str, default: i64) -> i64 {
+    self.attr(name).map_or(default, |attr| attr.i)
+  }
+
+  fn attr_f(&self, name: &str, default: f64) -> f64 {
+    self.attr(name).map_or(default, |attr| attr.f)
+  }
+
+  fn attr_s(&self, name: &str) -> String {
+    self.attr(name).map_or(String::new(), |attr| attr.s.clone())
+  }
+
+  fn attr_ints(&self, name: &str, default: Vec<i64>) -> Vec<i64> {
+    self.attr(name).map_or(default, |attr| attr.ints.clone())
+  }
+
+  fn attr_g(&self, name: &str) -> Result<OnnxGraph, String> {
+    self
+      .attr(name)
+      .and_then(|attr| attr.g.clone())
+      .ok_or_else(|| format!("no {}", name))
+  }
+
+  // Optional inputs can be left out or be empty names
+  fn input(&self, i: usize) -> Option<&String> {
+    self.inputs.get(i).filter(|name| !name.is_empty())
+  }
+}
+
+// Import
+
+// A tensor of the imported model, with its shape in the layer layout. 4D and 5D activations have
+// the channels last in the layers (NHWC, NDHWC) and second in the ONNX graph (NCHW, NCDHW).
+#[derive(Clone, Debug)]
+struct Value {
+  idx: i64,
+  shape: Vec<i64>,
+  nchw: bool,
+}
+
+impl Value {
+  fn onnx_shape(&self) -> Vec<i64> {
+    if self.nchw {
+      channels_first(self.shape.len())
+        .iter()
+        .map(|axis| self.shape[*axis])
+        .collect()
+    } else {
+      self.shape.clone()
+    }
+  }
+}
+
+// The permutations that move the channels of a rank 4 or 5 activation last, e.g., NCHW to NHWC,
+// and back
+fn channels_last(rank: usize) -> Vec<usize> {
+  let mut perm = vec![0];
+  perm.extend(2..rank);
+  perm.push(1);
+  perm
+}
+
+fn channels_first(rank: usize) -> Vec<usize> {
+  let mut perm = vec![0, rank - 1];
+  perm.extend(1..rank - 1);
+  perm
+}
+
+fn permute(tensor: &OnnxTensor, perm: &[usize]) -> OnnxTensor {
+  let dims = tensor.dims.iter().map(|x| *x as usize).collect::<Vec<_>>();
+  let arr = Array::from_shape_vec(IxDyn(&dims), tensor.data.clone()).unwrap();
+  let arr = arr.permuted_axes(IxDyn(perm));
+  OnnxTensor {
+    name: tensor.name.clone(),
+    dims: arr.shape().iter().map(|x| *x as i64).collect(),
+    data: arr.iter().cloned().collect(),
+  }
+}
+
+fn broadcast_shape(a: &[i64], b: &[i64]) -> Vec<i64> {
+  let len = a.len().max(b.len());
+  let dim = |s: &[i64], i: usize| {
+    if i + s.len() < len {
+      1
+    } else {
+      s[i + s.len() - len]
+    }
+  };
+  (0..len).map(|i| dim(a, i).max(dim(b, i))).collect()
+}
+
+// The TF style SAME padding (begin, end) of the conv layer, which puts the extra row at the end
+fn same_pads(size: i64, kernel: i64, stride: i64) -> (i64, i64) {
+  let total = if size % stride == 0 {
+    (kernel - stride).max(0)
+  } else {
+    (kernel - size % stride).max(0)
+  };
+  (total / 2, total - total / 2)
+}
+
+struct Importer {
+  options: OnnxOptions,
+  consts: HashMap<String, OnnxTensor>,
+  values: HashMap<String, Value>,
+  num_uses: HashMap<String, usize>,
+  graph_outputs: Vec<String>,
+  producers: HashMap<i64, usize>,
+  tensors: Vec<TensorMsgpack>,
+  layers: Vec<LayerMsgpack>,
+  branches: Vec<BranchMsgpack>,
+  next_idx: i64,
+}
+
+impl Importer {
+  fn quantize(&self, x: f64) -> i64 {
+    (x * self.options.scale_factor as f64).round() as i64
+  }
+
+  fn new_idx(&mut self) -> i64 {
+    self.next_idx += 1;
+    self.next_idx - 1
+  }
+
+  fn value(&self, name: &str) -> Result<Value, String> {
+    self
+      .values
+      .get(name)
+      .cloned()
+      .ok_or_else(|| format!("{} is not computed by the supported layers", name))
+  }
+
+  fn constant(&self, name: &str) -> Result<OnnxTensor, String> {
+    self
+      .consts
+      .get(name)
+      .cloned()
+      .ok_or_else(|| format!("{} must be a constant", name))
+  }
+
+  fn add_tensor(&mut self, tensor: &OnnxTensor) -> Value {
+    let idx = self.new_idx();
+    let data = tensor.data.iter().map(|x| self.quantize(*x)).collect();
+    let shape = if tensor.dims.is_empty() {
+      vec![1]
+    } else {
+      tensor.dims.clone()
+    };
+    self.tensors.push(TensorMsgpack {
+      idx,
+      shape: shape.clone(),
+      data,
+      dtype: None,
+    });
+    Value {
+      idx,
+      shape,
+      nchw: false,
+    }
+  }
+
+  // Integer tensors, e.g., the token ids of an embedding lookup, are not quantized
+  fn add_int_tensor(&mut self, tensor: &OnnxTensor) -> Value {
+    let value = self.add_tensor(tensor);
+    self.tensors.last_mut().unwrap().data = tensor.data.iter().map(|x| x.round() as i64).collect();
+    value
+  }
+
+  // A value in the ONNX order, or a constant added as a tensor
+  fn value_or_constant(&mut self, name: &str) -> Result<Value, String> {
+    match self.values.get(name).cloned() {
+      Some(x) => Ok(self.in_onnx_order(&x)),
+      None => {
+        let tensor = self.constant(name)?;
+        Ok(self.add_tensor(&tensor))
+      }
+    }
+  }
+
+  fn add_layer(
+    &mut self,
+    layer_type: &str,
+    params: Vec<i64>,
+    inps: &[&Value],
+    out_shape: Vec<i64>,
+    nchw: bool,
+  ) -> Value {
+    let idx = self.new_idx();
+    self.producers.insert(idx, self.layers.len());
+    self.layers.push(LayerMsgpack {
+      layer_type: layer_type.to_string(),
+      params,
+      inp_idxes: inps.iter().map(|v| v.idx).collect(),
+      inp_shapes: inps.iter().map(|v| v.shape.clone()).collect(),
+      out_idxes: vec![idx],
+      out_shapes: vec![out_shape.clone()],
+      mask: vec![],
+    });
+    Value {
+      idx,
+      shape: out_shape,
+      nchw,
+    }
+  }
+
+  // The value in the ONNX element order
+  fn in_onnx_order(&mut self, v: &Value) -> Value {
+    if !v.nchw {
+      return v.clone();
+    }
+    let mut params = v.shape.clone();
+    params.extend(channels_first(v.shape.len()).iter().map(|x| *x as i64));
+    self.add_layer("Transpose", params, &[v], v.onnx_shape(), false)
+  }
+
+  fn import_node(&mut self, node: &OnnxNode) -> Result<(), String> {
+    let out = match node.op_type.as_str() {
+      "Constant" => {
+        let tensor = node
+          .attr("value")
+          .and_then(|attr| attr.t.clone())
+          .ok_or("only tensor constants are supported")?;
+        self.consts.insert(node.outputs[0].clone(), tensor);
+        return Ok(());
+      }
+      "Identity" => self.value(&node.inputs[0])?,
+      "Dropout" => self.import_dropout(node)?,
+      "Conv" => self.import_conv(node)?,
+      "MatMul" if self.values.contains_key(&node.inputs[1]) => self.import_matmul(node)?,
+      "MatMul" | "Gemm" => self.import_gemm(node)?,
+      "Relu" => self.import_relu(node)?,
+      "Gelu" => {
+        let x = self.value(&node.inputs[0])?;
+        if !["", "none"].contains(&node.attr_s("approximate").as_str()) {
+          return Err("only the exact GELU is supported".to_string());
+        }
+        self.add_layer("Gelu", vec![], &[&x], x.shape.clone(), x.nchw)
+      }
+      "Exp" | "Log" | "Sqrt" | "Reciprocal" => {
+        let x = self.value(&node.inputs[0])?;
+        let layer_type = match node.op_type.as_str() {
+          "Log" => "Ln",
+          op => op,
+        };
+        self.add_layer(layer_type, vec![], &[&x], x.shape.clone(), x.nchw)
+      }
+      "Add" => self.import_add(node)?,
+      "Mul" | "Div" => self.import_mul(node)?,
+      "Transpose" => {
+        let x = self.value(&node.inputs[0])?;
+        let x = self.in_onnx_order(&x);
+        let rank = x.shape.len();
+        let perm = node.attr_ints("perm", (0..rank as i64).rev().collect());
+        let mut params = x.shape.clone();
+        params.extend(perm.iter());
+        let out_shape = perm.iter().map(|i| x.shape[*i as usize]).collect();
+        self.add_layer("Transpose", params, &[&x], out_shape, false)
+      }
+      "Softmax" => {
+        let x = self.value(&node.inputs[0])?;
+        let x = self.in_onnx_order(&x);
+        let rank = x.shape.len() as i64;
+        let axis = node.attr_i("axis", -1);
+        if axis != -1 && axis != rank - 1 {
+          return Err("only the softmax over the last axis is supported".to_string());
+        }
+        self.add_layer("Softmax", vec![], &[&x], x.shape.clone(), false)
+      }
+      "LayerNormalization" => self.import_layer_norm(node)?,
+      "MaxPool" | "AveragePool" => self.import_pool(node)?,
+      "GlobalAveragePool" => {
+        let x = self.value(&node.inputs[0])?;
+        if !x.nchw {
+          return Err("GlobalAveragePool needs a 4D or 5D input".to_string());
+        }
+        // The window is the whole input, its stride too
+        let window = x.shape[1..x.shape.len() - 1].to_vec();
+        let layer_type = if window.len() == 2 {
+          "AveragePool2D"
+        } else {
+          "AveragePool3D"
+        };
+        let mut params = window.clone();
+        params.extend(window.iter());
+        let mut out_shape = vec![1; x.shape.len()];
+        out_shape[0] = x.shape[0];
+        out_shape[x.shape.len() - 1] = *x.shape.last().unwrap();
+        self.add_layer(layer_type, params, &[&x], out_shape, true)
+      }
+      "Flatten" => {
+        let x = self.value(&node.inputs[0])?;
+        let shape = x.onnx_shape();
+        let axis = node.attr_i("axis", 1);
+        if axis != 1 {
+          return Err("only Flatten with axis 1 is supported".to_string());
+        }
+        let x = self.in_onnx_order(&x);
+        let out_shape = vec![shape[0], shape[1..].iter().product()];
+        self.add_layer("Reshape", vec![], &[&x], out_shape, false)
+      }
+      "Reshape" => self.import_reshape(node)?,
+      "Gather" => self.import_gather(node)?,
+      "Slice" => self.import_slice(node)?,
+      "ScatterND" => self.import_scatter_nd(node)?,
+      "Mod" => self.import_mod(node)?,
+      "If" => return self.import_if(node),
+      "Loop" => return self.import_loop(node),
+      "Scan" => return self.import_scan(node),
+      op => return Err(format!("unsupported ONNX op {}", op)),
+    };
+    self.values.insert(node.outputs[0].clone(), out);
+    Ok(())
+  }
+
+  // Imports the nodes of a subgraph with its inputs bound to the given values, and returns its
+  // outputs. Unrolled bodies are imported once per iteration under the same names, so the values
+  // of an iteration replace those of the previous one.
+  fn import_body(
+    &mut self,
+    graph: &OnnxGraph,
+    inps: Vec<(String, Value)>,
+    outs: &[String],
+  ) -> Result<Vec<Value>, String> {
+    for tensor in graph.initializers.iter() {
+      self.consts.insert(tensor.name.clone(), tensor.clone());
+    }
+    for (name, value) in inps {
+      self.values.insert(name, value);
+    }
+    for inner in graph.nodes.iter() {
+      self
+        .import_node(inner)
+        .map_err(|e| format!("{} ({}): {}", inner.op_type, inner.outputs[0], e))?;
+    }
+    outs.iter().map(|out| self.value(out)).collect()
+  }
+
+  // The values, in the ONNX order, stacked along a new first axis
+  fn stack(&mut self, values: &[Value]) -> Value {
+    let mut parts = vec![];
+    for value in values.iter() {
+      let value = self.in_onnx_order(value);
+      let shape = [vec![1], value.shape.clone()].concat();
+      parts.push(self.add_layer("Reshape", vec![], &[&value], shape, false));
+    }
+    let shape = [vec![values.len() as i64], values[0].onnx_shape()].concat();
+    let parts = parts.iter().collect::<Vec<_>>();
+    self.add_layer("Concatenation", vec![0], &parts, shape, false)
+  }
+
+  // The i-th element along the first axis of a value in the ONNX order
+  fn element(&mut self, value: &Value, i: i64) -> Value {
+    let rank = value.shape.len();
+    let mut params = vec![0; rank];
+    params[0] = i;
+    params.push(1);
+    params.extend(vec![-1; rank - 1]);
+    let shape = [vec![1], value.shape[1..].to_vec()].concat();
+    let slice = self.add_layer("Slice", params, &[value], shape, false);
+    self.add_layer(
+      "Reshape",
+      vec![],
+      &[&slice],
+      value.shape[1..].to_vec(),
+      false,
+    )
+  }
+
+  // Loops with a constant trip count are unrolled. The condition must stay true, i.e., the body
+  // passes its condition input through to its condition output, since the trip count can't
+  // depend on the data. The iteration number is a constant in every iteration.
+  fn import_loop(&mut self, node: &OnnxNode) -> Result<(), String> {
+    let trip_count = node
+      .input(0)
+      .ok_or("only loops with a trip count are supported")
+      .and_then(|name| {
+        self
+          .constant(name)
+          .map_err(|_| "the trip count must be a constant")
+      })?
+      .data
+      .get(0)
+      .map(|x| x.round() as i64)
+      .ok_or("the trip count is empty")?;
+    if trip_count < 1 {
+      return Err(format!(
+        "the trip count must be positive, got {}",
+        trip_count
+      ));
+    }
+    if let Some(name) = node.input(1) {
+      let cond = self
+        .constant(name)
+        .map_err(|_| "the loop condition must be a constant")?;
+      if cond.data.iter().any(|x| *x == 0.) {
+        return Err("the loop condition must be true".to_string());
+      }
+    }
+
+    let body = node.attr_g("body")?;
+    let num_carried = node.inputs.len() - 2;
+    if body.inputs.len() != num_carried + 2 || body.outputs.len() < num_carried + 1 {
+      return Err("the body does not match the loop carried values".to_string());
+    }
+    // The condition is only passed through, so its Identity nodes are not imported
+    let mut cond_names = vec![body.inputs[1].0.clone()];
+    let nodes = body
+      .nodes
+      .iter()
+      .filter(|inner| {
+        let is_cond = inner.op_type == "Identity" && cond_names.contains(&inner.inputs[0]);
+        if is_cond {
+          cond_names.push(inner.outputs[0].clone());
+        }
+        !is_cond
+      })
+      .cloned()
+      .collect::<Vec<_>>();
+    if !cond_names.contains(&body.outputs[0]) {
+      return Err("the loop body must pass its condition through".to_string());
+    }
+    let body = OnnxGraph { nodes, ..body };
+
+    let mut carried = node.inputs[2..]
+      .iter()
+      .map(|name| self.value(name))
+      .collect::<Result<Vec<_>, _>>()?;
+    let mut scans = vec![vec![]; body.outputs.len() - num_carried - 1];
+    for i in 0..trip_count {
+      let iteration = OnnxTensor {
+        name: body.inputs[0].0.clone(),
+        dims: vec![],
+        data: vec![i as f64],
+      };
+      self.consts.insert(iteration.name.clone(), iteration);
+      let inps = body.inputs[2..]
+        .iter()
+        .map(|(name, _)| name.clone())
+        .zip(carried.into_iter())
+        .collect();
+      let outs = self
+        .import_body(&body, inps, &body.outputs[1..])
+        .map_err(|e| format!("iteration {} {}", i, e))?;
+      carried = outs[..num_carried].to_vec();
+      for (scan, out) in scans.iter_mut().zip(outs[num_carried..].iter()) {
+        scan.push(out.clone());
+      }
+    }
+
+    let scans = scans
+      .iter()
+      .map(|scan| self.stack(scan))
+      .collect::<Vec<_>>();
+    for (name, value) in node.outputs.iter().zip(carried.into_iter().chain(scans)) {
+      self.values.insert(name.clone(), value);
+    }
+    Ok(())
+  }
+
+  // Scans are unrolled over the first axis of the scan inputs, forward only
+  fn import_scan(&mut self, node: &OnnxNode) -> Result<(), String> {
+    let num_scan = node.attr_i("num_scan_inputs", 0) as usize;
+    for name in [
+      "scan_input_axes",
+      "scan_input_directions",
+      "scan_output_axes",
+      "scan_output_directions",
+    ] {
+      if node.attr_ints(name, vec![]).iter().any(|x| *x != 0) {
+        return Err(format!("only the default {} are supported", name));
+      }
+    }
+    if num_scan == 0 || num_scan > node.inputs.len() {
+      return Err(format!("malformed num_scan_inputs {}", num_scan));
+    }
+    let num_states = node.inputs.len() - num_scan;
+
+    let body = node.attr_g("body")?;
+    if body.inputs.len() != node.inputs.len() || body.outputs.len() < num_states {
+      return Err("the body does not match the scan inputs".to_string());
+    }
+    let mut states = node.inputs[..num_states]
+      .iter()
+      .map(|name| self.value(name))
+      .collect::<Result<Vec<_>, _>>()?;
+    let scan_inps = node.inputs[num_states..]
+      .iter()
+      .map(|name| self.value(name))
+      .collect::<Result<Vec<_>, _>>()?;
+    let scan_inps = scan_inps
+      .iter()
+      .map(|x| self.in_onnx_order(x))
+      .collect::<Vec<_>>();
+    let trip_count = scan_inps[0].onnx_shape()[0];
+    if scan_inps.iter().any(|x| x.onnx_shape()[0] != trip_count) {
+      return Err("the scan inputs have different lengths".to_string());
+    }
+    if scan_inps.iter().any(|x| x.shape.len() == 5) {
+      return Err("scans over 5D inputs are not supported".to_string());
+    }
+
+    let mut scans = vec![vec![]; body.outputs.len() - num_states];
+    for i in 0..trip_count {
+      let elements = scan_inps
+        .iter()
+        .map(|x| self.element(x, i))
+        .collect::<Vec<_>>();
+      let inps = body
+        .inputs
+        .iter()
+        .map(|(name, _)| name.clone())
+        .zip(states.into_iter().chain(elements))
+        .collect();
+      let outs = self
+        .import_body(&body, inps, &body.outputs)
+        .map_err(|e| format!("iteration {} {}", i, e))?;
+      states = outs[..num_states].to_vec();
+      for (scan, out) in scans.iter_mut().zip(outs[num_states..].iter()) {
+        scan.push(out.clone());
+      }
+    }
+
+    let scans = scans
+      .iter()
+      .map(|scan| self.stack(scan))
+      .collect::<Vec<_>>();
+    for (name, value) in node.outputs.iter().zip(states.into_iter().chain(scans)) {
+      self.values.insert(name.clone(), value);
+    }
+    Ok(())
+  }
+
+  // Imports both subgraphs, and the outputs of the If are those of the branch taken
+  fn import_if(&mut self, node: &OnnxNode) -> Result<(), String> {
+    let cond = match self.values.get(&node.inputs[0]) {
+      Some(cond) => cond.clone(),
+      None => {
+        let cond = self.constant(&node.inputs[0])?;
+        self.add_tensor(&cond)
+      }
+    };
+    if cond.shape.iter().product::<i64>() != 1 {
+      return Err("the condition must have one element".to_string());
+    }
+
+    let mut sides = vec![];
+    for name in ["then_branch", "else_branch"] {
+      let graph = node.attr_g(name)?;
+      let outs = self
+        .import_body(&graph, vec![], &graph.outputs)
+        .map_err(|e| format!("{} {}", name, e))?;
+      if outs.len() != node.outputs.len() {
+        return Err(format!(
+          "{} has {} outputs, the If has {}",
+          name,
+          outs.len(),
+          node.outputs.len()
+        ));
+      }
+      sides.push(outs);
+    }
+
+    let mut branch = BranchMsgpack {
+      cond: cond.idx,
+      then_outs: vec![],
+      else_outs: vec![],
+      out_idxes: vec![],
+      taken: None,
+    };
+    for (i, name) in node.outputs.iter().enumerate() {
+      let (mut then, mut other) = (sides[0][i].clone(), sides[1][i].clone());
+      if then.nchw != other.nchw {
+        then = self.in_onnx_order(&then);
+        other = self.in_onnx_order(&other);
+      }
+      if then.shape != other.shape {
+        return Err(format!(
+          "the branches output shapes {:?} and {:?} for {}",
+          then.shape, other.shape, name
+        ));
+      }
+      let out = Value {
+        idx: self.new_idx(),
+        ..then.clone()
+      };
+      branch.then_outs.push(then.idx);
+      branch.else_outs.push(other.idx);
+      branch.out_idxes.push(out.idx);
+      self.values.insert(name.clone(), out);
+    }
+    self.branches.push(branch);
+    Ok(())
+  }
+
+  fn import_conv(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let weights = self.constant(&node.inputs[1])?;
+    if x.nchw && x.shape.len() == 5 && weights.dims.len() == 5 {
+      return self.import_conv3d(node, &x, &weights);
+    }
+    if !x.nchw || weights.dims.len() != 4 {
+      return Err("only 2D and 3D convolutions are supported".to_string());
+    }
+    if node.attr_ints("dilations", vec![1, 1]) != vec![1, 1] {
+      return Err("dilated convolutions are not supported".to_string());
+    }
+    let (n, h, w, c) = (x.shape[0], x.shape[1], x.shape[2], x.shape[3]);
+    let (o, kh, kw) = (weights.dims[0], weights.dims[2], weights.dims[3]);
+    // A group per input channel is a depthwise convolution, with o / c output channels per input
+    // channel. Other groups split the channels evenly.
+    let group = node.attr_i("group", 1);
+    let depthwise = group != 1 && group == c && weights.dims[1] == 1 && o % c == 0;
+    if group != 1 && !depthwise && (c % group != 0 || o % group != 0) {
+      return Err(format!(
+        "{} groups don't split {} input and {} output channels",
+        group, c, o
+      ));
+    }
+
+    let strides = node.attr_ints("strides", vec![1, 1]);
+    let (sh, sw) = (strides[0], strides[1]);
+    let pads = node.attr_ints("pads", vec![0; 4]);
+    let auto_pad = node.attr_s("auto_pad");
+    let (ph, pw) = (same_pads(h, kh, sh), same_pads(w, kw, sw));
+    let is_same =
+      auto_pad == "SAME_UPPER" || (pads != vec![0; 4] && pads == vec![ph.0, pw.0, ph.1, pw.1]);
+    let (padding, oh, ow) = if is_same && sh == sw {
+      (0, (h + sh - 1) / sh, (w + sw - 1) / sw)
+    } else if auto_pad == "VALID" || pads == vec![0; 4] {
+      (1, (h - kh) / sh + 1, (w - kw) / sw + 1)
+    } else {
+      return Err(format!("unsupported conv padding {:?}", pads));
+    };
+
+    // OIHW to OHWI, or to 1HWC for depthwise
+    let perm = if depthwise {
+      [1, 2, 3, 0]
+    } else {
+      [0, 2, 3, 1]
+    };
+    let weights = self.add_tensor(&permute(&weights, &perm));
+    let mut inps = vec![x.clone(), weights];
+    match node.input(2) {
+      Some(bias) => {
+        let bias = self.constant(bias)?;
+        inps.push(self.add_tensor(&bias));
+      }
+      // The depthwise conv always has a bias
+      None if depthwise => {
+        let bias = OnnxTensor {
+          name: String::new(),
+          dims: vec![o],
+          data: vec![0.; o as usize],
+        };
+        inps.push(self.add_tensor(&bias));
+      }
+      None => {}
+    }
+    let mut params = vec![depthwise as i64, padding, 0, sh, sw];
+    if group != 1 && !depthwise {
+      params.push(group);
+    }
+    let inps = inps.iter().collect::<Vec<_>>();
+    Ok(self.add_layer("Conv2D", params, &inps, vec![n, oh, ow, o], true))
+  }
+
+  // Conv over NCDHW, without groups. The padding is SAME if it is the SAME padding of every axis.
+  fn import_conv3d(
+    &mut self,
+    node: &OnnxNode,
+    x: &Value,
+    weights: &OnnxTensor,
+  ) -> Result<Value, String> {
+    if node.attr_ints("dilations", vec![1; 3]) != vec![1; 3] {
+      return Err("dilated convolutions are not supported".to_string());
+    }
+    if node.attr_i("group", 1) != 1 {
+      return Err("grouped 3D convolutions are not supported".to_string());
+    }
+    let (n, c, o) = (x.shape[0], x.shape[4], weights.dims[0]);
+    if weights.dims[1] != c {
+      return Err(format!(
+        "the weights have {} input channels, not {}",
+        weights.dims[1], c
+      ));
+    }
+
+    let strides = node.attr_ints("strides", vec![1; 3]);
+    let pads = node.attr_ints("pads", vec![0; 6]);
+    let auto_pad = node.attr_s("auto_pad");
+    let same = (0..3)
+      .map(|axis| same_pads(x.shape[axis + 1], weights.dims[axis + 2], strides[axis]))
+      .collect::<Vec<_>>();
+    let mut onnx_same = same.iter().map(|pad| pad.0).collect::<Vec<_>>();
+    onnx_same.extend(same.iter().map(|pad| pad.1));
+    let is_same = auto_pad == "SAME_UPPER" || (pads != vec![0; 6] && pads == onnx_same);
+    let (padding, out_dhw) = if is_same {
+      let out_dhw = (0..3)
+        .map(|axis| (x.shape[axis + 1] + strides[axis] - 1) / strides[axis])
+        .collect::<Vec<_>>();
+      (0, out_dhw)
+    } else if auto_pad == "VALID" || pads == vec![0; 6] {
+      let out_dhw = (0..3)
+        .map(|axis| (x.shape[axis + 1] - weights.dims[axis + 2]) / strides[axis] + 1)
+        .collect::<Vec<_>>();
+      (1, out_dhw)
+    } else {
+      return Err(format!("unsupported conv padding {:?}", pads));
+    };
+
+    // OIDHW to ODHWI
+    let weights = self.add_tensor(&permute(weights, &[0, 2, 3, 4, 1]));
+    let mut inps = vec![x.clone(), weights];
+    if let Some(bias) = node.input(2) {
+      let bias = self.constant(bias)?;
+      inps.push(self.add_tensor(&bias));
+    }
+    let params = vec![padding, 0, strides[0], strides[1], strides[2]];
+    let mut out_shape = vec![n];
+    out_shape.extend(out_dhw);
+    out_shape.push(o);
+    let inps = inps.iter().collect::<Vec<_>>();
+    Ok(self.add_layer("Conv3D", params, &inps, out_shape, true))
+  }
+
+  // MatMul of two activations, e.g., the attention scores and their product with the values. The
+  // leading axes are batch axes and must match.
+  fn import_matmul(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let a = self.value(&node.inputs[0])?;
+    let b = self.value(&node.inputs[1])?;
+    let (a, b) = (self.in_onnx_order(&a), self.in_onnx_order(&b));
+    let rank = a.shape.len();
+    if rank < 2 || b.shape.len() != rank || a.shape[..rank - 2] != b.shape[..rank - 2] {
+      return Err(format!(
+        "can't multiply {:?} and {:?}, the batch axes must match",
+        a.shape, b.shape
+      ));
+    }
+    if a.shape[rank - 1] != b.shape[rank - 2] {
+      return Err(format!("can't multiply {:?} and {:?}", a.shape, b.shape));
+    }
+    let mut out_shape = a.shape.clone();
+    out_shape[rank - 1] = b.shape[rank - 1];
+    Ok(self.add_layer("BatchMatMul", vec![0, 0], &[&a, &b], out_shape, false))
+  }
+
+  fn import_gemm(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    if node.op_type != "MatMul" || x.shape.len() <= 2 {
+      return self.fully_connected(node, &x);
+    }
+    // A MatMul of a sequence, e.g., [batch, tokens, features], with constant weights is the fully
+    // connected layer over the rows
+    let x = self.in_onnx_order(&x);
+    let rank = x.shape.len();
+    let rows = x.shape[..rank - 1].iter().product::<i64>();
+    let flat = self.add_layer(
+      "Reshape",
+      vec![],
+      &[&x],
+      vec![rows, x.shape[rank - 1]],
+      false,
+    );
+    let out = self.fully_connected(node, &flat)?;
+    let mut out_shape = x.shape.clone();
+    out_shape[rank - 1] = out.shape[1];
+    Ok(self.add_layer("Reshape", vec![], &[&out], out_shape, false))
+  }
+
+  fn fully_connected(&mut self, node: &OnnxNode, x: &Value) -> Result<Value, String> {
+    let weights = self.constant(&node.inputs[1])?;
+    if x.shape.len() != 2 || weights.dims.len() != 2 || node.attr_i("transA", 0) != 0 {
+      return Err(format!("{} needs a 2D input and 2D weights", node.op_type));
+    }
+    // The fully connected layer takes the weights as [out, in]
+    let mut weights = if node.attr_i("transB", 0) != 0 {
+      weights
+    } else {
+      permute(&weights, &[1, 0])
+    };
+    let alpha = node.attr_f("alpha", 1.);
+    weights.data.iter_mut().for_each(|x| *x *= alpha);
+    let num_out = weights.dims[0];
+    let weights = self.add_tensor(&weights);
+
+    let mut inps = vec![x.clone(), weights];
+    if let Some(bias) = node.input(2) {
+      let mut bias = self.constant(bias)?;
+      let beta = node.attr_f("beta", 1.);
+      if bias.data.len() == 1 {
+        bias.data = vec![bias.data[0]; num_out as usize];
+      }
+      if bias.data.len() as i64 != num_out {
+        return Err("the Gemm bias must have one value per output".to_string());
+      }
+      bias.data.iter_mut().for_each(|x| *x *= beta);
+      bias.dims = vec![num_out];
+      inps.push(self.add_tensor(&bias));
+    }
+    let inps = inps.iter().collect::<Vec<_>>();
+    Ok(self.add_layer(
+      "FullyConnected",
+      vec![0],
+      &inps,
+      vec![x.shape[0], num_out],
+      false,
+    ))
+  }
+
+  fn import_relu(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let inp = &node.inputs[0];
+    let x = self.value(inp)?;
+    let only_use = self.num_uses.get(inp) == Some(&1) && !self.graph_outputs.contains(inp);
+    if let Some(layer_idx) = self.producers.get(&x.idx).cloned() {
+      let layer = &mut self.layers[layer_idx];
+      let act_pos = match layer.layer_type.as_str() {
+        "Conv2D" => Some(2),
+        "Conv3D" => Some(1),
+        "FullyConnected" | "Add" => Some(0),
+        _ => None,
+      };
+      if let Some(act_pos) = act_pos {
+        if only_use && layer.params[act_pos] == 0 {
+          layer.params[act_pos] = 1;
+          return Ok(x);
+        }
+      }
+    }
+
+    // Otherwise a table over the input lookup, as the converter does for pointwise ops
+    let sf = self.options.scale_factor;
+    let x_max = (8 * sf).min((1 << (self.options.k - 1)) - 1);
+    let mut params = vec![-x_max];
+    params.extend((-x_max..=x_max).map(|x| x.max(0)));
+    Ok(self.add_layer("Tabulated", params, &[&x], x.shape.clone(), x.nchw))
+  }
+
+  fn import_add(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let (a, b) = (&node.inputs[0], &node.inputs[1]);
+    let (x, other) = match (self.values.get(a).cloned(), self.values.get(b).cloned()) {
+      (Some(x), Some(y)) => {
+        if x.nchw != y.nchw {
+          return Err("Add of 4D or 5D and lower rank activations is not supported".to_string());
+        }
+        (x, y)
+      }
+      (Some(x), None) | (None, Some(x)) => {
+        let name = if self.values.contains_key(a) { b } else { a };
+        let mut constant = self.constant(name)?;
+        if x.nchw {
+          // Broadcasts like numpy over NCHW, then moves the channels last
+          while constant.dims.len() < x.shape.len() {
+            constant.dims.insert(0, 1);
+          }
+          constant = permute(&constant, &channels_last(x.shape.len()));
+        }
+        let constant = self.add_tensor(&constant);
+        (x, constant)
+      }
+      (None, None) => return Err("Add of two constants is not supported".to_string()),
+    };
+    let out_shape = broadcast_shape(&x.shape, &other.shape);
+    Ok(self.add_layer("Add", vec![0], &[&x, &other], out_shape, x.nchw))
+  }
+
+  // Products of activations, and scaling by constants, e.g., the attention scores by
+  // 1 / sqrt(head size). Division is by a single constant, as the multiplication by its inverse.
+  fn import_mul(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let (a, b) = (&node.inputs[0], &node.inputs[1]);
+    let (x, other) = match (self.values.get(a).cloned(), self.values.get(b).cloned()) {
+      (Some(x), Some(y)) if node.op_type == "Mul" => {
+        let (x, y) = (self.in_onnx_order(&x), self.in_onnx_order(&y));
+        (x, y)
+      }
+      (Some(x), None) if node.op_type == "Div" => {
+        let mut divisor = self.constant(b)?;
+        if divisor.data.len() != 1 || divisor.data[0] == 0. {
+          return Err("only the division by one nonzero constant is supported".to_string());
+        }
+        divisor.data = vec![1. / divisor.data[0]];
+        divisor.dims = vec![1];
+        let divisor = self.add_tensor(&divisor);
+        (x, divisor)
+      }
+      (Some(x), None) | (None, Some(x)) if node.op_type == "Mul" => {
+        let name = if self.values.contains_key(a) { b } else { a };
+        let mut constant = self.constant(name)?;
+        if x.nchw && constant.data.len() != 1 {
+          while constant.dims.len() < x.shape.len() {
+            constant.dims.insert(0, 1);
+          }
+          constant = permute(&constant, &channels_last(x.shape.len()));
+        }
+        let constant = self.add_tensor(&constant);
+        (x, constant)
+      }
+      _ => {
+        return Err(format!(
+          "{} needs an activation and a constant, or two activations",
+          node.op_type
+        ))
+      }
+    };
+    let out_shape = broadcast_shape(&x.shape, &other.shape);
+    Ok(self.add_layer("Mul", vec![], &[&x, &other], out_shape, x.nchw))
+  }
+
+  // LayerNormalization over the last axis, with eps quantized to at least one
+  fn import_layer_norm(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let x = self.in_onnx_order(&x);
+    let rank = x.shape.len() as i64;
+    let axis = node.attr_i("axis", -1);
+    if axis != -1 && axis != rank - 1 {
+      return Err("only the normalization over the last axis is supported".to_string());
+    }
+    let d = x.shape[rank as usize - 1];
+    let mut gamma = self.constant(&node.inputs[1])?;
+    let mut beta = match node.input(2) {
+      Some(name) => self.constant(name)?,
+      None => OnnxTensor {
+        name: String::new(),
+        dims: vec![d],
+        data: vec![0.; d as usize],
+      },
+    };
+    if gamma.data.len() as i64 != d || beta.data.len() as i64 != d {
+      return Err("the scale and bias must have one value per feature".to_string());
+    }
+    gamma.dims = vec![d];
+    beta.dims = vec![d];
+    let (gamma, beta) = (self.add_tensor(&gamma), self.add_tensor(&beta));
+    let eps = self.quantize(node.attr_f("epsilon", 1e-5)).max(1);
+    Ok(self.add_layer(
+      "LayerNorm",
+      vec![eps],
+      &[&x, &gamma, &beta],
+      x.shape.clone(),
+      false,
+    ))
+  }
+
+  // Pools over the 2D windows of NCHW or the 3D windows of NCDHW
+  fn import_pool(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    if !x.nchw {
+      return Err(format!("{} needs a 4D or 5D input", node.op_type));
+    }
+    let dims = x.shape.len() - 2;
+    let kernel = node.attr_ints("kernel_shape", vec![]);
+    let strides = node.attr_ints("strides", vec![1; dims]);
+    let pads = node.attr_ints("pads", vec![0; 2 * dims]);
+    let auto_pad = node.attr_s("auto_pad");
+    if kernel.len() != dims
+      || strides.len() != dims
+      || pads != vec![0; 2 * dims]
+      || node.attr_i("ceil_mode", 0) != 0
+      || !["", "NOTSET", "VALID"].contains(&auto_pad.as_str())
+    {
+      return Err(format!(
+        "{} only supports {}D windows without padding",
+        node.op_type, dims
+      ));
+    }
+    let mut out_shape = vec![x.shape[0]];
+    out_shape.extend((0..dims).map(|axis| (x.shape[axis + 1] - kernel[axis]) / strides[axis] + 1));
+    out_shape.push(x.shape[dims + 1]);
+    let layer_type = match (node.op_type.as_str(), dims) {
+      ("MaxPool", 2) => "MaxPool2D",
+      ("MaxPool", _) => "MaxPool3D",
+      (_, 2) => "AveragePool2D",
+      _ => "AveragePool3D",
+    };
+    let mut params = kernel.clone();
+    params.extend(strides);
+    Ok(self.add_layer(layer_type, params, &[&x], out_shape, true))
+  }
+
+  fn import_reshape(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let inp_shape = x.onnx_shape();
+    let target = self.constant(&node.inputs[1])?;
+    let mut shape = target
+      .data
+      .iter()
+      .enumerate()
+      .map(|(i, d)| if *d == 0. { inp_shape[i] } else { *d as i64 })
+      .collect::<Vec<_>>();
+    let size = inp_shape.iter().product::<i64>();
+    if let Some(pos) = shape.iter().position(|d| *d == -1) {
+      let known = shape.iter().filter(|d| **d != -1).product::<i64>();
+      shape[pos] = size / known;
+    }
+    if shape.iter().product::<i64>() != size {
+      return Err(format!("can't reshape {:?} to {:?}", inp_shape, shape));
+    }
+
+    let x = self.in_onnx_order(&x);
+    let out = self.add_layer("Reshape", vec![], &[&x], shape.clone(), false);
+    if shape.len() != 4 && shape.len() != 5 {
+      return Ok(out);
+    }
+    // Back to channels last
+    let perm = channels_last(shape.len());
+    let mut params = shape.clone();
+    params.extend(perm.iter().map(|x| *x as i64));
+    let out_shape = perm.iter().map(|axis| shape[*axis]).collect();
+    Ok(self.add_layer("Transpose", params, &[&out], out_shape, true))
+  }
+
+  // Constant indices are copied into the params. Otherwise the indices are looked up with
+  // selectors (see GatherChip), and constant data, e.g., an embedding table, is added as weights.
+  fn import_gather(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let data = self.value_or_constant(&node.inputs[0])?;
+    let rank = data.shape.len() as i64;
+    let axis = node.attr_i("axis", 0);
+    let axis = if axis < 0 { axis + rank } else { axis };
+    if axis < 0 || axis >= rank {
+      return Err(format!("axis {} is out of the rank {}", axis, rank));
+    }
+    let dim = data.shape[axis as usize];
+
+    let (params, indices, idx_shape) = match self.consts.get(&node.inputs[1]).cloned() {
+      Some(indices) => {
+        if indices.data.is_empty() {
+          return Err("Gather needs at least one index".to_string());
+        }
+        let mut params = vec![axis];
+        for idx in indices.data.iter() {
+          let idx = *idx as i64;
+          let idx = if idx < 0 { idx + dim } else { idx };
+          if idx < 0 || idx >= dim {
+            return Err(format!("index {} is out of the axis of {}", idx, dim));
+          }
+          params.push(idx);
+        }
+        (params, None, indices.dims)
+      }
+      None => {
+        let indices = self.value(&node.inputs[1])?;
+        let indices = self.in_onnx_order(&indices);
+        let shape = indices.shape.clone();
+        (vec![axis], Some(indices), shape)
+      }
+    };
+
+    let axis = axis as usize;
+    let out_shape = [&data.shape[..axis], &idx_shape, &data.shape[axis + 1..]].concat();
+    let inps = [Some(&data), indices.as_ref()]
+      .into_iter()
+      .flatten()
+      .collect::<Vec<_>>();
+    Ok(self.add_layer("Gather", params, &inps, out_shape, false))
+  }
+
+  // The bounds are clamped to the axes as in ONNX. Older opsets have them as attributes.
+  fn import_slice(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let x = self.in_onnx_order(&x);
+    let rank = x.shape.len() as i64;
+
+    let ints = |i: usize| -> Result<Option<Vec<i64>>, String> {
+      match node.input(i) {
+        Some(name) => {
+          let tensor = self.constant(name)?;
+          Ok(Some(tensor.data.iter().map(|x| *x as i64).collect()))
+        }
+        None => Ok(None),
+      }
+    };
+    let (starts, ends, axes, steps) = if node.inputs.len() == 1 {
+      (
+        node.attr_ints("starts", vec![]),
+        node.attr_ints("ends", vec![]),
+        node.attr("axes").map(|attr| attr.ints.clone()),
+        None,
+      )
+    } else {
+      (
+        ints(1)?.ok_or("Slice needs starts")?,
+        ints(2)?.ok_or("Slice needs ends")?,
+        ints(3)?,
+        ints(4)?,
+      )
+    };
+    let axes = axes.unwrap_or((0..starts.len() as i64).collect());
+    let steps = steps.unwrap_or(vec![1; starts.len()]);
+    if ends.len() != starts.len() || axes.len() != starts.len() || steps.len() != starts.len() {
+      return Err("Slice needs as many starts, ends, axes, and steps".to_string());
+    }
+    if steps.iter().any(|step| *step != 1) {
+      return Err("only Slice with unit steps is supported".to_string());
+    }
+
+    let mut begin = vec![0; rank as usize];
+    let mut out_shape = x.shape.clone();
+    for ((start, end), axis) in starts.iter().zip(ends.iter()).zip(axes.iter()) {
+      let axis = if *axis < 0 { axis + rank } else { *axis };
+      if axis < 0 || axis >= rank {
+        return Err(format!("axis {} is out of the rank {}", axis, rank));
+      }
+      let dim = x.shape[axis as usize];
+      let clamp = |i: i64| if i < 0 { (i + dim).max(0) } else { i.min(dim) };
+      let (start, end) = (clamp(*start), clamp(*end));
+      if end <= start {
+        return Err(format!(
+          "the slice {}..{} of axis {} is empty",
+          start, end, axis
+        ));
+      }
+      begin[axis as usize] = start;
+      out_shape[axis as usize] = end - start;
+    }
+    let mut params = begin;
+    params.extend(out_shape.iter());
+    Ok(self.add_layer("Slice", params, &[&x], out_shape, false))
+  }
+
+  fn import_scatter_nd(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    if !["", "none"].contains(&node.attr_s("reduction").as_str()) {
+      return Err("only ScatterND without reduction is supported".to_string());
+    }
+    let indices = self
+      .constant(&node.inputs[1])
+      .map_err(|_| "ScatterND needs constant indices".to_string())?;
+    let data = self.value_or_constant(&node.inputs[0])?;
+    let updates = self.value_or_constant(&node.inputs[2])?;
+
+    let k = *indices
+      .dims
+      .last()
+      .ok_or("ScatterND needs indices of rank 1 or more")?;
+    if k < 1 || k > data.shape.len() as i64 {
+      return Err(format!(
+        "index tuples of {} don't fit the rank {}",
+        k,
+        data.shape.len()
+      ));
+    }
+    let mut params = vec![k];
+    for (i, idx) in indices.data.iter().enumerate() {
+      let dim = data.shape[i % k as usize];
+      let idx = *idx as i64;
+      let idx = if idx < 0 { idx + dim } else { idx };
+      if idx < 0 || idx >= dim {
+        return Err(format!("index {} is out of the axis of {}", idx, dim));
+      }
+      params.push(idx);
+    }
+    let slice_len = data.shape[k as usize..].iter().product::<i64>();
+    let num_updates = updates.shape.iter().product::<i64>();
+    if num_updates != indices.data.len() as i64 / k * slice_len {
+      return Err(format!(
+        "{} updates don't fit {} slices of {}",
+        num_updates,
+        indices.data.len() as i64 / k,
+        slice_len
+      ));
+    }
+    let out_shape = data.shape.clone();
+    Ok(self.add_layer("ScatterND", params, &[&data, &updates], out_shape, false))
+  }
+
+  // Mod by one positive constant. Both are at the scale factor, so the remainder of the fixed
+  // point values is the fixed point remainder. fmod takes the sign of the dividend, i.e.,
+  // truncates, and otherwise the sign of the divisor, i.e., floors.
+  fn import_mod(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let divisor = self
+      .constant(&node.inputs[1])
+      .map_err(|_| "Mod needs a constant divisor".to_string())?;
+    if divisor.data.len() != 1 || self.quantize(divisor.data[0]) <= 0 {
+      return Err("only Mod by one positive constant is supported".to_string());
+    }
+    let mode = if node.attr_i("fmod", 0) == 1 { 2 } else { 0 };
+    let params = vec![mode, 1, self.quantize(divisor.data[0])];
+    Ok(self.add_layer("DivMod", params, &[&x], x.shape.clone(), x.nchw))
+  }
+
+  // Dropout is the identity at inference. With a constant training_mode of true, e.g., for MC
+  // dropout, it is a Dropout layer whose masks are derived from the seed (see randomness.rs).
+  fn import_dropout(&mut self, node: &OnnxNode) -> Result<Value, String> {
+    let x = self.value(&node.inputs[0])?;
+    let training = match node.input(2) {
+      Some(name) => self
+        .constant(name)?
+        .data
+        .first()
+        .map_or(false, |x| *x != 0.),
+      None => false,
+    };
+    if !training {
+      return Ok(x);
+    }
+    let ratio = match node.input(1) {
+      Some(name) => self.constant(name)?.data.first().cloned().unwrap_or(0.5),
+      None => 0.5,
+    };
+    if !(0. ..1.).contains(&ratio) || self.quantize(1. - ratio) < 1 {
+      return Err(format!("dropout ratio {} is out of range", ratio));
+    }
+    let params = vec![self.quantize(1. - ratio)];
+    Ok(self.add_layer("Dropout", params, &[&x], x.shape.clone(), x.nchw))
+  }
+}
+
+// Imports the ONNX graph and quantizes the inputs. The inputs are in the ONNX layout, one
+// flattened float array per graph input.
+pub fn onnx_to_msgpack(
+  onnx: &[u8],
+  inputs: &[Vec<f64>],
+  options: &OnnxOptions,
+) -> Result<ModelMsgpack, String> {
+  let graph = parse_model(onnx)?;
+  let mut num_uses = HashMap::new();
+  count_uses(&graph, &mut num_uses);
+  let mut importer = Importer {
+    options: options.clone(),
+    consts: graph
+      .initializers
+      .iter()
+      .map(|tensor| (tensor.name.clone(), tensor.clone()))
+      .collect(),
+    values: HashMap::new(),
+    num_uses,
+    graph_outputs: graph.outputs.clone(),
+    producers: HashMap::new(),
+    tensors: vec![],
+    layers: vec![],
+    branches: vec![],
+    next_idx: 0,
+  };
+
+  // Older exporters also list the initializers as graph inputs
+  let graph_inputs = graph
+    .inputs
+    .iter()
+    .filter(|(name, _)| !importer.consts.contains_key(name))
+    .collect::<Vec<_>>();
+  if graph_inputs.len() != inputs.len() {
+    return Err(format!(
+      "the graph has {} inputs, but {} were given",
+      graph_inputs.len(),
+      inputs.len()
+    ));
+  }
+  // The graph inputs that are the indices of a gather, e.g., token ids
+  let index_inputs = graph
+    .nodes
+    .iter()
+    .filter(|node| node.op_type == "Gather")
+    .filter_map(|node| node.inputs.get(1))
+    .collect::<HashSet<_>>();
+  let mut inp_idxes = vec![];
+  for ((name, shape), data) in graph_inputs.into_iter().zip(inputs.iter()) {
+    // Only the batch dimension can be unknown, and is then 1
+    let mut dims = shape.clone();
+    if dims.first() == Some(&-1) {
+      dims[0] = 1;
+    }
+    if dims.iter().any(|d| *d < 0) || dims.iter().product::<i64>() != data.len() as i64 {
+      return Err(format!(
+        "input {} of shape {:?} got {} values",
+        name,
+        shape,
+        data.len()
+      ));
+    }
+    let tensor = OnnxTensor {
+      name: name.clone(),
+      dims: dims.clone(),
+      data: data.clone(),
+    };
+    let nchw = dims.len() == 4 || dims.len() == 5;
+    let tensor = if nchw {
+      permute(&tensor, &channels_last(dims.len()))
+    } else {
+      tensor
+    };
+    let mut value = if index_inputs.contains(name) {
+      importer.add_int_tensor(&tensor)
+    } else {
+      importer.add_tensor(&tensor)
+    };
+    value.nchw = nchw;
+    inp_idxes.push(value.idx);
+    importer.values.insert(name.clone(), value);
+  }
+
+  for node in graph.nodes.iter() {
+    importer
+      .import_node(node)
+      .map_err(|e| format!("{} ({}): {}", node.op_type, node.outputs[0], e))?;
+  }
+
+  let mut out_idxes = vec![];
+  for name in graph.outputs.iter() {
+    let value = importer.value(name)?;
+    let value = importer.in_onnx_order(&value);
+    out_idxes.push(value.idx);
+  }
+
+  // The seed of the Dropout layers is an input, 0 unless it is set
+  let random_seed = if importer.layers.iter().any(|l| l.layer_type == "Dropout") {
+    let seed = importer.add_int_tensor(&OnnxTensor {
+      name: "random_seed".to_string(),
+      dims: vec![1],
+      data: vec![0.],
+    });
+    inp_idxes.push(seed.idx);
+    Some(seed.idx)
+  } else {
+    None
+  };
+
+  let branches = if importer.branches.is_empty() {
+    None
+  } else {
+    Some(importer.branches)
+  };
+  let mut model = ModelMsgpack {
+    global_sf: options.scale_factor,
+    k: options.k,
+    num_cols: options.num_cols,
+    inp_idxes,
+    out_idxes,
+    tensors: importer.tensors,
+    layers: importer.layers,
+    branches,
+    random_seed,
+    ..Default::default()
+  };
+  set_defaults(&mut model);
+  Ok(model)
+}
+
+// The inputs are a JSON list with a flattened float array per graph input
+pub fn load_onnx(
+  onnx_path: &str,
+  inp_path: &str,
+  options: &OnnxOptions,
+) -> Result<ModelMsgpack, String> {
+  let inputs: Vec<Vec<f64>> = serde_json::from_slice(&read_artifact(inp_path)?)
+    .map_err(|e| format!("malformed inputs {}: {}", inp_path, e))?;
+  onnx_to_msgpack(&read_artifact(onnx_path)?, &inputs, options)
+}
@@ -0,0 +1,304 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack};
+
+// Requantization fusion. Every Mul rescales its product by the scale factor, so a chain like
+// conv -> mul by a constant -> add a constant rounds once per op. The pass folds multiplications
+// and additions by constants that directly follow a Conv2D or FullyConnected layer without an
+// activation into its weights and bias, and merges consecutive multiplications by constants, so
+// that the chain rescales once.
+// The conv and fully connected layers add the bias after the rescale, so a folded addition is
+// exact. A folded multiplication rounds the scaled weights instead of the output, which can shift
+// the outputs by the rounding error of the weights times the inputs.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fusion {
+  pub layer_type: String,
+  pub fused_type: String,
+  pub tensor_idx: i64, // The intermediate tensor that was removed
+  pub removed_divisions: usize,
+}
+
+// Rounds half up, like the division gadgets
+fn div_round(x: i128, d: i128) -> i64 {
+  (2 * x + d).div_euclid(2 * d) as i64
+}
+
+fn num_uses(model: &ModelMsgpack, idx: i64) -> usize {
+  model
+    .layers
+    .iter()
+    .map(|layer| layer.inp_idxes.iter().filter(|x| **x == idx).count())
+    .sum()
+}
+
+fn get_tensor(model: &ModelMsgpack, idx: i64) -> Option<&TensorMsgpack> {
+  if model.inp_idxes.contains(&idx) {
+    return None;
+  }
+  model.tensors.iter().find(|tensor| tensor.idx == idx)
+}
+
+fn next_idx(model: &ModelMsgpack) -> i64 {
+  let tensor_idxes = model.tensors.iter().map(|tensor| tensor.idx);
+  let layer_idxes = model.layers.iter().flat_map(|layer| {
+    layer
+      .inp_idxes
+      .iter()
+      .chain(layer.out_idxes.iter())
+      .cloned()
+  });
+  tensor_idxes.chain(layer_idxes).max().unwrap_or(-1) + 1
+}
+
+// The tensor to rewrite for a layer, copied to a new index if other layers also use it. The copy
+// is committed along with the original.
+fn own_tensor(model: &mut ModelMsgpack, idx: i64) -> i64 {
+  if num_uses(model, idx) <= 1 {
+    return idx;
+  }
+  let new_idx = next_idx(model);
+  let mut tensor = get_tensor(model, idx).unwrap().clone();
+  tensor.idx = new_idx;
+  model.tensors.push(tensor);
+  add_to_commit_groups(model, idx, new_idx);
+  new_idx
+}
+
+fn add_to_commit_groups(model: &mut ModelMsgpack, idx: i64, new_idx: i64) {
+  if let Some(groups) = model.commit_before.as_mut() {
+    for group in groups.iter_mut().filter(|group| group.contains(&idx)) {
+      group.push(new_idx);
+    }
+  }
+}
+
+fn tensor_mut(model: &mut ModelMsgpack, idx: i64) -> &mut TensorMsgpack {
+  model
+    .tensors
+    .iter_mut()
+    .find(|tensor| tensor.idx == idx)
+    .unwrap()
+}
+
+// The value of a constant for every channel, if it only varies along the last axis
+fn channel_values(tensor: &TensorMsgpack, num_channels: usize) -> Option<Vec<i64>> {
+  if tensor.data.len() == 1 {
+    return Some(vec![tensor.data[0]; num_channels]);
+  }
+  let channels = *tensor.shape.last()? as usize;
+  if channels == num_channels && tensor.data.len() == num_channels {
+    Some(tensor.data.clone())
+  } else {
+    None
+  }
+}
+
+// The position of the activation param, and the output channel axis of the weights
+fn linear_layout(layer: &LayerMsgpack) -> Option<(usize, usize)> {
+  match layer.layer_type.as_str() {
+    "Conv2D" => Some((2, if layer.params[0] == 1 { 3 } else { 0 })),
+    "Conv3D" => Some((1, 0)),
+    "FullyConnected" => Some((0, 0)),
+    _ => None,
+  }
+}
+
+fn channel_of(shape: &Vec<i64>, axis: usize, pos: usize) -> usize {
+  let stride = shape[axis + 1..].iter().product::<i64>() as usize;
+  (pos / stride) % shape[axis] as usize
+}
+
+// The constant operand of a Mul or Add, and the position of the other operand
+fn constant_operand<'a>(
+  model: &'a ModelMsgpack,
+  layer: &LayerMsgpack,
+) -> Option<(usize, &'a TensorMsgpack)> {
+  if layer.inp_idxes.len() != 2 {
+    return None;
+  }
+  match (
+    get_tensor(model, layer.inp_idxes[0]),
+    get_tensor(model, layer.inp_idxes[1]),
+  ) {
+    (None, Some(c)) => Some((0, c)),
+    (Some(c), None) => Some((1, c)),
+    _ => None,
+  }
+}
+
+// The layer that consumes the only output of the layer, if the output is not used elsewhere
+fn single_consumer(model: &ModelMsgpack, layer_idx: usize) -> Option<usize> {
+  let layer = &model.layers[layer_idx];
+  if layer.out_idxes.len() != 1 {
+    return None;
+  }
+  let out = layer.out_idxes[0];
+  let committed = model
+    .commit_after
+    .iter()
+    .flatten()
+    .any(|group| group.contains(&out));
+  if model.out_idxes.contains(&out) || committed || num_uses(model, out) != 1 {
+    return None;
+  }
+  let consumer = model
+    .layers
+    .iter()
+    .position(|layer| layer.inp_idxes.contains(&out))?;
+  // Broadcasting ops change the shape and can't be folded
+  if consumer <= layer_idx || model.layers[consumer].out_shapes != layer.out_shapes {
+    return None;
+  }
+  Some(consumer)
+}
+
+fn fuse_into_linear(model: &mut ModelMsgpack, layer_idx: usize, consumer: usize) -> Option<Fusion> {
+  let sf = model.global_sf as i128;
+  let layer = model.layers[layer_idx].clone();
+  let op = model.layers[consumer].clone();
+  let (act_pos, axis) = linear_layout(&layer)?;
+  // The weights and bias are rewritten, so they must be constants
+  let constant_params = layer.inp_idxes[1..]
+    .iter()
+    .all(|idx| get_tensor(model, *idx).is_some());
+  if layer.params[act_pos] != 0 || !constant_params {
+    return None;
+  }
+  let is_add = match op.layer_type.as_str() {
+    "Add" => op.params.get(0).map_or(true, |act| *act == 0),
+    "Mul" => false,
+    _ => return None,
+  };
+  let (_, constant) = constant_operand(model, &op)?;
+  let num_channels = *layer.out_shapes[0].last()? as usize;
+  let values = channel_values(constant, num_channels)?;
+  let num_outputs = layer.out_shapes[0].iter().product::<i64>() as usize;
+
+  let mut layer = layer;
+  if is_add {
+    if layer.inp_idxes.len() == 3 {
+      let bias_idx = own_tensor(model, layer.inp_idxes[2]);
+      layer.inp_idxes[2] = bias_idx;
+      let bias = tensor_mut(model, bias_idx);
+      for (i, x) in bias.data.iter_mut().enumerate() {
+        *x += values[i % num_channels];
+      }
+    } else {
+      let bias_idx = next_idx(model);
+      model.tensors.push(TensorMsgpack {
+        idx: bias_idx,
+        shape: vec![num_channels as i64],
+        data: values,
+        dtype: None,
+      });
+      add_to_commit_groups(model, layer.inp_idxes[1], bias_idx);
+      layer.inp_idxes.push(bias_idx);
+      layer.inp_shapes.push(vec![num_channels as i64]);
+    }
+  } else {
+    let weight_idx = own_tensor(model, layer.inp_idxes[1]);
+    layer.inp_idxes[1] = weight_idx;
+    let weights = tensor_mut(model, weight_idx);
+    let shape = weights.shape.clone();
+    for (i, x) in weights.data.iter_mut().enumerate() {
+      let m = values[channel_of(&shape, axis, i)] as i128;
+      *x = div_round(*x as i128 * m, sf);
+    }
+    if layer.inp_idxes.len() == 3 {
+      let bias_idx = own_tensor(model, layer.inp_idxes[2]);
+      layer.inp_idxes[2] = bias_idx;
+      let bias = tensor_mut(model, bias_idx);
+      for (i, x) in bias.data.iter_mut().enumerate() {
+        *x = div_round(*x as i128 * values[i % num_channels] as i128, sf);
+      }
+    }
+  }
+
+  let fusion = Fusion {
+    layer_type: layer.layer_type.clone(),
+    fused_type: op.layer_type.clone(),
+    tensor_idx: layer.out_idxes[0],
+    removed_divisions: if is_add { 0 } else { num_outputs },
+  };
+  layer.out_idxes = op.out_idxes.clone();
+  model.layers[layer_idx] = layer;
+  model.layers.remove(consumer);
+  Some(fusion)
+}
+
+// Mul by c1 then by c2 is a single Mul by c1 * c2 / sf
+fn fuse_muls(model: &mut ModelMsgpack, layer_idx: usize, consumer: usize) -> Option<Fusion> {
+  let sf = model.global_sf as i128;
+  let layer = model.layers[layer_idx].clone();
+  let op = model.layers[consumer].clone();
+  if layer.layer_type != "Mul" || op.layer_type != "Mul" {
+    return None;
+  }
+  let (other_pos, c1) = constant_operand(model, &layer)?;
+  let (_, c2) = constant_operand(model, &op)?;
+  if c1.shape != c2.shape && c2.data.len() != 1 {
+    return None;
+  }
+  let c2 = c2.data.clone();
+  let num_outputs = layer.out_shapes[0].iter().product::<i64>() as usize;
+
+  let mut layer = layer;
+  let c1_idx = own_tensor(model, layer.inp_idxes[1 - other_pos]);
+  layer.inp_idxes[1 - other_pos] = c1_idx;
+  let c1 = tensor_mut(model, c1_idx);
+  for (i, x) in c1.data.iter_mut().enumerate() {
+    *x = div_round(*x as i128 * c2[i % c2.len()] as i128, sf);
+  }
+
+  let fusion = Fusion {
+    layer_type: layer.layer_type.clone(),
+    fused_type: op.layer_type.clone(),
+    tensor_idx: layer.out_idxes[0],
+    removed_divisions: num_outputs,
+  };
+  layer.out_idxes = op.out_idxes.clone();
+  model.layers[layer_idx] = layer;
+  model.layers.remove(consumer);
+  Some(fusion)
+}
+
+// Applies the fusions until none applies, and returns them in order. The fusions only modify the
+// model once they apply.
+pub fn fuse_requantization(model: &mut ModelMsgpack) -> Vec<Fusion> {
+  let mut fusions = vec![];
+  loop {
+    let fusion = (0..model.layers.len()).find_map(|layer_idx| {
+      let consumer = single_consumer(model, layer_idx)?;
+      fuse_into_linear(model, layer_idx, consumer).or_else(|| fuse_muls(model, layer_idx, consumer))
+    });
+    match fusion {
+      Some(fusion) => fusions.push(fusion),
+      None => break,
+    }
+  }
+  fusions
+}
+
+// The output rows of the matmul a layer lowers to, if it uses the fully connected chip
+fn matmul_rows(layer: &LayerMsgpack) -> Option<i64> {
+  let shape = &layer.out_shapes[0];
+  match layer.layer_type.as_str() {
+    "Conv2D" if layer.params[0] == 1 => None,
+    "Conv2D" | "Conv3D" | "FullyConnected" | "BatchMatMul" => {
+      Some(shape[..shape.len() - 1].iter().product())
+    }
+    _ => None,
+  }
+}
+
+// A tile as tall as the largest matmul output keeps every output column of every matmul in a
+// single advice column
+pub fn default_gemm_tile_size(model: &ModelMsgpack) -> i64 {
+  model
+    .layers
+    .iter()
+    .filter_map(matmul_rows)
+    .max()
+    .unwrap_or(0)
+}
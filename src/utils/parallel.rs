@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use halo2_proofs::{circuit::AssignedCell, halo2curves::ff::PrimeField};
+
+use crate::utils::cpu::default_threads;
+
+// Witness generation spends most of its time computing the values of the large matrix products,
+// of the conv2d and fully connected layers. The assignment of the regions stays sequential: a
+// region is taken mutably by the layouter and the cells are Rc'd, so they can't cross threads.
+// With the parallel-witness feature, the values of a product are computed first, on a rayon pool
+// of ZKML_NUM_THREADS threads (one per performance core by default, see cpu.rs), and then assigned
+// in order.
+pub const WITNESS_THREADS_VAR: &str = "ZKML_NUM_THREADS";
+
+static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+// Overrides ZKML_NUM_THREADS. Must be called before the first synthesis, the pool keeps its size.
+pub fn set_witness_threads(num_threads: usize) {
+  NUM_THREADS.store(num_threads, Ordering::Relaxed);
+}
+
+pub fn witness_threads() -> usize {
+  match NUM_THREADS.load(Ordering::Relaxed) {
+    0 => std::env::var(WITNESS_THREADS_VAR)
+      .ok()
+      .and_then(|x| x.parse().ok())
+      .unwrap_or_else(default_threads),
+    n => n,
+  }
+}
+
+// The values of the cells, or None if any is unknown, e.g., in keygen
+pub fn known_values<'a, F: PrimeField>(
+  cells: impl Iterator<Item = &'a AssignedCell<F, F>>,
+) -> Option<Vec<F>> {
+  cells
+    .map(|cell| {
+      let mut val = None;
+      cell.value().map(|x| val = Some(*x));
+      val
+    })
+    .collect()
+}
+
+#[cfg(feature = "parallel-witness")]
+fn pool() -> &'static rayon::ThreadPool {
+  static POOL: once_cell::sync::OnceCell<rayon::ThreadPool> = once_cell::sync::OnceCell::new();
+  POOL.get_or_init(|| {
+    let num_threads = witness_threads();
+    info!("witness generation uses {} threads", num_threads);
+    rayon::ThreadPoolBuilder::new()
+      .num_threads(num_threads)
+      .build()
+      .unwrap()
+  })
+}
+
+// The row major (n, m) product of the row major (n, k) lhs and (k, m) rhs
+#[cfg(feature = "parallel-witness")]
+pub fn matmul<F: PrimeField>(lhs: &[F], rhs: &[F], n: usize, k: usize, m: usize) -> Vec<F> {
+  use rayon::prelude::*;
+  assert_eq!(lhs.len(), n * k);
+  assert_eq!(rhs.len(), k * m);
+  pool().install(|| {
+    (0..n * m)
+      .into_par_iter()
+      .map(|idx| {
+        let (i, j) = (idx / m, idx % m);
+        (0..k).fold(F::ZERO, |sum, l| sum + lhs[i * k + l] * rhs[l * m + j])
+      })
+      .collect()
+  })
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  cancel::{StageMetric, STAGES},
+  cpu::{core_topology, cpu_model, CoreTopology},
+  keygen::{keygen_threads, proving_threads},
+  loader::ModelMsgpack,
+};
+
+// Performance snapshots for bug reports and the cost model. Only opt-in, with --perf-report, and
+// only contain the hardware, the circuit shape, and the timings: no paths, host names, or tensor
+// data.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HardwareInfo {
+  pub os: String,
+  pub arch: String,
+  pub cpu_model: Option<String>,
+  pub num_cpus: usize,
+  pub memory_kb: Option<u64>,
+  // The performance and efficiency cores of big.LITTLE machines
+  #[serde(default)]
+  pub cores: Option<CoreTopology>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PerfReport {
+  pub zkml_version: String,
+  pub hardware: HardwareInfo,
+  pub k: i64,
+  pub num_cols: i64,
+  pub backend: String,
+  pub layers: BTreeMap<String, usize>,
+  pub num_weights: usize,
+  pub stages: Vec<StageMetric>,
+  pub peak_memory_kb: Option<u64>,
+  // The vkey and pkey stages, if the run generated the keys
+  pub keygen_seconds: Option<f64>,
+  pub keygen_threads: Option<usize>,
+  // The threads the proof ran on, e.g., in a scaling sweep (see testing/bench.sh)
+  #[serde(default)]
+  pub threads: Option<usize>,
+}
+
+// The value of a "<key>: <value>" line in a /proc file
+fn proc_field(path: &str, key: &str) -> Option<String> {
+  let contents = std::fs::read_to_string(path).ok()?;
+  contents.lines().find_map(|line| {
+    let (name, value) = line.split_once(':')?;
+    if name.trim() == key {
+      Some(value.trim().to_string())
+    } else {
+      None
+    }
+  })
+}
+
+fn proc_kb(path: &str, key: &str) -> Option<u64> {
+  proc_field(path, key)?
+    .split_whitespace()
+    .next()?
+    .parse()
+    .ok()
+}
+
+impl HardwareInfo {
+  pub fn detect() -> Self {
+    Self {
+      os: std::env::consts::OS.to_string(),
+      arch: std::env::consts::ARCH.to_string(),
+      cpu_model: proc_field("/proc/cpuinfo", "model name").or_else(cpu_model),
+      num_cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+      memory_kb: proc_kb("/proc/meminfo", "MemTotal"),
+      cores: core_topology(),
+    }
+  }
+}
+
+impl PerfReport {
+  // Uses the stages recorded so far
+  pub fn new(config: &ModelMsgpack, backend: &str) -> Self {
+    let mut layers = BTreeMap::new();
+    for layer in config.layers.iter() {
+      *layers.entry(layer.layer_type.clone()).or_insert(0) += 1;
+    }
+    let num_weights = config
+      .tensors
+      .iter()
+      .filter(|tensor| !config.inp_idxes.contains(&tensor.idx))
+      .map(|tensor| tensor.data.len())
+      .sum();
+
+    let stages = STAGES.lock().unwrap().clone();
+    let keygen_stages = stages
+      .iter()
+      .filter(|stage| stage.stage == "vkey" || stage.stage == "pkey")
+      .collect::<Vec<_>>();
+    let keygen_seconds = if keygen_stages.is_empty() {
+      None
+    } else {
+      Some(keygen_stages.iter().map(|stage| stage.seconds).sum())
+    };
+
+    Self {
+      zkml_version: env!("CARGO_PKG_VERSION").to_string(),
+      hardware: HardwareInfo::detect(),
+      k: config.k,
+      num_cols: config.num_cols,
+      backend: backend.to_string(),
+      layers,
+      num_weights,
+      stages,
+      peak_memory_kb: proc_kb("/proc/self/status", "VmHWM"),
+      keygen_seconds,
+      keygen_threads: keygen_seconds.map(|_| keygen_threads()),
+      threads: Some(proving_threads()),
+    }
+  }
+
+  pub fn write(&self, path: &str) {
+    std::fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+  }
+}
@@ -0,0 +1,117 @@
+use std::{
+  fs::File,
+  io::{BufReader, BufWriter, Write},
+};
+
+use halo2_proofs::{
+  halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    group::GroupEncoding,
+  },
+  plonk::ProvingKey,
+  poly::{commitment::Params, kzg::commitment::ParamsKZG},
+  SerdeFormat,
+};
+
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    artifacts::content_hash,
+    disk_pk::with_disk_pk,
+    envelope::circuit_layout,
+    keygen::keygen_kzg,
+    loader::{model_to_msgpack, ModelMsgpack},
+    storage::{artifact_exists, is_remote, local_path, read_artifact, write_artifact},
+    weight_cache::weights_hash,
+  },
+};
+
+// Keygen dominates the time to prove a single input, but the proving key only depends on the
+// circuit layout, the weights, and the SRS. The key is written to <name>.pkey in the raw halo2
+// format, and the hash of what it was generated for to <name>.pkey.hash, so that the key of an
+// edited model or another SRS is regenerated instead of producing proofs that fail to verify.
+pub fn pk_hash_path(pk_fname: &str) -> String {
+  format!("{}.hash", pk_fname)
+}
+
+// The hash of the layout without the inputs, which is the same with or without the input file
+pub fn layout_hash(config: &ModelMsgpack) -> String {
+  let mut layout = circuit_layout(config);
+  layout
+    .tensors
+    .retain(|tensor| !config.inp_idxes.contains(&tensor.idx));
+  content_hash(&model_to_msgpack(&layout))
+}
+
+// The hash of the layout without the inputs, of the weights, and of the SRS, which is identified
+// by its size and its first power of tau
+pub fn pk_hash(config: &ModelMsgpack, params: &ParamsKZG<Bn256>) -> String {
+  let layout_hash = layout_hash(config);
+  let srs_id = content_hash(params.get_g()[1].to_bytes().as_ref());
+  let hashes = format!(
+    "{}{}{}{}",
+    layout_hash,
+    weights_hash(config),
+    params.k(),
+    srs_id
+  );
+  content_hash(hashes.as_bytes())
+}
+
+pub fn write_pk(
+  pk_fname: &str,
+  config: &ModelMsgpack,
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+) -> Result<(), String> {
+  if is_remote(pk_fname) {
+    write_artifact(pk_fname, &pk.to_bytes(SerdeFormat::RawBytes))?;
+  } else {
+    // Streamed to the file, since a copy of the key may not fit in memory
+    let file = File::create(pk_fname).map_err(|e| format!("{}: {}", pk_fname, e))?;
+    let mut writer = BufWriter::new(file);
+    pk.write(&mut writer, SerdeFormat::RawBytes)
+      .and_then(|_| writer.flush())
+      .map_err(|e| format!("{}: {}", pk_fname, e))?;
+  }
+  write_artifact(&pk_hash_path(pk_fname), pk_hash(config, params).as_bytes())
+}
+
+// The cached proving key, or None if there is no key for this config. The circuit of the config
+// must have been generated first, since reading the key needs the gadget config.
+pub fn read_pk(
+  pk_fname: &str,
+  config: &ModelMsgpack,
+  params: &ParamsKZG<Bn256>,
+) -> Result<Option<ProvingKey<G1Affine>>, String> {
+  let hash_fname = pk_hash_path(pk_fname);
+  if !artifact_exists(pk_fname) || !artifact_exists(&hash_fname) {
+    return Ok(None);
+  }
+  if read_artifact(&hash_fname)? != pk_hash(config, params).as_bytes() {
+    return Ok(None);
+  }
+  let file = File::open(local_path(pk_fname)?).map_err(|e| format!("{}: {}", pk_fname, e))?;
+  let mut reader = BufReader::new(file);
+  let pk = with_disk_pk(|| {
+    ProvingKey::read::<_, ModelCircuit<Fr>>(&mut reader, SerdeFormat::RawBytes, ())
+  })
+  .map_err(|e| format!("malformed proving key {}: {}", pk_fname, e))?;
+  Ok(Some(pk))
+}
+
+// Reads the cached proving key, or generates it and writes it for the next proofs
+pub fn load_or_keygen_pk(
+  pk_fname: &str,
+  config: &ModelMsgpack,
+  params: &ParamsKZG<Bn256>,
+  circuit: &ModelCircuit<Fr>,
+) -> Result<ProvingKey<G1Affine>, String> {
+  if let Some(pk) = read_pk(pk_fname, config, params)? {
+    info!("Loaded the proving key from {}", pk_fname);
+    return Ok(pk);
+  }
+  let pk = keygen_kzg(params, circuit).map_err(|e| format!("keygen failed: {}", e))?;
+  write_pk(pk_fname, config, params, &pk)?;
+  Ok(pk)
+}
@@ -0,0 +1,188 @@
+use super::loader::ModelMsgpack;
+use crate::layers::predicate::THRESHOLD;
+
+// The fixed-point precision of the circuit. The exporters quantize the model and its inputs at
+// global_sf, and frac_bits requantizes both to 2^frac_bits when the circuit is built, so that the
+// trade-off between the proof size (k and the lookup tables grow with the scale) and the accuracy
+// can be explored from 2^8 to 2^16 without exporting the model again. Every tensor and every
+// fixed-point param is shifted by the difference of the bits, rounding when the precision is
+// lowered, and the tables of the Tabulated layers are resampled over the new grid. The lookup
+// tables of the nonlinearities and the rescales are built from the new scale factor.
+// The outputs are at the new scale (see working_sf).
+
+// sf^2 * 2^17 is the shift of the accumulators and must fit an i64
+pub const MAX_FRAC_BITS: i64 = 22;
+
+// The scale factor of the circuit and of its outputs
+pub fn working_sf(model: &ModelMsgpack) -> i64 {
+  match model.frac_bits {
+    Some(bits) => 1 << bits,
+    None => model.global_sf,
+  }
+}
+
+pub fn check_frac_bits(model: &ModelMsgpack) -> Result<(), String> {
+  let bits = match model.frac_bits {
+    Some(bits) => bits,
+    None => return Ok(()),
+  };
+  if !(1..=MAX_FRAC_BITS).contains(&bits) {
+    return Err(format!(
+      "frac_bits = {} is out of range [1, {}]",
+      bits, MAX_FRAC_BITS
+    ));
+  }
+  if model.global_sf < 1 || model.global_sf & (model.global_sf - 1) != 0 {
+    return Err(format!(
+      "the model is quantized at {}, which is not a power of two",
+      model.global_sf
+    ));
+  }
+  // The integer layers compute on their quantized values, not at the global scale
+  if let Some(layer) = model
+    .layers
+    .iter()
+    .find(|layer| layer.layer_type == "Requantize" || layer.layer_type == "DivMod")
+  {
+    return Err(format!(
+      "{} layers are integer only and can't be rescaled",
+      layer.layer_type
+    ));
+  }
+  Ok(())
+}
+
+// x * 2^shift, rounding half up for a negative shift
+fn shift_value(x: i64, shift: i64) -> i64 {
+  if shift >= 0 {
+    x << shift
+  } else {
+    (x + (1 << (-shift - 1))) >> -shift
+  }
+}
+
+// Params are [x_start, y_0, ...] (see tabulated.rs). Raising the precision interpolates between
+// the old entries, lowering it takes every 2^-shift-th entry.
+fn resample_table(table: &Vec<i64>, shift: i64) -> Vec<i64> {
+  let (x_start, ys) = (table[0], &table[1..]);
+  let x_end = x_start + ys.len() as i64 - 1;
+  let y_at = |x: i64| ys[(x - x_start).clamp(0, ys.len() as i64 - 1) as usize];
+  if shift >= 0 {
+    let step = 1 << shift;
+    let mut new_table = vec![x_start * step];
+    for x in x_start * step..x_end * step + 1 {
+      let (lo, frac) = (x.div_euclid(step), x.rem_euclid(step));
+      let (y0, y1) = (y_at(lo), y_at(lo + 1));
+      let y = y0 * step + (y1 - y0) * frac;
+      new_table.push(y);
+    }
+    return new_table;
+  }
+  let step = 1 << -shift;
+  let new_start = x_start.div_euclid(step) + (x_start.rem_euclid(step) != 0) as i64;
+  let new_end = x_end.div_euclid(step);
+  let mut new_table = vec![new_start];
+  for x in new_start..new_end + 1 {
+    new_table.push(shift_value(y_at(x * step), shift));
+  }
+  new_table
+}
+
+// The tensors that hold integers rather than fixed-point values (including the random seed), and
+// the outputs of the coprocessor layers, which are supplied at the new precision
+fn kept_tensors(model: &ModelMsgpack) -> Vec<i64> {
+  let mut idxes = model
+    .tensors
+    .iter()
+    .filter(|tensor| tensor.dtype.is_some())
+    .map(|tensor| tensor.idx)
+    .collect::<Vec<_>>();
+  // The indices of a runtime Gather
+  for layer in model.layers.iter() {
+    if layer.layer_type == "Gather" && layer.params.len() == 1 {
+      idxes.push(layer.inp_idxes[1]);
+    }
+  }
+  for pos in model.coprocessor_layers.iter().flatten() {
+    if let Some(layer) = model.layers.get(*pos as usize) {
+      idxes.extend(layer.out_idxes.iter());
+    }
+  }
+  idxes.extend(model.random_seed);
+  idxes
+}
+
+// The model at the precision of frac_bits, with frac_bits applied
+pub fn rescale_model(model: &ModelMsgpack) -> Result<ModelMsgpack, String> {
+  check_frac_bits(model)?;
+  let mut model = model.clone();
+  let bits = match model.frac_bits.take() {
+    Some(bits) => bits,
+    None => return Ok(model),
+  };
+  let shift = bits - model.global_sf.trailing_zeros() as i64;
+  model.global_sf = 1 << bits;
+  if shift == 0 {
+    return Ok(model);
+  }
+
+  let kept = kept_tensors(&model);
+  for tensor in model.tensors.iter_mut() {
+    if !kept.contains(&tensor.idx) {
+      tensor.data = tensor.data.iter().map(|x| shift_value(*x, shift)).collect();
+    }
+  }
+
+  for layer in model.layers.iter_mut() {
+    let params = &mut layer.params;
+    match layer.layer_type.as_str() {
+      "Tabulated" => *params = resample_table(params, shift),
+      // [n, x..., y...]
+      "PiecewiseLinear" => {
+        for x in params[1..].iter_mut() {
+          *x = shift_value(*x, shift);
+        }
+        let n = params[0] as usize;
+        if params[1..1 + n].windows(2).any(|x| x[0] >= x[1]) {
+          return Err(format!(
+            "knots of a piecewise linear layer merge at {} bits",
+            bits
+          ));
+        }
+      }
+      "LayerNorm" | "Dropout" => params[0] = shift_value(params[0], shift).max(1),
+      // The table sizes, so that the tables cover the same domains
+      "Exp" | "Ln" | "Reciprocal" if params.len() > 0 => {
+        params[0] = shift_value(params[0], shift).max(1)
+      }
+      "Quantile" => {
+        for q in params.iter_mut() {
+          *q = shift_value(*q, shift);
+        }
+      }
+      "Predicate" if params[0] == THRESHOLD => params[2] = shift_value(params[2], shift),
+      "RangeCheck" => {
+        params[0] += shift;
+        if params[0] < 1 {
+          return Err(format!(
+            "a range check of {} bits is below the precision",
+            params[0] - shift
+          ));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  if let Some(predicate) = model.output_predicate.as_mut() {
+    if predicate.get(0) == Some(&THRESHOLD) && predicate.len() == 4 {
+      predicate[3] = shift_value(predicate[3], shift);
+    }
+  }
+  Ok(model)
+}
+
+pub fn apply_frac_bits(model: &mut ModelMsgpack) -> Result<(), String> {
+  *model = rescale_model(model)?;
+  Ok(())
+}
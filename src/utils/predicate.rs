@@ -0,0 +1,101 @@
+use crate::layers::{
+  predicate::{ARGMAX, THRESHOLD},
+  top_k::{LARGEST, SMALLEST},
+};
+
+use super::{
+  loader::{LayerMsgpack, ModelMsgpack},
+  watermark::max_tensor_idx,
+};
+
+// Private outputs: many applications only need to know whether an output satisfies a predicate,
+// e.g., that the argmax is a class or that a score is over a threshold, and revealing the output
+// leaks more than that. The output predicate replaces an output with the bit of a Predicate layer
+// over it, in the same position of the public values, so the output stays private.
+// Formats: [ARGMAX, output tensor, class] or [THRESHOLD, output tensor, element, threshold], with
+// the threshold in fixed point.
+// The output top k instead reveals the indices of the k largest or smallest elements of an
+// output, e.g., the predicted class without the logits: [LARGEST or SMALLEST, output tensor, k],
+// so [LARGEST, tensor, 1] is the argmax and [SMALLEST, tensor, 1] the argmin.
+
+// The position of the output in out_idxes and its shape
+fn output_tensor(model: &ModelMsgpack, idx: i64) -> Result<(usize, Vec<i64>), String> {
+  let pos = model
+    .out_idxes
+    .iter()
+    .position(|x| *x == idx)
+    .ok_or_else(|| format!("tensor {} is not an output", idx))?;
+  let shape = model
+    .layers
+    .iter()
+    .find_map(|layer| {
+      let pos = layer.out_idxes.iter().position(|x| *x == idx)?;
+      Some(layer.out_shapes[pos].clone())
+    })
+    .ok_or_else(|| format!("output {} is not computed by a layer", idx))?;
+  Ok((pos, shape))
+}
+
+// Replaces the output with the single output of a new layer over it
+fn replace_output(
+  model: &mut ModelMsgpack,
+  pos: usize,
+  shape: Vec<i64>,
+  layer_type: &str,
+  params: Vec<i64>,
+  out_shape: Vec<i64>,
+) {
+  let out_idx = max_tensor_idx(model) + 1;
+  model.layers.push(LayerMsgpack {
+    layer_type: layer_type.to_string(),
+    params,
+    inp_idxes: vec![model.out_idxes[pos]],
+    inp_shapes: vec![shape],
+    out_idxes: vec![out_idx],
+    out_shapes: vec![out_shape],
+    mask: vec![],
+  });
+  model.out_idxes[pos] = out_idx;
+}
+
+// Adds the Predicate layer and reveals its bit instead of the output
+pub fn apply_output_predicate(model: &mut ModelMsgpack) -> Result<(), String> {
+  let predicate = model
+    .output_predicate
+    .take()
+    .ok_or("no output predicate is set")?;
+  let (idx, params) = match (predicate.get(0), predicate.get(1)) {
+    (Some(&ARGMAX), Some(idx)) if predicate.len() == 3 => (*idx, vec![ARGMAX, predicate[2]]),
+    (Some(&THRESHOLD), Some(idx)) if predicate.len() == 4 => {
+      (*idx, vec![THRESHOLD, predicate[2], predicate[3]])
+    }
+    _ => return Err(format!("malformed output predicate {:?}", predicate)),
+  };
+  let (pos, shape) = output_tensor(model, idx)?;
+  let len = shape.iter().product::<i64>();
+  if params[1] < 0 || params[1] >= len {
+    return Err(format!(
+      "the predicate uses element {} of an output of {} elements",
+      params[1], len
+    ));
+  }
+
+  replace_output(model, pos, shape, "Predicate", params, vec![1]);
+  Ok(())
+}
+
+// Adds the TopK layer and reveals its indices instead of the output
+pub fn apply_output_top_k(model: &mut ModelMsgpack) -> Result<(), String> {
+  let top_k = model.output_top_k.take().ok_or("no output top k is set")?;
+  let (order, idx, k) = match top_k[..] {
+    [order, idx, k] if order == LARGEST || order == SMALLEST => (order, idx, k),
+    _ => return Err(format!("malformed output top k {:?}", top_k)),
+  };
+  let (pos, shape) = output_tensor(model, idx)?;
+  let len = shape.iter().product::<i64>();
+  if k < 1 || k > len {
+    return Err(format!("the top {} of an output of {} elements", k, len));
+  }
+  replace_output(model, pos, shape, "TopK", vec![order, k], vec![k]);
+  Ok(())
+}
@@ -0,0 +1,54 @@
+use super::loader::ModelMsgpack;
+
+// Named column budgets, so that the resource trade-off can be picked without knowing the layout.
+// The gates of every gadget span all the advice columns, so the number of columns is also the gate
+// width: more columns fit more ops per row and the model needs fewer rows, but every column is
+// another polynomial to commit to. At a fixed k the prover's time and memory grow with the number
+// of columns, so the wider profiles only pay off when they let the model fit in a smaller k.
+// The circuit always has a single fixed column, and k is left alone since the lookup ranges, and
+// so the quantization, depend on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnProfile {
+  pub name: &'static str,
+  pub num_cols: i64,
+}
+
+pub const COLUMN_PROFILES: [ColumnProfile; 3] = [
+  // The fewest cells at a given k, for models with rows to spare
+  ColumnProfile {
+    name: "low-memory",
+    num_cols: 4,
+  },
+  // The converter default
+  ColumnProfile {
+    name: "balanced",
+    num_cols: 6,
+  },
+  // The fewest rows, to prove at a smaller k
+  ColumnProfile {
+    name: "fast-prover",
+    num_cols: 12,
+  },
+];
+
+pub fn column_profile(name: &str) -> Result<&'static ColumnProfile, String> {
+  COLUMN_PROFILES
+    .iter()
+    .find(|profile| profile.name == name)
+    .ok_or_else(|| {
+      let names = COLUMN_PROFILES.map(|profile| profile.name);
+      format!(
+        "unknown column profile {}, expected one of {:?}",
+        name, names
+      )
+    })
+}
+
+// Sets the columns of the config from its profile, if it has one. The profile overrides num_cols,
+// and applying it again does nothing.
+pub fn apply_column_profile(model: &mut ModelMsgpack) -> Result<(), String> {
+  if let Some(name) = &model.column_profile {
+    model.num_cols = column_profile(name)?.num_cols;
+  }
+  Ok(())
+}
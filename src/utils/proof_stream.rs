@@ -0,0 +1,85 @@
+use std::{
+  fs::File,
+  io::{self, BufReader, BufWriter, Read, Take, Write},
+};
+
+use crate::utils::storage::{is_remote, local_path};
+
+// The Blake2b transcript writes every commitment and evaluation to its writer as the prover makes
+// them, so with a file behind the transcript the proof is never held in memory, and the verifier
+// reads it back from the file as it goes. The bound stops a proof that grows past what the
+// environment can store, and a verifier from reading more than that from a hostile stream.
+
+// Far above the proofs of the models here, which are a few kB to a few MB
+pub const DEFAULT_MAX_PROOF_BYTES: u64 = 1 << 30;
+
+// Fails the write that would go over max_bytes, which fails the proof with a transcript error
+pub struct BoundedWriter<W: Write> {
+  inner: W,
+  written: u64,
+  max_bytes: u64,
+}
+
+impl<W: Write> BoundedWriter<W> {
+  pub fn new(inner: W, max_bytes: u64) -> Self {
+    Self {
+      inner,
+      written: 0,
+      max_bytes,
+    }
+  }
+
+  pub fn written(&self) -> u64 {
+    self.written
+  }
+
+  // Flushes the buffered end of the proof, which can still fail
+  pub fn finish(mut self) -> io::Result<W> {
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+}
+
+impl<W: Write> Write for BoundedWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.written + buf.len() as u64 > self.max_bytes {
+      return Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("the proof is over the bound of {} bytes", self.max_bytes),
+      ));
+    }
+    let n = self.inner.write(buf)?;
+    self.written += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+// The proof file to prove into. The stream goes to a local file, since the remote backends upload
+// whole artifacts.
+pub fn proof_writer(path: &str, max_bytes: u64) -> Result<BoundedWriter<BufWriter<File>>, String> {
+  if is_remote(path) {
+    return Err(format!("{}: proofs are only streamed to local files", path));
+  }
+  let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+  Ok(BoundedWriter::new(BufWriter::new(file), max_bytes))
+}
+
+// The proof file to verify from. A file over the bound is rejected before any of it is read.
+pub fn proof_reader(path: &str, max_bytes: u64) -> Result<Take<BufReader<File>>, String> {
+  let file = File::open(local_path(path)?).map_err(|e| format!("{}: {}", path, e))?;
+  let len = file
+    .metadata()
+    .map_err(|e| format!("{}: {}", path, e))?
+    .len();
+  if len > max_bytes {
+    return Err(format!(
+      "{}: the proof has {} bytes, over the bound of {}",
+      path, len, max_bytes
+    ));
+  }
+  Ok(BufReader::new(file).take(max_bytes))
+}
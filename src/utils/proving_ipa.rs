@@ -1,19 +1,18 @@
 use std::{
   fs::File,
-  io::{BufReader, Write},
+  io::{BufReader, Read, Write},
   path::Path,
   time::Instant,
 };
 
 use halo2_proofs::{
-  dev::MockProver,
   halo2curves::pasta::{EqAffine, Fp},
-  plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
+  plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, ProvingKey, VerifyingKey},
   poly::{
     commitment::{Params, ParamsProver},
     ipa::{
       commitment::{IPACommitmentScheme, ParamsIPA},
-      multiopen::ProverIPA,
+      multiopen::{ProverIPA, VerifierIPA},
       strategy::SingleStrategy,
     },
     VerificationStrategy,
@@ -22,10 +21,25 @@ use halo2_proofs::{
     Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
   },
 };
+use rand::RngCore;
 
-use crate::{model::ModelCircuit, utils::helpers::get_public_values};
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    cancel::{record_stage, CancelToken},
+    disk_pk::with_disk_pk,
+    errors::ZkmlError,
+    helpers::{instance_columns, instance_slices, prover_rng},
+    keygen::init_keygen_threads,
+    proof_stream::{proof_reader, proof_writer, DEFAULT_MAX_PROOF_BYTES},
+    srs::{fit_ipa_params, has_index, indexed_ipa_params},
+  },
+};
 
 pub fn get_ipa_params(params_dir: &str, degree: u32) -> ParamsIPA<EqAffine> {
+  if has_index(params_dir) {
+    return indexed_ipa_params(params_dir, degree);
+  }
   let path = format!("{}/{}.params", params_dir, degree);
   let params_path = Path::new(&path);
   if File::open(&params_path).is_err() {
@@ -40,13 +54,108 @@ pub fn get_ipa_params(params_dir: &str, degree: u32) -> ParamsIPA<EqAffine> {
   }
 
   let params_fs = File::open(&params_path).expect("couldn't load params");
-  let params: ParamsIPA<EqAffine> =
+  let mut params: ParamsIPA<EqAffine> =
     Params::read::<_>(&mut BufReader::new(params_fs)).expect("Failed to read params");
+  // The file name can be wrong, e.g., if it was copied by hand
+  if let Err(e) = fit_ipa_params(&mut params, degree) {
+    panic!("{}: {}", path, e);
+  }
   params
 }
 
-pub fn time_circuit_ipa(circuit: ModelCircuit<Fp>) {
-  let rng = rand::thread_rng();
+// Proves several circuits of the same model in one proof, as prove_batch_kzg. IPA needs no trusted
+// setup: the params are derived from a hash to the curve, so anyone can regenerate them for k.
+pub fn prove_batch_ipa<R: RngCore>(
+  params: &ParamsIPA<EqAffine>,
+  pk: &ProvingKey<EqAffine>,
+  circuits: Vec<ModelCircuit<Fp>>,
+  rng: R,
+) -> Result<(Vec<u8>, Vec<Vec<Fp>>), ZkmlError> {
+  prove_batch_ipa_to(params, pk, circuits, rng, vec![])
+}
+
+// Proves into the writer as the transcript goes, as prove_batch_kzg_to
+pub fn prove_batch_ipa_to<R: RngCore, W: Write>(
+  params: &ParamsIPA<EqAffine>,
+  pk: &ProvingKey<EqAffine>,
+  circuits: Vec<ModelCircuit<Fp>>,
+  rng: R,
+  writer: W,
+) -> Result<(W, Vec<Vec<Fp>>), ZkmlError> {
+  let public_vals = circuits
+    .iter()
+    .map(|circuit| circuit.compute_public_values())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(ZkmlError::public_values)?;
+  let num_commitments = circuits
+    .first()
+    .map_or(0, |circuit| circuit.num_commitments());
+  let instances = public_vals
+    .iter()
+    .map(|vals| instance_columns(vals, num_commitments))
+    .collect::<Vec<_>>();
+  let instance_refs = instances.iter().map(instance_slices).collect::<Vec<_>>();
+  let instance_refs = instance_refs.iter().map(|x| &x[..]).collect::<Vec<_>>();
+
+  let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(writer);
+  create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<EqAffine>, _, _, _, _>(
+    params,
+    pk,
+    &circuits,
+    &instance_refs,
+    rng,
+    &mut transcript,
+  )
+  .map_err(|e| ZkmlError::prover("proving", &e))?;
+  Ok((transcript.finalize(), public_vals))
+}
+
+pub fn check_batch_ipa(
+  params: &ParamsIPA<EqAffine>,
+  vk: &VerifyingKey<EqAffine>,
+  public_vals: &[Vec<Fp>],
+  num_commitments: usize,
+  proof: &[u8],
+) -> bool {
+  check_batch_ipa_from(params, vk, public_vals, num_commitments, proof)
+}
+
+// Checks a proof read from the stream, as check_batch_kzg_from
+pub fn check_batch_ipa_from<R: Read>(
+  params: &ParamsIPA<EqAffine>,
+  vk: &VerifyingKey<EqAffine>,
+  public_vals: &[Vec<Fp>],
+  num_commitments: usize,
+  proof: R,
+) -> bool {
+  let strategy = SingleStrategy::new(params);
+  let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+  let instances = public_vals
+    .iter()
+    .map(|vals| instance_columns(vals, num_commitments))
+    .collect::<Vec<_>>();
+  let instance_refs = instances.iter().map(instance_slices).collect::<Vec<_>>();
+  let instance_refs = instance_refs.iter().map(|x| &x[..]).collect::<Vec<_>>();
+  verify_proof::<
+    IPACommitmentScheme<EqAffine>,
+    VerifierIPA<'_, EqAffine>,
+    Challenge255<EqAffine>,
+    Blake2bRead<R, EqAffine, Challenge255<EqAffine>>,
+    SingleStrategy<'_, EqAffine>,
+  >(params, vk, strategy, &instance_refs, &mut transcript)
+  .is_ok()
+}
+
+pub fn time_circuit_ipa(circuit: ModelCircuit<Fp>) -> Result<(), String> {
+  time_circuit_ipa_cancellable(circuit, &CancelToken::new())
+}
+
+// Stops after the current stage once the token is cancelled
+pub fn time_circuit_ipa_cancellable(
+  circuit: ModelCircuit<Fp>,
+  token: &CancelToken,
+) -> Result<(), String> {
+  let rng = prover_rng(circuit.zero_knowledge);
   let start = Instant::now();
 
   let degree = circuit.k as u32;
@@ -60,62 +169,80 @@ pub fn time_circuit_ipa(circuit: ModelCircuit<Fp>) {
     "Time elapsed in params construction: {:?}",
     circuit_duration
   );
+  record_stage("params", circuit_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
+  init_keygen_threads();
   let vk = keygen_vk(&params, &empty_circuit).unwrap();
   let vk_duration = start.elapsed();
   info!(
     "Time elapsed in generating vkey: {:?}",
     vk_duration - circuit_duration
   );
+  record_stage("vkey", vk_duration - circuit_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
-  let pk = keygen_pk(&params, vk, &empty_circuit).unwrap();
+  let pk = with_disk_pk(|| keygen_pk(&params, vk, &empty_circuit)).unwrap();
   let pk_duration = start.elapsed();
   info!(
     "Time elapsed in generating pkey: {:?}",
     pk_duration - vk_duration
   );
+  record_stage("pkey", pk_duration - vk_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
   drop(empty_circuit);
 
   let fill_duration = start.elapsed();
-  let _prover = MockProver::run(degree, &proof_circuit, vec![vec![]]).unwrap();
-  let public_vals = get_public_values();
+  let public_vals = proof_circuit.compute_public_values()?;
   info!(
     "Time elapsed in filling circuit: {:?}",
     fill_duration - pk_duration
   );
+  record_stage("witness", fill_duration - pk_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
-  let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+  // The proof is streamed to its file
+  let instances = instance_columns(&public_vals, proof_circuit.num_commitments());
+  let writer = proof_writer("proof", DEFAULT_MAX_PROOF_BYTES).unwrap();
+  let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(writer);
   create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<EqAffine>, _, _, _, _>(
     &params,
     &pk,
     &[proof_circuit],
-    &[&[&public_vals]],
+    &[&instance_slices(&instances)[..]],
     rng,
     &mut transcript,
   )
   .unwrap();
-  let proof = transcript.finalize();
+  let writer = transcript.finalize();
+  let proof_size = writer.written();
+  writer.finish().unwrap();
   let proof_duration = start.elapsed();
   info!("Proving time: {:?}", proof_duration - fill_duration);
+  record_stage("proof", proof_duration - fill_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
-  let proof_size = {
-    let mut folder = std::path::PathBuf::new();
-    folder.push("proof");
-    let mut fd = std::fs::File::create(folder.as_path()).unwrap();
-    folder.pop();
-    fd.write_all(&proof).unwrap();
-    fd.metadata().unwrap().len()
-  };
   info!("Proof size: {} bytes", proof_size);
 
   let strategy = SingleStrategy::new(&params);
-  let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+  let proof = proof_reader("proof", DEFAULT_MAX_PROOF_BYTES).unwrap();
+  let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
   assert!(
     verify_proof(
       &params,
       pk.get_vk(),
       strategy,
-      &[&[&public_vals]],
+      &[&instance_slices(&instances)[..]],
       &mut transcript
     )
     .is_ok(),
@@ -123,4 +250,6 @@ pub fn time_circuit_ipa(circuit: ModelCircuit<Fp>) {
   );
   let verify_duration = start.elapsed();
   info!("Verifying time: {:?}", verify_duration - proof_duration);
+  record_stage("verify", verify_duration - proof_duration);
+  Ok(())
 }
@@ -1,14 +1,14 @@
 use std::{
   fs::File,
-  io::{BufReader, Write},
-  path::Path,
+  io::{BufReader, BufWriter, Read, Write},
   time::Instant,
 };
 
 use halo2_proofs::{
-  dev::MockProver,
   halo2curves::bn256::{Bn256, Fr, G1Affine},
-  plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, VerifyingKey},
+  plonk::{
+    create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, Error, ProvingKey, VerifyingKey,
+  },
   poly::{
     commitment::Params,
     kzg::{
@@ -22,27 +22,42 @@ use halo2_proofs::{
   },
   SerdeFormat,
 };
+use rand::RngCore;
 
-use crate::{model::ModelCircuit, utils::helpers::get_public_values};
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    cancel::{record_stage, CancelToken},
+    disk_pk::with_disk_pk,
+    errors::ZkmlError,
+    helpers::{instance_columns, instance_slices, prover_rng},
+    keygen::init_keygen_threads,
+    proof_stream::{proof_reader, proof_writer, DEFAULT_MAX_PROOF_BYTES},
+    srs::{has_index, indexed_kzg_params, stream_kzg_params},
+    storage::{artifact_exists, join_url, write_artifact},
+  },
+};
 
 pub fn get_kzg_params(params_dir: &str, degree: u32) -> ParamsKZG<Bn256> {
+  if has_index(params_dir) {
+    return indexed_kzg_params(params_dir, degree);
+  }
   let rng = rand::thread_rng();
-  let path = format!("{}/{}.params", params_dir, degree);
-  let params_path = Path::new(&path);
-  if File::open(&params_path).is_err() {
+  let path = join_url(params_dir, &format!("{}.params", degree));
+  if artifact_exists(&path) {
+    // The file name can be wrong, e.g., if it was copied by hand, which the header catches
+    match stream_kzg_params(&path, degree) {
+      Ok((params, _)) => params,
+      Err(e) => panic!("{}", e),
+    }
+  } else {
     let params = ParamsKZG::<Bn256>::setup(degree, rng);
     let mut buf = Vec::new();
 
     params.write(&mut buf).expect("Failed to write params");
-    let mut file = File::create(&params_path).expect("Failed to create params file");
-    file
-      .write_all(&buf[..])
-      .expect("Failed to write params to file");
+    write_artifact(&path, &buf).expect("Failed to write params to file");
+    params
   }
-
-  let mut params_fs = File::open(&params_path).expect("couldn't load params");
-  let params = ParamsKZG::<Bn256>::read(&mut params_fs).expect("Failed to read params");
-  params
 }
 
 pub fn serialize(data: &Vec<u8>, path: &str) -> u64 {
@@ -51,28 +66,157 @@ pub fn serialize(data: &Vec<u8>, path: &str) -> u64 {
   file.metadata().unwrap().len()
 }
 
+// Proves several circuits of the same model, e.g., a small batch of inputs, in a single proof with
+// one transcript. Returns the proof and the public values of every circuit, in order.
+pub fn prove_batch_kzg<R: RngCore>(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  circuits: Vec<ModelCircuit<Fr>>,
+  rng: R,
+) -> Result<(Vec<u8>, Vec<Vec<Fr>>), ZkmlError> {
+  prove_batch_kzg_to(params, pk, circuits, rng, vec![])
+}
+
+// Proves into the writer as the transcript goes, e.g., a proof file (see proof_stream.rs), and
+// returns the writer with the public values
+pub fn prove_batch_kzg_to<R: RngCore, W: Write>(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  circuits: Vec<ModelCircuit<Fr>>,
+  rng: R,
+  writer: W,
+) -> Result<(W, Vec<Vec<Fr>>), ZkmlError> {
+  let public_vals = circuits
+    .iter()
+    .map(|circuit| circuit.compute_public_values())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(ZkmlError::public_values)?;
+  let num_commitments = circuits
+    .first()
+    .map_or(0, |circuit| circuit.num_commitments());
+  let writer = create_proof_kzg_to(
+    params,
+    pk,
+    &circuits,
+    &public_vals,
+    num_commitments,
+    rng,
+    writer,
+  )
+  .map_err(|e| ZkmlError::prover("proving", &e))?;
+  Ok((writer, public_vals))
+}
+
+// Proves circuits whose public values are already known, e.g., the replay of an exported witness
+// (see witness.rs), into the writer. The circuits are of the same model, with num_commitments
+// commitments each.
+pub fn create_proof_kzg_to<C: Circuit<Fr>, R: RngCore, W: Write>(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  circuits: &[C],
+  public_vals: &[Vec<Fr>],
+  num_commitments: usize,
+  rng: R,
+  writer: W,
+) -> Result<W, Error> {
+  let instances = public_vals
+    .iter()
+    .map(|vals| instance_columns(vals, num_commitments))
+    .collect::<Vec<_>>();
+  let instance_refs = instances.iter().map(instance_slices).collect::<Vec<_>>();
+  let instance_refs = instance_refs.iter().map(|x| &x[..]).collect::<Vec<_>>();
+
+  let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(writer);
+  create_proof::<
+    KZGCommitmentScheme<Bn256>,
+    ProverSHPLONK<'_, Bn256>,
+    Challenge255<G1Affine>,
+    _,
+    Blake2bWrite<W, G1Affine, Challenge255<G1Affine>>,
+    C,
+  >(params, pk, circuits, &instance_refs, rng, &mut transcript)?;
+  Ok(transcript.finalize())
+}
+
+// Returns whether the proof is valid. The strategy borrows the params, so verifying right after
+// proving reuses the prover's SRS instead of reading another copy. The number of commitments is
+// the one of the verified circuit, which splits the public values into the instance columns.
+pub fn check_kzg(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  public_vals: &Vec<Fr>,
+  num_commitments: usize,
+  proof: &[u8],
+) -> bool {
+  check_batch_kzg(
+    params,
+    vk,
+    std::slice::from_ref(public_vals),
+    num_commitments,
+    proof,
+  )
+}
+
+// Checks a proof of several circuits, with the public values of every circuit in proving order
+pub fn check_batch_kzg(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  public_vals: &[Vec<Fr>],
+  num_commitments: usize,
+  proof: &[u8],
+) -> bool {
+  check_batch_kzg_from(params, vk, public_vals, num_commitments, proof)
+}
+
+// Checks a proof read from the stream as the verifier goes, e.g., a proof file
+pub fn check_batch_kzg_from<R: Read>(
+  params: &ParamsKZG<Bn256>,
+  vk: &VerifyingKey<G1Affine>,
+  public_vals: &[Vec<Fr>],
+  num_commitments: usize,
+  proof: R,
+) -> bool {
+  let strategy = SingleStrategy::new(params);
+  let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+  let instances = public_vals
+    .iter()
+    .map(|vals| instance_columns(vals, num_commitments))
+    .collect::<Vec<_>>();
+  let instance_refs = instances.iter().map(instance_slices).collect::<Vec<_>>();
+  let instance_refs = instance_refs.iter().map(|x| &x[..]).collect::<Vec<_>>();
+  verify_proof::<
+    KZGCommitmentScheme<Bn256>,
+    VerifierSHPLONK<'_, Bn256>,
+    Challenge255<G1Affine>,
+    Blake2bRead<R, G1Affine, Challenge255<G1Affine>>,
+    halo2_proofs::poly::kzg::strategy::SingleStrategy<'_, Bn256>,
+  >(params, vk, strategy, &instance_refs, &mut transcript)
+  .is_ok()
+}
+
 pub fn verify_kzg(
   params: &ParamsKZG<Bn256>,
   vk: &VerifyingKey<G1Affine>,
-  strategy: SingleStrategy<Bn256>,
   public_vals: &Vec<Fr>,
-  mut transcript: Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+  num_commitments: usize,
+  proof: &[u8],
 ) {
   assert!(
-    verify_proof::<
-      KZGCommitmentScheme<Bn256>,
-      VerifierSHPLONK<'_, Bn256>,
-      Challenge255<G1Affine>,
-      Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
-      halo2_proofs::poly::kzg::strategy::SingleStrategy<'_, Bn256>,
-    >(&params, &vk, strategy, &[&[&public_vals]], &mut transcript)
-    .is_ok(),
+    check_kzg(params, vk, public_vals, num_commitments, proof),
     "proof did not verify"
   );
 }
 
-pub fn time_circuit_kzg(circuit: ModelCircuit<Fr>) {
-  let rng = rand::thread_rng();
+pub fn time_circuit_kzg(circuit: ModelCircuit<Fr>) -> Result<(), String> {
+  time_circuit_kzg_cancellable(circuit, &CancelToken::new())
+}
+
+// Stops after the current stage once the token is cancelled
+pub fn time_circuit_kzg_cancellable(
+  circuit: ModelCircuit<Fr>,
+  token: &CancelToken,
+) -> Result<(), String> {
+  let rng = prover_rng(circuit.zero_knowledge);
   let start = Instant::now();
 
   let degree = circuit.k as u32;
@@ -83,8 +227,13 @@ pub fn time_circuit_kzg(circuit: ModelCircuit<Fr>) {
     "Time elapsed in params construction: {:?}",
     circuit_duration
   );
+  record_stage("params", circuit_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
   let vk_circuit = circuit.clone();
+  init_keygen_threads();
   let vk = keygen_vk(&params, &vk_circuit).unwrap();
   drop(vk_circuit);
   let vk_duration = start.elapsed();
@@ -92,30 +241,46 @@ pub fn time_circuit_kzg(circuit: ModelCircuit<Fr>) {
     "Time elapsed in generating vkey: {:?}",
     vk_duration - circuit_duration
   );
+  record_stage("vkey", vk_duration - circuit_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
   let vkey_size = serialize(&vk.to_bytes(SerdeFormat::RawBytes), "vkey");
   info!("vkey size: {} bytes", vkey_size);
 
   let pk_circuit = circuit.clone();
-  let pk = keygen_pk(&params, vk, &pk_circuit).unwrap();
+  let pk = with_disk_pk(|| keygen_pk(&params, vk, &pk_circuit)).unwrap();
   let pk_duration = start.elapsed();
   info!(
     "Time elapsed in generating pkey: {:?}",
     pk_duration - vk_duration
   );
+  record_stage("pkey", pk_duration - vk_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
   drop(pk_circuit);
 
-  let pkey_size = serialize(&pk.to_bytes(SerdeFormat::RawBytes), "pkey");
+  // Streamed, since a copy of the key may not fit in memory
+  let pkey_size = {
+    let mut writer = BufWriter::new(File::create("pkey").unwrap());
+    pk.write(&mut writer, SerdeFormat::RawBytes).unwrap();
+    writer.into_inner().unwrap().metadata().unwrap().len()
+  };
   info!("pkey size: {} bytes", pkey_size);
 
   let fill_duration = start.elapsed();
   let proof_circuit = circuit.clone();
-  let _prover = MockProver::run(degree, &proof_circuit, vec![vec![]]).unwrap();
-  let public_vals = get_public_values();
+  let public_vals = proof_circuit.compute_public_values()?;
   info!(
     "Time elapsed in filling circuit: {:?}",
     fill_duration - pk_duration
   );
+  record_stage("witness", fill_duration - pk_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
   // Convert public vals to serializable format
   let public_vals_u8: Vec<u8> = public_vals
@@ -126,45 +291,55 @@ pub fn time_circuit_kzg(circuit: ModelCircuit<Fr>) {
   let public_vals_u8_size = serialize(&public_vals_u8, "public_vals");
   info!("Public vals size: {} bytes", public_vals_u8_size);
 
-  let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+  // The proof is streamed to its file
+  let num_commitments = proof_circuit.num_commitments();
+  let instances = instance_columns(&public_vals, num_commitments);
+  let writer = proof_writer("proof", DEFAULT_MAX_PROOF_BYTES).unwrap();
+  let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(writer);
   create_proof::<
     KZGCommitmentScheme<Bn256>,
     ProverSHPLONK<'_, Bn256>,
     Challenge255<G1Affine>,
     _,
-    Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+    Blake2bWrite<_, G1Affine, Challenge255<G1Affine>>,
     ModelCircuit<Fr>,
   >(
     &params,
     &pk,
     &[proof_circuit],
-    &[&[&public_vals]],
+    &[&instance_slices(&instances)[..]],
     rng,
     &mut transcript,
   )
   .unwrap();
-  let proof = transcript.finalize();
+  let writer = transcript.finalize();
+  let proof_size = writer.written();
+  writer.finish().unwrap();
   let proof_duration = start.elapsed();
   info!("Proving time: {:?}", proof_duration - fill_duration);
-
-  let proof_size = serialize(&proof, "proof");
-  let proof = std::fs::read("proof").unwrap();
+  record_stage("proof", proof_duration - fill_duration);
+  if token.is_cancelled() {
+    return Ok(());
+  }
 
   info!("Proof size: {} bytes", proof_size);
 
-  let strategy = SingleStrategy::new(&params);
-  let transcript_read = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-
   info!("public vals: {:?}", public_vals);
-  verify_kzg(
-    &params,
-    &pk.get_vk(),
-    strategy,
-    &public_vals,
-    transcript_read,
+  let proof = proof_reader("proof", DEFAULT_MAX_PROOF_BYTES).unwrap();
+  assert!(
+    check_batch_kzg_from(
+      &params,
+      pk.get_vk(),
+      std::slice::from_ref(&public_vals),
+      num_commitments,
+      proof
+    ),
+    "proof did not verify"
   );
   let verify_duration = start.elapsed();
   info!("Verifying time: {:?}", verify_duration - proof_duration);
+  record_stage("verify", verify_duration - proof_duration);
+  Ok(())
 }
 
 // Standalone verification
@@ -186,7 +361,7 @@ pub fn verify_circuit_kzg(
   .unwrap();
   info!("Loaded vkey");
 
-  let proof = std::fs::read(proof_fname).unwrap();
+  let proof = proof_reader(proof_fname, DEFAULT_MAX_PROOF_BYTES).unwrap();
 
   let public_vals_u8 = std::fs::read(&public_vals_fname).unwrap();
   let public_vals: Vec<Fr> = public_vals_u8
@@ -194,12 +369,18 @@ pub fn verify_circuit_kzg(
     .map(|chunk| Fr::from_bytes(chunk.try_into().expect("conversion failed")).unwrap())
     .collect();
 
-  let strategy = SingleStrategy::new(&params);
-  let transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-
   let start = Instant::now();
   let verify_start = start.elapsed();
-  verify_kzg(&params, &vk, strategy, &public_vals, transcript);
+  assert!(
+    check_batch_kzg_from(
+      &params,
+      &vk,
+      &[public_vals],
+      circuit.num_commitments(),
+      proof
+    ),
+    "proof did not verify"
+  );
   let verify_duration = start.elapsed();
   info!("Verifying time: {:?}", verify_duration - verify_start);
   info!("Proof verified!")
@@ -0,0 +1,167 @@
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::model::ModelCircuit;
+
+use super::{
+  chaining::output_shapes,
+  envelope::decode_signed,
+  loader::{ModelMsgpack, TensorMsgpack},
+  precision::working_sf,
+};
+
+// Auditable randomness for the stochastic layers, e.g., MC dropout for uncertainty estimates.
+// Every random bit is derived from a public seed by the PRF in the circuit (see prf.rs): the seed
+// is an input tensor of one element, random_seed, and is revealed as the first output (after the
+// exit index), so the verifier knows the randomness of every proof and the prover can't pick the
+// masks. Estimates over several seeds, e.g., derived from a public beacon, are then reproducible
+// from the proofs alone (see mc_dropout).
+// When the circuit is built, every Dropout layer gets a second input, a new tensor with the
+// uniforms of its elements, which the circuit fills from the PRF, in layer order. The models that
+// helpers build from parts of a model (branch conditions, MoE gates, truncations) have no seed,
+// so they can't contain Dropout layers.
+
+pub fn check_random_seed(model: &ModelMsgpack) -> Result<(), String> {
+  let mut has_dropout = false;
+  for (pos, layer) in model.layers.iter().enumerate() {
+    if layer.layer_type != "Dropout" {
+      continue;
+    }
+    has_dropout = true;
+    match layer.params.get(0) {
+      Some(keep) if layer.params.len() == 1 && *keep >= 1 && *keep <= model.global_sf => {}
+      _ => {
+        return Err(format!(
+          "dropout layer {} needs a keep probability in (0, 1]",
+          pos
+        ))
+      }
+    }
+    if layer.inp_idxes.len() != 1 {
+      return Err(format!("dropout layer {} takes one input", pos));
+    }
+  }
+
+  let seed = match model.random_seed {
+    Some(seed) => seed,
+    None if has_dropout => return Err("Dropout layers need a random_seed".to_string()),
+    None => return Ok(()),
+  };
+  if !model.inp_idxes.contains(&seed) {
+    return Err(format!("the random seed {} is not an input", seed));
+  }
+  if let Some(tensor) = model.tensors.iter().find(|tensor| tensor.idx == seed) {
+    if tensor.shape.iter().product::<i64>() != 1 {
+      return Err(format!(
+        "the random seed {} has more than one element",
+        seed
+      ));
+    }
+  }
+  Ok(())
+}
+
+// Adds the uniforms to the Dropout layers and reveals the seed. Returns the (index, shape) of the
+// uniform tensors, in the order of the PRF.
+pub fn apply_random_seed(model: &mut ModelMsgpack) -> Result<Vec<(i64, Vec<i64>)>, String> {
+  check_random_seed(model)?;
+  let seed = match model.random_seed {
+    Some(seed) => seed,
+    None => return Ok(vec![]),
+  };
+
+  let mut next_idx = model
+    .tensors
+    .iter()
+    .map(|tensor| tensor.idx)
+    .chain(model.inp_idxes.iter().cloned())
+    .chain(model.layers.iter().flat_map(|layer| {
+      layer
+        .inp_idxes
+        .iter()
+        .chain(layer.out_idxes.iter())
+        .cloned()
+    }))
+    .max()
+    .unwrap_or(-1)
+    + 1;
+  let mut uniforms = vec![];
+  for layer in model.layers.iter_mut() {
+    if layer.layer_type != "Dropout" {
+      continue;
+    }
+    let shape = layer.inp_shapes[0].clone();
+    layer.inp_idxes.push(next_idx);
+    layer.inp_shapes.push(shape.clone());
+    uniforms.push((next_idx, shape));
+    next_idx += 1;
+  }
+  model.out_idxes.insert(0, seed);
+  Ok(uniforms)
+}
+
+// The outputs over several seeds, with their mean and standard deviation (at the scale of the
+// outputs)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct McDropoutReport {
+  pub seeds: Vec<i64>,
+  pub samples: Vec<Vec<i64>>,
+  pub mean: Vec<f64>,
+  pub std: Vec<f64>,
+}
+
+// The outputs of the model on the input with every seed, as the proofs with these seeds reveal
+// them
+pub fn mc_dropout(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+  seeds: &[i64],
+) -> Result<McDropoutReport, String> {
+  let seed_idx = model.random_seed.ok_or("the model has no random_seed")?;
+  check_random_seed(model)?;
+  let num_outputs = output_shapes(model)
+    .iter()
+    .map(|shape| shape.iter().product::<i64>() as usize)
+    .sum::<usize>();
+
+  let mut samples = vec![];
+  for seed in seeds.iter() {
+    let mut inp = inp.clone();
+    inp.retain(|tensor| tensor.idx != seed_idx);
+    inp.push(TensorMsgpack {
+      idx: seed_idx,
+      shape: vec![1],
+      data: vec![*seed],
+      dtype: None,
+    });
+    let public_vals = ModelCircuit::<Fr>::public_values(model, &inp)?;
+    let sample = public_vals[public_vals.len() - num_outputs..]
+      .iter()
+      .map(|x| decode_signed(x).map(|x| x as i64))
+      .collect::<Option<Vec<_>>>()
+      .ok_or("an output is not an integer")?;
+    samples.push(sample);
+  }
+
+  let sf = working_sf(model) as f64;
+  let num_samples = samples.len().max(1) as f64;
+  let mean = (0..num_outputs)
+    .map(|i| samples.iter().map(|x| x[i] as f64 / sf).sum::<f64>() / num_samples)
+    .collect::<Vec<_>>();
+  let std = (0..num_outputs)
+    .map(|i| {
+      let var = samples
+        .iter()
+        .map(|x| (x[i] as f64 / sf - mean[i]).powi(2))
+        .sum::<f64>()
+        / num_samples;
+      var.sqrt()
+    })
+    .collect::<Vec<_>>();
+  Ok(McDropoutReport {
+    seeds: seeds.to_vec(),
+    samples,
+    mean,
+    std,
+  })
+}
@@ -0,0 +1,75 @@
+use halo2_proofs::halo2curves::ff::PrimeField;
+
+use crate::{
+  commitments::merkle::i64_to_field,
+  utils::loader::{ModelMsgpack, TensorMsgpack},
+};
+
+// Large public inputs can be carried in the advice columns instead of the instance. The circuit
+// commits to the rlc_inputs tensors with Poseidon, and uses the commitment c as the challenge of
+// a random linear combination sum_i x_i * c^i over their values. Both c and the combination are
+// public, so the verifier, who knows the inputs, only evaluates the polynomial at c instead of
+// paying for one instance value per element. Since c binds the assigned values, a prover can only
+// pass the check with different values if c is a root of their difference.
+// The values are ordered by tensor index, then in row-major order.
+// These two values come right after the commit_before and commit_after commitments.
+pub const NUM_RLC_VALS: usize = 2;
+
+pub fn num_rlc_vals(model: &ModelMsgpack) -> usize {
+  match &model.rlc_inputs {
+    Some(idxes) if !idxes.is_empty() => NUM_RLC_VALS,
+    _ => 0,
+  }
+}
+
+// The position of the challenge in the public values, followed by the combination
+pub fn rlc_position(model: &ModelMsgpack) -> usize {
+  model.commit_before.clone().unwrap_or(vec![]).len()
+    + model.commit_after.clone().unwrap_or(vec![]).len()
+}
+
+pub fn tensors_rlc<F: PrimeField>(tensors: &Vec<&TensorMsgpack>, challenge: F) -> F {
+  let mut tensors = tensors.clone();
+  tensors.sort_by_key(|tensor| tensor.idx);
+  // Horner's rule from the last value
+  tensors
+    .iter()
+    .flat_map(|tensor| tensor.data.iter())
+    .rev()
+    .fold(F::ZERO, |acc, x| acc * challenge + i64_to_field::<F>(*x))
+}
+
+// Checks the combination in the public values against the revealed inputs
+pub fn check_rlc_inputs<F: PrimeField>(
+  model: &ModelMsgpack,
+  inputs: &Vec<TensorMsgpack>,
+  public_vals: &Vec<F>,
+) -> Result<(), String> {
+  let idxes = model.rlc_inputs.clone().unwrap_or(vec![]);
+  if idxes.is_empty() {
+    return Err("the model has no rlc_inputs".to_string());
+  }
+  let tensors = idxes
+    .iter()
+    .map(|idx| {
+      inputs
+        .iter()
+        .find(|tensor| tensor.idx == *idx)
+        .ok_or(format!("input tensor {} is missing", idx))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let pos = rlc_position(model);
+  if public_vals.len() < pos + NUM_RLC_VALS {
+    return Err(format!(
+      "expected at least {} public values, got {}",
+      pos + NUM_RLC_VALS,
+      public_vals.len()
+    ));
+  }
+  let challenge = public_vals[pos];
+  if tensors_rlc(&tensors, challenge) != public_vals[pos + 1] {
+    return Err("the inputs do not match the proof".to_string());
+  }
+  Ok(())
+}
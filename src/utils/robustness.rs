@@ -0,0 +1,123 @@
+use crate::utils::{
+  loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+  watermark::{max_tensor_idx, replicate_model, weight_idxes},
+};
+
+// Robustness spot checks: the model is evaluated on an input and on a set of perturbed inputs in
+// a single circuit, and a Robustness layer constrains every perturbation to be within epsilon in
+// L-infinity norm and the class to be an argmax of every set of logits. The weights, the input,
+// and the perturbed inputs are committed separately (the first three public values), and epsilon
+// is the only output.
+
+pub fn robustness_model(
+  model: &ModelMsgpack,
+  inp: &Vec<TensorMsgpack>,
+  perturbed: &Vec<Vec<TensorMsgpack>>,
+  epsilon: i64,
+  class: i64,
+) -> ModelMsgpack {
+  assert_eq!(
+    model.inp_idxes.len(),
+    1,
+    "only models with a single input are supported"
+  );
+  assert_eq!(
+    model.out_idxes.len(),
+    1,
+    "only models with a single output are supported"
+  );
+  assert!(!perturbed.is_empty(), "no perturbed inputs");
+  assert!(epsilon >= 0, "epsilon must be non-negative");
+
+  let inputs = [vec![inp.clone()], perturbed.clone()].concat();
+  let replicated = replicate_model(model, &inputs);
+  let weights = weight_idxes(model);
+
+  let eps_idx = max_tensor_idx(&replicated) + 1;
+  let out_idx = eps_idx + 1;
+  let inp_shape = inp[0].shape.clone();
+  let out_shape = replicated
+    .layers
+    .iter()
+    .find_map(|layer| {
+      let pos = layer
+        .out_idxes
+        .iter()
+        .position(|x| *x == replicated.out_idxes[0])?;
+      Some(layer.out_shapes[pos].clone())
+    })
+    .unwrap();
+
+  let mut inp_idxes = vec![replicated.inp_idxes[0], eps_idx, replicated.out_idxes[0]];
+  let mut inp_shapes = vec![inp_shape.clone(), vec![1], out_shape.clone()];
+  for (x, y) in replicated.inp_idxes[1..]
+    .iter()
+    .zip(replicated.out_idxes[1..].iter())
+  {
+    inp_idxes.extend([*x, *y]);
+    inp_shapes.extend([inp_shape.clone(), out_shape.clone()]);
+  }
+  let mut layers = replicated.layers.clone();
+  layers.push(LayerMsgpack {
+    layer_type: "Robustness".to_string(),
+    params: vec![class],
+    inp_shapes,
+    inp_idxes,
+    out_idxes: vec![out_idx],
+    out_shapes: vec![vec![1]],
+    mask: vec![],
+  });
+
+  let mut tensors = replicated.tensors.clone();
+  tensors.push(TensorMsgpack {
+    idx: eps_idx,
+    shape: vec![1],
+    data: vec![epsilon],
+    dtype: None,
+  });
+
+  let mut perturbed_idxes = replicated.inp_idxes[1..].to_vec();
+  perturbed_idxes.sort();
+  let commit_before = vec![weights, vec![replicated.inp_idxes[0]], perturbed_idxes];
+  ModelMsgpack {
+    out_idxes: vec![out_idx],
+    tensors,
+    layers,
+    commit_before: Some(commit_before),
+    commit_after: Some(vec![]),
+    ..replicated
+  }
+}
+
+// Returns the perturbed inputs that are not within epsilon of the input
+pub fn check_perturbations(
+  inp: &Vec<TensorMsgpack>,
+  perturbed: &Vec<Vec<TensorMsgpack>>,
+  epsilon: i64,
+) -> Vec<usize> {
+  perturbed
+    .iter()
+    .enumerate()
+    .filter(|(_, x)| {
+      x.len() != inp.len()
+        || x.iter().zip(inp.iter()).any(|(a, b)| {
+          a.shape != b.shape
+            || a
+              .data
+              .iter()
+              .zip(b.data.iter())
+              .any(|(a, b)| (a - b).abs() > epsilon)
+        })
+    })
+    .map(|(i, _)| i)
+    .collect()
+}
+
+pub fn argmax(logits: &Vec<i64>) -> usize {
+  logits
+    .iter()
+    .enumerate()
+    .max_by_key(|(i, x)| (**x, -(*i as i64)))
+    .map(|(i, _)| i)
+    .unwrap()
+}
@@ -0,0 +1,231 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+  cost_model::{load_snapshots, CostModel},
+  loader::ModelMsgpack,
+  perf::HardwareInfo,
+  profiles::column_profile,
+  storage::read_artifact,
+  validate::{checked_len, LoaderLimits},
+};
+
+// The hardened mode of the server, for models and inputs from users. On top of the checks of the
+// loader (see validate.rs), which only bound the sizes by the circuit, the profile caps the work
+// a request can ask for: k, the size of every tensor, the number of weights, and the proving time
+// the cost model (see cost_model.rs) estimates on this machine. A request over a cap gets a
+// rejection that names the cap, instead of the server trying and running out of time or memory.
+// The estimate can be off, so the server also stops waiting for a proof after max_seconds. The
+// paths of requests, local or s3:// and gs:// URLs, must be in data_dir, so clients can't read or
+// overwrite other files of the server. The profile is a JSON file, with the defaults for the
+// missing fields:
+//   {"max_k": 20, "max_seconds": 60, "snapshots": "./perf", "data_dir": "/srv/zkml"}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxProfile {
+  pub max_file_bytes: usize,
+  pub max_k: i64,
+  pub max_tensor_len: u64,
+  pub max_total_params: u64,
+  // Needs the perf snapshots to fit the cost model on
+  pub max_seconds: Option<f64>,
+  pub snapshots: Option<String>,
+  // Required, the directory or the bucket prefix of the inputs and envelopes of requests
+  pub data_dir: Option<String>,
+}
+
+impl Default for SandboxProfile {
+  fn default() -> Self {
+    Self {
+      max_file_bytes: 1 << 28,
+      max_k: 20,
+      max_tensor_len: 1 << 22,
+      max_total_params: 1 << 24,
+      max_seconds: None,
+      snapshots: None,
+      data_dir: None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rejection {
+  // The cap: "model" (the loader rejected it), "k", "tensor_len", "total_params", "seconds", or
+  // "path"
+  pub limit: String,
+  pub value: Option<f64>,
+  pub max: Option<f64>,
+  pub message: String,
+}
+
+impl Rejection {
+  pub fn invalid(message: String) -> Self {
+    Self {
+      limit: "model".to_string(),
+      value: None,
+      max: None,
+      message,
+    }
+  }
+
+  // The proof ran over max_seconds, which the estimate missed
+  pub fn timed_out(max_seconds: f64) -> Self {
+    Self {
+      limit: "seconds".to_string(),
+      value: None,
+      max: Some(max_seconds),
+      message: format!("proving took longer than {} seconds", max_seconds),
+    }
+  }
+
+  fn over(limit: &str, value: f64, max: f64, what: String) -> Self {
+    Self {
+      limit: limit.to_string(),
+      value: Some(value),
+      max: Some(max),
+      message: format!("{} is {}, the limit is {}", what, value, max),
+    }
+  }
+}
+
+pub struct Sandbox {
+  pub profile: SandboxProfile,
+  cost_model: Option<CostModel>,
+  hardware: HardwareInfo,
+}
+
+impl Sandbox {
+  pub fn new(profile: SandboxProfile) -> Result<Self, String> {
+    if profile.data_dir.is_none() {
+      return Err("the sandbox needs data_dir, the directory of the paths of requests".to_string());
+    }
+    let cost_model = match (profile.max_seconds, &profile.snapshots) {
+      (None, _) => None,
+      (Some(_), Some(snapshots)) => Some(CostModel::fit(&load_snapshots(snapshots)?)?),
+      (Some(_), None) => return Err("max_seconds needs the perf snapshots".to_string()),
+    };
+    Ok(Self {
+      profile,
+      cost_model,
+      hardware: HardwareInfo::detect(),
+    })
+  }
+
+  // The data directory can be set by the caller, e.g., serve --data
+  pub fn load(path: &str, data_dir: Option<String>) -> Result<Self, String> {
+    let mut profile: SandboxProfile = serde_json::from_slice(&read_artifact(path)?)
+      .map_err(|e| format!("malformed sandbox profile {}: {}", path, e))?;
+    if data_dir.is_some() {
+      profile.data_dir = data_dir;
+    }
+    Self::new(profile)
+  }
+
+  // Checks that a path or URL of a request is in the data directory. The check is on the string,
+  // so no component may be . or .., and the URL must have the scheme of the data directory.
+  pub fn check_path(&self, url: &str) -> Result<(), Rejection> {
+    let dir = self
+      .profile
+      .data_dir
+      .as_ref()
+      .unwrap()
+      .trim_end_matches('/');
+    let inside = url
+      .strip_prefix(dir)
+      .and_then(|rest| rest.strip_prefix('/'))
+      .map_or(false, |rest| {
+        rest
+          .split('/')
+          .all(|part| !part.is_empty() && part != "." && part != "..")
+      });
+    if !inside {
+      return Err(Rejection {
+        limit: "path".to_string(),
+        value: None,
+        max: None,
+        message: format!("{} is not in the data directory {}", url, dir),
+      });
+    }
+    Ok(())
+  }
+
+  // The loader limits, tightened to the profile
+  pub fn limits(&self) -> LoaderLimits {
+    let limits = LoaderLimits::default();
+    LoaderLimits {
+      max_file_bytes: self.profile.max_file_bytes.min(limits.max_file_bytes),
+      max_k: self.profile.max_k.min(limits.max_k),
+      max_params: (self.profile.max_total_params as usize).min(limits.max_params),
+      ..limits
+    }
+  }
+
+  // Checks a model the loader accepted, configs included: the tensors without data count by
+  // their shape
+  pub fn check(&self, model: &ModelMsgpack) -> Result<(), Rejection> {
+    let profile = &self.profile;
+    if model.k > profile.max_k {
+      return Err(Rejection::over(
+        "k",
+        model.k as f64,
+        profile.max_k as f64,
+        "k".to_string(),
+      ));
+    }
+
+    let limits = self.limits();
+    let len = |shape: &[i64]| checked_len(shape, &limits).map_err(Rejection::invalid);
+    let mut total_params = 0u64;
+    for tensor in model.tensors.iter() {
+      let tensor_len = len(&tensor.shape)?;
+      if tensor_len > profile.max_tensor_len {
+        return Err(Rejection::over(
+          "tensor_len",
+          tensor_len as f64,
+          profile.max_tensor_len as f64,
+          format!("the size of tensor {}", tensor.idx),
+        ));
+      }
+      if !model.inp_idxes.contains(&tensor.idx) {
+        total_params += tensor_len;
+      }
+    }
+    for (pos, layer) in model.layers.iter().enumerate() {
+      for (idx, shape) in layer.out_idxes.iter().zip(layer.out_shapes.iter()) {
+        let tensor_len = len(shape)?;
+        if tensor_len > profile.max_tensor_len {
+          return Err(Rejection::over(
+            "tensor_len",
+            tensor_len as f64,
+            profile.max_tensor_len as f64,
+            format!("the size of tensor {}, the output of layer {}", idx, pos),
+          ));
+        }
+      }
+    }
+    if total_params > profile.max_total_params {
+      return Err(Rejection::over(
+        "total_params",
+        total_params as f64,
+        profile.max_total_params as f64,
+        "the number of weights".to_string(),
+      ));
+    }
+
+    if let (Some(max_seconds), Some(cost_model)) = (profile.max_seconds, &self.cost_model) {
+      // The estimate applies the column profile, which must exist
+      if let Some(name) = &model.column_profile {
+        column_profile(name).map_err(Rejection::invalid)?;
+      }
+      let estimate = cost_model.estimate(model, &self.hardware);
+      if estimate.total_seconds > max_seconds {
+        return Err(Rejection::over(
+          "seconds",
+          estimate.total_seconds.ceil(),
+          max_seconds,
+          "the estimated proving time".to_string(),
+        ));
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+  audit::audit_model,
+  loader::{LayerMsgpack, ModelMsgpack},
+};
+
+// Scale propagation report. Every tensor edge is at the global scale factor, since the layers
+// rescale their outputs, so precision is lost wherever a layer divides: the rescale after a
+// product, fixed divisions, and averages. A division by d rounds away log2(d) bits, and the
+// output keeps log2(max |out|) bits, using the magnitude bounds of the audit.
+// A layer is flagged if it divides by more than the scale factor, or if its output keeps fewer
+// than min_bits bits.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EdgeScale {
+  pub tensor_idx: i64,
+  pub shape: Vec<i64>,
+  pub scale_factor: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayerScale {
+  pub layer_idx: usize,
+  pub layer_type: String,
+  pub outputs: Vec<EdgeScale>,
+  pub divisor: Option<f64>,
+  pub rounded_bits: f64,
+  pub out_bits: f64,
+  pub flagged: bool,
+  pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScaleReport {
+  pub scale_factor: i64,
+  pub min_bits: f64,
+  pub inputs: Vec<EdgeScale>,
+  pub layers: Vec<LayerScale>,
+}
+
+// The divisor of the layer, or None if it does not divide
+fn layer_divisor(layer: &LayerMsgpack, sf: f64) -> Option<f64> {
+  let params = &layer.params;
+  match layer.layer_type.as_str() {
+    "Conv2D" | "Conv3D" | "FullyConnected" | "BatchMatMul" | "Mul" | "Square"
+    | "SquaredDifference" | "Dropout" => Some(sf),
+    "Div" => Some(params[0] as f64),
+    "DivMod" => params[2..].iter().max().map(|d| *d as f64),
+    "AveragePool2D" => Some((params[0] * params[1]) as f64),
+    "AveragePool3D" => Some((params[0] * params[1] * params[2]) as f64),
+    "Mean" => {
+      let shape = &layer.inp_shapes[0];
+      let div = params
+        .iter()
+        .map(|axis| shape[*axis as usize])
+        .product::<i64>();
+      Some(div as f64)
+    }
+    _ => None,
+  }
+}
+
+pub fn scale_report(
+  model: &ModelMsgpack,
+  inp_bounds: &HashMap<i64, f64>,
+  min_bits: f64,
+) -> ScaleReport {
+  let sf = model.global_sf as f64;
+  let audit = audit_model(model, inp_bounds, 0.);
+
+  let inputs = model
+    .inp_idxes
+    .iter()
+    .map(|idx| EdgeScale {
+      tensor_idx: *idx,
+      shape: model
+        .tensors
+        .iter()
+        .find(|tensor| tensor.idx == *idx)
+        .map_or(vec![], |tensor| tensor.shape.clone()),
+      scale_factor: model.global_sf,
+    })
+    .collect();
+
+  let layers = model
+    .layers
+    .iter()
+    .zip(audit.layers.iter())
+    .enumerate()
+    .map(|(layer_idx, (layer, layer_audit))| {
+      let divisor = layer_divisor(layer, sf);
+      let rounded_bits = divisor.map_or(0., |d| d.max(1.).log2());
+      let out_bits = (layer_audit.out_bound + 1.).log2();
+
+      let reason = if divisor.map_or(false, |d| d > sf) {
+        Some(format!(
+          "divides by {} > scale factor {}",
+          divisor.unwrap(),
+          sf
+        ))
+      } else if divisor.is_some() && out_bits < min_bits {
+        Some(format!("output keeps {:.1} bits", out_bits))
+      } else {
+        None
+      };
+      LayerScale {
+        layer_idx,
+        layer_type: layer.layer_type.clone(),
+        outputs: layer
+          .out_idxes
+          .iter()
+          .zip(layer.out_shapes.iter())
+          .map(|(idx, shape)| EdgeScale {
+            tensor_idx: *idx,
+            shape: shape.clone(),
+            scale_factor: model.global_sf,
+          })
+          .collect(),
+        divisor,
+        rounded_bits,
+        out_bits,
+        flagged: reason.is_some(),
+        reason,
+      }
+    })
+    .collect();
+
+  ScaleReport {
+    scale_factor: model.global_sf,
+    min_bits,
+    inputs,
+    layers,
+  }
+}
@@ -0,0 +1,364 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::{BufRead, BufReader, Write},
+  os::unix::net::{UnixListener, UnixStream},
+  panic::{catch_unwind, AssertUnwindSafe},
+  sync::{Arc, Mutex},
+  thread::JoinHandle,
+  time::Duration,
+};
+
+use halo2_proofs::{
+  halo2curves::bn256::{Bn256, Fr, G1Affine},
+  plonk::{create_proof, ProvingKey},
+  poly::kzg::{
+    commitment::{KZGCommitmentScheme, ParamsKZG},
+    multiopen::ProverSHPLONK,
+  },
+  transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+  SerdeFormat,
+};
+use ndarray::{Array, IxDyn};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    artifacts::content_hash,
+    cancel::try_with_timeout,
+    envelope::{srs_hash, ProofEnvelope, VerifyOutcome},
+    helpers::{instance_columns, instance_slices, prover_rng},
+    loader::{add_inputs, parse_config_msgpack, parse_inputs_msgpack, set_defaults, ModelMsgpack},
+    pk_cache::load_or_keygen_pk,
+    proving_kzg::get_kzg_params,
+    sandbox::{Rejection, Sandbox},
+    storage::{list_artifacts, read_artifact},
+    validate::LoaderLimits,
+    weight_cache::{read_weight_cache, weight_cache_path, write_weight_cache},
+  },
+};
+
+// Warm-start server. Every <name>.msgpack config in the models directory is loaded at startup,
+// together with its proving key (<name>.pkey, generated and written if missing or stale, see
+// pk_cache.rs), its weights in the field (<name>.weights, generated and written if missing), and
+// the SRS for its k. Requests are then answered over a unix socket, one JSON object per line:
+//   {"op": "prove", "model": <name>, "input": <input path>, "output": <envelope path>}
+//   {"op": "verify", "envelope": <envelope path>, "model": <the name of the model to pin the vkey>}
+// Each request gets a single JSON line back. Requests are handled one at a time, since the
+// gadget config is global. In the hardened mode (see sandbox.rs), the models and the inputs over
+// the caps of the profile are rejected before any work, with a "rejection" naming the cap, and so
+// are the paths outside of the data directory. A proof that runs over max_seconds is rejected
+// too, but keeps running, since halo2 can't stop it, and the next request waits for it.
+pub struct ServedModel {
+  pub config: ModelMsgpack,
+  pub pk: Arc<ProvingKey<G1Affine>>,
+  pub vkey: Vec<u8>,
+  pub weights: Arc<BTreeMap<i64, Array<Fr, IxDyn>>>,
+}
+
+pub struct Server {
+  pub models: HashMap<String, ServedModel>,
+  pub params: HashMap<u32, Arc<ParamsKZG<Bn256>>>,
+  pub srs_hashes: HashMap<u32, String>,
+  pub sandbox: Option<Sandbox>,
+  // The proof of a request that timed out
+  worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+pub enum RequestError {
+  Failed(String),
+  Rejected(Rejection),
+}
+
+impl From<String> for RequestError {
+  fn from(e: String) -> Self {
+    RequestError::Failed(e)
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+  Prove {
+    model: String,
+    input: String,
+    output: String,
+  },
+  Verify {
+    envelope: String,
+    model: Option<String>,
+  },
+}
+
+// The proof and the public values. The circuit is built here, since it can't be sent to the
+// worker of a request with a timeout.
+fn create_served_proof(
+  params: &ParamsKZG<Bn256>,
+  pk: &ProvingKey<G1Affine>,
+  weights: &BTreeMap<i64, Array<Fr, IxDyn>>,
+  config: ModelMsgpack,
+) -> Result<(Vec<u8>, Vec<Fr>), String> {
+  let circuit = ModelCircuit::<Fr>::generate_from_msgpack_with_weights(config, true, weights);
+  let public_vals = circuit.compute_public_values()?;
+  let instances = instance_columns(&public_vals, circuit.num_commitments());
+  let rng = prover_rng(circuit.zero_knowledge);
+  let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+  create_proof::<
+    KZGCommitmentScheme<Bn256>,
+    ProverSHPLONK<'_, Bn256>,
+    Challenge255<G1Affine>,
+    _,
+    Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+    ModelCircuit<Fr>,
+  >(
+    params,
+    pk,
+    &[circuit],
+    &[&instance_slices(&instances)[..]],
+    rng,
+    &mut transcript,
+  )
+  .map_err(|e| format!("proving failed: {}", e))?;
+  Ok((transcript.finalize(), public_vals))
+}
+
+fn loader_limits(sandbox: &Option<Sandbox>) -> LoaderLimits {
+  sandbox
+    .as_ref()
+    .map_or_else(LoaderLimits::default, |sandbox| sandbox.limits())
+}
+
+impl Server {
+  pub fn load(models_dir: &str, params_dir: &str, sandbox: Option<Sandbox>) -> Self {
+    let limits = loader_limits(&sandbox);
+    let fnames = list_artifacts(models_dir)
+      .expect("could not read the models directory")
+      .into_iter()
+      .filter(|url| url.ends_with(".msgpack"))
+      .collect::<Vec<_>>();
+
+    let mut models = HashMap::new();
+    let mut params = HashMap::new();
+    for fname in fnames {
+      let stem = fname.trim_end_matches(".msgpack");
+      let name = stem.rsplit('/').next().unwrap().to_string();
+      // Model files can come from third parties, a malformed one is skipped
+      let config = read_artifact(&fname).and_then(|buf| parse_config_msgpack(&buf, &limits));
+      let checked = config.map_err(Rejection::invalid).and_then(|config| {
+        if let Some(sandbox) = &sandbox {
+          sandbox.check(&config)?;
+        }
+        Ok(config)
+      });
+      let mut config = match checked {
+        Ok(config) => config,
+        Err(rejection) => {
+          println!("Skipped {}: {}", name, rejection.message);
+          continue;
+        }
+      };
+      set_defaults(&mut config);
+      let k = config.k as u32;
+      let params: &ParamsKZG<Bn256> = params
+        .entry(k)
+        .or_insert_with(|| Arc::new(get_kzg_params(params_dir, k)));
+
+      let pk_fname = format!("{}.pkey", stem);
+      let weights_fname = weight_cache_path(&pk_fname);
+      let cached = read_weight_cache::<Fr>(&weights_fname, &config).unwrap();
+      // Also sets up the gadget config, which is needed to read the proving key
+      let circuit = ModelCircuit::<Fr>::generate_from_msgpack_with_weights(
+        config.clone(),
+        false,
+        cached.as_ref().unwrap_or(&BTreeMap::new()),
+      );
+      if cached.is_none() {
+        write_weight_cache(&weights_fname, &config, &circuit).unwrap();
+      }
+      let weights = circuit
+        .tensors
+        .iter()
+        .filter(|(idx, _)| !config.inp_idxes.contains(idx))
+        .map(|(idx, tensor)| (*idx, tensor.clone()))
+        .collect();
+      let pk = load_or_keygen_pk(&pk_fname, &config, params, &circuit).unwrap();
+      let vkey = pk.get_vk().to_bytes(SerdeFormat::RawBytes);
+      println!("Loaded {} (k = {})", name, k);
+      models.insert(
+        name,
+        ServedModel {
+          config,
+          pk: Arc::new(pk),
+          vkey,
+          weights: Arc::new(weights),
+        },
+      );
+    }
+
+    let srs_hashes = params
+      .iter()
+      .map(|(k, params)| (*k, srs_hash(params)))
+      .collect();
+    Self {
+      models,
+      params,
+      srs_hashes,
+      sandbox,
+      worker: Mutex::new(None),
+    }
+  }
+
+  // Waits for the proof of a request that timed out, which still uses the gadget config
+  fn wait_for_worker(&self) {
+    if let Some(worker) = self.worker.lock().unwrap().take() {
+      let _ = worker.join();
+    }
+  }
+
+  fn check_path(&self, url: &str) -> Result<(), RequestError> {
+    match &self.sandbox {
+      Some(sandbox) => sandbox.check_path(url).map_err(RequestError::Rejected),
+      None => Ok(()),
+    }
+  }
+
+  pub fn prove(&self, name: &str, inp_fname: &str, out_fname: &str) -> Result<(), RequestError> {
+    let model = self
+      .models
+      .get(name)
+      .ok_or_else(|| format!("unknown model {}", name))?;
+    self.check_path(inp_fname)?;
+    self.check_path(out_fname)?;
+    let limits = loader_limits(&self.sandbox);
+    let inp = parse_inputs_msgpack(&read_artifact(inp_fname)?, &limits)
+      .and_then(|inp| add_inputs(model.config.clone(), inp, &limits));
+    let config = match (inp, &self.sandbox) {
+      (Ok(config), Some(sandbox)) => {
+        sandbox.check(&config).map_err(RequestError::Rejected)?;
+        config
+      }
+      (Ok(config), None) => config,
+      (Err(e), Some(_)) => return Err(RequestError::Rejected(Rejection::invalid(e))),
+      (Err(e), None) => return Err(RequestError::Failed(e)),
+    };
+    let k = config.k as u32;
+    let params = &self.params[&k];
+
+    let max_seconds = self.sandbox.as_ref().and_then(|x| x.profile.max_seconds);
+    let (proof, public_vals) = match max_seconds {
+      Some(max_seconds) => {
+        let (params, pk, weights) = (params.clone(), model.pk.clone(), model.weights.clone());
+        let prove = move || create_served_proof(&params, &pk, &weights, config);
+        match try_with_timeout(Duration::from_secs_f64(max_seconds), prove) {
+          Ok(proof) => proof?,
+          Err(worker) => {
+            // No envelope is written for it
+            *self.worker.lock().unwrap() = Some(worker);
+            return Err(RequestError::Rejected(Rejection::timed_out(max_seconds)));
+          }
+        }
+      }
+      None => create_served_proof(params, &model.pk, &model.weights, config)?,
+    };
+
+    let public_vals_u8 = public_vals
+      .iter()
+      .map(|v: &Fr| v.to_bytes().to_vec())
+      .flatten()
+      .collect();
+    let envelope = ProofEnvelope::new(&model.config, model.vkey.clone(), proof, public_vals_u8)
+      .with_srs_hash(self.srs_hashes[&k].clone());
+    envelope.write(out_fname);
+    Ok(())
+  }
+
+  pub fn handle(&self, request: &Request) -> serde_json::Value {
+    self.wait_for_worker();
+    match request {
+      Request::Prove {
+        model,
+        input,
+        output,
+      } => match self.prove(model, input, output) {
+        Ok(()) => serde_json::json!({ "ok": true, "envelope": output }),
+        Err(RequestError::Failed(e)) => serde_json::json!({ "ok": false, "error": e }),
+        Err(RequestError::Rejected(rejection)) => serde_json::json!({
+          "ok": false,
+          "error": rejection.message,
+          "rejection": rejection,
+        }),
+      },
+      Request::Verify { envelope, model } => {
+        if let Some(Err(rejection)) = self.sandbox.as_ref().map(|x| x.check_path(envelope)) {
+          return serde_json::json!({
+            "ok": false,
+            "error": rejection.message,
+            "rejection": rejection,
+          });
+        }
+        let envelope = match ProofEnvelope::read(envelope) {
+          Ok(envelope) => envelope,
+          Err(e) => return serde_json::json!({ "ok": false, "error": e }),
+        };
+        // The vk of the envelope is the prover's, so it is pinned to the vk of a served model
+        let trusted_vkey_hash = match model {
+          Some(name) => match self.models.get(name) {
+            Some(model) => content_hash(&model.vkey),
+            None => return serde_json::json!({ "ok": false, "error": "unknown model" }),
+          },
+          None => {
+            let msg = "no trusted verification key, pass the model".to_string();
+            let mut res = VerifyOutcome::VkMismatch(msg).to_json();
+            res["ok"] = serde_json::json!(true);
+            return res;
+          }
+        };
+        let params = match self.params.get(&envelope.k) {
+          Some(params) => params,
+          None => return serde_json::json!({ "ok": false, "error": "no SRS loaded for this k" }),
+        };
+        let params_hash = self.srs_hashes.get(&envelope.k).map(|x| x.as_str());
+        let mut res = envelope
+          .verify(params, srs_hash, &trusted_vkey_hash)
+          .to_json();
+        res["ok"] = serde_json::json!(true);
+        res
+      }
+    }
+  }
+
+  fn handle_stream(&self, stream: UnixStream) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+      let line = line?;
+      if line.trim().is_empty() {
+        continue;
+      }
+      let res = match serde_json::from_str::<Request>(&line) {
+        // A panic in the circuit fails the request, not the server
+        Ok(request) => catch_unwind(AssertUnwindSafe(|| self.handle(&request)))
+          .unwrap_or_else(|_| serde_json::json!({ "ok": false, "error": "request panicked" })),
+        Err(e) => serde_json::json!({ "ok": false, "error": format!("bad request: {}", e) }),
+      };
+      writeln!(writer, "{}", res)?;
+    }
+    Ok(())
+  }
+
+  pub fn serve(&self, socket_path: &str) {
+    // Remove a stale socket from a previous run
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path).expect("could not bind the socket");
+    println!("Serving {} models on {}", self.models.len(), socket_path);
+    for stream in listener.incoming() {
+      match stream {
+        Ok(stream) => {
+          if let Err(e) = self.handle_stream(stream) {
+            eprintln!("connection failed: {}", e);
+          }
+        }
+        Err(e) => eprintln!("could not accept a connection: {}", e),
+      }
+    }
+  }
+}
@@ -0,0 +1,229 @@
+use std::{
+  io::Write,
+  process::{Command, Stdio},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  artifacts::content_hash,
+  storage::{read_artifact, write_artifact},
+};
+
+// Signs proof envelopes with the prover identity key. The key stays in a KMS or an HSM: the
+// signers only hand the message to the aws CLI (v2) or to OpenSC's pkcs11-tool, as the storage
+// backends do with the object stores, so the credentials and configuration of those tools apply
+// and the process never sees the key. Both sign with ECDSA P-256 over SHA-256 and give DER
+// signatures, which anyone can also check with the public key, e.g.,
+// openssl dgst -sha256 -verify pub.pem -signature <sig> <message>.
+pub trait Signer {
+  // Identifies the key in the signature file, e.g., kms:<key id>
+  fn key_id(&self) -> String;
+  fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String>;
+  fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, String>;
+}
+
+fn run(mut command: Command, stdin: Option<&[u8]>) -> Result<Vec<u8>, String> {
+  let program = command.get_program().to_string_lossy().to_string();
+  let mut child = command
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| format!("could not run {}: {}", program, e))?;
+  let mut pipe = child.stdin.take().unwrap();
+  if let Some(data) = stdin {
+    pipe
+      .write_all(data)
+      .map_err(|e| format!("could not write to {}: {}", program, e))?;
+  }
+  drop(pipe);
+  let output = child.wait_with_output().map_err(|e| e.to_string())?;
+  if !output.status.success() {
+    return Err(format!(
+      "{} failed: {}",
+      program,
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  Ok(output.stdout)
+}
+
+// The aws CLI takes and gives blobs in base64
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+  BASE64
+    .decode(text.trim())
+    .map_err(|e| format!("{:?} is not base64: {}", text, e))
+}
+
+fn to_hex(data: &[u8]) -> String {
+  data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+  if text.len() % 2 != 0 || !text.is_ascii() {
+    return Err(format!("{:?} is not hex", text));
+  }
+  (0..text.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| format!("{:?} is not hex", text)))
+    .collect()
+}
+
+// An asymmetric ECC_NIST_P256 SIGN_VERIFY key of AWS KMS, by id, alias, or ARN
+pub struct KmsSigner {
+  pub key: String,
+}
+
+impl KmsSigner {
+  fn command(&self, op: &str, message: &[u8]) -> Command {
+    let mut command = Command::new("aws");
+    command.args(["kms", op, "--key-id", &self.key]);
+    command.args([
+      "--message",
+      &BASE64.encode(message),
+      "--message-type",
+      "RAW",
+    ]);
+    command.args(["--signing-algorithm", "ECDSA_SHA_256", "--output", "text"]);
+    command
+  }
+}
+
+impl Signer for KmsSigner {
+  fn key_id(&self) -> String {
+    format!("kms:{}", self.key)
+  }
+
+  fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+    let mut command = self.command("sign", message);
+    command.args(["--query", "Signature"]);
+    base64_decode(&String::from_utf8_lossy(&run(command, None)?))
+  }
+
+  fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, String> {
+    let mut command = self.command("verify", message);
+    command.args(["--signature", &BASE64.encode(signature)]);
+    command.args(["--query", "SignatureValid"]);
+    // The CLI fails on an invalid signature, and any other failure is not a valid signature either
+    Ok(run(command, None).map_or(false, |out| String::from_utf8_lossy(&out).trim() == "True"))
+  }
+}
+
+// A key of a PKCS#11 token, by the path of the token's module and the hex id of the key. The user
+// PIN is read by pkcs11-tool from ZKML_PKCS11_PIN, so it is not on the command line.
+pub struct Pkcs11Signer {
+  pub module: String,
+  pub id: String,
+}
+
+impl Pkcs11Signer {
+  fn command(&self) -> Command {
+    let mut command = Command::new("pkcs11-tool");
+    command.args(["--module", &self.module, "--id", &self.id]);
+    command.args([
+      "--mechanism",
+      "ECDSA-SHA256",
+      "--signature-format",
+      "openssl",
+    ]);
+    command
+  }
+}
+
+impl Signer for Pkcs11Signer {
+  fn key_id(&self) -> String {
+    format!("pkcs11:{}:{}", self.module, self.id)
+  }
+
+  fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+    let mut command = self.command();
+    command.args(["--login", "--pin", "env:ZKML_PKCS11_PIN", "--sign"]);
+    command.args(["--input-file", "/dev/stdin", "--output-file", "/dev/stdout"]);
+    run(command, Some(message))
+  }
+
+  // pkcs11-tool reads the signature from a file, and the message from stdin. The file has a random
+  // name, is created exclusively and only readable by the user, and is removed when dropped.
+  fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, String> {
+    let mut sig_file = tempfile::NamedTempFile::new().map_err(|e| e.to_string())?;
+    sig_file
+      .write_all(signature)
+      .and_then(|()| sig_file.flush())
+      .map_err(|e| e.to_string())?;
+    let mut command = self.command();
+    command.args(["--verify", "--input-file", "/dev/stdin", "--signature-file"]);
+    command.arg(sig_file.path());
+    Ok(run(command, Some(message)).is_ok())
+  }
+}
+
+// kms:<key id, alias, or ARN>, or pkcs11:<module path>:<key id>
+pub fn parse_signer(spec: &str) -> Result<Box<dyn Signer>, String> {
+  if let Some(key) = spec.strip_prefix("kms:") {
+    return Ok(Box::new(KmsSigner {
+      key: key.to_string(),
+    }));
+  }
+  if let Some((module, id)) = spec
+    .strip_prefix("pkcs11:")
+    .and_then(|x| x.rsplit_once(':'))
+  {
+    return Ok(Box::new(Pkcs11Signer {
+      module: module.to_string(),
+      id: id.to_string(),
+    }));
+  }
+  Err(format!(
+    "unknown signer {}, expected kms:<key> or pkcs11:<module>:<key id>",
+    spec
+  ))
+}
+
+// The detached signature of an envelope file, next to it as <envelope>.sig
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnvelopeSignature {
+  pub signer: String,
+  pub envelope_hash: String,
+  pub signature: String, // Hex, DER
+}
+
+pub fn signature_path(envelope_fname: &str) -> String {
+  format!("{}.sig", envelope_fname)
+}
+
+// The signed message, which names what is signed so the signature can't be used for anything else
+fn signed_message(envelope_hash: &str) -> Vec<u8> {
+  format!("zkml proof envelope {}", envelope_hash).into_bytes()
+}
+
+pub fn sign_envelope(envelope_fname: &str, signer: &dyn Signer) -> Result<(), String> {
+  let envelope_hash = content_hash(&read_artifact(envelope_fname)?);
+  let signature = signer.sign(&signed_message(&envelope_hash))?;
+  let signature = EnvelopeSignature {
+    signer: signer.key_id(),
+    envelope_hash,
+    signature: to_hex(&signature),
+  };
+  let buf = serde_json::to_vec_pretty(&signature).unwrap();
+  write_artifact(&signature_path(envelope_fname), &buf)
+}
+
+// Whether the signature file is for this envelope and was made by the signer's key
+pub fn verify_envelope_signature(
+  envelope_fname: &str,
+  signer: &dyn Signer,
+) -> Result<bool, String> {
+  let sig_fname = signature_path(envelope_fname);
+  let signature: EnvelopeSignature = serde_json::from_slice(&read_artifact(&sig_fname)?)
+    .map_err(|e| format!("malformed signature {}: {}", sig_fname, e))?;
+  let envelope_hash = content_hash(&read_artifact(envelope_fname)?);
+  if signature.signer != signer.key_id() || signature.envelope_hash != envelope_hash {
+    return Ok(false);
+  }
+  signer.verify(
+    &signed_message(&envelope_hash),
+    &from_hex(&signature.signature)?,
+  )
+}
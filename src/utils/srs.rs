@@ -0,0 +1,260 @@
+use std::{
+  fs::File,
+  io::{BufReader, Read},
+};
+
+use halo2_proofs::{
+  halo2curves::{
+    bn256::{Bn256, G1Affine, G2Affine},
+    pasta::EqAffine,
+    serde::SerdeObject,
+  },
+  poly::{
+    commitment::{Params, ParamsProver},
+    ipa::commitment::ParamsIPA,
+    kzg::commitment::ParamsKZG,
+  },
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+  artifacts::{content_hash, content_hasher},
+  storage::{artifact_exists, join_url, local_path, read_artifact, write_artifact},
+};
+
+// A params directory can hold the SRS for several curves and sizes, listed in an index.json.
+// Proving and verifying pick the smallest SRS for the curve that is at least as large as the
+// circuit, and downsize it. Directories without an index keep the <k>.params layout.
+pub const INDEX_NAME: &str = "index.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SrsEntry {
+  pub scheme: String, // kzg or ipa
+  pub curve: String,  // bn256 for kzg, pasta for ipa
+  pub k: u32,
+  pub file: String,
+  pub hash: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SrsIndex {
+  pub entries: Vec<SrsEntry>,
+}
+
+pub fn scheme_curve(scheme: &str) -> &'static str {
+  match scheme {
+    "kzg" => "bn256",
+    "ipa" => "pasta",
+    _ => panic!("unknown scheme {}, must be kzg or ipa", scheme),
+  }
+}
+
+pub fn has_index(dir: &str) -> bool {
+  artifact_exists(&join_url(dir, INDEX_NAME))
+}
+
+impl SrsIndex {
+  pub fn load(dir: &str) -> Result<Self, String> {
+    let path = join_url(dir, INDEX_NAME);
+    if !artifact_exists(&path) {
+      return Ok(Self::default());
+    }
+    serde_json::from_slice(&read_artifact(&path)?)
+      .map_err(|e| format!("malformed SRS index {}: {}", path, e))
+  }
+
+  pub fn save(&self, dir: &str) -> Result<(), String> {
+    let buf = serde_json::to_vec_pretty(self).unwrap();
+    write_artifact(&join_url(dir, INDEX_NAME), &buf)
+  }
+
+  // Writes the serialized params to the directory and indexes them, replacing the entry for the
+  // same scheme and k
+  pub fn add(&mut self, dir: &str, scheme: &str, k: u32, data: &[u8]) -> Result<SrsEntry, String> {
+    let curve = scheme_curve(scheme);
+    let file = format!("{}-{}-{}.params", scheme, curve, k);
+    write_artifact(&join_url(dir, &file), data)?;
+    let entry = SrsEntry {
+      scheme: scheme.to_string(),
+      curve: curve.to_string(),
+      k,
+      file,
+      hash: content_hash(data),
+    };
+    self.entries.retain(|e| !(e.scheme == scheme && e.k == k));
+    self.entries.push(entry.clone());
+    self.entries.sort_by_key(|e| (e.scheme.clone(), e.k));
+    Ok(entry)
+  }
+
+  pub fn select(&self, scheme: &str, k: u32) -> Option<&SrsEntry> {
+    self
+      .entries
+      .iter()
+      .filter(|e| e.scheme == scheme && e.k >= k)
+      .min_by_key(|e| e.k)
+  }
+}
+
+pub fn k_mismatch_error(srs_k: u32, k: u32) -> String {
+  format!(
+    "SRS supports k={} but model requires k={}; regenerate or downsize params",
+    srs_k, k
+  )
+}
+
+// Downsizes the params to the circuit size, and fails if they are too small
+pub fn fit_kzg_params(params: &mut ParamsKZG<Bn256>, k: u32) -> Result<(), String> {
+  if params.k() < k {
+    return Err(k_mismatch_error(params.k(), k));
+  }
+  if params.k() > k {
+    params.downsize(k);
+  }
+  Ok(())
+}
+
+pub fn fit_ipa_params(params: &mut ParamsIPA<EqAffine>, k: u32) -> Result<(), String> {
+  if params.k() < k {
+    return Err(k_mismatch_error(params.k(), k));
+  }
+  if params.k() > k {
+    params.downsize(k);
+  }
+  Ok(())
+}
+
+// Hashes the bytes as they are read
+pub struct HashingReader<R: Read> {
+  inner: R,
+  state: blake2b_simd::State,
+}
+
+impl<R: Read> HashingReader<R> {
+  pub fn new(inner: R) -> Self {
+    Self {
+      inner,
+      state: content_hasher(),
+    }
+  }
+
+  // Reads the rest of the input, and returns the hash of all of it
+  pub fn finalize(mut self) -> Result<String, String> {
+    std::io::copy(&mut self, &mut std::io::sink()).map_err(|e| e.to_string())?;
+    Ok(self.state.finalize().to_hex().to_string())
+  }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.state.update(&buf[..n]);
+    Ok(n)
+  }
+}
+
+fn skip<R: Read>(reader: &mut R, len: u64) -> std::io::Result<()> {
+  let skipped = std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+  if skipped < len {
+    return Err(std::io::ErrorKind::UnexpectedEof.into());
+  }
+  Ok(())
+}
+
+// Reads the params for a circuit of size k from an SRS of size at least k, in the raw format of
+// Params::write, without materializing the SRS. Only the first 2^k powers are kept and the rest
+// is skipped. The Lagrange basis is read if the SRS has size k, and is recomputed from the powers
+// otherwise, so the peak memory is that of the params of size k, not of the SRS.
+pub fn read_kzg_params<R: Read>(reader: &mut R, k: u32) -> Result<ParamsKZG<Bn256>, String> {
+  let malformed = |e: std::io::Error| format!("malformed params: {}", e);
+  let mut header = [0u8; 4];
+  reader.read_exact(&mut header).map_err(malformed)?;
+  let srs_k = u32::from_le_bytes(header);
+  if srs_k < k {
+    return Err(k_mismatch_error(srs_k, k));
+  }
+  if srs_k > 32 {
+    return Err(format!("malformed params: k = {}", srs_k));
+  }
+
+  let (n, srs_n) = (1u64 << k, 1u64 << srs_k);
+  let point_len = G1Affine::default().to_raw_bytes().len() as u64;
+  let mut read_points = |n: u64| {
+    (0..n)
+      .map(|_| G1Affine::read_raw(reader))
+      .collect::<Result<Vec<_>, _>>()
+  };
+  let g = read_points(n).map_err(malformed)?;
+  let g_lagrange = if srs_k == k {
+    Some(read_points(n).map_err(malformed)?)
+  } else {
+    None
+  };
+  if g_lagrange.is_none() {
+    // The rest of the powers, and the Lagrange basis of the SRS
+    info!("downsizing the SRS from k = {} to k = {}", srs_k, k);
+    skip(reader, (2 * srs_n - n) * point_len).map_err(malformed)?;
+  }
+  let g2 = G2Affine::read_raw(reader).map_err(malformed)?;
+  let s_g2 = G2Affine::read_raw(reader).map_err(malformed)?;
+
+  // from_parts doesn't read its receiver, which only needs to be some params
+  let base = ParamsKZG::<Bn256>::setup(1, rand::thread_rng());
+  Ok(base.from_parts(k, g, g_lagrange, g2, s_g2))
+}
+
+// Reads the params for a circuit of size k from an SRS file, without a copy of the file in memory
+// next to them (see read_kzg_params). Returns the params and the hash of the file.
+pub fn stream_kzg_params(url: &str, k: u32) -> Result<(ParamsKZG<Bn256>, String), String> {
+  let path = local_path(url)?;
+  let file = File::open(&path).map_err(|e| format!("couldn't open {}: {}", url, e))?;
+  let mut reader = HashingReader::new(BufReader::new(file));
+  let params = read_kzg_params(&mut reader, k).map_err(|e| format!("{}: {}", url, e))?;
+  Ok((params, reader.finalize()?))
+}
+
+// The params for the circuit size from the index, generating and indexing them if no SRS is large
+// enough
+pub fn indexed_kzg_params(dir: &str, k: u32) -> ParamsKZG<Bn256> {
+  let mut index = SrsIndex::load(dir).unwrap();
+  let entry = match index.select("kzg", k) {
+    Some(entry) => entry.clone(),
+    None => {
+      let params = ParamsKZG::<Bn256>::setup(k, rand::thread_rng());
+      let mut buf = vec![];
+      params.write(&mut buf).expect("Failed to write params");
+      let entry = index.add(dir, "kzg", k, &buf).unwrap();
+      index.save(dir).unwrap();
+      entry
+    }
+  };
+  let (params, hash) = stream_kzg_params(&join_url(dir, &entry.file), k).unwrap();
+  assert_eq!(hash, entry.hash, "{} does not match the index", entry.file);
+  params
+}
+
+pub fn indexed_ipa_params(dir: &str, k: u32) -> ParamsIPA<EqAffine> {
+  let mut index = SrsIndex::load(dir).unwrap();
+  let entry = match index.select("ipa", k) {
+    Some(entry) => entry.clone(),
+    None => {
+      let params: ParamsIPA<EqAffine> = ParamsIPA::new(k);
+      let mut buf = vec![];
+      params.write(&mut buf).expect("Failed to write params");
+      let entry = index.add(dir, "ipa", k, &buf).unwrap();
+      index.save(dir).unwrap();
+      entry
+    }
+  };
+  let buf = read_artifact(&join_url(dir, &entry.file)).expect("couldn't load params");
+  assert_eq!(
+    content_hash(&buf),
+    entry.hash,
+    "{} does not match the index",
+    entry.file
+  );
+  let mut params: ParamsIPA<EqAffine> =
+    Params::read::<_>(&mut &buf[..]).expect("Failed to read params");
+  fit_ipa_params(&mut params, k).unwrap();
+  params
+}
@@ -0,0 +1,59 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use halo2_proofs::{
+  dev::MockProver,
+  halo2curves::ff::{FromUniformBytes, PrimeField},
+  plonk::{Circuit, ConstraintSystem},
+};
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{model::ModelCircuit, utils::helpers::NUM_INSTANCE_COLS};
+
+// Row counts are recorded by the gadgets as they are laid out. Regions that layers assign
+// directly (e.g., the tensor assignment) are not included, so these track the cost of the
+// gadgets rather than the total number of rows.
+lazy_static! {
+  pub static ref GADGET_ROWS: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+}
+
+pub fn record_gadget_rows(name: &str, num_rows: usize) {
+  let mut rows = GADGET_ROWS.lock().unwrap();
+  *rows.entry(name.to_string()).or_insert(0) += num_rows;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CircuitStats {
+  pub k: usize,
+  pub num_advice_columns: usize,
+  pub num_fixed_columns: usize,
+  pub num_gates: usize,
+  pub num_lookups: usize,
+  pub degree: usize,
+  pub total_gadget_rows: usize,
+  pub gadget_rows: BTreeMap<String, usize>,
+}
+
+// Configures the circuit and synthesizes it with the MockProver to count the rows
+pub fn circuit_stats<F: PrimeField + Ord + FromUniformBytes<64>>(
+  circuit: &ModelCircuit<F>,
+) -> CircuitStats {
+  let mut cs = ConstraintSystem::<F>::default();
+  ModelCircuit::<F>::configure(&mut cs);
+
+  GADGET_ROWS.lock().unwrap().clear();
+  let instances = vec![vec![]; NUM_INSTANCE_COLS];
+  let _prover = MockProver::run(circuit.k as u32, circuit, instances).unwrap();
+  let gadget_rows = GADGET_ROWS.lock().unwrap().clone();
+
+  CircuitStats {
+    k: circuit.k,
+    num_advice_columns: cs.num_advice_columns(),
+    num_fixed_columns: cs.num_fixed_columns(),
+    num_gates: cs.gates().len(),
+    num_lookups: cs.lookups().len(),
+    degree: cs.degree(),
+    total_gadget_rows: gadget_rows.values().sum(),
+    gadget_rows,
+  }
+}
@@ -0,0 +1,188 @@
+use std::{
+  io::Write,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+};
+
+// Artifacts (SRS, keys, models, inputs, proofs) are addressed by URLs. Plain paths are local
+// files, s3:// URLs go through the aws CLI, and gs:// URLs through gsutil, so the credentials and
+// configuration of those tools apply.
+pub trait StorageBackend {
+  fn read(&self, url: &str) -> Result<Vec<u8>, String>;
+  fn write(&self, url: &str, data: &[u8]) -> Result<(), String>;
+  fn exists(&self, url: &str) -> bool;
+  // The URLs of the objects directly under a directory or prefix
+  fn list(&self, url: &str) -> Result<Vec<String>, String>;
+}
+
+pub struct LocalStorage;
+
+impl StorageBackend for LocalStorage {
+  fn read(&self, url: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(url).map_err(|e| format!("could not read {}: {}", url, e))
+  }
+
+  fn write(&self, url: &str, data: &[u8]) -> Result<(), String> {
+    std::fs::write(url, data).map_err(|e| format!("could not write {}: {}", url, e))
+  }
+
+  fn exists(&self, url: &str) -> bool {
+    Path::new(url).exists()
+  }
+
+  fn list(&self, url: &str) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(url).map_err(|e| format!("could not list {}: {}", url, e))?;
+    let mut urls = entries
+      .map(|entry| entry.unwrap().path().to_str().unwrap().to_string())
+      .collect::<Vec<_>>();
+    urls.sort();
+    Ok(urls)
+  }
+}
+
+// The object store CLIs, which stream objects through stdin and stdout with "-"
+pub struct ObjectStorage {
+  pub program: &'static str,
+  pub args: &'static [&'static str],
+}
+
+pub const S3: ObjectStorage = ObjectStorage {
+  program: "aws",
+  args: &["s3"],
+};
+pub const GCS: ObjectStorage = ObjectStorage {
+  program: "gsutil",
+  args: &[],
+};
+
+impl ObjectStorage {
+  fn command(&self) -> Command {
+    let mut command = Command::new(self.program);
+    command.args(self.args);
+    command
+  }
+}
+
+impl StorageBackend for ObjectStorage {
+  fn read(&self, url: &str) -> Result<Vec<u8>, String> {
+    let output = self
+      .command()
+      .args(["cp", url, "-"])
+      .output()
+      .map_err(|e| format!("could not run {}: {}", self.program, e))?;
+    if !output.status.success() {
+      return Err(format!(
+        "could not read {}: {}",
+        url,
+        String::from_utf8_lossy(&output.stderr)
+      ));
+    }
+    Ok(output.stdout)
+  }
+
+  fn write(&self, url: &str, data: &[u8]) -> Result<(), String> {
+    let mut child = self
+      .command()
+      .args(["cp", "-", url])
+      .stdin(Stdio::piped())
+      .spawn()
+      .map_err(|e| format!("could not run {}: {}", self.program, e))?;
+    child
+      .stdin
+      .take()
+      .unwrap()
+      .write_all(data)
+      .map_err(|e| format!("could not write {}: {}", url, e))?;
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+      return Err(format!("could not write {}", url));
+    }
+    Ok(())
+  }
+
+  fn exists(&self, url: &str) -> bool {
+    self
+      .command()
+      .args(["ls", url])
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status()
+      .map_or(false, |status| status.success())
+  }
+
+  fn list(&self, url: &str) -> Result<Vec<String>, String> {
+    let prefix = format!("{}/", url.trim_end_matches('/'));
+    let output = self
+      .command()
+      .args(["ls", &prefix])
+      .output()
+      .map_err(|e| format!("could not run {}: {}", self.program, e))?;
+    if !output.status.success() {
+      return Err(format!("could not list {}", url));
+    }
+    // aws prints "<date> <time> <size> <name>" and gsutil prints full URLs
+    let mut urls = String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| line.split_whitespace().last())
+      .map(|name| {
+        if name.contains("://") {
+          name.to_string()
+        } else {
+          format!("{}{}", prefix, name)
+        }
+      })
+      .filter(|name| !name.ends_with('/'))
+      .collect::<Vec<_>>();
+    urls.sort();
+    Ok(urls)
+  }
+}
+
+pub fn is_remote(url: &str) -> bool {
+  url.starts_with("s3://") || url.starts_with("gs://")
+}
+
+pub fn backend(url: &str) -> &'static dyn StorageBackend {
+  if url.starts_with("s3://") {
+    &S3
+  } else if url.starts_with("gs://") {
+    &GCS
+  } else {
+    &LocalStorage
+  }
+}
+
+pub fn read_artifact(url: &str) -> Result<Vec<u8>, String> {
+  backend(url).read(url)
+}
+
+pub fn write_artifact(url: &str, data: &[u8]) -> Result<(), String> {
+  backend(url).write(url, data)
+}
+
+pub fn artifact_exists(url: &str) -> bool {
+  backend(url).exists(url)
+}
+
+pub fn list_artifacts(url: &str) -> Result<Vec<String>, String> {
+  backend(url).list(url)
+}
+
+// Joins a directory or prefix and a file name
+pub fn join_url(dir: &str, name: &str) -> String {
+  format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+// A local copy of an artifact, for APIs that need a file. Remote artifacts are downloaded to the
+// temporary directory once per process.
+pub fn local_path(url: &str) -> Result<PathBuf, String> {
+  if !is_remote(url) {
+    return Ok(PathBuf::from(url));
+  }
+  let name = url.replace("://", "_").replace('/', "_");
+  let path = std::env::temp_dir().join(format!("zkml_{}_{}", std::process::id(), name));
+  if !path.exists() {
+    std::fs::write(&path, read_artifact(url)?).map_err(|e| e.to_string())?;
+  }
+  Ok(path)
+}
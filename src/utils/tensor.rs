@@ -0,0 +1,139 @@
+use std::ops::{Deref, DerefMut};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD, IxDyn, SliceInfoElem};
+
+use super::loader::TensorMsgpack;
+
+// The tensors of the model and input files (TensorMsgpack) are flat data with a shape. Tensor is
+// their checked conversion to ndarray, for the loader (generate_from_msgpack) and the weight cache.
+// The layers already work on ndarray, AssignedTensor is an ArrayD of cells, so they keep that API:
+// this replaces the flat tensors at the file boundary, not inside the layers. Conversions to and
+// from ArrayD move the underlying buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor<T> {
+  data: ArrayD<T>,
+}
+
+impl<T> Tensor<T> {
+  pub fn from_shape_vec(shape: &[usize], data: Vec<T>) -> Result<Self, String> {
+    let num_el: usize = shape.iter().product();
+    if num_el != data.len() {
+      return Err(format!(
+        "tensor shape {:?} and data length {} mismatch",
+        shape,
+        data.len()
+      ));
+    }
+    Ok(Self {
+      data: ArrayD::from_shape_vec(IxDyn(shape), data).unwrap(),
+    })
+  }
+
+  pub fn shape(&self) -> &[usize] {
+    self.data.shape()
+  }
+
+  pub fn len(&self) -> usize {
+    self.data.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.is_empty()
+  }
+
+  pub fn view(&self) -> ArrayViewD<'_, T> {
+    self.data.view()
+  }
+
+  pub fn view_mut(&mut self) -> ArrayViewMutD<'_, T> {
+    self.data.view_mut()
+  }
+
+  // One (start, end) pair per axis, end exclusive
+  pub fn slice(&self, ranges: &[(usize, usize)]) -> ArrayViewD<'_, T> {
+    assert_eq!(ranges.len(), self.data.ndim());
+    let info = ranges
+      .iter()
+      .map(|(start, end)| SliceInfoElem::Slice {
+        start: *start as isize,
+        end: Some(*end as isize),
+        step: 1,
+      })
+      .collect::<Vec<_>>();
+    self.data.slice(info.as_slice())
+  }
+
+  pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Tensor<U> {
+    Tensor {
+      data: self.data.map(f),
+    }
+  }
+
+  pub fn into_array(self) -> ArrayD<T> {
+    self.data
+  }
+}
+
+impl<T: Clone> Tensor<T> {
+  // Row-major flat data, as stored in the msgpack files
+  pub fn into_flat(self) -> (Vec<usize>, Vec<T>) {
+    let shape = self.data.shape().to_vec();
+    let data = if self.data.is_standard_layout() {
+      self.data.into_raw_vec()
+    } else {
+      self.data.as_standard_layout().into_owned().into_raw_vec()
+    };
+    (shape, data)
+  }
+}
+
+impl<T> From<ArrayD<T>> for Tensor<T> {
+  fn from(data: ArrayD<T>) -> Self {
+    Self { data }
+  }
+}
+
+impl<T> From<Tensor<T>> for ArrayD<T> {
+  fn from(tensor: Tensor<T>) -> Self {
+    tensor.data
+  }
+}
+
+impl<T> Deref for Tensor<T> {
+  type Target = ArrayD<T>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.data
+  }
+}
+
+impl<T> DerefMut for Tensor<T> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.data
+  }
+}
+
+impl TryFrom<&TensorMsgpack> for Tensor<i64> {
+  type Error = String;
+
+  fn try_from(flat: &TensorMsgpack) -> Result<Self, Self::Error> {
+    let shape = flat
+      .shape
+      .iter()
+      .map(|x| usize::try_from(*x))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| format!("tensor {} has a negative dim in {:?}", flat.idx, flat.shape))?;
+    Self::from_shape_vec(&shape, flat.data.clone())
+  }
+}
+
+impl Tensor<i64> {
+  pub fn to_msgpack(&self, idx: i64) -> TensorMsgpack {
+    TensorMsgpack {
+      idx,
+      shape: self.shape().iter().map(|x| *x as i64).collect(),
+      data: self.data.iter().cloned().collect(),
+      dtype: None,
+    }
+  }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use crate::{
+  gadgets::nonlinear::{non_linearity::activation_div, pwl::PiecewiseLinear},
+  model::layer_type_from_name,
+};
+
+use super::{
+  coprocessor::check_coprocessor_layers,
+  loader::{ModelMsgpack, TensorMsgpack},
+  precision::{check_frac_bits, working_sf},
+  randomness::check_random_seed,
+};
+
+// Checks of model and input files from third parties, before anything allocates or indexes from
+// them. The loader then either returns a model that builds a circuit, or an error: every index
+// refers to a tensor defined before it, every shape agrees with its data and with the layers that
+// read it, and the sizes fit in the circuit, whose num_cols * 2^k cells bound every tensor and the
+// sum of them. The shape math is overflow checked. The params of the layers are read by the layers
+// themselves, so a service should still synthesize a checked model in catch_unwind.
+#[derive(Clone, Debug)]
+pub struct LoaderLimits {
+  pub max_file_bytes: usize,
+  pub max_k: i64,
+  pub max_num_cols: i64,
+  pub max_rank: usize,
+  pub max_layers: usize,
+  pub max_tensors: usize,
+  pub max_params: usize,
+  // Values beyond this overflow the conversion to the field
+  pub max_abs_value: i64,
+}
+
+impl Default for LoaderLimits {
+  fn default() -> Self {
+    Self {
+      max_file_bytes: 1 << 32,
+      max_k: 28,
+      max_num_cols: 1024,
+      max_rank: 8,
+      max_layers: 1 << 20,
+      max_tensors: 1 << 20,
+      max_params: 1 << 28,
+      max_abs_value: 1 << 62,
+    }
+  }
+}
+
+// The number of elements of the shape, if the dims are non-negative and the product fits
+pub fn checked_len(shape: &[i64], limits: &LoaderLimits) -> Result<u64, String> {
+  if shape.len() > limits.max_rank {
+    return Err(format!(
+      "shape {:?} has more than {} dims",
+      shape, limits.max_rank
+    ));
+  }
+  shape.iter().try_fold(1u64, |len, dim| {
+    u64::try_from(*dim)
+      .ok()
+      .and_then(|dim| len.checked_mul(dim))
+      .ok_or_else(|| format!("shape {:?} is negative or too large", shape))
+  })
+}
+
+pub fn check_file_size(len: usize, limits: &LoaderLimits) -> Result<(), String> {
+  if len > limits.max_file_bytes {
+    return Err(format!(
+      "the file has {} bytes, the limit is {}",
+      len, limits.max_file_bytes
+    ));
+  }
+  Ok(())
+}
+
+// Adds to the running total of cells, which the circuit bounds
+fn add_cells(total: &mut u64, len: u64, max_cells: u64, what: &str) -> Result<(), String> {
+  *total = total
+    .checked_add(len)
+    .filter(|total| *total <= max_cells)
+    .ok_or_else(|| {
+      format!(
+        "{} need more than the {} cells of the circuit",
+        what, max_cells
+      )
+    })?;
+  Ok(())
+}
+
+fn check_tensor(
+  tensor: &TensorMsgpack,
+  limits: &LoaderLimits,
+  complete: bool,
+) -> Result<u64, String> {
+  let len = checked_len(&tensor.shape, limits)?;
+  let has_data = !tensor.data.is_empty() || len == 0;
+  if (has_data || complete) && tensor.data.len() as u64 != len {
+    return Err(format!(
+      "tensor {} has {} values for shape {:?}",
+      tensor.idx,
+      tensor.data.len(),
+      tensor.shape
+    ));
+  }
+  if let Some(x) = tensor
+    .data
+    .iter()
+    .find(|x| x.unsigned_abs() > limits.max_abs_value as u64)
+  {
+    return Err(format!(
+      "tensor {} has value {} out of range",
+      tensor.idx, x
+    ));
+  }
+  Ok(len)
+}
+
+fn check_defined(defined: &HashMap<i64, u64>, idxes: &[i64], what: &str) -> Result<(), String> {
+  match idxes.iter().find(|idx| !defined.contains_key(idx)) {
+    Some(idx) => Err(format!(
+      "{} refers to tensor {}, which is never defined",
+      what, idx
+    )),
+    None => Ok(()),
+  }
+}
+
+// The structure of the model. A complete model has the data of every tensor, a config can leave
+// the data of the weights and the inputs out.
+pub fn validate_model(
+  model: &ModelMsgpack,
+  limits: &LoaderLimits,
+  complete: bool,
+) -> Result<(), String> {
+  if model.k < 1 || model.k > limits.max_k {
+    return Err(format!("k = {} is out of range", model.k));
+  }
+  if model.num_cols < 1 || model.num_cols > limits.max_num_cols {
+    return Err(format!("num_cols = {} is out of range", model.num_cols));
+  }
+  if model.global_sf < 1 {
+    return Err(format!("scale factor {} is not positive", model.global_sf));
+  }
+  check_frac_bits(model)?;
+  check_coprocessor_layers(model)?;
+  check_random_seed(model)?;
+  activation_div(working_sf(model), model.activation_table_bits)?;
+  if model.layers.len() > limits.max_layers || model.tensors.len() > limits.max_tensors {
+    return Err(format!(
+      "{} layers and {} tensors exceed the limits",
+      model.layers.len(),
+      model.tensors.len()
+    ));
+  }
+  let max_cells = (model.num_cols as u64) << model.k;
+
+  let mut defined = HashMap::new();
+  let mut num_cells = 0;
+  for tensor in model.tensors.iter() {
+    let len = check_tensor(tensor, limits, complete)?;
+    add_cells(&mut num_cells, len, max_cells, "the tensors")?;
+    defined.insert(tensor.idx, len);
+  }
+  check_defined(&defined, &model.inp_idxes, "the inputs")?;
+
+  // The outputs of a branch are defined once the outputs of both sides are
+  let branch_outs = model
+    .branches
+    .iter()
+    .flatten()
+    .flat_map(|b| {
+      b.out_idxes
+        .iter()
+        .zip(b.then_outs.iter().zip(b.else_outs.iter()))
+    })
+    .collect::<Vec<_>>();
+
+  let define_branch_outs = |defined: &mut HashMap<i64, u64>| {
+    for (out, (then_out, else_out)) in branch_outs.iter() {
+      if let (Some(len), Some(_)) = (defined.get(*then_out), defined.get(*else_out)) {
+        let len = *len;
+        defined.entry(**out).or_insert(len);
+      }
+    }
+  };
+
+  let mut num_cells = 0;
+  for (pos, layer) in model.layers.iter().enumerate() {
+    define_branch_outs(&mut defined);
+    let what = format!("layer {} ({})", pos, layer.layer_type);
+    layer_type_from_name(&layer.layer_type).ok_or_else(|| format!("{} is unknown", what))?;
+    if layer.inp_idxes.len() != layer.inp_shapes.len()
+      || layer.out_idxes.len() != layer.out_shapes.len()
+    {
+      return Err(format!("{} doesn't have one shape per tensor", what));
+    }
+    if layer.params.len() > limits.max_params || layer.mask.len() > limits.max_params {
+      return Err(format!("{} has too many params", what));
+    }
+    if layer.layer_type == "PiecewiseLinear" {
+      let pwl = PiecewiseLinear::from_params(&layer.params, model.global_sf as u64)
+        .map_err(|e| format!("{}: {}", what, e))?;
+      // The table is a lookup, of at most 2^k rows
+      if pwl.table_len(model.global_sf as u64) > 1 << model.k {
+        return Err(format!("{} has knots beyond the input lookup", what));
+      }
+    }
+
+    check_defined(&defined, &layer.inp_idxes, &what)?;
+    for (idx, shape) in layer.inp_idxes.iter().zip(layer.inp_shapes.iter()) {
+      let len = checked_len(shape, limits)?;
+      if defined[idx] != len {
+        return Err(format!(
+          "{} reads tensor {} as {:?}, which has {} values",
+          what, idx, shape, defined[idx]
+        ));
+      }
+    }
+    for (idx, shape) in layer.out_idxes.iter().zip(layer.out_shapes.iter()) {
+      let len = checked_len(shape, limits)?;
+      add_cells(&mut num_cells, len, max_cells, "the layer outputs")?;
+      defined.insert(*idx, len);
+    }
+  }
+  define_branch_outs(&mut defined);
+
+  check_defined(&defined, &model.out_idxes, "the outputs")?;
+  for group in model
+    .commit_before
+    .iter()
+    .chain(model.commit_after.iter())
+    .flatten()
+  {
+    check_defined(&defined, group, "a commitment")?;
+  }
+  for exit in model.exits.iter().flatten() {
+    check_defined(&defined, exit, "an exit")?;
+  }
+  if let Some(exit) = model.exit {
+    let num_exits = model.exits.as_ref().map_or(0, |exits| exits.len());
+    if exit < 0 || exit as usize >= num_exits {
+      return Err(format!("exit {} is out of range", exit));
+    }
+  }
+  for branch in model.branches.iter().flatten() {
+    check_defined(&defined, &[branch.cond], "a branch")?;
+    check_defined(&defined, &branch.then_outs, "a branch")?;
+    check_defined(&defined, &branch.else_outs, "a branch")?;
+  }
+  if let Some(idx) = model
+    .rlc_inputs
+    .iter()
+    .flatten()
+    .find(|idx| !model.inp_idxes.contains(idx))
+  {
+    return Err(format!("rlc input {} is not an input", idx));
+  }
+  Ok(())
+}
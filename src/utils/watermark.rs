@@ -0,0 +1,159 @@
+use std::collections::BTreeSet;
+
+use halo2_proofs::{circuit::Value, halo2curves::ff::PrimeField};
+
+use crate::utils::{
+  chaining::output_shapes,
+  helpers::convert_pos_int,
+  loader::{LayerMsgpack, ModelMsgpack, TensorMsgpack},
+};
+
+// Watermark verification: the model is evaluated on a set of secret trigger inputs in a single
+// circuit. The weights and the triggers are committed separately (the first two public values),
+// so the proof shows that the committed model produces the public outputs on the committed
+// triggers without revealing the triggers.
+
+pub fn weight_idxes(model: &ModelMsgpack) -> Vec<i64> {
+  model
+    .tensors
+    .iter()
+    .filter(|tensor| !model.inp_idxes.contains(&tensor.idx))
+    .map(|tensor| tensor.idx)
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .collect()
+}
+
+pub fn max_tensor_idx(model: &ModelMsgpack) -> i64 {
+  model
+    .layers
+    .iter()
+    .flat_map(|layer| layer.inp_idxes.iter().chain(layer.out_idxes.iter()))
+    .chain(model.tensors.iter().map(|tensor| &tensor.idx))
+    .max()
+    .cloned()
+    .unwrap()
+}
+
+// Copies the layers once per set of inputs. Weights are shared between the copies and every
+// other tensor index of copy i is offset by i * (max index + 1). The inputs and outputs are those
+// of all copies, in order, and the commitments are left as they are.
+pub fn replicate_model(model: &ModelMsgpack, inputs: &Vec<Vec<TensorMsgpack>>) -> ModelMsgpack {
+  let weight_idxes = weight_idxes(model);
+  let max_idx = max_tensor_idx(model);
+  let remap = |idx: i64, copy: usize| -> i64 {
+    if weight_idxes.contains(&idx) {
+      idx
+    } else {
+      idx + copy as i64 * (max_idx + 1)
+    }
+  };
+
+  let mut layers = vec![];
+  let mut inp_idxes = vec![];
+  let mut out_idxes = vec![];
+  let mut tensors = model
+    .tensors
+    .iter()
+    .filter(|tensor| weight_idxes.contains(&tensor.idx))
+    .cloned()
+    .collect::<Vec<_>>();
+  for (copy, inp) in inputs.iter().enumerate() {
+    for layer in model.layers.iter() {
+      layers.push(LayerMsgpack {
+        inp_idxes: layer
+          .inp_idxes
+          .iter()
+          .map(|idx| remap(*idx, copy))
+          .collect(),
+        out_idxes: layer
+          .out_idxes
+          .iter()
+          .map(|idx| remap(*idx, copy))
+          .collect(),
+        ..layer.clone()
+      });
+    }
+    for tensor in inp.iter() {
+      assert!(
+        model.inp_idxes.contains(&tensor.idx),
+        "tensor {} is not an input",
+        tensor.idx
+      );
+      tensors.push(TensorMsgpack {
+        idx: remap(tensor.idx, copy),
+        ..tensor.clone()
+      });
+    }
+    inp_idxes.extend(model.inp_idxes.iter().map(|idx| remap(*idx, copy)));
+    out_idxes.extend(model.out_idxes.iter().map(|idx| remap(*idx, copy)));
+  }
+
+  ModelMsgpack {
+    inp_idxes,
+    out_idxes,
+    tensors,
+    layers,
+    ..model.clone()
+  }
+}
+
+pub fn watermark_model(model: &ModelMsgpack, triggers: &Vec<Vec<TensorMsgpack>>) -> ModelMsgpack {
+  assert!(!triggers.is_empty(), "no trigger inputs");
+  let weight_idxes = weight_idxes(model);
+  assert!(
+    !weight_idxes.is_empty(),
+    "the model has no weights to commit to"
+  );
+
+  let replicated = replicate_model(model, triggers);
+  let mut trigger_idxes = replicated.inp_idxes.clone();
+  trigger_idxes.sort();
+  ModelMsgpack {
+    commit_before: Some(vec![weight_idxes, trigger_idxes]),
+    commit_after: Some(vec![]),
+    ..replicated
+  }
+}
+
+// The outputs for every trigger in the public values of the watermark circuit. `model` is the
+// original model.
+pub fn watermark_outputs<F: PrimeField>(
+  model: &ModelMsgpack,
+  num_triggers: usize,
+  public_vals: &Vec<F>,
+) -> Vec<Vec<i64>> {
+  let num_outputs = output_shapes(model)
+    .iter()
+    .map(|shape| shape.iter().product::<i64>() as usize)
+    .sum::<usize>();
+  // Skip the weight and trigger commitments
+  let vals = public_vals[2..]
+    .iter()
+    .map(|x| convert_pos_int(Value::known(*x)) as i64)
+    .collect::<Vec<_>>();
+  assert_eq!(vals.len(), num_outputs * num_triggers);
+  vals.chunks(num_outputs).map(|x| x.to_vec()).collect()
+}
+
+// Returns the triggers whose outputs are not within `tolerance` of the expected outputs
+pub fn check_watermark(
+  outputs: &Vec<Vec<i64>>,
+  expected: &Vec<Vec<i64>>,
+  tolerance: i64,
+) -> Vec<usize> {
+  assert_eq!(outputs.len(), expected.len());
+  outputs
+    .iter()
+    .zip(expected.iter())
+    .enumerate()
+    .filter(|(_, (outp, exp))| {
+      outp.len() != exp.len()
+        || outp
+          .iter()
+          .zip(exp.iter())
+          .any(|(a, b)| (a - b).abs() > tolerance)
+    })
+    .map(|(i, _)| i)
+    .collect()
+}
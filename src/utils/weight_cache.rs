@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use halo2_proofs::halo2curves::{ff::PrimeField, serde::SerdeObject};
+use ndarray::{Array, IxDyn};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  model::ModelCircuit,
+  utils::{
+    artifacts::content_hasher,
+    loader::ModelMsgpack,
+    storage::{artifact_exists, read_artifact, write_artifact},
+    tensor::Tensor,
+    watermark::weight_idxes,
+  },
+};
+
+// Moving the weights into the field costs a few multiplications per weight on every circuit
+// construction. The field elements are cached next to the proving key (<name>.weights), in their
+// raw internal form so that reading them needs no conversion. The cache is keyed by a hash of the
+// integer weights, and is as trusted as the proving key: it is read unchecked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedTensor {
+  idx: i64,
+  shape: Vec<usize>,
+  data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WeightCache {
+  weights_hash: String,
+  tensors: Vec<CachedTensor>,
+}
+
+pub fn weight_cache_path(pk_fname: &str) -> String {
+  format!("{}.weights", pk_fname.trim_end_matches(".pkey"))
+}
+
+// The hash of the weight tensors of the config, in index order
+pub fn weights_hash(config: &ModelMsgpack) -> String {
+  let mut state = content_hasher();
+  for idx in weight_idxes(config) {
+    let tensor = config.tensors.iter().find(|t| t.idx == idx).unwrap();
+    state.update(&idx.to_le_bytes());
+    state.update(&(tensor.shape.len() as u64).to_le_bytes());
+    for dim in tensor.shape.iter() {
+      state.update(&dim.to_le_bytes());
+    }
+    for x in tensor.data.iter() {
+      state.update(&x.to_le_bytes());
+    }
+  }
+  state.finalize().to_hex().to_string()
+}
+
+pub fn write_weight_cache<F: PrimeField + SerdeObject>(
+  path: &str,
+  config: &ModelMsgpack,
+  circuit: &ModelCircuit<F>,
+) -> Result<(), String> {
+  let tensors = weight_idxes(config)
+    .into_iter()
+    .filter_map(|idx| circuit.tensors.get(&idx).map(|tensor| (idx, tensor)))
+    .map(|(idx, tensor)| CachedTensor {
+      idx,
+      shape: tensor.shape().to_vec(),
+      data: tensor.iter().flat_map(|x| x.to_raw_bytes()).collect(),
+    })
+    .collect();
+  let cache = WeightCache {
+    weights_hash: weights_hash(config),
+    tensors,
+  };
+  write_artifact(path, &bincode::serialize(&cache).unwrap())
+}
+
+// The cached weights, or None if there is no cache for these weights
+pub fn read_weight_cache<F: PrimeField + SerdeObject>(
+  path: &str,
+  config: &ModelMsgpack,
+) -> Result<Option<BTreeMap<i64, Array<F, IxDyn>>>, String> {
+  if !artifact_exists(path) {
+    return Ok(None);
+  }
+  let cache: WeightCache = bincode::deserialize(&read_artifact(path)?)
+    .map_err(|e| format!("malformed weight cache {}: {}", path, e))?;
+  if cache.weights_hash != weights_hash(config) {
+    return Ok(None);
+  }
+
+  let elem_size = F::ZERO.to_raw_bytes().len();
+  let mut weights = BTreeMap::new();
+  for tensor in cache.tensors {
+    let data = tensor
+      .data
+      .chunks(elem_size)
+      .map(|bytes| F::from_raw_bytes_unchecked(bytes))
+      .collect::<Vec<_>>();
+    let tensor_arr = Tensor::from_shape_vec(&tensor.shape, data)
+      .map_err(|e| format!("malformed weight cache {}: {}", path, e))?;
+    weights.insert(tensor.idx, tensor_arr.into_array());
+  }
+  Ok(Some(weights))
+}
@@ -0,0 +1,395 @@
+use std::{
+  marker::PhantomData,
+  panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use halo2_proofs::{
+  circuit::{Layouter, SimpleFloorPlanner, Value},
+  halo2curves::ff::{FromUniformBytes, PrimeField},
+  plonk::{
+    Advice, Any, Assigned, Assignment, Challenge, Circuit, Column, ConstraintSystem, Error, Fixed,
+    FloorPlanner, Instance, Selector,
+  },
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+  model::{ModelCircuit, ModelConfig},
+  utils::{
+    artifacts::content_hash,
+    helpers::get_public_values,
+    loader::ModelMsgpack,
+    pk_cache::layout_hash,
+    storage::{read_artifact, write_artifact},
+    weight_cache::weights_hash,
+  },
+};
+
+// Splits witness generation from proving: a trusted machine runs the model and exports the
+// witness, i.e., the value of every assigned advice cell, and a larger machine proves it without
+// running the model. The fixed columns, selectors, and copy constraints are in the proving key, so
+// the replay only assigns the advice cells back to their rows. The witness holds the inputs, the
+// weights, and every intermediate value, so the prover learns all of them: the split moves the
+// cryptographic work, it does not hide anything from the prover.
+
+pub const WITNESS_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WitnessFile {
+  pub version: u32,
+  // The hash of the layout and the weights, which the prover checks against its config
+  pub model_hash: String,
+  pub k: u32,
+  // Every assigned advice cell, as its column, its row, and its 32 byte little endian value
+  pub columns: Vec<u32>,
+  pub rows: Vec<u32>,
+  pub values: Vec<u8>,
+  // Flat, in the same encoding
+  pub public_vals: Vec<u8>,
+}
+
+pub fn model_hash(config: &ModelMsgpack) -> String {
+  let hashes = format!("{}{}", layout_hash(config), weights_hash(config));
+  content_hash(hashes.as_bytes())
+}
+
+fn to_bytes<F: PrimeField>(vals: &[F]) -> Vec<u8> {
+  vals
+    .iter()
+    .flat_map(|x| x.to_repr().as_ref().to_vec())
+    .collect()
+}
+
+fn from_bytes<F: PrimeField>(bytes: &[u8]) -> Result<Vec<F>, String> {
+  if bytes.len() % 32 != 0 {
+    return Err(format!(
+      "{} bytes are not a list of field elements",
+      bytes.len()
+    ));
+  }
+  bytes
+    .chunks_exact(32)
+    .map(|chunk| {
+      let mut repr = F::Repr::default();
+      repr.as_mut().copy_from_slice(chunk);
+      Option::from(F::from_repr(repr))
+        .ok_or("a value of the witness is not in the field".to_string())
+    })
+    .collect()
+}
+
+// Records the advice assignments of a synthesis and ignores everything else, which keygen
+// already put in the proving key. Without cells, it only bounds the rows.
+struct WitnessRecorder<F: PrimeField> {
+  k: u32,
+  usable_rows: usize,
+  cells: Option<Vec<(usize, usize, F)>>,
+}
+
+impl<F: PrimeField> Assignment<F> for WitnessRecorder<F> {
+  fn enter_region<NR, N>(&mut self, _name_fn: N)
+  where
+    NR: Into<String>,
+    N: FnOnce() -> NR,
+  {
+  }
+
+  fn annotate_column<A, AR>(&mut self, _annotation: A, _column: Column<Any>)
+  where
+    A: FnOnce() -> AR,
+    AR: Into<String>,
+  {
+  }
+
+  fn exit_region(&mut self) {}
+
+  fn enable_selector<A, AR>(
+    &mut self,
+    _annotation: A,
+    _selector: &Selector,
+    _row: usize,
+  ) -> Result<(), Error>
+  where
+    A: FnOnce() -> AR,
+    AR: Into<String>,
+  {
+    Ok(())
+  }
+
+  fn query_instance(&self, _column: Column<Instance>, _row: usize) -> Result<Value<F>, Error> {
+    Ok(Value::unknown())
+  }
+
+  fn assign_advice<V, VR, A, AR>(
+    &mut self,
+    _annotation: A,
+    column: Column<Advice>,
+    row: usize,
+    to: V,
+  ) -> Result<(), Error>
+  where
+    V: FnOnce() -> Value<VR>,
+    VR: Into<Assigned<F>>,
+    A: FnOnce() -> AR,
+    AR: Into<String>,
+  {
+    if row >= self.usable_rows {
+      return Err(Error::NotEnoughRowsAvailable { current_k: self.k });
+    }
+    if let Some(cells) = &mut self.cells {
+      to().map(|x| cells.push((column.index(), row, x.into().evaluate())));
+    }
+    Ok(())
+  }
+
+  fn assign_fixed<V, VR, A, AR>(
+    &mut self,
+    _annotation: A,
+    _column: Column<Fixed>,
+    _row: usize,
+    _to: V,
+  ) -> Result<(), Error>
+  where
+    V: FnOnce() -> Value<VR>,
+    VR: Into<Assigned<F>>,
+    A: FnOnce() -> AR,
+    AR: Into<String>,
+  {
+    Ok(())
+  }
+
+  fn copy(
+    &mut self,
+    _left_column: Column<Any>,
+    _left_row: usize,
+    _right_column: Column<Any>,
+    _right_row: usize,
+  ) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn fill_from_row(
+    &mut self,
+    _column: Column<Fixed>,
+    _row: usize,
+    _to: Value<Assigned<F>>,
+  ) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn get_challenge(&self, _challenge: Challenge) -> Value<F> {
+    Value::unknown()
+  }
+
+  fn push_namespace<NR, N>(&mut self, _name_fn: N)
+  where
+    NR: Into<String>,
+    N: FnOnce() -> NR,
+  {
+  }
+
+  fn pop_namespace(&mut self, _gadget_name: Option<String>) {}
+}
+
+// Runs the model by synthesizing the circuit, like the prover does, and records its witness and
+// public values
+pub fn export_witness<F: PrimeField + Ord + FromUniformBytes<64>>(
+  circuit: &ModelCircuit<F>,
+  config: &ModelMsgpack,
+) -> Result<WitnessFile, String> {
+  let k = circuit.k as u32;
+  let mut cs = ConstraintSystem::<F>::default();
+  let model_config = ModelCircuit::<F>::configure(&mut cs);
+  let mut recorder = WitnessRecorder {
+    k,
+    usable_rows: (1 << k) - (cs.blinding_factors() + 1),
+    cells: Some(vec![]),
+  };
+  SimpleFloorPlanner::synthesize(&mut recorder, circuit, model_config, cs.constants().clone())
+    .map_err(|e| format!("synthesis failed: {:?}", e))?;
+
+  let (mut columns, mut rows, mut values) = (vec![], vec![], vec![]);
+  for (column, row, value) in recorder.cells.iter().flatten() {
+    columns.push(*column as u32);
+    rows.push(*row as u32);
+    values.push(*value);
+  }
+  Ok(WitnessFile {
+    version: WITNESS_VERSION,
+    model_hash: model_hash(config),
+    k,
+    columns,
+    rows,
+    values: to_bytes(&values),
+    public_vals: to_bytes(&get_public_values::<F>()),
+  })
+}
+
+// The public values of a synthesis with the chips of the prover, which computes every cell but
+// stores none and checks nothing, so it costs the witness generation of a proof and not the
+// 2^k rows of the MockProver. A layer that can't assign its witness, e.g., on an input outside
+// the table it reads its output from, fails the synthesis or panics, which is the error. An
+// input that only breaks a constraint fails when it is proven.
+pub fn synthesize_public_values<F: PrimeField + Ord + FromUniformBytes<64>>(
+  circuit: &ModelCircuit<F>,
+) -> Result<Vec<F>, String> {
+  let k = circuit.k as u32;
+  let mut cs = ConstraintSystem::<F>::default();
+  let model_config = ModelCircuit::<F>::configure(&mut cs);
+  let mut recorder = WitnessRecorder {
+    k,
+    usable_rows: (1 << k) - (cs.blinding_factors() + 1),
+    cells: None,
+  };
+  let constants = cs.constants().clone();
+  let synthesized = catch_unwind(AssertUnwindSafe(|| {
+    SimpleFloorPlanner::synthesize(&mut recorder, circuit, model_config, constants)
+  }));
+  match synthesized {
+    Ok(Ok(())) => Ok(get_public_values::<F>()),
+    Ok(Err(e)) => Err(format!("synthesis failed: {:?}", e)),
+    Err(panic) => {
+      let msg = match (panic.downcast_ref::<String>(), panic.downcast_ref::<&str>()) {
+        (Some(msg), _) => msg.clone(),
+        (None, Some(msg)) => msg.to_string(),
+        (None, None) => "a layer panicked".to_string(),
+      };
+      Err(format!("synthesis failed: {}", msg))
+    }
+  }
+}
+
+// A summary of a witness that is the same on every platform iff the witness is, to catch the
+// platform dependent float arithmetic that must not reach the fixed point pipeline. The cells are
+// hashed in (column, row) order, and the public values are kept, so a mismatch shows whether the
+// outputs moved too.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WitnessDigest {
+  pub num_cells: usize,
+  pub cells_hash: String,
+  pub public_vals: Vec<String>, // Hex, little endian
+}
+
+pub fn witness_digest(witness: &WitnessFile) -> WitnessDigest {
+  let mut cells = (0..witness.columns.len()).collect::<Vec<_>>();
+  cells.sort_by_key(|i| (witness.columns[*i], witness.rows[*i]));
+  let mut buf = vec![];
+  for i in cells.iter() {
+    buf.extend(witness.columns[*i].to_le_bytes());
+    buf.extend(witness.rows[*i].to_le_bytes());
+    buf.extend(&witness.values[32 * i..32 * (i + 1)]);
+  }
+  let public_vals = witness
+    .public_vals
+    .chunks(32)
+    .map(|x| x.iter().map(|b| format!("{:02x}", b)).collect())
+    .collect();
+  WitnessDigest {
+    num_cells: cells.len(),
+    cells_hash: content_hash(&buf),
+    public_vals,
+  }
+}
+
+pub fn write_witness(path: &str, witness: &WitnessFile) -> Result<(), String> {
+  let buf = bincode::serialize(witness).map_err(|e| format!("{}: {}", path, e))?;
+  write_artifact(path, &buf)
+}
+
+pub fn read_witness(path: &str) -> Result<WitnessFile, String> {
+  let buf = read_artifact(path)?;
+  let witness: WitnessFile =
+    bincode::deserialize(&buf).map_err(|e| format!("malformed witness {}: {}", path, e))?;
+  if witness.version != WITNESS_VERSION {
+    return Err(format!(
+      "{} is a version {} witness, not {}",
+      path, witness.version, WITNESS_VERSION
+    ));
+  }
+  Ok(witness)
+}
+
+// Assigns the exported advice cells to their rows in a single region. It has the constraint
+// system of the model circuit, so it proves with the model's proving key, whose circuit must have
+// been generated first for the gadget config.
+#[derive(Clone, Debug)]
+pub struct WitnessCircuit<F: PrimeField> {
+  pub k: u32,
+  pub cells: Vec<(usize, usize, F)>,
+  pub _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> WitnessCircuit<F> {
+  // The circuit and the public values of the witness, which must be of the config's model
+  pub fn from_witness(
+    witness: &WitnessFile,
+    config: &ModelMsgpack,
+  ) -> Result<(Self, Vec<F>), String> {
+    if witness.model_hash != model_hash(config) {
+      return Err("the witness is of another model or other weights".to_string());
+    }
+    if witness.k != config.k as u32 {
+      return Err(format!(
+        "the witness is for k = {}, the model for {}",
+        witness.k, config.k
+      ));
+    }
+    let values = from_bytes::<F>(&witness.values)?;
+    let num_cols = config.num_cols as u32;
+    if witness.columns.len() != values.len() || witness.rows.len() != values.len() {
+      return Err("the witness has a different number of columns, rows, and values".to_string());
+    }
+    if let Some(column) = witness.columns.iter().find(|column| **column >= num_cols) {
+      return Err(format!("the model has no advice column {}", column));
+    }
+    if let Some(row) = witness.rows.iter().find(|row| **row >= 1 << witness.k) {
+      return Err(format!("row {} is out of the circuit", row));
+    }
+
+    let cells = witness
+      .columns
+      .iter()
+      .zip(witness.rows.iter())
+      .zip(values.into_iter())
+      .map(|((column, row), value)| (*column as usize, *row as usize, value))
+      .collect();
+    let circuit = Self {
+      k: witness.k,
+      cells,
+      _marker: PhantomData,
+    };
+    Ok((circuit, from_bytes(&witness.public_vals)?))
+  }
+}
+
+impl<F: PrimeField + Ord + FromUniformBytes<64>> Circuit<F> for WitnessCircuit<F> {
+  type Config = ModelConfig<F>;
+  type FloorPlanner = SimpleFloorPlanner;
+  type Params = ();
+
+  fn without_witnesses(&self) -> Self {
+    Self {
+      k: self.k,
+      cells: vec![],
+      _marker: PhantomData,
+    }
+  }
+
+  fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+    ModelCircuit::<F>::configure(meta)
+  }
+
+  // The region is the only one, so it starts at row 0 and its offsets are the rows of the cells
+  fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+    let columns = &config.gadget_config.columns;
+    layouter.assign_region(
+      || "witness",
+      |mut region| {
+        for (column, row, value) in self.cells.iter() {
+          region.assign_advice(|| "", columns[*column], *row, || Value::known(*value))?;
+        }
+        Ok(())
+      },
+    )
+  }
+}
@@ -0,0 +1,49 @@
+#!/bin/bash
+# Proving benchmark over the reference models, with a sweep over the number of threads
+# Usage: testing/bench.sh [x86_64 or aarch64]
+# The profile defaults to the architecture of the machine. Every run writes a perf report to
+# testing/bench/<profile>/<model>-<threads>.json, which estimate can fit on (--snapshots). The
+# aarch64 profile sweeps up to the performance cores and then all the CPUs: on big.LITTLE machines
+# (Apple M-series, some Arm servers), proving on the efficiency cores as well can be slower, since
+# halo2 splits its FFTs and MSMs evenly between the threads.
+set -e
+
+profile=${1:-$(uname -m)}
+num_cpus=$(getconf _NPROCESSORS_ONLN)
+case $profile in
+  x86_64)
+    threads="1 $((num_cpus / 2)) $num_cpus"
+    ;;
+  aarch64 | arm64)
+    profile=aarch64
+    if [ "$(uname)" = Darwin ]; then
+      perf_cores=$(sysctl -n hw.perflevel0.logicalcpu 2>/dev/null || echo "$num_cpus")
+    else
+      # The cores with the largest capacity, if Linux lists them
+      perf_cores=$(cat /sys/devices/system/cpu/cpu*/cpu_capacity 2>/dev/null | sort -n | uniq -c |
+        tail -1 | awk '{print $1}')
+      perf_cores=${perf_cores:-$num_cpus}
+    fi
+    threads="1 $((perf_cores / 2)) $perf_cores $num_cpus"
+    ;;
+  *)
+    echo "unknown profile $profile, expected x86_64 or aarch64"
+    exit 1
+    ;;
+esac
+
+cargo build --release --bin time_circuit --features parallel-keygen,parallel-witness
+out=testing/bench/$profile
+mkdir -p "$out"
+
+for t in $(echo "$threads" | tr ' ' '\n' | awk '$1 > 0' | sort -nu); do
+  # Every pool gets the same size: halo2's (rayon's global pool), keygen's, and the witness one
+  RAYON_NUM_THREADS=$t ZKML_KEYGEN_THREADS=$t ZKML_NUM_THREADS=$t ./target/release/time_circuit \
+    examples/mnist/model.msgpack examples/mnist/inp.msgpack kzg --perf-report "$out/mnist-$t.json"
+  python3 -c "
+import json, sys
+report = json.load(open(sys.argv[1]))
+stages = {stage['stage']: stage['seconds'] for stage in report['stages']}
+print('$t threads: proof {:.2f}s, total {:.2f}s'.format(stages['proof'], sum(stages.values())))
+" "$out/mnist-$t.json"
+done
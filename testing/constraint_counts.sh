@@ -0,0 +1,16 @@
+#!/bin/bash
+# Constraint count regression gate over the reference models
+# The pins are committed in testing/constraint_counts/, and a missing pin fails the gate. Run with
+# ZKML_BLESS=1 to write the missing pins, then review and commit them. Delete a pin and bless it
+# to accept a new baseline.
+set -e
+
+cargo build --release --bin test_constraint_counts
+mkdir -p testing/constraint_counts
+
+./target/release/test_constraint_counts examples/mnist/model.msgpack examples/mnist/inp.msgpack \
+  testing/constraint_counts/mnist.json
+./target/release/test_constraint_counts examples/twitter/config.msgpack \
+  examples/twitter/inp1.msgpack testing/constraint_counts/twitter.json
+./target/release/test_constraint_counts examples/train_graph/train.msgpack \
+  examples/train_graph/input.msgpack testing/constraint_counts/train_graph.json
@@ -0,0 +1,17 @@
+#!/bin/bash
+# Witness reproducibility gate over the reference models
+# The witness, and so the public values, must be the same on every target: pin the digests on one
+# with ZKML_BLESS=1, which writes the missing ones to testing/witness_digests/, and commit them,
+# then run this on the others, e.g., x86-64 and aarch64 on Linux and macOS. A missing digest fails.
+# Delete a digest and bless it to accept a new baseline.
+set -e
+
+cargo build --release --bin test_witness_repro
+mkdir -p testing/witness_digests
+
+./target/release/test_witness_repro examples/mnist/model.msgpack examples/mnist/inp.msgpack \
+  testing/witness_digests/mnist.json
+for i in 1 2 3; do
+  ./target/release/test_witness_repro examples/twitter/config.msgpack \
+    examples/twitter/inp$i.msgpack testing/witness_digests/twitter_$i.json
+done